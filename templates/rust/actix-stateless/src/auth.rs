@@ -0,0 +1,38 @@
+//! Authentication against the configured OpenID Connect provider. There
+//! are no sessions or accounts here: the provider issues the tokens and
+//! this service only checks them.
+
+use actix_web::dev::Payload;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, FromRequest, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use platform_auth::oidc::OidcClaims;
+
+use crate::errors::AppError;
+use crate::AppState;
+
+/// The caller's verified token; taking it as a handler argument makes the
+/// route answer `401` to requests without a valid one.
+#[derive(Debug, Clone)]
+pub struct Authenticated(pub OidcClaims);
+
+impl FromRequest for Authenticated {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let state = req.app_data::<web::Data<AppState>>().cloned();
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::to_string);
+
+        Box::pin(async move {
+            let state = state.ok_or(AppError::InternalServerError)?;
+            let token = token.ok_or(AppError::Unauthorized)?;
+            state.verifier.verify(&token).await.map(Authenticated)
+        })
+    }
+}
@@ -0,0 +1,83 @@
+use config::builder::{ConfigBuilder, DefaultState};
+use config::{Config, ConfigError};
+use platform_auth::oidc::OidcSettings;
+use platform_core::lifecycle::LifecycleSettings;
+use platform_observability::log_level::LogLevelSettings;
+use platform_observability::otel::OtelSettings;
+use platform_observability::propagation::{self, PropagationSettings};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Settings {
+    pub server: ServerSettings,
+    /// The identity provider whose user tokens are accepted.
+    pub oidc: OidcSettings,
+    pub items: ItemSettings,
+    pub lifecycle: LifecycleSettings,
+    pub log_level: LogLevelSettings,
+    /// Span export and trace context propagation.
+    pub otel: OtelSettings,
+    /// Mesh and tracing headers handed on to the services a request calls.
+    pub propagation: PropagationSettings,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ServerSettings {
+    pub host: String,
+    pub port: u16,
+    pub workers: usize,
+    /// Limit on JSON request bodies.
+    pub max_json_body_bytes: usize,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ItemSettings {
+    pub backend: ItemBackend,
+    /// Base URL of the items API, for the `http` backend.
+    pub base_url: String,
+    pub timeout_secs: u64,
+}
+
+/// Where items live; see [`crate::items`].
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemBackend {
+    /// In this process, lost on restart; for development and tests.
+    Memory,
+    /// Another service's REST API.
+    Http,
+}
+
+impl Settings {
+    pub fn new() -> Result<Self, ConfigError> {
+        platform_core::config::load(Self::defaults()?, "ACTIX_STATELESS")
+    }
+
+    /// Builder holding only the default values, e.g. for tests to add
+    /// overrides to. `oidc.issuer`, `oidc.audience` and `oidc.keys` have
+    /// none.
+    pub fn defaults() -> Result<ConfigBuilder<DefaultState>, ConfigError> {
+        Config::builder()
+            .set_default("server.host", "0.0.0.0")?
+            .set_default("server.port", 8080)?
+            .set_default("server.workers", 4)?
+            .set_default("server.max_json_body_bytes", 262_144)?
+            .set_default("oidc.scope_claim", "scope")?
+            .set_default("oidc.jwks_refresh_secs", 300)?
+            .set_default("items.backend", "memory")?
+            .set_default("items.base_url", "http://localhost:8081")?
+            .set_default("items.timeout_secs", 10)?
+            .set_default("lifecycle.hook_timeout_secs", 10)?
+            .set_default("lifecycle.shutdown_timeout_secs", 30)?
+            .set_default("log_level.default", "info")?
+            .set_default("log_level.revert_after_secs", 900)?
+            .set_default("log_level.max_revert_after_secs", 86400)?
+            .set_default("otel.enabled", false)?
+            .set_default("otel.endpoint", "http://localhost:4317")?
+            .set_default("otel.service_name", "actix-stateless-template")?
+            .set_default("otel.sample_ratio", 1.0)?
+            .set_default("otel.filter", "info")?
+            .set_default("otel.timeout_secs", 10)?
+            .set_default("propagation.headers", propagation::DEFAULT_HEADERS.to_vec())
+    }
+}
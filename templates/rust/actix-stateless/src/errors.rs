@@ -0,0 +1,4 @@
+//! Errors come from the shared `platform-core` crate, so this service
+//! reports the same variants and stable codes as the others.
+
+pub use platform_core::errors::{AppError, AppResult, ErrorCode, ErrorResponse, RetryHint};
@@ -0,0 +1,48 @@
+use actix_web::{get, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+
+#[derive(Serialize, Deserialize)]
+pub struct HealthResponse {
+    pub status: String,
+    pub version: String,
+    pub timestamp: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReadinessResponse {
+    /// `ready` or `not ready`.
+    pub status: String,
+    /// `up` or `down`.
+    pub items: String,
+    pub timestamp: String,
+}
+
+/// Liveness: the process is up and serving requests.
+#[get("/health")]
+pub async fn health_check() -> HttpResponse {
+    HttpResponse::Ok().json(HealthResponse {
+        status: "healthy".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+/// Readiness: whether the item store can serve requests. There's no
+/// database to check.
+#[get("/ready")]
+pub async fn readiness_check(app_state: web::Data<AppState>) -> HttpResponse {
+    let ready = app_state.items.ready().await;
+    let response = ReadinessResponse {
+        status: if ready { "ready" } else { "not ready" }.to_string(),
+        items: if ready { "up" } else { "down" }.to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    };
+
+    if ready {
+        HttpResponse::Ok().json(response)
+    } else {
+        HttpResponse::ServiceUnavailable().json(response)
+    }
+}
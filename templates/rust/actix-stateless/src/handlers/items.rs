@@ -0,0 +1,44 @@
+use actix_web::{get, post, web, HttpResponse};
+use uuid::Uuid;
+
+use crate::auth::Authenticated;
+use crate::errors::AppResult;
+use crate::items::NewItem;
+use crate::AppState;
+
+pub const READ_ITEMS: &str = "read:items";
+pub const WRITE_ITEMS: &str = "write:items";
+
+#[get("")]
+pub async fn list_items(app_state: web::Data<AppState>, auth: Authenticated) -> AppResult<HttpResponse> {
+    auth.0.require_scope(READ_ITEMS)?;
+    let items = app_state.items.list().await?;
+    Ok(HttpResponse::Ok().json(items))
+}
+
+#[get("/{id}")]
+pub async fn get_item(
+    app_state: web::Data<AppState>,
+    auth: Authenticated,
+    id: web::Path<Uuid>,
+) -> AppResult<HttpResponse> {
+    auth.0.require_scope(READ_ITEMS)?;
+    let item = app_state.items.get(id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(item))
+}
+
+/// Creates an item owned by the caller.
+#[post("")]
+pub async fn create_item(
+    app_state: web::Data<AppState>,
+    auth: Authenticated,
+    body: web::Json<NewItem>,
+) -> AppResult<HttpResponse> {
+    auth.0.require_scope(WRITE_ITEMS)?;
+    body.validate()?;
+
+    let item = app_state.items.create(&body, &auth.0.subject).await?;
+    tracing::info!(item_id = %item.id, owner = %item.owner, "Created item");
+
+    Ok(HttpResponse::Created().json(item))
+}
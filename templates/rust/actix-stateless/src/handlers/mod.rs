@@ -0,0 +1,23 @@
+use actix_web::dev::Service;
+use actix_web::web;
+use platform_observability::propagation;
+
+pub mod health;
+pub mod items;
+
+/// Registers the routes under `/api/v1`, each handled with its
+/// `propagation.headers` in scope.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/v1")
+            .wrap_fn(|req, srv| propagation::scope(crate::propagation::capture(&req), srv.call(req)))
+            .service(health::health_check)
+            .service(health::readiness_check)
+            .service(
+                web::scope("/items")
+                    .service(items::list_items)
+                    .service(items::create_item)
+                    .service(items::get_item),
+            ),
+    );
+}
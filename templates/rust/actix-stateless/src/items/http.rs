@@ -0,0 +1,96 @@
+use futures_util::future::BoxFuture;
+use reqwest::{Client, StatusCode};
+use serde_json::json;
+use std::time::Duration;
+use uuid::Uuid;
+
+use super::{item_not_found, Item, ItemStore, NewItem};
+use crate::config::ItemSettings;
+use crate::errors::{AppError, AppResult, ErrorCode, RetryHint};
+use crate::propagation::with_trace_context;
+
+/// Items kept by another service, at `{base_url}/items`.
+pub struct HttpItems {
+    client: Client,
+    base_url: String,
+}
+
+impl HttpItems {
+    pub fn from_settings(settings: &ItemSettings) -> anyhow::Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .build()?;
+
+        Ok(Self {
+            client,
+            base_url: settings.base_url.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+/// The items API failed or couldn't be reached; callers may retry.
+fn upstream_failed(error: impl std::fmt::Display) -> AppError {
+    tracing::warn!("Items API request failed: {}", error);
+    AppError::Unavailable {
+        message: "The items API is unavailable".to_string(),
+        retry: RetryHint::after(Duration::from_secs(5)),
+    }
+    .with_code(ErrorCode::ServiceUnavailable)
+}
+
+impl ItemStore for HttpItems {
+    fn list(&self) -> BoxFuture<'_, AppResult<Vec<Item>>> {
+        Box::pin(async move {
+            with_trace_context(self.client.get(format!("{}/items", self.base_url)))
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(upstream_failed)?
+                .json()
+                .await
+                .map_err(upstream_failed)
+        })
+    }
+
+    fn get(&self, id: Uuid) -> BoxFuture<'_, AppResult<Item>> {
+        Box::pin(async move {
+            let response = with_trace_context(self.client.get(format!("{}/items/{}", self.base_url, id)))
+                .send()
+                .await
+                .map_err(upstream_failed)?;
+            if response.status() == StatusCode::NOT_FOUND {
+                return Err(item_not_found());
+            }
+            response
+                .error_for_status()
+                .map_err(upstream_failed)?
+                .json()
+                .await
+                .map_err(upstream_failed)
+        })
+    }
+
+    fn create<'a>(&'a self, item: &'a NewItem, owner: &'a str) -> BoxFuture<'a, AppResult<Item>> {
+        Box::pin(async move {
+            with_trace_context(self.client.post(format!("{}/items", self.base_url)))
+                .json(&json!({ "name": item.name.trim(), "owner": owner }))
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(upstream_failed)?
+                .json()
+                .await
+                .map_err(upstream_failed)
+        })
+    }
+
+    fn ready(&self) -> BoxFuture<'_, bool> {
+        Box::pin(async move {
+            self.client
+                .get(format!("{}/items", self.base_url))
+                .send()
+                .await
+                .is_ok_and(|response| !response.status().is_server_error())
+        })
+    }
+}
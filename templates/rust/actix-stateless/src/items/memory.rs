@@ -0,0 +1,41 @@
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use uuid::Uuid;
+
+use super::{item_not_found, Item, ItemStore, NewItem};
+use crate::errors::AppResult;
+
+/// Items in a map, gone when the process exits.
+#[derive(Default)]
+pub struct InMemoryItems {
+    items: RwLock<HashMap<Uuid, Item>>,
+}
+
+impl ItemStore for InMemoryItems {
+    fn list(&self) -> BoxFuture<'_, AppResult<Vec<Item>>> {
+        Box::pin(async move {
+            let mut items: Vec<Item> = self.items.read().unwrap().values().cloned().collect();
+            items.sort_by_key(|item| (item.created_at, item.id));
+            Ok(items)
+        })
+    }
+
+    fn get(&self, id: Uuid) -> BoxFuture<'_, AppResult<Item>> {
+        Box::pin(async move { self.items.read().unwrap().get(&id).cloned().ok_or_else(item_not_found) })
+    }
+
+    fn create<'a>(&'a self, item: &'a NewItem, owner: &'a str) -> BoxFuture<'a, AppResult<Item>> {
+        Box::pin(async move {
+            let item = Item {
+                id: Uuid::new_v4(),
+                name: item.name.trim().to_string(),
+                owner: owner.to_string(),
+                created_at: Utc::now(),
+            };
+            self.items.write().unwrap().insert(item.id, item.clone());
+            Ok(item)
+        })
+    }
+}
@@ -0,0 +1,74 @@
+//! The example resource, behind [`ItemStore`] so handlers don't care where
+//! items live: [`InMemoryItems`] keeps them in the process,
+//! [`HttpItems`] asks another service. A real service replaces items with
+//! its own resources and stores, keeping the same shape.
+
+mod http;
+mod memory;
+
+pub use http::HttpItems;
+pub use memory::InMemoryItems;
+
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::config::{ItemBackend, ItemSettings};
+use crate::errors::{AppError, AppResult};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Item {
+    pub id: Uuid,
+    pub name: String,
+    /// Subject of the user who created it.
+    pub owner: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewItem {
+    pub name: String,
+}
+
+impl NewItem {
+    pub const MAX_NAME_CHARS: usize = 200;
+
+    pub fn validate(&self) -> AppResult<()> {
+        let chars = self.name.trim().chars().count();
+        if chars == 0 || chars > Self::MAX_NAME_CHARS {
+            return Err(AppError::ValidationError(format!(
+                "name: Name must be between 1 and {} characters",
+                Self::MAX_NAME_CHARS
+            )));
+        }
+        Ok(())
+    }
+}
+
+pub trait ItemStore: Send + Sync {
+    /// Items, oldest first.
+    fn list(&self) -> BoxFuture<'_, AppResult<Vec<Item>>>;
+
+    fn get(&self, id: Uuid) -> BoxFuture<'_, AppResult<Item>>;
+
+    fn create<'a>(&'a self, item: &'a NewItem, owner: &'a str) -> BoxFuture<'a, AppResult<Item>>;
+
+    /// Whether the store can serve requests, for readiness.
+    fn ready(&self) -> BoxFuture<'_, bool> {
+        Box::pin(async { true })
+    }
+}
+
+/// Builds the store selected by `items.backend`.
+pub fn from_settings(settings: &ItemSettings) -> anyhow::Result<Arc<dyn ItemStore>> {
+    match settings.backend {
+        ItemBackend::Memory => Ok(Arc::new(InMemoryItems::default())),
+        ItemBackend::Http => Ok(Arc::new(HttpItems::from_settings(settings)?)),
+    }
+}
+
+fn item_not_found() -> AppError {
+    AppError::NotFound("Item not found".to_string())
+}
@@ -0,0 +1,53 @@
+//! The actix template without a database, for services that orchestrate
+//! others instead of owning data: no sqlx, no migrations, users'
+//! tokens checked against an OpenID Connect provider's JWKS, and handlers
+//! backed by memory or another service's API.
+
+pub mod auth;
+pub mod config;
+pub mod errors;
+pub mod handlers;
+pub mod items;
+pub mod propagation;
+
+use actix_web::web;
+use platform_auth::oidc::OidcVerifier;
+use platform_core::clock::SharedClock;
+use std::sync::Arc;
+
+use crate::config::Settings;
+use crate::errors::AppError;
+use crate::items::ItemStore;
+
+pub struct AppState {
+    pub settings: Settings,
+    /// Checks users' tokens.
+    pub verifier: OidcVerifier,
+    pub items: Arc<dyn ItemStore>,
+    pub clock: SharedClock,
+}
+
+impl AppState {
+    pub fn new(settings: Settings, clock: SharedClock) -> anyhow::Result<Self> {
+        let verifier = OidcVerifier::from_settings(&settings.oidc, clock.clone())?;
+        let items = items::from_settings(&settings.items)?;
+
+        Ok(Self {
+            settings,
+            verifier,
+            items,
+            clock,
+        })
+    }
+}
+
+/// Everything an `App` needs: the state, JSON limits with errors in the
+/// shared format, and the routes.
+pub fn configure(state: web::Data<AppState>) -> impl FnOnce(&mut web::ServiceConfig) {
+    move |cfg| {
+        let json = web::JsonConfig::default()
+            .limit(state.settings.server.max_json_body_bytes)
+            .error_handler(|error, _| AppError::ValidationError(error.to_string()).into());
+        cfg.app_data(state).app_data(json).configure(handlers::configure);
+    }
+}
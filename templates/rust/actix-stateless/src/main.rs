@@ -0,0 +1,52 @@
+use actix_web::middleware::Logger;
+use actix_web::{web, App, HttpServer};
+use anyhow::Result;
+use dotenv::dotenv;
+use platform_core::clock::SystemClock;
+use platform_core::lifecycle::Lifecycle;
+use platform_observability::{log_level, otel};
+use std::sync::Arc;
+use tracing::info;
+
+use actix_stateless_template::config::Settings;
+use actix_stateless_template::AppState;
+
+#[actix_web::main]
+async fn main() -> Result<()> {
+    // Load environment variables
+    dotenv().ok();
+
+    // Load configuration, then tracing with the configured filter
+    let settings = Settings::new()?;
+    log_level::init(&settings.log_level, &settings.otel)?;
+
+    let app_state = web::Data::new(AppState::new(settings.clone(), Arc::new(SystemClock))?);
+    info!(
+        "Accepting tokens of {}; items are kept in {:?}",
+        settings.oidc.issuer, settings.items.backend
+    );
+
+    let mut lifecycle = Lifecycle::new(&settings.lifecycle);
+    lifecycle.start().await?;
+
+    // Stops on SIGINT or SIGTERM, after in-flight requests
+    let address = format!("{}:{}", settings.server.host, settings.server.port);
+    info!("Starting server at http://{}", address);
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(Logger::default())
+            .configure(actix_stateless_template::configure(app_state.clone()))
+    })
+    .workers(settings.server.workers)
+    .shutdown_timeout(settings.lifecycle.shutdown_timeout_secs)
+    .bind(&address)?
+    .run();
+    lifecycle.ready().await;
+    let result = server.await;
+
+    lifecycle.shutdown().await;
+    tokio::task::spawn_blocking(otel::shutdown).await?;
+    result?;
+
+    Ok(())
+}
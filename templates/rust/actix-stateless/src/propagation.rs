@@ -0,0 +1,50 @@
+//! Mesh and tracing headers of incoming requests handed on to the items
+//! API, so the mesh joins both calls into one trace.
+
+use actix_web::dev::ServiceRequest;
+use actix_web::web;
+use platform_observability::otel::{self, Extractor, Injector};
+use platform_observability::propagation::{self, Propagated};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::RequestBuilder;
+
+use crate::AppState;
+
+/// The `propagation.headers` of `req`, for [`propagation::scope`].
+pub fn capture(req: &ServiceRequest) -> Propagated {
+    match req.app_data::<web::Data<AppState>>() {
+        Some(state) => propagation::capture(&state.settings.propagation, &RequestHeaders(req.headers())),
+        None => Propagated::default(),
+    }
+}
+
+/// Adds the headers captured from the request being handled, then the
+/// current span's `traceparent`, to a request to another service.
+pub fn with_trace_context(request: RequestBuilder) -> RequestBuilder {
+    let mut headers = HeaderMap::new();
+    propagation::inject(&mut OutgoingHeaders(&mut headers));
+    otel::inject(&tracing::Span::current(), &mut OutgoingHeaders(&mut headers));
+    request.headers(headers)
+}
+
+struct RequestHeaders<'a>(&'a actix_web::http::header::HeaderMap);
+
+impl Extractor for RequestHeaders<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+struct OutgoingHeaders<'a>(&'a mut HeaderMap);
+
+impl Injector for OutgoingHeaders<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(&value)) {
+            self.0.insert(name, value);
+        }
+    }
+}
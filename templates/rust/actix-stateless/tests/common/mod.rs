@@ -0,0 +1,68 @@
+//! Test kit: the app with the items store in memory, or pointed at a fake
+//! items API, accepting tokens signed with a fixture key. Nothing binds a
+//! port except the fake API.
+
+#![allow(dead_code)]
+
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use chrono::Utc;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use platform_core::clock::SystemClock;
+use serde_json::json;
+use std::sync::Arc;
+
+use actix_stateless_template::config::Settings;
+use actix_stateless_template::AppState;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../platform-auth/tests/fixtures/workload");
+pub const ISSUER: &str = "https://id.example.com/";
+pub const AUDIENCE: &str = "https://items.example.com";
+
+pub fn settings() -> Settings {
+    Settings::defaults()
+        .and_then(|config| config.set_override("oidc.issuer", ISSUER))
+        .and_then(|config| config.set_override("oidc.audience", AUDIENCE))
+        .and_then(|config| config.set_override("oidc.keys.path", format!("{}/kubernetes-jwks.json", FIXTURES)))
+        .and_then(|config| config.build())
+        .and_then(|config| config.try_deserialize())
+        .unwrap()
+}
+
+pub struct TestApp {
+    pub state: web::Data<AppState>,
+}
+
+impl TestApp {
+    pub fn new(settings: Settings) -> Self {
+        let state = AppState::new(settings, Arc::new(SystemClock)).expect("app state");
+        Self {
+            state: web::Data::new(state),
+        }
+    }
+
+    /// Serves `request` as the server would; the body is `null` when it
+    /// isn't JSON.
+    pub async fn request(&self, request: test::TestRequest) -> (StatusCode, serde_json::Value) {
+        let app = test::init_service(App::new().configure(actix_stateless_template::configure(self.state.clone()))).await;
+        let response = test::call_service(&app, request.to_request()).await;
+        let status = response.status();
+        let body = test::read_body(response).await;
+        (status, serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null))
+    }
+}
+
+/// A provider token for `subject` granting `scopes`.
+pub fn token(subject: &str, scopes: &str) -> String {
+    let pem = std::fs::read(format!("{}/kubernetes.pem", FIXTURES)).unwrap();
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = Some("kube-1".to_string());
+    let claims = json!({
+        "iss": ISSUER,
+        "sub": subject,
+        "aud": AUDIENCE,
+        "exp": Utc::now().timestamp() + 600,
+        "scope": scopes,
+    });
+    encode(&header, &claims, &EncodingKey::from_ec_pem(&pem).unwrap()).unwrap()
+}
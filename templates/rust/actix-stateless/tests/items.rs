@@ -0,0 +1,176 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use serde_json::json;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use actix_stateless_template::config::ItemBackend;
+use common::{settings, token, TestApp};
+
+fn bearer(token: &str) -> (&'static str, String) {
+    ("Authorization", format!("Bearer {}", token))
+}
+
+/// An items API that knows one item and fails everything else.
+async fn items_api() -> SocketAddr {
+    let make_service = make_service_fn(|_| async {
+        Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+            let item = json!({
+                "id": "8d1c0a52-3c5e-4c1e-9b1e-0c7f7d7c2a10",
+                "name": "Upstream item",
+                "owner": "auth0|1",
+                "created_at": "2024-01-02T03:04:05Z",
+            });
+            let (status, body) = match request.uri().path() {
+                "/items" => (200, json!([item])),
+                "/items/8d1c0a52-3c5e-4c1e-9b1e-0c7f7d7c2a10" => (200, item),
+                path if path.starts_with("/items/") => (404, json!({ "error_code": "NOT_FOUND" })),
+                _ => (500, json!({})),
+            };
+            Ok::<_, Infallible>(Response::builder().status(status).body(Body::from(body.to_string())).unwrap())
+        }))
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+#[actix_web::test]
+async fn requests_need_a_provider_token_with_the_scope() {
+    let app = TestApp::new(settings());
+
+    let (status, body) = app.request(TestRequest::get().uri("/api/v1/items")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error_code"], "UNAUTHENTICATED");
+
+    let forged = format!("{}x", token("auth0|1", "read:items"));
+    let (status, body) = app.request(TestRequest::get().uri("/api/v1/items").insert_header(bearer(&forged))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error_code"], "INVALID_TOKEN");
+
+    let request = TestRequest::post()
+        .uri("/api/v1/items")
+        .insert_header(bearer(&token("auth0|1", "read:items")))
+        .set_json(json!({ "name": "Widget" }));
+    let (status, body) = app.request(request).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error_code"], "AUTH_INSUFFICIENT_SCOPE");
+}
+
+#[actix_web::test]
+async fn items_are_kept_in_memory_by_default() {
+    let app = TestApp::new(settings());
+    let token = token("auth0|1", "read:items write:items");
+
+    let request = TestRequest::post()
+        .uri("/api/v1/items")
+        .insert_header(bearer(&token))
+        .set_json(json!({ "name": "  Widget " }));
+    let (status, created) = app.request(request).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(created["name"], "Widget");
+    assert_eq!(created["owner"], "auth0|1");
+
+    let uri = format!("/api/v1/items/{}", created["id"].as_str().unwrap());
+    let (status, item) = app.request(TestRequest::get().uri(&uri).insert_header(bearer(&token))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(item, created);
+
+    let (_, items) = app.request(TestRequest::get().uri("/api/v1/items").insert_header(bearer(&token))).await;
+    assert_eq!(items, json!([created]));
+
+    let request = TestRequest::post()
+        .uri("/api/v1/items")
+        .insert_header(bearer(&token))
+        .set_json(json!({ "name": " " }));
+    let (status, body) = app.request(request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error_code"], "VALIDATION_FAILED");
+}
+
+#[actix_web::test]
+async fn the_http_backend_reads_another_services_items() {
+    let addr = items_api().await;
+    let mut settings = settings();
+    settings.items.backend = ItemBackend::Http;
+    settings.items.base_url = format!("http://{}/", addr);
+    let app = TestApp::new(settings);
+    let token = token("auth0|1", "read:items");
+
+    let (status, items) = app.request(TestRequest::get().uri("/api/v1/items").insert_header(bearer(&token))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(items[0]["name"], "Upstream item");
+
+    let uri = format!("/api/v1/items/{}", uuid::Uuid::new_v4());
+    let (status, body) = app.request(TestRequest::get().uri(&uri).insert_header(bearer(&token))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error_code"], "NOT_FOUND");
+
+    let (status, _) = app.request(TestRequest::get().uri("/api/v1/ready")).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn mesh_headers_are_handed_on_to_the_items_api() {
+    // Answers with the headers it was sent as the items
+    let make_service = make_service_fn(|_| async {
+        Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+            let items: Vec<_> = ["x-b3-traceid", "baggage", "authorization"]
+                .iter()
+                .filter(|name| request.headers().contains_key(**name))
+                .map(|name| {
+                    json!({
+                        "id": "8d1c0a52-3c5e-4c1e-9b1e-0c7f7d7c2a10",
+                        "name": format!("{}={}", name, request.headers()[*name].to_str().unwrap()),
+                        "owner": "auth0|1",
+                        "created_at": "2024-01-02T03:04:05Z",
+                    })
+                })
+                .collect();
+            Ok::<_, Infallible>(Response::new(Body::from(json!(items).to_string())))
+        }))
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+
+    let mut settings = settings();
+    settings.items.backend = ItemBackend::Http;
+    settings.items.base_url = format!("http://{}", addr);
+    let app = TestApp::new(settings);
+
+    let request = TestRequest::get()
+        .uri("/api/v1/items")
+        .insert_header(bearer(&token("auth0|1", "read:items")))
+        .insert_header(("X-B3-TraceId", "80f198ee56343ba864fe8b2a57d3eff7"))
+        .insert_header(("baggage", "tenant=acme"));
+    let (status, items) = app.request(request).await;
+    assert_eq!(status, StatusCode::OK);
+    let names: Vec<_> = items.as_array().unwrap().iter().map(|item| item["name"].as_str().unwrap()).collect();
+    // The caller's token stays here
+    assert_eq!(names, ["x-b3-traceid=80f198ee56343ba864fe8b2a57d3eff7", "baggage=tenant=acme"]);
+}
+
+#[actix_web::test]
+async fn an_unreachable_items_api_is_unavailable_not_an_error() {
+    let mut settings = settings();
+    settings.items.backend = ItemBackend::Http;
+    settings.items.base_url = "http://127.0.0.1:1".to_string();
+    let app = TestApp::new(settings);
+
+    let request = TestRequest::get()
+        .uri("/api/v1/items")
+        .insert_header(bearer(&token("auth0|1", "read:items")));
+    let (status, body) = app.request(request).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["error_code"], "SERVICE_UNAVAILABLE");
+
+    let (status, body) = app.request(TestRequest::get().uri("/api/v1/ready")).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["items"], "down");
+}
@@ -0,0 +1,46 @@
+//! Embeds the parts of the template manifest only known at build time; see
+//! `src/manifest.rs`.
+
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+/// Sources defining the routes and the types they accept and return.
+const API_SOURCES: [&str; 2] = ["src/handlers", "src/models"];
+
+fn main() {
+    // `#[table]` models are checked against the migrations, and new files
+    // don't touch any source
+    println!("cargo:rerun-if-changed=migrations");
+
+    let mut files = Vec::new();
+    for dir in API_SOURCES {
+        println!("cargo:rerun-if-changed={}", dir);
+        collect(Path::new(dir), &mut files);
+    }
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for file in &files {
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update(fs::read(file).expect("readable API source"));
+    }
+    println!("cargo:rustc-env=TEMPLATE_API_HASH={:x}", hasher.finalize());
+
+    let mut features: Vec<_> = env::vars()
+        .filter_map(|(key, _)| Some(key.strip_prefix("CARGO_FEATURE_")?.to_lowercase().replace('_', "-")))
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=TEMPLATE_FEATURES={}", features.join(","));
+}
+
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).expect("readable API source directory") {
+        let path = entry.expect("readable directory entry").path();
+        if path.is_dir() {
+            collect(&path, files);
+        } else if path.extension().is_some_and(|extension| extension == "rs") {
+            files.push(path);
+        }
+    }
+}
@@ -0,0 +1,454 @@
+//! Attribute macros applying the template's cross-cutting concerns to a
+//! single handler, next to its route attribute:
+//!
+//! ```ignore
+//! #[cached(ttl = "30s", tags = ["users:list"])]
+//! #[authorize("read:users")]
+//! #[get("")]
+//! pub async fn get_users(app_state: web::Data<AppState>) -> AppResult<HttpResponse> {
+//!     ...
+//! }
+//! ```
+//!
+//! Both use the handler's `HttpRequest` argument, or add one.
+//!
+//! `#[table("users")]` checks a `FromRow` model against the table the
+//! migrations create, so a renamed column fails the build instead of the
+//! first query decoding it.
+//!
+//! The generated code calls into `actix_template::cache` and
+//! `actix_template::middleware::auth`, so the macros work in the template
+//! (which names itself `actix_template` for this) and its tests.
+
+mod schema;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote, quote_spanned};
+use std::path::PathBuf;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Field, FnArg, GenericArgument, Ident, ItemFn, ItemStruct, LitStr, Pat,
+    PathArguments, Token, Type,
+};
+
+use schema::Column;
+
+/// Serves the handler's successful responses from the response cache for
+/// `ttl` (`"500ms"`, `"30s"`, `"5m"`, `"1h"`). Responses are cached per
+/// method, path, query and credential, so users never see each other's.
+///
+/// `tags = ["users:list", "user:{id}"]` labels the responses for
+/// `ResponseCache::invalidate_tag`; `{id}` is the route's `id` parameter.
+#[proc_macro_attribute]
+pub fn cached(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut ttl = None;
+    let mut tags = Vec::new();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("ttl") {
+            let value: LitStr = meta.value()?.parse()?;
+            ttl = Some(parse_duration(&value)?);
+            Ok(())
+        } else if meta.path.is_ident("tags") {
+            let value = meta.value()?;
+            let content;
+            syn::bracketed!(content in value);
+            for tag in Punctuated::<LitStr, Token![,]>::parse_terminated(&content)? {
+                check_tag(&tag)?;
+                tags.push(tag);
+            }
+            Ok(())
+        } else {
+            Err(meta.error("expected `ttl = \"30s\"` or `tags = [\"...\"]`"))
+        }
+    });
+    parse_macro_input!(args with parser);
+    let mut handler = parse_macro_input!(item as ItemFn);
+
+    let Some(ttl_ms) = ttl else {
+        return error(Span::call_site(), "missing `ttl`, e.g. #[cached(ttl = \"30s\")]");
+    };
+    if handler.sig.asyncness.is_none() {
+        return error(handler.sig.fn_token.span, "#[cached] handlers must be async");
+    }
+
+    let request = request_arg(&mut handler);
+    let block = &handler.block;
+    handler.block = parse_quote!({
+        let __cached_request = #request.clone();
+        ::actix_template::cache::cached(
+            &__cached_request,
+            ::std::time::Duration::from_millis(#ttl_ms),
+            &[#(#tags),*],
+            async move #block,
+        )
+        .await
+    });
+
+    quote!(#handler).into()
+}
+
+/// Rejects callers whose credential doesn't grant `scope`, written as the
+/// API token scopes are (`"read:users"`). Unknown scopes fail to compile.
+///
+/// Authorization always runs before a cache lookup, whichever order this
+/// and `#[cached]` are written in.
+#[proc_macro_attribute]
+pub fn authorize(args: TokenStream, item: TokenStream) -> TokenStream {
+    let scope = parse_macro_input!(args as LitStr);
+    let mut handler = parse_macro_input!(item as ItemFn);
+
+    // `#[cached]` below this attribute would wrap the check; have it expand
+    // first so the check lands in front of the lookup
+    if let Some(position) = handler.attrs.iter().position(is_cached) {
+        let cached = handler.attrs.remove(position);
+        return quote!(#cached #[authorize(#scope)] #handler).into();
+    }
+
+    let variant = match scope_variant(&scope) {
+        Ok(variant) => variant,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let request = request_arg(&mut handler);
+    let check = quote_spanned!(scope.span()=>
+        ::actix_template::middleware::auth::require_scope(&#request, ::actix_template::models::api_token::Scope::#variant)?;
+    );
+    handler.block.stmts.insert(0, syn::parse2(check).expect("a statement"));
+
+    quote!(#handler).into()
+}
+
+/// Checks the model's fields against the `table` the crate's `migrations`
+/// leave behind: every field must name a column (after `#[sqlx(rename)]`;
+/// `skip` and `flatten` fields are left alone), nullable columns must be
+/// read into an `Option`, and fields of plain types (`i32`, `String`,
+/// `DateTime<Utc>`, ...) must match the column's type. Newtypes such as
+/// `UserId` aren't type-checked.
+///
+/// Errors list the table's columns as fields, ready to paste. Columns the
+/// model leaves out are fine, as they are for `SELECT *`.
+#[proc_macro_attribute]
+pub fn table(args: TokenStream, item: TokenStream) -> TokenStream {
+    let table = parse_macro_input!(args as LitStr);
+    let model = parse_macro_input!(item as ItemStruct);
+
+    let dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default()).join("migrations");
+    let tables = match schema::load(&dir) {
+        Ok(tables) => tables,
+        Err(e) => return error(table.span(), &e),
+    };
+    let Some(columns) = tables.get(&table.value()) else {
+        let names: Vec<_> = tables.keys().map(String::as_str).collect();
+        let message = format!("the migrations create no table `{}`; they create {}", table.value(), names.join(", "));
+        return error(table.span(), &message);
+    };
+
+    let mut errors: Option<syn::Error> = None;
+    for field in &model.fields {
+        if let Err(e) = check_field(&table.value(), columns, field) {
+            match &mut errors {
+                Some(errors) => errors.combine(e),
+                None => errors = Some(e),
+            }
+        }
+    }
+    let errors = errors.map(|e| e.to_compile_error());
+
+    quote!(#errors #model).into()
+}
+
+fn check_field(table: &str, columns: &[Column], field: &Field) -> syn::Result<()> {
+    let Some(ident) = &field.ident else {
+        return Err(syn::Error::new(field.span(), "#[table] models have named fields"));
+    };
+    let mut name = ident.to_string();
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("sqlx")) {
+        let mut skipped = false;
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                name = meta.value()?.parse::<LitStr>()?.value();
+            } else if meta.path.is_ident("skip") || meta.path.is_ident("flatten") {
+                skipped = true;
+            } else if meta.input.peek(Token![=]) {
+                meta.value()?.parse::<syn::Expr>()?;
+            }
+            Ok(())
+        })?;
+        if skipped {
+            return Ok(());
+        }
+    }
+
+    let Some(column) = columns.iter().find(|column| column.name == name) else {
+        let message = format!("`{}` has no column `{}`; its columns are:\n{}", table, name, column_fields(columns));
+        return Err(syn::Error::new(ident.span(), message));
+    };
+
+    let (optional, inner) = match generic_arg(&field.ty, "Option") {
+        Some(inner) => (true, inner),
+        None => (false, &field.ty),
+    };
+    if column.nullable && !optional {
+        let message = format!("`{}.{}` is nullable; read it as Option<{}>", table, name, type_name(inner));
+        return Err(syn::Error::new(field.ty.span(), message));
+    }
+    if rust_type_matches(inner, &column.sql_type) == Some(false) {
+        let message = format!(
+            "`{}.{}` is {}, read as {}",
+            table,
+            name,
+            column.sql_type,
+            rust_type(&column.sql_type).unwrap_or("a type implementing sqlx::Decode for it")
+        );
+        return Err(syn::Error::new(inner.span(), message));
+    }
+    Ok(())
+}
+
+/// `DateTime<Utc>` rather than `DateTime < Utc >`.
+fn type_name(ty: &Type) -> String {
+    quote!(#ty).to_string().replace(" < ", "<").replace(" >", ">").replace(" :: ", "::")
+}
+
+/// `pub name: Type,` for each column.
+fn column_fields(columns: &[Column]) -> String {
+    columns
+        .iter()
+        .map(|column| {
+            let rust = rust_type(&column.sql_type).unwrap_or("_");
+            match column.nullable {
+                true => format!("    pub {}: Option<{}>,", column.name, rust),
+                false => format!("    pub {}: {},", column.name, rust),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The type sqlx decodes a Postgres column type into.
+fn rust_type(sql_type: &str) -> Option<&'static str> {
+    if let Some(element) = sql_type.strip_suffix("[]") {
+        return match rust_type(element)? {
+            "String" => Some("Vec<String>"),
+            "Uuid" => Some("Vec<Uuid>"),
+            "i32" => Some("Vec<i32>"),
+            "i64" => Some("Vec<i64>"),
+            _ => None,
+        };
+    }
+    let base = sql_type.split('(').next().unwrap_or_default().trim();
+    Some(match base {
+        "BOOLEAN" | "BOOL" => "bool",
+        "SMALLINT" | "INT2" | "SMALLSERIAL" => "i16",
+        "INTEGER" | "INT" | "INT4" | "SERIAL" => "i32",
+        "BIGINT" | "INT8" | "BIGSERIAL" => "i64",
+        "REAL" | "FLOAT4" => "f32",
+        "DOUBLE PRECISION" | "FLOAT8" => "f64",
+        "TEXT" | "VARCHAR" | "CHARACTER VARYING" | "CHAR" | "CHARACTER" | "CITEXT" => "String",
+        "UUID" => "Uuid",
+        "BYTEA" => "Vec<u8>",
+        "JSON" | "JSONB" => "serde_json::Value",
+        "TIMESTAMP WITH TIME ZONE" | "TIMESTAMPTZ" => "DateTime<Utc>",
+        "TIMESTAMP" | "TIMESTAMP WITHOUT TIME ZONE" => "NaiveDateTime",
+        "DATE" => "NaiveDate",
+        _ => return None,
+    })
+}
+
+/// Whether `ty` decodes `sql_type`, or `None` for types not known here
+/// (newtypes, `Json<T>`, ...).
+fn rust_type_matches(ty: &Type, sql_type: &str) -> Option<bool> {
+    let Type::Path(path) = ty else { return None };
+    let name = path.path.segments.last()?.ident.to_string();
+    let expected = rust_type(sql_type);
+    if name == "Vec" {
+        let element = generic_arg(ty, "Vec")?;
+        return match sql_type.strip_suffix("[]") {
+            Some(sql_element) => rust_type_matches(element, sql_element),
+            None if expected == Some("Vec<u8>") => None,
+            None => Some(false),
+        };
+    }
+    if name == "Json" {
+        return Some(matches!(expected, Some("serde_json::Value")));
+    }
+    let known = ["bool", "i16", "i32", "i64", "f32", "f64", "String", "Uuid", "Value", "DateTime", "NaiveDateTime", "NaiveDate"];
+    if !known.contains(&name.as_str()) {
+        return None;
+    }
+    let expected = expected?;
+    let expected = expected.split('<').next().unwrap_or_default().rsplit("::").next().unwrap_or_default();
+    Some(expected == name)
+}
+
+/// `T` in `Wrapper<T>`.
+fn generic_arg<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+    let Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last()?;
+    if segment.ident != wrapper {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else { return None };
+    match args.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    }
+}
+
+/// The handler's `HttpRequest` argument, added if it has none.
+fn request_arg(handler: &mut ItemFn) -> Ident {
+    for input in &handler.sig.inputs {
+        if let FnArg::Typed(arg) = input {
+            let is_request = match arg.ty.as_ref() {
+                Type::Path(path) => path.path.segments.last().is_some_and(|segment| segment.ident == "HttpRequest"),
+                _ => false,
+            };
+            if let (true, Pat::Ident(pat)) = (is_request, arg.pat.as_ref()) {
+                return pat.ident.clone();
+            }
+        }
+    }
+
+    let request = format_ident!("__request");
+    handler.sig.inputs.push(parse_quote!(#request: ::actix_web::HttpRequest));
+    request
+}
+
+fn is_cached(attr: &Attribute) -> bool {
+    attr.path().segments.last().is_some_and(|segment| segment.ident == "cached")
+}
+
+/// `"read:users"` -> `ReadUsers`.
+fn scope_variant(scope: &LitStr) -> syn::Result<Ident> {
+    let value = scope.value();
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() != 2 || parts.iter().any(|part| part.is_empty() || !part.chars().all(|c| c.is_ascii_lowercase())) {
+        return Err(syn::Error::new(scope.span(), "expected a scope such as \"read:users\""));
+    }
+
+    let name: String = parts
+        .iter()
+        .map(|part| part[..1].to_ascii_uppercase() + &part[1..])
+        .collect();
+    Ok(Ident::new(&name, scope.span()))
+}
+
+/// Tags are non-empty and their `{param}` placeholders are closed and named.
+fn check_tag(tag: &LitStr) -> syn::Result<()> {
+    let value = tag.value();
+    let mut placeholder: Option<usize> = None;
+    let mut valid = !value.is_empty();
+    for c in value.chars() {
+        placeholder = match (placeholder, c) {
+            (None, '{') => Some(0),
+            (None, '}') | (Some(_), '{') | (Some(0), '}') => {
+                valid = false;
+                break;
+            }
+            (Some(_), '}') => None,
+            (Some(length), _) => Some(length + 1),
+            (None, _) => None,
+        };
+    }
+    if !valid || placeholder.is_some() {
+        return Err(syn::Error::new(tag.span(), "expected a tag such as \"users:list\" or \"user:{id}\""));
+    }
+    Ok(())
+}
+
+/// Milliseconds in `"500ms"`, `"30s"`, `"5m"` or `"1h"`.
+fn parse_duration(value: &LitStr) -> syn::Result<u64> {
+    let text = value.value();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (amount, unit) = text.split_at(split);
+    let unit_ms = match unit {
+        "ms" => 1,
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        _ => return Err(syn::Error::new(value.span(), "expected a duration such as \"30s\", \"5m\" or \"500ms\"")),
+    };
+    match amount.parse::<u64>() {
+        Ok(amount) if amount > 0 => Ok(amount * unit_ms),
+        _ => Err(syn::Error::new(value.span(), "expected a positive duration")),
+    }
+}
+
+fn error(span: Span, message: &str) -> TokenStream {
+    syn::Error::new(span, message).to_compile_error().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lit(value: &str) -> LitStr {
+        LitStr::new(value, Span::call_site())
+    }
+
+    #[test]
+    fn durations_take_a_unit() {
+        assert_eq!(parse_duration(&lit("500ms")).unwrap(), 500);
+        assert_eq!(parse_duration(&lit("30s")).unwrap(), 30_000);
+        assert_eq!(parse_duration(&lit("5m")).unwrap(), 300_000);
+        assert_eq!(parse_duration(&lit("1h")).unwrap(), 3_600_000);
+        assert!(parse_duration(&lit("30")).is_err());
+        assert!(parse_duration(&lit("0s")).is_err());
+        assert!(parse_duration(&lit("1d")).is_err());
+    }
+
+    #[test]
+    fn tags_close_their_placeholders() {
+        assert!(check_tag(&lit("users:list")).is_ok());
+        assert!(check_tag(&lit("user:{id}")).is_ok());
+        assert!(check_tag(&lit("")).is_err());
+        assert!(check_tag(&lit("user:{id")).is_err());
+        assert!(check_tag(&lit("user:{}")).is_err());
+        assert!(check_tag(&lit("user:{{id}}")).is_err());
+    }
+
+    #[test]
+    fn column_types_map_to_the_types_sqlx_decodes() {
+        let matches = |ty: Type, sql_type| rust_type_matches(&ty, sql_type);
+        assert_eq!(matches(parse_quote!(i32), "INTEGER"), Some(true));
+        assert_eq!(matches(parse_quote!(i64), "INTEGER"), Some(false));
+        assert_eq!(matches(parse_quote!(String), "VARCHAR(255)"), Some(true));
+        assert_eq!(matches(parse_quote!(DateTime<Utc>), "TIMESTAMP WITH TIME ZONE"), Some(true));
+        assert_eq!(matches(parse_quote!(serde_json::Value), "JSONB"), Some(true));
+        assert_eq!(matches(parse_quote!(Json<DeviceInfo>), "JSONB"), Some(true));
+        assert_eq!(matches(parse_quote!(Json<DeviceInfo>), "TEXT"), Some(false));
+        assert_eq!(matches(parse_quote!(Vec<String>), "TEXT[]"), Some(true));
+        assert_eq!(matches(parse_quote!(Vec<String>), "TEXT"), Some(false));
+        assert_eq!(matches(parse_quote!(UserId), "UUID"), None);
+    }
+
+    #[test]
+    fn fields_must_name_a_column_of_their_nullability() {
+        let columns = [
+            Column { name: "id".to_string(), sql_type: "UUID".to_string(), nullable: false },
+            Column { name: "full_name".to_string(), sql_type: "TEXT".to_string(), nullable: true },
+        ];
+        let check = |field: syn::FieldsNamed| check_field("users", &columns, &field.named[0]);
+
+        assert!(check(parse_quote!({ pub id: Uuid })).is_ok());
+        assert!(check(parse_quote!({ pub full_name: Option<String> })).is_ok());
+        assert!(check(parse_quote!({ #[sqlx(rename = "full_name")] pub name: Option<String> })).is_ok());
+        assert!(check(parse_quote!({ #[sqlx(skip)] pub extra: bool })).is_ok());
+
+        let error = check(parse_quote!({ pub name: Option<String> })).unwrap_err().to_string();
+        assert!(error.contains("no column `name`"), "{}", error);
+        assert!(error.contains("pub full_name: Option<String>,"), "{}", error);
+        let error = check(parse_quote!({ pub full_name: String })).unwrap_err().to_string();
+        assert!(error.contains("read it as Option<String>"), "{}", error);
+        assert!(check(parse_quote!({ pub id: i64 })).is_err());
+    }
+
+    #[test]
+    fn scopes_name_their_variant() {
+        assert_eq!(scope_variant(&lit("read:users")).unwrap(), "ReadUsers");
+        assert_eq!(scope_variant(&lit("write:profile")).unwrap(), "WriteProfile");
+        assert!(scope_variant(&lit("users")).is_err());
+        assert!(scope_variant(&lit("Read:users")).is_err());
+    }
+}
@@ -0,0 +1,451 @@
+//! The tables the migrations leave behind, replayed from their SQL for
+//! `#[table]`.
+//!
+//! Only the statements shaping tables are followed: `CREATE TABLE`,
+//! `DROP TABLE` and the `ALTER TABLE` actions adding, dropping, renaming and
+//! retyping columns or changing their nullability. Everything else (indexes,
+//! functions, `DO` blocks, data changes) is skipped.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    /// As written in the migration, upper-cased: `VARCHAR(255)`, `TEXT[]`.
+    pub sql_type: String,
+    pub nullable: bool,
+}
+
+pub type Tables = BTreeMap<String, Vec<Column>>;
+
+/// Replays the `*.sql` files in `dir` in name order, as sqlx applies them.
+pub fn load(dir: &Path) -> Result<Tables, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("cannot read {}: {}", dir.display(), e))?;
+    let mut files: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "sql"))
+        .collect();
+    files.sort();
+
+    let mut tables = Tables::new();
+    for file in files {
+        let sql = fs::read_to_string(&file).map_err(|e| format!("cannot read {}: {}", file.display(), e))?;
+        replay(&mut tables, &sql);
+    }
+    Ok(tables)
+}
+
+pub fn replay(tables: &mut Tables, sql: &str) {
+    for statement in statements(sql) {
+        let mut tokens = Tokens(&statement);
+        if tokens.keywords(&["CREATE", "TABLE"]) {
+            create_table(tables, &mut tokens);
+        } else if tokens.keywords(&["ALTER", "TABLE"]) {
+            alter_table(tables, &mut tokens);
+        } else if tokens.keywords(&["DROP", "TABLE"]) {
+            tokens.keywords(&["IF", "EXISTS"]);
+            for name in tokens.split(",") {
+                if let Some(name) = Tokens(name).next() {
+                    tables.remove(&ident(name));
+                }
+            }
+        }
+    }
+}
+
+fn create_table(tables: &mut Tables, tokens: &mut Tokens) {
+    let if_not_exists = tokens.keywords(&["IF", "NOT", "EXISTS"]);
+    let Some(name) = tokens.next() else { return };
+    let name = ident(name);
+    // Partitions share their parent's columns
+    if tokens.peek_keyword("PARTITION") || (if_not_exists && tables.contains_key(&name)) {
+        return;
+    }
+    let Some(body) = tokens.parenthesized() else { return };
+
+    let mut columns = Vec::new();
+    let mut primary_key = Vec::new();
+    for element in Tokens(body).split(",") {
+        let mut element = Tokens(element);
+        if element.keywords(&["PRIMARY", "KEY"]) {
+            primary_key.extend(element.parenthesized().unwrap_or_default().iter().filter(|t| *t != ",").map(ident));
+        } else if !is_table_constraint(&element) {
+            columns.extend(column(element.0));
+        }
+    }
+    for column in &mut columns {
+        column.nullable &= !primary_key.contains(&column.name);
+    }
+    tables.insert(name, columns);
+}
+
+fn alter_table(tables: &mut Tables, tokens: &mut Tokens) {
+    tokens.keywords(&["IF", "EXISTS"]);
+    tokens.keywords(&["ONLY"]);
+    let Some(name) = tokens.next() else { return };
+    let name = ident(name);
+
+    for action in tokens.split(",") {
+        let mut action = Tokens(action);
+        if action.keywords(&["RENAME", "TO"]) {
+            if let (Some(columns), Some(to)) = (tables.remove(&name), action.next()) {
+                tables.insert(ident(to), columns);
+            }
+            return;
+        }
+        let Some(columns) = tables.get_mut(&name) else { return };
+
+        if action.keywords(&["ADD"]) {
+            if is_table_constraint(&action) {
+                continue;
+            }
+            action.keywords(&["COLUMN"]);
+            let if_not_exists = action.keywords(&["IF", "NOT", "EXISTS"]);
+            if let Some(column) = column(action.0) {
+                match columns.iter().position(|c| c.name == column.name) {
+                    Some(_) if if_not_exists => {}
+                    Some(position) => columns[position] = column,
+                    None => columns.push(column),
+                }
+            }
+        } else if action.keywords(&["DROP"]) {
+            if action.peek_keyword("CONSTRAINT") {
+                continue;
+            }
+            action.keywords(&["COLUMN"]);
+            action.keywords(&["IF", "EXISTS"]);
+            if let Some(dropped) = action.next().map(ident) {
+                columns.retain(|c| c.name != dropped);
+            }
+        } else if action.keywords(&["RENAME"]) {
+            if action.peek_keyword("CONSTRAINT") {
+                continue;
+            }
+            action.keywords(&["COLUMN"]);
+            let (Some(from), true, Some(to)) = (action.next().map(ident), action.keywords(&["TO"]), action.next()) else {
+                continue;
+            };
+            if let Some(column) = columns.iter_mut().find(|c| c.name == from) {
+                column.name = ident(to);
+            }
+        } else if action.keywords(&["ALTER"]) {
+            action.keywords(&["COLUMN"]);
+            let Some(altered) = action.next().map(ident) else { continue };
+            let Some(column) = columns.iter_mut().find(|c| c.name == altered) else { continue };
+            if action.keywords(&["SET", "NOT", "NULL"]) {
+                column.nullable = false;
+            } else if action.keywords(&["DROP", "NOT", "NULL"]) {
+                column.nullable = true;
+            } else if action.keywords(&["SET", "DATA", "TYPE"]) || action.keywords(&["TYPE"]) {
+                let end = action.0.iter().position(|t| keyword(t, "USING") || keyword(t, "COLLATE"));
+                column.sql_type = sql_type(&action.0[..end.unwrap_or(action.0.len())]);
+            }
+        }
+    }
+}
+
+fn is_table_constraint(tokens: &Tokens) -> bool {
+    ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN", "EXCLUDE", "LIKE"]
+        .iter()
+        .any(|word| tokens.peek_keyword(word))
+}
+
+/// `name TYPE [constraints]`.
+fn column(tokens: &[String]) -> Option<Column> {
+    let (name, rest) = tokens.split_first()?;
+    let end = rest
+        .iter()
+        .position(|t| {
+            ["NOT", "NULL", "PRIMARY", "UNIQUE", "DEFAULT", "REFERENCES", "CHECK", "CONSTRAINT", "GENERATED", "COLLATE"]
+                .iter()
+                .any(|word| keyword(t, word))
+        })
+        .unwrap_or(rest.len());
+    let (column_type, constraints) = rest.split_at(end);
+
+    // Default expressions and checks can mention NOT NULL too; only look at
+    // the constraints' own keywords
+    let mut nullable = true;
+    let mut depth = 0;
+    for (i, token) in constraints.iter().enumerate() {
+        match token.as_str() {
+            "(" => depth += 1,
+            ")" => depth -= 1,
+            _ if depth == 0 && keyword(token, "NOT") && constraints.get(i + 1).is_some_and(|t| keyword(t, "NULL")) => {
+                nullable = false
+            }
+            _ if depth == 0 && keyword(token, "PRIMARY") => nullable = false,
+            _ => {}
+        }
+    }
+
+    Some(Column { name: ident(name), sql_type: sql_type(column_type), nullable })
+}
+
+fn sql_type(tokens: &[String]) -> String {
+    let mut text = String::new();
+    for token in tokens {
+        let word = token.chars().next().is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '"');
+        if word && text.ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+            text.push(' ');
+        }
+        text.push_str(token);
+    }
+    text.to_ascii_uppercase()
+}
+
+fn keyword(token: &str, word: &str) -> bool {
+    token.eq_ignore_ascii_case(word)
+}
+
+/// Unquoted identifiers fold to lower case.
+fn ident(token: impl AsRef<str>) -> String {
+    let token = token.as_ref();
+    match token.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        Some(quoted) => quoted.replace("\"\"", "\""),
+        None => token.to_ascii_lowercase(),
+    }
+}
+
+struct Tokens<'a>(&'a [String]);
+
+impl<'a> Tokens<'a> {
+    /// The next token; schema-qualified names (`public.users`) read as
+    /// their last part.
+    fn next(&mut self) -> Option<&'a String> {
+        let (first, rest) = self.0.split_first()?;
+        if rest.first().is_some_and(|t| t == ".") && rest.len() > 1 {
+            self.0 = &rest[2..];
+            return Some(&rest[1]);
+        }
+        self.0 = rest;
+        Some(first)
+    }
+
+    fn peek_keyword(&self, word: &str) -> bool {
+        self.0.first().is_some_and(|t| keyword(t, word))
+    }
+
+    /// Consumes `words` if the tokens start with them.
+    fn keywords(&mut self, words: &[&str]) -> bool {
+        let matches = self.0.len() >= words.len() && self.0.iter().zip(words).all(|(t, word)| keyword(t, word));
+        if matches {
+            self.0 = &self.0[words.len()..];
+        }
+        matches
+    }
+
+    /// The tokens inside the parentheses the tokens start with.
+    fn parenthesized(&mut self) -> Option<&'a [String]> {
+        if self.0.first()? != "(" {
+            return None;
+        }
+        let mut depth = 0;
+        for (i, token) in self.0.iter().enumerate() {
+            match token.as_str() {
+                "(" => depth += 1,
+                ")" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let inside = &self.0[1..i];
+                        self.0 = &self.0[i + 1..];
+                        return Some(inside);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// The rest, split at `separator`s outside parentheses.
+    fn split(&self, separator: &str) -> Vec<&'a [String]> {
+        let mut parts = Vec::new();
+        let (mut start, mut depth) = (0, 0);
+        for (i, token) in self.0.iter().enumerate() {
+            match token.as_str() {
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                t if t == separator && depth == 0 => {
+                    parts.push(&self.0[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        parts.push(&self.0[start..]);
+        parts.into_iter().filter(|part| !part.is_empty()).collect()
+    }
+}
+
+/// The statements in `sql`, tokenized, with comments dropped. String
+/// literals, quoted identifiers and dollar-quoted bodies are single tokens.
+fn statements(sql: &str) -> Vec<Vec<String>> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut statements = Vec::new();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i += 2;
+            continue;
+        } else if c == ';' {
+            statements.push(std::mem::take(&mut tokens));
+            i += 1;
+            continue;
+        } else if c == '\'' || c == '"' {
+            i += 1;
+            while i < chars.len() {
+                if chars[i] == c && chars.get(i + 1) == Some(&c) {
+                    i += 2;
+                } else if chars[i] == c {
+                    i += 1;
+                    break;
+                } else {
+                    i += 1;
+                }
+            }
+        } else if c == '$' && dollar_tag(&chars[i..]).is_some() {
+            let tag = dollar_tag(&chars[i..]).unwrap_or_default();
+            i += tag.len();
+            while i < chars.len() && !chars[i..].starts_with(&tag) {
+                i += 1;
+            }
+            i = (i + tag.len()).min(chars.len());
+        } else if c.is_alphanumeric() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$') {
+                i += 1;
+            }
+        } else {
+            i += 1;
+        }
+        tokens.push(chars[start..i].iter().collect());
+    }
+    statements.push(tokens);
+    statements.retain(|statement| !statement.is_empty());
+    statements
+}
+
+/// `$$` or `$body$` opening a dollar-quoted string.
+fn dollar_tag(chars: &[char]) -> Option<Vec<char>> {
+    let end = chars[1..].iter().position(|c| !(c.is_alphanumeric() || *c == '_'))? + 1;
+    (chars[end] == '$' && !chars[1].is_ascii_digit()).then(|| chars[..=end].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tables(sql: &str) -> Tables {
+        let mut tables = Tables::new();
+        replay(&mut tables, sql);
+        tables
+    }
+
+    fn columns(tables: &Tables, table: &str) -> Vec<(String, String, bool)> {
+        tables[table]
+            .iter()
+            .map(|c| (c.name.clone(), c.sql_type.clone(), c.nullable))
+            .collect()
+    }
+
+    fn column(name: &str, sql_type: &str, nullable: bool) -> (String, String, bool) {
+        (name.to_string(), sql_type.to_string(), nullable)
+    }
+
+    #[test]
+    fn created_tables_list_their_columns() {
+        let tables = tables(
+            "-- Users; the semicolon in this comment doesn't end anything
+            CREATE TABLE IF NOT EXISTS public.users (
+                id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+                email VARCHAR(255) UNIQUE NOT NULL,
+                scopes TEXT[] NOT NULL DEFAULT '{}',
+                progress INTEGER CHECK (progress IS NOT NULL),
+                created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+                \"Quoted\" jsonb,
+                CONSTRAINT users_email_check CHECK (email <> '')
+            );
+            CREATE TABLE events (id UUID NOT NULL, at TIMESTAMPTZ, PRIMARY KEY (id, at)) PARTITION BY RANGE (at);
+            CREATE TABLE events_default PARTITION OF events DEFAULT;",
+        );
+
+        assert_eq!(tables.keys().collect::<Vec<_>>(), ["events", "users"]);
+        assert_eq!(
+            columns(&tables, "users"),
+            [
+                column("id", "UUID", false),
+                column("email", "VARCHAR(255)", false),
+                column("scopes", "TEXT[]", false),
+                column("progress", "INTEGER", true),
+                column("created_at", "TIMESTAMP WITH TIME ZONE", true),
+                column("Quoted", "JSONB", true),
+            ]
+        );
+        assert_eq!(columns(&tables, "events"), [column("id", "UUID", false), column("at", "TIMESTAMPTZ", false)]);
+    }
+
+    #[test]
+    fn alterations_are_replayed_in_order() {
+        let tables = tables(
+            "CREATE TABLE users (id UUID PRIMARY KEY, name TEXT, is_active BOOLEAN);
+            ALTER TABLE users ADD COLUMN IF NOT EXISTS phone VARCHAR(16) UNIQUE;
+            ALTER TABLE users ADD COLUMN IF NOT EXISTS phone TEXT;
+            ALTER TABLE users
+                ADD COLUMN is_guest BOOLEAN NOT NULL DEFAULT false,
+                ADD CONSTRAINT users_name_key UNIQUE (name),
+                ALTER COLUMN is_active SET NOT NULL,
+                ALTER name TYPE VARCHAR(100) USING name::varchar(100);
+            ALTER TABLE users RENAME COLUMN name TO full_name;
+            ALTER TABLE users DROP COLUMN IF EXISTS phone;
+            ALTER TABLE users RENAME TO accounts;
+            CREATE TABLE IF NOT EXISTS accounts (id UUID);",
+        );
+
+        assert!(!tables.contains_key("users"));
+        assert_eq!(
+            columns(&tables, "accounts"),
+            [
+                column("id", "UUID", false),
+                column("full_name", "VARCHAR(100)", true),
+                column("is_active", "BOOLEAN", false),
+                column("is_guest", "BOOLEAN", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn function_bodies_and_dropped_tables_leave_no_trace() {
+        let tables = tables(
+            "CREATE TABLE audit (id UUID);
+            ALTER TABLE audit RENAME TO audit_old;
+            CREATE TABLE audit (id UUID NOT NULL, device JSONB);
+            DO $$
+            BEGIN
+                EXECUTE 'CREATE TABLE audit_p1 PARTITION OF audit; DROP TABLE audit;';
+                CREATE TABLE inside_function (id UUID);
+            END $$;
+            CREATE FUNCTION touch() RETURNS TRIGGER AS $body$ BEGIN RETURN NEW; END; $body$ LANGUAGE plpgsql;
+            DROP TABLE IF EXISTS audit_old;",
+        );
+
+        assert_eq!(tables.keys().collect::<Vec<_>>(), ["audit"]);
+        assert_eq!(columns(&tables, "audit"), [column("id", "UUID", false), column("device", "JSONB", true)]);
+    }
+}
@@ -0,0 +1,3 @@
+-- Keys for HMAC request signing, sealed with the application signing keys.
+-- Tokens minted without request signing leave it empty.
+ALTER TABLE api_tokens ADD COLUMN IF NOT EXISTS signing_key TEXT;
@@ -0,0 +1,19 @@
+-- Phone numbers in E.164 format, verified by SMS
+ALTER TABLE users ADD COLUMN IF NOT EXISTS phone_number VARCHAR(16) UNIQUE;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS phone_verified_at TIMESTAMP WITH TIME ZONE;
+
+-- One-time codes sent by SMS, for phone verification and OTP login
+CREATE TABLE IF NOT EXISTS phone_otps (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    phone_number VARCHAR(16) NOT NULL,
+    purpose VARCHAR(32) NOT NULL,
+    code_hash VARCHAR(64) NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    consumed_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_phone_otps_phone_number ON phone_otps(phone_number, created_at);
+CREATE INDEX idx_phone_otps_user_id ON phone_otps(user_id);
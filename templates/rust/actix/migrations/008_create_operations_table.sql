@@ -0,0 +1,19 @@
+-- Long-running operations (imports, exports, deletions) polled or watched
+-- by clients until done
+CREATE TABLE IF NOT EXISTS operations (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind VARCHAR(100) NOT NULL,
+    done BOOLEAN NOT NULL DEFAULT false,
+    progress_percent INTEGER NOT NULL DEFAULT 0 CHECK (progress_percent BETWEEN 0 AND 100),
+    progress_message TEXT,
+    metadata JSONB NOT NULL DEFAULT '{}',
+    -- Set once done: the result on success, the error otherwise
+    response JSONB,
+    error_code VARCHAR(64),
+    error_message TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_operations_user_id ON operations(user_id);
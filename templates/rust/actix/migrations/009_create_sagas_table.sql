@@ -0,0 +1,19 @@
+-- Multi-step processes with compensations; see src/saga
+CREATE TABLE IF NOT EXISTS sagas (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    kind VARCHAR(100) NOT NULL,
+    -- running, completed, compensating, compensated or failed
+    status VARCHAR(16) NOT NULL DEFAULT 'running',
+    -- Steps completed so far; while compensating, steps still to undo
+    current_step INTEGER NOT NULL DEFAULT 0,
+    data JSONB NOT NULL DEFAULT '{}',
+    last_error TEXT,
+    compensation_attempts INTEGER NOT NULL DEFAULT 0,
+    -- The job running the saga and the lease of whoever executes it
+    job_id UUID,
+    locked_until TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_sagas_active ON sagas(updated_at) WHERE status IN ('running', 'compensating');
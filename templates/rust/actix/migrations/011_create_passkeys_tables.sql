@@ -0,0 +1,26 @@
+-- WebAuthn credentials registered to an account
+CREATE TABLE IF NOT EXISTS passkeys (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- Base64url credential ID, as authenticators present it
+    credential_id TEXT NOT NULL UNIQUE,
+    name VARCHAR(100) NOT NULL,
+    -- Public key and signature counter, as webauthn-rs serialises them
+    credential JSONB NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_used_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX idx_passkeys_user_id ON passkeys(user_id);
+
+-- Registrations and logins between their start and finish requests
+CREATE TABLE IF NOT EXISTS webauthn_ceremonies (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    -- The registering user; NULL for logins
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    state JSONB NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_webauthn_ceremonies_expires_at ON webauthn_ceremonies(expires_at);
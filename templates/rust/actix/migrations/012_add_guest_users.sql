@@ -0,0 +1,7 @@
+-- Guests are users without credentials until they register; an expired
+-- guest is deleted along with everything it created
+ALTER TABLE users
+    ADD COLUMN is_guest BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN guest_expires_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX idx_users_guest_expires_at ON users(guest_expires_at) WHERE is_guest;
@@ -0,0 +1,4 @@
+-- Browser, OS and device parsed from the User-Agent of the client that
+-- obtained a refresh token or triggered an audit event
+ALTER TABLE refresh_tokens ADD COLUMN device JSONB;
+ALTER TABLE audit_events ADD COLUMN device JSONB;
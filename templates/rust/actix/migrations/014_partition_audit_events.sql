@@ -0,0 +1,53 @@
+-- Partition audit events by month of created_at (UTC), so old months can
+-- be archived and dropped whole instead of deleted row by row. Partitions
+-- are named audit_events_pYYYYMM; the partition manager creates upcoming
+-- ones and retires expired ones. Rows outside every partition land in
+-- audit_events_default until the manager moves them into their own.
+ALTER TABLE audit_events RENAME TO audit_events_unpartitioned;
+ALTER TABLE audit_events_unpartitioned RENAME CONSTRAINT audit_events_pkey TO audit_events_unpartitioned_pkey;
+ALTER TABLE audit_events_unpartitioned
+    RENAME CONSTRAINT audit_events_user_id_fkey TO audit_events_unpartitioned_user_id_fkey;
+DROP INDEX idx_audit_events_user_id;
+DROP INDEX idx_audit_events_created_at;
+
+CREATE TABLE audit_events (
+    id UUID NOT NULL DEFAULT uuid_generate_v4(),
+    event_type VARCHAR(100) NOT NULL,
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    ip_address VARCHAR(64),
+    metadata JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    device JSONB,
+    -- Unique constraints must include the partition key
+    PRIMARY KEY (id, created_at)
+) PARTITION BY RANGE (created_at);
+
+CREATE TABLE audit_events_default PARTITION OF audit_events DEFAULT;
+
+-- One partition per month from the oldest event through next month
+DO $$
+DECLARE
+    month TIMESTAMP;
+BEGIN
+    FOR month IN
+        SELECT generate_series(
+            date_trunc('month', LEAST(COALESCE(MIN(created_at), now()), now()) AT TIME ZONE 'UTC'),
+            date_trunc('month', now() AT TIME ZONE 'UTC') + INTERVAL '1 month',
+            INTERVAL '1 month'
+        )
+        FROM audit_events_unpartitioned
+    LOOP
+        EXECUTE format(
+            'CREATE TABLE %I PARTITION OF audit_events FOR VALUES FROM (%L) TO (%L)',
+            'audit_events_p' || to_char(month, 'YYYYMM'),
+            month AT TIME ZONE 'UTC',
+            (month + INTERVAL '1 month') AT TIME ZONE 'UTC'
+        );
+    END LOOP;
+END $$;
+
+INSERT INTO audit_events SELECT * FROM audit_events_unpartitioned;
+DROP TABLE audit_events_unpartitioned;
+
+CREATE INDEX idx_audit_events_user_id ON audit_events(user_id);
+CREATE INDEX idx_audit_events_created_at ON audit_events(created_at);
@@ -0,0 +1,11 @@
+-- Schedule and last outcome of each analytics dataset; see src/analytics.rs
+CREATE TABLE IF NOT EXISTS analytics_exports (
+    dataset VARCHAR(100) PRIMARY KEY,
+    next_run_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    last_exported_at TIMESTAMP WITH TIME ZONE,
+    -- Object storage path and row count of the last successful export
+    last_path TEXT,
+    last_rows BIGINT,
+    last_error TEXT,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
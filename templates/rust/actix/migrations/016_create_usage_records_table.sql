@@ -0,0 +1,19 @@
+-- Billable usage per user, API token and hour; see src/metering.rs
+CREATE TABLE IF NOT EXISTS usage_records (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    -- No foreign keys: usage outlives deleted users and revoked tokens
+    user_id UUID NOT NULL,
+    api_token_id UUID,
+    metric VARCHAR(32) NOT NULL,
+    hour TIMESTAMP WITH TIME ZONE NOT NULL,
+    quantity BIGINT NOT NULL,
+    -- Cleared whenever the quantity changes, so the record is exported again
+    exported_at TIMESTAMP WITH TIME ZONE,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_usage_records_key ON usage_records (
+    user_id, COALESCE(api_token_id, '00000000-0000-0000-0000-000000000000'::uuid), metric, hour
+);
+CREATE INDEX idx_usage_records_hour ON usage_records(hour);
+CREATE INDEX idx_usage_records_unexported ON usage_records(hour) WHERE exported_at IS NULL;
@@ -0,0 +1,38 @@
+-- When an account was deactivated, so deactivated accounts can be deleted
+-- after the deactivated_users retention policy's TTL; see src/retention.rs
+ALTER TABLE users ADD COLUMN deactivated_at TIMESTAMP WITH TIME ZONE;
+UPDATE users SET deactivated_at = updated_at WHERE is_active = false;
+
+CREATE OR REPLACE FUNCTION set_users_deactivated_at()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.is_active IS DISTINCT FROM false THEN
+        NEW.deactivated_at = NULL;
+    ELSIF TG_OP = 'INSERT' OR OLD.is_active IS DISTINCT FROM false THEN
+        NEW.deactivated_at = CURRENT_TIMESTAMP;
+    END IF;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+CREATE TRIGGER set_users_deactivated_at BEFORE INSERT OR UPDATE OF is_active
+    ON users FOR EACH ROW EXECUTE FUNCTION set_users_deactivated_at();
+
+CREATE INDEX idx_users_deactivated_at ON users(deactivated_at) WHERE deactivated_at IS NOT NULL;
+
+-- Schedule and progress of each retention policy
+CREATE TABLE IF NOT EXISTS retention_runs (
+    policy VARCHAR(100) PRIMARY KEY,
+    next_run_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    -- The last run: when it started, what it removed rows older than, and
+    -- how many it removed so far (or would have, in dry-run mode)
+    last_run_at TIMESTAMP WITH TIME ZONE,
+    last_cutoff TIMESTAMP WITH TIME ZONE,
+    last_rows BIGINT,
+    last_dry_run BOOLEAN,
+    last_finished_at TIMESTAMP WITH TIME ZONE,
+    last_error TEXT,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_refresh_tokens_expires_at ON refresh_tokens(expires_at);
@@ -0,0 +1,14 @@
+-- The user model reads these as never NULL; nothing writes NULL to them,
+-- but the columns allowed it
+UPDATE users SET
+    is_active = COALESCE(is_active, true),
+    is_verified = COALESCE(is_verified, false),
+    created_at = COALESCE(created_at, CURRENT_TIMESTAMP),
+    updated_at = COALESCE(updated_at, CURRENT_TIMESTAMP)
+WHERE is_active IS NULL OR is_verified IS NULL OR created_at IS NULL OR updated_at IS NULL;
+
+ALTER TABLE users
+    ALTER COLUMN is_active SET NOT NULL,
+    ALTER COLUMN is_verified SET NOT NULL,
+    ALTER COLUMN created_at SET NOT NULL,
+    ALTER COLUMN updated_at SET NOT NULL;
@@ -0,0 +1,10 @@
+-- Messages a consumer has handled, to skip redeliveries; see src/consumer.rs
+CREATE TABLE IF NOT EXISTS processed_messages (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    consumer VARCHAR(100) NOT NULL,
+    message_id VARCHAR(255) NOT NULL,
+    processed_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    UNIQUE (consumer, message_id)
+);
+
+CREATE INDEX idx_processed_messages_processed_at ON processed_messages(processed_at);
@@ -0,0 +1,28 @@
+-- Account deletions waiting for downstream services to confirm their
+-- cleanup; see src/saga/deletion_fanout.rs
+CREATE TABLE IF NOT EXISTS user_deletions (
+    -- The account deletion saga's id
+    id UUID PRIMARY KEY,
+    -- No foreign key: finishing the deletion deletes the user
+    user_id UUID NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    requested_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    -- Still unconfirmed by then, the deletion goes to manual review
+    deadline_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    finished_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX idx_user_deletions_status ON user_deletions(status, requested_at);
+
+CREATE TABLE IF NOT EXISTS user_deletion_acks (
+    deletion_id UUID NOT NULL REFERENCES user_deletions(id) ON DELETE CASCADE,
+    service VARCHAR(100) NOT NULL,
+    confirmed_at TIMESTAMP WITH TIME ZONE,
+    -- Times UserDeletionRequested was sent
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    last_error TEXT,
+    PRIMARY KEY (deletion_id, service)
+);
+
+CREATE INDEX idx_user_deletion_acks_due ON user_deletion_acks(next_attempt_at) WHERE confirmed_at IS NULL;
@@ -0,0 +1,15 @@
+-- Named queues with their own priority, concurrency and rate limits; see
+-- src/jobs/mod.rs. Existing jobs run in the default queue.
+ALTER TABLE jobs ADD COLUMN queue VARCHAR(50) NOT NULL DEFAULT 'default';
+-- Jobs sharing a key count against one rate limit of their queue
+ALTER TABLE jobs ADD COLUMN rate_key VARCHAR(255);
+
+DROP INDEX idx_jobs_pending_run_at;
+CREATE INDEX idx_jobs_pending_queue_run_at ON jobs(queue, run_at) WHERE status = 'pending';
+
+-- Queues paused through the admin API, for every replica's worker
+CREATE TABLE IF NOT EXISTS job_queues (
+    name VARCHAR(50) PRIMARY KEY,
+    paused BOOLEAN NOT NULL DEFAULT false,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
@@ -0,0 +1,4 @@
+-- Running jobs past jobs.visibility_timeout_secs are claimed again; see
+-- src/jobs/worker.rs. Pending jobs, scheduled or not, are found through
+-- idx_jobs_pending_queue_run_at.
+CREATE INDEX idx_jobs_running_queue_locked_at ON jobs(queue, locked_at) WHERE status = 'running';
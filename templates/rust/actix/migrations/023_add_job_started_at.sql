@@ -0,0 +1,8 @@
+-- When the latest attempt started, for the run times and queue waits of
+-- the job dashboard; see src/jobs/dashboard.rs
+ALTER TABLE jobs ADD COLUMN started_at TIMESTAMP WITH TIME ZONE;
+
+-- Listing jobs by status, newest first
+DROP INDEX idx_jobs_status;
+CREATE INDEX idx_jobs_status_created_at ON jobs(status, created_at DESC);
+CREATE INDEX idx_jobs_finished_at ON jobs(finished_at) WHERE finished_at IS NOT NULL;
@@ -0,0 +1,45 @@
+-- Role-based access control; see src/services/role_service.rs. A user's
+-- roles, and the permissions they grant, are copied into their access
+-- tokens when issued.
+CREATE TABLE IF NOT EXISTS roles (
+    name VARCHAR(50) PRIMARY KEY,
+    description TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS permissions (
+    name VARCHAR(100) PRIMARY KEY,
+    description TEXT NOT NULL DEFAULT ''
+);
+
+CREATE TABLE IF NOT EXISTS role_permissions (
+    role VARCHAR(50) NOT NULL REFERENCES roles(name) ON DELETE CASCADE,
+    permission VARCHAR(100) NOT NULL REFERENCES permissions(name) ON DELETE CASCADE,
+    PRIMARY KEY (role, permission)
+);
+
+CREATE TABLE IF NOT EXISTS user_roles (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(50) NOT NULL REFERENCES roles(name) ON DELETE CASCADE,
+    granted_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    granted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, role)
+);
+
+INSERT INTO permissions (name, description) VALUES
+    ('users.read', 'List and read any user'),
+    ('users.write', 'Update any user, including deactivating them'),
+    ('users.delete', 'Delete any user')
+ON CONFLICT DO NOTHING;
+
+INSERT INTO roles (name, description) VALUES
+    ('admin', 'Manages users and their roles'),
+    ('support', 'Looks up users to help them')
+ON CONFLICT DO NOTHING;
+
+INSERT INTO role_permissions (role, permission) VALUES
+    ('admin', 'users.read'),
+    ('admin', 'users.write'),
+    ('admin', 'users.delete'),
+    ('support', 'users.read')
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,30 @@
+-- Rollups for /api/v1/stats, refreshed by the materialized_views.refresh
+-- job; see src/materialized_views.rs. Refreshing CONCURRENTLY, which keeps
+-- the view readable meanwhile, needs a unique index on each.
+CREATE MATERIALIZED VIEW IF NOT EXISTS daily_signups AS
+SELECT date_trunc('day', created_at)::date AS day, COUNT(*) AS signups
+FROM users
+WHERE NOT is_guest
+GROUP BY 1;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_daily_signups_day ON daily_signups(day);
+
+-- Users who signed in or refreshed their session each day. Sessions are
+-- deleted by the sessions retention policy, and the days they covered with
+-- them.
+CREATE MATERIALIZED VIEW IF NOT EXISTS daily_active_users AS
+SELECT date_trunc('day', created_at)::date AS day, COUNT(DISTINCT user_id) AS active_users
+FROM refresh_tokens
+GROUP BY 1;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_daily_active_users_day ON daily_active_users(day);
+
+-- Schedule and outcome of each view's refreshes
+CREATE TABLE IF NOT EXISTS materialized_view_refreshes (
+    view_name VARCHAR(100) PRIMARY KEY,
+    next_run_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    last_refreshed_at TIMESTAMP WITH TIME ZONE,
+    last_duration_ms BIGINT,
+    last_error TEXT,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,3 @@
+-- When the last verification email went out, to space out resends; see
+-- src/services/email_verification_service.rs
+ALTER TABLE users ADD COLUMN IF NOT EXISTS verification_sent_at TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,266 @@
+//! Scheduled exports of tables and query results to object storage as
+//! Parquet, so the data platform can load service data without access to
+//! the database.
+//!
+//! Each dataset in `analytics.datasets` names a table or a query and how
+//! often it is exported. With `analytics.enabled`, the server looks for due
+//! datasets every `analytics.poll_interval_secs` and enqueues an
+//! [`EXPORT_JOB`] for each; claiming a dataset is a conditional update, so
+//! only one replica enqueues it. The job writes
+//! `<prefix>/<dataset>/date=YYYY-MM-DD/<dataset>-YYYYMMDDTHHMMSSZ.parquet`,
+//! named after the time it was scheduled so retries overwrite their own
+//! file, and records the outcome in `analytics_exports`.
+//!
+//! ```toml
+//! [analytics.datasets.signups]
+//! query = "SELECT id, created_at, is_verified FROM users"
+//! interval_secs = 86400
+//! ```
+
+use chrono::{DateTime, Duration, Utc};
+use config::ConfigError;
+use futures_util::future::BoxFuture;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use platform_core::lifecycle::{Hook, Lifecycle};
+use serde::{Deserialize, Serialize};
+use sqlx::{Connection, PgPool};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+
+use crate::archive;
+use crate::config::AnalyticsSettings;
+use crate::errors::{AppError, AppResult, ResultExt};
+use crate::jobs::{JobHandler, JobQueue, Worker};
+use crate::models::job::Job;
+use crate::utils::SharedClock;
+use crate::AppState;
+
+pub const EXPORT_JOB: &str = "analytics.export";
+
+struct Dataset {
+    select: String,
+    interval: Duration,
+}
+
+pub struct AnalyticsExporter {
+    db: PgPool,
+    settings: AnalyticsSettings,
+    datasets: BTreeMap<String, Dataset>,
+    store: Arc<dyn ObjectStore>,
+    job_queue: Arc<JobQueue>,
+    clock: SharedClock,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportPayload {
+    pub dataset: String,
+    pub scheduled_at: DateTime<Utc>,
+}
+
+impl AnalyticsExporter {
+    pub fn new(
+        db: PgPool,
+        settings: AnalyticsSettings,
+        store: Arc<dyn ObjectStore>,
+        job_queue: Arc<JobQueue>,
+        clock: SharedClock,
+    ) -> Result<Self, ConfigError> {
+        let datasets = settings
+            .datasets
+            .iter()
+            .map(|(name, dataset)| {
+                let select = dataset.select().ok_or_else(|| {
+                    ConfigError::Message(format!("analytics dataset {} needs exactly one of table and query", name))
+                })?;
+                if dataset.interval_secs <= 0 {
+                    return Err(ConfigError::Message(format!(
+                        "analytics dataset {} needs a positive interval_secs",
+                        name
+                    )));
+                }
+                let interval = Duration::seconds(dataset.interval_secs);
+                Ok((name.clone(), Dataset { select, interval }))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            db,
+            settings,
+            datasets,
+            store,
+            job_queue,
+            clock,
+        })
+    }
+
+    /// Enqueues an export of every dataset that is due and returns their
+    /// names. Datasets are due when first configured and `interval_secs`
+    /// after each time they were scheduled.
+    pub async fn schedule_due(&self) -> AppResult<Vec<String>> {
+        let now = self.clock.now();
+        let mut scheduled = Vec::new();
+
+        for (name, dataset) in &self.datasets {
+            let mut tx = self.db.begin().await.context("begin transaction")?;
+            sqlx::query(
+                "INSERT INTO analytics_exports (dataset, next_run_at) VALUES ($1, $2) ON CONFLICT (dataset) DO NOTHING",
+            )
+            .bind(name)
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .entity_context("register analytics dataset", name)?;
+
+            let claimed = sqlx::query(
+                r#"
+                UPDATE analytics_exports SET next_run_at = $3, updated_at = $2
+                WHERE dataset = $1 AND next_run_at <= $2
+                "#,
+            )
+            .bind(name)
+            .bind(now)
+            .bind(now + dataset.interval)
+            .execute(&mut *tx)
+            .await
+            .entity_context("claim analytics dataset", name)?
+            .rows_affected()
+                == 1;
+
+            if claimed {
+                let payload = ExportPayload {
+                    dataset: name.clone(),
+                    scheduled_at: now,
+                };
+                self.job_queue.enqueue_with(&mut *tx, EXPORT_JOB, &payload).await?;
+                scheduled.push(name.clone());
+            }
+            tx.commit().await.context("commit transaction")?;
+        }
+
+        Ok(scheduled)
+    }
+
+    /// Calls [`schedule_due`](Self::schedule_due) every
+    /// `analytics.poll_interval_secs` until `shutdown` completes.
+    pub async fn schedule_until(&self, shutdown: impl Future<Output = ()>) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(self.settings.poll_interval_secs));
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.schedule_due().await {
+                        tracing::warn!("Failed to schedule analytics exports: {}", e);
+                    }
+                }
+                _ = &mut shutdown => break,
+            }
+        }
+    }
+
+    /// Exports `dataset` as scheduled at `scheduled_at` and returns where it
+    /// was written.
+    pub async fn export(&self, dataset: &str, scheduled_at: DateTime<Utc>) -> AppResult<String> {
+        let select = &self
+            .datasets
+            .get(dataset)
+            .ok_or_else(|| AppError::NotFound(format!("Analytics dataset {} is not configured", dataset)))?
+            .select;
+        let path = Path::from(format!(
+            "{prefix}/{dataset}/date={date}/{dataset}-{time}.parquet",
+            prefix = self.settings.prefix,
+            date = scheduled_at.format("%Y-%m-%d"),
+            time = scheduled_at.format("%Y%m%dT%H%M%SZ"),
+        ));
+
+        match self.write(select, &path).await {
+            Ok(rows) => {
+                sqlx::query(
+                    r#"
+                    UPDATE analytics_exports
+                    SET last_exported_at = $2, last_path = $3, last_rows = $4, last_error = NULL, updated_at = $2
+                    WHERE dataset = $1
+                    "#,
+                )
+                .bind(dataset)
+                .bind(self.clock.now())
+                .bind(path.to_string())
+                .bind(rows as i64)
+                .execute(&self.db)
+                .await
+                .entity_context("record analytics export", dataset)?;
+                tracing::info!("Exported {} rows of {} to {}", rows, dataset, path);
+                Ok(path.to_string())
+            }
+            Err(e) => {
+                sqlx::query("UPDATE analytics_exports SET last_error = $2, updated_at = $3 WHERE dataset = $1")
+                    .bind(dataset)
+                    .bind(e.source_chain())
+                    .bind(self.clock.now())
+                    .execute(&self.db)
+                    .await
+                    .entity_context("record analytics export", dataset)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Writes `select` from one snapshot, in a transaction that can't
+    /// write even if the configured query tries to.
+    async fn write(&self, select: &str, path: &Path) -> AppResult<u64> {
+        let mut conn = self.db.acquire().await.context("acquire connection")?;
+        let mut tx = conn.begin().await.context("begin export")?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+            .execute(&mut *tx)
+            .await
+            .context("begin export")?;
+        let rows = archive::write_query(&mut tx, select, self.store.clone(), path).await?;
+        tx.commit().await.context("commit export")?;
+        Ok(rows)
+    }
+}
+
+/// Schedules exports from startup until shutdown. Nothing when
+/// `analytics.enabled` is off; the exports themselves run as jobs.
+pub fn register_hooks(lifecycle: &mut Lifecycle, state: &Arc<AppState>) {
+    if !state.settings.analytics.enabled {
+        return;
+    }
+
+    let exporter = state.analytics.clone();
+    let (stop, stopped) = oneshot::channel::<()>();
+    let (started, scheduling) = oneshot::channel();
+    lifecycle.on_start(Hook::new("analytics", move || async move {
+        let stopped = async move {
+            let _ = stopped.await;
+        };
+        let _ = started.send(tokio::spawn(async move { exporter.schedule_until(stopped).await }));
+        Ok(())
+    }));
+    lifecycle.on_shutdown(Hook::new("analytics", move || async move {
+        let _ = stop.send(());
+        if let Ok(scheduling) = scheduling.await {
+            scheduling.await?;
+        }
+        Ok(())
+    }));
+}
+
+struct Export(Arc<AnalyticsExporter>);
+
+impl JobHandler for Export {
+    fn handle<'a>(&'a self, job: &'a Job) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let payload: ExportPayload = job.payload()?;
+            self.0.export(&payload.dataset, payload.scheduled_at).await?;
+            Ok(())
+        })
+    }
+}
+
+pub fn register_jobs(worker: Worker, state: &AppState) -> Worker {
+    worker.handler(EXPORT_JOB, Export(state.analytics.clone()))
+}
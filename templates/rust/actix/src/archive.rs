@@ -0,0 +1,221 @@
+//! Writes Postgres tables and query results to object storage as Parquet
+//! files, e.g. partitions before they are dropped or analytics exports.
+//!
+//! Columns keep their type where Parquet has a natural equivalent
+//! (booleans, integers, floats, `timestamptz`); everything else, UUIDs and
+//! JSON included, is written as its Postgres text representation.
+
+use arrow_array::builder::{
+    BooleanBuilder, Float64Builder, Int32Builder, Int64Builder, StringBuilder, TimestampMicrosecondBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use parquet::arrow::async_writer::ParquetObjectWriter;
+use parquet::arrow::AsyncArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use sqlx::postgres::PgRow;
+use sqlx::{Column as _, Executor, PgConnection, Row, TypeInfo};
+use std::sync::Arc;
+
+use crate::db::quote_ident;
+use crate::errors::{AppError, AppResult, ResultExt};
+
+/// Rows per Parquet row group.
+const BATCH_ROWS: usize = 8192;
+
+/// Writes every row of `table` to `path` in `store` and returns how many
+/// were written.
+pub async fn write_table(
+    conn: &mut PgConnection,
+    table: &str,
+    store: Arc<dyn ObjectStore>,
+    path: &Path,
+) -> AppResult<u64> {
+    write_query(conn, &format!("SELECT * FROM {}", quote_ident(table)), store, path).await
+}
+
+/// Writes the result of `query` to `path` in `store` and returns how many
+/// rows were written.
+pub async fn write_query(
+    conn: &mut PgConnection,
+    query: &str,
+    store: Arc<dyn ObjectStore>,
+    path: &Path,
+) -> AppResult<u64> {
+    let described = (&mut *conn).describe(query).await.context("describe export query")?;
+    let columns: Vec<Column> = described
+        .columns()
+        .iter()
+        .map(|column| Column {
+            name: column.name().to_string(),
+            kind: ColumnKind::of(column.type_info().name()),
+        })
+        .collect();
+
+    let schema = Arc::new(Schema::new(
+        columns
+            .iter()
+            .map(|column| Field::new(&column.name, column.kind.data_type(), true))
+            .collect::<Vec<_>>(),
+    ));
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_max_row_group_size(BATCH_ROWS)
+        .build();
+    let mut writer = AsyncArrowWriter::try_new(
+        ParquetObjectWriter::new(store, path.clone()),
+        schema.clone(),
+        Some(properties),
+    )
+    .map_err(|e| write_failed(path, e))?;
+
+    let select = format!(
+        "SELECT {} FROM ({}) AS export",
+        columns.iter().map(Column::select).collect::<Vec<_>>().join(", "),
+        query,
+    );
+    let mut builders: Vec<ColumnBuilder> = columns.iter().map(|column| ColumnBuilder::new(column.kind)).collect();
+    let mut buffered = 0;
+    let mut rows = sqlx::query(&select).fetch(&mut *conn);
+
+    while let Some(row) = rows.try_next().await.context("read export rows")? {
+        for (index, builder) in builders.iter_mut().enumerate() {
+            builder.append(&row, index).context("read export rows")?;
+        }
+        buffered += 1;
+
+        if buffered == BATCH_ROWS {
+            write_batch(&mut writer, &schema, &mut builders, path).await?;
+            buffered = 0;
+        }
+    }
+    if buffered > 0 {
+        write_batch(&mut writer, &schema, &mut builders, path).await?;
+    }
+
+    let metadata = writer.close().await.map_err(|e| write_failed(path, e))?;
+    Ok(metadata.num_rows as u64)
+}
+
+async fn write_batch(
+    writer: &mut AsyncArrowWriter<ParquetObjectWriter>,
+    schema: &Arc<Schema>,
+    builders: &mut [ColumnBuilder],
+    path: &Path,
+) -> AppResult<()> {
+    let arrays = builders.iter_mut().map(ColumnBuilder::finish).collect();
+    let batch = RecordBatch::try_new(schema.clone(), arrays).map_err(|e| write_failed(path, e))?;
+    writer.write(&batch).await.map_err(|e| write_failed(path, e))
+}
+
+fn write_failed(path: &Path, error: impl std::fmt::Display) -> AppError {
+    tracing::error!("Failed to write Parquet file {}: {}", path, error);
+    AppError::InternalServerError
+}
+
+struct Column {
+    name: String,
+    kind: ColumnKind,
+}
+
+impl Column {
+    /// The select expression reading the column as its kind's Rust type.
+    fn select(&self) -> String {
+        let name = quote_ident(&self.name);
+        match self.kind {
+            ColumnKind::Int32 => format!("{}::int4", name),
+            ColumnKind::Float64 => format!("{}::float8", name),
+            ColumnKind::Text => format!("{}::text", name),
+            ColumnKind::Boolean | ColumnKind::Int64 | ColumnKind::Timestamp => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ColumnKind {
+    Boolean,
+    Int32,
+    Int64,
+    Float64,
+    Timestamp,
+    Text,
+}
+
+impl ColumnKind {
+    /// The kind a column of Postgres type `pg_type` is written as.
+    fn of(pg_type: &str) -> Self {
+        match pg_type {
+            "BOOL" => Self::Boolean,
+            "INT2" | "INT4" => Self::Int32,
+            "INT8" => Self::Int64,
+            "FLOAT4" | "FLOAT8" => Self::Float64,
+            "TIMESTAMPTZ" => Self::Timestamp,
+            _ => Self::Text,
+        }
+    }
+
+    fn data_type(self) -> DataType {
+        match self {
+            Self::Boolean => DataType::Boolean,
+            Self::Int32 => DataType::Int32,
+            Self::Int64 => DataType::Int64,
+            Self::Float64 => DataType::Float64,
+            Self::Timestamp => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            Self::Text => DataType::Utf8,
+        }
+    }
+}
+
+enum ColumnBuilder {
+    Boolean(BooleanBuilder),
+    Int32(Int32Builder),
+    Int64(Int64Builder),
+    Float64(Float64Builder),
+    Timestamp(TimestampMicrosecondBuilder),
+    Text(StringBuilder),
+}
+
+impl ColumnBuilder {
+    fn new(kind: ColumnKind) -> Self {
+        match kind {
+            ColumnKind::Boolean => Self::Boolean(BooleanBuilder::new()),
+            ColumnKind::Int32 => Self::Int32(Int32Builder::new()),
+            ColumnKind::Int64 => Self::Int64(Int64Builder::new()),
+            ColumnKind::Float64 => Self::Float64(Float64Builder::new()),
+            ColumnKind::Timestamp => Self::Timestamp(TimestampMicrosecondBuilder::new().with_timezone("UTC")),
+            ColumnKind::Text => Self::Text(StringBuilder::new()),
+        }
+    }
+
+    fn append(&mut self, row: &PgRow, index: usize) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Boolean(builder) => builder.append_option(row.try_get::<Option<bool>, _>(index)?),
+            Self::Int32(builder) => builder.append_option(row.try_get::<Option<i32>, _>(index)?),
+            Self::Int64(builder) => builder.append_option(row.try_get::<Option<i64>, _>(index)?),
+            Self::Float64(builder) => builder.append_option(row.try_get::<Option<f64>, _>(index)?),
+            Self::Timestamp(builder) => builder.append_option(
+                row.try_get::<Option<DateTime<Utc>>, _>(index)?
+                    .map(|timestamp| timestamp.timestamp_micros()),
+            ),
+            Self::Text(builder) => builder.append_option(row.try_get::<Option<String>, _>(index)?),
+        }
+        Ok(())
+    }
+
+    /// The values appended since the last call.
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            Self::Boolean(builder) => Arc::new(builder.finish()),
+            Self::Int32(builder) => Arc::new(builder.finish()),
+            Self::Int64(builder) => Arc::new(builder.finish()),
+            Self::Float64(builder) => Arc::new(builder.finish()),
+            Self::Timestamp(builder) => Arc::new(builder.finish()),
+            Self::Text(builder) => Arc::new(builder.finish()),
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! Response cache behind `#[cached(ttl = "...")]`.
+//!
+//! Successful (`200`) responses are kept in memory, keyed by method, path,
+//! query and a digest of the caller's credential, so a cached response is
+//! only ever served to the caller it was built for. Entries expire after the
+//! handler's TTL.
+//!
+//! Handlers label what their responses show with tags, e.g.
+//! `#[cached(ttl = "30s", tags = ["users:list", "user:{id}"])]`, where
+//! `{id}` is filled in from the route. Code changing the data calls
+//! [`ResponseCache::invalidate_tag`] with the same tags, which drops exactly
+//! the responses built from it. Each replica has its own cache; with
+//! `cache.broadcast_invalidations` tag invalidations are published on Redis
+//! so every replica drops its entries too.
+
+use actix_web::body;
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, COOKIE};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use platform_core::lifecycle::{Hook, Lifecycle};
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::config::CacheSettings;
+use crate::errors::{AppError, AppResult};
+use crate::utils::{hash_token, SharedClock};
+use crate::AppState;
+
+/// Wait before resubscribing after the Redis connection dropped.
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+/// `hit` or `miss` on responses of cached handlers.
+pub const CACHE_HEADER: HeaderName = HeaderName::from_static("x-cache");
+
+struct Entry {
+    path: String,
+    tags: Vec<String>,
+    expires_at: DateTime<Utc>,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: web::Bytes,
+}
+
+pub struct ResponseCache {
+    settings: CacheSettings,
+    entries: Mutex<HashMap<String, Entry>>,
+    /// Publishes tag invalidations, with `cache.broadcast_invalidations`.
+    redis: Option<(redis::Client, ConnectionManager)>,
+    /// Identifies this replica's invalidations, so it skips its own.
+    instance_id: String,
+    clock: SharedClock,
+}
+
+impl ResponseCache {
+    /// Connects to Redis if invalidations are broadcast.
+    pub async fn new(settings: CacheSettings, redis_url: &str, clock: SharedClock) -> redis::RedisResult<Self> {
+        let redis = if settings.broadcast_invalidations {
+            let client = redis::Client::open(redis_url)?;
+            let connection = ConnectionManager::new(client.clone()).await?;
+            Some((client, connection))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            settings,
+            entries: Mutex::new(HashMap::new()),
+            redis,
+            instance_id: Uuid::new_v4().to_string(),
+            clock,
+        })
+    }
+
+    fn get(&self, key: &str) -> Option<HttpResponse> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(key).filter(|entry| entry.expires_at > self.clock.now())?;
+
+        let mut response = HttpResponse::build(entry.status).body(entry.body.clone());
+        *response.headers_mut() = entry.headers.clone();
+        response.headers_mut().insert(CACHE_HEADER, HeaderValue::from_static("hit"));
+        Some(response)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn insert(
+        &self,
+        key: String,
+        path: &str,
+        tags: Vec<String>,
+        ttl: Duration,
+        status: StatusCode,
+        headers: HeaderMap,
+        body: web::Bytes,
+    ) {
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().unwrap();
+
+        // Full caches drop what expired, then stop taking new entries until
+        // more does
+        if entries.len() >= self.settings.max_entries {
+            entries.retain(|_, entry| entry.expires_at > now);
+            if entries.len() >= self.settings.max_entries {
+                return;
+            }
+        }
+
+        let ttl = chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::zero());
+        entries.insert(
+            key,
+            Entry {
+                path: path.to_string(),
+                tags,
+                expires_at: now + ttl,
+                status,
+                headers,
+                body,
+            },
+        );
+    }
+
+    /// Drops cached responses for `path` and everything below it, after a
+    /// write changed what they show.
+    pub fn invalidate(&self, path: &str) {
+        self.entries.lock().unwrap().retain(|_, entry| !entry.path.starts_with(path));
+    }
+
+    /// Drops cached responses tagged `tag` on every replica, after a write
+    /// changed the data behind them. A failed broadcast is logged; the other
+    /// replicas then serve their entries until they expire.
+    pub async fn invalidate_tag(&self, tag: &str) {
+        self.drop_tag(tag);
+
+        let Some((_, mut connection)) = self.redis.clone() else {
+            return;
+        };
+        let message = format!("{} {}", self.instance_id, tag);
+        if let Err(e) = connection.publish::<_, _, ()>(&self.settings.invalidation_channel, message).await {
+            tracing::warn!(tag, error = %e, "Failed to broadcast cache invalidation");
+        }
+    }
+
+    /// Applies an invalidation another replica broadcast.
+    pub fn receive(&self, message: &str) {
+        match message.split_once(' ') {
+            Some((origin, _)) if origin == self.instance_id => {}
+            Some((_, tag)) => self.drop_tag(tag),
+            None => tracing::warn!(message, "Ignoring malformed cache invalidation"),
+        }
+    }
+
+    fn drop_tag(&self, tag: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|_, entry| !entry.tags.iter().any(|entry_tag| entry_tag == tag));
+    }
+
+    fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Listens for other replicas' invalidations from startup until shutdown,
+/// when `cache.broadcast_invalidations` is on.
+pub fn register_hooks(lifecycle: &mut Lifecycle, state: &Arc<AppState>) {
+    let cache = state.response_cache.clone();
+    let Some((client, _)) = cache.redis.clone() else {
+        return;
+    };
+
+    let (stop, stopped) = oneshot::channel::<()>();
+    let (started, listening) = oneshot::channel();
+    lifecycle.on_start(Hook::new("cache invalidations", move || async move {
+        let listen = async move {
+            tokio::select! {
+                _ = listen(&cache, &client) => {}
+                _ = stopped => {}
+            }
+        };
+        let _ = started.send(tokio::spawn(listen));
+        Ok(())
+    }));
+    lifecycle.on_shutdown(Hook::new("cache invalidations", move || async move {
+        let _ = stop.send(());
+        if let Ok(listening) = listening.await {
+            listening.await?;
+        }
+        Ok(())
+    }));
+}
+
+/// Applies broadcast invalidations, resubscribing whenever the connection
+/// drops. Whatever was published meanwhile is lost, so the cache starts over
+/// after each reconnect.
+async fn listen(cache: &ResponseCache, client: &redis::Client) {
+    let channel = &cache.settings.invalidation_channel;
+    loop {
+        let subscribed = async {
+            let mut pubsub = client.get_async_pubsub().await?;
+            pubsub.subscribe(channel).await?;
+            redis::RedisResult::Ok(pubsub)
+        };
+        match subscribed.await {
+            Ok(pubsub) => {
+                cache.clear();
+                let mut messages = pubsub.into_on_message();
+                while let Some(message) = messages.next().await {
+                    match message.get_payload::<String>() {
+                        Ok(payload) => cache.receive(&payload),
+                        Err(e) => tracing::warn!(error = %e, "Ignoring unreadable cache invalidation"),
+                    }
+                }
+                tracing::warn!(channel, "Lost the cache invalidation subscription; resubscribing");
+            }
+            Err(e) => tracing::warn!(channel, error = %e, "Failed to subscribe to cache invalidations"),
+        }
+        tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+/// Serves `req` from the cache, or runs `handler` and caches its response
+/// for `ttl` under `tags` if it succeeded. Tags may name route parameters,
+/// as in `user:{id}`. Called by the code `#[cached]` generates.
+pub async fn cached<F>(req: &HttpRequest, ttl: Duration, tags: &[&str], handler: F) -> AppResult<HttpResponse>
+where
+    F: Future<Output = AppResult<HttpResponse>>,
+{
+    let Some(cache) = req
+        .app_data::<web::Data<AppState>>()
+        .map(|state| state.response_cache.clone())
+        .filter(|cache| cache.settings.enabled)
+    else {
+        return handler.await;
+    };
+
+    let key = key(req);
+    if let Some(response) = cache.get(&key) {
+        return Ok(response);
+    }
+
+    let response = handler.await?;
+    if response.status() != StatusCode::OK {
+        return Ok(response);
+    }
+
+    let (response, body) = response.into_parts();
+    let body = body::to_bytes(body).await.map_err(|_| AppError::InternalServerError)?;
+    let tags = tags.iter().map(|tag| expand_tag(tag, req)).collect();
+    cache.insert(key, req.path(), tags, ttl, response.status(), response.headers().clone(), body.clone());
+
+    let mut response = response.set_body(body).map_into_boxed_body();
+    response.headers_mut().insert(CACHE_HEADER, HeaderValue::from_static("miss"));
+    Ok(response)
+}
+
+fn key(req: &HttpRequest) -> String {
+    let header = |name| req.headers().get(name).map(|value| value.as_bytes()).unwrap_or_default();
+    let credential = [header(AUTHORIZATION), b"\n", header(COOKIE)].concat();
+
+    format!(
+        "{} {}?{} {}",
+        req.method(),
+        req.path(),
+        req.query_string(),
+        hash_token(&String::from_utf8_lossy(&credential))
+    )
+}
+
+/// `tag` with each `{name}` replaced by that route parameter.
+fn expand_tag(tag: &str, req: &HttpRequest) -> String {
+    let mut expanded = String::with_capacity(tag.len());
+    let mut rest = tag;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            break;
+        };
+        expanded.push_str(&rest[..start]);
+        let name = &rest[start + 1..end];
+        expanded.push_str(req.match_info().get(name).unwrap_or_default());
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
@@ -0,0 +1,173 @@
+//! Administrative commands run instead of the server, e.g.
+//! `actix-template jwt-keys add` or `actix-template --manifest`.
+
+use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+use platform_core::domain::Email;
+use sqlx::postgres::PgPoolOptions;
+use std::sync::Arc;
+
+use crate::config::{JwtKeySettings, JwtSettings, Settings};
+use crate::db;
+use crate::factories::{UserFactory, FACTORY_PASSWORD};
+use crate::manifest::Manifest;
+use crate::models::role::ROLE_ADMIN;
+use crate::models::user::User;
+use crate::services::RoleService;
+use crate::utils::{generate_secret, SystemClock};
+use crate::utils::jwt::DEFAULT_KID;
+
+const JWT_SECRET_LENGTH: usize = 64;
+
+/// The user `seed` signs developers in as.
+pub const SEED_EMAIL: &str = "dev@example.test";
+pub const SEED_USERNAME: &str = "dev";
+const DEFAULT_SEED_USERS: usize = 20;
+
+/// Runs the command named by `args`, or returns `None` to start the server.
+pub async fn run(args: &[String]) -> Option<Result<()>> {
+    match args.first().map(String::as_str) {
+        Some("jwt-keys") => Some(jwt_keys(&args[1..])),
+        Some("seed") => Some(seed(&args[1..]).await),
+        Some("grant-role") => Some(grant_role(&args[1..]).await),
+        Some("--manifest") => Some(manifest()),
+        _ => None,
+    }
+}
+
+/// Migrates the configured database and fills it for local development: a
+/// verified `dev` user plus `--users` more (20 by default), all with the
+/// factory password. Does nothing if the `dev` user exists, so
+/// `cargo xtask dev` can run it on every start.
+async fn seed(args: &[String]) -> Result<()> {
+    let users = match args {
+        [] => DEFAULT_SEED_USERS,
+        [flag, count] if flag == "--users" => count.parse().map_err(|_| anyhow!("--users takes a number"))?,
+        _ => bail!("usage: seed [--users <count>]"),
+    };
+
+    let settings = Settings::new()?;
+    let pool = PgPoolOptions::new().max_connections(1).connect(&settings.database.url).await?;
+    db::run_migrations(&pool, &settings.database).await?;
+
+    let seeded: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users WHERE username = $1)")
+        .bind(SEED_USERNAME)
+        .fetch_one(&pool)
+        .await?;
+    if seeded {
+        eprintln!("Already seeded; sign in as {} with password {}", SEED_EMAIL, FACTORY_PASSWORD);
+        return Ok(());
+    }
+
+    let dev = UserFactory::build()
+        .verified()
+        .email(SEED_EMAIL)
+        .username(SEED_USERNAME)
+        .full_name(Some("Dev User"))
+        .insert(&pool)
+        .await?;
+    RoleService::new(pool.clone(), Arc::new(SystemClock))
+        .grant(dev.id, ROLE_ADMIN, None)
+        .await?;
+    for _ in 0..users {
+        UserFactory::build().verified().insert(&pool).await?;
+    }
+    eprintln!("Seeded {} users; sign in as {} with password {}", users + 1, SEED_EMAIL, FACTORY_PASSWORD);
+    Ok(())
+}
+
+/// Grants a user a role, e.g. `grant-role ada@example.com admin` for the
+/// first admin, who can grant roles over the admin API from then on.
+async fn grant_role(args: &[String]) -> Result<()> {
+    let [email, role] = args else {
+        bail!("usage: grant-role <email> <role>");
+    };
+    let email = Email::parse(email)?;
+
+    let settings = Settings::new()?;
+    let pool = PgPoolOptions::new().max_connections(1).connect(&settings.database.url).await?;
+    let user: User = sqlx::query_as("SELECT * FROM users WHERE email = $1")
+        .bind(&email)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| anyhow!("No user with email {}", email))?;
+
+    RoleService::new(pool, Arc::new(SystemClock)).grant(user.id, role, None).await?;
+    eprintln!("Granted {} the {} role; it applies from their next sign-in or token refresh", email, role);
+    Ok(())
+}
+
+/// Prints the template manifest as JSON, for build pipelines to record.
+fn manifest() -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&Manifest::current())?);
+    Ok(())
+}
+
+/// Key rotation happens in three deploys, each applying the printed `[jwt]`
+/// block to every replica:
+///
+/// 1. `jwt-keys add` introduces a new key that validates but doesn't sign.
+/// 2. `jwt-keys promote <kid>` makes it the signer once all replicas know it.
+/// 3. `jwt-keys retire <kid>` drops the old key after its tokens expired.
+fn jwt_keys(args: &[String]) -> Result<()> {
+    let settings = Settings::new()?;
+    let mut jwt = settings.jwt;
+
+    // Carry a legacy single secret over as a named key
+    if jwt.keys.is_empty() {
+        let secret = jwt
+            .secret
+            .take()
+            .ok_or_else(|| anyhow!("either jwt.secret or jwt.keys must be configured"))?;
+        jwt.keys.push(JwtKeySettings {
+            kid: DEFAULT_KID.to_string(),
+            secret,
+        });
+        jwt.active_kid = Some(DEFAULT_KID.to_string());
+    }
+
+    match (args.first().map(String::as_str), args.get(1)) {
+        (Some("add"), None) => {
+            let kid = format!("{}-{}", Utc::now().format("%Y%m%d"), generate_secret(6).to_lowercase());
+            eprintln!("Added key '{}'; promote it once every replica runs with this configuration", kid);
+            jwt.keys.push(JwtKeySettings {
+                kid,
+                secret: generate_secret(JWT_SECRET_LENGTH),
+            });
+        }
+        (Some("promote"), Some(kid)) => {
+            if !jwt.keys.iter().any(|key| &key.kid == kid) {
+                bail!("unknown key '{}'", kid);
+            }
+            jwt.active_kid = Some(kid.clone());
+        }
+        (Some("retire"), Some(kid)) => {
+            if jwt.active_kid.as_ref() == Some(kid) {
+                bail!("cannot retire the active key '{}'; promote another key first", kid);
+            }
+            let before = jwt.keys.len();
+            jwt.keys.retain(|key| &key.kid != kid);
+            if jwt.keys.len() == before {
+                bail!("unknown key '{}'", kid);
+            }
+        }
+        _ => bail!("usage: jwt-keys add | jwt-keys promote <kid> | jwt-keys retire <kid>"),
+    }
+
+    print!("{}", render_jwt_keys(&jwt));
+    Ok(())
+}
+
+fn render_jwt_keys(jwt: &JwtSettings) -> String {
+    let mut output = format!(
+        "[jwt]\nactive_kid = \"{}\"\n",
+        jwt.active_kid.as_deref().unwrap_or_default()
+    );
+    for key in &jwt.keys {
+        output.push_str(&format!(
+            "\n[[jwt.keys]]\nkid = \"{}\"\nsecret = \"{}\"\n",
+            key.kid, key.secret
+        ));
+    }
+    output
+}
@@ -0,0 +1,154 @@
+//! An adaptive limit on concurrent requests, after Netflix's
+//! concurrency-limits: rather than a fixed cap that's either too low for a
+//! quiet database or too high for a struggling one, the limit follows the
+//! latency requests see. When requests slow down (queueing for pool
+//! connections, a saturated database) it shrinks and the excess is
+//! rejected with 503 straight away, instead of piling up until the pool
+//! times out for everyone. [`ConcurrencyLimit`](crate::middleware::ConcurrencyLimit)
+//! applies it to the database-heavy scopes.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::{ConcurrencyLimitSettings, LimitAlgorithm};
+
+/// Samples the long-term latency of `gradient` averages over.
+const LONG_WINDOW: f64 = 600.0;
+
+/// The limit shared by every actix worker.
+pub struct AdaptiveLimiter {
+    settings: ConcurrencyLimitSettings,
+    state: Mutex<State>,
+}
+
+struct State {
+    limit: f64,
+    inflight: usize,
+    /// Exponential average of latency in seconds, for `gradient`.
+    long_latency: Option<f64>,
+}
+
+/// A request's place under the limit. Finish it with the request's
+/// outcome; dropping it unfinished, e.g. when the client goes away, just
+/// frees the place.
+pub struct Permit {
+    limiter: Arc<AdaptiveLimiter>,
+    /// Requests in flight when this one started, itself included.
+    inflight: usize,
+    finished: bool,
+}
+
+impl AdaptiveLimiter {
+    pub fn new(settings: ConcurrencyLimitSettings) -> Self {
+        let limit = settings.initial_limit.clamp(settings.min_limit, settings.max_limit) as f64;
+        metrics::gauge!("concurrency_limit").set(limit);
+        Self {
+            settings,
+            state: Mutex::new(State {
+                limit,
+                inflight: 0,
+                long_latency: None,
+            }),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    /// How long rejected requests are told to wait.
+    pub fn retry_after(&self) -> Duration {
+        Duration::from_secs(self.settings.retry_after_secs)
+    }
+
+    /// A place under the limit, or `None` if it's reached.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<Permit> {
+        let mut state = self.state.lock().unwrap();
+        if state.inflight >= state.limit as usize {
+            metrics::counter!("concurrency_limit_rejected_total").increment(1);
+            return None;
+        }
+        state.inflight += 1;
+        Some(Permit {
+            limiter: self.clone(),
+            inflight: state.inflight,
+            finished: false,
+        })
+    }
+
+    /// Requests allowed at once.
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit as usize
+    }
+
+    pub fn inflight(&self) -> usize {
+        self.state.lock().unwrap().inflight
+    }
+
+    fn release(&self, sample: Option<(Duration, usize, bool)>) {
+        let mut state = self.state.lock().unwrap();
+        state.inflight -= 1;
+        let Some((latency, inflight, overloaded)) = sample else {
+            return;
+        };
+
+        let limit = match self.settings.algorithm {
+            LimitAlgorithm::Aimd => self.aimd(&state, latency, inflight, overloaded),
+            LimitAlgorithm::Gradient => self.gradient(&mut state, latency, inflight, overloaded),
+        };
+        state.limit = limit.clamp(self.settings.min_limit as f64, self.settings.max_limit as f64);
+        metrics::gauge!("concurrency_limit").set(state.limit);
+    }
+
+    fn aimd(&self, state: &State, latency: Duration, inflight: usize, overloaded: bool) -> f64 {
+        if overloaded || latency > Duration::from_millis(self.settings.latency_threshold_ms) {
+            state.limit * self.settings.backoff_ratio
+        } else if inflight as f64 * 2.0 >= state.limit {
+            // Only grow while the limit is what's holding requests back
+            state.limit + 1.0
+        } else {
+            state.limit
+        }
+    }
+
+    fn gradient(&self, state: &mut State, latency: Duration, inflight: usize, overloaded: bool) -> f64 {
+        let latency = latency.as_secs_f64().max(f64::EPSILON);
+        let long = match state.long_latency {
+            Some(long) => long + (latency - long) / LONG_WINDOW,
+            None => latency,
+        };
+        // Once load drops, let the average catch up with faster requests
+        let long = if long / latency > 2.0 { long * 0.95 } else { long };
+        state.long_latency = Some(long);
+
+        if !overloaded && (inflight as f64) < state.limit / 2.0 {
+            return state.limit;
+        }
+        let gradient = if overloaded {
+            0.5
+        } else {
+            (self.settings.tolerance * long / latency).clamp(0.5, 1.0)
+        };
+        // Leave room for a queue, so the limit can probe upwards
+        let estimate = state.limit * gradient + state.limit.sqrt();
+        state.limit * (1.0 - self.settings.smoothing) + estimate * self.settings.smoothing
+    }
+}
+
+impl Permit {
+    /// Frees the place and adapts the limit to how long the request took
+    /// and whether it failed for lack of capacity (503, 504, a pool
+    /// timeout).
+    pub fn finish(mut self, latency: Duration, overloaded: bool) {
+        self.finished = true;
+        self.limiter.release(Some((latency, self.inflight, overloaded)));
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.limiter.release(None);
+        }
+    }
+}
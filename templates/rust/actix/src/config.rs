@@ -1,58 +1,1348 @@
-use config::{Config, ConfigError, Environment, File};
-use serde::Deserialize;
+use config::builder::{ConfigBuilder, DefaultState};
+use config::{Config, ConfigError};
+use platform_auth::workload::WorkloadIdentitySettings;
+use platform_core::lifecycle::LifecycleSettings;
+use platform_core::rate_limit::BackendSettings;
+use platform_observability::log_level::LogLevelSettings;
+use platform_observability::otel::OtelSettings;
+use platform_observability::propagation::{self, PropagationSettings};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use utoipa::ToSchema;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
     pub server: ServerSettings,
+    pub runtime: RuntimeSettings,
     pub database: DatabaseSettings,
     pub jwt: JwtSettings,
     pub redis: RedisSettings,
+    pub session: SessionSettings,
+    pub token_delivery: TokenDeliverySettings,
+    #[serde(default)]
+    pub signing: SigningSettings,
+    pub auth_throttle: AuthThrottleSettings,
+    pub rate_limit: RateLimitSettings,
+    pub maintenance: MaintenanceSettings,
+    pub concurrency_limit: ConcurrencyLimitSettings,
+    pub security: SecuritySettings,
+    pub request_signing: RequestSigningSettings,
+    pub egress: EgressSettings,
+    pub email: EmailSettings,
+    pub mail: MailSettings,
+    pub email_verification: EmailVerificationSettings,
+    pub sms: SmsSettings,
+    pub phone_otp: PhoneOtpSettings,
+    pub magic_link: MagicLinkSettings,
+    pub password_reset: PasswordResetSettings,
+    pub webauthn: WebauthnSettings,
+    pub guests: GuestSettings,
+    pub impersonation: ImpersonationSettings,
+    pub step_up: StepUpSettings,
+    pub jobs: JobSettings,
+    pub push: PushSettings,
+    pub operations: OperationSettings,
+    pub sagas: SagaSettings,
+    pub deletion_fanout: DeletionFanoutSettings,
+    pub health: HealthSettings,
+    pub cache: CacheSettings,
+    pub pagination: PaginationSettings,
+    pub object_storage: ObjectStorageSettings,
+    pub partitions: PartitionSettings,
+    pub analytics: AnalyticsSettings,
+    pub olap: OlapSettings,
+    pub metering: MeteringSettings,
+    pub retention: RetentionSettings,
+    pub materialized_views: MaterializedViewSettings,
+    pub stats: StatsSettings,
+    pub lifecycle: LifecycleSettings,
+    pub log_level: LogLevelSettings,
+    /// Span export and trace context propagation.
+    pub otel: OtelSettings,
+    /// Mesh and tracing headers handed on to the services a request calls.
+    pub propagation: PropagationSettings,
+    pub telemetry: TelemetrySettings,
+    /// Other services calling with Kubernetes or SPIFFE tokens.
+    #[serde(default)]
+    pub workload_identity: WorkloadIdentitySettings,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ServerSettings {
     pub host: String,
     pub port: u16,
+    /// actix workers, each a thread with its own single-threaded runtime,
+    /// serving the public listeners.
     pub workers: usize,
+    /// Blocking threads of each actix worker; by default 512 shared
+    /// among the workers.
+    pub worker_max_blocking_threads: Option<usize>,
+    /// Time between SIGTERM and closing the listeners, while `/ready`
+    /// already fails, for load balancers to stop sending requests.
+    pub drain_delay_secs: u64,
+    /// Time in-flight requests get to finish once the listeners close.
+    pub drain_timeout_secs: u64,
+    /// Limit on JSON request bodies after decompression, so a small
+    /// compressed body can't expand into an arbitrarily large one.
+    pub max_json_body_bytes: usize,
+    /// Limit on other request bodies (raw payloads, forms).
+    pub max_payload_bytes: usize,
+    /// Key naming of JSON request and response bodies.
+    pub json_naming: JsonNaming,
+    /// The middleware stack, outermost first; see
+    /// [`crate::middleware::Pipeline`].
+    pub middleware: Vec<MiddlewareSettings>,
+    /// Addresses to accept connections on. Empty means a single plaintext
+    /// public listener on `host:port`.
+    pub listeners: Vec<ListenerSettings>,
+    /// Sets `SO_REUSEPORT` on public listeners, so several processes can
+    /// listen on the same port and the kernel spreads connections among
+    /// them. Unix only.
+    pub reuse_port: bool,
+    /// Accept loops per public listener, each on its own `SO_REUSEPORT`
+    /// socket with a share of `workers`. Above 1 implies `reuse_port`.
+    pub acceptors: usize,
+    pub cpu_pinning: CpuPinningSettings,
+    /// How long clients may remember the `Alt-Svc` header advertising
+    /// HTTP/3 listeners.
+    pub http3_alt_svc_max_age_secs: u64,
+}
+
+impl ServerSettings {
+    /// The configured listeners, or `host:port` when none are.
+    pub fn listeners(&self) -> Vec<ListenerSettings> {
+        if !self.listeners.is_empty() {
+            return self.listeners.clone();
+        }
+        vec![ListenerSettings {
+            address: format!("{}:{}", self.host, self.port),
+            role: ListenerRole::Public,
+            tls: None,
+            http3: false,
+        }]
+    }
+}
+
+/// The tokio runtime the server's main thread, its background loops and
+/// hooks run on; see [`crate::runtime`]. Request handling runs on the actix
+/// workers of [`ServerSettings::workers`].
+#[derive(Debug, Deserialize, Clone)]
+pub struct RuntimeSettings {
+    /// Defaults to one per CPU.
+    pub worker_threads: Option<usize>,
+    /// Threads `spawn_blocking` may start, beyond the workers.
+    pub max_blocking_threads: usize,
+    /// Stack size of worker and blocking threads in bytes; tokio's default
+    /// is 2 MiB.
+    pub thread_stack_size: Option<usize>,
+    /// Name of the runtime's threads, as `top -H` and profilers show them.
+    pub thread_name: String,
+}
+
+/// How keys of JSON bodies are named on the wire. Models serialize with
+/// snake_case keys; other namings are applied by
+/// [`JsonNamingTransform`](crate::middleware::JsonNamingTransform).
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonNaming {
+    /// `full_name`
+    #[default]
+    SnakeCase,
+    /// `fullName`
+    CamelCase,
+}
+
+/// Pins each actix worker serving public listeners to a CPU core; see
+/// [`crate::runtime::CpuPinning`].
+#[derive(Debug, Deserialize, Clone)]
+pub struct CpuPinningSettings {
+    pub enabled: bool,
+    /// Cores to pin workers to, round robin. Empty means every core this
+    /// process may run on.
+    pub cores: Vec<usize>,
+}
+
+/// An address the server accepts connections on, e.g. `0.0.0.0:8080` or
+/// `[::]:8443`. IPv6 wildcards only accept IPv6, so listing both families
+/// on one port gives a dual-stack server.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ListenerSettings {
+    pub address: String,
+    #[serde(default)]
+    pub role: ListenerRole,
+    /// Serves HTTPS when set.
+    pub tls: Option<TlsSettings>,
+    /// Also serves HTTP/3 over QUIC, on the same port over UDP with the
+    /// same certificate. Public TLS listeners only; needs the `http3`
+    /// feature.
+    #[serde(default)]
+    pub http3: bool,
+}
+
+/// What a listener serves.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenerRole {
+    /// The full API.
+    #[default]
+    Public,
+    /// Health and readiness only, for probes on an internal port.
+    Admin,
+}
+
+/// PEM files for a TLS listener.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TlsSettings {
+    /// Certificate chain, leaf first.
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+/// An entry of `server.middleware`, named by its `name` key.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "name", rename_all = "snake_case")]
+pub enum MiddlewareSettings {
+    /// Request spans for `tracing`.
+    Tracing,
+    /// Assigns each request an `X-Request-Id` and logs failures with it.
+    RequestId,
+    /// Access log; `format` takes actix `Logger` format strings.
+    Logger { format: Option<String> },
+    /// Hardening headers, CSRF and CORS, configured under `security`.
+    Security,
+    /// Records every request to the OLAP sink configured under `olap`.
+    RequestEvents,
+    /// Checks JSON responses against the OpenAPI document; for development
+    /// and staging.
+    ResponseValidation {
+        #[serde(default)]
+        on_mismatch: OnMismatch,
+    },
+}
+
+impl MiddlewareSettings {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MiddlewareSettings::Tracing => "tracing",
+            MiddlewareSettings::RequestId => "request_id",
+            MiddlewareSettings::Logger { .. } => "logger",
+            MiddlewareSettings::Security => "security",
+            MiddlewareSettings::RequestEvents => "request_events",
+            MiddlewareSettings::ResponseValidation { .. } => "response_validation",
+        }
+    }
+}
+
+/// What [`ResponseValidation`](crate::middleware::ResponseValidation) does
+/// with a response that doesn't match the OpenAPI document.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnMismatch {
+    /// Logs a warning and sends the response anyway.
+    #[default]
+    Log,
+    /// Logs an error and answers 500 instead.
+    Fail,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct DatabaseSettings {
     pub url: String,
     pub max_connections: u32,
+    /// Disable to leave migrations to the deployment pipeline.
+    pub run_migrations: bool,
+    pub migration_lock_timeout_secs: u64,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct JwtSettings {
-    pub secret: String,
+    /// Single signing secret, used when no `keys` are configured.
+    pub secret: Option<String>,
+    /// Signing keys identified by `kid`; all of them validate tokens.
+    #[serde(default)]
+    pub keys: Vec<JwtKeySettings>,
+    /// The key that signs newly issued tokens.
+    pub active_kid: Option<String>,
     pub access_token_expiry: i64,
     pub refresh_token_expiry: i64,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct JwtKeySettings {
+    pub kid: String,
+    pub secret: String,
+}
+
+/// Keys for signed and encrypted payloads (email links, signed URLs, cookies).
+/// Derived from the JWT keys when none are configured.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SigningSettings {
+    #[serde(default)]
+    pub keys: Vec<SigningKeySettings>,
+    pub active_kid: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SigningKeySettings {
+    pub kid: String,
+    pub secret: String,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct RedisSettings {
     pub url: String,
 }
 
+/// Token buckets per client IP for every `/api/v1` request; see
+/// [`crate::rate_limit`].
+#[derive(Debug, Deserialize, Clone)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    /// Requests per second per client IP on routes without their own rule.
+    /// 0 disables.
+    pub default_rate_per_second: f64,
+    pub default_burst: u32,
+    /// Rules for matching routes, the first match winning. Without any
+    /// configured, sign-in and sign-up are held to a trickle per client.
+    #[serde(default = "default_route_limits")]
+    pub routes: Vec<RouteLimitSettings>,
+    /// Gateways (IPs or CIDRs) in front of the service, such as the load
+    /// balancer. Requests through them count against the client their
+    /// `X-Forwarded-For` names.
+    #[serde(default)]
+    pub trusted_gateways: Vec<String>,
+    /// Where buckets are kept: `memory` per replica, or `redis` shared by
+    /// all of them.
+    #[serde(default)]
+    pub backend: BackendSettings,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RouteLimitSettings {
+    /// Full path, or a prefix ending in `*` such as `/api/v1/auth/*`.
+    pub path: String,
+    /// Only requests with this method match; any method when unset.
+    #[serde(default)]
+    pub method: Option<String>,
+    /// 0 exempts matching routes from limits altogether.
+    pub rate_per_second: f64,
+    /// Defaults to one second's worth of requests.
+    #[serde(default)]
+    pub burst: Option<u32>,
+}
+
+fn default_route_limits() -> Vec<RouteLimitSettings> {
+    ["/api/v1/auth/login", "/api/v1/auth/register"]
+        .into_iter()
+        .map(|path| RouteLimitSettings {
+            path: path.to_string(),
+            method: Some("POST".to_string()),
+            rate_per_second: 0.5,
+            burst: Some(10),
+        })
+        .collect()
+}
+
+/// Limits for `/auth/*` routes, independent of any general API limits.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AuthThrottleSettings {
+    pub enabled: bool,
+    /// Length of the counting window, in seconds.
+    pub window_secs: u64,
+    /// Requests allowed per client IP per window.
+    pub ip_limit: u64,
+    /// Failed attempts allowed per account per window before it's locked.
+    pub account_failure_limit: u64,
+    /// Failed attempts (per IP or account) before responses are delayed.
+    pub delay_after_failures: u64,
+    /// Delay added for each failure beyond `delay_after_failures`.
+    pub delay_step_ms: u64,
+    pub max_delay_ms: u64,
+    /// Gateways (IPs or CIDRs) exempt from per-IP limits because they
+    /// aggregate many clients.
+    #[serde(default)]
+    pub trusted_gateways: Vec<String>,
+}
+
+/// Maintenance mode: API routes answer 503 with a `Retry-After` hint while
+/// health checks keep working.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MaintenanceSettings {
+    pub enabled: bool,
+    pub retry_after_secs: u64,
+    pub message: String,
+}
+
+/// Adaptive limit on the requests the database-heavy scopes serve at once;
+/// see [`crate::concurrency_limit`].
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConcurrencyLimitSettings {
+    pub enabled: bool,
+    pub algorithm: LimitAlgorithm,
+    pub initial_limit: usize,
+    pub min_limit: usize,
+    pub max_limit: usize,
+    /// `aimd`: responses slower than this count as overload.
+    pub latency_threshold_ms: u64,
+    /// `aimd`: factor the limit is multiplied by on overload.
+    pub backoff_ratio: f64,
+    /// `gradient`: how many times the long-term latency a request may take
+    /// before the limit shrinks.
+    pub tolerance: f64,
+    /// `gradient`: weight of each new estimate of the limit, from 0 to 1.
+    pub smoothing: f64,
+    /// `Retry-After` of rejected requests.
+    pub retry_after_secs: u64,
+}
+
+/// How [`ConcurrencyLimitSettings`] adapts the limit.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitAlgorithm {
+    /// Additive increase, multiplicative decrease: grows by one while
+    /// requests are fast, shrinks by `backoff_ratio` when one is slow or
+    /// the database is out of connections.
+    Aimd,
+    /// Follows the ratio of long-term to recent latency, so it needs no
+    /// latency threshold.
+    Gradient,
+}
+
+/// Settings of the `security` preset. Each protection can be tuned or
+/// switched off on its own.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SecuritySettings {
+    /// Add the hardening response headers.
+    pub headers: bool,
+    /// `Strict-Transport-Security` max age; 0 leaves the header out.
+    pub hsts_max_age_secs: u64,
+    pub content_security_policy: String,
+    pub frame_options: String,
+    pub referrer_policy: String,
+    /// Reject cookie-authenticated changes from other origins.
+    pub csrf: bool,
+    /// Origins allowed to call the API from a browser; none by default.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    pub cors_max_age_secs: usize,
+}
+
+/// HMAC-signed requests from machine clients.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RequestSigningSettings {
+    /// How far a request's `X-Dxp-Date` may be from the server clock,
+    /// bounding the window in which a captured request can be replayed.
+    pub max_skew_secs: i64,
+}
+
+/// Outbound requests; see [`EgressPolicy`](crate::egress::EgressPolicy).
+/// Schemes, ports and hosts constrain user-supplied URLs, address ranges
+/// every request.
+#[derive(Debug, Deserialize, Clone)]
+pub struct EgressSettings {
+    pub allowed_schemes: Vec<String>,
+    pub allowed_ports: Vec<u16>,
+    /// Hosts URLs may point to (`*.example.com` for subdomains); any
+    /// public host when empty.
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    /// Ranges blocked on top of the built-in private and reserved ones.
+    #[serde(default)]
+    pub blocked_cidrs: Vec<String>,
+    /// Built-in blocked ranges that may be reached anyway, e.g. the network
+    /// internal webhook receivers live on.
+    #[serde(default)]
+    pub allowed_cidrs: Vec<String>,
+    /// Let URLs reach private and loopback addresses, for local development.
+    pub allow_private: bool,
+    pub timeout_secs: u64,
+}
+
+/// Branding shared by every email; see [`templates`](crate::templates).
+#[derive(Debug, Deserialize, Clone)]
+pub struct EmailSettings {
+    pub product_name: String,
+    pub support_email: String,
+}
+
+/// Email delivery; see [`mail`](crate::mail).
+#[derive(Debug, Deserialize, Clone)]
+pub struct MailSettings {
+    pub provider: MailProvider,
+    /// Sender address, e.g. `Acme <no-reply@acme.test>`.
+    #[serde(default)]
+    pub from_address: Option<String>,
+    #[serde(default)]
+    pub postmark_server_token: Option<String>,
+    pub postmark_api_url: String,
+    /// SMTP relay, e.g. `smtp.sendgrid.net`.
+    #[serde(default)]
+    pub smtp_host: Option<String>,
+    pub smtp_port: u16,
+    /// Credentials for relays that require them; both or neither.
+    #[serde(default)]
+    pub smtp_username: Option<String>,
+    #[serde(default)]
+    pub smtp_password: Option<String>,
+    pub smtp_tls: SmtpTls,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MailProvider {
+    /// Log emails instead of sending them.
+    Console,
+    Postmark,
+    Smtp,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Upgrade a plain connection, usually on port 587.
+    Starttls,
+    /// TLS from the start, usually on port 465.
+    Tls,
+    /// Plain text, for a local relay such as Mailpit.
+    None,
+}
+
+/// Confirming the email address of new accounts.
+#[derive(Debug, Deserialize, Clone)]
+pub struct EmailVerificationSettings {
+    pub token_ttl_secs: i64,
+    /// Time before another verification email may be sent to an account.
+    pub resend_interval_secs: i64,
+    /// Where links point; the signed token is appended as `?token=`. The
+    /// page posts it to `/auth/verify-email`.
+    pub verify_url: String,
+}
+
+/// SMS delivery; see [`sms`](crate::sms).
+#[derive(Debug, Deserialize, Clone)]
+pub struct SmsSettings {
+    pub provider: SmsProvider,
+    /// Sender number in E.164 format.
+    #[serde(default)]
+    pub from_number: Option<String>,
+    #[serde(default)]
+    pub twilio_account_sid: Option<String>,
+    #[serde(default)]
+    pub twilio_auth_token: Option<String>,
+    pub twilio_api_url: String,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmsProvider {
+    /// Log messages instead of sending them.
+    Console,
+    Twilio,
+}
+
+/// One-time codes sent by SMS for phone verification and OTP login.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PhoneOtpSettings {
+    /// Allow logging in with a code sent to a verified phone number.
+    pub login_enabled: bool,
+    pub code_length: u32,
+    pub code_ttl_secs: i64,
+    /// Wrong guesses before a code is burned.
+    pub max_attempts: i32,
+    /// Codes sent to one phone number per window.
+    pub send_limit: i64,
+    pub send_window_secs: i64,
+}
+
+/// Password-less login with single-use links sent by email.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MagicLinkSettings {
+    pub enabled: bool,
+    pub link_ttl_secs: i64,
+    /// Links sent to one email address per window.
+    pub send_limit: i64,
+    pub send_window_secs: i64,
+    /// Where links point; the signed token is appended as `?token=`.
+    pub verify_url: String,
+    /// Only accept a link from the client (user agent and `X-Device-Id`)
+    /// that requested it.
+    pub bind_device: bool,
+}
+
+/// Resetting a forgotten password with a link sent by email.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PasswordResetSettings {
+    pub link_ttl_secs: i64,
+    /// Links sent to one email address per window.
+    pub send_limit: i64,
+    pub send_window_secs: i64,
+    /// Where links point; the signed token is appended as `?token=`. The
+    /// page posts it with the new password to `/auth/reset-password`.
+    pub reset_url: String,
+}
+
+/// Anonymous guest sessions that can later become a full account.
+#[derive(Debug, Deserialize, Clone)]
+pub struct GuestSettings {
+    pub enabled: bool,
+    /// Lifetime of a guest and its token; an expired guest and everything
+    /// it created are deleted.
+    pub ttl_secs: i64,
+}
+
+/// Support staff acting as a user from an admin listener.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ImpersonationSettings {
+    pub token_ttl_secs: i64,
+}
+
+/// Re-authentication for sensitive operations; see
+/// [`step_up`](crate::middleware::step_up).
+#[derive(Debug, Deserialize, Clone)]
+pub struct StepUpSettings {
+    pub enabled: bool,
+    /// How long a re-authentication counts as recent; also the lifetime of
+    /// the elevated token.
+    pub max_age_secs: i64,
+}
+
+/// Passkeys; see [`webauthn`](crate::webauthn).
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebauthnSettings {
+    pub enabled: bool,
+    /// The relying party ID: the domain passkeys are scoped to, e.g.
+    /// `example.com`.
+    pub rp_id: String,
+    /// The origin browsers run the ceremonies from, e.g.
+    /// `https://app.example.com`; must be `rp_id` or one of its subdomains.
+    pub rp_origin: String,
+    /// Shown by authenticators next to the account.
+    pub rp_name: String,
+    /// How long a started registration or login can be finished.
+    pub ceremony_ttl_secs: i64,
+}
+
+/// Background jobs; see [`jobs`](crate::jobs).
+#[derive(Debug, Deserialize, Clone)]
+pub struct JobSettings {
+    /// Run a worker inside the server process.
+    pub enabled: bool,
+    /// Jobs a worker runs at once.
+    pub concurrency: usize,
+    pub poll_interval_ms: u64,
+    pub max_attempts: i32,
+    /// Delay before the first retry, doubled for each further one.
+    pub retry_base_secs: u64,
+    pub retry_max_secs: u64,
+    /// A running job whose worker hasn't finished it this long after
+    /// claiming it, e.g. because the process died, is claimed again; jobs
+    /// must finish well within it.
+    pub visibility_timeout_secs: i64,
+    /// Payload fields the job dashboard shows as `[REDACTED]`: any whose
+    /// name contains one of these, ignoring case, at any depth.
+    pub redacted_fields: Vec<String>,
+    /// Named queues; jobs of kinds no queue lists run in `default`.
+    #[serde(default)]
+    pub queues: BTreeMap<String, JobQueueSettings>,
+}
+
+impl JobSettings {
+    /// The queue jobs of `kind` run in.
+    pub fn queue_of(&self, kind: &str) -> &str {
+        self.queues
+            .iter()
+            .find(|(_, queue)| queue.kinds.iter().any(|k| k == kind))
+            .map_or(DEFAULT_JOB_QUEUE, |(name, _)| name.as_str())
+    }
+
+    /// Every queue, including `default` when it isn't configured.
+    pub fn all_queues(&self) -> BTreeMap<String, JobQueueSettings> {
+        let mut queues = self.queues.clone();
+        queues.entry(DEFAULT_JOB_QUEUE.to_string()).or_default();
+        queues
+    }
+}
+
+/// The queue of jobs whose kind no queue lists.
+pub const DEFAULT_JOB_QUEUE: &str = "default";
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct JobQueueSettings {
+    /// Job kinds that run in this queue.
+    #[serde(default)]
+    pub kinds: Vec<String>,
+    /// Queues with a higher priority are served first when they compete for
+    /// the worker.
+    #[serde(default)]
+    pub priority: i32,
+    /// Jobs of this queue a worker runs at once, within `jobs.concurrency`;
+    /// unlimited if unset.
+    #[serde(default)]
+    pub concurrency: Option<usize>,
+    #[serde(default)]
+    pub rate_limit: Option<JobRateLimitSettings>,
+}
+
+/// At most `max` jobs per `period_secs` per rate key, counted by each
+/// worker. Jobs enqueued without a key share one limit.
+#[derive(Debug, Deserialize, Clone, Serialize)]
+pub struct JobRateLimitSettings {
+    pub max: u32,
+    pub period_secs: i64,
+}
+
+/// Long-running operations; see [`OperationService`](crate::services::OperationService).
+#[derive(Debug, Deserialize, Clone)]
+pub struct OperationSettings {
+    /// How often progress streams re-read the operation, to pick up updates
+    /// made by other replicas.
+    pub watch_poll_interval_ms: u64,
+    /// Interval between SSE keep-alive comments.
+    pub keep_alive_secs: u64,
+}
+
+/// The in-memory response cache used by `#[cached]` handlers.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CacheSettings {
+    /// Off, `#[cached]` handlers run on every request.
+    pub enabled: bool,
+    /// Responses kept at most; new ones aren't cached while it is full.
+    pub max_entries: usize,
+    /// Publish tag invalidations on Redis so every replica drops its
+    /// entries, not just the one handling the write.
+    pub broadcast_invalidations: bool,
+    /// Redis pub/sub channel the replicas share.
+    pub invalidation_channel: String,
+}
+
+/// List endpoints' paging; see `GET /users`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PaginationSettings {
+    /// How totals are counted when the request has no `count` parameter.
+    pub count: CountMode,
+}
+
+/// How a paginated response computes `total`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CountMode {
+    /// `COUNT(*)`, which scans the whole table.
+    Exact,
+    /// The query planner's row estimate, from table statistics; instant but
+    /// only as fresh as the last `ANALYZE`.
+    Estimated,
+    /// No total at all.
+    None,
+}
+
+/// Where archives are written; see [`object_storage`](crate::object_storage).
+#[derive(Debug, Deserialize, Clone)]
+pub struct ObjectStorageSettings {
+    pub provider: ObjectStorageProvider,
+    /// Root directory of the `local` provider.
+    pub path: String,
+    #[serde(default)]
+    pub bucket: Option<String>,
+    #[serde(default)]
+    pub region: Option<String>,
+    /// S3-compatible endpoint such as MinIO; AWS when unset.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    /// Credentials; when unset they come from the `AWS_*` environment
+    /// variables or the instance's role.
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+    #[serde(default)]
+    pub secret_access_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ObjectStorageProvider {
+    /// Files under `object_storage.path`.
+    Local,
+    S3,
+}
+
+/// Monthly partitions of high-volume tables; see
+/// [`partitions`](crate::partitions).
+#[derive(Debug, Deserialize, Clone)]
+pub struct PartitionSettings {
+    pub enabled: bool,
+    /// How often partitions are created and retired.
+    pub interval_secs: u64,
+    /// Months partitions are created ahead of the current one.
+    pub premake_months: u32,
+    /// Object storage prefix archives are written under, as
+    /// `<prefix>/<table>/<partition>.parquet`.
+    pub archive_prefix: String,
+    /// Managed tables by name. Each must be partitioned by range on a
+    /// timestamp, with partitions named `<table>_pYYYYMM`.
+    pub tables: HashMap<String, PartitionedTableSettings>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PartitionedTableSettings {
+    /// Partitions whose month ended this many months ago are retired.
+    /// 0 keeps them forever.
+    pub retention_months: u32,
+    /// Copy retired partitions to object storage as Parquet before
+    /// dropping them.
+    pub archive: bool,
+}
+
+/// Scheduled Parquet exports for the data platform; see
+/// [`analytics`](crate::analytics).
+#[derive(Debug, Deserialize, Clone)]
+pub struct AnalyticsSettings {
+    pub enabled: bool,
+    /// How often the server looks for datasets due for export.
+    pub poll_interval_secs: u64,
+    /// Object storage prefix exports are written under.
+    pub prefix: String,
+    /// Exported datasets by name.
+    #[serde(default)]
+    pub datasets: HashMap<String, DatasetSettings>,
+}
+
+/// A table or query exported every `interval_secs`; exactly one of
+/// `table` and `query` must be set.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DatasetSettings {
+    #[serde(default)]
+    pub table: Option<String>,
+    /// Runs in a read-only transaction.
+    #[serde(default)]
+    pub query: Option<String>,
+    pub interval_secs: i64,
+}
+
+impl DatasetSettings {
+    /// The statement to export, or `None` unless exactly one of `table`
+    /// and `query` is set.
+    pub fn select(&self) -> Option<String> {
+        match (&self.table, &self.query) {
+            (Some(table), None) => Some(format!("SELECT * FROM {}", crate::db::quote_ident(table))),
+            (None, Some(query)) => Some(query.trim().trim_end_matches(';').to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Request and domain events for an OLAP store; see [`olap`](crate::olap).
+#[derive(Debug, Deserialize, Clone)]
+pub struct OlapSettings {
+    pub provider: OlapProvider,
+    /// Events held in memory awaiting a write. Once it is full, new events
+    /// are dropped rather than slowing requests down.
+    pub buffer_size: usize,
+    /// Most events written in one insert.
+    pub batch_size: usize,
+    pub flush_interval_ms: u64,
+    /// Attempts at writing a batch before it is dropped.
+    pub max_attempts: u32,
+    pub request_table: String,
+    pub domain_table: String,
+    /// ClickHouse HTTP interface.
+    pub clickhouse_url: String,
+    pub clickhouse_database: String,
+    #[serde(default)]
+    pub clickhouse_user: Option<String>,
+    #[serde(default)]
+    pub clickhouse_password: Option<String>,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OlapProvider {
+    /// Events aren't recorded.
+    None,
+    /// Log events instead of writing them.
+    Console,
+    ClickHouse,
+}
+
+/// Billable usage per user and API key; see [`metering`](crate::metering).
+#[derive(Debug, Deserialize, Clone)]
+pub struct MeteringSettings {
+    pub enabled: bool,
+    /// How often counts are added to the hourly usage records, and closed
+    /// hours exported.
+    pub flush_interval_secs: u64,
+    /// Hours are exported this long after they end, once every replica has
+    /// flushed its counts for them.
+    pub export_delay_secs: i64,
+    pub export: UsageExportProvider,
+    /// Where the `webhook` exporter posts usage records.
+    #[serde(default)]
+    pub export_url: Option<String>,
+    /// Bearer token for `export_url`.
+    #[serde(default)]
+    pub export_token: Option<String>,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageExportProvider {
+    /// Usage is only kept in `usage_records`.
+    None,
+    /// Log exported records.
+    Console,
+    /// POST them to `metering.export_url`.
+    Webhook,
+}
+
+/// TTLs of record types, enforced by deleting expired rows; see
+/// [`retention`](crate::retention).
+#[derive(Debug, Deserialize, Clone)]
+pub struct RetentionSettings {
+    pub enabled: bool,
+    /// How often the server looks for policies due for enforcement.
+    pub poll_interval_secs: u64,
+    /// How often each policy is enforced.
+    pub interval_secs: i64,
+    /// Rows deleted per statement, so enforcement never holds many locks.
+    pub batch_size: i64,
+    /// Count the rows every policy would delete instead of deleting them.
+    pub dry_run: bool,
+    /// Policies by record type: `audit_events`, `sessions` or
+    /// `deactivated_users`.
+    #[serde(default)]
+    pub policies: HashMap<String, RetentionPolicySettings>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RetentionPolicySettings {
+    /// Days rows are kept, counted from when they became the record type's
+    /// to delete; see [`retention`](crate::retention).
+    pub ttl_days: i64,
+    /// Count the rows this policy would delete instead of deleting them.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Materialized views the server refreshes; see
+/// [`materialized_views`](crate::materialized_views).
+#[derive(Debug, Deserialize, Clone)]
+pub struct MaterializedViewSettings {
+    pub enabled: bool,
+    /// How often the server looks for views due for a refresh.
+    pub poll_interval_secs: u64,
+    /// Views created by migrations, by name.
+    #[serde(default)]
+    pub views: HashMap<String, MaterializedViewConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MaterializedViewConfig {
+    /// How often the view is refreshed, which bounds how stale it gets.
+    pub interval_secs: i64,
+    /// Order of the rows `/api/v1/stats/{view}` returns, e.g. `day DESC`.
+    #[serde(default)]
+    pub order_by: Option<String>,
+}
+
+/// `GET /stats`; see [`StatsService`](crate::services::StatsService).
+#[derive(Debug, Deserialize, Clone)]
+pub struct StatsSettings {
+    /// How old the figures served may be.
+    pub max_staleness_secs: i64,
+    /// Days of signups returned, today included.
+    pub signup_days: i64,
+}
+
+/// Readiness checks of the database and downstream services; see
+/// [`HealthRegistry`](crate::health::HealthRegistry).
+#[derive(Debug, Deserialize, Clone)]
+pub struct HealthSettings {
+    /// How long a check result is reused, so frequent probes don't turn
+    /// into load on dependencies.
+    pub cache_ttl_ms: u64,
+    pub check_timeout_ms: u64,
+    /// Consecutive failures after which a dependency's circuit opens and
+    /// it is reported down without being checked.
+    pub failure_threshold: u32,
+    /// How long a circuit stays open before one trial check.
+    pub open_secs: i64,
+}
+
+/// Heartbeats to the platform control plane; see [`telemetry`](crate::telemetry).
+#[derive(Debug, Deserialize, Clone)]
+pub struct TelemetrySettings {
+    pub enabled: bool,
+    /// URL heartbeats are POSTed to.
+    #[serde(default)]
+    pub endpoint: String,
+    /// Sent as a bearer token.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// How this service appears in the platform inventory.
+    pub service_name: String,
+    pub environment: String,
+    pub interval_secs: u64,
+    /// Cap on the delay between retries of failed heartbeats, which
+    /// doubles with each failure starting from `interval_secs`.
+    pub max_backoff_secs: u64,
+    pub timeout_secs: u64,
+}
+
+/// Saga execution; see [`saga`](crate::saga).
+#[derive(Debug, Deserialize, Clone)]
+pub struct SagaSettings {
+    /// How long a saga stays claimed by the worker executing it; must
+    /// exceed the slowest step. Sagas whose lease ran out are resumed.
+    pub lease_secs: i64,
+    /// Failed compensations are retried this often before the saga is
+    /// marked `failed` for manual review.
+    pub max_compensation_attempts: i32,
+    /// How often the server looks for sagas to resume.
+    pub resume_interval_secs: u64,
+}
+
+/// Downstream services that must confirm a user's deletion before it is
+/// finished; see [`deletion_fanout`](crate::saga::deletion_fanout).
+#[derive(Debug, Deserialize, Clone)]
+pub struct DeletionFanoutSettings {
+    pub publisher: DeletionPublisherProvider,
+    /// Services by name. None means deletions finish right away.
+    #[serde(default)]
+    pub services: BTreeMap<String, DownstreamServiceSettings>,
+    /// How often `UserDeletionRequested` is sent again to services that
+    /// haven't confirmed.
+    pub retry_interval_secs: i64,
+    /// Deletions still unconfirmed this long after they were requested are
+    /// left for manual review.
+    pub timeout_secs: i64,
+    /// How often the server looks for services to remind and deletions to
+    /// finish or give up on.
+    pub poll_interval_secs: u64,
+    pub request_timeout_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DownstreamServiceSettings {
+    /// The workload identity subject the service confirms with, e.g.
+    /// `system:serviceaccount:billing:invoicer`.
+    pub identity: String,
+    /// Where the `webhook` publisher posts the service's events.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Bearer token for `url`.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletionPublisherProvider {
+    /// Log events instead of sending them.
+    Console,
+    /// POST them to each service's `url`.
+    Webhook,
+}
+
+/// Push notification delivery; see [`push`](crate::push).
+#[derive(Debug, Deserialize, Clone)]
+pub struct PushSettings {
+    pub provider: PushProvider,
+    #[serde(default)]
+    pub fcm_project_id: Option<String>,
+    /// Path to the Firebase service account JSON key.
+    #[serde(default)]
+    pub fcm_service_account_path: Option<String>,
+    pub fcm_api_url: String,
+    /// Path to the APNs `.p8` signing key.
+    #[serde(default)]
+    pub apns_key_path: Option<String>,
+    #[serde(default)]
+    pub apns_key_id: Option<String>,
+    #[serde(default)]
+    pub apns_team_id: Option<String>,
+    /// The app's bundle id.
+    #[serde(default)]
+    pub apns_topic: Option<String>,
+    /// `https://api.sandbox.push.apple.com` for development builds.
+    pub apns_api_url: String,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PushProvider {
+    /// Log notifications instead of sending them.
+    Console,
+    /// FCM for Android devices and APNs for iOS ones, for whichever of the
+    /// two is configured.
+    Native,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SessionSettings {
+    pub binding_mode: BindingMode,
+    pub bind_fingerprint: bool,
+    pub bind_ip_subnet: bool,
+    pub ipv4_subnet_prefix: u8,
+    pub ipv6_subnet_prefix: u8,
+}
+
+/// How strictly refresh tokens are tied to the client that obtained them.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BindingMode {
+    /// Bindings are not checked.
+    Off,
+    /// Mismatches are recorded as audit events but the refresh succeeds.
+    Audit,
+    /// Mismatches revoke the token family and force re-authentication.
+    Enforce,
+}
+
+/// How login responses hand out tokens; see
+/// [`token_delivery`](crate::token_delivery).
+#[derive(Debug, Deserialize, Clone)]
+pub struct TokenDeliverySettings {
+    pub mode: TokenDeliveryMode,
+    pub access_cookie: String,
+    pub refresh_cookie: String,
+    /// Mark the cookies `Secure`; only turn off for plain-HTTP development.
+    pub secure_cookies: bool,
+    pub same_site: CookieSameSite,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenDeliveryMode {
+    /// Tokens in JSON bodies, sent back as `Authorization: Bearer`.
+    Body,
+    /// Tokens in httpOnly cookies that scripts can't read, for browser apps.
+    Cookies,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CookieSameSite {
+    Strict,
+    Lax,
+}
+
 impl Settings {
     pub fn new() -> Result<Self, ConfigError> {
-        let run_mode = std::env::var("RUN_MODE").unwrap_or_else(|_| "development".into());
+        platform_core::config::load(Self::defaults()?, "ACTIX")
+    }
 
-        let config = Config::builder()
-            // Start off with default values
+    /// Builder holding only the default values, e.g. for tests to add
+    /// overrides to.
+    pub fn defaults() -> Result<ConfigBuilder<DefaultState>, ConfigError> {
+        Config::builder()
             .set_default("server.host", "0.0.0.0")?
             .set_default("server.port", 8080)?
             .set_default("server.workers", 4)?
+            .set_default("server.drain_delay_secs", 5)?
+            .set_default("server.drain_timeout_secs", 30)?
+            .set_default("runtime.max_blocking_threads", 512)?
+            .set_default("runtime.thread_name", "actix-template")?
+            .set_default("server.max_json_body_bytes", 256 * 1024)?
+            .set_default("server.max_payload_bytes", 256 * 1024)?
+            .set_default("server.json_naming", "snake_case")?
+            .set_default(
+                "server.middleware",
+                ["tracing", "request_id", "logger", "security"]
+                    .map(|name| HashMap::from([("name".to_string(), name)]))
+                    .to_vec(),
+            )?
+            .set_default("server.listeners", Vec::<String>::new())?
+            .set_default("server.reuse_port", false)?
+            .set_default("server.acceptors", 1)?
+            .set_default("server.cpu_pinning.enabled", false)?
+            .set_default("server.cpu_pinning.cores", Vec::<u64>::new())?
+            .set_default("server.http3_alt_svc_max_age_secs", 86400)?
             .set_default("database.max_connections", 10)?
+            .set_default("database.run_migrations", true)?
+            .set_default("database.migration_lock_timeout_secs", 60)?
             .set_default("jwt.access_token_expiry", 3600)?
             .set_default("jwt.refresh_token_expiry", 86400)?
-            // Add in settings from config file
-            .add_source(File::with_name("config/default").required(false))
-            .add_source(File::with_name(&format!("config/{}", run_mode)).required(false))
-            // Add in settings from environment variables (with prefix ACTIX)
-            .add_source(Environment::with_prefix("ACTIX").separator("_"))
-            .build()?;
-
-        config.try_deserialize()
+            .set_default("session.binding_mode", "enforce")?
+            .set_default("session.bind_fingerprint", true)?
+            .set_default("session.bind_ip_subnet", true)?
+            .set_default("session.ipv4_subnet_prefix", 24)?
+            .set_default("session.ipv6_subnet_prefix", 64)?
+            .set_default("token_delivery.mode", "body")?
+            .set_default("token_delivery.access_cookie", "dxp_access")?
+            .set_default("token_delivery.refresh_cookie", "dxp_refresh")?
+            .set_default("token_delivery.secure_cookies", true)?
+            .set_default("token_delivery.same_site", "strict")?
+            .set_default("auth_throttle.enabled", true)?
+            .set_default("auth_throttle.window_secs", 900)?
+            .set_default("auth_throttle.ip_limit", 100)?
+            .set_default("auth_throttle.account_failure_limit", 10)?
+            .set_default("auth_throttle.delay_after_failures", 3)?
+            .set_default("auth_throttle.delay_step_ms", 500)?
+            .set_default("auth_throttle.max_delay_ms", 5000)?
+            .set_default("rate_limit.enabled", true)?
+            .set_default("rate_limit.default_rate_per_second", 0.0)?
+            .set_default("rate_limit.default_burst", 100)?
+            .set_default("maintenance.enabled", false)?
+            .set_default("maintenance.retry_after_secs", 300)?
+            .set_default("maintenance.message", "Service is down for maintenance")?
+            .set_default("concurrency_limit.enabled", true)?
+            .set_default("concurrency_limit.algorithm", "gradient")?
+            .set_default("concurrency_limit.initial_limit", 20)?
+            .set_default("concurrency_limit.min_limit", 4)?
+            .set_default("concurrency_limit.max_limit", 200)?
+            .set_default("concurrency_limit.latency_threshold_ms", 500)?
+            .set_default("concurrency_limit.backoff_ratio", 0.9)?
+            .set_default("concurrency_limit.tolerance", 2.0)?
+            .set_default("concurrency_limit.smoothing", 0.2)?
+            .set_default("concurrency_limit.retry_after_secs", 1)?
+            .set_default("security.headers", true)?
+            .set_default("security.hsts_max_age_secs", 365 * 24 * 3600)?
+            .set_default("security.content_security_policy", "default-src 'none'; frame-ancestors 'none'")?
+            .set_default("security.frame_options", "DENY")?
+            .set_default("security.referrer_policy", "no-referrer")?
+            .set_default("security.csrf", true)?
+            .set_default("security.cors_max_age_secs", 3600)?
+            .set_default("request_signing.max_skew_secs", 300)?
+            .set_default("egress.allowed_schemes", vec!["https"])?
+            .set_default("egress.allowed_ports", vec![443])?
+            .set_default("egress.allow_private", false)?
+            .set_default("egress.timeout_secs", 10)?
+            .set_default("email.product_name", "Actix Template")?
+            .set_default("email.support_email", "support@example.com")?
+            .set_default("mail.provider", "console")?
+            .set_default("mail.postmark_api_url", "https://api.postmarkapp.com")?
+            .set_default("mail.smtp_port", 587)?
+            .set_default("mail.smtp_tls", "starttls")?
+            .set_default("mail.timeout_secs", 10)?
+            .set_default("email_verification.token_ttl_secs", 86400)?
+            .set_default("email_verification.resend_interval_secs", 60)?
+            .set_default("email_verification.verify_url", "http://localhost:3000/verify-email")?
+            .set_default("sms.provider", "console")?
+            .set_default("sms.twilio_api_url", "https://api.twilio.com")?
+            .set_default("sms.timeout_secs", 10)?
+            .set_default("phone_otp.login_enabled", false)?
+            .set_default("phone_otp.code_length", 6)?
+            .set_default("phone_otp.code_ttl_secs", 300)?
+            .set_default("phone_otp.max_attempts", 5)?
+            .set_default("phone_otp.send_limit", 3)?
+            .set_default("phone_otp.send_window_secs", 900)?
+            .set_default("magic_link.enabled", false)?
+            .set_default("magic_link.link_ttl_secs", 900)?
+            .set_default("magic_link.send_limit", 3)?
+            .set_default("magic_link.send_window_secs", 900)?
+            .set_default("magic_link.verify_url", "http://localhost:8080/api/v1/auth/magic-link/verify")?
+            .set_default("magic_link.bind_device", true)?
+            .set_default("password_reset.link_ttl_secs", 1800)?
+            .set_default("password_reset.send_limit", 3)?
+            .set_default("password_reset.send_window_secs", 3600)?
+            .set_default("password_reset.reset_url", "http://localhost:3000/reset-password")?
+            .set_default("webauthn.enabled", false)?
+            .set_default("webauthn.rp_id", "localhost")?
+            .set_default("webauthn.rp_origin", "http://localhost:8080")?
+            .set_default("webauthn.rp_name", "Actix Template")?
+            .set_default("webauthn.ceremony_ttl_secs", 300)?
+            .set_default("guests.enabled", false)?
+            .set_default("guests.ttl_secs", 604800)?
+            .set_default("impersonation.token_ttl_secs", 900)?
+            .set_default("step_up.enabled", false)?
+            .set_default("step_up.max_age_secs", 300)?
+            .set_default("jobs.enabled", true)?
+            .set_default("jobs.concurrency", 4)?
+            .set_default("jobs.poll_interval_ms", 1000)?
+            .set_default("jobs.max_attempts", 5)?
+            .set_default("jobs.retry_base_secs", 10)?
+            .set_default("jobs.retry_max_secs", 3600)?
+            .set_default("jobs.visibility_timeout_secs", 900)?
+            .set_default(
+                "jobs.redacted_fields",
+                vec!["password", "secret", "token", "authorization", "api_key", "otp", "code", "email", "phone"],
+            )?
+            .set_default("push.provider", "console")?
+            .set_default("push.fcm_api_url", "https://fcm.googleapis.com")?
+            .set_default("push.apns_api_url", "https://api.push.apple.com")?
+            .set_default("push.timeout_secs", 10)?
+            .set_default("operations.watch_poll_interval_ms", 2000)?
+            .set_default("operations.keep_alive_secs", 15)?
+            .set_default("sagas.lease_secs", 300)?
+            .set_default("sagas.max_compensation_attempts", 5)?
+            .set_default("sagas.resume_interval_secs", 60)?
+            .set_default("deletion_fanout.publisher", "console")?
+            .set_default("deletion_fanout.retry_interval_secs", 900)?
+            .set_default("deletion_fanout.timeout_secs", 604800)?
+            .set_default("deletion_fanout.poll_interval_secs", 60)?
+            .set_default("deletion_fanout.request_timeout_secs", 10)?
+            .set_default("health.cache_ttl_ms", 5000)?
+            .set_default("health.check_timeout_ms", 2000)?
+            .set_default("health.failure_threshold", 3)?
+            .set_default("health.open_secs", 30)?
+            .set_default("cache.enabled", true)?
+            .set_default("cache.max_entries", 10000)?
+            .set_default("cache.broadcast_invalidations", false)?
+            .set_default("cache.invalidation_channel", "dxp:cache:invalidations")?
+            .set_default("pagination.count", "exact")?
+            .set_default("object_storage.provider", "local")?
+            .set_default("object_storage.path", "./data/objects")?
+            .set_default("partitions.enabled", true)?
+            .set_default("partitions.interval_secs", 3600)?
+            .set_default("partitions.premake_months", 2)?
+            .set_default("partitions.archive_prefix", "archive")?
+            .set_default("partitions.tables.audit_events.retention_months", 12)?
+            .set_default("partitions.tables.audit_events.archive", true)?
+            .set_default("analytics.enabled", false)?
+            .set_default("analytics.poll_interval_secs", 60)?
+            .set_default("analytics.prefix", "analytics")?
+            .set_default("olap.provider", "none")?
+            .set_default("olap.buffer_size", 10000)?
+            .set_default("olap.batch_size", 1000)?
+            .set_default("olap.flush_interval_ms", 1000)?
+            .set_default("olap.max_attempts", 3)?
+            .set_default("olap.request_table", "request_events")?
+            .set_default("olap.domain_table", "domain_events")?
+            .set_default("olap.clickhouse_url", "http://localhost:8123")?
+            .set_default("olap.clickhouse_database", "default")?
+            .set_default("olap.timeout_secs", 10)?
+            .set_default("metering.enabled", false)?
+            .set_default("metering.flush_interval_secs", 60)?
+            .set_default("metering.export_delay_secs", 300)?
+            .set_default("metering.export", "none")?
+            .set_default("metering.timeout_secs", 10)?
+            .set_default("retention.enabled", false)?
+            .set_default("retention.poll_interval_secs", 300)?
+            .set_default("retention.interval_secs", 86400)?
+            .set_default("retention.batch_size", 1000)?
+            .set_default("retention.dry_run", false)?
+            .set_default("retention.policies.audit_events.ttl_days", 90)?
+            .set_default("retention.policies.sessions.ttl_days", 30)?
+            .set_default("retention.policies.deactivated_users.ttl_days", 14)?
+            .set_default("retention.policies.processed_messages.ttl_days", 7)?
+            .set_default("materialized_views.enabled", true)?
+            .set_default("materialized_views.poll_interval_secs", 60)?
+            .set_default("materialized_views.views.daily_signups.interval_secs", 3600)?
+            .set_default("materialized_views.views.daily_signups.order_by", "day DESC")?
+            .set_default("materialized_views.views.daily_active_users.interval_secs", 3600)?
+            .set_default("materialized_views.views.daily_active_users.order_by", "day DESC")?
+            .set_default("stats.max_staleness_secs", 3600)?
+            .set_default("stats.signup_days", 30)?
+            .set_default("lifecycle.hook_timeout_secs", 10)?
+            .set_default("lifecycle.shutdown_timeout_secs", 30)?
+            .set_default("log_level.default", "info")?
+            .set_default("log_level.revert_after_secs", 900)?
+            .set_default("log_level.max_revert_after_secs", 86400)?
+            .set_default("otel.enabled", false)?
+            .set_default("otel.endpoint", "http://localhost:4317")?
+            .set_default("otel.service_name", "actix-template")?
+            .set_default("otel.sample_ratio", 1.0)?
+            .set_default("otel.filter", "info")?
+            .set_default("otel.timeout_secs", 10)?
+            .set_default("propagation.headers", propagation::DEFAULT_HEADERS.to_vec())?
+            .set_default("telemetry.enabled", false)?
+            .set_default("telemetry.service_name", "actix-template")?
+            .set_default("telemetry.environment", "development")?
+            .set_default("telemetry.interval_secs", 60)?
+            .set_default("telemetry.max_backoff_secs", 900)?
+            .set_default("telemetry.timeout_secs", 10)
     }
 }
\ No newline at end of file
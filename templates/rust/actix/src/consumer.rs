@@ -0,0 +1,99 @@
+//! Exactly-once effects for at-least-once deliveries.
+//!
+//! Brokers, webhooks and the job queue all redeliver a message when its
+//! handler may not have finished. An [`IdempotentConsumer`] records each
+//! message id in `processed_messages` in the same transaction as the
+//! handler's writes, so a redelivered message is skipped if and only if
+//! those writes were committed. Side effects outside the database can't be
+//! rolled back; pass the message id along to them as an idempotency key.
+//!
+//! ```ignore
+//! let consumer = IdempotentConsumer::new(db, "billing.invoice_paid", clock);
+//! let outcome = consumer
+//!     .consume(&message.id, |conn| Box::pin(async move {
+//!         sqlx::query("UPDATE invoices SET paid = TRUE WHERE id = $1").bind(invoice_id).execute(conn).await?;
+//!         Ok(())
+//!     }))
+//!     .await?;
+//! ```
+//!
+//! Push fan-out jobs go through one, so a fan-out run again after its
+//! worker was lost doesn't queue every delivery twice.
+//!
+//! Concurrent deliveries of the same message are serialized by the unique
+//! key: the second waits for the first transaction, then is skipped if it
+//! committed. Skipped deliveries count in `consumer_duplicates_total`. Ids
+//! are kept until the `processed_messages` retention policy removes them,
+//! which should be well past the broker's redelivery window.
+
+use futures_util::future::BoxFuture;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+
+use crate::errors::{AppResult, ResultExt};
+use crate::utils::SharedClock;
+
+/// What became of a delivery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Consumed<T> {
+    /// The handler ran and its writes were committed.
+    Processed(T),
+    /// An earlier delivery was processed; the handler didn't run.
+    Duplicate,
+}
+
+pub struct IdempotentConsumer {
+    db: PgPool,
+    /// Ids are unique per consumer, so several consumers can see the same
+    /// message.
+    name: String,
+    clock: SharedClock,
+}
+
+impl IdempotentConsumer {
+    pub fn new(db: PgPool, name: impl Into<String>, clock: SharedClock) -> Self {
+        Self { db, name: name.into(), clock }
+    }
+
+    /// Runs `handler` in a transaction that also marks `message_id`
+    /// processed, unless an earlier delivery already did. An error from the
+    /// handler rolls both back, so the message is handled again when it's
+    /// redelivered.
+    pub async fn consume<T, F>(&self, message_id: &str, handler: F) -> AppResult<Consumed<T>>
+    where
+        F: for<'c> FnOnce(&'c mut PgConnection) -> BoxFuture<'c, AppResult<T>>,
+    {
+        let Some(mut tx) = self.claim(message_id).await? else {
+            return Ok(Consumed::Duplicate);
+        };
+        let value = handler(&mut tx).await?;
+        tx.commit().await.entity_context("commit consumed message", message_id)?;
+        Ok(Consumed::Processed(value))
+    }
+
+    /// A transaction in which `message_id` is marked processed, for
+    /// handlers that manage it themselves; `None` for a duplicate. The mark
+    /// only sticks if the transaction is committed.
+    pub async fn claim(&self, message_id: &str) -> AppResult<Option<Transaction<'static, Postgres>>> {
+        let mut tx = self.db.begin().await.context("begin transaction")?;
+        let claimed = sqlx::query(
+            "INSERT INTO processed_messages (consumer, message_id, processed_at) VALUES ($1, $2, $3)
+             ON CONFLICT (consumer, message_id) DO NOTHING",
+        )
+        .bind(&self.name)
+        .bind(message_id)
+        .bind(self.clock.now())
+        .execute(&mut *tx)
+        .await
+        .entity_context("claim message", message_id)?
+        .rows_affected()
+            == 1;
+
+        if !claimed {
+            tracing::debug!(consumer = %self.name, message_id, "Skipping a message already processed");
+            metrics::counter!("consumer_duplicates_total", "consumer" => self.name.clone()).increment(1);
+            tx.rollback().await.context("roll back duplicate")?;
+            return Ok(None);
+        }
+        Ok(Some(tx))
+    }
+}
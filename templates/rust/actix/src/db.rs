@@ -0,0 +1,144 @@
+use anyhow::{bail, Result};
+use sqlx::postgres::PgRow;
+use sqlx::{Encode, FromRow, PgConnection, PgPool, Postgres, Type};
+use std::fmt::Display;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::DatabaseSettings;
+use crate::errors::{AppResult, Jitter, ResultExt, RetryHint};
+use crate::models::session::RefreshToken;
+use crate::models::user::User;
+
+/// Advisory lock key guarding schema migrations.
+const MIGRATION_LOCK_KEY: i64 = 0x6478_705f_6d69_6772;
+
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Applies pending migrations unless disabled in settings.
+///
+/// When several replicas start at once, the first to take the advisory lock
+/// applies the migrations while the others wait for it, giving up after
+/// `migration_lock_timeout_secs`. Once the lock is released, waiting
+/// replicas find nothing left to apply.
+pub async fn run_migrations(pool: &PgPool, settings: &DatabaseSettings) -> Result<()> {
+    if !settings.run_migrations {
+        info!("Skipping database migrations (database.run_migrations = false)");
+        return Ok(());
+    }
+
+    let mut migrator = sqlx::migrate!("./migrations");
+    // The lock below already serializes replicas
+    migrator.set_locking(false);
+
+    let mut conn = pool.acquire().await?;
+    let timeout = Duration::from_secs(settings.migration_lock_timeout_secs);
+    let started = Instant::now();
+
+    loop {
+        let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+            .bind(MIGRATION_LOCK_KEY)
+            .fetch_one(&mut *conn)
+            .await?;
+
+        if locked {
+            break;
+        }
+
+        if started.elapsed() >= timeout {
+            bail!(
+                "Timed out after {}s waiting for another instance to finish migrations",
+                timeout.as_secs()
+            );
+        }
+
+        info!("Waiting for another instance to finish migrations");
+        tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+    }
+
+    let result = migrator.run(&mut *conn).await;
+
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(MIGRATION_LOCK_KEY)
+        .execute(&mut *conn)
+        .await?;
+
+    result?;
+    info!("Database migrations are up to date");
+
+    Ok(())
+}
+
+/// `name` quoted as an identifier, for table and column names that can't
+/// be bound as parameters.
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Times a transaction is attempted before a deadlock or serialization
+/// failure is returned to the caller.
+pub const TRANSACTION_ATTEMPTS: u32 = 3;
+
+const TRANSACTION_RETRY_BASE: Duration = Duration::from_millis(20);
+
+/// Rows that flows lock with [`find_for_update`] to serialize their writes.
+pub trait Lockable: for<'r> FromRow<'r, PgRow> + Send + Unpin {
+    const TABLE: &'static str;
+}
+
+impl Lockable for User {
+    const TABLE: &'static str = "users";
+}
+
+impl Lockable for RefreshToken {
+    const TABLE: &'static str = "refresh_tokens";
+}
+
+/// Loads the row with `id` and locks it until the transaction on `conn`
+/// ends. Other transactions locking or writing the same row wait for it, so
+/// a read-check-write sequence can't interleave with another one.
+pub async fn find_for_update<T, I>(conn: &mut PgConnection, id: I) -> AppResult<Option<T>>
+where
+    T: Lockable,
+    I: for<'q> Encode<'q, Postgres> + Type<Postgres> + Display + Send,
+{
+    let entity_id = id.to_string();
+    find_for_update_by(conn, "id", id).await.entity_context("lock row", entity_id)
+}
+
+/// [`find_for_update`] by another unique `column`.
+pub async fn find_for_update_by<T, V>(conn: &mut PgConnection, column: &'static str, value: V) -> AppResult<Option<T>>
+where
+    T: Lockable,
+    V: for<'q> Encode<'q, Postgres> + Type<Postgres> + Send,
+{
+    let query = format!("SELECT * FROM {} WHERE {} = $1 FOR UPDATE", T::TABLE, column);
+    sqlx::query_as::<_, T>(&query)
+        .bind(value)
+        .fetch_optional(conn)
+        .await
+        .context("lock row")
+}
+
+/// Runs `transaction` again when Postgres aborted it over a deadlock or
+/// serialization failure, up to [`TRANSACTION_ATTEMPTS`] times with
+/// jittered backoff. Each attempt must begin its own transaction.
+pub async fn retry_conflicts<T, F, Fut>(mut transaction: F) -> AppResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = AppResult<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match transaction().await {
+            Err(e) if e.is_transaction_conflict() && attempt < TRANSACTION_ATTEMPTS => {
+                warn!(attempt, error = %e.source_chain(), "Retrying transaction after a conflict");
+                let backoff = TRANSACTION_RETRY_BASE * 2u32.pow(attempt - 1);
+                tokio::time::sleep(RetryHint::after(backoff).with_jitter(Jitter::Full).delay()).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
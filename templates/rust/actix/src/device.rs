@@ -0,0 +1,114 @@
+//! What a `User-Agent` says about the client: browser, operating system and
+//! device, parsed with the [uap-core](https://github.com/ua-parser/uap-core)
+//! rules. Sessions and audit events store this instead of the raw header,
+//! which is too noisy to show users.
+//!
+//! The rules are compiled into the binary from `data/uap-regexes.yaml`; to
+//! pick up new browsers and devices, replace that file with a newer
+//! uap-core `regexes.yaml`.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use uaparser::{Parser, UserAgentParser};
+
+static PARSER: Lazy<UserAgentParser> = Lazy::new(|| {
+    // User agents are ASCII, so the much lighter non-Unicode regexes suffice
+    UserAgentParser::builder()
+        .with_unicode_support(false)
+        .build_from_bytes(include_bytes!("../data/uap-regexes.yaml"))
+        .expect("data/uap-regexes.yaml is valid")
+});
+
+/// Parsed client details, with `None` for whatever the user agent doesn't
+/// reveal.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    pub kind: DeviceKind,
+    pub browser: Option<Software>,
+    pub os: Option<Software>,
+    /// The hardware, e.g. `iPhone` or `Pixel 7`; unknown for most desktops.
+    pub device: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceKind {
+    Desktop,
+    Mobile,
+    Tablet,
+    Bot,
+    #[default]
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Software {
+    pub name: String,
+    /// Major and minor version, e.g. `17.4`.
+    pub version: Option<String>,
+}
+
+/// Parses `user_agent`; an empty or unrecognised one gives
+/// [`DeviceInfo::default`].
+pub fn parse(user_agent: &str) -> DeviceInfo {
+    if user_agent.is_empty() {
+        return DeviceInfo::default();
+    }
+
+    let client = PARSER.parse(user_agent);
+    let known = |family: &str| family != "Other";
+
+    let browser = known(&client.user_agent.family).then(|| Software {
+        name: client.user_agent.family.to_string(),
+        version: version(client.user_agent.major.as_deref(), client.user_agent.minor.as_deref()),
+    });
+    let os = known(&client.os.family).then(|| Software {
+        name: client.os.family.to_string(),
+        version: version(client.os.major.as_deref(), client.os.minor.as_deref()),
+    });
+    let device = known(&client.device.family).then(|| client.device.family.to_string());
+    let kind = kind(&client.device.family, &client.os.family, user_agent);
+
+    DeviceInfo { kind, browser, os, device }
+}
+
+impl DeviceInfo {
+    /// A short description for UIs, e.g. `Chrome 114.0 on Mac OS X`.
+    pub fn label(&self) -> String {
+        let describe = |software: &Software| match &software.version {
+            Some(version) => format!("{} {}", software.name, version),
+            None => software.name.clone(),
+        };
+
+        match (&self.browser, &self.os) {
+            (Some(browser), Some(os)) => format!("{} on {}", describe(browser), describe(os)),
+            (Some(software), None) | (None, Some(software)) => describe(software),
+            (None, None) => "Unknown device".to_string(),
+        }
+    }
+}
+
+fn version(major: Option<&str>, minor: Option<&str>) -> Option<String> {
+    match (major, minor) {
+        (Some(major), Some(minor)) => Some(format!("{}.{}", major, minor)),
+        (Some(major), None) => Some(major.to_string()),
+        _ => None,
+    }
+}
+
+/// uap-core doesn't classify devices, so this goes by the usual hints.
+fn kind(device: &str, os: &str, user_agent: &str) -> DeviceKind {
+    if device == "Spider" {
+        return DeviceKind::Bot;
+    }
+    if device.contains("iPad") || device.contains("Tablet") || user_agent.contains("Tablet") {
+        return DeviceKind::Tablet;
+    }
+    // Android tablets leave `Mobile` out of their user agent
+    match os {
+        "Android" if !user_agent.contains("Mobile") => DeviceKind::Tablet,
+        "Android" | "iOS" | "Windows Phone" | "KaiOS" => DeviceKind::Mobile,
+        "Windows" | "Mac OS X" | "Linux" | "Ubuntu" | "Fedora" | "Chrome OS" | "FreeBSD" => DeviceKind::Desktop,
+        _ => DeviceKind::Unknown,
+    }
+}
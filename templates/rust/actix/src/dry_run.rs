@@ -0,0 +1,105 @@
+//! Dry runs of create, update and delete endpoints.
+//!
+//! A request with `?dry_run=true` or `X-Dry-Run: true` goes through the
+//! same authentication, permission checks and validation as any other and
+//! gets the response it would have got, marked with `X-Dry-Run: true`, but
+//! its transaction is rolled back instead of committed. Clients use it to
+//! check input before submitting it for real.
+//!
+//! Handlers take a [`DryRun`] and pass it down to the service, which ends
+//! its transaction with [`DryRun::finish`]:
+//!
+//! ```ignore
+//! let mut tx = self.db.begin().await.context("begin device registration")?;
+//! let device = /* ... */.fetch_one(&mut *tx).await?;
+//! dry_run.finish(tx).await?;
+//! ```
+//!
+//! Side effects outside the database (cache invalidation, jobs' effects,
+//! sagas) are skipped, so a dry run's result can carry ids and secrets that
+//! never existed.
+
+use actix_web::{
+    dev::Payload,
+    http::{header::HeaderName, StatusCode},
+    web, FromRequest, HttpRequest, HttpResponseBuilder,
+};
+use serde::Deserialize;
+use sqlx::{Postgres, Transaction};
+use std::future::{ready, Ready};
+
+use crate::errors::{AppError, AppResult, ResultExt};
+
+pub const DRY_RUN_HEADER: HeaderName = HeaderName::from_static("x-dry-run");
+
+/// Whether a request is a dry run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DryRun(pub bool);
+
+#[derive(Deserialize)]
+struct DryRunQuery {
+    dry_run: Option<String>,
+}
+
+impl DryRun {
+    /// A dry run if the `dry_run` query parameter or the `X-Dry-Run` header
+    /// says so. Values other than `true`/`false` (or `1`/`0`) are refused
+    /// rather than taken for a real run.
+    pub fn from_request(req: &HttpRequest) -> AppResult<Self> {
+        let query = web::Query::<DryRunQuery>::from_query(req.query_string())
+            .map(|query| query.into_inner().dry_run)
+            .unwrap_or_default();
+        let header = req
+            .headers()
+            .get(DRY_RUN_HEADER)
+            .map(|value| value.to_str().unwrap_or_default().to_string());
+
+        let mut dry_run = false;
+        for value in query.iter().chain(header.iter()) {
+            dry_run |= parse(value)?;
+        }
+        Ok(Self(dry_run))
+    }
+
+    pub fn is_dry_run(self) -> bool {
+        self.0
+    }
+
+    /// Commits `tx`, or rolls it back in a dry run.
+    pub async fn finish(self, tx: Transaction<'_, Postgres>) -> AppResult<()> {
+        if self.0 {
+            tx.rollback().await.context("roll back dry run")
+        } else {
+            tx.commit().await.context("commit transaction")
+        }
+    }
+
+    /// A response with `status`, marked as a dry run's if it is one.
+    pub fn response(self, status: StatusCode) -> HttpResponseBuilder {
+        let mut response = HttpResponseBuilder::new(status);
+        if self.0 {
+            response.insert_header((DRY_RUN_HEADER, "true"));
+        }
+        response
+    }
+}
+
+fn parse(value: &str) -> AppResult<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(AppError::BadRequest(format!(
+            "Invalid dry run value {:?}; expected true or false",
+            value
+        ))),
+    }
+}
+
+impl FromRequest for DryRun {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(DryRun::from_request(req))
+    }
+}
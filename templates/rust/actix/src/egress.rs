@@ -0,0 +1,242 @@
+//! Egress policy for every outbound HTTP request: the server must not be
+//! talked into calling its own network.
+//!
+//! URLs users hand us (webhook endpoints, avatar URLs) pass when their
+//! scheme and port are allow-listed, their host is allowed and every
+//! address it resolves to is public. Fetch them through
+//! [`EgressPolicy::client`], which pins the connection to the vetted
+//! addresses so a second DNS answer can't point it somewhere else, and
+//! doesn't follow redirects, which would skip the check:
+//!
+//! ```ignore
+//! let (client, url) = state.egress.client(&webhook.url).await?;
+//! client.post(url).json(&event).send().await?;
+//! ```
+//!
+//! Clients of configured endpoints (providers, webhook receivers,
+//! telemetry, ClickHouse) are built once from
+//! [`EgressPolicy::client_builder`] after [`EgressPolicy::check_endpoint`].
+//! Operators chose those URLs, so only the address ranges apply to them,
+//! checked on every connection as it is resolved.
+
+use config::ConfigError;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{redirect, Client, ClientBuilder, Url};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::EgressSettings;
+use crate::errors::{AppError, AppResult, ErrorCode};
+use crate::utils::ip_in_cidr;
+
+/// Ranges that never belong to a public service: loopback, private,
+/// link-local (cloud metadata lives at 169.254.169.254), carrier-grade NAT,
+/// documentation, benchmarking, multicast and reserved space.
+const BLOCKED_CIDRS: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.0.2.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "198.51.100.0/24",
+    "203.0.113.0/24",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/128",
+    "::1/128",
+    "100::/64",
+    "2001:db8::/32",
+    "fc00::/7",
+    "fe80::/10",
+    "ff00::/8",
+];
+
+/// A URL that passed the policy and the addresses it may be fetched from.
+#[derive(Debug, Clone)]
+pub struct ResolvedUrl {
+    pub url: Url,
+    pub addrs: Vec<SocketAddr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EgressPolicy {
+    settings: EgressSettings,
+}
+
+impl EgressPolicy {
+    pub fn new(settings: EgressSettings) -> Self {
+        Self { settings }
+    }
+
+    /// Checks a URL without resolving it, for validating input before it is
+    /// stored.
+    pub fn check_url(&self, url: &str) -> AppResult<Url> {
+        let url = Url::parse(url).map_err(|_| denied("is not a valid URL"))?;
+
+        if !self.settings.allowed_schemes.iter().any(|scheme| scheme == url.scheme()) {
+            return Err(denied(&format!("uses scheme '{}'", url.scheme())));
+        }
+        if !url.username().is_empty() || url.password().is_some() {
+            return Err(denied("contains credentials"));
+        }
+        let port = url.port_or_known_default().ok_or_else(|| denied("has no port"))?;
+        if !self.settings.allowed_ports.contains(&port) {
+            return Err(denied(&format!("uses port {}", port)));
+        }
+        let host = url.host_str().ok_or_else(|| denied("has no host"))?;
+        if !self.host_allowed(host) {
+            return Err(denied(&format!("points to host '{}'", host)));
+        }
+        // IP literals can be judged now; names are judged once resolved
+        if let Some(ip) = ip_literal(&url) {
+            self.check_ip(ip)?;
+        }
+
+        Ok(url)
+    }
+
+    /// Checks a URL and resolves its host, rejecting it if any address is
+    /// blocked.
+    pub async fn resolve(&self, url: &str) -> AppResult<ResolvedUrl> {
+        let url = self.check_url(url)?;
+        let host = url.host_str().unwrap_or_default();
+        let port = url.port_or_known_default().unwrap_or_default();
+
+        let addrs: Vec<SocketAddr> = match ip_literal(&url) {
+            Some(ip) => vec![SocketAddr::new(ip, port)],
+            None => tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|_| denied(&format!("host '{}' could not be resolved", host)))?
+                .collect(),
+        };
+        if addrs.is_empty() {
+            return Err(denied(&format!("host '{}' could not be resolved", host)));
+        }
+        for addr in &addrs {
+            self.check_ip(addr.ip())?;
+        }
+
+        Ok(ResolvedUrl { url, addrs })
+    }
+
+    /// Resolves `url` and returns a client that can only reach the vetted
+    /// addresses for its host.
+    pub async fn client(&self, url: &str) -> AppResult<(Client, Url)> {
+        let resolved = self.resolve(url).await?;
+        let mut builder = Client::builder()
+            .redirect(redirect::Policy::none())
+            .timeout(Duration::from_secs(self.settings.timeout_secs));
+        if ip_literal(&resolved.url).is_none() {
+            let host = resolved.url.host_str().unwrap_or_default();
+            builder = builder.resolve_to_addrs(host, &resolved.addrs);
+        }
+        let client = builder.build().map_err(|_| AppError::InternalServerError)?;
+
+        Ok((client, resolved.url))
+    }
+
+    /// Checks a configured endpoint, e.g. `metering.export_url`, at startup.
+    /// Only its address ranges are checked, and only an IP literal can be
+    /// checked this early; names are checked by the resolver of
+    /// [`client_builder`](Self::client_builder) clients.
+    pub fn check_endpoint(&self, key: &str, url: &str) -> Result<(), ConfigError> {
+        let parsed = Url::parse(url).map_err(|_| ConfigError::Message(format!("{} is not a valid URL", key)))?;
+        if let Some(ip) = ip_literal(&parsed) {
+            self.check_ip(ip)
+                .map_err(|e| ConfigError::Message(format!("{} is not allowed: {}", key, e)))?;
+        }
+        Ok(())
+    }
+
+    /// A client builder for configured endpoints. Every address its clients
+    /// resolve must pass the policy, and they don't follow redirects.
+    pub fn client_builder(&self) -> ClientBuilder {
+        Client::builder()
+            .redirect(redirect::Policy::none())
+            .dns_resolver(Arc::new(PolicyResolver(self.clone())))
+    }
+
+    /// Whether the policy lets requests reach `ip`.
+    pub fn ip_allowed(&self, ip: IpAddr) -> bool {
+        self.check_ip(ip).is_ok()
+    }
+
+    fn check_ip(&self, ip: IpAddr) -> AppResult<()> {
+        let ip = canonical_ip(ip);
+        let blocked_by_default = !self.settings.allow_private
+            && BLOCKED_CIDRS.iter().any(|cidr| ip_in_cidr(ip, cidr))
+            && !self.settings.allowed_cidrs.iter().any(|cidr| ip_in_cidr(ip, cidr));
+        let blocked = blocked_by_default || self.settings.blocked_cidrs.iter().any(|cidr| ip_in_cidr(ip, cidr));
+
+        if blocked {
+            return Err(denied(&format!("resolves to blocked address {}", ip)));
+        }
+        Ok(())
+    }
+
+    /// With no allow-list any public host is fine; entries match a host
+    /// exactly, or its subdomains when written as `*.example.com`.
+    fn host_allowed(&self, host: &str) -> bool {
+        let allowed = &self.settings.allowed_hosts;
+        allowed.is_empty()
+            || allowed.iter().any(|pattern| match pattern.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|subdomain| subdomain.ends_with('.') && subdomain.len() > 1),
+                None => host.eq_ignore_ascii_case(pattern),
+            })
+    }
+}
+
+/// Resolves hosts for [`EgressPolicy::client_builder`] clients, failing the
+/// connection when any address is blocked.
+struct PolicyResolver(EgressPolicy);
+
+impl Resolve for PolicyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.0.clone();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            for addr in &addrs {
+                policy.check_ip(addr.ip())?;
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn denied(reason: &str) -> AppError {
+    AppError::BadRequest(format!("URL not allowed: it {}", reason)).with_code(ErrorCode::EgressDenied)
+}
+
+fn ip_literal(url: &Url) -> Option<IpAddr> {
+    let host = url.host_str()?;
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+        .parse()
+        .ok()
+}
+
+/// Unwraps IPv4 addresses embedded in IPv6 ones (`::ffff:127.0.0.1`,
+/// NAT64 `64:ff9b::/96`) so they are judged by the IPv4 ranges.
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+    let IpAddr::V6(v6) = ip else {
+        return ip;
+    };
+    if let Some(v4) = v6.to_ipv4_mapped() {
+        return IpAddr::V4(v4);
+    }
+    let segments = v6.segments();
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [a, b, c, d] = (u128::from(v6) as u32).to_be_bytes();
+        return IpAddr::V4(Ipv4Addr::new(a, b, c, d));
+    }
+    ip
+}
@@ -1,77 +1,6 @@
-use actix_web::{error::ResponseError, http::StatusCode, HttpResponse};
-use serde::Serialize;
-use std::fmt;
-use thiserror::Error;
+//! Errors come from the shared `platform-core` crate, so REST and gRPC
+//! services report the same variants and stable codes.
 
-#[derive(Debug, Serialize)]
-pub struct ErrorResponse {
-    pub code: u16,
-    pub error: String,
-    pub message: String,
-}
-
-#[derive(Error, Debug)]
-pub enum AppError {
-    #[error("Internal Server Error")]
-    InternalServerError,
-    
-    #[error("Bad Request: {0}")]
-    BadRequest(String),
-    
-    #[error("Unauthorized")]
-    Unauthorized,
-    
-    #[error("Forbidden")]
-    Forbidden,
-    
-    #[error("Not Found: {0}")]
-    NotFound(String),
-    
-    #[error("Conflict: {0}")]
-    Conflict(String),
-    
-    #[error("Unprocessable Entity: {0}")]
-    UnprocessableEntity(String),
-    
-    #[error("Database error")]
-    DatabaseError(#[from] sqlx::Error),
-    
-    #[error("Validation error: {0}")]
-    ValidationError(String),
-    
-    #[error("JWT error")]
-    JwtError(#[from] jsonwebtoken::errors::Error),
-    
-    #[error("Hash error")]
-    HashError(#[from] bcrypt::BcryptError),
-}
-
-impl ResponseError for AppError {
-    fn error_response(&self) -> HttpResponse {
-        let status_code = self.status_code();
-        let error_response = ErrorResponse {
-            code: status_code.as_u16(),
-            error: status_code.to_string(),
-            message: self.to_string(),
-        };
-        HttpResponse::build(status_code).json(error_response)
-    }
-
-    fn status_code(&self) -> StatusCode {
-        match self {
-            AppError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
-            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
-            AppError::Forbidden => StatusCode::FORBIDDEN,
-            AppError::NotFound(_) => StatusCode::NOT_FOUND,
-            AppError::Conflict(_) => StatusCode::CONFLICT,
-            AppError::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
-            AppError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::ValidationError(_) => StatusCode::BAD_REQUEST,
-            AppError::JwtError(_) => StatusCode::UNAUTHORIZED,
-            AppError::HashError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        }
-    }
-}
-
-pub type AppResult<T> = Result<T, AppError>;
\ No newline at end of file
+pub use platform_core::errors::{
+    AppError, AppResult, ErrorCode, ErrorContext, ErrorResponse, Jitter, ResultExt, RetryHint,
+};
@@ -0,0 +1,565 @@
+use actix_web::{delete, get, http::StatusCode, post, put, route, web, HttpRequest, HttpResponse};
+use platform_core::domain::UserId;
+use platform_observability::log_level::LogLevelStatus;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    dry_run::DryRun,
+    errors::{AppError, AppResult},
+    jobs::dashboard::JobFilter,
+    metering::UsageQuery,
+    middleware::{auth::require_session, roles::require_permission, RequireRole},
+    models::{
+        audit_event::{
+            AuditEvent, DEAD_JOBS_PURGED, IMPERSONATION_STARTED, JOB_CANCELLED, JOB_QUEUE_PAUSED, JOB_QUEUE_RESUMED,
+            JOB_RETRIED, LOG_LEVEL_CHANGED, ROLE_GRANTED, ROLE_REVOKED, USER_DELETED_BY_ADMIN, USER_UPDATED_BY_ADMIN,
+        },
+        role::{ROLE_ADMIN, USERS_DELETE, USERS_READ, USERS_WRITE},
+        session::ClientContext,
+        user::{Claims, ImpersonateUser, ImpersonationResponse, PaginationParams, UpdateUser, UserResponse},
+        user_deletion::{DELETION_COMPLETED, DELETION_MANUAL_REVIEW, DELETION_PENDING},
+    },
+    runtime_stats::RuntimeStats,
+    saga::account_deletion,
+    utils::create_impersonation_token,
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct SetLogLevel {
+    /// `EnvFilter` directives, e.g. `info,sqlx=debug`.
+    pub filter: String,
+    /// Defaults to `log_level.revert_after_secs`.
+    pub revert_after_secs: Option<u64>,
+}
+
+#[get("/log-level", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn get_log_level(app_state: web::Data<AppState>, req: HttpRequest) -> AppResult<HttpResponse> {
+    require_session(&req)?;
+
+    Ok(HttpResponse::Ok().json(app_state.log_level.status()))
+}
+
+/// Overrides the tracing filter until it reverts on its own; every change is
+/// audited with who made it.
+#[put("/log-level", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn set_log_level(
+    app_state: web::Data<AppState>,
+    body: web::Json<SetLogLevel>,
+    client: ClientContext,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_session(&req)?;
+
+    let previous = app_state.log_level.status();
+    let status = app_state
+        .log_level
+        .set(&body.filter, body.revert_after_secs.map(Duration::from_secs))?;
+    audit_change(&app_state, &claims, &client, &previous, &status).await?;
+
+    Ok(HttpResponse::Ok().json(status))
+}
+
+/// Restores the default filter ahead of the scheduled revert.
+#[delete("/log-level", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn reset_log_level(
+    app_state: web::Data<AppState>,
+    client: ClientContext,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_session(&req)?;
+
+    let previous = app_state.log_level.status();
+    let status = app_state.log_level.reset()?;
+    audit_change(&app_state, &claims, &client, &previous, &status).await?;
+
+    Ok(HttpResponse::Ok().json(status))
+}
+
+/// Statistics of the runtimes serving requests, and whether tokio-console
+/// can attach.
+#[get("/runtime", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn runtime_stats(app_state: web::Data<AppState>, req: HttpRequest) -> AppResult<HttpResponse> {
+    require_session(&req)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "console": cfg!(feature = "console"),
+        "runtimes": RuntimeStats::all(&app_state.runtimes),
+    })))
+}
+
+/// The longest span `/usage` returns at once.
+const MAX_USAGE_DAYS: i64 = 31;
+
+/// Hourly usage records, of the last day unless `from` and `to` say
+/// otherwise, optionally for one user, API token or metric. Counts reach
+/// the records every `metering.flush_interval_secs`.
+#[get("/usage", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn usage(
+    app_state: web::Data<AppState>,
+    query: web::Query<UsageQuery>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    require_session(&req)?;
+
+    let mut query = query.into_inner();
+    let to = query.to.unwrap_or_else(|| app_state.clock.now());
+    let from = query.from.unwrap_or(to - chrono::Duration::days(1));
+    if from >= to {
+        return Err(AppError::BadRequest("from must be before to".to_string()));
+    }
+    if to - from > chrono::Duration::days(MAX_USAGE_DAYS) {
+        return Err(AppError::BadRequest(format!(
+            "Usage can be read for at most {} days at a time",
+            MAX_USAGE_DAYS
+        )));
+    }
+    query.from = Some(from);
+    query.to = Some(to);
+
+    let records = app_state.meter.usage(&query).await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "from": from,
+        "to": to,
+        "records": records,
+    })))
+}
+
+/// Job queues by priority, with whether they're paused and their pending,
+/// running and dead jobs.
+#[get("/job-queues", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn list_job_queues(app_state: web::Data<AppState>, req: HttpRequest) -> AppResult<HttpResponse> {
+    require_session(&req)?;
+
+    let queues = app_state.job_queue.queues().await?;
+    Ok(HttpResponse::Ok().json(json!({ "queues": queues })))
+}
+
+/// Stops every worker from claiming the queue's jobs; running ones finish.
+#[post("/job-queues/{name}/pause", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn pause_job_queue(
+    app_state: web::Data<AppState>,
+    path: web::Path<String>,
+    client: ClientContext,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_session(&req)?;
+
+    app_state.job_queue.pause(&path).await?;
+    record_audit(&app_state, &claims, &client, JOB_QUEUE_PAUSED, json!({ "queue": *path })).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/job-queues/{name}/resume", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn resume_job_queue(
+    app_state: web::Data<AppState>,
+    path: web::Path<String>,
+    client: ClientContext,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_session(&req)?;
+
+    app_state.job_queue.resume(&path).await?;
+    record_audit(&app_state, &claims, &client, JOB_QUEUE_RESUMED, json!({ "queue": *path })).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Jobs newest first with their payloads redacted, filtered by `status`,
+/// `queue` and `kind`; `before` pages through older ones.
+#[get("/jobs", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn list_jobs(
+    app_state: web::Data<AppState>,
+    query: web::Query<JobFilter>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    require_session(&req)?;
+
+    let jobs = app_state.job_queue.list(&query).await?;
+    Ok(HttpResponse::Ok().json(json!({ "jobs": jobs })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobStatsQuery {
+    /// Defaults to a day ago.
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// Run time and queue wait percentiles per kind, and the commonest reasons
+/// jobs died, of jobs finished since `since`.
+#[get("/jobs/stats", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn job_stats(
+    app_state: web::Data<AppState>,
+    query: web::Query<JobStatsQuery>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    require_session(&req)?;
+
+    let since = query.since.unwrap_or_else(|| app_state.clock.now() - chrono::Duration::days(1));
+    let stats = app_state.job_queue.stats(since).await?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PurgeDeadJobs {
+    pub kind: Option<String>,
+    /// Only jobs that died before this.
+    pub before: Option<DateTime<Utc>>,
+}
+
+/// Deletes dead jobs, of one kind or that died before `before` if given.
+#[delete("/jobs/dead", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn purge_dead_jobs(
+    app_state: web::Data<AppState>,
+    query: web::Query<PurgeDeadJobs>,
+    client: ClientContext,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_session(&req)?;
+
+    let purged = app_state.job_queue.purge_dead(query.kind.as_deref(), query.before).await?;
+    let metadata = json!({ "kind": query.kind, "before": query.before, "purged": purged });
+    record_audit(&app_state, &claims, &client, DEAD_JOBS_PURGED, metadata).await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "purged": purged })))
+}
+
+/// A job with its payload redacted, its attempts and last error.
+#[get("/jobs/{id}", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn get_job(app_state: web::Data<AppState>, path: web::Path<Uuid>, req: HttpRequest) -> AppResult<HttpResponse> {
+    require_session(&req)?;
+
+    let job = app_state.job_queue.inspect(path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(job))
+}
+
+/// Runs a dead or pending job now with its attempts reset.
+#[post("/jobs/{id}/retry", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn retry_job(
+    app_state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    client: ClientContext,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_session(&req)?;
+
+    let job = app_state.job_queue.retry(*path).await?;
+    let metadata = json!({ "job_id": job.id, "kind": job.kind });
+    record_audit(&app_state, &claims, &client, JOB_RETRIED, metadata).await?;
+
+    Ok(HttpResponse::Ok().json(job))
+}
+
+/// Cancels a job that hasn't started yet.
+#[delete("/jobs/{id}", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn cancel_job(
+    app_state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    client: ClientContext,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_session(&req)?;
+
+    app_state.job_queue.cancel(*path).await?;
+    record_audit(&app_state, &claims, &client, JOB_CANCELLED, json!({ "job_id": *path })).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+async fn record_audit(
+    app_state: &AppState,
+    claims: &Claims,
+    client: &ClientContext,
+    event_type: &'static str,
+    metadata: Value,
+) -> AppResult<()> {
+    app_state
+        .audit_service
+        .record(AuditEvent {
+            event_type,
+            user_id: Some(claims.sub),
+            ip_address: client.ip.map(|ip| ip.to_string()),
+            device: Some(client.device()),
+            metadata,
+        })
+        .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserDeletionQuery {
+    /// `pending`, `completed` or `manual_review`.
+    pub status: Option<String>,
+}
+
+/// The latest user deletions with each downstream service's confirmation,
+/// e.g. `?status=manual_review` for those waiting on an admin.
+#[get("/user-deletions", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn list_user_deletions(
+    app_state: web::Data<AppState>,
+    query: web::Query<UserDeletionQuery>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    require_session(&req)?;
+
+    let status = query.status.as_deref();
+    if let Some(status) = status {
+        if ![DELETION_PENDING, DELETION_COMPLETED, DELETION_MANUAL_REVIEW].contains(&status) {
+            return Err(AppError::BadRequest(format!("Unknown user deletion status {}", status)));
+        }
+    }
+
+    let deletions = app_state.deletion_fanout.list(status).await?;
+    Ok(HttpResponse::Ok().json(json!({ "deletions": deletions })))
+}
+
+/// Deletes the user of a deletion without waiting for the services that
+/// haven't confirmed, after an admin made sure their data is gone.
+#[post("/user-deletions/{id}/finalize", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn finalize_user_deletion(
+    app_state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_session(&req)?;
+
+    let deletion = app_state.deletion_fanout.finalize(path.into_inner(), claims.sub).await?;
+    Ok(HttpResponse::Ok().json(deletion))
+}
+
+/// Every user, including those whose own listing wouldn't show them.
+#[get("/users")]
+pub async fn list_users(
+    app_state: web::Data<AppState>,
+    query: web::Query<PaginationParams>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    require_session(&req)?;
+    require_permission(&req, USERS_READ)?;
+
+    query.validate()
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+    let page = query.page.unwrap_or(1);
+    let limit = query.limit.unwrap_or(20);
+    let count = query.count.unwrap_or(app_state.settings.pagination.count);
+
+    let users = app_state.user_queries.get_users(page, limit, count).await?;
+    Ok(HttpResponse::Ok().json(users))
+}
+
+/// A user with the roles they've been granted.
+#[get("/users/{id}")]
+pub async fn get_user(app_state: web::Data<AppState>, path: web::Path<UserId>, req: HttpRequest) -> AppResult<HttpResponse> {
+    require_session(&req)?;
+    require_permission(&req, USERS_READ)?;
+
+    let user = app_state.user_queries.get_user_by_id(*path).await?;
+    let roles = app_state.role_service.roles_of(user.id).await?;
+    let user: UserResponse = user.into();
+    Ok(HttpResponse::Ok().json(json!({ "user": user, "roles": roles })))
+}
+
+/// Updates any user's profile, unlike `PUT /api/v1/users/{id}`, which
+/// only lets users update their own.
+#[route("/users/{id}", method = "PUT", method = "PATCH")]
+pub async fn update_user(
+    app_state: web::Data<AppState>,
+    path: web::Path<UserId>,
+    body: web::Json<UpdateUser>,
+    client: ClientContext,
+    dry_run: DryRun,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_session(&req)?;
+    require_permission(&req, USERS_WRITE)?;
+
+    body.validate()
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+
+    let user_id = path.into_inner();
+    let fields = body.fields();
+
+    let user = app_state
+        .user_commands
+        .update_user(user_id, body.into_inner(), None, dry_run)
+        .await?;
+    if !dry_run.is_dry_run() {
+        app_state.response_cache.invalidate_tag(&format!("user:{}", user_id)).await;
+        app_state.response_cache.invalidate_tag("users:list").await;
+
+        let metadata = json!({ "subject": user_id, "fields": fields });
+        record_audit(&app_state, &claims, &client, USER_UPDATED_BY_ADMIN, metadata).await?;
+    }
+
+    let user: UserResponse = user.into();
+    Ok(dry_run.response(StatusCode::OK).json(user))
+}
+
+/// Deletes any user's account, the way they would delete it themselves.
+#[delete("/users/{id}")]
+pub async fn delete_user(
+    app_state: web::Data<AppState>,
+    path: web::Path<UserId>,
+    client: ClientContext,
+    dry_run: DryRun,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_session(&req)?;
+    require_permission(&req, USERS_DELETE)?;
+
+    let user_id = path.into_inner();
+    app_state.user_queries.get_user_by_id(user_id).await?;
+    // As for users deleting themselves, a dry run stops at checking there
+    // is an account to delete
+    if !dry_run.is_dry_run() {
+        app_state
+            .saga_engine
+            .start(account_deletion::ACCOUNT_DELETION, &account_deletion::AccountDeletion { user_id })
+            .await?;
+        record_audit(&app_state, &claims, &client, USER_DELETED_BY_ADMIN, json!({ "subject": user_id })).await?;
+    }
+
+    Ok(dry_run.response(StatusCode::ACCEPTED).finish())
+}
+
+/// The roles there are and the permissions each grants.
+#[get("/roles")]
+pub async fn list_roles(app_state: web::Data<AppState>, req: HttpRequest) -> AppResult<HttpResponse> {
+    require_session(&req)?;
+    require_permission(&req, USERS_READ)?;
+
+    let roles = app_state.role_service.list_roles().await?;
+    Ok(HttpResponse::Ok().json(json!({ "roles": roles })))
+}
+
+/// Grants a user a role. It reaches their requests when they next refresh
+/// their access token.
+#[put("/users/{id}/roles/{role}", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn grant_role(
+    app_state: web::Data<AppState>,
+    path: web::Path<(UserId, String)>,
+    client: ClientContext,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_session(&req)?;
+    let (user_id, role) = path.into_inner();
+    if user_id == claims.sub {
+        return Err(AppError::BadRequest("Admins can't change their own roles".to_string()));
+    }
+
+    app_state.user_queries.get_user_by_id(user_id).await?;
+    app_state.role_service.grant(user_id, &role, Some(claims.sub)).await?;
+    let metadata = json!({ "subject": user_id, "role": role });
+    record_audit(&app_state, &claims, &client, ROLE_GRANTED, metadata).await?;
+
+    let roles = app_state.role_service.roles_of(user_id).await?;
+    Ok(HttpResponse::Ok().json(json!({ "roles": roles })))
+}
+
+/// Takes a role away from a user. Access tokens they already hold keep
+/// it until they expire.
+#[delete("/users/{id}/roles/{role}", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn revoke_role(
+    app_state: web::Data<AppState>,
+    path: web::Path<(UserId, String)>,
+    client: ClientContext,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_session(&req)?;
+    let (user_id, role) = path.into_inner();
+    if user_id == claims.sub {
+        return Err(AppError::BadRequest("Admins can't change their own roles".to_string()));
+    }
+
+    app_state.role_service.revoke(user_id, &role).await?;
+    let metadata = json!({ "subject": user_id, "role": role });
+    record_audit(&app_state, &claims, &client, ROLE_REVOKED, metadata).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Issues a short-lived token to act as a user for support. The token names
+/// both the user and the admin; it's audited before it's issued, as is
+/// every change made with it. Admins can't be impersonated, so the token
+/// never carries more than the user's own grants.
+#[post("/impersonate", wrap = "RequireRole::new(ROLE_ADMIN)")]
+pub async fn impersonate_user(
+    app_state: web::Data<AppState>,
+    body: web::Json<ImpersonateUser>,
+    client: ClientContext,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_session(&req)?;
+
+    body.validate()
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+    if body.user_id == claims.sub {
+        return Err(AppError::BadRequest("Admins can't impersonate themselves".to_string()));
+    }
+
+    let subject = app_state.user_queries.get_user_by_id(body.user_id).await?;
+    if app_state.role_service.grants_of(subject.id).await?.has_role(ROLE_ADMIN) {
+        return Err(AppError::Forbidden);
+    }
+    let ttl_secs = app_state.settings.impersonation.token_ttl_secs;
+
+    app_state
+        .audit_service
+        .record(AuditEvent {
+            event_type: IMPERSONATION_STARTED,
+            user_id: Some(claims.sub),
+            ip_address: client.ip.map(|ip| ip.to_string()),
+            device: Some(client.device()),
+            metadata: json!({
+                "subject": subject.id,
+                "reason": body.reason,
+                "expires_at": app_state.clock.now() + chrono::Duration::seconds(ttl_secs),
+            }),
+        })
+        .await?;
+
+    let access_token = create_impersonation_token(
+        &subject,
+        claims.sub,
+        &app_state.jwt_keys,
+        ttl_secs,
+        app_state.clock.as_ref(),
+    )?;
+
+    let response = ImpersonationResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: ttl_secs,
+        user: subject.into(),
+    };
+
+    Ok(HttpResponse::Created().json(response))
+}
+
+async fn audit_change(
+    app_state: &AppState,
+    claims: &Claims,
+    client: &ClientContext,
+    previous: &LogLevelStatus,
+    status: &LogLevelStatus,
+) -> AppResult<()> {
+    app_state
+        .audit_service
+        .record(AuditEvent {
+            event_type: LOG_LEVEL_CHANGED,
+            user_id: Some(claims.sub),
+            ip_address: client.ip.map(|ip| ip.to_string()),
+            device: Some(client.device()),
+            metadata: json!({
+                "filter": status.filter,
+                "previous": previous.filter,
+                "reverts_at": status.reverts_at,
+            }),
+        })
+        .await
+}
@@ -0,0 +1,28 @@
+use actix_web::http::header;
+use actix_web::middleware::DefaultHeaders;
+use actix_web::{get, web, HttpResponse};
+use utoipa_swagger_ui::{Config, SwaggerUi};
+
+use crate::openapi;
+
+pub const SPEC_PATH: &str = "/api-docs/openapi.json";
+
+/// Swagger UI loads its own scripts, styles and inline images, which the
+/// default `security.content_security_policy` blocks.
+const SWAGGER_UI_CSP: &str = "default-src 'self'; img-src 'self' data:; style-src 'self' 'unsafe-inline'";
+
+/// The OpenAPI document; see [`openapi`](crate::openapi).
+#[get("/api-docs/openapi.json")]
+pub async fn openapi_spec() -> HttpResponse {
+    HttpResponse::Ok().json(openapi::spec())
+}
+
+/// Registers the document at [`SPEC_PATH`] and Swagger UI under
+/// `/swagger-ui/`.
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(openapi_spec).service(
+        web::scope("/swagger-ui")
+            .wrap(DefaultHeaders::new().add((header::CONTENT_SECURITY_POLICY, SWAGGER_UI_CSP)))
+            .service(SwaggerUi::new("/{_:.*}").config(Config::new([SPEC_PATH]))),
+    );
+}
@@ -0,0 +1,63 @@
+use actix_web::{delete, get, http::StatusCode, post, web, HttpRequest, HttpResponse};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    dry_run::DryRun,
+    errors::{AppError, AppResult},
+    middleware::auth::require_scope,
+    models::{
+        api_token::Scope,
+        push_device::{PushDeviceResponse, RegisterPushDevice},
+    },
+    AppState,
+};
+
+#[get("")]
+pub async fn list_devices(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_scope(&req, Scope::ReadProfile)?;
+
+    let devices = app_state.push_service.list_devices(claims.sub).await?;
+    let device_responses: Vec<PushDeviceResponse> = devices.into_iter().map(|d| d.into()).collect();
+
+    Ok(HttpResponse::Ok().json(device_responses))
+}
+
+/// Registers a device token for the caller. Registering a token again
+/// refreshes it, or moves it over from the account that had it.
+#[post("")]
+pub async fn register_device(
+    app_state: web::Data<AppState>,
+    device_data: web::Json<RegisterPushDevice>,
+    dry_run: DryRun,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_scope(&req, Scope::WriteProfile)?;
+
+    device_data.validate()
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+
+    let device = app_state.push_service
+        .register_device(claims.sub, device_data.into_inner(), dry_run)
+        .await?;
+    let device_response: PushDeviceResponse = device.into();
+
+    Ok(dry_run.response(StatusCode::CREATED).json(device_response))
+}
+
+#[delete("/{id}")]
+pub async fn unregister_device(
+    app_state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    dry_run: DryRun,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_scope(&req, Scope::WriteProfile)?;
+
+    app_state.push_service.unregister_device(claims.sub, path.into_inner(), dry_run).await?;
+
+    Ok(dry_run.response(StatusCode::NO_CONTENT).finish())
+}
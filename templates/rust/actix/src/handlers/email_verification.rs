@@ -0,0 +1,65 @@
+use actix_web::{post, web, HttpResponse};
+
+use crate::{
+    errors::{AppResult, ErrorCode, ErrorResponse},
+    models::{
+        email_verification::{ResendVerificationRequest, VerifyEmailRequest},
+        user::UserResponse,
+    },
+    AppState,
+};
+
+/// Confirms the email address a verification link was sent to.
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/verify-email",
+    tag = "auth",
+    request_body = VerifyEmailRequest,
+    responses(
+        (status = 200, description = "The user, now verified", body = UserResponse),
+        (status = 401, description = "Invalid or expired token, or the address changed since", body = ErrorResponse),
+    ),
+)]
+#[post("/verify-email")]
+pub async fn verify_email(
+    app_state: web::Data<AppState>,
+    body: web::Json<VerifyEmailRequest>,
+) -> AppResult<HttpResponse> {
+    let user = app_state.email_verification_service.verify(&body.token).await?;
+
+    app_state.response_cache.invalidate_tag(&format!("user:{}", user.id)).await;
+    app_state.response_cache.invalidate_tag("users:list").await;
+
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
+}
+
+/// Sends another verification link to an unverified account.
+///
+/// Always answers 202, even for unknown or verified addresses or within
+/// the resend interval, so the endpoint can't be used to find accounts.
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/resend-verification",
+    tag = "auth",
+    request_body = ResendVerificationRequest,
+    responses(
+        (status = 202, description = "Sent, if the account exists and is unverified"),
+        (status = 400, description = "Malformed email address", body = ErrorResponse),
+    ),
+)]
+#[post("/resend-verification")]
+pub async fn resend_verification(
+    app_state: web::Data<AppState>,
+    body: web::Json<ResendVerificationRequest>,
+) -> AppResult<HttpResponse> {
+    if let Some(user) = app_state.user_queries.get_active_user_by_email(&body.email).await? {
+        match app_state.email_verification_service.send(&user).await {
+            Err(e) if e.code() == ErrorCode::EmailVerificationRateLimited => {
+                tracing::warn!(user_id = %user.id, "Verification email resend interval not over");
+            }
+            result => result?,
+        }
+    }
+
+    Ok(HttpResponse::Accepted().finish())
+}
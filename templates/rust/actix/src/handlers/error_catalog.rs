@@ -0,0 +1,8 @@
+use actix_web::{get, HttpResponse};
+
+/// Every error code the API can return, so clients can branch on
+/// `error_code` instead of parsing messages.
+#[get("/errors")]
+pub async fn error_catalog() -> HttpResponse {
+    HttpResponse::Ok().json(platform_core::errors::catalog())
+}
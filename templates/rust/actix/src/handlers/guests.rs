@@ -0,0 +1,97 @@
+use actix_web::{http::StatusCode, post, web, HttpRequest, HttpResponse};
+use chrono::Duration;
+use validator::Validate;
+
+use crate::{
+    errors::{AppError, AppResult},
+    middleware::auth::require_scope,
+    models::{
+        api_token::Scope,
+        session::ClientContext,
+        user::{CreateUser, GuestResponse, LoginResponse},
+    },
+    token_delivery,
+    utils::{create_guest_token, create_jwt_token},
+    AppState,
+};
+
+/// Starts an anonymous guest session, e.g. for a product trial.
+#[post("")]
+pub async fn create_guest(app_state: web::Data<AppState>) -> AppResult<HttpResponse> {
+    require_guests(&app_state)?;
+
+    let ttl_secs = app_state.settings.guests.ttl_secs;
+    let now = app_state.clock.now();
+    let guest = app_state
+        .user_commands
+        .create_guest(now, now + Duration::seconds(ttl_secs))
+        .await?;
+
+    let access_token = create_guest_token(&guest, &app_state.jwt_keys, app_state.clock.as_ref())?;
+
+    let response = GuestResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: ttl_secs,
+        user: guest.into(),
+    };
+
+    Ok(token_delivery::guest(&app_state.settings, response))
+}
+
+/// Registers the calling guest as a full account, keeping everything it
+/// created, and logs it in.
+#[post("")]
+pub async fn upgrade_guest(
+    app_state: web::Data<AppState>,
+    user_data: web::Json<CreateUser>,
+    client: ClientContext,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    require_guests(&app_state)?;
+    let claims = require_scope(&req, Scope::WriteProfile)?;
+
+    user_data.validate()
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+
+    let user = app_state
+        .user_commands
+        .upgrade_guest(claims.sub, user_data.into_inner(), app_state.clock.now())
+        .await?;
+    // Guests aren't listed, so the upgraded account is new to the list
+    app_state.response_cache.invalidate_tag("users:list").await;
+    if let Err(e) = app_state.email_verification_service.send(&user).await {
+        tracing::warn!(user_id = %user.id, "Failed to send verification email: {}", e);
+    }
+
+    let access_token = create_jwt_token(
+        user.id,
+        &user.email,
+        app_state.role_service.grants_of(user.id).await?,
+        &app_state.jwt_keys,
+        app_state.settings.jwt.access_token_expiry / 3600,
+        app_state.clock.as_ref(),
+    )?;
+
+    let refresh_token = app_state.session_service
+        .issue_refresh_token(user.id, &client)
+        .await?;
+
+    let response = LoginResponse {
+        access_token,
+        refresh_token,
+        token_type: "Bearer".to_string(),
+        expires_in: app_state.settings.jwt.access_token_expiry,
+        user: user.into(),
+    };
+
+    Ok(token_delivery::login(&app_state.settings, StatusCode::OK, response))
+}
+
+fn require_guests(app_state: &AppState) -> AppResult<()> {
+    if app_state.settings.guests.enabled {
+        Ok(())
+    } else {
+        Err(AppError::NotFound("Guest sessions are disabled".to_string()))
+    }
+}
@@ -1,23 +1,34 @@
 use actix_web::{get, web, HttpResponse};
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use utoipa::ToSchema;
 
+use crate::health::DependencyStatus;
 use crate::AppState;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct HealthResponse {
     pub status: String,
     pub version: String,
     pub timestamp: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct ReadinessResponse {
+    /// `ready`, `degraded` when a non-critical dependency is down,
+    /// `not ready`, or `draining` once the server is shutting down.
     pub status: String,
     pub database: String,
+    pub dependencies: Vec<DependencyStatus>,
     pub timestamp: String,
 }
 
+/// Liveness: the process is up and serving requests.
+#[utoipa::path(
+    get,
+    path = "/api/v1/health",
+    tag = "health",
+    responses((status = 200, body = HealthResponse)),
+)]
 #[get("/health")]
 pub async fn health_check() -> HttpResponse {
     let response = HealthResponse {
@@ -29,26 +40,39 @@ pub async fn health_check() -> HttpResponse {
     HttpResponse::Ok().json(response)
 }
 
+/// Readiness: whether the critical dependencies answer their checks and
+/// the server isn't shutting down.
+#[utoipa::path(
+    get,
+    path = "/api/v1/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Ready, or degraded", body = ReadinessResponse),
+        (status = 503, description = "A critical dependency is down, or the server is draining", body = ReadinessResponse),
+    ),
+)]
 #[get("/ready")]
 pub async fn readiness_check(app_state: web::Data<AppState>) -> HttpResponse {
-    // Check database connection
-    let db_status = match sqlx::query("SELECT 1")
-        .fetch_one(&app_state.db)
-        .await
-    {
-        Ok(_) => "connected",
-        Err(_) => "disconnected",
+    let report = app_state.health.report().await;
+    let database_up = report.dependency("database").is_some_and(|database| database.up);
+
+    let draining = app_state.draining.is_draining();
+    let status = match (report.ready, report.degraded) {
+        _ if draining => "draining",
+        (false, _) => "not ready",
+        (true, true) => "degraded",
+        (true, false) => "ready",
     };
-    
     let response = ReadinessResponse {
-        status: if db_status == "connected" { "ready" } else { "not ready" }.to_string(),
-        database: db_status.to_string(),
+        status: status.to_string(),
+        database: if database_up { "connected" } else { "disconnected" }.to_string(),
+        dependencies: report.dependencies,
         timestamp: chrono::Utc::now().to_rfc3339(),
     };
     
-    if db_status == "connected" {
+    if report.ready && !draining {
         HttpResponse::Ok().json(response)
     } else {
         HttpResponse::ServiceUnavailable().json(response)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,9 @@
+use actix_web::{get, HttpResponse};
+
+use crate::manifest::Manifest;
+
+/// The template manifest, for the platform's inventory.
+#[get("/info")]
+pub async fn info() -> HttpResponse {
+    HttpResponse::Ok().json(Manifest::current())
+}
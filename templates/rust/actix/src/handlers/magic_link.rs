@@ -0,0 +1,85 @@
+use actix_web::{get, http::StatusCode, post, web, HttpResponse};
+
+use crate::{
+    errors::{AppError, AppResult, ErrorCode},
+    models::{
+        magic_link::{MagicLinkRequest, MagicLinkVerify},
+        session::ClientContext,
+        user::LoginResponse,
+    },
+    token_delivery,
+    utils::create_jwt_token,
+    AppState,
+};
+
+/// Emails a sign-in link to an active account.
+///
+/// Always answers 202, even for unknown addresses or when the address's
+/// send limit is reached, so the endpoint can't be used to find accounts.
+#[post("/magic-link")]
+pub async fn request_magic_link(
+    app_state: web::Data<AppState>,
+    body: web::Json<MagicLinkRequest>,
+    client: ClientContext,
+) -> AppResult<HttpResponse> {
+    require_magic_link(&app_state)?;
+
+    if let Some(user) = app_state.user_queries.get_active_user_by_email(&body.email).await? {
+        match app_state.magic_link_service.send_link(&user, &client).await {
+            Err(e) if e.code() == ErrorCode::MagicLinkRateLimited => {
+                tracing::warn!(user_id = %user.id, "Magic link send limit reached");
+            }
+            result => result?,
+        }
+    }
+
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// Exchanges the token of a sign-in link for tokens.
+#[get("/magic-link/verify")]
+pub async fn verify_magic_link(
+    app_state: web::Data<AppState>,
+    query: web::Query<MagicLinkVerify>,
+    client: ClientContext,
+) -> AppResult<HttpResponse> {
+    require_magic_link(&app_state)?;
+
+    let user_id = app_state.magic_link_service.verify_link(&query.token, &client).await?;
+
+    let user = app_state.user_queries.get_user_by_id(user_id).await?;
+    if !user.is_active {
+        return Err(AppError::Forbidden.with_code(ErrorCode::AuthAccountDisabled));
+    }
+
+    let access_token = create_jwt_token(
+        user.id,
+        &user.email,
+        app_state.role_service.grants_of(user.id).await?,
+        &app_state.jwt_keys,
+        app_state.settings.jwt.access_token_expiry / 3600,
+        app_state.clock.as_ref(),
+    )?;
+
+    let refresh_token = app_state.session_service
+        .issue_refresh_token(user.id, &client)
+        .await?;
+
+    let response = LoginResponse {
+        access_token,
+        refresh_token,
+        token_type: "Bearer".to_string(),
+        expires_in: app_state.settings.jwt.access_token_expiry,
+        user: user.into(),
+    };
+
+    Ok(token_delivery::login(&app_state.settings, StatusCode::OK, response))
+}
+
+fn require_magic_link(app_state: &AppState) -> AppResult<()> {
+    if app_state.magic_link_service.enabled() {
+        Ok(())
+    } else {
+        Err(AppError::NotFound("Magic link login is disabled".to_string()))
+    }
+}
@@ -1,2 +1,240 @@
+use actix_web::error::JsonPayloadError;
+use actix_web::web;
+
+use crate::errors::AppError;
+use crate::middleware::{AuthMiddleware, AuthThrottle, ConcurrencyLimit, Maintenance, RateLimiter};
+
+pub mod admin;
+pub mod api_docs;
+pub mod devices;
+pub mod email_verification;
+pub mod error_catalog;
+pub mod guests;
 pub mod health;
-pub mod users;
\ No newline at end of file
+pub mod info;
+pub mod magic_link;
+pub mod operations;
+pub mod passkeys;
+pub mod password_reset;
+pub mod phone;
+pub mod sessions;
+pub mod stats;
+pub mod step_up;
+pub mod tokens;
+pub mod user_deletions;
+pub mod users;
+
+/// Registers every route under `/api/v1`, and the OpenAPI document with
+/// Swagger UI next to it.
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.configure(api_docs::routes).service(
+        web::scope("/api/v1")
+            .wrap(RateLimiter)
+            .service(health::health_check)
+            .service(health::readiness_check)
+            .service(info::info)
+            .service(error_catalog::error_catalog)
+            .service(
+                web::scope("/users")
+                    .wrap(AuthMiddleware)
+                    .wrap(ConcurrencyLimit)
+                    .wrap(Maintenance)
+                    .service(users::get_users)
+                    .service(users::get_user)
+                    .service(users::create_user)
+                    .service(users::update_user)
+                    .service(users::delete_user)
+                    .service(phone::send_phone_verification)
+                    .service(phone::verify_phone),
+            )
+            .service(
+                web::scope("/internal/user-deletions")
+                    .wrap(AuthMiddleware)
+                    .service(user_deletions::acknowledge_deletion),
+            )
+            .service(
+                web::scope("/tokens")
+                    .wrap(AuthMiddleware)
+                    .wrap(ConcurrencyLimit)
+                    .wrap(Maintenance)
+                    .service(tokens::list_tokens)
+                    .service(tokens::create_token)
+                    .service(tokens::revoke_token),
+            )
+            .service(
+                web::scope("/devices")
+                    .wrap(AuthMiddleware)
+                    .wrap(ConcurrencyLimit)
+                    .wrap(Maintenance)
+                    .service(devices::list_devices)
+                    .service(devices::register_device)
+                    .service(devices::unregister_device),
+            )
+            .service(
+                web::scope("/operations")
+                    .wrap(AuthMiddleware)
+                    .wrap(ConcurrencyLimit)
+                    .wrap(Maintenance)
+                    .service(operations::get_operation)
+                    .service(operations::watch_operation),
+            )
+            .service(
+                web::scope("/stats")
+                    .wrap(AuthMiddleware)
+                    .wrap(ConcurrencyLimit)
+                    .wrap(Maintenance)
+                    .service(stats::get_summary)
+                    .service(stats::get_stats),
+            )
+            .service(
+                web::scope("/me/sessions")
+                    .wrap(AuthMiddleware)
+                    .wrap(ConcurrencyLimit)
+                    .wrap(Maintenance)
+                    .service(sessions::list_sessions),
+            )
+            .service(
+                web::scope("/me/passkeys")
+                    .wrap(AuthMiddleware)
+                    .wrap(ConcurrencyLimit)
+                    .wrap(Maintenance)
+                    .service(passkeys::list_passkeys)
+                    .service(passkeys::start_registration)
+                    .service(passkeys::finish_registration)
+                    .service(passkeys::rename_passkey)
+                    .service(passkeys::delete_passkey),
+            )
+            // Registered before `/auth`, which would otherwise claim their paths
+            .service(
+                web::scope("/auth/step-up")
+                    .wrap(AuthMiddleware)
+                    .wrap(AuthThrottle)
+                    .wrap(ConcurrencyLimit)
+                    .wrap(Maintenance)
+                    .service(step_up::step_up),
+            )
+            .service(
+                web::scope("/auth/guest")
+                    .wrap(AuthThrottle)
+                    .wrap(ConcurrencyLimit)
+                    .wrap(Maintenance)
+                    .service(guests::create_guest)
+                    .service(
+                        web::scope("/upgrade")
+                            .wrap(AuthMiddleware)
+                            .service(guests::upgrade_guest),
+                    ),
+            )
+            .service(
+                web::scope("/auth")
+                    .wrap(AuthThrottle)
+                    .wrap(ConcurrencyLimit)
+                    .wrap(Maintenance)
+                    .service(users::login)
+                    .service(users::register)
+                    .service(users::refresh)
+                    .service(users::logout)
+                    .service(email_verification::verify_email)
+                    .service(email_verification::resend_verification)
+                    .service(password_reset::forgot_password)
+                    .service(password_reset::reset_password)
+                    .service(phone::request_login_code)
+                    .service(phone::verify_login_code)
+                    .service(magic_link::request_magic_link)
+                    .service(magic_link::verify_magic_link)
+                    .service(passkeys::start_login)
+                    .service(passkeys::finish_login),
+            ),
+    );
+}
+
+/// Registers health, readiness, the manifest and the operational endpoints
+/// under `/admin`, for admin listeners. Every `/admin` handler requires the
+/// `admin` role, or a permission of its own.
+pub fn admin_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/v1")
+            .service(health::health_check)
+            .service(health::readiness_check)
+            .service(info::info)
+            .service(
+                web::scope("/admin")
+                    .wrap(AuthMiddleware)
+                    .service(admin::get_log_level)
+                    .service(admin::set_log_level)
+                    .service(admin::reset_log_level)
+                    .service(admin::runtime_stats)
+                    .service(admin::usage)
+                    .service(admin::list_job_queues)
+                    .service(admin::pause_job_queue)
+                    .service(admin::resume_job_queue)
+                    .service(admin::list_jobs)
+                    .service(admin::job_stats)
+                    .service(admin::purge_dead_jobs)
+                    .service(admin::get_job)
+                    .service(admin::retry_job)
+                    .service(admin::cancel_job)
+                    .service(admin::list_user_deletions)
+                    .service(admin::finalize_user_deletion)
+                    .service(admin::list_users)
+                    .service(admin::get_user)
+                    .service(admin::update_user)
+                    .service(admin::delete_user)
+                    .service(admin::list_roles)
+                    .service(admin::grant_role)
+                    .service(admin::revoke_role)
+                    .service(admin::impersonate_user),
+            ),
+    );
+}
+
+/// JSON extractor configuration shared by all handlers. Oversized bodies are
+/// rejected with 413 and malformed ones with the standard error body. Fields
+/// whose domain type refuses the value (`Email`, `Username`) fail validation
+/// the same way `Validate` rules do.
+///
+/// The limit is enforced on the decoded payload, so it also caps how far a
+/// compressed request may expand.
+pub fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(move |err, _req| {
+            let error = match err {
+                JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+                    AppError::PayloadTooLarge(format!("JSON bodies are limited to {} bytes", limit))
+                }
+                JsonPayloadError::ContentType => {
+                    AppError::BadRequest("Expected Content-Type: application/json".to_string())
+                }
+                JsonPayloadError::Deserialize(err) if err.is_data() => match domain_violation(&err) {
+                    Some(message) => AppError::ValidationError(message),
+                    None => AppError::BadRequest(JsonPayloadError::Deserialize(err).to_string()),
+                },
+                err => AppError::BadRequest(err.to_string()),
+            };
+            error.into()
+        })
+}
+
+/// Path extractor configuration shared by all handlers. A segment that
+/// doesn't parse, such as an ID that isn't a UUID, names no resource: 404
+/// with the standard error body rather than actix's plain text.
+pub fn path_config() -> web::PathConfig {
+    web::PathConfig::default().error_handler(|err, _req| AppError::NotFound(err.to_string()).into())
+}
+
+/// Query string extractor configuration shared by all handlers: parameters
+/// of the wrong type or out of range are rejected with 400 and the standard
+/// error body.
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(|err, _req| AppError::BadRequest(err.to_string()).into())
+}
+
+/// The message of a domain type rejecting a field's value, without the
+/// position serde appends.
+fn domain_violation(err: &serde_json::Error) -> Option<String> {
+    let message = err.to_string();
+    let message = message.strip_prefix("Validation error: ")?;
+    let message = message.rsplit_once(" at line ").map_or(message, |(message, _)| message);
+    Some(message.to_string())
+}
@@ -0,0 +1,78 @@
+use actix_web::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use futures_util::stream::{self, StreamExt};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::{
+    errors::{AppError, AppResult},
+    middleware::auth::require_scope,
+    models::{api_token::Scope, operation::OperationResponse},
+    AppState,
+};
+
+#[get("/{id}")]
+pub async fn get_operation(
+    app_state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_scope(&req, Scope::ReadProfile)?;
+
+    let operation = app_state.operation_service.get_operation(claims.sub, path.into_inner()).await?;
+    let operation_response: OperationResponse = operation.into();
+
+    Ok(HttpResponse::Ok().json(operation_response))
+}
+
+/// Streams the operation as server-sent events: one `operation` event now
+/// and after every change, the last one with `done: true`. Comments keep
+/// idle connections open through proxies.
+#[get("/{id}/events")]
+pub async fn watch_operation(
+    app_state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_scope(&req, Scope::ReadProfile)?;
+    let operation_id = path.into_inner();
+
+    // Fail with a regular error response if it doesn't exist
+    app_state.operation_service.get_operation(claims.sub, operation_id).await?;
+
+    let keep_alive = Duration::from_secs(app_state.operation_service.settings().keep_alive_secs);
+    let updates = app_state
+        .operation_service
+        .clone()
+        .watch(claims.sub, operation_id)
+        .boxed();
+
+    let events = stream::unfold(Some(updates), move |updates| async move {
+        let mut updates = updates?;
+        let event = match tokio::time::timeout(keep_alive, updates.next()).await {
+            Err(_) => Bytes::from_static(b": keep-alive\n\n"),
+            Ok(None) => return None,
+            Ok(Some(Ok(operation))) => {
+                let operation: OperationResponse = operation.into();
+                sse_event("operation", &serde_json::to_string(&operation).unwrap_or_default())
+            }
+            Ok(Some(Err(e))) => {
+                // The stream ends after an error
+                e.log(None);
+                let body = serde_json::json!({ "error_code": e.code().as_str(), "message": e.code().description() });
+                return Some((Ok::<_, AppError>(sse_event("error", &body.to_string())), None));
+            }
+        };
+        Some((Ok(event), Some(updates)))
+    });
+
+    Ok(HttpResponse::Ok()
+        .insert_header((CONTENT_TYPE, "text/event-stream"))
+        .insert_header((CACHE_CONTROL, "no-cache"))
+        .streaming(events))
+}
+
+fn sse_event(name: &str, data: &str) -> Bytes {
+    Bytes::from(format!("event: {}\ndata: {}\n\n", name, data))
+}
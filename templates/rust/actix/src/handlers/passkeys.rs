@@ -0,0 +1,169 @@
+use actix_web::{delete, get, http::StatusCode, patch, post, web, HttpRequest, HttpResponse};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    dry_run::DryRun,
+    errors::{AppError, AppResult, ErrorCode},
+    middleware::auth::require_scope,
+    models::{
+        api_token::Scope,
+        passkey::{FinishPasskeyLogin, FinishPasskeyRegistration, PasskeyResponse, RenamePasskey},
+        session::ClientContext,
+        user::LoginResponse,
+    },
+    token_delivery,
+    utils::create_jwt_token,
+    AppState,
+};
+
+#[get("")]
+pub async fn list_passkeys(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    require_passkeys(&app_state)?;
+    let claims = require_scope(&req, Scope::ReadProfile)?;
+
+    let passkeys = app_state.passkey_service.list_passkeys(claims.sub).await?;
+    let passkey_responses: Vec<PasskeyResponse> = passkeys.into_iter().map(|p| p.into()).collect();
+
+    Ok(HttpResponse::Ok().json(passkey_responses))
+}
+
+/// Starts registering a passkey for the caller.
+#[post("/register/start")]
+pub async fn start_registration(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    require_passkeys(&app_state)?;
+    let claims = require_scope(&req, Scope::WriteProfile)?;
+
+    let user = app_state.user_queries.get_user_by_id(claims.sub).await?;
+    let challenge = app_state.passkey_service.start_registration(&user).await?;
+
+    Ok(HttpResponse::Ok().json(challenge))
+}
+
+/// Stores the passkey the authenticator created for the challenge.
+#[post("/register/finish")]
+pub async fn finish_registration(
+    app_state: web::Data<AppState>,
+    body: web::Json<FinishPasskeyRegistration>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    require_passkeys(&app_state)?;
+    let claims = require_scope(&req, Scope::WriteProfile)?;
+
+    body.validate()
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+
+    let passkey = app_state
+        .passkey_service
+        .finish_registration(claims.sub, body.into_inner())
+        .await?;
+    let passkey_response: PasskeyResponse = passkey.into();
+
+    Ok(HttpResponse::Created().json(passkey_response))
+}
+
+#[patch("/{id}")]
+pub async fn rename_passkey(
+    app_state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    body: web::Json<RenamePasskey>,
+    dry_run: DryRun,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    require_passkeys(&app_state)?;
+    let claims = require_scope(&req, Scope::WriteProfile)?;
+
+    body.validate()
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+
+    let passkey = app_state
+        .passkey_service
+        .rename_passkey(claims.sub, path.into_inner(), &body.name, dry_run)
+        .await?;
+    let passkey_response: PasskeyResponse = passkey.into();
+
+    Ok(dry_run.response(StatusCode::OK).json(passkey_response))
+}
+
+#[delete("/{id}")]
+pub async fn delete_passkey(
+    app_state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    dry_run: DryRun,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    require_passkeys(&app_state)?;
+    let claims = require_scope(&req, Scope::WriteProfile)?;
+
+    app_state.passkey_service.delete_passkey(claims.sub, path.into_inner(), dry_run).await?;
+
+    Ok(dry_run.response(StatusCode::NO_CONTENT).finish())
+}
+
+/// Starts a passkey login; the browser offers the passkeys it holds.
+#[post("/passkey/start")]
+pub async fn start_login(app_state: web::Data<AppState>) -> AppResult<HttpResponse> {
+    require_passkeys(&app_state)?;
+
+    let challenge = app_state.passkey_service.start_login().await?;
+
+    Ok(HttpResponse::Ok().json(challenge))
+}
+
+/// Exchanges the authenticator's answer for tokens.
+#[post("/passkey/finish")]
+pub async fn finish_login(
+    app_state: web::Data<AppState>,
+    body: web::Json<FinishPasskeyLogin>,
+    client: ClientContext,
+) -> AppResult<HttpResponse> {
+    require_passkeys(&app_state)?;
+
+    let user_id = app_state.passkey_service.finish_login(body.into_inner()).await?;
+
+    let user = app_state.user_queries.get_user_by_id(user_id).await?;
+    if !user.is_active {
+        return Err(AppError::Forbidden.with_code(ErrorCode::AuthAccountDisabled));
+    }
+    // A guest's passkeys sign in once it has upgraded to an account
+    if user.is_guest {
+        return Err(AppError::Unauthorized.with_code(ErrorCode::PasskeyVerificationFailed));
+    }
+
+    let access_token = create_jwt_token(
+        user.id,
+        &user.email,
+        app_state.role_service.grants_of(user.id).await?,
+        &app_state.jwt_keys,
+        app_state.settings.jwt.access_token_expiry / 3600,
+        app_state.clock.as_ref(),
+    )?;
+
+    let refresh_token = app_state.session_service
+        .issue_refresh_token(user.id, &client)
+        .await?;
+
+    let response = LoginResponse {
+        access_token,
+        refresh_token,
+        token_type: "Bearer".to_string(),
+        expires_in: app_state.settings.jwt.access_token_expiry,
+        user: user.into(),
+    };
+
+    Ok(token_delivery::login(&app_state.settings, StatusCode::OK, response))
+}
+
+fn require_passkeys(app_state: &AppState) -> AppResult<()> {
+    if app_state.passkey_service.enabled() {
+        Ok(())
+    } else {
+        Err(AppError::NotFound("Passkeys are disabled".to_string()))
+    }
+}
@@ -0,0 +1,83 @@
+use actix_web::{post, web, HttpResponse};
+use serde_json::json;
+use validator::Validate;
+
+use crate::{
+    errors::{AppError, AppResult, ErrorCode, ErrorResponse},
+    models::{
+        audit_event::{AuditEvent, PASSWORD_RESET},
+        password_reset::{ForgotPasswordRequest, ResetPasswordRequest},
+        session::ClientContext,
+    },
+    AppState,
+};
+
+/// Emails a password reset link to an active account.
+///
+/// Always answers 202, even for unknown addresses or when the address's
+/// send limit is reached, so the endpoint can't be used to find accounts.
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/forgot-password",
+    tag = "auth",
+    request_body = ForgotPasswordRequest,
+    responses(
+        (status = 202, description = "Sent, if the account exists and is active"),
+        (status = 400, description = "Malformed email address", body = ErrorResponse),
+    ),
+)]
+#[post("/forgot-password")]
+pub async fn forgot_password(
+    app_state: web::Data<AppState>,
+    body: web::Json<ForgotPasswordRequest>,
+    client: ClientContext,
+) -> AppResult<HttpResponse> {
+    if let Some(user) = app_state.user_queries.get_active_user_by_email(&body.email).await? {
+        match app_state.password_reset_service.send_link(&user, &client).await {
+            Err(e) if e.code() == ErrorCode::PasswordResetRateLimited => {
+                tracing::warn!(user_id = %user.id, "Password reset send limit reached");
+            }
+            result => result?,
+        }
+    }
+
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// Sets a new password with the token of a reset link, signing the user
+/// out of every session.
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/reset-password",
+    tag = "auth",
+    request_body = ResetPasswordRequest,
+    responses(
+        (status = 204, description = "Password changed; log in with it"),
+        (status = 400, description = "The new password is too short", body = ErrorResponse),
+        (status = 401, description = "Invalid, expired or used token", body = ErrorResponse),
+    ),
+)]
+#[post("/reset-password")]
+pub async fn reset_password(
+    app_state: web::Data<AppState>,
+    body: web::Json<ResetPasswordRequest>,
+    client: ClientContext,
+) -> AppResult<HttpResponse> {
+    body.validate()
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+
+    let user_id = app_state.password_reset_service.reset(&body.token, &body.new_password).await?;
+
+    app_state
+        .audit_service
+        .record(AuditEvent {
+            event_type: PASSWORD_RESET,
+            user_id: Some(user_id),
+            ip_address: client.ip.map(|ip| ip.to_string()),
+            device: Some(client.device()),
+            metadata: json!({}),
+        })
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,183 @@
+use actix_web::{http::StatusCode, post, web, HttpRequest, HttpResponse};
+use platform_core::domain::UserId;
+use validator::Validate;
+
+use crate::{
+    errors::{AppError, AppResult, ErrorCode},
+    middleware::auth::require_scope,
+    models::{
+        api_token::Scope,
+        phone_otp::{OtpLoginRequest, OtpLoginVerify, OtpPurpose, VerifyPhone},
+        session::ClientContext,
+        user::{LoginResponse, UserResponse},
+    },
+    token_delivery,
+    utils::{create_jwt_token, mask_phone_number},
+    AppState,
+};
+
+/// Texts a verification code to the user's phone number.
+#[post("/{id}/phone/verification")]
+pub async fn send_phone_verification(
+    app_state: web::Data<AppState>,
+    path: web::Path<UserId>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_scope(&req, Scope::WriteProfile)?;
+    let user_id = path.into_inner();
+    if claims.sub != user_id {
+        return Err(AppError::Forbidden);
+    }
+
+    let user = app_state.user_queries.get_user_by_id(user_id).await?;
+    let phone_number = user.phone_number.ok_or_else(|| {
+        AppError::BadRequest("Set a phone number first".to_string()).with_code(ErrorCode::PhoneNumberMissing)
+    })?;
+
+    app_state
+        .phone_otp_service
+        .send_code(user_id, &phone_number, OtpPurpose::VerifyPhone)
+        .await?;
+
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// Confirms the phone number with the code sent to it.
+#[post("/{id}/phone/verify")]
+pub async fn verify_phone(
+    app_state: web::Data<AppState>,
+    path: web::Path<UserId>,
+    body: web::Json<VerifyPhone>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_scope(&req, Scope::WriteProfile)?;
+    let user_id = path.into_inner();
+    if claims.sub != user_id {
+        return Err(AppError::Forbidden);
+    }
+
+    body.validate()
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+
+    let user = app_state.user_queries.get_user_by_id(user_id).await?;
+    let phone_number = user.phone_number.ok_or_else(|| {
+        AppError::BadRequest("Set a phone number first".to_string()).with_code(ErrorCode::PhoneNumberMissing)
+    })?;
+
+    let owner = app_state
+        .phone_otp_service
+        .verify_code(&phone_number, OtpPurpose::VerifyPhone, &body.code)
+        .await?;
+    if owner != user_id {
+        return Err(AppError::BadRequest("Invalid or expired code".to_string()).with_code(ErrorCode::PhoneOtpInvalid));
+    }
+
+    let user = app_state
+        .user_commands
+        .mark_phone_verified(user_id, &phone_number, app_state.clock.now())
+        .await?;
+    app_state.response_cache.invalidate_tag(&format!("user:{}", user_id)).await;
+    app_state.response_cache.invalidate_tag("users:list").await;
+    let user_response: UserResponse = user.into();
+
+    Ok(HttpResponse::Ok().json(user_response))
+}
+
+/// Texts a login code to a verified phone number.
+///
+/// Always answers 202, even for unknown numbers or when the number's send
+/// limit is reached, so the endpoint can't be used to find accounts.
+#[post("/otp")]
+pub async fn request_login_code(
+    app_state: web::Data<AppState>,
+    body: web::Json<OtpLoginRequest>,
+) -> AppResult<HttpResponse> {
+    require_otp_login(&app_state)?;
+    body.validate()
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+
+    if let Some(user) = app_state.user_queries.get_user_by_verified_phone(&body.phone_number).await? {
+        let sent = app_state
+            .phone_otp_service
+            .send_code(user.id, &body.phone_number, OtpPurpose::Login)
+            .await;
+        match sent {
+            Err(e) if e.code() == ErrorCode::PhoneOtpRateLimited => {
+                tracing::warn!(phone_number = %mask_phone_number(&body.phone_number), "Login code send limit reached");
+            }
+            result => result?,
+        }
+    }
+
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// Exchanges a login code for tokens.
+#[post("/otp/verify")]
+pub async fn verify_login_code(
+    app_state: web::Data<AppState>,
+    body: web::Json<OtpLoginVerify>,
+    client: ClientContext,
+) -> AppResult<HttpResponse> {
+    require_otp_login(&app_state)?;
+    body.validate()
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+
+    // Failures count against the phone number like password failures
+    // count against the email
+    let delay = app_state.auth_throttle_service
+        .check_login(client.ip, &body.phone_number)
+        .await?;
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+
+    let user_id = match app_state
+        .phone_otp_service
+        .verify_code(&body.phone_number, OtpPurpose::Login, &body.code)
+        .await
+    {
+        Ok(user_id) => user_id,
+        Err(e) => {
+            app_state.auth_throttle_service.record_failure(client.ip, &body.phone_number).await;
+            return Err(e);
+        }
+    };
+    app_state.auth_throttle_service.record_success(&body.phone_number).await;
+
+    let user = app_state.user_queries.get_user_by_id(user_id).await?;
+    if !user.is_active {
+        return Err(AppError::Forbidden.with_code(ErrorCode::AuthAccountDisabled));
+    }
+
+    let access_token = create_jwt_token(
+        user.id,
+        &user.email,
+        app_state.role_service.grants_of(user.id).await?,
+        &app_state.jwt_keys,
+        app_state.settings.jwt.access_token_expiry / 3600,
+        app_state.clock.as_ref(),
+    )?;
+
+    let refresh_token = app_state.session_service
+        .issue_refresh_token(user.id, &client)
+        .await?;
+
+    let response = LoginResponse {
+        access_token,
+        refresh_token,
+        token_type: "Bearer".to_string(),
+        expires_in: app_state.settings.jwt.access_token_expiry,
+        user: user.into(),
+    };
+
+    Ok(token_delivery::login(&app_state.settings, StatusCode::OK, response))
+}
+
+fn require_otp_login(app_state: &AppState) -> AppResult<()> {
+    if app_state.phone_otp_service.login_enabled() {
+        Ok(())
+    } else {
+        Err(AppError::NotFound("OTP login is disabled".to_string()))
+    }
+}
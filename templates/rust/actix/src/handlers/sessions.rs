@@ -0,0 +1,22 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+
+use crate::{
+    errors::AppResult,
+    middleware::auth::require_scope,
+    models::{api_token::Scope, session::SessionResponse},
+    AppState,
+};
+
+/// Lists where the caller is signed in, with the device of each session.
+#[get("")]
+pub async fn list_sessions(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let claims = require_scope(&req, Scope::ReadProfile)?;
+
+    let sessions = app_state.session_service.list_sessions(claims.sub).await?;
+    let session_responses: Vec<SessionResponse> = sessions.into_iter().map(|s| s.into()).collect();
+
+    Ok(HttpResponse::Ok().json(session_responses))
+}
@@ -0,0 +1,44 @@
+use actix_template_macros::authorize;
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+
+use crate::{
+    errors::{AppError, AppResult},
+    AppState,
+};
+
+/// The most rows `/stats/{view}` returns at once.
+const MAX_STATS_ROWS: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    /// Defaults to 100.
+    pub limit: Option<i64>,
+}
+
+/// Users in total, active and verified, and signups per day, at most
+/// `stats.max_staleness_secs` old.
+#[authorize("read:stats")]
+#[get("")]
+pub async fn get_summary(app_state: web::Data<AppState>) -> AppResult<HttpResponse> {
+    let summary = app_state.stats_service.summary().await?;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+/// Rows of a materialized view in `materialized_views.views`, as of its
+/// last refresh.
+#[authorize("read:stats")]
+#[get("/{view}")]
+pub async fn get_stats(
+    app_state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<StatsQuery>,
+) -> AppResult<HttpResponse> {
+    let limit = query.limit.unwrap_or(100);
+    if !(1..=MAX_STATS_ROWS).contains(&limit) {
+        return Err(AppError::BadRequest(format!("limit must be between 1 and {}", MAX_STATS_ROWS)));
+    }
+
+    let rows = app_state.materialized_views.rows(&path.into_inner(), limit).await?;
+    Ok(HttpResponse::Ok().json(rows))
+}
@@ -0,0 +1,83 @@
+use actix_web::{post, web, HttpRequest, HttpResponse};
+
+use crate::{
+    errors::{AppError, AppResult, ErrorCode},
+    middleware::auth::require_session,
+    models::{
+        session::ClientContext,
+        user::{Acr, StepUpRequest, StepUpResponse},
+    },
+    utils::create_step_up_token,
+    AppState,
+};
+
+/// Re-authenticates the caller and answers with an elevated token for the
+/// routes marked with [`RequireStepUp`](crate::middleware::RequireStepUp).
+#[post("")]
+pub async fn step_up(
+    app_state: web::Data<AppState>,
+    body: web::Json<StepUpRequest>,
+    client: ClientContext,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    require_step_up_enabled(&app_state)?;
+    let claims = require_session(&req)?;
+
+    let user = app_state.user_queries.get_user_by_id(claims.sub).await?;
+    let acr = match body.into_inner() {
+        StepUpRequest::Password { password } => {
+            // Throttled like logins, so a stolen session can't guess the
+            // password here instead
+            let delay = app_state.auth_throttle_service
+                .check_login(client.ip, user.email.as_str())
+                .await?;
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+
+            if let Err(e) = app_state.user_queries.verify_user_credentials(&user.email, &password).await {
+                app_state.auth_throttle_service.record_failure(client.ip, user.email.as_str()).await;
+                return Err(e);
+            }
+            app_state.auth_throttle_service.record_success(user.email.as_str()).await;
+            Acr::Password
+        }
+        StepUpRequest::Passkey(ceremony) => {
+            if !app_state.passkey_service.enabled() {
+                return Err(AppError::NotFound("Passkeys are disabled".to_string()));
+            }
+            // Someone else's passkey proves nothing about this session
+            let user_id = app_state.passkey_service.finish_login(*ceremony).await?;
+            if user_id != user.id {
+                return Err(AppError::Unauthorized.with_code(ErrorCode::PasskeyVerificationFailed));
+            }
+            Acr::Passkey
+        }
+    };
+
+    let max_age_secs = app_state.settings.step_up.max_age_secs;
+    let access_token = create_step_up_token(
+        user.id,
+        &user.email,
+        app_state.role_service.grants_of(user.id).await?,
+        acr,
+        &app_state.jwt_keys,
+        max_age_secs,
+        app_state.clock.as_ref(),
+    )?;
+
+    Ok(HttpResponse::Ok().json(StepUpResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: max_age_secs,
+        acr,
+    }))
+}
+
+fn require_step_up_enabled(app_state: &AppState) -> AppResult<()> {
+    if app_state.settings.step_up.enabled {
+        Ok(())
+    } else {
+        Err(AppError::NotFound("Step-up authentication is disabled".to_string()))
+    }
+}
@@ -0,0 +1,67 @@
+use actix_web::{delete, get, http::StatusCode, post, web, HttpRequest, HttpResponse};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    dry_run::DryRun,
+    errors::AppResult,
+    middleware::{auth::require_session, RequireStepUp},
+    models::api_token::{ApiTokenResponse, CreateApiToken, CreatedApiTokenResponse},
+    AppState,
+};
+
+#[get("")]
+pub async fn list_tokens(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    // Token management is restricted to interactive sessions
+    let claims = require_session(&req)?;
+
+    let tokens = app_state.api_token_service.list_tokens(claims.sub).await?;
+    let token_responses: Vec<ApiTokenResponse> = tokens.into_iter().map(|t| t.into()).collect();
+
+    Ok(HttpResponse::Ok().json(token_responses))
+}
+
+#[post("", wrap = "RequireStepUp::recent()")]
+pub async fn create_token(
+    app_state: web::Data<AppState>,
+    token_data: web::Json<CreateApiToken>,
+    dry_run: DryRun,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    // Token management is restricted to interactive sessions
+    let claims = require_session(&req)?;
+
+    // Validate input
+    token_data.validate()
+        .map_err(|e| crate::errors::AppError::ValidationError(e.to_string()))?;
+
+    let created = app_state.api_token_service
+        .create_token(claims.sub, token_data.into_inner(), dry_run)
+        .await?;
+
+    let response = CreatedApiTokenResponse {
+        token: created.token,
+        signing_key: created.signing_key,
+        details: created.api_token.into(),
+    };
+
+    Ok(dry_run.response(StatusCode::CREATED).json(response))
+}
+
+#[delete("/{id}", wrap = "RequireStepUp::recent()")]
+pub async fn revoke_token(
+    app_state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    dry_run: DryRun,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    // Token management is restricted to interactive sessions
+    let claims = require_session(&req)?;
+
+    app_state.api_token_service.revoke_token(claims.sub, path.into_inner(), dry_run).await?;
+
+    Ok(dry_run.response(StatusCode::NO_CONTENT).finish())
+}
@@ -0,0 +1,27 @@
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+use crate::{
+    errors::AppResult,
+    middleware::auth::require_workload_role,
+    saga::deletion_fanout::ACK_ROLE,
+    AppState,
+};
+
+/// A downstream service confirming it deleted what it kept about the user;
+/// see [`deletion_fanout`](crate::saga::deletion_fanout).
+#[post("/{id}/ack")]
+pub async fn acknowledge_deletion(
+    app_state: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let identity = require_workload_role(&req, ACK_ROLE)?;
+
+    app_state
+        .deletion_fanout
+        .acknowledge(path.into_inner(), &identity.subject)
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
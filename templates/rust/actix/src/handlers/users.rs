@@ -1,46 +1,85 @@
-use actix_web::{delete, get, post, put, web, HttpMessage, HttpRequest, HttpResponse};
-use serde::{Deserialize, Serialize};
-use uuid::Uuid;
+use actix_template_macros::{authorize, cached};
+use actix_web::{delete, get, http::{header, StatusCode}, post, route, web, HttpRequest, HttpResponse};
+use platform_core::domain::UserId;
+use serde::Deserialize;
+use utoipa::ToSchema;
 use validator::Validate;
 
 use crate::{
-    errors::AppResult,
-    models::user::{Claims, CreateUser, LoginRequest, LoginResponse, PaginationParams, UpdateUser, UserResponse},
+    dry_run::DryRun,
+    errors::{AppError, AppResult, ErrorCode, ErrorResponse},
+    middleware::{
+        auth::require_scope,
+        step_up::{require_step_up, StepUp},
+        RequireStepUp,
+    },
+    models::{
+        api_token::Scope,
+        role::Grants,
+        session::ClientContext,
+        user::{
+            parse_etag, CreateUser, LoginRequest, LoginResponse, PaginatedUsers, PaginationParams, UpdateUser,
+            UserResponse,
+        },
+    },
+    saga::account_deletion,
+    token_delivery,
     utils::create_jwt_token,
     AppState,
 };
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct RefreshTokenRequest {
     pub refresh_token: String,
 }
 
+/// Creates an account, emails it a verification link and signs it in. With
+/// `token_delivery.mode = cookies` the tokens are set as cookies and left
+/// out of the body.
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/register",
+    tag = "auth",
+    request_body = CreateUser,
+    responses(
+        (status = 201, body = LoginResponse),
+        (status = 400, description = "Invalid email, username or password", body = ErrorResponse),
+        (status = 409, description = "Email or username taken", body = ErrorResponse),
+    ),
+)]
 #[post("/register")]
 pub async fn register(
     app_state: web::Data<AppState>,
     user_data: web::Json<CreateUser>,
+    client: ClientContext,
 ) -> AppResult<HttpResponse> {
     // Validate input
     user_data.validate()
         .map_err(|e| crate::errors::AppError::ValidationError(e.to_string()))?;
     
     // Create user
-    let user = app_state.user_service.create_user(user_data.into_inner()).await?;
+    let user = app_state.user_commands.create_user(user_data.into_inner(), DryRun::default()).await?;
+
+    // The account works without it; a failed send can be retried through
+    // /auth/resend-verification
+    if let Err(e) = app_state.email_verification_service.send(&user).await {
+        tracing::warn!(user_id = %user.id, "Failed to send verification email: {}", e);
+    }
     
     // Generate tokens
     let access_token = create_jwt_token(
         user.id,
         &user.email,
-        &app_state.settings.jwt.secret,
+        // New users have no roles yet
+        Grants::default(),
+        &app_state.jwt_keys,
         app_state.settings.jwt.access_token_expiry / 3600,
+        app_state.clock.as_ref(),
     )?;
     
-    let refresh_token = create_jwt_token(
-        user.id,
-        &user.email,
-        &app_state.settings.jwt.secret,
-        app_state.settings.jwt.refresh_token_expiry / 3600,
-    )?;
+    let refresh_token = app_state.session_service
+        .issue_refresh_token(user.id, &client)
+        .await?;
     
     let response = LoginResponse {
         access_token,
@@ -50,37 +89,62 @@ pub async fn register(
         user: user.into(),
     };
     
-    Ok(HttpResponse::Created().json(response))
+    Ok(token_delivery::login(&app_state.settings, StatusCode::CREATED, response))
 }
 
+/// Signs in with email and password. Repeated failures are slowed down and
+/// then locked out.
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, body = LoginResponse),
+        (status = 401, description = "Wrong email or password", body = ErrorResponse),
+        (status = 429, description = "Too many failed attempts", body = ErrorResponse),
+    ),
+)]
 #[post("/login")]
 pub async fn login(
     app_state: web::Data<AppState>,
     credentials: web::Json<LoginRequest>,
+    client: ClientContext,
 ) -> AppResult<HttpResponse> {
-    // Validate input
-    credentials.validate()
-        .map_err(|e| crate::errors::AppError::ValidationError(e.to_string()))?;
+    // Refuse locked accounts and slow down repeated failures
+    let delay = app_state.auth_throttle_service
+        .check_login(client.ip, credentials.email.as_str())
+        .await?;
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
     
     // Verify credentials
-    let user = app_state.user_service
+    let user = match app_state.user_queries
         .verify_user_credentials(&credentials.email, &credentials.password)
-        .await?;
+        .await
+    {
+        Ok(user) => user,
+        Err(e) => {
+            app_state.auth_throttle_service.record_failure(client.ip, credentials.email.as_str()).await;
+            return Err(e);
+        }
+    };
+    app_state.auth_throttle_service.record_success(credentials.email.as_str()).await;
     
     // Generate tokens
     let access_token = create_jwt_token(
         user.id,
         &user.email,
-        &app_state.settings.jwt.secret,
+        app_state.role_service.grants_of(user.id).await?,
+        &app_state.jwt_keys,
         app_state.settings.jwt.access_token_expiry / 3600,
+        app_state.clock.as_ref(),
     )?;
     
-    let refresh_token = create_jwt_token(
-        user.id,
-        &user.email,
-        &app_state.settings.jwt.secret,
-        app_state.settings.jwt.refresh_token_expiry / 3600,
-    )?;
+    let refresh_token = app_state.session_service
+        .issue_refresh_token(user.id, &client)
+        .await?;
     
     let response = LoginResponse {
         access_token,
@@ -90,36 +154,47 @@ pub async fn login(
         user: user.into(),
     };
     
-    Ok(HttpResponse::Ok().json(response))
+    Ok(token_delivery::login(&app_state.settings, StatusCode::OK, response))
 }
 
+/// Trades a refresh token, from the body or its cookie, for a new pair.
+/// Refresh tokens are single-use.
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/refresh",
+    tag = "auth",
+    request_body = Option<RefreshTokenRequest>,
+    responses(
+        (status = 200, body = LoginResponse),
+        (status = 401, description = "Unknown, used or expired refresh token", body = ErrorResponse),
+    ),
+)]
 #[post("/refresh")]
-pub async fn refresh_token(
+pub async fn refresh(
     app_state: web::Data<AppState>,
-    refresh_data: web::Json<RefreshTokenRequest>,
+    refresh_data: Option<web::Json<RefreshTokenRequest>>,
+    client: ClientContext,
+    req: HttpRequest,
 ) -> AppResult<HttpResponse> {
-    // Decode refresh token
-    let claims = crate::utils::decode_jwt_token(
-        &refresh_data.refresh_token,
-        &app_state.settings.jwt.secret,
-    )?;
+    let presented = presented_refresh_token(&app_state, refresh_data, &req)
+        .ok_or_else(|| AppError::Unauthorized.with_code(ErrorCode::AuthRefreshTokenInvalid))?;
+
+    // Consume the refresh token, checking its client binding
+    let (user_id, refresh_token) = app_state.session_service
+        .rotate_refresh_token(&presented, &client)
+        .await?;
     
     // Get user
-    let user = app_state.user_service.get_user_by_id(claims.sub).await?;
+    let user = app_state.user_queries.get_user_by_id(user_id).await?;
     
-    // Generate new tokens
+    // Generate new access token
     let access_token = create_jwt_token(
         user.id,
         &user.email,
-        &app_state.settings.jwt.secret,
+        app_state.role_service.grants_of(user.id).await?,
+        &app_state.jwt_keys,
         app_state.settings.jwt.access_token_expiry / 3600,
-    )?;
-    
-    let refresh_token = create_jwt_token(
-        user.id,
-        &user.email,
-        &app_state.settings.jwt.secret,
-        app_state.settings.jwt.refresh_token_expiry / 3600,
+        app_state.clock.as_ref(),
     )?;
     
     let response = LoginResponse {
@@ -130,58 +205,170 @@ pub async fn refresh_token(
         user: user.into(),
     };
     
-    Ok(HttpResponse::Ok().json(response))
+    Ok(token_delivery::login(&app_state.settings, StatusCode::OK, response))
 }
 
+/// Signs out the session of the presented refresh token and, in cookie
+/// mode, clears the token cookies.
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/logout",
+    tag = "auth",
+    request_body = Option<RefreshTokenRequest>,
+    responses((status = 204)),
+)]
+#[post("/logout")]
+pub async fn logout(
+    app_state: web::Data<AppState>,
+    refresh_data: Option<web::Json<RefreshTokenRequest>>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    if let Some(token) = presented_refresh_token(&app_state, refresh_data, &req) {
+        app_state.session_service.revoke_token(&token).await?;
+    }
+
+    let mut response = HttpResponse::NoContent();
+    token_delivery::clear(&app_state.settings, &mut response);
+    Ok(response.finish())
+}
+
+/// The refresh token from the body or, in cookie mode, its cookie.
+fn presented_refresh_token(
+    app_state: &AppState,
+    refresh_data: Option<web::Json<RefreshTokenRequest>>,
+    req: &HttpRequest,
+) -> Option<String> {
+    refresh_data
+        .map(|body| body.into_inner().refresh_token)
+        .or_else(|| token_delivery::refresh_token(&app_state.settings, req))
+}
+
+/// A page of users.
+#[utoipa::path(
+    get,
+    path = "/api/v1/users",
+    tag = "users",
+    params(PaginationParams),
+    responses(
+        (status = 200, body = PaginatedUsers),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "Missing the `read:users` scope", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = ["read:users"])),
+)]
+#[cached(ttl = "30s", tags = ["users:list"])]
+#[authorize("read:users")]
 #[get("")]
 pub async fn get_users(
     app_state: web::Data<AppState>,
     query: web::Query<PaginationParams>,
 ) -> AppResult<HttpResponse> {
+    query.validate()
+        .map_err(|e| crate::errors::AppError::ValidationError(e.to_string()))?;
     let page = query.page.unwrap_or(1);
     let limit = query.limit.unwrap_or(20);
+    let count = query.count.unwrap_or(app_state.settings.pagination.count);
     
-    let users = app_state.user_service.get_users(page, limit).await?;
+    let users = app_state.user_queries.get_users(page, limit, count).await?;
     
     Ok(HttpResponse::Ok().json(users))
 }
 
+/// A user, with an `ETag` to send back as `If-Match` when updating it.
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{id}",
+    tag = "users",
+    params(("id" = UserId, Path)),
+    responses(
+        (status = 200, body = UserResponse, headers(("ETag" = String))),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "Missing the `read:users` scope", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+    security(("bearer_auth" = ["read:users"])),
+)]
+#[cached(ttl = "30s", tags = ["user:{id}"])]
+#[authorize("read:users")]
 #[get("/{id}")]
 pub async fn get_user(
     app_state: web::Data<AppState>,
-    path: web::Path<Uuid>,
+    path: web::Path<UserId>,
 ) -> AppResult<HttpResponse> {
-    let user = app_state.user_service.get_user_by_id(path.into_inner()).await?;
+    let user = app_state.user_queries.get_user_by_id(path.into_inner()).await?;
+    let etag = user.etag();
     let user_response: UserResponse = user.into();
     
-    Ok(HttpResponse::Ok().json(user_response))
+    Ok(HttpResponse::Ok().insert_header((header::ETAG, etag)).json(user_response))
 }
 
+/// Creates a user without signing it in.
+#[utoipa::path(
+    post,
+    path = "/api/v1/users",
+    tag = "users",
+    params(("X-Dry-Run" = Option<bool>, Header, description = "Validate without creating")),
+    request_body = CreateUser,
+    responses(
+        (status = 201, body = UserResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "Missing the `write:users` scope", body = ErrorResponse),
+        (status = 409, description = "Email or username taken", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = ["write:users"])),
+)]
+#[authorize("write:users")]
 #[post("")]
 pub async fn create_user(
     app_state: web::Data<AppState>,
     user_data: web::Json<CreateUser>,
+    dry_run: DryRun,
 ) -> AppResult<HttpResponse> {
     // Validate input
     user_data.validate()
         .map_err(|e| crate::errors::AppError::ValidationError(e.to_string()))?;
     
-    let user = app_state.user_service.create_user(user_data.into_inner()).await?;
+    let user = app_state.user_commands.create_user(user_data.into_inner(), dry_run).await?;
+    if !dry_run.is_dry_run() {
+        app_state.response_cache.invalidate_tag("users:list").await;
+    }
     let user_response: UserResponse = user.into();
     
-    Ok(HttpResponse::Created().json(user_response))
+    Ok(dry_run.response(StatusCode::CREATED).json(user_response))
 }
 
-#[put("/{id}")]
+/// Updates the caller's own profile; `PATCH` is accepted too. Changing the
+/// email address needs a recent step-up.
+#[utoipa::path(
+    put,
+    path = "/api/v1/users/{id}",
+    tag = "users",
+    params(
+        ("id" = UserId, Path),
+        ("If-Match" = Option<String>, Header, description = "ETag from a previous read; stale updates get 409"),
+        ("X-Dry-Run" = Option<bool>, Header, description = "Validate without updating"),
+    ),
+    request_body = UpdateUser,
+    responses(
+        (status = 200, body = UserResponse, headers(("ETag" = String))),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "Not the caller's profile, or a step-up is needed", body = ErrorResponse),
+        (status = 409, description = "Modified since the `If-Match` read", body = ErrorResponse),
+    ),
+    security(("bearer_auth" = ["write:profile"])),
+)]
+#[route("/{id}", method = "PUT", method = "PATCH")]
 pub async fn update_user(
     app_state: web::Data<AppState>,
-    path: web::Path<Uuid>,
+    path: web::Path<UserId>,
     user_data: web::Json<UpdateUser>,
+    dry_run: DryRun,
     req: HttpRequest,
 ) -> AppResult<HttpResponse> {
     // Get claims from request extensions (set by auth middleware)
-    let claims = req.extensions().get::<Claims>().cloned()
-        .ok_or(crate::errors::AppError::Unauthorized)?;
+    let claims = require_scope(&req, Scope::WriteProfile)?;
     
     // Check if user is updating their own profile
     let user_id = path.into_inner();
@@ -189,25 +376,70 @@ pub async fn update_user(
         return Err(crate::errors::AppError::Forbidden);
     }
     
+    // Changing the email address hands over the account, so it needs a
+    // recent re-authentication
+    if user_data.email.is_some() {
+        require_step_up(&req, StepUp::Recent)?;
+    }
+    
     // Validate input
     user_data.validate()
         .map_err(|e| crate::errors::AppError::ValidationError(e.to_string()))?;
     
-    let user = app_state.user_service.update_user(user_id, user_data.into_inner()).await?;
+    // Optional optimistic concurrency: If-Match carries the ETag from a
+    // previous read
+    let expected_updated_at = match req.headers().get(header::IF_MATCH) {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(parse_etag)
+                .ok_or_else(|| crate::errors::AppError::BadRequest("Invalid If-Match header".to_string()))?,
+        ),
+        None => None,
+    };
+
+    let user = app_state
+        .user_commands
+        .update_user(user_id, user_data.into_inner(), expected_updated_at, dry_run)
+        .await?;
+    if !dry_run.is_dry_run() {
+        app_state.response_cache.invalidate_tag(&format!("user:{}", user_id)).await;
+        app_state.response_cache.invalidate_tag("users:list").await;
+    }
+    let etag = user.etag();
     let user_response: UserResponse = user.into();
     
-    Ok(HttpResponse::Ok().json(user_response))
+    Ok(dry_run.response(StatusCode::OK).insert_header((header::ETAG, etag)).json(user_response))
 }
 
-#[delete("/{id}")]
+/// Starts deleting the caller's own account, which completes in the
+/// background. Needs a recent step-up.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/users/{id}",
+    tag = "users",
+    params(
+        ("id" = UserId, Path),
+        ("X-Dry-Run" = Option<bool>, Header, description = "Check without deleting"),
+    ),
+    responses(
+        (status = 202),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "Not the caller's account, or a step-up is needed", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+    security(("bearer_auth" = ["write:profile"])),
+)]
+#[delete("/{id}", wrap = "RequireStepUp::recent()")]
 pub async fn delete_user(
     app_state: web::Data<AppState>,
-    path: web::Path<Uuid>,
+    path: web::Path<UserId>,
+    dry_run: DryRun,
     req: HttpRequest,
 ) -> AppResult<HttpResponse> {
     // Get claims from request extensions (set by auth middleware)
-    let claims = req.extensions().get::<Claims>().cloned()
-        .ok_or(crate::errors::AppError::Unauthorized)?;
+    let claims = require_scope(&req, Scope::WriteProfile)?;
     
     // Check if user is deleting their own profile
     let user_id = path.into_inner();
@@ -215,7 +447,16 @@ pub async fn delete_user(
         return Err(crate::errors::AppError::Forbidden);
     }
     
-    app_state.user_service.delete_user(user_id).await?;
+    // Deletion spans several steps and runs in the background; a dry run
+    // stops at checking there is an account to delete
+    if dry_run.is_dry_run() {
+        app_state.user_queries.get_user_by_id(user_id).await?;
+    } else {
+        app_state
+            .saga_engine
+            .start(account_deletion::ACCOUNT_DELETION, &account_deletion::AccountDeletion { user_id })
+            .await?;
+    }
     
-    Ok(HttpResponse::NoContent().finish())
+    Ok(dry_run.response(StatusCode::ACCEPTED).finish())
 }
\ No newline at end of file
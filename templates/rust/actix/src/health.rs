@@ -0,0 +1,275 @@
+//! Readiness of the database and downstream services.
+//!
+//! The [`HealthRegistry`] runs every registered [`HealthCheck`] for
+//! `/api/v1/ready`. Client modules contribute checks for the services they
+//! call, e.g. [`PushSender::health_checks`](crate::push::PushSender::health_checks),
+//! so a configured provider shows up without extra wiring. Results are
+//! cached for `health.cache_ttl_ms`, and each dependency has a circuit
+//! breaker: after `health.failure_threshold` consecutive failures it is
+//! reported down without being checked for `health.open_secs`, then checked
+//! once more to decide whether to close again.
+//!
+//! Only critical dependencies decide readiness; others, like push and SMS
+//! providers, are reported and make the service `degraded`. Checks record
+//! `dependency_up`, `dependency_circuit_open` and
+//! `dependency_check_duration_seconds` through the `metrics` facade.
+
+use chrono::{DateTime, Utc};
+use futures_util::future::{join_all, BoxFuture};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::config::HealthSettings;
+use crate::utils::SharedClock;
+
+pub trait HealthCheck: Send + Sync {
+    /// Identifies the dependency in responses and metrics.
+    fn name(&self) -> &str;
+
+    /// Whether the service can't serve requests without the dependency.
+    fn critical(&self) -> bool {
+        true
+    }
+
+    fn check(&self) -> BoxFuture<'_, Result<(), String>>;
+}
+
+pub struct DatabaseCheck(pub PgPool);
+
+impl HealthCheck for DatabaseCheck {
+    fn name(&self) -> &str {
+        "database"
+    }
+
+    fn check(&self) -> BoxFuture<'_, Result<(), String>> {
+        Box::pin(async move {
+            sqlx::query("SELECT 1")
+                .execute(&self.0)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+    }
+}
+
+/// An HTTP service, up if it answers at all: providers respond to an
+/// unauthenticated probe with a client error, so only server errors and
+/// connection failures count as down. Not critical.
+pub struct HttpDependency {
+    name: String,
+    client: Client,
+    url: String,
+}
+
+impl HttpDependency {
+    /// Probes `url` with `client`, usually the one the module sends with.
+    pub fn new(name: &str, client: Client, url: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            client,
+            url: url.to_string(),
+        }
+    }
+}
+
+impl HealthCheck for HttpDependency {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn critical(&self) -> bool {
+        false
+    }
+
+    fn check(&self) -> BoxFuture<'_, Result<(), String>> {
+        Box::pin(async move {
+            let response = self.client.head(&self.url).send().await.map_err(|e| e.to_string())?;
+            if response.status().is_server_error() {
+                return Err(format!("responded with {}", response.status()));
+            }
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    /// The open period is over; the next check decides.
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DependencyStatus {
+    pub name: String,
+    pub up: bool,
+    pub critical: bool,
+    pub circuit: CircuitState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub latency_ms: u64,
+    pub checked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthReport {
+    /// Every critical dependency is up.
+    pub ready: bool,
+    /// Some non-critical dependency is down.
+    pub degraded: bool,
+    pub dependencies: Vec<DependencyStatus>,
+}
+
+impl HealthReport {
+    pub fn dependency(&self, name: &str) -> Option<&DependencyStatus> {
+        self.dependencies.iter().find(|dependency| dependency.name == name)
+    }
+}
+
+#[derive(Default)]
+struct Breaker {
+    last: Option<DependencyStatus>,
+    consecutive_failures: u32,
+    opened_at: Option<DateTime<Utc>>,
+}
+
+struct Entry {
+    check: Arc<dyn HealthCheck>,
+    // Held while checking, so concurrent probes share one check
+    breaker: Mutex<Breaker>,
+}
+
+pub struct HealthRegistry {
+    settings: HealthSettings,
+    clock: SharedClock,
+    entries: Vec<Entry>,
+}
+
+impl HealthRegistry {
+    pub fn new(settings: HealthSettings, clock: SharedClock) -> Self {
+        Self {
+            settings,
+            clock,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn register(mut self, check: Arc<dyn HealthCheck>) -> Self {
+        self.entries.push(Entry {
+            check,
+            breaker: Mutex::new(Breaker::default()),
+        });
+        self
+    }
+
+    pub fn register_all(self, checks: impl IntoIterator<Item = Arc<dyn HealthCheck>>) -> Self {
+        checks.into_iter().fold(self, |registry, check| registry.register(check))
+    }
+
+    /// The state of every dependency, checking those without a fresh result.
+    pub async fn report(&self) -> HealthReport {
+        let dependencies = join_all(self.entries.iter().map(|entry| self.status(entry))).await;
+
+        HealthReport {
+            ready: dependencies.iter().all(|dependency| dependency.up || !dependency.critical),
+            degraded: dependencies.iter().any(|dependency| !dependency.up && !dependency.critical),
+            dependencies,
+        }
+    }
+
+    async fn status(&self, entry: &Entry) -> DependencyStatus {
+        let mut breaker = entry.breaker.lock().await;
+        let now = self.clock.now();
+
+        if let Some(last) = &breaker.last {
+            let age = now - last.checked_at;
+            if age < chrono::Duration::milliseconds(self.settings.cache_ttl_ms as i64) {
+                return last.clone();
+            }
+        }
+
+        let circuit = match breaker.opened_at {
+            Some(opened_at) if now - opened_at < chrono::Duration::seconds(self.settings.open_secs) => {
+                CircuitState::Open
+            }
+            Some(_) => CircuitState::HalfOpen,
+            None => CircuitState::Closed,
+        };
+        let status = if circuit == CircuitState::Open {
+            let error = breaker.last.as_ref().and_then(|last| last.error.clone());
+            self.record(entry, false, CircuitState::Open, error, Duration::ZERO, now)
+        } else {
+            self.check(entry, &mut breaker, now).await
+        };
+
+        breaker.last = Some(status.clone());
+        status
+    }
+
+    async fn check(&self, entry: &Entry, breaker: &mut Breaker, now: DateTime<Utc>) -> DependencyStatus {
+        let timeout = Duration::from_millis(self.settings.check_timeout_ms);
+        let started = Instant::now();
+        let result = match tokio::time::timeout(timeout, entry.check.check()).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("timed out after {}ms", self.settings.check_timeout_ms)),
+        };
+        let latency = started.elapsed();
+
+        let circuit = match &result {
+            Ok(()) => {
+                breaker.consecutive_failures = 0;
+                breaker.opened_at = None;
+                CircuitState::Closed
+            }
+            Err(e) => {
+                breaker.consecutive_failures += 1;
+                tracing::warn!(dependency = entry.check.name(), failures = breaker.consecutive_failures, "Health check failed: {}", e);
+                // A failed trial reopens right away
+                if breaker.opened_at.is_some() || breaker.consecutive_failures >= self.settings.failure_threshold {
+                    breaker.opened_at = Some(now);
+                    CircuitState::Open
+                } else {
+                    CircuitState::Closed
+                }
+            }
+        };
+
+        self.record(entry, result.is_ok(), circuit, result.err(), latency, now)
+    }
+
+    fn record(
+        &self,
+        entry: &Entry,
+        up: bool,
+        circuit: CircuitState,
+        error: Option<String>,
+        latency: Duration,
+        now: DateTime<Utc>,
+    ) -> DependencyStatus {
+        let name = entry.check.name().to_string();
+        metrics::gauge!("dependency_up", "dependency" => name.clone()).set(if up { 1.0 } else { 0.0 });
+        metrics::gauge!("dependency_circuit_open", "dependency" => name.clone())
+            .set(if circuit == CircuitState::Open { 1.0 } else { 0.0 });
+        if circuit != CircuitState::Open {
+            metrics::histogram!("dependency_check_duration_seconds", "dependency" => name.clone())
+                .record(latency.as_secs_f64());
+        }
+
+        DependencyStatus {
+            name,
+            up,
+            critical: entry.check.critical(),
+            circuit,
+            error,
+            latency_ms: latency.as_millis() as u64,
+            checked_at: now,
+        }
+    }
+}
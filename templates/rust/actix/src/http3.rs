@@ -0,0 +1,206 @@
+//! HTTP/3 for listeners with `http3` set: a QUIC endpoint on the
+//! listener's port, over UDP and with its certificate, serving the same
+//! API as the TCP side. actix doesn't speak HTTP/3, so h3 reads each
+//! request and the API's actix service answers it, on a thread of the
+//! endpoint's own. TCP responses advertise the endpoint with `Alt-Svc`;
+//! see [`listeners::api`](crate::listeners::api).
+
+use actix_http::{Payload, Request, Version};
+use actix_service::{IntoServiceFactory, Service, ServiceFactory};
+use actix_web::body::MessageBody;
+use actix_web::dev::{AppConfig, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::Method;
+use actix_web::web;
+use anyhow::anyhow;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use h3::error::ErrorLevel;
+use h3::server::RequestStream;
+use h3_quinn::BidiStream;
+use platform_core::lifecycle::Draining;
+use quinn::crypto::rustls::QuicServerConfig;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::listeners;
+use crate::middleware::Pipeline;
+use crate::AppState;
+
+/// Connection-specific headers, which HTTP/3 forbids.
+const HOP_BY_HOP: &[&str] = &["connection", "keep-alive", "transfer-encoding", "upgrade"];
+
+/// Serves HTTP/3 on `socket` from a new thread until the server drains.
+pub fn spawn(
+    socket: UdpSocket,
+    mut tls: rustls::ServerConfig,
+    app_state: web::Data<AppState>,
+    pipeline: Pipeline,
+    alt_svc: Option<String>,
+) -> io::Result<()> {
+    tls.alpn_protocols = vec![b"h3".to_vec()];
+    let crypto = QuicServerConfig::try_from(tls).map_err(io::Error::other)?;
+    let config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+
+    std::thread::Builder::new().name("http3".to_string()).spawn(move || {
+        actix_rt::System::new().block_on(async move {
+            if let Err(e) = serve(socket, config, app_state, pipeline, alt_svc).await {
+                tracing::error!("HTTP/3 listener failed: {:#}", e);
+            }
+        })
+    })?;
+    Ok(())
+}
+
+async fn serve(
+    socket: UdpSocket,
+    config: quinn::ServerConfig,
+    app_state: web::Data<AppState>,
+    pipeline: Pipeline,
+    alt_svc: Option<String>,
+) -> anyhow::Result<()> {
+    app_state.runtimes.register_current("http3");
+    let server = app_state.settings.server.clone();
+    let draining = app_state.draining.clone();
+    let endpoint = quinn::Endpoint::new(
+        quinn::EndpointConfig::default(),
+        Some(config),
+        socket,
+        Arc::new(quinn::TokioRuntime),
+    )?;
+    let app = listeners::api(app_state, pipeline, alt_svc)
+        .into_factory()
+        .new_service(AppConfig::default())
+        .await
+        .map_err(|()| anyhow!("building the API service failed"))?;
+    let app = Rc::new(app);
+    let max_body = server.max_json_body_bytes.max(server.max_payload_bytes);
+
+    loop {
+        let incoming = tokio::select! {
+            incoming = endpoint.accept() => incoming,
+            _ = draining.closed() => None,
+        };
+        let Some(incoming) = incoming else {
+            break;
+        };
+
+        let (app, draining) = (app.clone(), draining.clone());
+        actix_rt::spawn(async move {
+            if let Err(e) = serve_connection(incoming, app, max_body, draining).await {
+                tracing::debug!("HTTP/3 connection closed with error: {:#}", e);
+            }
+        });
+    }
+
+    // Connections were sent a GOAWAY; give their requests the drain timeout
+    let drain_timeout = Duration::from_secs(server.drain_timeout_secs);
+    if tokio::time::timeout(drain_timeout, endpoint.wait_idle()).await.is_err() {
+        endpoint.close(0u32.into(), b"shutting down");
+    }
+    Ok(())
+}
+
+async fn serve_connection<S, B>(
+    incoming: quinn::Incoming,
+    app: Rc<S>,
+    max_body: usize,
+    draining: Draining,
+) -> anyhow::Result<()>
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    let connection = incoming.await?;
+    let peer = connection.remote_address();
+    let mut connection = h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(connection)).await?;
+
+    let mut closing = false;
+    loop {
+        let accepted = tokio::select! {
+            accepted = connection.accept() => accepted,
+            _ = draining.closed(), if !closing => {
+                // Lets in-flight requests finish, refusing new ones
+                closing = true;
+                connection.shutdown(0).await?;
+                continue;
+            }
+        };
+
+        match accepted {
+            Ok(Some((request, stream))) => {
+                let app = app.clone();
+                actix_rt::spawn(async move {
+                    if let Err(e) = respond(request, stream, app, peer, max_body).await {
+                        tracing::debug!("HTTP/3 request failed: {:#}", e);
+                    }
+                });
+            }
+            Ok(None) => return Ok(()),
+            Err(e) => match e.get_error_level() {
+                ErrorLevel::ConnectionError => return Err(e.into()),
+                ErrorLevel::StreamError => continue,
+            },
+        }
+    }
+}
+
+/// Reads the request body, with the larger of the JSON and payload limits
+/// applied before actix sees it, and streams back the API's response.
+async fn respond<S, B>(
+    request: http1::Request<()>,
+    mut stream: RequestStream<BidiStream<Bytes>, Bytes>,
+    app: Rc<S>,
+    peer: SocketAddr,
+    max_body: usize,
+) -> anyhow::Result<()>
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody + 'static,
+{
+    let mut body = BytesMut::new();
+    while let Some(chunk) = stream.recv_data().await? {
+        if body.len() + chunk.remaining() > max_body {
+            let too_large = http1::Response::builder().status(413).body(())?;
+            stream.send_response(too_large).await?;
+            return Ok(stream.finish().await?);
+        }
+        body.put(chunk);
+    }
+
+    let mut actix_request = Request::with_payload(Payload::from(body.freeze()));
+    let head = actix_request.head_mut();
+    head.method = Method::from_bytes(request.method().as_str().as_bytes())?;
+    head.uri = request.uri().to_string().parse()?;
+    head.version = Version::HTTP_3;
+    head.peer_addr = Some(peer);
+    for (name, value) in request.headers() {
+        head.headers.append(
+            HeaderName::from_bytes(name.as_str().as_bytes())?,
+            HeaderValue::from_bytes(value.as_bytes())?,
+        );
+    }
+
+    let response = match app.call(actix_request).await {
+        Ok(response) => response.map_into_boxed_body().into_parts().1,
+        Err(error) => error.error_response(),
+    };
+
+    let mut head = http1::Response::builder().status(response.status().as_u16());
+    for (name, value) in response.headers() {
+        if !HOP_BY_HOP.contains(&name.as_str()) {
+            head = head.header(name.as_str(), value.as_bytes());
+        }
+    }
+    stream.send_response(head.body(())?).await?;
+
+    let body = response.into_body();
+    let mut body = std::pin::pin!(body);
+    while let Some(chunk) = std::future::poll_fn(|cx| body.as_mut().poll_next(cx)).await {
+        stream.send_data(chunk.map_err(|e| anyhow!("{}", e))?).await?;
+    }
+    stream.finish().await?;
+    Ok(())
+}
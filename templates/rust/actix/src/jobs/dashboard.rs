@@ -0,0 +1,224 @@
+//! What admins need to operate background jobs without `psql`: listing and
+//! inspecting jobs with redacted payloads, retrying and cancelling them,
+//! run time and queue wait percentiles with the commonest failures per
+//! kind, and purging dead jobs. Served under `/api/v1/admin/jobs`.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::errors::{AppError, AppResult, ResultExt};
+use crate::jobs::JobQueue;
+use crate::models::job::{Job, JOB_COMPLETED, JOB_DEAD, JOB_PENDING};
+
+/// Shown in place of redacted payload values.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Jobs [`JobQueue::list`] returns at most.
+pub const MAX_LIST_LIMIT: i64 = 200;
+
+/// Failure reasons [`JobQueue::stats`] returns at most.
+const FAILURE_LIMIT: i64 = 20;
+
+/// Filters of [`JobQueue::list`]; all optional.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct JobFilter {
+    pub status: Option<String>,
+    pub queue: Option<String>,
+    pub kind: Option<String>,
+    /// Jobs created before this, to page through older ones.
+    pub before: Option<DateTime<Utc>>,
+    /// Defaults to 50, at most [`MAX_LIST_LIMIT`].
+    pub limit: Option<i64>,
+}
+
+/// Run times and queue waits of a kind's jobs finished since the start of
+/// the window, in seconds.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct KindStats {
+    pub kind: String,
+    pub completed: i64,
+    pub dead: i64,
+    pub duration_p50_secs: Option<f64>,
+    pub duration_p95_secs: Option<f64>,
+    pub duration_p99_secs: Option<f64>,
+    /// From when a job was due to when its last attempt started.
+    pub wait_p50_secs: Option<f64>,
+    pub wait_p95_secs: Option<f64>,
+}
+
+/// How many jobs of a kind died with an error.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct FailureReason {
+    pub kind: String,
+    /// The error, cut to 200 characters.
+    pub error: Option<String>,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStats {
+    pub since: DateTime<Utc>,
+    pub kinds: Vec<KindStats>,
+    /// The commonest errors of dead jobs, most frequent first.
+    pub failures: Vec<FailureReason>,
+}
+
+impl JobQueue {
+    /// Jobs matching `filter`, newest first, with redacted payloads.
+    pub async fn list(&self, filter: &JobFilter) -> AppResult<Vec<Job>> {
+        let limit = filter.limit.unwrap_or(50);
+        if !(1..=MAX_LIST_LIMIT).contains(&limit) {
+            return Err(AppError::BadRequest(format!("limit must be between 1 and {}", MAX_LIST_LIMIT)));
+        }
+
+        let jobs: Vec<Job> = sqlx::query_as(
+            r#"
+            SELECT * FROM jobs
+            WHERE ($1::text IS NULL OR status = $1)
+              AND ($2::text IS NULL OR queue = $2)
+              AND ($3::text IS NULL OR kind = $3)
+              AND ($4::timestamptz IS NULL OR created_at < $4)
+            ORDER BY created_at DESC, id
+            LIMIT $5
+            "#,
+        )
+        .bind(&filter.status)
+        .bind(&filter.queue)
+        .bind(&filter.kind)
+        .bind(filter.before)
+        .bind(limit)
+        .fetch_all(&self.db)
+        .await
+        .context("list jobs")?;
+        Ok(jobs.into_iter().map(|job| self.redacted(job)).collect())
+    }
+
+    /// A job with its payload redacted.
+    pub async fn inspect(&self, job_id: Uuid) -> AppResult<Job> {
+        self.get_job(job_id)
+            .await?
+            .map(|job| self.redacted(job))
+            .ok_or_else(|| AppError::NotFound(format!("Job {} not found", job_id)))
+    }
+
+    /// Runs a dead or pending job again right away, with its attempts
+    /// reset. Its last error is kept until the next attempt.
+    pub async fn retry(&self, job_id: Uuid) -> AppResult<Job> {
+        let job = sqlx::query_as(
+            r#"
+            UPDATE jobs SET status = $2, attempts = 0, run_at = $3, locked_at = NULL, finished_at = NULL
+            WHERE id = $1 AND status = ANY($4)
+            RETURNING *
+            "#,
+        )
+        .bind(job_id)
+        .bind(JOB_PENDING)
+        .bind(self.clock.now())
+        .bind([JOB_DEAD, JOB_PENDING])
+        .fetch_optional(&self.db)
+        .await
+        .entity_context("retry job", job_id)?;
+        match job {
+            Some(job) => Ok(self.redacted(job)),
+            None => Err(self.unchangeable(job_id, "dead or pending").await),
+        }
+    }
+
+    /// Run time and queue wait percentiles per kind, and the commonest
+    /// failures, of jobs finished since `since`.
+    pub async fn stats(&self, since: DateTime<Utc>) -> AppResult<JobStats> {
+        let kinds = sqlx::query_as(
+            r#"
+            SELECT kind,
+                COUNT(*) FILTER (WHERE status = $2) AS completed,
+                COUNT(*) FILTER (WHERE status = $3) AS dead,
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM finished_at - started_at)::float8)
+                    FILTER (WHERE status = $2) AS duration_p50_secs,
+                percentile_cont(0.95) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM finished_at - started_at)::float8)
+                    FILTER (WHERE status = $2) AS duration_p95_secs,
+                percentile_cont(0.99) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM finished_at - started_at)::float8)
+                    FILTER (WHERE status = $2) AS duration_p99_secs,
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM started_at - run_at)::float8)
+                    AS wait_p50_secs,
+                percentile_cont(0.95) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM started_at - run_at)::float8)
+                    AS wait_p95_secs
+            FROM jobs
+            WHERE finished_at >= $1
+            GROUP BY kind
+            ORDER BY kind
+            "#,
+        )
+        .bind(since)
+        .bind(JOB_COMPLETED)
+        .bind(JOB_DEAD)
+        .fetch_all(&self.db)
+        .await
+        .context("load job stats")?;
+
+        let failures = sqlx::query_as(
+            r#"
+            SELECT kind, LEFT(last_error, 200) AS error, COUNT(*) AS count
+            FROM jobs
+            WHERE status = $2 AND finished_at >= $1
+            GROUP BY kind, LEFT(last_error, 200)
+            ORDER BY count DESC, kind
+            LIMIT $3
+            "#,
+        )
+        .bind(since)
+        .bind(JOB_DEAD)
+        .bind(FAILURE_LIMIT)
+        .fetch_all(&self.db)
+        .await
+        .context("load job failures")?;
+
+        Ok(JobStats { since, kinds, failures })
+    }
+
+    /// Deletes dead jobs, optionally only those of `kind` or that died
+    /// before `before`. Returns how many.
+    pub async fn purge_dead(&self, kind: Option<&str>, before: Option<DateTime<Utc>>) -> AppResult<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM jobs
+            WHERE status = $1
+              AND ($2::text IS NULL OR kind = $2)
+              AND ($3::timestamptz IS NULL OR finished_at < $3)
+            "#,
+        )
+        .bind(JOB_DEAD)
+        .bind(kind)
+        .bind(before)
+        .execute(&self.db)
+        .await
+        .context("purge dead jobs")?;
+        Ok(result.rows_affected())
+    }
+
+    fn redacted(&self, mut job: Job) -> Job {
+        redact(&mut job.payload, &self.settings.redacted_fields);
+        job
+    }
+}
+
+/// Replaces the values of fields whose name contains one of `fields`,
+/// ignoring case, at any depth.
+pub fn redact(value: &mut Value, fields: &[String]) {
+    match value {
+        Value::Object(object) => {
+            for (name, value) in object.iter_mut() {
+                let name = name.to_lowercase();
+                if fields.iter().any(|field| name.contains(&field.to_lowercase())) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value, fields);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(|value| redact(value, fields)),
+        _ => {}
+    }
+}
@@ -0,0 +1,312 @@
+//! Background jobs stored in Postgres.
+//!
+//! Jobs are enqueued with a kind and a JSON payload, possibly in the
+//! transaction that makes them necessary, and run by a [`Worker`] with the
+//! [`JobHandler`] registered for their kind. Failed jobs are retried with
+//! exponential backoff until `jobs.max_attempts`, then left `dead` with
+//! their last error.
+//!
+//! Jobs can also be scheduled: [`JobQueue::enqueue_at`] and
+//! [`JobQueue::enqueue_in`] enqueue them to run later, e.g. a reminder in a
+//! day, and pending jobs can be [rescheduled](JobQueue::reschedule) or
+//! [cancelled](JobQueue::cancel) until then. A job still running
+//! `jobs.visibility_timeout_secs` after it was claimed is presumed lost with
+//! its worker and claimed again.
+//!
+//! Jobs run in the queue of `jobs.queues` listing their kind, or `default`.
+//! Each queue has a `priority`, deciding which is served first when they
+//! compete for the worker's `jobs.concurrency`, an optional `concurrency` of
+//! its own and an optional `rate_limit` of jobs per period, counted per
+//! [`JobOptions::rate_key`] (e.g. a webhook endpoint). Admins pause and
+//! resume queues through `/api/v1/admin/job-queues`; a paused queue keeps
+//! its jobs pending, on every replica.
+//!
+//! ```ignore
+//! state.job_queue.enqueue(push::FAN_OUT_JOB, &payload).await?;
+//! state.job_queue.enqueue_in(REMINDER_JOB, &payload, Duration::hours(24)).await?;
+//! ```
+//!
+//! Workers record `jobs_processed_total{queue, outcome}`, `jobs_running{queue}`
+//! and `job_duration_seconds{queue}` through the `metrics` facade.
+
+use chrono::{DateTime, Duration, Utc};
+use futures_util::future::BoxFuture;
+use platform_core::lifecycle::{Hook, Lifecycle};
+use serde::Serialize;
+use sqlx::{PgExecutor, PgPool};
+use std::sync::Arc;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::config::JobSettings;
+use crate::errors::{AppError, AppResult, ResultExt};
+use crate::models::job::{Job, JobQueueStatus, JOB_DEAD, JOB_PENDING, JOB_RUNNING};
+use crate::{analytics, materialized_views, push, retention, saga};
+use crate::utils::SharedClock;
+use crate::AppState;
+
+pub mod dashboard;
+pub mod worker;
+
+pub use worker::Worker;
+
+pub trait JobHandler: Send + Sync {
+    /// Runs `job`; an error schedules a retry.
+    fn handle<'a>(&'a self, job: &'a Job) -> BoxFuture<'a, AppResult<()>>;
+}
+
+/// A worker with every job kind of the application registered.
+pub fn worker(state: &AppState) -> Worker {
+    let worker =
+        Worker::new(state.db.clone(), state.settings.jobs.clone(), state.clock.clone()).meter(state.meter.clone());
+    let worker = push::register_jobs(worker, state);
+    let worker = analytics::register_jobs(worker, state);
+    let worker = retention::register_jobs(worker, state);
+    let worker = materialized_views::register_jobs(worker, state);
+    saga::register_jobs(worker, state)
+}
+
+/// Runs the worker and saga recovery from startup until shutdown, when the
+/// worker finishes its running jobs. Nothing when `jobs.enabled` is off.
+pub fn register_hooks(lifecycle: &mut Lifecycle, state: &Arc<AppState>) {
+    if !state.settings.jobs.enabled {
+        return;
+    }
+
+    let (stop, stopped) = watch::channel(());
+    let (started, tasks) = oneshot::channel::<Vec<JoinHandle<()>>>();
+    let stopped = move || {
+        let mut stopped = stopped.clone();
+        async move {
+            let _ = stopped.changed().await;
+        }
+    };
+
+    let state = state.clone();
+    lifecycle.on_start(Hook::new("jobs", move || async move {
+        let worker = worker(&state).run(stopped());
+        let saga_engine = state.saga_engine.clone();
+        let stopped = stopped();
+        let saga_recovery = async move { saga_engine.resume_stalled_until(stopped).await };
+        let _ = started.send(vec![tokio::spawn(worker), tokio::spawn(saga_recovery)]);
+        Ok(())
+    }));
+    lifecycle.on_shutdown(Hook::new("jobs", move || async move {
+        let _ = stop.send(());
+        for task in tasks.await.unwrap_or_default() {
+            task.await?;
+        }
+        Ok(())
+    }));
+}
+
+/// How a job is enqueued beyond its kind and payload.
+#[derive(Debug, Default, Clone)]
+pub struct JobOptions {
+    /// Runs the job in this queue instead of its kind's.
+    pub queue: Option<String>,
+    /// Jobs sharing a key count against one rate limit of their queue, e.g.
+    /// the host of the endpoint a job calls.
+    pub rate_key: Option<String>,
+    /// Runs the job no earlier than this instead of right away.
+    pub run_at: Option<DateTime<Utc>>,
+}
+
+pub struct JobQueue {
+    db: PgPool,
+    settings: JobSettings,
+    clock: SharedClock,
+}
+
+impl JobQueue {
+    pub fn new(db: PgPool, settings: &JobSettings, clock: SharedClock) -> Self {
+        Self {
+            db,
+            settings: settings.clone(),
+            clock,
+        }
+    }
+
+    /// Enqueues a job to run as soon as a worker is free.
+    pub async fn enqueue(&self, kind: &str, payload: &impl Serialize) -> AppResult<Uuid> {
+        self.enqueue_with(&self.db, kind, payload).await
+    }
+
+    /// Enqueues a job to run at `run_at`.
+    pub async fn enqueue_at(&self, kind: &str, payload: &impl Serialize, run_at: DateTime<Utc>) -> AppResult<Uuid> {
+        let options = JobOptions {
+            run_at: Some(run_at),
+            ..Default::default()
+        };
+        self.enqueue_with_options(&self.db, kind, payload, &options).await
+    }
+
+    /// Enqueues a job to run once `delay` has passed.
+    pub async fn enqueue_in(&self, kind: &str, payload: &impl Serialize, delay: Duration) -> AppResult<Uuid> {
+        self.enqueue_at(kind, payload, self.clock.now() + delay).await
+    }
+
+    /// Enqueues a job through `executor`, e.g. a transaction, so it only
+    /// runs if the transaction commits.
+    pub async fn enqueue_with<'e>(
+        &self,
+        executor: impl PgExecutor<'e>,
+        kind: &str,
+        payload: &impl Serialize,
+    ) -> AppResult<Uuid> {
+        self.enqueue_with_options(executor, kind, payload, &JobOptions::default()).await
+    }
+
+    /// Enqueues a job through `executor` with `options`.
+    pub async fn enqueue_with_options<'e>(
+        &self,
+        executor: impl PgExecutor<'e>,
+        kind: &str,
+        payload: &impl Serialize,
+        options: &JobOptions,
+    ) -> AppResult<Uuid> {
+        let payload = serde_json::to_value(payload)
+            .map_err(|e| AppError::BadRequest(format!("Invalid {} job payload: {}", kind, e)))?;
+        let queue = options.queue.as_deref().unwrap_or_else(|| self.settings.queue_of(kind));
+
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO jobs (kind, queue, rate_key, payload, max_attempts, run_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id
+            "#,
+        )
+        .bind(kind)
+        .bind(queue)
+        .bind(&options.rate_key)
+        .bind(payload)
+        .bind(self.settings.max_attempts)
+        .bind(options.run_at.unwrap_or_else(|| self.clock.now()))
+        .fetch_one(executor)
+        .await
+        .context("enqueue job")
+    }
+
+    pub async fn get_job(&self, job_id: Uuid) -> AppResult<Option<Job>> {
+        sqlx::query_as::<_, Job>("SELECT * FROM jobs WHERE id = $1")
+            .bind(job_id)
+            .fetch_optional(&self.db)
+            .await
+            .entity_context("load job", job_id)
+    }
+
+    /// Moves a pending job to `run_at`. Jobs already running or finished
+    /// can't be rescheduled.
+    pub async fn reschedule(&self, job_id: Uuid, run_at: DateTime<Utc>) -> AppResult<Job> {
+        let job = sqlx::query_as("UPDATE jobs SET run_at = $2 WHERE id = $1 AND status = $3 RETURNING *")
+            .bind(job_id)
+            .bind(run_at)
+            .bind(JOB_PENDING)
+            .fetch_optional(&self.db)
+            .await
+            .entity_context("reschedule job", job_id)?;
+        match job {
+            Some(job) => Ok(job),
+            None => Err(self.not_pending(job_id).await),
+        }
+    }
+
+    /// Deletes a pending job, e.g. a scheduled purge the user averted.
+    pub async fn cancel(&self, job_id: Uuid) -> AppResult<()> {
+        let result = sqlx::query("DELETE FROM jobs WHERE id = $1 AND status = $2")
+            .bind(job_id)
+            .bind(JOB_PENDING)
+            .execute(&self.db)
+            .await
+            .entity_context("cancel job", job_id)?;
+        if result.rows_affected() == 0 {
+            return Err(self.not_pending(job_id).await);
+        }
+        Ok(())
+    }
+
+    /// Why a job couldn't be changed: it doesn't exist or it's past pending.
+    async fn not_pending(&self, job_id: Uuid) -> AppError {
+        self.unchangeable(job_id, JOB_PENDING).await
+    }
+
+    /// Why a job couldn't be changed: it doesn't exist or isn't `expected`.
+    async fn unchangeable(&self, job_id: Uuid, expected: &str) -> AppError {
+        match self.get_job(job_id).await {
+            Ok(Some(job)) => AppError::Conflict(format!("Job {} is {}, not {}", job_id, job.status, expected)),
+            Ok(None) => AppError::NotFound(format!("Job {} not found", job_id)),
+            Err(e) => e,
+        }
+    }
+
+    /// Every configured queue with whether it's paused and its unfinished
+    /// jobs, highest priority first.
+    pub async fn queues(&self) -> AppResult<Vec<JobQueueStatus>> {
+        let paused: Vec<String> = sqlx::query_scalar("SELECT name FROM job_queues WHERE paused")
+            .fetch_all(&self.db)
+            .await
+            .context("load paused job queues")?;
+        let counts: Vec<(String, String, i64)> = sqlx::query_as(
+            "SELECT queue, status, COUNT(*) FROM jobs WHERE status = ANY($1) GROUP BY queue, status",
+        )
+        .bind([JOB_PENDING, JOB_RUNNING, JOB_DEAD])
+        .fetch_all(&self.db)
+        .await
+        .context("count jobs by queue")?;
+        let count = |queue: &str, status: &str| {
+            counts
+                .iter()
+                .find(|(q, s, _)| q == queue && s == status)
+                .map_or(0, |(_, _, count)| *count)
+        };
+
+        let mut queues: Vec<JobQueueStatus> = self
+            .settings
+            .all_queues()
+            .into_iter()
+            .map(|(name, queue)| JobQueueStatus {
+                paused: paused.contains(&name),
+                pending: count(&name, JOB_PENDING),
+                running: count(&name, JOB_RUNNING),
+                dead: count(&name, JOB_DEAD),
+                name,
+                priority: queue.priority,
+                concurrency: queue.concurrency,
+                rate_limit: queue.rate_limit,
+            })
+            .collect();
+        queues.sort_by_key(|queue| std::cmp::Reverse(queue.priority));
+        Ok(queues)
+    }
+
+    /// Stops workers from claiming jobs of `queue` until it's resumed.
+    /// Running jobs finish.
+    pub async fn pause(&self, queue: &str) -> AppResult<()> {
+        self.set_paused(queue, true).await
+    }
+
+    pub async fn resume(&self, queue: &str) -> AppResult<()> {
+        self.set_paused(queue, false).await
+    }
+
+    async fn set_paused(&self, queue: &str, paused: bool) -> AppResult<()> {
+        if !self.settings.all_queues().contains_key(queue) {
+            return Err(AppError::NotFound(format!("Job queue {} not found", queue)));
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO job_queues (name, paused, updated_at) VALUES ($1, $2, $3)
+            ON CONFLICT (name) DO UPDATE SET paused = EXCLUDED.paused, updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(queue)
+        .bind(paused)
+        .bind(self.clock.now())
+        .execute(&self.db)
+        .await
+        .context("pause job queue")?;
+        Ok(())
+    }
+}
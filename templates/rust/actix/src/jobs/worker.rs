@@ -0,0 +1,312 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::{JobRateLimitSettings, JobSettings};
+use crate::errors::{AppResult, ResultExt};
+use crate::jobs::JobHandler;
+use crate::metering::Meter;
+use crate::models::job::{Job, JOB_COMPLETED, JOB_DEAD, JOB_PENDING, JOB_RUNNING};
+use crate::utils::SharedClock;
+
+/// Claims due jobs and runs up to `jobs.concurrency` of them at a time,
+/// serving queues by priority within their own concurrency and rate limits.
+///
+/// Jobs are claimed with `FOR UPDATE SKIP LOCKED`, so any number of workers
+/// across replicas can share the table. Rate limits are counted by each
+/// worker, so replicas together run up to their number times the limit.
+pub struct Worker {
+    db: PgPool,
+    settings: JobSettings,
+    clock: SharedClock,
+    handlers: HashMap<String, Arc<dyn JobHandler>>,
+    meter: Option<Arc<Meter>>,
+    /// Highest priority first.
+    queues: Vec<Queue>,
+}
+
+struct Queue {
+    name: String,
+    /// `None` when only `jobs.concurrency` limits the queue.
+    permits: Option<Arc<Semaphore>>,
+    rate_limit: Option<JobRateLimitSettings>,
+    /// Jobs claimed per rate key in its current period, and when the
+    /// period started.
+    claimed: Mutex<HashMap<String, (DateTime<Utc>, u32)>>,
+}
+
+impl Queue {
+    /// Rate keys out of jobs until their period ends.
+    fn throttled(&self, now: DateTime<Utc>) -> Vec<String> {
+        let Some(limit) = &self.rate_limit else {
+            return Vec::new();
+        };
+        let mut claimed = self.claimed.lock().unwrap();
+        claimed.retain(|_, (started, _)| now < *started + Duration::seconds(limit.period_secs));
+        claimed
+            .iter()
+            .filter(|(_, (_, count))| *count >= limit.max)
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    fn count(&self, rate_key: Option<&str>, now: DateTime<Utc>) {
+        if self.rate_limit.is_some() {
+            let mut claimed = self.claimed.lock().unwrap();
+            claimed.entry(rate_key.unwrap_or_default().to_string()).or_insert((now, 0)).1 += 1;
+        }
+    }
+}
+
+impl Worker {
+    pub fn new(db: PgPool, settings: JobSettings, clock: SharedClock) -> Self {
+        let mut queues: Vec<_> = settings.all_queues().into_iter().collect();
+        queues.sort_by_key(|(_, queue)| std::cmp::Reverse(queue.priority));
+        let queues = queues
+            .into_iter()
+            .map(|(name, queue)| Queue {
+                name,
+                permits: queue.concurrency.map(|concurrency| Arc::new(Semaphore::new(concurrency))),
+                rate_limit: queue.rate_limit,
+                claimed: Mutex::new(HashMap::new()),
+            })
+            .collect();
+
+        Self {
+            db,
+            settings,
+            clock,
+            handlers: HashMap::new(),
+            meter: None,
+            queues,
+        }
+    }
+
+    /// Runs jobs of `kind` with `handler`.
+    pub fn handler(mut self, kind: &str, handler: impl JobHandler + 'static) -> Self {
+        self.handlers.insert(kind.to_string(), Arc::new(handler));
+        self
+    }
+
+    /// Counts completed jobs as usage of the user they ran for; see
+    /// [`Meter::record_job`].
+    pub fn meter(mut self, meter: Arc<Meter>) -> Self {
+        self.meter = Some(meter);
+        self
+    }
+
+    /// Processes jobs until `shutdown` completes, then waits for the running
+    /// ones to finish.
+    pub async fn run(self, shutdown: impl Future<Output = ()>) {
+        let worker = Arc::new(self);
+        let permits = Arc::new(Semaphore::new(worker.settings.concurrency));
+        let poll_interval = std::time::Duration::from_millis(worker.settings.poll_interval_ms);
+        tokio::pin!(shutdown);
+
+        loop {
+            let permit = tokio::select! {
+                permit = permits.clone().acquire_owned() => permit.expect("semaphore is never closed"),
+                _ = &mut shutdown => break,
+            };
+
+            match worker.claim().await {
+                Ok(Some((job, queue_permit))) => {
+                    let worker = worker.clone();
+                    tokio::spawn(async move {
+                        worker.process(job).await;
+                        drop(queue_permit);
+                        drop(permit);
+                    });
+                }
+                result => {
+                    if let Err(e) = result {
+                        tracing::warn!("Failed to claim job: {}", e);
+                    }
+                    drop(permit);
+                    tokio::select! {
+                        _ = tokio::time::sleep(poll_interval) => {}
+                        _ = &mut shutdown => break,
+                    }
+                }
+            }
+        }
+
+        // Every permit back means every running job finished
+        let _ = permits.acquire_many(worker.settings.concurrency as u32).await;
+    }
+
+    /// Runs every due job, one after the other; for tests and one-off
+    /// commands. Returns how many ran.
+    pub async fn drain(&self) -> AppResult<usize> {
+        let mut processed = 0;
+        while let Some((job, _permit)) = self.claim().await? {
+            self.process(job).await;
+            processed += 1;
+        }
+        Ok(processed)
+    }
+
+    /// Claims a due job of the first queue, by priority, that isn't paused
+    /// and has room under its concurrency and rate limits.
+    async fn claim(&self) -> AppResult<Option<(Job, Option<OwnedSemaphorePermit>)>> {
+        for queue in &self.queues {
+            let permit = match &queue.permits {
+                Some(permits) => match permits.clone().try_acquire_owned() {
+                    Ok(permit) => Some(permit),
+                    Err(_) => continue,
+                },
+                None => None,
+            };
+            let now = self.clock.now();
+            if let Some(job) = self.claim_from(&queue.name, &queue.throttled(now)).await? {
+                queue.count(job.rate_key.as_deref(), now);
+                return Ok(Some((job, permit)));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn claim_from(&self, queue: &str, throttled: &[String]) -> AppResult<Option<Job>> {
+        let now = self.clock.now();
+
+        sqlx::query_as::<_, Job>(
+            r#"
+            UPDATE jobs SET status = $1, locked_at = $2, started_at = $2, attempts = attempts + 1
+            WHERE id = (
+                SELECT id FROM jobs
+                WHERE queue = $4
+                  AND ((status = $3 AND run_at <= $2) OR (status = $1 AND locked_at <= $6))
+                  AND COALESCE(rate_key, '') <> ALL($5)
+                  AND NOT EXISTS (SELECT 1 FROM job_queues WHERE name = $4 AND paused)
+                ORDER BY run_at
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING *
+            "#,
+        )
+        .bind(JOB_RUNNING)
+        .bind(now)
+        .bind(JOB_PENDING)
+        .bind(queue)
+        .bind(throttled)
+        .bind(now - Duration::seconds(self.settings.visibility_timeout_secs))
+        .fetch_optional(&self.db)
+        .await
+        .context("claim job")
+    }
+
+    async fn process(&self, job: Job) {
+        let running = metrics::gauge!("jobs_running", "queue" => job.queue.clone());
+        running.increment(1.0);
+        let started = Instant::now();
+        let outcome = self.execute(&job).await;
+        running.decrement(1.0);
+        metrics::histogram!("job_duration_seconds", "queue" => job.queue.clone())
+            .record(started.elapsed().as_secs_f64());
+        metrics::counter!("jobs_processed_total", "queue" => job.queue.clone(), "outcome" => outcome).increment(1);
+    }
+
+    /// Runs `job` and records its outcome: `completed`, `retried` or `dead`.
+    async fn execute(&self, job: &Job) -> &'static str {
+        // Claimed again after every attempt was lost with its worker
+        if job.attempts > job.max_attempts {
+            tracing::error!(job_id = %job.id, kind = %job.kind, "Job lost with its worker too often");
+            self.finish(job, JOB_DEAD, Some("lost with its worker after every attempt".to_string())).await;
+            return JOB_DEAD;
+        }
+
+        let result = match self.handlers.get(&job.kind) {
+            Some(handler) => handler.handle(job).await,
+            None => {
+                tracing::error!(job_id = %job.id, kind = %job.kind, "No handler for job kind");
+                self.finish(job, JOB_DEAD, Some(format!("no handler for job kind {}", job.kind)))
+                    .await;
+                return JOB_DEAD;
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                self.finish(job, JOB_COMPLETED, None).await;
+                if let Some(meter) = &self.meter {
+                    meter.record_job(job);
+                }
+                JOB_COMPLETED
+            }
+            Err(e) if job.attempts >= job.max_attempts => {
+                tracing::error!(job_id = %job.id, kind = %job.kind, attempts = job.attempts, "Job failed for good: {}", e);
+                self.finish(job, JOB_DEAD, Some(e.to_string())).await;
+                JOB_DEAD
+            }
+            Err(e) => {
+                tracing::warn!(job_id = %job.id, kind = %job.kind, attempts = job.attempts, "Job failed, retrying: {}", e);
+                self.retry(job, e.to_string()).await;
+                "retried"
+            }
+        }
+    }
+
+    async fn finish(&self, job: &Job, status: &str, error: Option<String>) {
+        let result = sqlx::query(
+            r#"
+            UPDATE jobs SET status = $2, locked_at = NULL, finished_at = $3, last_error = COALESCE($4, last_error)
+            WHERE id = $1 AND locked_at = $5
+            "#,
+        )
+        .bind(job.id)
+        .bind(status)
+        .bind(self.clock.now())
+        .bind(error)
+        .bind(job.locked_at)
+        .execute(&self.db)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => reclaimed(job),
+            Ok(_) => {}
+            Err(e) => tracing::error!(job_id = %job.id, "Failed to record job outcome: {}", e),
+        }
+    }
+
+    async fn retry(&self, job: &Job, error: String) {
+        let run_at = self.clock.now() + self.backoff(job.attempts);
+        let result = sqlx::query(
+            "UPDATE jobs SET status = $2, locked_at = NULL, run_at = $3, last_error = $4 WHERE id = $1 AND locked_at = $5",
+        )
+        .bind(job.id)
+        .bind(JOB_PENDING)
+        .bind(run_at)
+        .bind(error)
+        .bind(job.locked_at)
+        .execute(&self.db)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => reclaimed(job),
+            Ok(_) => {}
+            Err(e) => tracing::error!(job_id = %job.id, "Failed to reschedule job: {}", e),
+        }
+    }
+
+    /// `retry_base_secs` doubled per attempt, capped at `retry_max_secs`.
+    fn backoff(&self, attempts: i32) -> Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 30) as u32;
+        let secs = self
+            .settings
+            .retry_base_secs
+            .saturating_mul(1 << exponent)
+            .min(self.settings.retry_max_secs);
+        Duration::seconds(secs as i64)
+    }
+}
+
+/// The job ran past `jobs.visibility_timeout_secs` and was claimed again;
+/// the outcome of the later run counts.
+fn reclaimed(job: &Job) {
+    tracing::warn!(job_id = %job.id, kind = %job.kind, "Job outlived its visibility timeout and was claimed again");
+}
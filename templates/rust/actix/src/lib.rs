@@ -0,0 +1,345 @@
+use object_store::ObjectStore;
+use platform_auth::workload::WorkloadVerifier;
+use platform_core::lifecycle::Draining;
+use platform_observability::log_level::LogLevel;
+use platform_observability::runtime_stats::Runtimes;
+use std::sync::Arc;
+
+// Lets `#[cached]` and `#[authorize]` refer to this crate the same way
+// inside it and from tests
+extern crate self as actix_template;
+
+pub mod analytics;
+pub mod archive;
+pub mod cache;
+pub mod commands;
+pub mod concurrency_limit;
+pub mod config;
+pub mod consumer;
+pub mod db;
+pub mod device;
+pub mod dry_run;
+pub mod errors;
+pub mod egress;
+pub mod factories;
+pub mod handlers;
+pub mod health;
+#[cfg(feature = "http3")]
+pub mod http3;
+pub mod jobs;
+pub mod listeners;
+pub mod mail;
+pub mod manifest;
+pub mod materialized_views;
+pub mod metering;
+pub mod middleware;
+pub mod models;
+pub mod object_storage;
+pub mod olap;
+pub mod openapi;
+pub mod partitions;
+pub mod push;
+pub mod rate_limit;
+pub mod retention;
+pub mod runtime;
+pub mod runtime_stats;
+pub mod saga;
+pub mod security;
+pub mod services;
+pub mod sms;
+pub mod telemetry;
+pub mod templates;
+pub mod token_delivery;
+pub mod utils;
+pub mod webauthn;
+
+use crate::analytics::AnalyticsExporter;
+use crate::cache::ResponseCache;
+use crate::concurrency_limit::AdaptiveLimiter;
+use crate::config::Settings;
+use crate::egress::EgressPolicy;
+use crate::health::{DatabaseCheck, HealthRegistry};
+use crate::jobs::JobQueue;
+use crate::materialized_views::MaterializedViews;
+use crate::metering::Meter;
+use crate::olap::EventBuffer;
+use crate::partitions::PartitionManager;
+use crate::rate_limit::RateLimits;
+use crate::retention::RetentionEngine;
+use crate::saga::deletion_fanout::{self, DeletionFanout};
+use crate::saga::{account_deletion, SagaEngine};
+use crate::services::{
+    ApiTokenService, AuditService, AuthThrottleService, EmailVerificationService, MagicLinkService, OperationService, PasskeyService, PasswordResetService, PhoneOtpService,
+    PgUserCommands, PgUserQueries, PushService, RoleService, SessionService, StatsService, UserCommands, UserQueries,
+};
+use crate::utils::{JwtKeys, SharedClock, SigningKeys};
+
+pub struct AppState {
+    pub db: sqlx::PgPool,
+    pub settings: Settings,
+    pub jwt_keys: JwtKeys,
+    pub signing_keys: SigningKeys,
+    /// Reads of users, which may be served from a replica or a cache.
+    pub user_queries: Arc<dyn UserQueries>,
+    /// Changes to users, on the primary.
+    pub user_commands: Arc<dyn UserCommands>,
+    pub role_service: Arc<RoleService>,
+    pub api_token_service: Arc<ApiTokenService>,
+    pub audit_service: Arc<AuditService>,
+    pub session_service: Arc<SessionService>,
+    pub auth_throttle_service: Arc<AuthThrottleService>,
+    pub phone_otp_service: Arc<PhoneOtpService>,
+    pub magic_link_service: Arc<MagicLinkService>,
+    /// Confirms new accounts' email addresses.
+    pub email_verification_service: Arc<EmailVerificationService>,
+    /// Resets forgotten passwords.
+    pub password_reset_service: Arc<PasswordResetService>,
+    pub passkey_service: Arc<PasskeyService>,
+    /// Background jobs, run by `jobs::worker`.
+    pub job_queue: Arc<JobQueue>,
+    pub push_service: Arc<PushService>,
+    pub operation_service: Arc<OperationService>,
+    /// Multi-step processes such as account deletion, run as jobs.
+    pub saga_engine: Arc<SagaEngine>,
+    /// Deletions waiting for downstream services to confirm.
+    pub deletion_fanout: Arc<DeletionFanout>,
+    /// Readiness of the database and downstream services.
+    pub health: Arc<HealthRegistry>,
+    /// Set on SIGTERM, failing readiness while requests drain.
+    pub draining: Draining,
+    /// Responses of `#[cached]` handlers.
+    pub response_cache: Arc<ResponseCache>,
+    /// Where archives are written.
+    pub object_store: Arc<dyn ObjectStore>,
+    /// Creates and retires partitions of high-volume tables.
+    pub partition_manager: Arc<PartitionManager>,
+    /// Scheduled Parquet exports for the data platform.
+    pub analytics: Arc<AnalyticsExporter>,
+    /// Request and domain events awaiting a write to the OLAP store.
+    pub olap_events: Arc<EventBuffer>,
+    /// Billable usage per user and API key.
+    pub meter: Arc<Meter>,
+    /// Deletes rows whose record type's TTL has passed.
+    pub retention: Arc<RetentionEngine>,
+    /// Refreshes the views behind `/api/v1/stats`.
+    pub materialized_views: Arc<MaterializedViews>,
+    /// User counts for `/api/v1/stats`, cached within their staleness budget.
+    pub stats_service: Arc<StatsService>,
+    /// Vets user-supplied URLs before the server fetches them.
+    pub egress: EgressPolicy,
+    /// The tracing filter, changeable through `/admin/log-level`.
+    pub log_level: Arc<LogLevel>,
+    /// Runtimes serving requests, for `/admin/runtime`.
+    pub runtimes: Runtimes,
+    /// Adaptive limit on requests to the database-heavy scopes.
+    pub concurrency_limiter: Arc<AdaptiveLimiter>,
+    /// Per-client request rates, applied to all of `/api/v1`.
+    pub rate_limits: Arc<RateLimits>,
+    /// Verifies other services' Kubernetes and SPIFFE tokens; `None` unless
+    /// `workload_identity.issuers` are configured.
+    pub workload_verifier: Option<Arc<WorkloadVerifier>>,
+    /// Time source for expiry checks; a `MockClock` in tests.
+    pub clock: SharedClock,
+}
+
+impl AppState {
+    /// Derives keys from `settings` and builds the services on top of `db`.
+    pub async fn new(db: sqlx::PgPool, settings: Settings, clock: SharedClock) -> anyhow::Result<Self> {
+        let jwt_keys = JwtKeys::from_settings(&settings.jwt)?;
+        let signing_keys = SigningKeys::from_settings(&settings.signing, &jwt_keys, clock.clone())?;
+
+        // Every outbound HTTP client goes through the egress policy
+        let egress = EgressPolicy::new(settings.egress.clone());
+        let olap_sink = olap::from_settings(&settings.olap, &egress)?;
+        let olap_health = olap_sink.as_ref().map(|sink| sink.health_checks()).unwrap_or_default();
+        let olap_events = Arc::new(EventBuffer::new(olap_sink, settings.olap.clone()));
+        let user_queries: Arc<dyn UserQueries> = Arc::new(PgUserQueries::new(db.clone()));
+        let user_commands: Arc<dyn UserCommands> =
+            Arc::new(PgUserCommands::new(db.clone(), olap_events.clone(), clock.clone()));
+        let role_service = Arc::new(RoleService::new(db.clone(), clock.clone()));
+        let api_token_service = Arc::new(ApiTokenService::new(db.clone(), clock.clone(), signing_keys.clone()));
+        let audit_service = Arc::new(AuditService::new(db.clone(), olap_events.clone(), clock.clone()));
+        let session_service = Arc::new(SessionService::new(
+            db.clone(),
+            settings.session.clone(),
+            settings.jwt.refresh_token_expiry,
+            audit_service.clone(),
+            clock.clone(),
+        ));
+        let concurrency_limiter = Arc::new(AdaptiveLimiter::new(settings.concurrency_limit.clone()));
+        let rate_limits = Arc::new(RateLimits::from_settings(&settings.rate_limit).await?);
+        let auth_throttle_service = Arc::new(
+            AuthThrottleService::new(&settings.redis.url, settings.auth_throttle.clone()).await?,
+        );
+
+        let mailer = mail::from_settings(&settings.mail, &egress)?;
+        let sms_sender = sms::from_settings(&settings.sms, &egress)?;
+        let push_sender = push::from_settings(&settings.push, &egress)?;
+        let usage_exporter = metering::from_settings(&settings.metering, &egress)?;
+        // Downstream clients contribute their own readiness checks
+        let health = Arc::new(
+            HealthRegistry::new(settings.health.clone(), clock.clone())
+                .register(Arc::new(DatabaseCheck(db.clone())))
+                .register_all(mailer.health_checks())
+                .register_all(sms_sender.health_checks())
+                .register_all(push_sender.health_checks())
+                .register_all(olap_health)
+                .register_all(usage_exporter.as_ref().map(|exporter| exporter.health_checks()).unwrap_or_default()),
+        );
+
+        let phone_otp_service = Arc::new(PhoneOtpService::new(
+            db.clone(),
+            sms_sender,
+            settings.phone_otp.clone(),
+            &settings.email,
+            clock.clone(),
+        ));
+        let email_verification_service = Arc::new(EmailVerificationService::new(
+            db.clone(),
+            mailer.clone(),
+            signing_keys.clone(),
+            settings.email_verification.clone(),
+            settings.email.clone(),
+            clock.clone(),
+        ));
+        let password_reset_service = Arc::new(PasswordResetService::new(
+            db.clone(),
+            mailer.clone(),
+            signing_keys.clone(),
+            session_service.clone(),
+            settings.password_reset.clone(),
+            settings.email.clone(),
+            clock.clone(),
+        ));
+        let magic_link_service = Arc::new(MagicLinkService::new(
+            db.clone(),
+            mailer,
+            signing_keys.clone(),
+            settings.magic_link.clone(),
+            settings.email.clone(),
+            clock.clone(),
+        ));
+        let passkey_service = Arc::new(PasskeyService::new(
+            db.clone(),
+            webauthn::from_settings(&settings.webauthn)?,
+            settings.webauthn.clone(),
+            clock.clone(),
+        ));
+        let job_queue = Arc::new(JobQueue::new(db.clone(), &settings.jobs, clock.clone()));
+        let push_service = Arc::new(PushService::new(
+            db.clone(),
+            job_queue.clone(),
+            push_sender,
+            clock.clone(),
+        ));
+        let operation_service = Arc::new(OperationService::new(
+            db.clone(),
+            settings.operations.clone(),
+            clock.clone(),
+        ));
+        let response_cache =
+            Arc::new(ResponseCache::new(settings.cache.clone(), &settings.redis.url, clock.clone()).await?);
+        let deletion_fanout = Arc::new(DeletionFanout::new(
+            db.clone(),
+            settings.deletion_fanout.clone(),
+            deletion_fanout::from_settings(&settings.deletion_fanout, &egress)?,
+            audit_service.clone(),
+            response_cache.clone(),
+            clock.clone(),
+        ));
+        let saga_engine = Arc::new(
+            SagaEngine::new(db.clone(), job_queue.clone(), settings.sagas.clone(), clock.clone()).register(
+                account_deletion::saga(
+                    user_queries.clone(),
+                    user_commands.clone(),
+                    session_service.clone(),
+                    api_token_service.clone(),
+                    push_service.clone(),
+                    deletion_fanout.clone(),
+                ),
+            ),
+        );
+        let object_store = object_storage::from_settings(&settings.object_storage)?;
+        let partition_manager = Arc::new(PartitionManager::new(
+            db.clone(),
+            settings.partitions.clone(),
+            object_store.clone(),
+            clock.clone(),
+        ));
+        let analytics = Arc::new(AnalyticsExporter::new(
+            db.clone(),
+            settings.analytics.clone(),
+            object_store.clone(),
+            job_queue.clone(),
+            clock.clone(),
+        )?);
+        let meter = Arc::new(Meter::new(
+            db.clone(),
+            settings.metering.clone(),
+            usage_exporter,
+            clock.clone(),
+        ));
+        let retention = Arc::new(RetentionEngine::new(
+            db.clone(),
+            settings.retention.clone(),
+            job_queue.clone(),
+            clock.clone(),
+        )?);
+        let materialized_views = Arc::new(MaterializedViews::new(
+            db.clone(),
+            settings.materialized_views.clone(),
+            job_queue.clone(),
+            clock.clone(),
+        )?);
+        let stats_service = Arc::new(StatsService::new(
+            db.clone(),
+            settings.stats.clone(),
+            materialized_views.clone(),
+            clock.clone(),
+        ));
+        let log_level = Arc::new(LogLevel::new(settings.log_level.clone(), clock.clone()));
+        let workload_verifier = WorkloadVerifier::from_settings(&settings.workload_identity, clock.clone())?.map(Arc::new);
+
+        Ok(Self {
+            db,
+            settings,
+            jwt_keys,
+            signing_keys,
+            user_queries,
+            user_commands,
+            role_service,
+            api_token_service,
+            audit_service,
+            session_service,
+            auth_throttle_service,
+            phone_otp_service,
+            magic_link_service,
+            email_verification_service,
+            password_reset_service,
+            passkey_service,
+            job_queue,
+            push_service,
+            operation_service,
+            saga_engine,
+            deletion_fanout,
+            health,
+            draining: Draining::default(),
+            response_cache,
+            object_store,
+            partition_manager,
+            analytics,
+            olap_events,
+            meter,
+            retention,
+            materialized_views,
+            stats_service,
+            egress,
+            log_level,
+            runtimes: Runtimes::default(),
+            concurrency_limiter,
+            rate_limits,
+            workload_verifier,
+            clock,
+        })
+    }
+}
\ No newline at end of file
@@ -0,0 +1,283 @@
+//! The sockets listed in `server.listeners` and the servers behind them.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Server, ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::middleware::{Condition, DefaultHeaders};
+use actix_web::{web, App, HttpServer};
+use anyhow::Context;
+use config::ConfigError;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use futures_util::future;
+use socket2::{Domain, Socket, Type};
+use std::future::Future;
+use std::io;
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::{ListenerRole, ServerSettings, TlsSettings};
+use crate::handlers;
+use crate::middleware::Pipeline;
+use crate::runtime::CpuPinning;
+use crate::security;
+use crate::AppState;
+
+/// Connections queued per listener before the kernel refuses more.
+const BACKLOG: i32 = 1024;
+
+/// A bound socket, ready to be served.
+pub struct Listener {
+    pub role: ListenerRole,
+    pub socket: TcpListener,
+    pub tls: Option<rustls::ServerConfig>,
+    /// Which of `server.acceptors` serves this socket; 0 for admin
+    /// listeners.
+    pub acceptor: usize,
+    /// The UDP socket of an `http3` listener, on its first acceptor.
+    pub quic: Option<UdpSocket>,
+}
+
+/// Binds every listener, so a bad address, certificate or key stops the
+/// server before it serves anything. Public listeners are bound once per
+/// acceptor, all on the port the first one got.
+pub fn bind(settings: &ServerSettings) -> anyhow::Result<Vec<Listener>> {
+    let listeners = settings.listeners();
+    if !listeners.iter().any(|listener| listener.role == ListenerRole::Public) {
+        return Err(ConfigError::Message("server.listeners needs a public listener".to_string()).into());
+    }
+    if settings.acceptors == 0 {
+        return Err(ConfigError::Message("server.acceptors must be at least 1".to_string()).into());
+    }
+
+    let mut bound = Vec::new();
+    for listener in &listeners {
+        let mut address: SocketAddr = listener
+            .address
+            .parse()
+            .with_context(|| format!("invalid listener address {}", listener.address))?;
+        let tls = listener.tls.as_ref().map(tls_config).transpose()?;
+        if listener.http3 && (listener.role != ListenerRole::Public || tls.is_none()) {
+            let message = format!("HTTP/3 listener {} needs to be public and have tls", listener.address);
+            return Err(ConfigError::Message(message).into());
+        }
+        let (acceptors, reuse_port) = match listener.role {
+            ListenerRole::Public => (settings.acceptors, settings.reuse_port || settings.acceptors > 1),
+            ListenerRole::Admin => (1, false),
+        };
+
+        for acceptor in 0..acceptors {
+            let socket = bind_socket(address, reuse_port).with_context(|| format!("binding {}", address))?;
+            // Port 0 picks a free port; the other acceptors need the same one
+            address = socket.local_addr()?;
+            let quic = (listener.http3 && acceptor == 0)
+                .then(|| bind_quic(address).with_context(|| format!("binding {} for HTTP/3", address)))
+                .transpose()?;
+            bound.push(Listener {
+                role: listener.role,
+                socket,
+                tls: tls.clone(),
+                acceptor,
+                quic,
+            });
+        }
+    }
+    Ok(bound)
+}
+
+fn bind_socket(address: SocketAddr, reuse_port: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(address), Type::STREAM, None)?;
+    // Otherwise `[::]` also takes the port for IPv4, and a separate IPv4
+    // listener on it fails to bind
+    if address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    if reuse_port {
+        set_reuse_port(&socket)?;
+    }
+    socket.bind(&address.into())?;
+    socket.listen(BACKLOG)?;
+
+    Ok(socket.into())
+}
+
+#[cfg(feature = "http3")]
+fn bind_quic(address: SocketAddr) -> anyhow::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(address), Type::DGRAM, None)?;
+    if address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.bind(&address.into())?;
+    Ok(socket.into())
+}
+
+#[cfg(not(feature = "http3"))]
+fn bind_quic(_: SocketAddr) -> anyhow::Result<UdpSocket> {
+    Err(ConfigError::Message("HTTP/3 listeners need the http3 feature".to_string()).into())
+}
+
+#[cfg(unix)]
+fn set_reuse_port(socket: &Socket) -> io::Result<()> {
+    socket.set_reuse_port(true)
+}
+
+#[cfg(not(unix))]
+fn set_reuse_port(_: &Socket) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "server.reuse_port needs SO_REUSEPORT"))
+}
+
+/// Loads a listener's certificate chain and key.
+pub fn tls_config(settings: &TlsSettings) -> anyhow::Result<rustls::ServerConfig> {
+    let certs = CertificateDer::pem_file_iter(&settings.cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("reading TLS certificates from {}", settings.cert_path))?;
+    let key = PrivateKeyDer::from_pem_file(&settings.key_path)
+        .with_context(|| format!("reading TLS key from {}", settings.key_path))?;
+
+    rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .with_context(|| format!("loading TLS certificate {}", settings.cert_path))
+}
+
+/// Attaches listeners to an `HttpServer`; a macro since spelling out the
+/// server's type parameters takes more than the loop itself.
+macro_rules! listen {
+    ($server:expr, $listeners:expr) => {
+        $listeners.into_iter().try_fold($server, |server, listener| match listener.tls {
+            Some(tls) => server.listen_rustls_0_23(listener.socket, tls),
+            None => server.listen(listener.socket),
+        })
+    };
+}
+
+/// The public API as each worker, and the HTTP/3 endpoint, serves it.
+/// `alt_svc` advertises HTTP/3 listeners.
+pub fn api(
+    app_state: web::Data<AppState>,
+    pipeline: Pipeline,
+    alt_svc: Option<String>,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let advertise = DefaultHeaders::new().add(("Alt-Svc", alt_svc.clone().unwrap_or_default()));
+    App::new()
+        .app_data(app_state.clone())
+        .configure(security::defaults(&app_state.settings))
+        .wrap(pipeline)
+        .configure(handlers::routes)
+        .wrap(Condition::new(alt_svc.is_some(), advertise))
+}
+
+/// The `Alt-Svc` value advertising the HTTP/3 listeners among `listeners`.
+fn alt_svc(listeners: &[Listener], max_age_secs: u64) -> io::Result<Option<String>> {
+    let mut ports = Vec::new();
+    for socket in listeners.iter().filter_map(|listener| listener.quic.as_ref()) {
+        let port = socket.local_addr()?.port();
+        if !ports.contains(&port) {
+            ports.push(port);
+        }
+    }
+    let services: Vec<_> = ports
+        .iter()
+        .map(|port| format!("h3=\":{}\"; ma={}", port, max_age_secs))
+        .collect();
+    Ok((!services.is_empty()).then(|| services.join(", ")))
+}
+
+/// Serves the API on the public listeners and, if there are any, health,
+/// readiness and the `/admin` endpoints on the admin ones. Each of
+/// `server.acceptors` gets a server of its own, with its own accept loop
+/// and share of the workers. The servers ignore signals; [`run_until`]
+/// stops them.
+pub fn start(app_state: web::Data<AppState>, pipeline: Pipeline, listeners: Vec<Listener>) -> io::Result<Vec<Server>> {
+    let (mut public, admin): (Vec<_>, Vec<_>) = listeners
+        .into_iter()
+        .partition(|listener| listener.role == ListenerRole::Public);
+
+    let server = app_state.settings.server.clone();
+    let alt_svc = alt_svc(&public, server.http3_alt_svc_max_age_secs)?;
+    #[cfg(feature = "http3")]
+    for listener in &mut public {
+        if let (Some(socket), Some(tls)) = (listener.quic.take(), listener.tls.clone()) {
+            crate::http3::spawn(socket, tls, app_state.clone(), pipeline.clone(), alt_svc.clone())?;
+        }
+    }
+    let pinning = Arc::new(CpuPinning::new(&server.cpu_pinning)?);
+    let acceptors = public.iter().map(|listener| listener.acceptor + 1).max().unwrap_or(1);
+    let mut servers = Vec::new();
+    for acceptor in 0..acceptors {
+        let (sockets, rest): (Vec<_>, Vec<_>) = public.into_iter().partition(|listener| listener.acceptor == acceptor);
+        public = rest;
+        // Spreads the workers evenly, and every acceptor needs at least one
+        let workers = (server.workers / acceptors + usize::from(acceptor < server.workers % acceptors)).max(1);
+
+        let app_state = app_state.clone();
+        let pipeline = pipeline.clone();
+        let pinning = pinning.clone();
+        let alt_svc = alt_svc.clone();
+        let api = HttpServer::new(move || {
+            // Called on each worker's thread, so this is the worker's runtime
+            app_state.runtimes.register_current("api");
+            pinning.pin_current();
+            api(app_state.clone(), pipeline.clone(), alt_svc.clone())
+        })
+        .workers(workers)
+        .disable_signals()
+        .shutdown_timeout(server.drain_timeout_secs);
+        let api = match server.worker_max_blocking_threads {
+            Some(threads) => api.worker_max_blocking_threads(threads),
+            None => api,
+        };
+        servers.push(listen!(api, sockets)?.run());
+    }
+
+    if !admin.is_empty() {
+        let admin_server = HttpServer::new(move || {
+            app_state.runtimes.register_current("admin");
+            App::new()
+                .app_data(app_state.clone())
+                .configure(security::defaults(&app_state.settings))
+                .wrap(pipeline.clone())
+                .configure(handlers::admin_routes)
+        })
+        .workers(1)
+        .disable_signals()
+        .shutdown_timeout(server.drain_timeout_secs);
+        servers.push(listen!(admin_server, admin)?.run());
+    }
+
+    Ok(servers)
+}
+
+/// Runs `servers` until they stop or `shutdown` resolves. Then `/ready`
+/// fails for `server.drain_delay_secs` while the listeners still accept,
+/// and once they close in-flight requests get `server.drain_timeout_secs`
+/// to finish.
+pub async fn run_until(servers: Vec<Server>, state: &AppState, shutdown: impl Future<Output = ()>) -> io::Result<()> {
+    let handles: Vec<_> = servers.iter().map(Server::handle).collect();
+    let servers = future::try_join_all(servers);
+    tokio::pin!(servers);
+
+    tokio::select! {
+        result = &mut servers => return result.map(drop),
+        _ = shutdown => {}
+    }
+
+    // The servers only make progress while polled, so drain alongside them
+    let delay = Duration::from_secs(state.settings.server.drain_delay_secs);
+    let drain = async {
+        state.draining.drain(delay).await;
+        future::join_all(handles.iter().map(|handle| handle.stop(true))).await;
+    };
+    let (result, ()) = future::join(servers, drain).await;
+    result.map(drop)
+}
@@ -0,0 +1,220 @@
+//! Outbound email behind the [`Mailer`] trait, picked by `mail.provider`:
+//! [`ConsoleMailer`] logs emails for development, [`PostmarkMailer`] sends
+//! them through Postmark's Email API and [`SmtpMailer`] through any SMTP
+//! relay.
+
+use config::ConfigError;
+use futures_util::future::BoxFuture;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use platform_core::domain::Email;
+use reqwest::Client;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::{MailProvider, MailSettings, SmtpTls};
+use crate::egress::EgressPolicy;
+use crate::errors::{AppError, AppResult, ErrorCode, RetryHint};
+use crate::health::{HealthCheck, HttpDependency};
+use crate::templates::RenderedEmail;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailMessage {
+    pub to: Email,
+    pub content: RenderedEmail,
+}
+
+pub trait Mailer: Send + Sync {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, AppResult<()>>;
+
+    /// Readiness checks of the provider this mailer calls.
+    fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+        Vec::new()
+    }
+}
+
+/// Builds the mailer selected by `mail.provider`, sending through `egress`.
+pub fn from_settings(settings: &MailSettings, egress: &EgressPolicy) -> Result<Arc<dyn Mailer>, ConfigError> {
+    match settings.provider {
+        MailProvider::Console => Ok(Arc::new(ConsoleMailer::default())),
+        MailProvider::Postmark => Ok(Arc::new(PostmarkMailer::from_settings(settings, egress)?)),
+        MailProvider::Smtp => Ok(Arc::new(SmtpMailer::from_settings(settings)?)),
+    }
+}
+
+fn required(value: &Option<String>, key: &str, provider: &str) -> Result<String, ConfigError> {
+    value
+        .clone()
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| ConfigError::Message(format!("mail.{} is required for the {} provider", key, provider)))
+}
+
+fn delivery_failed() -> AppError {
+    AppError::Unavailable {
+        message: "Email delivery failed".to_string(),
+        retry: RetryHint::after(Duration::from_secs(30)),
+    }
+    .with_code(ErrorCode::EmailDeliveryFailed)
+}
+
+/// How many emails [`ConsoleMailer`] keeps.
+const OUTBOX_CAPACITY: usize = 100;
+
+/// Logs emails instead of sending them and keeps the latest ones, so links
+/// can be read from the log in development and from
+/// [`outbox`](Self::outbox) in tests.
+#[derive(Default)]
+pub struct ConsoleMailer {
+    outbox: Mutex<VecDeque<EmailMessage>>,
+}
+
+impl ConsoleMailer {
+    /// Emails sent so far, oldest first.
+    pub fn outbox(&self) -> Vec<EmailMessage> {
+        self.outbox.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl Mailer for ConsoleMailer {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            tracing::info!(to = %message.to, subject = %message.content.subject, "Email:\n{}", message.content.text);
+
+            let mut outbox = self.outbox.lock().unwrap();
+            if outbox.len() == OUTBOX_CAPACITY {
+                outbox.pop_front();
+            }
+            outbox.push_back(message.clone());
+            Ok(())
+        })
+    }
+}
+
+pub struct PostmarkMailer {
+    client: Client,
+    api_url: String,
+    server_token: String,
+    from_address: String,
+}
+
+impl PostmarkMailer {
+    pub fn from_settings(settings: &MailSettings, egress: &EgressPolicy) -> Result<Self, ConfigError> {
+        let server_token = required(&settings.postmark_server_token, "postmark_server_token", "postmark")?;
+        let from_address = required(&settings.from_address, "from_address", "postmark")?;
+
+        egress.check_endpoint("mail.postmark_api_url", &settings.postmark_api_url)?;
+        let client = egress
+            .client_builder()
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .build()
+            .map_err(|e| ConfigError::Message(format!("failed to build the Postmark client: {}", e)))?;
+
+        Ok(Self {
+            client,
+            api_url: settings.postmark_api_url.trim_end_matches('/').to_string(),
+            server_token,
+            from_address,
+        })
+    }
+}
+
+impl Mailer for PostmarkMailer {
+    fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+        vec![Arc::new(HttpDependency::new("postmark", self.client.clone(), &self.api_url))]
+    }
+
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(format!("{}/email", self.api_url))
+                .header("X-Postmark-Server-Token", &self.server_token)
+                .json(&json!({
+                    "From": self.from_address,
+                    "To": message.to,
+                    "Subject": message.content.subject,
+                    "HtmlBody": message.content.html,
+                    "TextBody": message.content.text,
+                    "MessageStream": "outbound",
+                }))
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+
+            response.map(|_| ()).map_err(|e| {
+                tracing::warn!("Postmark rejected email: {}", e);
+                delivery_failed()
+            })
+        })
+    }
+}
+
+/// Sends multipart (plain text and HTML) emails through an SMTP relay.
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn from_settings(settings: &MailSettings) -> Result<Self, ConfigError> {
+        let host = required(&settings.smtp_host, "smtp_host", "smtp")?;
+        let from = required(&settings.from_address, "from_address", "smtp")?
+            .parse::<Mailbox>()
+            .map_err(|e| ConfigError::Message(format!("mail.from_address is not a valid mailbox: {}", e)))?;
+
+        let builder = match settings.smtp_tls {
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host),
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&host),
+            SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&host)),
+        }
+        .map_err(|e| ConfigError::Message(format!("failed to build the SMTP transport: {}", e)))?;
+        let builder = builder
+            .port(settings.smtp_port)
+            .timeout(Some(Duration::from_secs(settings.timeout_secs)));
+        let builder = match (&settings.smtp_username, &settings.smtp_password) {
+            (Some(username), Some(password)) => builder.credentials(Credentials::new(username.clone(), password.clone())),
+            (None, None) => builder,
+            _ => {
+                return Err(ConfigError::Message(
+                    "mail.smtp_username and mail.smtp_password must be set together".to_string(),
+                ))
+            }
+        };
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let to = message.to.as_str().parse::<Mailbox>().map_err(|e| {
+                tracing::warn!("Email address not accepted for SMTP: {}", e);
+                AppError::ValidationError("Invalid email address".to_string())
+            })?;
+            let email = Message::builder()
+                .from(self.from.clone())
+                .to(to)
+                .subject(&message.content.subject)
+                .multipart(MultiPart::alternative_plain_html(
+                    message.content.text.clone(),
+                    message.content.html.clone(),
+                ))
+                .map_err(|e| {
+                    tracing::error!("Failed to build email: {}", e);
+                    AppError::InternalServerError
+                })?;
+
+            self.transport.send(email).await.map(|_| ()).map_err(|e| {
+                tracing::warn!("SMTP relay rejected email: {}", e);
+                delivery_failed()
+            })
+        })
+    }
+}
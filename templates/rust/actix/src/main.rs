@@ -1,48 +1,55 @@
-use actix_cors::Cors;
-use actix_web::{middleware::Logger, web, App, HttpServer};
+use actix_web::web;
 use anyhow::Result;
 use dotenv::dotenv;
+use platform_core::lifecycle::{shutdown_signal, Hook, Lifecycle};
+use platform_observability::{log_level, otel};
 use sqlx::postgres::PgPoolOptions;
 use std::sync::Arc;
-use tracing::{info, Level};
-use tracing_subscriber::FmtSubscriber;
+use tracing::info;
 
-mod config;
-mod errors;
-mod handlers;
-mod middleware;
-mod models;
-mod services;
-mod utils;
+use actix_template::{analytics, cache, commands, db, jobs, listeners, materialized_views, metering, olap, partitions, retention, runtime, telemetry};
+use actix_template::saga::deletion_fanout;
+use actix_template::config::Settings;
+use actix_template::middleware::Pipeline;
+use actix_template::utils::SystemClock;
+use actix_template::AppState;
 
-use crate::config::Settings;
-use crate::handlers::{health, users};
-use crate::middleware::{auth::AuthMiddleware, request_id::RequestId};
-use crate::services::user_service::UserService;
-
-pub struct AppState {
-    pub db: sqlx::PgPool,
-    pub settings: Settings,
-    pub user_service: Arc<UserService>,
-}
-
-#[actix_web::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     // Load environment variables
     dotenv().ok();
 
-    // Initialize tracing
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(Level::INFO)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber)
-        .expect("setting default subscriber failed");
+    // Run administrative commands instead of the server when requested, on
+    // a runtime of their own since they're not worth tuning
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let commands_runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    if let Some(result) = commands_runtime.block_on(commands::run(&args)) {
+        return result;
+    }
+    drop(commands_runtime);
 
-    // Load configuration
+    // Load configuration, then serve on the runtime it describes
     let settings = Settings::new()?;
-    let bind_address = format!("{}:{}", settings.server.host, settings.server.port);
+    runtime::system(&settings.runtime)?.block_on(serve(settings))
+}
 
-    info!("Starting server at {}", bind_address);
+async fn serve(settings: Settings) -> Result<()> {
+    // Tracing with the configured filter, exporting spans if enabled
+    log_level::init(&settings.log_level, &settings.otel)?;
+
+    let pipeline = Pipeline::from_settings(&settings)?;
+    let listeners = listeners::bind(&settings.server)?;
+
+    for listener in listeners.iter().filter(|listener| listener.acceptor == 0) {
+        let scheme = if listener.tls.is_some() { "https" } else { "http" };
+        info!("Starting {:?} listener at {}://{}", listener.role, scheme, listener.socket.local_addr()?);
+        if let Some(quic) = &listener.quic {
+            info!("Starting HTTP/3 listener at https://{} (UDP)", quic.local_addr()?);
+        }
+    }
+    if settings.server.acceptors > 1 {
+        info!("Accepting public connections on {} SO_REUSEPORT sockets each", settings.server.acceptors);
+    }
+    info!("Middleware: {}", pipeline.names().join(" -> "));
 
     // Create database pool
     let db_pool = PgPoolOptions::new()
@@ -51,56 +58,42 @@ async fn main() -> Result<()> {
         .await?;
 
     // Run migrations
-    sqlx::migrate!("./migrations").run(&db_pool).await?;
-
-    // Initialize services
-    let user_service = Arc::new(UserService::new(db_pool.clone()));
+    db::run_migrations(&db_pool, &settings.database).await?;
 
     // Create app state
-    let app_state = web::Data::new(AppState {
-        db: db_pool,
-        settings: settings.clone(),
-        user_service,
+    let app_state = web::Data::new(AppState::new(db_pool, settings.clone(), Arc::new(SystemClock)).await?);
+    app_state.runtimes.register_current("main");
+
+    // Hooks run around serving; shutdown hooks once the servers have
+    // finished in-flight requests
+    let mut lifecycle = Lifecycle::new(&settings.lifecycle);
+    let state = app_state.clone().into_inner();
+    cache::register_hooks(&mut lifecycle, &state);
+    jobs::register_hooks(&mut lifecycle, &state);
+    partitions::register_hooks(&mut lifecycle, &state);
+    analytics::register_hooks(&mut lifecycle, &state);
+    olap::register_hooks(&mut lifecycle, &state);
+    metering::register_hooks(&mut lifecycle, &state);
+    retention::register_hooks(&mut lifecycle, &state);
+    materialized_views::register_hooks(&mut lifecycle, &state);
+    deletion_fanout::register_hooks(&mut lifecycle, &state);
+    telemetry::register_hooks(&mut lifecycle, &state)?;
+    let db = app_state.db.clone();
+    let close_database = Hook::new("database", move || async move {
+        db.close().await;
+        Ok(())
     });
+    lifecycle.on_shutdown(close_database.order(100));
+    lifecycle.start().await?;
 
-    // Start HTTP server
-    HttpServer::new(move || {
-        let cors = Cors::default()
-            .allow_any_origin()
-            .allow_any_method()
-            .allow_any_header()
-            .max_age(3600);
+    // Start the HTTP servers; on SIGINT or SIGTERM they drain, then stop
+    let servers = listeners::start(app_state.clone(), pipeline, listeners)?;
+    lifecycle.ready().await;
+    let result = listeners::run_until(servers, &app_state, shutdown_signal()).await;
 
-        App::new()
-            .app_data(app_state.clone())
-            .wrap(cors)
-            .wrap(Logger::default())
-            .wrap(RequestId::new())
-            .wrap(tracing_actix_web::TracingLogger::default())
-            .service(
-                web::scope("/api/v1")
-                    .service(health::health_check)
-                    .service(health::readiness_check)
-                    .service(
-                        web::scope("/users")
-                            .wrap(AuthMiddleware)
-                            .service(users::get_users)
-                            .service(users::get_user)
-                            .service(users::create_user)
-                            .service(users::update_user)
-                            .service(users::delete_user),
-                    )
-                    .service(
-                        web::scope("/auth")
-                            .service(users::login)
-                            .service(users::register)
-                            .service(users::refresh_token),
-                    ),
-            )
-    })
-    .bind(&bind_address)?
-    .run()
-    .await?;
+    lifecycle.shutdown().await;
+    tokio::task::spawn_blocking(otel::shutdown).await?;
+    result?;
 
     Ok(())
 }
\ No newline at end of file
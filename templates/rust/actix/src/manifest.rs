@@ -0,0 +1,42 @@
+//! What this service was built from, so the platform can spot services
+//! generated from outdated templates. Features and the API hash are filled
+//! in by `build.rs`.
+
+use serde::{Deserialize, Serialize};
+
+pub const TEMPLATE_NAME: &str = "rust/actix";
+/// Bumped with every release of the template; generated services keep the
+/// version they were generated from.
+pub const TEMPLATE_VERSION: &str = "2.0.0";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub template: String,
+    pub template_version: String,
+    /// The crate's own name and version.
+    pub service: String,
+    pub version: String,
+    /// Cargo features the binary was built with.
+    pub features: Vec<String>,
+    /// SHA-256 over the handler and model sources. It matches the
+    /// template's own until either the service's API or the template
+    /// changes.
+    pub api_hash: String,
+}
+
+impl Manifest {
+    pub fn current() -> Self {
+        Self {
+            template: TEMPLATE_NAME.to_string(),
+            template_version: TEMPLATE_VERSION.to_string(),
+            service: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            features: env!("TEMPLATE_FEATURES")
+                .split(',')
+                .filter(|feature| !feature.is_empty())
+                .map(str::to_string)
+                .collect(),
+            api_hash: env!("TEMPLATE_API_HASH").to_string(),
+        }
+    }
+}
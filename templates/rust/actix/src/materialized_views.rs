@@ -0,0 +1,311 @@
+//! Refreshes of the materialized views holding expensive aggregates, such
+//! as signups and active users per day, served by `/api/v1/stats/{view}`.
+//!
+//! Views are created by migrations (see
+//! `migrations/025_create_stats_views.sql`) with a unique index, and listed
+//! under `materialized_views.views` with how often they're refreshed. With
+//! `materialized_views.enabled`, the server looks for views due every
+//! `materialized_views.poll_interval_secs` and enqueues a [`REFRESH_JOB`]
+//! for each, once per `interval_secs`. The job runs
+//! `REFRESH MATERIALIZED VIEW CONCURRENTLY`, so readers keep seeing the
+//! previous rows until it completes, and records when it finished, how long
+//! it took and any error in `materialized_view_refreshes`. Durations are
+//! recorded in `materialized_view_refresh_seconds{view}`.
+//!
+//! ```toml
+//! [materialized_views.views.daily_signups]
+//! interval_secs = 3600
+//! order_by = "day DESC"
+//! ```
+
+use chrono::{DateTime, Duration, Utc};
+use config::ConfigError;
+use futures_util::future::BoxFuture;
+use platform_core::lifecycle::{Hook, Lifecycle};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::oneshot;
+
+use crate::config::MaterializedViewSettings;
+use crate::errors::{AppError, AppResult, ResultExt};
+use crate::jobs::{JobHandler, JobQueue, Worker};
+use crate::models::job::Job;
+use crate::utils::SharedClock;
+use crate::AppState;
+
+pub const REFRESH_JOB: &str = "materialized_views.refresh";
+
+struct View {
+    interval: Duration,
+    order_by: Option<String>,
+}
+
+/// A view's rows, as of its last refresh.
+#[derive(Debug, Clone, Serialize)]
+pub struct ViewRows {
+    pub view: String,
+    /// `None` until the server has refreshed the view; migrations populate
+    /// it when they create it.
+    pub refreshed_at: Option<DateTime<Utc>>,
+    pub rows: Vec<Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshPayload {
+    pub view: String,
+}
+
+pub struct MaterializedViews {
+    db: PgPool,
+    settings: MaterializedViewSettings,
+    views: BTreeMap<String, View>,
+    job_queue: Arc<JobQueue>,
+    clock: SharedClock,
+}
+
+impl MaterializedViews {
+    pub fn new(
+        db: PgPool,
+        settings: MaterializedViewSettings,
+        job_queue: Arc<JobQueue>,
+        clock: SharedClock,
+    ) -> Result<Self, ConfigError> {
+        let views = settings
+            .views
+            .iter()
+            .map(|(name, view)| {
+                // Names end up in statements, which can't bind them
+                if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+                    return Err(ConfigError::Message(format!(
+                        "materialized view {} must be named in lowercase letters, digits and underscores",
+                        name
+                    )));
+                }
+                if view.interval_secs <= 0 {
+                    return Err(ConfigError::Message(format!(
+                        "materialized view {} needs a positive interval_secs",
+                        name
+                    )));
+                }
+                let view = View {
+                    interval: Duration::seconds(view.interval_secs),
+                    order_by: view.order_by.clone(),
+                };
+                Ok((name.clone(), view))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            db,
+            settings,
+            views,
+            job_queue,
+            clock,
+        })
+    }
+
+    /// Enqueues a refresh of every view that is due and returns their names.
+    /// Views are due when first configured and `interval_secs` after each
+    /// time they were scheduled.
+    pub async fn schedule_due(&self) -> AppResult<Vec<String>> {
+        let now = self.clock.now();
+        let mut scheduled = Vec::new();
+
+        for (name, view) in &self.views {
+            let mut tx = self.db.begin().await.context("begin transaction")?;
+            sqlx::query(
+                "INSERT INTO materialized_view_refreshes (view_name, next_run_at) VALUES ($1, $2) ON CONFLICT (view_name) DO NOTHING",
+            )
+            .bind(name)
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .entity_context("register materialized view", name)?;
+
+            let claimed = sqlx::query(
+                r#"
+                UPDATE materialized_view_refreshes SET next_run_at = $3, updated_at = $2
+                WHERE view_name = $1 AND next_run_at <= $2
+                "#,
+            )
+            .bind(name)
+            .bind(now)
+            .bind(now + view.interval)
+            .execute(&mut *tx)
+            .await
+            .entity_context("claim materialized view", name)?
+            .rows_affected()
+                == 1;
+
+            if claimed {
+                let payload = RefreshPayload { view: name.clone() };
+                self.job_queue.enqueue_with(&mut *tx, REFRESH_JOB, &payload).await?;
+                scheduled.push(name.clone());
+            }
+            tx.commit().await.context("commit transaction")?;
+        }
+
+        Ok(scheduled)
+    }
+
+    /// Calls [`schedule_due`](Self::schedule_due) every
+    /// `materialized_views.poll_interval_secs` until `shutdown` completes.
+    pub async fn schedule_until(&self, shutdown: impl Future<Output = ()>) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(self.settings.poll_interval_secs));
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.schedule_due().await {
+                        tracing::warn!("Failed to schedule materialized view refreshes: {}", e);
+                    }
+                }
+                _ = &mut shutdown => break,
+            }
+        }
+    }
+
+    /// Refreshes `view` without blocking its readers and records the
+    /// outcome in `materialized_view_refreshes`.
+    pub async fn refresh(&self, name: &str) -> AppResult<()> {
+        self.view(name)?;
+        let started = Instant::now();
+
+        let result = sqlx::query(&format!(r#"REFRESH MATERIALIZED VIEW CONCURRENTLY "{}""#, name))
+            .execute(&self.db)
+            .await
+            .entity_context("refresh materialized view", name);
+        let elapsed = started.elapsed();
+
+        match result {
+            Ok(_) => {
+                metrics::histogram!("materialized_view_refresh_seconds", "view" => name.to_string())
+                    .record(elapsed.as_secs_f64());
+                sqlx::query(
+                    r#"
+                    INSERT INTO materialized_view_refreshes (view_name, next_run_at, last_refreshed_at, last_duration_ms)
+                    VALUES ($1, $2, $2, $3)
+                    ON CONFLICT (view_name) DO UPDATE
+                    SET last_refreshed_at = $2, last_duration_ms = $3, last_error = NULL, updated_at = $2
+                    "#,
+                )
+                .bind(name)
+                .bind(self.clock.now())
+                .bind(elapsed.as_millis() as i64)
+                .execute(&self.db)
+                .await
+                .entity_context("record materialized view refresh", name)?;
+                tracing::info!("Refreshed materialized view {} in {:?}", name, elapsed);
+                Ok(())
+            }
+            Err(e) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO materialized_view_refreshes (view_name, next_run_at, last_error, updated_at)
+                    VALUES ($1, $3, $2, $3)
+                    ON CONFLICT (view_name) DO UPDATE SET last_error = $2, updated_at = $3
+                    "#,
+                )
+                .bind(name)
+                .bind(e.source_chain())
+                .bind(self.clock.now())
+                .execute(&self.db)
+                .await
+                .entity_context("record materialized view refresh", name)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Up to `limit` rows of `view`, in its configured order.
+    pub async fn rows(&self, name: &str, limit: i64) -> AppResult<ViewRows> {
+        let view = self.view(name)?;
+        let order_by = view
+            .order_by
+            .as_deref()
+            .map(|order_by| format!("ORDER BY {}", order_by))
+            .unwrap_or_default();
+
+        let rows: Vec<Value> = sqlx::query_scalar(&format!(
+            r#"SELECT to_jsonb(v) FROM "{}" v {} LIMIT $1"#,
+            name, order_by
+        ))
+        .bind(limit)
+        .fetch_all(&self.db)
+        .await
+        .entity_context("read materialized view", name)?;
+
+        Ok(ViewRows {
+            view: name.to_string(),
+            refreshed_at: self.refreshed_at(name).await?,
+            rows,
+        })
+    }
+
+    /// When the server last refreshed `view`; `None` if it hasn't yet.
+    pub async fn refreshed_at(&self, name: &str) -> AppResult<Option<DateTime<Utc>>> {
+        self.view(name)?;
+        let refreshed_at =
+            sqlx::query_scalar("SELECT last_refreshed_at FROM materialized_view_refreshes WHERE view_name = $1")
+                .bind(name)
+                .fetch_optional(&self.db)
+                .await
+                .entity_context("read materialized view refresh", name)?
+                .flatten();
+        Ok(refreshed_at)
+    }
+
+    fn view(&self, name: &str) -> AppResult<&View> {
+        self.views
+            .get(name)
+            .ok_or_else(|| AppError::NotFound(format!("Materialized view {} is not configured", name)))
+    }
+}
+
+/// Schedules refreshes from startup until shutdown. Nothing when
+/// `materialized_views.enabled` is off; the refreshes themselves run as
+/// jobs.
+pub fn register_hooks(lifecycle: &mut Lifecycle, state: &Arc<AppState>) {
+    if !state.settings.materialized_views.enabled {
+        return;
+    }
+
+    let views = state.materialized_views.clone();
+    let (stop, stopped) = oneshot::channel::<()>();
+    let (started, scheduling) = oneshot::channel();
+    lifecycle.on_start(Hook::new("materialized_views", move || async move {
+        let stopped = async move {
+            let _ = stopped.await;
+        };
+        let _ = started.send(tokio::spawn(async move { views.schedule_until(stopped).await }));
+        Ok(())
+    }));
+    lifecycle.on_shutdown(Hook::new("materialized_views", move || async move {
+        let _ = stop.send(());
+        if let Ok(scheduling) = scheduling.await {
+            scheduling.await?;
+        }
+        Ok(())
+    }));
+}
+
+struct Refresh(Arc<MaterializedViews>);
+
+impl JobHandler for Refresh {
+    fn handle<'a>(&'a self, job: &'a Job) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let payload: RefreshPayload = job.payload()?;
+            self.0.refresh(&payload.view).await
+        })
+    }
+}
+
+pub fn register_jobs(worker: Worker, state: &AppState) -> Worker {
+    worker.handler(REFRESH_JOB, Refresh(state.materialized_views.clone()))
+}
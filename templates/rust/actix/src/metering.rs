@@ -0,0 +1,486 @@
+//! Billable usage per user and API key, for the billing pipeline.
+//!
+//! With `metering.enabled`, authenticated requests, jobs run on a user's
+//! behalf (those whose payload has a `user_id`) and the bytes each user
+//! stores are counted as [`Metric`]s. Counts are kept in memory and added to
+//! the hourly `usage_records` every `metering.flush_interval_secs`; storage
+//! is measured once an hour. `metering.export_delay_secs` after an hour
+//! ends, its records are handed to the [`UsageExporter`] picked by
+//! `metering.export`. A record that changes after it was exported, e.g. by
+//! a late flush, is exported again with its new quantity, so the billing
+//! pipeline should upsert records by `id`.
+//!
+//! Admins read the records through `GET /api/v1/admin/usage`.
+
+use actix_template_macros::table;
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use config::ConfigError;
+use futures_util::future::BoxFuture;
+use platform_core::domain::UserId;
+use platform_core::lifecycle::{Hook, Lifecycle};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{FromRow, PgPool};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::config::{MeteringSettings, UsageExportProvider};
+use crate::egress::EgressPolicy;
+use crate::errors::{AppError, AppResult, ResultExt, RetryHint};
+use crate::health::{HealthCheck, HttpDependency};
+use crate::models::job::Job;
+use crate::utils::SharedClock;
+use crate::AppState;
+
+/// Records handed to the exporter at a time.
+const EXPORT_BATCH: i64 = 500;
+
+/// Tables whose rows count towards their user's stored bytes.
+const STORAGE_QUERY: &str = r#"
+    SELECT id AS user_id, pg_column_size(users.*) AS bytes FROM users
+    UNION ALL SELECT user_id, pg_column_size(api_tokens.*) FROM api_tokens
+    UNION ALL SELECT user_id, pg_column_size(push_devices.*) FROM push_devices
+    UNION ALL SELECT user_id, pg_column_size(passkeys.*) FROM passkeys
+    UNION ALL SELECT user_id, pg_column_size(operations.*) FROM operations
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Authenticated requests.
+    Requests,
+    /// Bytes of the user's rows, measured once an hour.
+    StorageBytes,
+    /// Jobs completed on the user's behalf.
+    JobsExecuted,
+}
+
+impl Metric {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Metric::Requests => "requests",
+            Metric::StorageBytes => "storage_bytes",
+            Metric::JobsExecuted => "jobs_executed",
+        }
+    }
+}
+
+/// Usage of one metric by a user, through one of their API tokens or
+/// (`api_token_id` unset) otherwise, in the hour starting at `hour`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, FromRow)]
+#[table("usage_records")]
+pub struct UsageRecord {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub api_token_id: Option<Uuid>,
+    pub metric: String,
+    pub hour: DateTime<Utc>,
+    pub quantity: i64,
+    pub exported_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Filters of [`Meter::usage`]; all optional.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct UsageQuery {
+    pub user_id: Option<UserId>,
+    pub api_token_id: Option<Uuid>,
+    pub metric: Option<Metric>,
+    /// Records of hours starting at or after this.
+    pub from: Option<DateTime<Utc>>,
+    /// Records of hours starting at or before this, so the current hour's
+    /// are included up to now.
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct UsageKey {
+    user_id: UserId,
+    api_token_id: Option<Uuid>,
+    metric: Metric,
+    hour: DateTime<Utc>,
+}
+
+pub struct Meter {
+    db: PgPool,
+    settings: MeteringSettings,
+    exporter: Option<Arc<dyn UsageExporter>>,
+    clock: SharedClock,
+    counts: Mutex<HashMap<UsageKey, i64>>,
+    /// The hour storage was last measured in.
+    storage_measured: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl Meter {
+    pub fn new(
+        db: PgPool,
+        settings: MeteringSettings,
+        exporter: Option<Arc<dyn UsageExporter>>,
+        clock: SharedClock,
+    ) -> Self {
+        Self {
+            db,
+            settings,
+            exporter,
+            clock,
+            counts: Mutex::new(HashMap::new()),
+            storage_measured: Mutex::new(None),
+        }
+    }
+
+    /// Counts `quantity` of `metric` for the current hour. Nothing unless
+    /// `metering.enabled`.
+    pub fn record(&self, user_id: UserId, api_token_id: Option<Uuid>, metric: Metric, quantity: i64) {
+        if !self.settings.enabled || quantity == 0 {
+            return;
+        }
+        let key = UsageKey {
+            user_id,
+            api_token_id,
+            metric,
+            hour: self.current_hour(),
+        };
+        *self.counts.lock().unwrap().entry(key).or_default() += quantity;
+    }
+
+    /// Counts `job` for the user in its payload's `user_id`, if it has one.
+    pub fn record_job(&self, job: &Job) {
+        let user_id = job
+            .payload
+            .get("user_id")
+            .and_then(|user_id| user_id.as_str())
+            .and_then(|user_id| user_id.parse().ok());
+        if let Some(user_id) = user_id {
+            self.record(user_id, None, Metric::JobsExecuted, 1);
+        }
+    }
+
+    /// Adds the counts so far to the usage records and returns how many
+    /// records changed. Counts are kept for the next flush if it fails.
+    pub async fn flush(&self) -> AppResult<usize> {
+        let counts = std::mem::take(&mut *self.counts.lock().unwrap());
+        if counts.is_empty() {
+            return Ok(0);
+        }
+
+        let mut user_ids = Vec::with_capacity(counts.len());
+        let mut api_token_ids = Vec::with_capacity(counts.len());
+        let mut metrics = Vec::with_capacity(counts.len());
+        let mut hours = Vec::with_capacity(counts.len());
+        let mut quantities = Vec::with_capacity(counts.len());
+        for (key, quantity) in &counts {
+            user_ids.push(Uuid::from(key.user_id));
+            api_token_ids.push(key.api_token_id);
+            metrics.push(key.metric.as_str());
+            hours.push(key.hour);
+            quantities.push(*quantity);
+        }
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO usage_records (user_id, api_token_id, metric, hour, quantity, updated_at)
+            SELECT usage.*, $6 FROM UNNEST($1::uuid[], $2::uuid[], $3::text[], $4::timestamptz[], $5::int8[]) AS usage
+            ON CONFLICT (user_id, COALESCE(api_token_id, '00000000-0000-0000-0000-000000000000'::uuid), metric, hour)
+            DO UPDATE SET quantity = usage_records.quantity + EXCLUDED.quantity, exported_at = NULL,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(user_ids)
+        .bind(api_token_ids)
+        .bind(metrics)
+        .bind(hours)
+        .bind(quantities)
+        .bind(self.clock.now())
+        .execute(&self.db)
+        .await
+        .context("record usage");
+
+        if result.is_err() {
+            let mut pending = self.counts.lock().unwrap();
+            for (key, quantity) in counts {
+                *pending.entry(key).or_default() += quantity;
+            }
+        }
+        Ok(result?.rows_affected() as usize)
+    }
+
+    /// Records the bytes every user stores for the current hour, unless
+    /// already measured this hour. Returns whether it measured.
+    pub async fn measure_storage(&self) -> AppResult<bool> {
+        let hour = self.current_hour();
+        if *self.storage_measured.lock().unwrap() == Some(hour) {
+            return Ok(false);
+        }
+
+        sqlx::query(&format!(
+            r#"
+            INSERT INTO usage_records (user_id, metric, hour, quantity, updated_at)
+            SELECT user_id, $1, $2, SUM(bytes)::int8, $3 FROM ({}) AS stored GROUP BY user_id
+            ON CONFLICT (user_id, COALESCE(api_token_id, '00000000-0000-0000-0000-000000000000'::uuid), metric, hour)
+            DO UPDATE SET quantity = EXCLUDED.quantity, updated_at = EXCLUDED.updated_at,
+                exported_at = CASE WHEN usage_records.quantity = EXCLUDED.quantity THEN usage_records.exported_at END
+            "#,
+            STORAGE_QUERY
+        ))
+        .bind(Metric::StorageBytes.as_str())
+        .bind(hour)
+        .bind(self.clock.now())
+        .execute(&self.db)
+        .await
+        .context("measure storage usage")?;
+
+        *self.storage_measured.lock().unwrap() = Some(hour);
+        Ok(true)
+    }
+
+    /// Hands the unexported records of hours that ended
+    /// `metering.export_delay_secs` ago to the exporter, marking them
+    /// exported once it accepts them. Returns how many were exported.
+    pub async fn export_closed(&self) -> AppResult<usize> {
+        let Some(exporter) = &self.exporter else {
+            return Ok(0);
+        };
+        let closed_before = self.clock.now() - Duration::hours(1) - Duration::seconds(self.settings.export_delay_secs);
+        let mut exported = 0;
+
+        loop {
+            // Locked so replicas export disjoint batches
+            let mut tx = self.db.begin().await.context("begin usage export")?;
+            let records: Vec<UsageRecord> = sqlx::query_as(
+                r#"
+                SELECT * FROM usage_records
+                WHERE exported_at IS NULL AND hour <= $1
+                ORDER BY hour, id
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+                "#,
+            )
+            .bind(closed_before)
+            .bind(EXPORT_BATCH)
+            .fetch_all(&mut *tx)
+            .await
+            .context("load usage to export")?;
+            if records.is_empty() {
+                return Ok(exported);
+            }
+
+            exporter.export(&records).await?;
+            let ids: Vec<Uuid> = records.iter().map(|record| record.id).collect();
+            sqlx::query("UPDATE usage_records SET exported_at = $2 WHERE id = ANY($1)")
+                .bind(&ids)
+                .bind(self.clock.now())
+                .execute(&mut *tx)
+                .await
+                .context("mark usage exported")?;
+            tx.commit().await.context("commit usage export")?;
+            exported += records.len();
+        }
+    }
+
+    /// Usage records matching `query`, oldest hour first.
+    pub async fn usage(&self, query: &UsageQuery) -> AppResult<Vec<UsageRecord>> {
+        sqlx::query_as(
+            r#"
+            SELECT * FROM usage_records
+            WHERE ($1::uuid IS NULL OR user_id = $1)
+              AND ($2::uuid IS NULL OR api_token_id = $2)
+              AND ($3::text IS NULL OR metric = $3)
+              AND ($4::timestamptz IS NULL OR hour >= $4)
+              AND ($5::timestamptz IS NULL OR hour <= $5)
+            ORDER BY hour, user_id, metric, api_token_id NULLS FIRST
+            "#,
+        )
+        .bind(query.user_id)
+        .bind(query.api_token_id)
+        .bind(query.metric.map(Metric::as_str))
+        .bind(query.from)
+        .bind(query.to)
+        .fetch_all(&self.db)
+        .await
+        .context("load usage")
+    }
+
+    /// Flushes, measures storage and exports every
+    /// `metering.flush_interval_secs` until `shutdown` completes, then
+    /// flushes once more.
+    pub async fn run_until(&self, shutdown: impl Future<Output = ()>) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(self.settings.flush_interval_secs));
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.flush().await {
+                        tracing::warn!("Failed to record usage: {}", e);
+                    }
+                    if let Err(e) = self.measure_storage().await {
+                        tracing::warn!("Failed to measure storage usage: {}", e);
+                    }
+                    if let Err(e) = self.export_closed().await {
+                        tracing::warn!("Failed to export usage: {}", e);
+                    }
+                }
+                _ = &mut shutdown => break,
+            }
+        }
+        if let Err(e) = self.flush().await {
+            tracing::warn!("Failed to record usage: {}", e);
+        }
+    }
+
+    fn current_hour(&self) -> DateTime<Utc> {
+        self.clock
+            .now()
+            .duration_trunc(Duration::hours(1))
+            .expect("an hour divides any timestamp")
+    }
+}
+
+/// Runs the meter from startup until shutdown, when pending counts are
+/// recorded. Nothing when `metering.enabled` is off.
+pub fn register_hooks(lifecycle: &mut Lifecycle, state: &Arc<AppState>) {
+    if !state.settings.metering.enabled {
+        return;
+    }
+
+    let meter = state.meter.clone();
+    let (stop, stopped) = oneshot::channel::<()>();
+    let (started, running) = oneshot::channel();
+    lifecycle.on_start(Hook::new("metering", move || async move {
+        let stopped = async move {
+            let _ = stopped.await;
+        };
+        let _ = started.send(tokio::spawn(async move { meter.run_until(stopped).await }));
+        Ok(())
+    }));
+    lifecycle.on_shutdown(Hook::new("metering", move || async move {
+        let _ = stop.send(());
+        if let Ok(running) = running.await {
+            running.await?;
+        }
+        Ok(())
+    }));
+}
+
+/// The billing pipeline's side of usage export.
+pub trait UsageExporter: Send + Sync {
+    /// Delivers `records`; an error leaves them to be exported again.
+    fn export<'a>(&'a self, records: &'a [UsageRecord]) -> BoxFuture<'a, AppResult<()>>;
+
+    /// Readiness checks of the service records are exported to.
+    fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+        Vec::new()
+    }
+}
+
+/// Builds the exporter selected by `metering.export`, or `None` when usage
+/// isn't exported. Webhooks are sent through `egress`.
+pub fn from_settings(
+    settings: &MeteringSettings,
+    egress: &EgressPolicy,
+) -> Result<Option<Arc<dyn UsageExporter>>, ConfigError> {
+    match settings.export {
+        UsageExportProvider::None => Ok(None),
+        UsageExportProvider::Console => Ok(Some(Arc::new(ConsoleUsageExporter::default()))),
+        UsageExportProvider::Webhook => Ok(Some(Arc::new(WebhookUsageExporter::from_settings(settings, egress)?))),
+    }
+}
+
+/// How many records [`ConsoleUsageExporter`] keeps.
+const OUTBOX_CAPACITY: usize = 1000;
+
+/// Logs records instead of exporting them and keeps the latest ones, for
+/// development and [`outbox`](Self::outbox) in tests.
+#[derive(Default)]
+pub struct ConsoleUsageExporter {
+    outbox: Mutex<VecDeque<UsageRecord>>,
+}
+
+impl ConsoleUsageExporter {
+    /// Records exported so far, oldest first.
+    pub fn outbox(&self) -> Vec<UsageRecord> {
+        self.outbox.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl UsageExporter for ConsoleUsageExporter {
+    fn export<'a>(&'a self, records: &'a [UsageRecord]) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let mut outbox = self.outbox.lock().unwrap();
+            for record in records {
+                tracing::info!(
+                    target: "metering",
+                    user_id = %record.user_id,
+                    api_token_id = ?record.api_token_id,
+                    metric = %record.metric,
+                    hour = %record.hour,
+                    quantity = record.quantity,
+                    "Usage exported"
+                );
+                if outbox.len() == OUTBOX_CAPACITY {
+                    outbox.pop_front();
+                }
+                outbox.push_back(record.clone());
+            }
+            Ok(())
+        })
+    }
+}
+
+/// POSTs `{"records": [...]}` to `metering.export_url`, with
+/// `metering.export_token` as a bearer token if set.
+pub struct WebhookUsageExporter {
+    client: Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl WebhookUsageExporter {
+    pub fn from_settings(settings: &MeteringSettings, egress: &EgressPolicy) -> Result<Self, ConfigError> {
+        let url = settings
+            .export_url
+            .clone()
+            .filter(|url| !url.is_empty())
+            .ok_or_else(|| ConfigError::Message("metering.export_url is required for the webhook exporter".to_string()))?;
+        egress.check_endpoint("metering.export_url", &url)?;
+        let client = egress
+            .client_builder()
+            .timeout(std::time::Duration::from_secs(settings.timeout_secs))
+            .build()
+            .map_err(|e| ConfigError::Message(format!("failed to build the usage export client: {}", e)))?;
+
+        Ok(Self {
+            client,
+            url,
+            token: settings.export_token.clone(),
+        })
+    }
+}
+
+impl UsageExporter for WebhookUsageExporter {
+    fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+        vec![Arc::new(HttpDependency::new("usage export", self.client.clone(), &self.url))]
+    }
+
+    fn export<'a>(&'a self, records: &'a [UsageRecord]) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let mut request = self.client.post(&self.url).json(&json!({ "records": records }));
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+
+            let response = request.send().await.and_then(|response| response.error_for_status());
+            response.map(|_| ()).map_err(|e| {
+                tracing::warn!("Usage export of {} records failed: {}", records.len(), e);
+                AppError::Unavailable {
+                    message: "Usage export failed".to_string(),
+                    retry: RetryHint::after(std::time::Duration::from_secs(60)),
+                }
+            })
+        })
+    }
+}
@@ -1,16 +1,37 @@
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    error::ErrorUnauthorized,
-    http::header::{HeaderValue, AUTHORIZATION},
-    Error, HttpMessage,
+    http::header::{HeaderName, HeaderValue, AUTHORIZATION},
+    web, Error, HttpMessage, HttpRequest,
 };
 use futures_util::future::LocalBoxFuture;
+use platform_auth::workload::WorkloadIdentity;
+use platform_core::domain::UserId;
+use serde_json::json;
 use std::{
     future::{ready, Ready},
     rc::Rc,
 };
+use uuid::Uuid;
 
-use crate::{utils::decode_jwt_token, AppState};
+use crate::{
+    errors::{AppError, AppResult, ErrorCode},
+    metering::Metric,
+    models::{
+        api_token::{ApiToken, GrantedScopes, Scope},
+        audit_event::{AuditEvent, IMPERSONATED_REQUEST},
+        role::Grants,
+        session::ClientContext,
+        user::Claims,
+    },
+    token_delivery,
+    utils::request_signing::{canonical_request, parse_signing_date, DATE_HEADER},
+    utils::{decode_jwt_token, is_api_token, is_signed_request, SignatureHeader},
+    AppState,
+};
+
+/// Set on responses to requests made with an impersonation token, to the
+/// acting admin's id.
+pub const IMPERSONATED_BY_HEADER: &str = "x-impersonated-by";
 
 pub struct AuthMiddleware;
 
@@ -49,37 +70,222 @@ where
 
     forward_ready!(service);
 
-    fn call(&self, req: ServiceRequest) -> Self::Future {
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
         let service = self.service.clone();
 
         Box::pin(async move {
-            // Get authorization header
-            let auth_header = req.headers().get(AUTHORIZATION);
-            
-            if let Some(auth_value) = auth_header {
-                if let Ok(auth_str) = auth_value.to_str() {
-                    if auth_str.starts_with("Bearer ") {
-                        let token = &auth_str[7..];
-                        
-                        // Get app state to access JWT secret
-                        if let Some(app_state) = req.app_data::<actix_web::web::Data<AppState>>() {
-                            match decode_jwt_token(token, &app_state.settings.jwt.secret) {
-                                Ok(claims) => {
-                                    // Insert claims into request extensions
-                                    req.extensions_mut().insert(claims);
-                                    let res = service.call(req).await?;
-                                    return Ok(res);
-                                }
-                                Err(_) => {
-                                    return Err(ErrorUnauthorized("Invalid token"));
-                                }
-                            }
-                        }
-                    }
+            // Get app state to access JWT secret and token store
+            let app_state = req.app_data::<web::Data<AppState>>().cloned();
+
+            // Get authorization header, or the access token cookie in cookie
+            // delivery mode
+            let header = req
+                .headers()
+                .get(AUTHORIZATION)
+                .and_then(|auth_value| auth_value.to_str().ok())
+                .map(str::to_owned)
+                .or_else(|| {
+                    let settings = &app_state.as_ref()?.settings;
+                    token_delivery::access_token(settings, req.request()).map(|token| format!("Bearer {}", token))
+                });
+
+            let (Some(header), Some(app_state)) = (header, app_state) else {
+                return Err(AppError::Unauthorized.into());
+            };
+
+            // The token is the API key usage is metered under
+            let (claims, scopes, api_token_id) = if is_signed_request(&header) {
+                authenticate_signed_request(&app_state, &mut req, &header).await?
+            } else {
+                let Some(token) = header.strip_prefix("Bearer ") else {
+                    return Err(AppError::Unauthorized.into());
+                };
+
+                // Other services' platform-issued tokens carry an identity
+                // rather than a user's claims
+                if let Some(verifier) = app_state.workload_verifier.as_ref().filter(|verifier| verifier.recognizes(token)) {
+                    let identity = verifier.verify(token).await?;
+                    req.extensions_mut().insert(identity);
+                    return service.call(req).await;
                 }
+
+                if is_api_token(token) {
+                    authenticate_api_token(&app_state, token)
+                        .await
+                        .map_err(|_| AppError::Unauthorized.with_code(ErrorCode::InvalidToken))?
+                } else {
+                    // Keeps the code telling an expired token from a bad one
+                    let claims = decode_jwt_token(token, &app_state.jwt_keys, app_state.clock.as_ref())
+                        .map_err(|e| AppError::Unauthorized.with_code(e.code()))?;
+                    let scopes = if claims.guest { GrantedScopes::Guest } else { GrantedScopes::Session };
+                    (claims, scopes, None)
+                }
+            };
+
+            // Changes made while impersonating are audited before they happen
+            let actor = claims.act.as_ref().map(|act| act.sub);
+            if let Some(actor) = actor.filter(|_| !req.method().is_safe()) {
+                audit_impersonated_request(&app_state, actor, &claims, &req).await?;
             }
-            
-            Err(ErrorUnauthorized("Missing or invalid authorization header"))
+
+            app_state.meter.record(claims.sub, api_token_id, Metric::Requests, 1);
+
+            // Insert claims and granted scopes into request extensions
+            req.extensions_mut().insert(claims);
+            req.extensions_mut().insert(scopes);
+
+            let mut res = service.call(req).await?;
+            if let Some(actor) = actor {
+                res.headers_mut().insert(
+                    HeaderName::from_static(IMPERSONATED_BY_HEADER),
+                    HeaderValue::from_str(&actor.to_string()).expect("a UUID is a valid header value"),
+                );
+            }
+            Ok(res)
+        })
+    }
+}
+
+/// Records a state-changing request made with an impersonation token.
+async fn audit_impersonated_request(
+    app_state: &AppState,
+    actor: UserId,
+    claims: &Claims,
+    req: &ServiceRequest,
+) -> AppResult<()> {
+    let client = ClientContext::from_request(req.request());
+    app_state
+        .audit_service
+        .record(AuditEvent {
+            event_type: IMPERSONATED_REQUEST,
+            user_id: Some(actor),
+            ip_address: client.ip.map(|ip| ip.to_string()),
+            device: Some(client.device()),
+            metadata: json!({
+                "subject": claims.sub,
+                "method": req.method().as_str(),
+                "path": req.path(),
+            }),
         })
+        .await
+}
+
+/// Resolves a personal access token into the claims of its owner.
+async fn authenticate_api_token(
+    app_state: &AppState,
+    token: &str,
+) -> AppResult<(Claims, GrantedScopes, Option<Uuid>)> {
+    let api_token = app_state.api_token_service.authenticate(token).await?;
+    token_owner_claims(app_state, api_token).await
+}
+
+/// Verifies an HMAC-signed request and resolves it into the claims of the
+/// signing token's owner. The body is read to hash it, then put back for
+/// the handler.
+async fn authenticate_signed_request(
+    app_state: &AppState,
+    req: &mut ServiceRequest,
+    header: &str,
+) -> Result<(Claims, GrantedScopes, Option<Uuid>), Error> {
+    let invalid = || AppError::Unauthorized.with_code(ErrorCode::AuthSignatureInvalid);
+
+    let signature = SignatureHeader::parse(header).ok_or_else(invalid)?;
+    let date = req
+        .headers()
+        .get(DATE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+        .ok_or_else(invalid)?;
+
+    // Bounds how long a captured request can be replayed
+    let signed_at = parse_signing_date(&date).ok_or_else(invalid)?;
+    let skew = (app_state.clock.now() - signed_at).num_seconds().abs();
+    if skew > app_state.settings.request_signing.max_skew_secs {
+        return Err(invalid().into());
     }
-}
\ No newline at end of file
+
+    let body = req.extract::<web::Bytes>().await?;
+    let canonical = canonical_request(req.method().as_str(), req.path(), req.query_string(), &date, &body);
+    req.set_payload(body.into());
+
+    let api_token = app_state
+        .api_token_service
+        .authenticate_signed(&signature, &date, &canonical)
+        .await?;
+
+    Ok(token_owner_claims(app_state, api_token).await?)
+}
+
+/// Claims of an API token's owner, who must still be active, with the
+/// token's scopes and id.
+async fn token_owner_claims(
+    app_state: &AppState,
+    api_token: ApiToken,
+) -> AppResult<(Claims, GrantedScopes, Option<Uuid>)> {
+    let user = app_state.user_queries.get_user_by_id(api_token.user_id).await?;
+
+    if !user.is_active {
+        return Err(AppError::Forbidden.with_code(ErrorCode::AuthAccountDisabled));
+    }
+
+    let claims = Claims {
+        sub: user.id,
+        email: user.email,
+        exp: api_token
+            .expires_at
+            .map(|expires_at| expires_at.timestamp() as usize)
+            .unwrap_or(usize::MAX),
+        iat: api_token.created_at.timestamp() as usize,
+        guest: false,
+        act: None,
+        auth_time: None,
+        acr: None,
+        grants: Grants::default(),
+    };
+
+    Ok((claims, api_token.granted_scopes(), Some(api_token.id)))
+}
+
+/// Returns the caller's claims if the authenticated credential grants `scope`.
+pub fn require_scope(req: &HttpRequest, scope: Scope) -> AppResult<Claims> {
+    let extensions = req.extensions();
+    let claims = extensions.get::<Claims>().cloned().ok_or(AppError::Unauthorized)?;
+    let scopes = extensions.get::<GrantedScopes>().ok_or(AppError::Unauthorized)?;
+
+    if !scopes.allows(scope) {
+        return Err(AppError::Forbidden.with_code(ErrorCode::AuthInsufficientScope));
+    }
+
+    Ok(claims)
+}
+
+/// Returns the caller's claims if they authenticated with an interactive
+/// session rather than a personal access token or as a guest. Impersonation
+/// tokens are refused too, so support can't mint credentials or reach admin
+/// endpoints as the user.
+pub fn require_session(req: &HttpRequest) -> AppResult<Claims> {
+    let extensions = req.extensions();
+    let claims = extensions.get::<Claims>().cloned().ok_or(AppError::Unauthorized)?;
+
+    if claims.act.is_some() {
+        return Err(AppError::Forbidden);
+    }
+
+    match extensions.get::<GrantedScopes>() {
+        Some(GrantedScopes::Session) => Ok(claims),
+        Some(GrantedScopes::Token(_) | GrantedScopes::Guest) => Err(AppError::Forbidden),
+        None => Err(AppError::Unauthorized),
+    }
+}
+
+/// Returns the calling workload's identity if it was granted `role`. Users
+/// are turned away; see [`platform_auth::workload`].
+pub fn require_workload_role(req: &HttpRequest, role: &str) -> AppResult<WorkloadIdentity> {
+    let identity = req
+        .extensions()
+        .get::<WorkloadIdentity>()
+        .cloned()
+        .ok_or(AppError::Forbidden)?;
+    identity.require_role(role)?;
+    Ok(identity)
+}
@@ -0,0 +1,67 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web, Error,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use crate::AppState;
+
+/// Applies the per-IP limits of the auth throttle to every request in the
+/// wrapped scope. Per-account limits are applied by the login handler, which
+/// knows the account being targeted.
+pub struct AuthThrottle;
+
+impl<S, B> Transform<S, ServiceRequest> for AuthThrottle
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthThrottleMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthThrottleMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct AuthThrottleMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthThrottleMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let app_state = req.app_data::<web::Data<AppState>>().cloned();
+            let peer_ip = req.peer_addr().map(|addr| addr.ip());
+
+            if let (Some(app_state), Some(ip)) = (app_state, peer_ip) {
+                app_state.auth_throttle_service.hit_ip(ip).await?;
+            }
+
+            service.call(req).await
+        })
+    }
+}
@@ -0,0 +1,100 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::StatusCode,
+    web, Error,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+    time::Instant,
+};
+
+use crate::errors::{AppError, ErrorCode, Jitter, RetryHint};
+use crate::AppState;
+
+/// Rejects requests in the wrapped scope with 503 and code `OVERLOADED`
+/// while as many as the adaptive concurrency limit allows are in flight;
+/// see [`crate::concurrency_limit`]. The limit is shared by every scope it
+/// wraps, as they share the database pool.
+pub struct ConcurrencyLimit;
+
+impl<S, B> Transform<S, ServiceRequest> for ConcurrencyLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ConcurrencyLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ConcurrencyLimitMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct ConcurrencyLimitMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ConcurrencyLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let limiter = req
+                .app_data::<web::Data<AppState>>()
+                .map(|app_state| app_state.concurrency_limiter.clone())
+                .filter(|limiter| limiter.enabled());
+            let Some(limiter) = limiter else {
+                return service.call(req).await;
+            };
+
+            let Some(permit) = limiter.try_acquire() else {
+                let retry = RetryHint::after(limiter.retry_after()).with_jitter(Jitter::Full);
+                return Err(AppError::Unavailable {
+                    message: "Too many requests in flight; retry shortly".to_string(),
+                    retry,
+                }
+                .with_code(ErrorCode::Overloaded)
+                .into());
+            };
+
+            let started = Instant::now();
+            let result = service.call(req).await;
+            let overloaded = match &result {
+                Ok(response) => overloaded(response),
+                Err(_) => false,
+            };
+            permit.finish(started.elapsed(), overloaded);
+            result
+        })
+    }
+}
+
+/// Whether a response says the server ran out of capacity rather than
+/// the request being wrong.
+fn overloaded<B>(response: &ServiceResponse<B>) -> bool {
+    let pool_timed_out = response
+        .response()
+        .error()
+        .and_then(|error| error.as_error::<AppError>())
+        .is_some_and(|error| matches!(error, AppError::DatabaseError(sqlx::Error::PoolTimedOut)));
+    pool_timed_out || matches!(response.status(), StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT)
+}
@@ -0,0 +1,212 @@
+use actix_web::{
+    body::{self, BoxBody, MessageBody},
+    dev::{forward_ready, Decompress, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderMap, HeaderValue},
+    web::{Bytes, BytesMut},
+    error::InternalError,
+    mime, Error, HttpMessage, HttpResponse,
+};
+use futures_util::{future::LocalBoxFuture, StreamExt};
+use serde_json::{Map, Value};
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use crate::config::JsonNaming;
+use crate::errors::AppError;
+
+/// Renames the keys of JSON bodies between the snake_case the models
+/// serialize with and `server.json_naming`: request bodies on the way in,
+/// responses (errors included) on the way out. Values, query parameters and
+/// headers keep their names.
+///
+/// The pipeline puts it outside every other layer when the naming isn't
+/// snake_case, so errors raised by any middleware come out renamed too.
+pub struct JsonNamingTransform {
+    naming: JsonNaming,
+    max_body_bytes: usize,
+}
+
+impl JsonNamingTransform {
+    /// Request bodies over `max_body_bytes` after decompression are refused,
+    /// as the JSON extractor would.
+    pub fn new(naming: JsonNaming, max_body_bytes: usize) -> Self {
+        Self { naming, max_body_bytes }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for JsonNamingTransform
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = JsonNamingMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(JsonNamingMiddleware {
+            service: Rc::new(service),
+            naming: self.naming,
+            max_body_bytes: self.max_body_bytes,
+        }))
+    }
+}
+
+pub struct JsonNamingMiddleware<S> {
+    service: Rc<S>,
+    naming: JsonNaming,
+    max_body_bytes: usize,
+}
+
+impl<S, B> Service<ServiceRequest> for JsonNamingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let naming = self.naming;
+        let max_body_bytes = self.max_body_bytes;
+
+        Box::pin(async move {
+            if is_json(req.headers()) {
+                if let Err(error) = rename_request(&mut req, max_body_bytes).await {
+                    let (req, response) = req.error_response(error).into_parts();
+                    let response = rename_response(response.map_into_boxed_body(), naming).await;
+                    return Ok(ServiceResponse::new(req, response));
+                }
+            }
+
+            match service.call(req).await {
+                Ok(response) => {
+                    let (req, response) = response.into_parts();
+                    let response = rename_response(response.map_into_boxed_body(), naming).await;
+                    Ok(ServiceResponse::new(req, response))
+                }
+                // Raised by a middleware; passed on with its response renamed
+                Err(error) => {
+                    let cause = error.to_string();
+                    let response = rename_response(error.error_response(), naming).await;
+                    Err(InternalError::from_response(cause, response).into())
+                }
+            }
+        })
+    }
+}
+
+pub(super) fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<mime::Mime>().ok())
+        .is_some_and(|mime| mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON))
+}
+
+/// Replaces the payload with its decompressed copy, keys in snake_case.
+/// Bodies that aren't valid JSON are passed on as they are, for the
+/// extractor to reject.
+async fn rename_request(req: &mut ServiceRequest, max_body_bytes: usize) -> Result<(), AppError> {
+    let mut payload = Decompress::from_headers(req.take_payload(), req.headers());
+    let mut bytes = BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| AppError::BadRequest(e.to_string()))?;
+        if bytes.len() + chunk.len() > max_body_bytes {
+            return Err(AppError::PayloadTooLarge(format!("JSON bodies are limited to {} bytes", max_body_bytes)));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    let bytes = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(value) => Bytes::from(serde_json::to_vec(&rename_keys(value, &to_snake_case)).unwrap_or_default()),
+        Err(_) => bytes.freeze(),
+    };
+
+    let headers = req.headers_mut();
+    headers.remove(header::CONTENT_ENCODING);
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(bytes.len()));
+    req.set_payload(Payload::from(bytes));
+    Ok(())
+}
+
+async fn rename_response(response: HttpResponse, naming: JsonNaming) -> HttpResponse {
+    if !is_json(response.headers()) {
+        return response;
+    }
+
+    let (response, body) = response.into_parts();
+    let body = match body::to_bytes(body).await {
+        Ok(bytes) => match serde_json::from_slice::<Value>(&bytes) {
+            Ok(value) => Bytes::from(serde_json::to_vec(&rename_keys(value, &|key| rename(naming, key))).unwrap_or_default()),
+            Err(_) => bytes,
+        },
+        // The body already failed; nothing useful to send
+        Err(_) => Bytes::new(),
+    };
+    response.set_body(BoxBody::new(body))
+}
+
+fn rename(naming: JsonNaming, key: &str) -> String {
+    match naming {
+        JsonNaming::SnakeCase => key.to_string(),
+        JsonNaming::CamelCase => to_camel_case(key),
+    }
+}
+
+/// `value` with the keys of every object in it renamed.
+pub fn rename_keys(value: Value, rename: &dyn Fn(&str) -> String) -> Value {
+    match value {
+        Value::Object(object) => Value::Object(
+            object
+                .into_iter()
+                .map(|(key, value)| (rename(&key), rename_keys(value, rename)))
+                .collect::<Map<_, _>>(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(|value| rename_keys(value, rename)).collect()),
+        value => value,
+    }
+}
+
+/// `full_name` as `fullName`.
+pub fn to_camel_case(key: &str) -> String {
+    let mut renamed = String::with_capacity(key.len());
+    let mut upper = false;
+    for c in key.chars() {
+        if c == '_' && !renamed.is_empty() {
+            upper = true;
+        } else if upper {
+            renamed.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            renamed.push(c);
+        }
+    }
+    renamed
+}
+
+/// `fullName` as `full_name`.
+pub fn to_snake_case(key: &str) -> String {
+    let mut renamed = String::with_capacity(key.len() + 4);
+    for c in key.chars() {
+        if c.is_uppercase() {
+            if !renamed.is_empty() {
+                renamed.push('_');
+            }
+            renamed.extend(c.to_lowercase());
+        } else {
+            renamed.push(c);
+        }
+    }
+    renamed
+}
@@ -0,0 +1,78 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web, Error,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+    time::Duration,
+};
+
+use crate::errors::{AppError, ErrorCode, Jitter, RetryHint};
+use crate::AppState;
+
+/// Rejects every request in the wrapped scope with 503 and a `Retry-After`
+/// while `maintenance.enabled` is set. Health checks should stay outside
+/// the scope so orchestrators keep seeing the instance.
+pub struct Maintenance;
+
+impl<S, B> Transform<S, ServiceRequest> for Maintenance
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = MaintenanceMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(MaintenanceMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct MaintenanceMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for MaintenanceMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let settings = req
+                .app_data::<web::Data<AppState>>()
+                .map(|app_state| app_state.settings.maintenance.clone());
+
+            if let Some(settings) = settings.filter(|settings| settings.enabled) {
+                // Spread retries so clients don't all return at once
+                let retry = RetryHint::after(Duration::from_secs(settings.retry_after_secs))
+                    .with_jitter(Jitter::Equal);
+                return Err(AppError::Unavailable {
+                    message: settings.message,
+                    retry,
+                }
+                .with_code(ErrorCode::MaintenanceMode)
+                .into());
+            }
+
+            service.call(req).await
+        })
+    }
+}
@@ -1,5 +1,27 @@
 pub mod auth;
+pub mod auth_throttle;
+pub mod concurrency_limit;
+pub mod json_naming;
+pub mod maintenance;
+pub mod pipeline;
+pub mod propagation;
+pub mod rate_limiter;
+pub mod request_events;
 pub mod request_id;
+pub mod response_validation;
+pub mod roles;
+pub mod step_up;
 
 pub use auth::AuthMiddleware;
-pub use request_id::RequestId;
\ No newline at end of file
+pub use auth_throttle::AuthThrottle;
+pub use concurrency_limit::ConcurrencyLimit;
+pub use json_naming::JsonNamingTransform;
+pub use maintenance::Maintenance;
+pub use pipeline::Pipeline;
+pub use propagation::Propagation;
+pub use rate_limiter::RateLimiter;
+pub use request_events::RequestEvents;
+pub use request_id::RequestId;
+pub use response_validation::ResponseValidation;
+pub use roles::RequireRole;
+pub use step_up::RequireStepUp;
\ No newline at end of file
@@ -0,0 +1,214 @@
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    middleware::Logger,
+    Error,
+};
+use config::ConfigError;
+use futures_util::future::LocalBoxFuture;
+use platform_observability::propagation::PropagationSettings;
+use std::task::{Context, Poll};
+use tracing_actix_web::TracingLogger;
+
+use crate::config::{JsonNaming, MiddlewareSettings, SecuritySettings, Settings};
+use crate::middleware::{JsonNamingTransform, Propagation, RequestEvents, RequestId, ResponseValidation};
+use crate::security::Security;
+use crate::token_delivery;
+
+/// Pairs of middlewares where, when both are listed, the first has to wrap
+/// the second.
+const ORDERING: &[(&str, &str, &str)] = &[
+    (
+        "request_id",
+        "security",
+        "so requests it rejects are logged with their request ID",
+    ),
+    (
+        "request_id",
+        "request_events",
+        "so request events carry the request ID",
+    ),
+];
+
+type BoxedService = Box<
+    dyn Service<
+        ServiceRequest,
+        Response = ServiceResponse,
+        Error = Error,
+        Future = LocalBoxFuture<'static, Result<ServiceResponse, Error>>,
+    >,
+>;
+
+/// The middleware stack described by `server.middleware`, outermost first.
+///
+/// Build it once at startup so a bad description stops the server before it
+/// binds, then wrap every `App` in it:
+///
+/// ```ignore
+/// let pipeline = Pipeline::from_settings(&settings)?;
+/// HttpServer::new(move || App::new().wrap(pipeline.clone()).configure(handlers::routes))
+/// ```
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    middleware: Vec<MiddlewareSettings>,
+    security: SecuritySettings,
+    json_naming: JsonNaming,
+    max_json_body_bytes: usize,
+    propagation: PropagationSettings,
+}
+
+impl Pipeline {
+    /// Also refuses cookie token delivery without the CSRF check; see
+    /// [`token_delivery::check_csrf`].
+    pub fn from_settings(settings: &Settings) -> Result<Self, ConfigError> {
+        token_delivery::check_csrf(settings)?;
+        let pipeline = Self::new(settings.server.middleware.clone(), settings.security.clone())?;
+        Ok(pipeline
+            .json_naming(settings.server.json_naming, settings.server.max_json_body_bytes)
+            .propagation(settings.propagation.clone()))
+    }
+
+    /// Checks that no middleware is listed twice, `tracing` (whose span
+    /// should cover everything else) comes first, and the [`ORDERING`] rules
+    /// hold.
+    pub fn new(middleware: Vec<MiddlewareSettings>, security: SecuritySettings) -> Result<Self, ConfigError> {
+        let names: Vec<&str> = middleware.iter().map(MiddlewareSettings::name).collect();
+        let position = |name: &str| names.iter().position(|listed| *listed == name);
+
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                return Err(invalid(format!("{} is listed more than once", name)));
+            }
+        }
+        if position("tracing").is_some_and(|i| i > 0) {
+            return Err(invalid("tracing must come first so its spans cover the other middleware".to_string()));
+        }
+        for (outer, inner, reason) in ORDERING {
+            if let (Some(outer_at), Some(inner_at)) = (position(outer), position(inner)) {
+                if outer_at > inner_at {
+                    return Err(invalid(format!("{} must come before {} {}", outer, inner, reason)));
+                }
+            }
+        }
+
+        Ok(Self {
+            middleware,
+            security,
+            json_naming: JsonNaming::default(),
+            max_json_body_bytes: usize::MAX,
+            propagation: PropagationSettings::default(),
+        })
+    }
+
+    /// Renames JSON keys to `naming` outside every listed middleware; see
+    /// [`JsonNamingTransform`].
+    pub fn json_naming(mut self, naming: JsonNaming, max_json_body_bytes: usize) -> Self {
+        self.json_naming = naming;
+        self.max_json_body_bytes = max_json_body_bytes;
+        self
+    }
+
+    /// Hands on `settings.headers` of every request to the services it
+    /// calls, outside every listed middleware; see [`Propagation`].
+    pub fn propagation(mut self, settings: PropagationSettings) -> Self {
+        self.propagation = settings;
+        self
+    }
+
+    /// Middleware names, outermost first.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.middleware.iter().map(MiddlewareSettings::name).collect()
+    }
+}
+
+fn invalid(reason: String) -> ConfigError {
+    ConfigError::Message(format!("server.middleware: {}", reason))
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Pipeline
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = BoxedService;
+    type Future = LocalBoxFuture<'static, Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let pipeline = self.clone();
+
+        Box::pin(async move {
+            // Wrap from the innermost middleware outwards
+            let mut service = boxed(service);
+            for middleware in pipeline.middleware.iter().rev() {
+                service = match middleware {
+                    MiddlewareSettings::Tracing => wrap(TracingLogger::default(), service).await?,
+                    MiddlewareSettings::RequestId => wrap(RequestId::new(), service).await?,
+                    MiddlewareSettings::Logger { format } => {
+                        let logger = format.as_deref().map_or_else(Logger::default, Logger::new);
+                        wrap(logger, service).await?
+                    }
+                    MiddlewareSettings::Security => wrap(Security::new(&pipeline.security), service).await?,
+                    MiddlewareSettings::RequestEvents => wrap(RequestEvents, service).await?,
+                    MiddlewareSettings::ResponseValidation { on_mismatch } => {
+                        wrap(ResponseValidation::new(*on_mismatch), service).await?
+                    }
+                };
+            }
+            if pipeline.json_naming != JsonNaming::SnakeCase {
+                let json_naming = JsonNamingTransform::new(pipeline.json_naming, pipeline.max_json_body_bytes);
+                service = wrap(json_naming, service).await?;
+            }
+            if !pipeline.propagation.headers.is_empty() {
+                service = wrap(Propagation::new(&pipeline.propagation), service).await?;
+            }
+            Ok(service)
+        })
+    }
+}
+
+async fn wrap<T, B>(transform: T, service: BoxedService) -> Result<BoxedService, ()>
+where
+    T: Transform<BoxedService, ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    T::Transform: 'static,
+    <T::Transform as Service<ServiceRequest>>::Future: 'static,
+    B: MessageBody + 'static,
+{
+    let service = transform.new_transform(service).await.map_err(|_| ())?;
+    Ok(boxed(service))
+}
+
+fn boxed<S, B>(service: S) -> BoxedService
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    Box::new(BoxBodies(service))
+}
+
+/// Erases a layer's body type so layers can be stacked in any order.
+struct BoxBodies<S>(S);
+
+impl<S, B> Service<ServiceRequest> for BoxBodies<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let response = self.0.call(req);
+        Box::pin(async move { response.await.map(ServiceResponse::map_into_boxed_body) })
+    }
+}
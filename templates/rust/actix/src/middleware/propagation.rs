@@ -0,0 +1,81 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::HeaderMap,
+    Error,
+};
+use futures_util::future::LocalBoxFuture;
+use platform_observability::otel::Extractor;
+use platform_observability::propagation::{self, PropagationSettings};
+use std::future::{ready, Ready};
+
+/// Captures the `propagation.headers` of each request so calls made while
+/// handling it hand them on; see [`with_trace_context`](crate::utils::with_trace_context).
+pub struct Propagation {
+    settings: PropagationSettings,
+}
+
+impl Propagation {
+    pub fn new(settings: &PropagationSettings) -> Self {
+        Self {
+            settings: settings.clone(),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Propagation
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = PropagationMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(PropagationMiddleware {
+            service,
+            settings: self.settings.clone(),
+        }))
+    }
+}
+
+pub struct PropagationMiddleware<S> {
+    service: S,
+    settings: PropagationSettings,
+}
+
+impl<S, B> Service<ServiceRequest> for PropagationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let headers = propagation::capture(&self.settings, &HeaderExtractor(req.headers()));
+        if headers.is_empty() {
+            return Box::pin(self.service.call(req));
+        }
+        Box::pin(propagation::scope(headers, self.service.call(req)))
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
@@ -0,0 +1,67 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web, Error,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use crate::AppState;
+
+/// Rejects requests in the wrapped scope with 429 and a `Retry-After` once
+/// their client IP exceeds the route's rate; see [`crate::rate_limit`].
+/// Behind a trusted gateway the client IP comes from `X-Forwarded-For`.
+/// Requests without a peer address, such as in tests, aren't limited.
+pub struct RateLimiter;
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimiterMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimiterMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RateLimiterMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimiterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            if let Some(app_state) = req.app_data::<web::Data<AppState>>().cloned() {
+                let peer_ip = req.peer_addr().map(|addr| addr.ip());
+                let client_ip = app_state.rate_limits.client_ip(peer_ip, req.headers());
+                app_state.rate_limits.check(req.method(), req.path(), client_ip).await?;
+            }
+
+            service.call(req).await
+        })
+    }
+}
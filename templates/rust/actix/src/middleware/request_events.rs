@@ -0,0 +1,95 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web, Error, HttpMessage,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+    time::Instant,
+};
+
+use crate::models::user::Claims;
+use crate::olap::{OlapEvent, RequestEvent};
+use crate::AppState;
+
+/// Records a [`RequestEvent`] for every request once it is answered. Put it
+/// inside `request_id` so events carry the request ID.
+pub struct RequestEvents;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestEvents
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestEventsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestEventsMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestEventsMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestEventsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let Some(app_state) = req.app_data::<web::Data<AppState>>().cloned() else {
+                return service.call(req).await;
+            };
+            if !app_state.olap_events.enabled() {
+                return service.call(req).await;
+            }
+
+            let timestamp = app_state.clock.now();
+            let started = Instant::now();
+            let method = req.method().to_string();
+            let request_id = req.extensions().get::<String>().cloned();
+
+            // Routing and the auth middleware fill in the route and claims
+            // on the way in, so they are read from the response's request
+            let result = service.call(req).await;
+            let (status, route, user_id) = match &result {
+                Ok(response) => (
+                    response.status(),
+                    response.request().match_pattern(),
+                    response.request().extensions().get::<Claims>().map(|claims| claims.sub),
+                ),
+                Err(error) => (error.as_response_error().status_code(), None, None),
+            };
+
+            app_state.olap_events.record(OlapEvent::Request(RequestEvent {
+                timestamp,
+                request_id,
+                method,
+                route,
+                status: status.as_u16(),
+                duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+                user_id,
+            }));
+            result
+        })
+    }
+}
@@ -1,13 +1,15 @@
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
     http::header::{HeaderName, HeaderValue},
-    Error,
+    Error, HttpMessage,
 };
+use crate::errors::AppError;
 use futures_util::future::LocalBoxFuture;
 use std::{
     future::{ready, Ready},
     rc::Rc,
 };
+use tracing_actix_web::RequestId as TracingRequestId;
 use uuid::Uuid;
 
 pub struct RequestId;
@@ -41,6 +43,12 @@ impl RequestId {
     }
 }
 
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
@@ -55,14 +63,30 @@ where
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let service = self.service.clone();
-        let request_id = Uuid::new_v4().to_string();
+        // Reuse the ID the `tracing` middleware gave the request's span, so
+        // a request ID quoted by a client finds its trace
+        let request_id = req
+            .extensions()
+            .get::<TracingRequestId>()
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
         
         Box::pin(async move {
             // Add request ID to request extensions
             req.extensions_mut().insert(request_id.clone());
             
-            // Call the service
-            let mut res = service.call(req).await?;
+            // Call the service; failures are logged with the request ID so
+            // clients can quote it without seeing internal details
+            let mut res = match service.call(req).await {
+                Ok(res) => res,
+                Err(error) => {
+                    log_error(&error, &request_id);
+                    return Err(error);
+                }
+            };
+            if let Some(error) = res.response().error() {
+                log_error(error, &request_id);
+            }
             
             // Add request ID to response headers
             res.headers_mut().insert(
@@ -73,4 +97,10 @@ where
             Ok(res)
         })
     }
-}
\ No newline at end of file
+}
+
+fn log_error(error: &Error, request_id: &str) {
+    if let Some(error) = error.as_error::<AppError>() {
+        error.log(Some(request_id));
+    }
+}
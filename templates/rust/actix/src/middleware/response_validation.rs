@@ -0,0 +1,261 @@
+use actix_web::{
+    body::{self, BoxBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{Method, StatusCode},
+    web::Bytes,
+    Error, ResponseError,
+};
+use futures_util::future::LocalBoxFuture;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use crate::config::OnMismatch;
+use crate::errors::AppError;
+use crate::middleware::json_naming::is_json;
+use crate::openapi;
+
+/// Checks JSON responses of documented operations against the schema the
+/// OpenAPI document gives for their status, so serialization drift (a field
+/// turned into an `Option`, renamed or dropped) shows up in development and
+/// staging before clients trip over it.
+///
+/// Mismatches are logged; with [`OnMismatch::Fail`] the response is also
+/// replaced by a 500. Routes, statuses and content the document doesn't
+/// describe pass unchecked. Every checked body is buffered, so leave it out
+/// of production stacks.
+pub struct ResponseValidation {
+    on_mismatch: OnMismatch,
+}
+
+impl ResponseValidation {
+    pub fn new(on_mismatch: OnMismatch) -> Self {
+        Self { on_mismatch }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ResponseValidation
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ResponseValidationMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ResponseValidationMiddleware {
+            service: Rc::new(service),
+            on_mismatch: self.on_mismatch,
+        }))
+    }
+}
+
+pub struct ResponseValidationMiddleware<S> {
+    service: Rc<S>,
+    on_mismatch: OnMismatch,
+}
+
+impl<S, B> Service<ServiceRequest> for ResponseValidationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let on_mismatch = self.on_mismatch;
+
+        Box::pin(async move {
+            let response = service.call(req).await?.map_into_boxed_body();
+
+            let method = response.request().method().clone();
+            let status = response.status();
+            // Routing fills in the pattern on the way in, so it is read
+            // from the response's request
+            let schema = response
+                .request()
+                .match_pattern()
+                .and_then(|route| Some((response_schema(&method, &route, status)?, route)));
+            let Some((schema, route)) = schema.filter(|_| is_json(response.headers())) else {
+                return Ok(response);
+            };
+
+            let (req, response) = response.into_parts();
+            let (response, body) = response.into_parts();
+            let bytes = match body::to_bytes(body).await {
+                Ok(bytes) => bytes,
+                // The body already failed; nothing useful to send
+                Err(_) => Bytes::new(),
+            };
+
+            let mismatches = match serde_json::from_slice::<Value>(&bytes) {
+                Ok(value) => {
+                    let mut mismatches = Vec::new();
+                    check(&SPEC, schema, &value, "", &mut mismatches);
+                    mismatches
+                }
+                Err(e) => vec![format!("/: not JSON: {}", e)],
+            };
+            if mismatches.is_empty() {
+                return Ok(ServiceResponse::new(req, response.set_body(BoxBody::new(bytes))));
+            }
+
+            match on_mismatch {
+                OnMismatch::Log => {
+                    tracing::warn!(%method, %route, status = status.as_u16(), ?mismatches, "response doesn't match the OpenAPI document");
+                    Ok(ServiceResponse::new(req, response.set_body(BoxBody::new(bytes))))
+                }
+                OnMismatch::Fail => {
+                    tracing::error!(%method, %route, status = status.as_u16(), ?mismatches, "response doesn't match the OpenAPI document");
+                    Ok(ServiceResponse::new(req, AppError::InternalServerError.error_response()))
+                }
+            }
+        })
+    }
+}
+
+/// How `body` differs from the schema the OpenAPI document gives `method`
+/// on the route `pattern` (e.g. `/api/v1/users/{id}`) for `status`, one
+/// line per difference, each starting with a JSON pointer. `None` when the
+/// document has no JSON schema for that response.
+pub fn mismatches(method: &Method, pattern: &str, status: StatusCode, body: &Value) -> Option<Vec<String>> {
+    let schema = response_schema(method, pattern, status)?;
+    let mut mismatches = Vec::new();
+    check(&SPEC, schema, body, "", &mut mismatches);
+    Some(mismatches)
+}
+
+/// The document as JSON, which is what the schemas are walked as.
+static SPEC: Lazy<Value> = Lazy::new(|| serde_json::to_value(openapi::spec()).unwrap_or_default());
+
+fn response_schema(method: &Method, pattern: &str, status: StatusCode) -> Option<&'static Value> {
+    let responses = &SPEC["paths"][pattern][method.as_str().to_lowercase()]["responses"];
+    let response = responses.get(status.as_str()).or_else(|| responses.get("default"))?;
+    response["content"]["application/json"].get("schema")
+}
+
+/// Adds to `mismatches` how `value`, found at `at`, differs from `schema`.
+/// Covers what serde derives can drift on: types, nullability, required
+/// properties, array items and enums. Formats and bounds are left to the
+/// request side.
+fn check(spec: &Value, schema: &Value, value: &Value, at: &str, mismatches: &mut Vec<String>) {
+    if value.is_null() && nullable(schema) {
+        return;
+    }
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.trim_start_matches("#/components/schemas/");
+        return check(spec, &spec["components"]["schemas"][name], value, at, mismatches);
+    }
+    if let Some(all) = schema["allOf"].as_array() {
+        for schema in all {
+            check(spec, schema, value, at, mismatches);
+        }
+    }
+    for composition in ["oneOf", "anyOf"] {
+        if let Some(alternatives) = schema[composition].as_array() {
+            let matches = |schema: &Value| {
+                let mut found = Vec::new();
+                check(spec, schema, value, at, &mut found);
+                found.is_empty()
+            };
+            if !alternatives.iter().any(matches) {
+                mismatches.push(format!("{}: matches none of the {} alternatives", pointer(at), composition));
+            }
+        }
+    }
+    if let Some(allowed) = schema["enum"].as_array() {
+        if !allowed.contains(value) {
+            mismatches.push(format!("{}: {} isn't one of {}", pointer(at), value, Value::from(allowed.clone())));
+        }
+    }
+
+    let Some(expected) = schema_type(schema) else {
+        return;
+    };
+    let matches = match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        _ => true,
+    };
+    if !matches {
+        mismatches.push(format!("{}: expected {}, got {}", pointer(at), expected, type_of(value)));
+        return;
+    }
+
+    match value {
+        Value::Object(object) => {
+            for required in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                if !object.contains_key(required) {
+                    mismatches.push(format!("{}: missing required property", pointer(&format!("{}/{}", at, required))));
+                }
+            }
+            for (key, value) in object {
+                let at = format!("{}/{}", at, key);
+                match (schema["properties"].get(key), &schema["additionalProperties"]) {
+                    (Some(property), _) => check(spec, property, value, &at, mismatches),
+                    (None, Value::Bool(false)) => mismatches.push(format!("{}: undocumented property", pointer(&at))),
+                    (None, additional) if additional.is_object() => check(spec, additional, value, &at, mismatches),
+                    (None, _) => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(spec, schema, item, &format!("{}/{}", at, i), mismatches);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `nullable: true` (OpenAPI 3.0) or `null` among the types (3.1).
+fn nullable(schema: &Value) -> bool {
+    schema["nullable"] == Value::Bool(true)
+        || schema["type"].as_array().is_some_and(|kinds| kinds.iter().any(|kind| kind == "null"))
+}
+
+fn schema_type(schema: &Value) -> Option<&str> {
+    match &schema["type"] {
+        Value::String(kind) => Some(kind.as_str()),
+        Value::Array(kinds) => kinds.iter().filter_map(Value::as_str).find(|kind| *kind != "null"),
+        _ => None,
+    }
+}
+
+fn type_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn pointer(at: &str) -> &str {
+    if at.is_empty() {
+        "/"
+    } else {
+        at
+    }
+}
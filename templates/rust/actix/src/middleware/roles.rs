@@ -0,0 +1,108 @@
+//! Role-based access control.
+//!
+//! Users are granted roles (`admin`, `support`, ...), which grant
+//! permissions (`users.read`, ...); see
+//! [`RoleService`](crate::services::RoleService). Access tokens carry both,
+//! so checking them needs no database round trip. Callers without them get
+//! `403` with code `AUTH_ROLE_MISSING`.
+//!
+//! Routes are restricted with the [`RequireRole`] middleware, e.g.
+//! `#[get("/roles", wrap = "RequireRole::new(ROLE_ADMIN)")]`, or by calling
+//! [`require_role`] or [`require_permission`], which return the caller's
+//! claims like `require_session` does.
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage, HttpRequest,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use crate::{
+    errors::{AppError, AppResult, ErrorCode},
+    models::user::Claims,
+};
+
+/// The caller's claims if their token grants `role`.
+pub fn require_role(req: &HttpRequest, role: &str) -> AppResult<Claims> {
+    let claims = req.extensions().get::<Claims>().cloned().ok_or(AppError::Unauthorized)?;
+    if !claims.grants.has_role(role) {
+        return Err(role_missing());
+    }
+    Ok(claims)
+}
+
+/// The caller's claims if one of their roles grants `permission`.
+pub fn require_permission(req: &HttpRequest, permission: &str) -> AppResult<Claims> {
+    let claims = req.extensions().get::<Claims>().cloned().ok_or(AppError::Unauthorized)?;
+    if !claims.grants.has_permission(permission) {
+        return Err(role_missing());
+    }
+    Ok(claims)
+}
+
+fn role_missing() -> AppError {
+    AppError::Forbidden.with_code(ErrorCode::AuthRoleMissing)
+}
+
+/// Restricts a route or scope to holders of a role; see the
+/// [module docs](self). Must run inside
+/// [`AuthMiddleware`](super::AuthMiddleware).
+pub struct RequireRole(&'static str);
+
+impl RequireRole {
+    pub fn new(role: &'static str) -> Self {
+        Self(role)
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequireRole
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequireRoleMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireRoleMiddleware {
+            service: Rc::new(service),
+            role: self.0,
+        }))
+    }
+}
+
+pub struct RequireRoleMiddleware<S> {
+    service: Rc<S>,
+    role: &'static str,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireRoleMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let checked = require_role(req.request(), self.role);
+
+        Box::pin(async move {
+            checked?;
+            service.call(req).await
+        })
+    }
+}
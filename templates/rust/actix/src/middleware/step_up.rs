@@ -0,0 +1,124 @@
+//! Step-up authentication for sensitive operations.
+//!
+//! A valid token isn't always enough: changing the email address, deleting
+//! the account or managing API tokens also needs the user to have proved
+//! who they are recently. They do so at `/auth/step-up`, which answers with
+//! a short-lived elevated token carrying `auth_time` and `acr`; marked
+//! routes refuse anything else with `401` and code `STEP_UP_REQUIRED`.
+//!
+//! Routes are marked with the [`RequireStepUp`] middleware, e.g.
+//! `#[delete("/{id}", wrap = "RequireStepUp::recent()")]`, or, when only
+//! some requests to a route are sensitive, by calling [`require_step_up`].
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web, Error, HttpMessage, HttpRequest,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use crate::{
+    errors::{AppError, AppResult, ErrorCode},
+    models::user::Claims,
+    AppState,
+};
+
+/// What a sensitive route needs on top of a valid token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepUp {
+    /// A re-authentication within `step_up.max_age_secs`.
+    Recent,
+    /// A recent re-authentication with a second factor, i.e. a passkey.
+    MultiFactor,
+}
+
+/// Fails with `STEP_UP_REQUIRED` unless the caller's token satisfies
+/// `level`. Always passes while `step_up.enabled` is off.
+pub fn require_step_up(req: &HttpRequest, level: StepUp) -> AppResult<()> {
+    let app_state = req.app_data::<web::Data<AppState>>().ok_or(AppError::InternalServerError)?;
+    let settings = &app_state.settings.step_up;
+    if !settings.enabled {
+        return Ok(());
+    }
+
+    let claims = req.extensions().get::<Claims>().cloned().ok_or(AppError::Unauthorized)?;
+    let now = app_state.clock.now().timestamp();
+    let recent = claims
+        .auth_time
+        .is_some_and(|auth_time| auth_time as i64 + settings.max_age_secs >= now);
+    let strong_enough = match level {
+        StepUp::Recent => true,
+        StepUp::MultiFactor => claims.acr.is_some_and(|acr| acr.is_multi_factor()),
+    };
+
+    if !(recent && strong_enough) {
+        return Err(AppError::Unauthorized.with_code(ErrorCode::StepUpRequired));
+    }
+
+    Ok(())
+}
+
+/// Marks a route as sensitive; see the [module docs](self). Must run inside
+/// [`AuthMiddleware`](super::AuthMiddleware).
+pub struct RequireStepUp(StepUp);
+
+impl RequireStepUp {
+    pub fn recent() -> Self {
+        Self(StepUp::Recent)
+    }
+
+    pub fn multi_factor() -> Self {
+        Self(StepUp::MultiFactor)
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequireStepUp
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequireStepUpMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireStepUpMiddleware {
+            service: Rc::new(service),
+            level: self.0,
+        }))
+    }
+}
+
+pub struct RequireStepUpMiddleware<S> {
+    service: Rc<S>,
+    level: StepUp,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireStepUpMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let checked = require_step_up(req.request(), self.level);
+
+        Box::pin(async move {
+            checked?;
+            service.call(req).await
+        })
+    }
+}
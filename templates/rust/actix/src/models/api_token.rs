@@ -0,0 +1,166 @@
+use actix_template_macros::table;
+use chrono::{DateTime, Utc};
+use platform_core::domain::UserId;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Fine-grained permissions that can be granted to a personal access token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "read:users")]
+    ReadUsers,
+    #[serde(rename = "write:users")]
+    WriteUsers,
+    #[serde(rename = "read:profile")]
+    ReadProfile,
+    #[serde(rename = "write:profile")]
+    WriteProfile,
+    #[serde(rename = "read:stats")]
+    ReadStats,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 5] = [
+        Scope::ReadUsers,
+        Scope::WriteUsers,
+        Scope::ReadProfile,
+        Scope::WriteProfile,
+        Scope::ReadStats,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ReadUsers => "read:users",
+            Scope::WriteUsers => "write:users",
+            Scope::ReadProfile => "read:profile",
+            Scope::WriteProfile => "write:profile",
+            Scope::ReadStats => "read:stats",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("Unknown scope: {}", s))
+    }
+}
+
+/// What a guest may do: manage its own profile, devices and the like, but
+/// not see other users or mint tokens.
+pub const GUEST_SCOPES: [Scope; 2] = [Scope::ReadProfile, Scope::WriteProfile];
+
+/// Scopes granted to the authenticated caller, inserted into request
+/// extensions by the auth middleware next to the `Claims`.
+#[derive(Debug, Clone)]
+pub enum GrantedScopes {
+    /// Interactive session authenticated with a JWT; every scope is granted.
+    Session,
+    /// Personal access token restricted to the scopes it was minted with.
+    Token(Vec<Scope>),
+    /// Guest session, restricted to [`GUEST_SCOPES`].
+    Guest,
+}
+
+impl GrantedScopes {
+    pub fn allows(&self, scope: Scope) -> bool {
+        match self {
+            GrantedScopes::Session => true,
+            GrantedScopes::Token(scopes) => scopes.contains(&scope),
+            GrantedScopes::Guest => GUEST_SCOPES.contains(&scope),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+#[table("api_tokens")]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub name: String,
+    pub prefix: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    /// Request signing key, sealed with the application signing keys.
+    #[serde(skip_serializing)]
+    pub signing_key: Option<String>,
+}
+
+impl ApiToken {
+    pub fn granted_scopes(&self) -> GrantedScopes {
+        GrantedScopes::Token(
+            self.scopes
+                .iter()
+                .filter_map(|scope| scope.parse().ok())
+                .collect(),
+        )
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateApiToken {
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters"))]
+    pub name: String,
+    #[validate(length(min = 1, message = "At least one scope is required"))]
+    pub scopes: Vec<Scope>,
+    #[validate(range(min = 1, max = 365, message = "Expiry must be between 1 and 365 days"))]
+    pub expires_in_days: Option<u32>,
+    /// Also issue a key for signing requests with it.
+    #[serde(default)]
+    pub signing: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiTokenResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ApiToken> for ApiTokenResponse {
+    fn from(token: ApiToken) -> Self {
+        ApiTokenResponse {
+            id: token.id,
+            name: token.name,
+            prefix: token.prefix,
+            scopes: token.scopes,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+            created_at: token.created_at,
+        }
+    }
+}
+
+/// Returned once, at creation time; the plaintext token is never stored.
+#[derive(Debug, Serialize)]
+pub struct CreatedApiTokenResponse {
+    pub token: String,
+    /// Present when the token was minted for request signing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_key: Option<String>,
+    #[serde(flatten)]
+    pub details: ApiTokenResponse,
+}
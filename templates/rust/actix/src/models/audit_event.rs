@@ -0,0 +1,32 @@
+use platform_core::domain::UserId;
+
+use crate::device::DeviceInfo;
+
+pub const REFRESH_TOKEN_REUSED: &str = "auth.refresh_token_reused";
+pub const REFRESH_TOKEN_BINDING_MISMATCH: &str = "auth.refresh_token_binding_mismatch";
+pub const PASSWORD_RESET: &str = "auth.password_reset";
+pub const ACCOUNT_DELETED: &str = "account.deleted";
+/// Downstream services didn't all confirm a deletion in time.
+pub const ACCOUNT_DELETION_STALLED: &str = "account.deletion_stalled";
+pub const LOG_LEVEL_CHANGED: &str = "admin.log_level_changed";
+pub const JOB_QUEUE_PAUSED: &str = "admin.job_queue_paused";
+pub const JOB_QUEUE_RESUMED: &str = "admin.job_queue_resumed";
+pub const JOB_RETRIED: &str = "admin.job_retried";
+pub const JOB_CANCELLED: &str = "admin.job_cancelled";
+pub const DEAD_JOBS_PURGED: &str = "admin.dead_jobs_purged";
+pub const IMPERSONATION_STARTED: &str = "admin.impersonation_started";
+pub const USER_UPDATED_BY_ADMIN: &str = "admin.user_updated";
+pub const USER_DELETED_BY_ADMIN: &str = "admin.user_deleted";
+pub const ROLE_GRANTED: &str = "admin.role_granted";
+pub const ROLE_REVOKED: &str = "admin.role_revoked";
+pub const IMPERSONATED_REQUEST: &str = "admin.impersonated_request";
+
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    pub event_type: &'static str,
+    pub user_id: Option<UserId>,
+    pub ip_address: Option<String>,
+    /// Parsed from the user agent of the client, if there was one.
+    pub device: Option<DeviceInfo>,
+    pub metadata: serde_json::Value,
+}
@@ -0,0 +1,22 @@
+use platform_core::domain::{Email, UserId};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// What the signed token in a verification link carries. Naming the address
+/// voids links sent before the user changed it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailVerificationClaims {
+    pub user_id: UserId,
+    pub email: Email,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct VerifyEmailRequest {
+    /// The `token` of the link in the verification email.
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ResendVerificationRequest {
+    pub email: Email,
+}
@@ -0,0 +1,59 @@
+use actix_template_macros::table;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::config::JobRateLimitSettings;
+use crate::errors::{AppError, AppResult};
+
+pub const JOB_PENDING: &str = "pending";
+pub const JOB_RUNNING: &str = "running";
+pub const JOB_COMPLETED: &str = "completed";
+/// Out of attempts, or of a kind no handler knows.
+pub const JOB_DEAD: &str = "dead";
+
+#[derive(Debug, Serialize, FromRow, Clone)]
+#[table("jobs")]
+pub struct Job {
+    pub id: Uuid,
+    pub kind: String,
+    /// The queue it runs in; see [`jobs`](crate::jobs).
+    pub queue: String,
+    /// Jobs sharing a key count against one rate limit of their queue.
+    pub rate_key: Option<String>,
+    pub payload: serde_json::Value,
+    pub status: String,
+    /// Attempts so far, including the running one.
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: DateTime<Utc>,
+    pub locked_at: Option<DateTime<Utc>>,
+    /// When the latest attempt started.
+    pub started_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A queue as admins see it.
+#[derive(Debug, Serialize, Clone)]
+pub struct JobQueueStatus {
+    pub name: String,
+    pub priority: i32,
+    pub concurrency: Option<usize>,
+    pub rate_limit: Option<JobRateLimitSettings>,
+    pub paused: bool,
+    pub pending: i64,
+    pub running: i64,
+    pub dead: i64,
+}
+
+impl Job {
+    /// The payload as the type it was enqueued with.
+    pub fn payload<T: DeserializeOwned>(&self) -> AppResult<T> {
+        serde_json::from_value(self.payload.clone())
+            .map_err(|e| AppError::BadRequest(format!("Invalid {} job payload: {}", self.kind, e)))
+    }
+}
@@ -0,0 +1,34 @@
+use actix_template_macros::table;
+use chrono::{DateTime, Utc};
+use platform_core::domain::{Email, UserId};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, FromRow, Clone)]
+#[table("magic_links")]
+pub struct MagicLink {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub email: Email,
+    pub fingerprint_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub consumed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What the signed token in a link carries; the rest stays in the database.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MagicLinkClaims {
+    pub id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MagicLinkRequest {
+    pub email: Email,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MagicLinkVerify {
+    pub token: String,
+}
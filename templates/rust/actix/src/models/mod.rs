@@ -1,3 +1,17 @@
 pub mod user;
+pub mod api_token;
+pub mod audit_event;
+pub mod email_verification;
+pub mod job;
+pub mod magic_link;
+pub mod operation;
+pub mod passkey;
+pub mod password_reset;
+pub mod phone_otp;
+pub mod push_device;
+pub mod role;
+pub mod saga;
+pub mod session;
+pub mod stats;
+pub mod user_deletion;
 
-pub use user::{User, CreateUser, UpdateUser, LoginRequest, LoginResponse};
\ No newline at end of file
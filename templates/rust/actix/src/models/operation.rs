@@ -0,0 +1,88 @@
+use actix_template_macros::table;
+use chrono::{DateTime, Utc};
+use platform_core::domain::UserId;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+#[table("operations")]
+pub struct Operation {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub kind: String,
+    pub done: bool,
+    pub progress_percent: i32,
+    pub progress_message: Option<String>,
+    /// Kind-specific details, e.g. the file being imported.
+    pub metadata: serde_json::Value,
+    pub response: Option<serde_json::Value>,
+    pub error_code: Option<String>,
+    pub error_message: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Operation {
+    /// The resource name, `operations/{id}`.
+    pub fn name(&self) -> String {
+        format!("operations/{}", self.id)
+    }
+}
+
+/// An operation in the AIP-151 shape: `done` plus either `response` or
+/// `error` once it finished, with progress under `metadata`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OperationResponse {
+    pub name: String,
+    pub done: bool,
+    pub metadata: OperationMetadata,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<OperationError>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OperationMetadata {
+    pub kind: String,
+    pub progress_percent: i32,
+    pub progress_message: Option<String>,
+    pub details: serde_json::Value,
+    pub create_time: DateTime<Utc>,
+    pub update_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OperationError {
+    pub code: String,
+    pub message: String,
+}
+
+impl From<Operation> for OperationResponse {
+    fn from(operation: Operation) -> Self {
+        let name = operation.name();
+        let error = match (operation.error_code, operation.error_message) {
+            (Some(code), message) => Some(OperationError {
+                code,
+                message: message.unwrap_or_default(),
+            }),
+            _ => None,
+        };
+
+        OperationResponse {
+            name,
+            done: operation.done,
+            metadata: OperationMetadata {
+                kind: operation.kind,
+                progress_percent: operation.progress_percent,
+                progress_message: operation.progress_message,
+                details: operation.metadata,
+                create_time: operation.created_at,
+                update_time: operation.updated_at,
+            },
+            response: operation.response,
+            error,
+        }
+    }
+}
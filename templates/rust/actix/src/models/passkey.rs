@@ -0,0 +1,69 @@
+use actix_template_macros::table;
+use chrono::{DateTime, Utc};
+use platform_core::domain::UserId;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+use webauthn_rs::prelude::{Passkey, PublicKeyCredential, RegisterPublicKeyCredential};
+
+#[derive(Debug, FromRow, Clone)]
+#[table("passkeys")]
+pub struct StoredPasskey {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub credential_id: String,
+    pub name: String,
+    pub credential: Json<Passkey>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// The first step of a ceremony: `options` go to
+/// `navigator.credentials.create()` or `.get()`, and `ceremony_id` comes
+/// back with the authenticator's answer.
+#[derive(Debug, Serialize)]
+pub struct CeremonyChallenge<T> {
+    pub ceremony_id: Uuid,
+    pub options: T,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct FinishPasskeyRegistration {
+    pub ceremony_id: Uuid,
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters"))]
+    pub name: String,
+    pub credential: RegisterPublicKeyCredential,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct RenamePasskey {
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters"))]
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FinishPasskeyLogin {
+    pub ceremony_id: Uuid,
+    pub credential: PublicKeyCredential,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasskeyResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl From<StoredPasskey> for PasskeyResponse {
+    fn from(passkey: StoredPasskey) -> Self {
+        PasskeyResponse {
+            id: passkey.id,
+            name: passkey.name,
+            created_at: passkey.created_at,
+            last_used_at: passkey.last_used_at,
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use actix_template_macros::table;
+use chrono::{DateTime, Utc};
+use platform_core::domain::{Email, UserId};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Debug, FromRow, Clone)]
+#[table("password_resets")]
+pub struct PasswordReset {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub email: Email,
+    pub expires_at: DateTime<Utc>,
+    pub consumed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What the signed token in a reset link carries; the rest stays in the
+/// database.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordResetClaims {
+    pub id: Uuid,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ForgotPasswordRequest {
+    pub email: Email,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct ResetPasswordRequest {
+    /// The `token` of the link in the reset email.
+    pub token: String,
+    #[validate(length(min = 8, message = "Password must be at least 8 characters"))]
+    #[schema(format = Password, min_length = 8)]
+    pub new_password: String,
+}
@@ -0,0 +1,60 @@
+use actix_template_macros::table;
+use chrono::{DateTime, Utc};
+use platform_core::domain::UserId;
+use serde::Deserialize;
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::utils::validate_e164;
+
+/// What a one-time code may be used for; a code sent for one purpose is
+/// never accepted for another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpPurpose {
+    VerifyPhone,
+    Login,
+}
+
+impl OtpPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OtpPurpose::VerifyPhone => "verify_phone",
+            OtpPurpose::Login => "login",
+        }
+    }
+}
+
+#[derive(Debug, FromRow, Clone)]
+#[table("phone_otps")]
+pub struct PhoneOtp {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub phone_number: String,
+    pub purpose: String,
+    pub code_hash: String,
+    pub attempts: i32,
+    pub expires_at: DateTime<Utc>,
+    pub consumed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct OtpLoginRequest {
+    #[validate(custom(function = "validate_e164"))]
+    pub phone_number: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct OtpLoginVerify {
+    #[validate(custom(function = "validate_e164"))]
+    pub phone_number: String,
+    #[validate(length(min = 4, max = 10, message = "Invalid code"))]
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct VerifyPhone {
+    #[validate(length(min = 4, max = 10, message = "Invalid code"))]
+    pub code: String,
+}
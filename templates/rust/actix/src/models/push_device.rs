@@ -0,0 +1,84 @@
+use actix_template_macros::table;
+use chrono::{DateTime, Utc};
+use platform_core::domain::UserId;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+use validator::Validate;
+
+/// The service a device token belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PushPlatform {
+    /// Firebase Cloud Messaging (Android, and iOS apps using Firebase).
+    Fcm,
+    /// Apple Push Notification service.
+    Apns,
+}
+
+impl PushPlatform {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PushPlatform::Fcm => "fcm",
+            PushPlatform::Apns => "apns",
+        }
+    }
+}
+
+impl fmt::Display for PushPlatform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for PushPlatform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fcm" => Ok(PushPlatform::Fcm),
+            "apns" => Ok(PushPlatform::Apns),
+            _ => Err(format!("Unknown push platform: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+#[table("push_devices")]
+pub struct PushDevice {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub platform: String,
+    #[serde(skip_serializing)]
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct RegisterPushDevice {
+    pub platform: PushPlatform,
+    #[validate(length(min = 1, max = 4096, message = "Token must be between 1 and 4096 characters"))]
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PushDeviceResponse {
+    pub id: Uuid,
+    pub platform: String,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+impl From<PushDevice> for PushDeviceResponse {
+    fn from(device: PushDevice) -> Self {
+        PushDeviceResponse {
+            id: device.id,
+            platform: device.platform,
+            created_at: device.created_at,
+            last_seen_at: device.last_seen_at,
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use actix_template_macros::table;
+use chrono::{DateTime, Utc};
+use platform_core::domain::UserId;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Manages users and their roles.
+pub const ROLE_ADMIN: &str = "admin";
+/// Looks up users to help them.
+pub const ROLE_SUPPORT: &str = "support";
+
+pub const USERS_READ: &str = "users.read";
+pub const USERS_WRITE: &str = "users.write";
+pub const USERS_DELETE: &str = "users.delete";
+
+/// A role with the permissions it grants.
+#[derive(Debug, Serialize, FromRow, Clone)]
+#[table("roles")]
+pub struct Role {
+    pub name: String,
+    pub description: String,
+    #[sqlx(skip)]
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, FromRow, Clone)]
+#[table("user_roles")]
+pub struct UserRole {
+    #[serde(skip_serializing)]
+    pub user_id: UserId,
+    pub role: String,
+    /// `None` for roles granted from the command line, or by a deleted
+    /// user.
+    pub granted_by: Option<UserId>,
+    pub granted_at: DateTime<Utc>,
+}
+
+/// A user's roles and the permissions they grant, as their access tokens
+/// carry them.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Grants {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<String>,
+}
+
+impl Grants {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|granted| granted == role)
+    }
+
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|granted| granted == permission)
+    }
+}
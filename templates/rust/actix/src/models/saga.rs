@@ -0,0 +1,37 @@
+use actix_template_macros::table;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+pub const SAGA_RUNNING: &str = "running";
+pub const SAGA_COMPLETED: &str = "completed";
+/// A step failed; completed steps are being undone.
+pub const SAGA_COMPENSATING: &str = "compensating";
+pub const SAGA_COMPENSATED: &str = "compensated";
+/// Compensation failed for good and needs a human.
+pub const SAGA_FAILED: &str = "failed";
+
+#[derive(Debug, Serialize, FromRow, Clone)]
+#[table("sagas")]
+pub struct SagaRecord {
+    pub id: Uuid,
+    pub kind: String,
+    pub status: String,
+    /// Steps completed so far; while compensating, steps still to undo.
+    pub current_step: i32,
+    pub data: serde_json::Value,
+    pub last_error: Option<String>,
+    pub compensation_attempts: i32,
+    pub job_id: Option<Uuid>,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SagaRecord {
+    /// Whether the saga still has work to do.
+    pub fn is_active(&self) -> bool {
+        self.status == SAGA_RUNNING || self.status == SAGA_COMPENSATING
+    }
+}
@@ -0,0 +1,103 @@
+use actix_template_macros::table;
+use actix_web::{dev::Payload, http::header::USER_AGENT, Error, FromRequest, HttpRequest};
+use chrono::{DateTime, Utc};
+use platform_core::domain::UserId;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::FromRow;
+use std::future::{ready, Ready};
+use std::net::IpAddr;
+use uuid::Uuid;
+
+use crate::device::{self, DeviceInfo};
+
+pub const DEVICE_ID_HEADER: &str = "x-device-id";
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+#[table("refresh_tokens")]
+pub struct RefreshToken {
+    pub id: Uuid,
+    pub family_id: Uuid,
+    pub user_id: UserId,
+    #[serde(skip_serializing)]
+    pub fingerprint_hash: String,
+    pub ip_subnet: Option<String>,
+    pub device: Option<Json<DeviceInfo>>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A signed-in session: a refresh token family, described by its latest
+/// token.
+#[derive(Debug, FromRow)]
+pub struct Session {
+    pub id: Uuid,
+    pub device: Option<Json<DeviceInfo>>,
+    pub started_at: DateTime<Utc>,
+    pub last_active_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionResponse {
+    pub id: Uuid,
+    pub device: DeviceInfo,
+    /// The device summed up for display, e.g. `Mobile Safari 17.4 on iOS 17.4`.
+    pub label: String,
+    pub started_at: DateTime<Utc>,
+    pub last_active_at: DateTime<Utc>,
+}
+
+impl From<Session> for SessionResponse {
+    fn from(session: Session) -> Self {
+        let device = session.device.map(|device| device.0).unwrap_or_default();
+        Self {
+            id: session.id,
+            label: device.label(),
+            device,
+            started_at: session.started_at,
+            last_active_at: session.last_active_at,
+        }
+    }
+}
+
+/// Client characteristics captured from the request that issues or presents
+/// a refresh token.
+#[derive(Debug, Clone)]
+pub struct ClientContext {
+    pub user_agent: String,
+    pub device_id: Option<String>,
+    pub ip: Option<IpAddr>,
+}
+
+impl ClientContext {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let header = |name| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+        };
+
+        ClientContext {
+            user_agent: header(USER_AGENT.as_str()).unwrap_or_default(),
+            device_id: header(DEVICE_ID_HEADER),
+            ip: req.peer_addr().map(|addr| addr.ip()),
+        }
+    }
+
+    /// The browser, OS and device the user agent describes.
+    pub fn device(&self) -> DeviceInfo {
+        device::parse(&self.user_agent)
+    }
+}
+
+impl FromRequest for ClientContext {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(ClientContext::from_request(req)))
+    }
+}
@@ -0,0 +1,23 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+
+/// Accounts created on a day, guests aside.
+#[derive(Debug, Clone, Serialize, FromRow, PartialEq, Eq)]
+pub struct DailySignups {
+    pub day: NaiveDate,
+    pub signups: i64,
+}
+
+/// What `GET /stats` returns. Guests aren't counted.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct StatsSummary {
+    pub total_users: i64,
+    pub active_users: i64,
+    pub verified_users: i64,
+    /// The last `stats.signup_days` days, most recent first; days without
+    /// signups are left out.
+    pub signups_per_day: Vec<DailySignups>,
+    /// When the oldest of these figures was computed.
+    pub as_of: DateTime<Utc>,
+}
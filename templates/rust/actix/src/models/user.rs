@@ -1,52 +1,140 @@
+use actix_template_macros::table;
 use chrono::{DateTime, Utc};
+use platform_auth::HashedPassword;
+use platform_core::domain::{Email, UserId, Username};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
-use uuid::Uuid;
-use validator::Validate;
+use utoipa::{IntoParams, ToSchema};
+use validator::{Validate, ValidationError};
+
+use crate::config::CountMode;
+use crate::models::passkey::FinishPasskeyLogin;
+use crate::models::role::Grants;
+use crate::utils::validate_e164;
 
 #[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+#[table("users")]
 pub struct User {
-    pub id: Uuid,
-    pub email: String,
-    pub username: String,
+    pub id: UserId,
+    pub email: Email,
+    pub username: Username,
     #[serde(skip_serializing)]
-    pub password_hash: String,
+    pub password_hash: HashedPassword,
     pub full_name: Option<String>,
     pub is_active: bool,
     pub is_verified: bool,
+    /// E.164 phone number, unverified until `phone_verified_at` is set.
+    pub phone_number: Option<String>,
+    pub phone_verified_at: Option<DateTime<Utc>>,
+    /// Guests have placeholder credentials until they register, and are
+    /// deleted at `guest_expires_at` if they don't.
+    pub is_guest: bool,
+    pub guest_expires_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+impl User {
+    /// The phone number, if the user proved they own it.
+    pub fn verified_phone_number(&self) -> Option<&str> {
+        self.phone_verified_at.and(self.phone_number.as_deref())
+    }
+
+    /// Entity tag for conditional updates, derived from `updated_at`.
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.updated_at.timestamp_micros())
+    }
+}
+
+/// Parses an `If-Match` value produced by [`User::etag`] back into the
+/// `updated_at` it was derived from.
+pub fn parse_etag(value: &str) -> Option<DateTime<Utc>> {
+    let micros = value.trim().strip_prefix("W/").unwrap_or(value.trim());
+    let micros = micros.strip_prefix('"')?.strip_suffix('"')?.parse().ok()?;
+    DateTime::from_timestamp_micros(micros)
+}
+
+/// Email and username are checked as they deserialize; `validate` covers
+/// the password.
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct CreateUser {
-    #[validate(email(message = "Invalid email format"))]
-    pub email: String,
-    #[validate(length(min = 3, max = 50, message = "Username must be between 3 and 50 characters"))]
-    pub username: String,
+    pub email: Email,
+    pub username: Username,
     #[validate(length(min = 8, message = "Password must be at least 8 characters"))]
+    #[schema(format = Password, min_length = 8)]
     pub password: String,
+    #[validate(
+        length(max = 255, message = "Full name must be at most 255 characters"),
+        custom(function = "validate_full_name")
+    )]
+    #[schema(max_length = 255)]
     pub full_name: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+/// Partial update of a user: absent fields are left untouched, and
+/// `"full_name": null` clears the full name. Changing the phone number
+/// resets its verification.
+#[derive(Debug, Default, Deserialize, Validate, ToSchema)]
 pub struct UpdateUser {
-    #[validate(email(message = "Invalid email format"))]
-    pub email: Option<String>,
-    #[validate(length(min = 3, max = 50, message = "Username must be between 3 and 50 characters"))]
-    pub username: Option<String>,
-    pub full_name: Option<String>,
+    pub email: Option<Email>,
+    pub username: Option<Username>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(
+        length(max = 255, message = "Full name must be at most 255 characters"),
+        custom(function = "validate_full_name")
+    )]
+    #[schema(value_type = Option<String>, nullable, max_length = 255)]
+    pub full_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(custom(function = "validate_e164"))]
+    #[schema(value_type = Option<String>, nullable, example = "+14155550123")]
+    pub phone_number: Option<Option<String>>,
     pub is_active: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+impl UpdateUser {
+    /// The fields the update sets, for audit and domain events.
+    pub fn fields(&self) -> Vec<&'static str> {
+        [
+            self.email.as_ref().map(|_| "email"),
+            self.username.as_ref().map(|_| "username"),
+            self.full_name.as_ref().map(|_| "full_name"),
+            self.phone_number.as_ref().map(|_| "phone_number"),
+            self.is_active.as_ref().map(|_| "is_active"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+/// Rejects control characters, among them NUL, which Postgres can't store.
+fn validate_full_name(full_name: &str) -> Result<(), ValidationError> {
+    if full_name.chars().any(char::is_control) {
+        let mut error = ValidationError::new("control_characters");
+        error.message = Some("Full name must not contain control characters".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Deserializes a present field, including `null`, as `Some`.
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LoginRequest {
-    #[validate(email(message = "Invalid email format"))]
-    pub email: String,
+    pub email: Email,
+    #[schema(format = Password)]
     pub password: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct LoginResponse {
     pub access_token: String,
     pub refresh_token: String,
@@ -55,14 +143,27 @@ pub struct LoginResponse {
     pub user: UserResponse,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A guest token. There's no refresh token; a guest lasts as long as its
+/// token and keeps its data by upgrading to an account.
+#[derive(Debug, Serialize)]
+pub struct GuestResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub user: UserResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct UserResponse {
-    pub id: Uuid,
-    pub email: String,
-    pub username: String,
+    pub id: UserId,
+    pub email: Email,
+    pub username: Username,
     pub full_name: Option<String>,
     pub is_active: bool,
     pub is_verified: bool,
+    pub phone_number: Option<String>,
+    pub phone_verified: bool,
+    pub is_guest: bool,
     pub created_at: DateTime<Utc>,
 }
 
@@ -75,23 +176,110 @@ impl From<User> for UserResponse {
             full_name: user.full_name,
             is_active: user.is_active,
             is_verified: user.is_verified,
+            phone_verified: user.phone_verified_at.is_some(),
+            phone_number: user.phone_number,
+            is_guest: user.is_guest,
             created_at: user.created_at,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
-    pub sub: Uuid,
-    pub email: String,
+    pub sub: UserId,
+    pub email: Email,
     pub exp: usize,
     pub iat: usize,
+    /// Set on guest tokens, which only get
+    /// [`GUEST_SCOPES`](crate::models::api_token::GUEST_SCOPES).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub guest: bool,
+    /// Set on impersonation tokens: `sub` is the impersonated user and
+    /// `act` the admin acting as them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+    /// When the user last re-authenticated, and how; only set on elevated
+    /// tokens from `/auth/step-up`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acr: Option<Acr>,
+    /// The `roles` and `permissions` claims; empty on guest, impersonation
+    /// and API token credentials.
+    #[serde(flatten)]
+    pub grants: Grants,
+}
+
+/// How a user re-authenticated (the `acr` claim).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Acr {
+    Password,
+    /// A passkey with user verification: something the user has plus a
+    /// PIN or biometric, so it counts as multi-factor.
+    Passkey,
 }
 
+impl Acr {
+    pub fn is_multi_factor(self) -> bool {
+        matches!(self, Acr::Passkey)
+    }
+}
+
+/// Re-authentication for `/auth/step-up`, by password or by a passkey
+/// login ceremony started at `/auth/passkey/start`.
 #[derive(Debug, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum StepUpRequest {
+    Password { password: String },
+    Passkey(Box<FinishPasskeyLogin>),
+}
+
+/// An elevated token; it expires once the re-authentication stops counting
+/// as recent.
+#[derive(Debug, Serialize)]
+pub struct StepUpResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub acr: Acr,
+}
+
+/// The `act` claim of RFC 8693: who is acting on the subject's behalf.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Actor {
+    pub sub: UserId,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ImpersonateUser {
+    pub user_id: UserId,
+    /// Why support needs to act as the user, e.g. a ticket reference.
+    #[validate(length(min = 1, max = 500, message = "Reason must be between 1 and 500 characters"))]
+    pub reason: String,
+}
+
+/// An impersonation token; like a guest's, it can't be refreshed.
+#[derive(Debug, Serialize)]
+pub struct ImpersonationResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub user: UserResponse,
+}
+
+/// Pages are bounded so the offset they make fits.
+#[derive(Debug, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct PaginationParams {
+    #[validate(range(min = 1, max = 100_000, message = "Page must be between 1 and 100000"))]
+    #[param(minimum = 1, maximum = 100_000)]
     pub page: Option<u32>,
+    #[validate(range(min = 1, max = 100, message = "Limit must be between 1 and 100"))]
+    #[param(minimum = 1, maximum = 100)]
     pub limit: Option<u32>,
+    /// Overrides `pagination.count` for this request.
+    pub count: Option<CountMode>,
 }
 
 impl Default for PaginationParams {
@@ -99,15 +287,20 @@ impl Default for PaginationParams {
         Self {
             page: Some(1),
             limit: Some(20),
+            count: None,
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(PaginatedUsers = PaginatedResponse<UserResponse>)]
 pub struct PaginatedResponse<T> {
     pub data: Vec<T>,
-    pub total: i64,
+    /// `None` when counting was skipped.
+    pub total: Option<i64>,
     pub page: u32,
     pub limit: u32,
-    pub total_pages: u32,
+    pub total_pages: Option<u32>,
+    /// How `total` was computed.
+    pub count: CountMode,
 }
\ No newline at end of file
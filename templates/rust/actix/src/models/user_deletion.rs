@@ -0,0 +1,36 @@
+use actix_template_macros::table;
+use chrono::{DateTime, Utc};
+use platform_core::domain::UserId;
+use serde::Serialize;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Waiting for downstream services to confirm.
+pub const DELETION_PENDING: &str = "pending";
+pub const DELETION_COMPLETED: &str = "completed";
+/// Unconfirmed past its deadline; an admin decides.
+pub const DELETION_MANUAL_REVIEW: &str = "manual_review";
+
+#[derive(Debug, Serialize, FromRow, Clone)]
+#[table("user_deletions")]
+pub struct UserDeletion {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub status: String,
+    pub requested_at: DateTime<Utc>,
+    pub deadline_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// A downstream service's confirmation of a deletion.
+#[derive(Debug, Serialize, FromRow, Clone)]
+#[table("user_deletion_acks")]
+pub struct DeletionAck {
+    #[serde(skip_serializing)]
+    pub deletion_id: Uuid,
+    pub service: String,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
@@ -0,0 +1,46 @@
+//! Object storage for archives, picked by `object_storage.provider`: a
+//! local directory for development or an S3-compatible bucket.
+
+use config::ConfigError;
+use object_store::aws::AmazonS3Builder;
+use object_store::local::LocalFileSystem;
+use object_store::ObjectStore;
+use std::sync::Arc;
+
+use crate::config::{ObjectStorageProvider, ObjectStorageSettings};
+
+/// Builds the store selected by `object_storage.provider`.
+pub fn from_settings(settings: &ObjectStorageSettings) -> Result<Arc<dyn ObjectStore>, ConfigError> {
+    match settings.provider {
+        ObjectStorageProvider::Local => {
+            std::fs::create_dir_all(&settings.path).map_err(|e| {
+                ConfigError::Message(format!("object_storage.path {} can't be created: {}", settings.path, e))
+            })?;
+            let store = LocalFileSystem::new_with_prefix(&settings.path)
+                .map_err(|e| ConfigError::Message(format!("invalid object_storage.path: {}", e)))?;
+            Ok(Arc::new(store))
+        }
+        ObjectStorageProvider::S3 => {
+            let bucket = settings
+                .bucket
+                .as_deref()
+                .ok_or_else(|| ConfigError::Message("object_storage.bucket is required for s3".into()))?;
+
+            let mut builder = AmazonS3Builder::from_env().with_bucket_name(bucket);
+            if let Some(region) = &settings.region {
+                builder = builder.with_region(region);
+            }
+            if let Some(endpoint) = &settings.endpoint {
+                builder = builder.with_endpoint(endpoint).with_allow_http(endpoint.starts_with("http://"));
+            }
+            if let (Some(key_id), Some(secret)) = (&settings.access_key_id, &settings.secret_access_key) {
+                builder = builder.with_access_key_id(key_id).with_secret_access_key(secret);
+            }
+
+            let store = builder
+                .build()
+                .map_err(|e| ConfigError::Message(format!("invalid object_storage settings: {}", e)))?;
+            Ok(Arc::new(store))
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use config::ConfigError;
+use futures_util::future::BoxFuture;
+use reqwest::Client;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::EventSink;
+use crate::config::OlapSettings;
+use crate::egress::EgressPolicy;
+use crate::errors::{AppError, AppResult, RetryHint};
+use crate::health::{HealthCheck, HttpDependency};
+
+/// Inserts rows through ClickHouse's HTTP interface as `JSONEachRow`, so
+/// tables only need columns named after the event fields.
+pub struct ClickHouseSink {
+    client: Client,
+    url: String,
+    database: String,
+    user: Option<String>,
+    password: Option<String>,
+}
+
+impl ClickHouseSink {
+    pub fn from_settings(settings: &OlapSettings, egress: &EgressPolicy) -> Result<Self, ConfigError> {
+        egress.check_endpoint("olap.clickhouse_url", &settings.clickhouse_url)?;
+        let client = egress
+            .client_builder()
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .build()
+            .map_err(|e| ConfigError::Message(format!("failed to build the ClickHouse client: {}", e)))?;
+
+        Ok(Self {
+            client,
+            url: settings.clickhouse_url.trim_end_matches('/').to_string(),
+            database: settings.clickhouse_database.clone(),
+            user: settings.clickhouse_user.clone(),
+            password: settings.clickhouse_password.clone(),
+        })
+    }
+}
+
+impl EventSink for ClickHouseSink {
+    fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+        vec![Arc::new(HttpDependency::new("clickhouse", self.client.clone(), &format!("{}/ping", self.url)))]
+    }
+
+    fn write<'a>(&'a self, table: &'a str, rows: &'a [serde_json::Value]) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let mut body = String::new();
+            for row in rows {
+                body.push_str(&row.to_string());
+                body.push('\n');
+            }
+
+            let query = format!("INSERT INTO `{}`.`{}` FORMAT JSONEachRow", self.database, table);
+            let mut request = self
+                .client
+                .post(format!("{}/", self.url))
+                // Timestamps are serialized as RFC 3339
+                .query(&[("query", query.as_str()), ("date_time_input_format", "best_effort")])
+                .body(body);
+            if let Some(user) = &self.user {
+                request = request.header("X-ClickHouse-User", user);
+            }
+            if let Some(password) = &self.password {
+                request = request.header("X-ClickHouse-Key", password);
+            }
+
+            let response = request.send().await.and_then(|response| response.error_for_status());
+            response.map(|_| ()).map_err(|e| {
+                tracing::warn!("ClickHouse rejected {} rows for {}: {}", rows.len(), table, e);
+                AppError::Unavailable {
+                    message: "OLAP event write failed".to_string(),
+                    retry: RetryHint::after(Duration::from_secs(5)),
+                }
+            })
+        })
+    }
+}
@@ -0,0 +1,278 @@
+//! Per-request and domain events for an OLAP store, where Prometheus'
+//! aggregates aren't enough.
+//!
+//! Events are recorded into an [`EventBuffer`] without waiting: the
+//! `request_events` middleware records every request, and
+//! [`AuditService`](crate::services::AuditService) every audit event. The
+//! buffer writes them to the [`EventSink`] picked by `olap.provider` every
+//! `olap.flush_interval_ms`, `olap.batch_size` at a time, retrying failed
+//! batches. While the sink is slow or down the buffer fills up, and once
+//! `olap.buffer_size` events are waiting new ones are dropped and counted
+//! in `olap_events_dropped_total`, so the store never slows requests down.
+//!
+//! Other stores plug in by implementing [`EventSink`], which receives rows
+//! as JSON objects.
+
+use chrono::{DateTime, Utc};
+use config::ConfigError;
+use futures_util::future::BoxFuture;
+use platform_core::domain::UserId;
+use platform_core::lifecycle::{Hook, Lifecycle};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::config::{OlapProvider, OlapSettings};
+use crate::egress::EgressPolicy;
+use crate::errors::AppResult;
+use crate::health::HealthCheck;
+use crate::AppState;
+
+pub mod clickhouse;
+
+pub use clickhouse::ClickHouseSink;
+
+const RETRY_BASE: Duration = Duration::from_millis(200);
+
+pub trait EventSink: Send + Sync {
+    /// Inserts `rows` into `table`.
+    fn write<'a>(&'a self, table: &'a str, rows: &'a [serde_json::Value]) -> BoxFuture<'a, AppResult<()>>;
+
+    /// Readiness checks of the store this sink writes to.
+    fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+        Vec::new()
+    }
+}
+
+/// Builds the sink selected by `olap.provider`, or `None` when events
+/// aren't recorded. ClickHouse is reached through `egress`.
+pub fn from_settings(
+    settings: &OlapSettings,
+    egress: &EgressPolicy,
+) -> Result<Option<Arc<dyn EventSink>>, ConfigError> {
+    match settings.provider {
+        OlapProvider::None => Ok(None),
+        OlapProvider::Console => Ok(Some(Arc::new(ConsoleEventSink::default()))),
+        OlapProvider::ClickHouse => Ok(Some(Arc::new(ClickHouseSink::from_settings(settings, egress)?))),
+    }
+}
+
+/// A handled request.
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestEvent {
+    pub timestamp: DateTime<Utc>,
+    pub request_id: Option<String>,
+    pub method: String,
+    /// The matched route pattern, e.g. `/api/v1/users/{id}`, so requests
+    /// group by endpoint; `None` for unknown paths and requests middleware
+    /// rejected.
+    pub route: Option<String>,
+    pub status: u16,
+    pub duration_ms: f64,
+    pub user_id: Option<UserId>,
+}
+
+/// Something that happened in the domain, such as an audit event.
+#[derive(Debug, Clone, Serialize)]
+pub struct DomainEvent {
+    pub timestamp: DateTime<Utc>,
+    pub event_type: String,
+    pub user_id: Option<UserId>,
+    /// JSON text, which any store can hold.
+    pub metadata: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum OlapEvent {
+    Request(RequestEvent),
+    Domain(DomainEvent),
+}
+
+/// Holds recorded events until they are written; see the [module
+/// docs](self).
+pub struct EventBuffer {
+    sink: Option<Arc<dyn EventSink>>,
+    settings: OlapSettings,
+    sender: mpsc::Sender<OlapEvent>,
+    receiver: tokio::sync::Mutex<mpsc::Receiver<OlapEvent>>,
+    dropped: AtomicU64,
+}
+
+impl EventBuffer {
+    pub fn new(sink: Option<Arc<dyn EventSink>>, settings: OlapSettings) -> Self {
+        let (sender, receiver) = mpsc::channel(settings.buffer_size.max(1));
+        Self {
+            sink,
+            settings,
+            sender,
+            receiver: tokio::sync::Mutex::new(receiver),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    /// Queues `event` for writing, or drops it if the buffer is full.
+    pub fn record(&self, event: OlapEvent) {
+        if !self.enabled() {
+            return;
+        }
+        if self.sender.try_send(event).is_err() {
+            self.drop_events(1);
+        }
+    }
+
+    /// Events dropped so far, because the buffer was full or their batch
+    /// kept failing.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Writes every buffered event and returns how many were written.
+    pub async fn flush(&self) -> usize {
+        let Some(sink) = &self.sink else {
+            return 0;
+        };
+        let mut receiver = self.receiver.lock().await;
+        let mut written = 0;
+
+        loop {
+            let mut batch = Vec::new();
+            while batch.len() < self.settings.batch_size.max(1) {
+                match receiver.try_recv() {
+                    Ok(event) => batch.push(event),
+                    Err(_) => break,
+                }
+            }
+            if batch.is_empty() {
+                return written;
+            }
+
+            for (table, rows) in self.rows_by_table(batch) {
+                if self.write(sink.as_ref(), table, &rows).await {
+                    written += rows.len();
+                } else {
+                    self.drop_events(rows.len());
+                }
+            }
+        }
+    }
+
+    /// Calls [`flush`](Self::flush) every `olap.flush_interval_ms` until
+    /// `shutdown` completes, then once more.
+    pub async fn flush_until(&self, shutdown: impl Future<Output = ()>) {
+        let mut interval = tokio::time::interval(Duration::from_millis(self.settings.flush_interval_ms));
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    self.flush().await;
+                }
+                _ = &mut shutdown => break,
+            }
+        }
+        self.flush().await;
+    }
+
+    fn rows_by_table(&self, batch: Vec<OlapEvent>) -> BTreeMap<&str, Vec<serde_json::Value>> {
+        let mut tables: BTreeMap<&str, Vec<serde_json::Value>> = BTreeMap::new();
+        for event in batch {
+            let (table, row) = match event {
+                OlapEvent::Request(event) => (&self.settings.request_table, serde_json::to_value(event)),
+                OlapEvent::Domain(event) => (&self.settings.domain_table, serde_json::to_value(event)),
+            };
+            match row {
+                Ok(row) => tables.entry(table.as_str()).or_default().push(row),
+                Err(e) => tracing::warn!("Failed to serialize OLAP event: {}", e),
+            }
+        }
+        tables
+    }
+
+    /// Writes `rows`, retrying with exponential backoff; `false` once out
+    /// of attempts.
+    async fn write(&self, sink: &dyn EventSink, table: &str, rows: &[serde_json::Value]) -> bool {
+        for attempt in 0..self.settings.max_attempts.max(1) {
+            if attempt > 0 {
+                tokio::time::sleep(RETRY_BASE * 2u32.pow(attempt - 1)).await;
+            }
+            match sink.write(table, rows).await {
+                Ok(()) => return true,
+                Err(e) => tracing::warn!(table, rows = rows.len(), attempt, "Failed to write OLAP events: {}", e),
+            }
+        }
+        false
+    }
+
+    fn drop_events(&self, count: usize) {
+        self.dropped.fetch_add(count as u64, Ordering::Relaxed);
+        metrics::counter!("olap_events_dropped_total").increment(count as u64);
+    }
+}
+
+/// Flushes the buffer from startup until shutdown, when the remaining
+/// events are written. Nothing when `olap.provider` is `none`.
+pub fn register_hooks(lifecycle: &mut Lifecycle, state: &Arc<AppState>) {
+    if !state.olap_events.enabled() {
+        return;
+    }
+
+    let buffer = state.olap_events.clone();
+    let (stop, stopped) = oneshot::channel::<()>();
+    let (started, flushing) = oneshot::channel();
+    lifecycle.on_start(Hook::new("olap events", move || async move {
+        let stopped = async move {
+            let _ = stopped.await;
+        };
+        let _ = started.send(tokio::spawn(async move { buffer.flush_until(stopped).await }));
+        Ok(())
+    }));
+    lifecycle.on_shutdown(Hook::new("olap events", move || async move {
+        let _ = stop.send(());
+        if let Ok(flushing) = flushing.await {
+            flushing.await?;
+        }
+        Ok(())
+    }));
+}
+
+/// How many rows [`ConsoleEventSink`] keeps.
+const OUTBOX_CAPACITY: usize = 1000;
+
+/// Logs rows instead of writing them and keeps the latest ones, so events
+/// can be inspected in development and from [`outbox`](Self::outbox) in
+/// tests.
+#[derive(Default)]
+pub struct ConsoleEventSink {
+    outbox: Mutex<VecDeque<(String, serde_json::Value)>>,
+}
+
+impl ConsoleEventSink {
+    /// Rows written so far with their table, oldest first.
+    pub fn outbox(&self) -> Vec<(String, serde_json::Value)> {
+        self.outbox.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl EventSink for ConsoleEventSink {
+    fn write<'a>(&'a self, table: &'a str, rows: &'a [serde_json::Value]) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let mut outbox = self.outbox.lock().unwrap();
+            for row in rows {
+                tracing::debug!(target: "olap", table, %row, "OLAP event");
+                if outbox.len() == OUTBOX_CAPACITY {
+                    outbox.pop_front();
+                }
+                outbox.push_back((table.to_string(), row.clone()));
+            }
+            Ok(())
+        })
+    }
+}
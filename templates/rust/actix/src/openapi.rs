@@ -0,0 +1,100 @@
+//! The OpenAPI document of the health, auth and user endpoints, served at
+//! `/api-docs/openapi.json` with Swagger UI at `/swagger-ui/`.
+//!
+//! Each handler describes itself with `#[utoipa::path]` above its other
+//! attributes, and the request and response models derive `ToSchema`, so
+//! the document follows the structs the handlers actually use. A new
+//! handler is documented by annotating it and listing it in [`ApiDoc`]:
+//!
+//! ```ignore
+//! #[utoipa::path(get, path = "/api/v1/users", tag = "users", responses((status = 200, body = PaginatedUsers)))]
+//! #[authorize("read:users")]
+//! #[get("")]
+//! pub async fn get_users(...) -> AppResult<HttpResponse> { ... }
+//! ```
+//!
+//! Property names are as the structs serialize them; with
+//! `server.json_naming = camel_case` clients see them camelCased.
+
+use once_cell::sync::Lazy;
+use platform_core::domain::{Email, UserId, Username};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::config::CountMode;
+use crate::errors::ErrorResponse;
+use crate::handlers::{email_verification, health, password_reset, users};
+use crate::health::{CircuitState, DependencyStatus};
+use crate::models::email_verification::{ResendVerificationRequest, VerifyEmailRequest};
+use crate::models::password_reset::{ForgotPasswordRequest, ResetPasswordRequest};
+use crate::models::user::{CreateUser, LoginRequest, LoginResponse, PaginatedUsers, UpdateUser, UserResponse};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        health::health_check,
+        health::readiness_check,
+        users::register,
+        users::login,
+        users::refresh,
+        users::logout,
+        email_verification::verify_email,
+        email_verification::resend_verification,
+        password_reset::forgot_password,
+        password_reset::reset_password,
+        users::get_users,
+        users::get_user,
+        users::create_user,
+        users::update_user,
+        users::delete_user,
+    ),
+    components(schemas(
+        UserId,
+        Email,
+        Username,
+        CountMode,
+        ErrorResponse,
+        CreateUser,
+        UpdateUser,
+        LoginRequest,
+        LoginResponse,
+        UserResponse,
+        PaginatedUsers,
+        users::RefreshTokenRequest,
+        VerifyEmailRequest,
+        ResendVerificationRequest,
+        ForgotPasswordRequest,
+        ResetPasswordRequest,
+        health::HealthResponse,
+        health::ReadinessResponse,
+        DependencyStatus,
+        CircuitState,
+    )),
+    modifiers(&BearerAuth),
+    tags(
+        (name = "health", description = "Liveness and readiness"),
+        (name = "auth", description = "Accounts and sessions"),
+        (name = "users", description = "User management"),
+    ),
+)]
+pub struct ApiDoc;
+
+/// Session JWTs and API tokens, both sent as `Authorization: Bearer`.
+/// Operations list the scope they need.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()),
+        );
+    }
+}
+
+/// The document, built once.
+pub fn spec() -> &'static utoipa::openapi::OpenApi {
+    static SPEC: Lazy<utoipa::openapi::OpenApi> = Lazy::new(ApiDoc::openapi);
+    &SPEC
+}
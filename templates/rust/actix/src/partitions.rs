@@ -0,0 +1,342 @@
+//! Monthly partitions of high-volume tables.
+//!
+//! Tables listed in `partitions.tables` are partitioned by range on a
+//! timestamp, with one partition per UTC month named `<table>_pYYYYMM` and
+//! a default partition catching rows outside them; migration 014 sets this
+//! up for `audit_events`, and other append-only tables such as an outbox
+//! opt in the same way. Every `partitions.interval_secs` the
+//! [`PartitionManager`]:
+//!
+//! 1. creates the partitions of the current month and the
+//!    `premake_months` after it, and of any month rows in the default
+//!    partition belong to, moving those rows over;
+//! 2. detaches partitions whose month ended `retention_months` ago, which
+//!    takes their rows out of queries at once;
+//! 3. writes detached partitions to object storage as Parquet when the
+//!    table's `archive` is set, then drops them.
+//!
+//! A partition whose upload fails stays detached and is archived on the
+//! next run, so rows are never dropped without their archive.
+
+use chrono::{Datelike, Months, NaiveDate};
+use object_store::path::Path;
+use object_store::ObjectStore;
+use platform_core::lifecycle::{Hook, Lifecycle};
+use sqlx::{Connection, PgConnection, PgPool};
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+use crate::archive;
+use crate::config::{PartitionSettings, PartitionedTableSettings};
+use crate::db::quote_ident;
+use crate::errors::{AppResult, ResultExt};
+use crate::utils::SharedClock;
+use crate::AppState;
+
+/// Advisory lock key letting one replica at a time maintain partitions.
+const PARTITION_LOCK_KEY: i64 = 0x6478_705f_7061_7274;
+
+/// What a maintenance run changed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MaintenanceReport {
+    pub created: Vec<String>,
+    pub detached: Vec<String>,
+    /// Object storage paths of the archives written.
+    pub archived: Vec<String>,
+    pub dropped: Vec<String>,
+}
+
+pub struct PartitionManager {
+    db: PgPool,
+    settings: PartitionSettings,
+    store: Arc<dyn ObjectStore>,
+    clock: SharedClock,
+}
+
+impl PartitionManager {
+    pub fn new(db: PgPool, settings: PartitionSettings, store: Arc<dyn ObjectStore>, clock: SharedClock) -> Self {
+        Self {
+            db,
+            settings,
+            store,
+            clock,
+        }
+    }
+
+    /// Maintains every configured table once. `None` when another replica
+    /// is already at it.
+    pub async fn maintain(&self) -> AppResult<Option<MaintenanceReport>> {
+        let mut conn = self.db.acquire().await.context("acquire connection")?;
+        let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+            .bind(PARTITION_LOCK_KEY)
+            .fetch_one(&mut *conn)
+            .await
+            .context("lock partitions")?;
+        if !locked {
+            return Ok(None);
+        }
+
+        let mut report = MaintenanceReport::default();
+        let mut tables: Vec<_> = self.settings.tables.iter().collect();
+        tables.sort_by_key(|(table, _)| *table);
+        let mut result = Ok(());
+        for (table, settings) in tables {
+            result = self.maintain_table(&mut conn, table, settings, &mut report).await;
+            if result.is_err() {
+                break;
+            }
+        }
+
+        sqlx::query("SELECT pg_advisory_unlock($1)")
+            .bind(PARTITION_LOCK_KEY)
+            .execute(&mut *conn)
+            .await
+            .context("unlock partitions")?;
+
+        result.map(|_| Some(report))
+    }
+
+    /// Calls [`maintain`](Self::maintain) every `partitions.interval_secs`
+    /// until `shutdown` completes.
+    pub async fn maintain_until(&self, shutdown: impl Future<Output = ()>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.settings.interval_secs));
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.maintain().await {
+                        tracing::warn!("Failed to maintain partitions: {}", e);
+                    }
+                }
+                _ = &mut shutdown => break,
+            }
+        }
+    }
+
+    async fn maintain_table(
+        &self,
+        conn: &mut PgConnection,
+        table: &str,
+        settings: &PartitionedTableSettings,
+        report: &mut MaintenanceReport,
+    ) -> AppResult<()> {
+        let key: Option<String> = sqlx::query_scalar("SELECT pg_get_partkeydef($1::regclass)")
+            .bind(quote_ident(table))
+            .fetch_one(&mut *conn)
+            .await
+            .entity_context("load partition key", table)?;
+        let Some(key) = key.as_deref().and_then(range_column) else {
+            tracing::warn!("Skipping {}: it isn't partitioned by range on a column", table);
+            return Ok(());
+        };
+
+        let partitions: Vec<(String, bool)> = sqlx::query_as(
+            r#"
+            SELECT c.relname::text, pg_get_expr(c.relpartbound, c.oid) = 'DEFAULT'
+            FROM pg_inherits i JOIN pg_class c ON c.oid = i.inhrelid
+            WHERE i.inhparent = $1::regclass
+            "#,
+        )
+        .bind(quote_ident(table))
+        .fetch_all(&mut *conn)
+        .await
+        .entity_context("list partitions", table)?;
+        let default = partitions.iter().find(|(_, is_default)| *is_default).map(|(name, _)| name.as_str());
+        let mut attached: BTreeSet<NaiveDate> =
+            partitions.iter().filter_map(|(name, _)| partition_month(table, name)).collect();
+
+        // Upcoming months, and past or far-off ones that rows ended up in
+        let current = month_of(self.clock.now().date_naive());
+        let mut wanted: BTreeSet<NaiveDate> = (0..=self.settings.premake_months)
+            .map(|months| current + Months::new(months))
+            .collect();
+        if let Some(default) = default {
+            let months: Vec<NaiveDate> = sqlx::query_scalar(&format!(
+                "SELECT DISTINCT date_trunc('month', {} AT TIME ZONE 'UTC')::date FROM {}",
+                quote_ident(key),
+                quote_ident(default),
+            ))
+            .fetch_all(&mut *conn)
+            .await
+            .entity_context("list months of the default partition", table)?;
+            wanted.extend(months);
+        }
+
+        let missing: Vec<NaiveDate> = wanted.difference(&attached).copied().collect();
+        for month in missing {
+            let name = self.create_partition(conn, table, default, key, month).await?;
+            attached.insert(month);
+            report.created.push(name);
+        }
+
+        if settings.retention_months == 0 {
+            return Ok(());
+        }
+        let cutoff = current - Months::new(settings.retention_months);
+        let expired = |month: &NaiveDate| *month + Months::new(1) <= cutoff;
+
+        for month in attached.iter().filter(|month| expired(month)) {
+            let name = partition_name(table, *month);
+            sqlx::query(&format!("ALTER TABLE {} DETACH PARTITION {}", quote_ident(table), quote_ident(&name)))
+                .execute(&mut *conn)
+                .await
+                .entity_context("detach partition", &name)?;
+            tracing::info!("Detached partition {}", name);
+            report.detached.push(name);
+        }
+
+        // Including those detached by runs that failed to archive them
+        let detached: Vec<String> = sqlx::query_scalar(
+            r#"
+            SELECT relname::text FROM pg_class
+            WHERE relkind = 'r' AND NOT relispartition AND pg_table_is_visible(oid) AND starts_with(relname, $1)
+            ORDER BY relname
+            "#,
+        )
+        .bind(format!("{}_p", table))
+        .fetch_all(&mut *conn)
+        .await
+        .entity_context("list detached partitions", table)?;
+
+        for name in detached {
+            if !partition_month(table, &name).is_some_and(|month| expired(&month)) {
+                continue;
+            }
+
+            if settings.archive {
+                let path = Path::from(format!("{}/{}/{}.parquet", self.settings.archive_prefix, table, name));
+                let rows = archive::write_table(conn, &name, self.store.clone(), &path).await?;
+                tracing::info!("Archived {} rows of {} to {}", rows, name, path);
+                report.archived.push(path.to_string());
+            }
+
+            sqlx::query(&format!("DROP TABLE {}", quote_ident(&name)))
+                .execute(&mut *conn)
+                .await
+                .entity_context("drop partition", &name)?;
+            tracing::info!("Dropped partition {}", name);
+            report.dropped.push(name);
+        }
+
+        Ok(())
+    }
+
+    /// Creates and attaches the partition of `month`, first moving its rows
+    /// out of the default partition, which would otherwise reject it.
+    async fn create_partition(
+        &self,
+        conn: &mut PgConnection,
+        table: &str,
+        default: Option<&str>,
+        key: &str,
+        month: NaiveDate,
+    ) -> AppResult<String> {
+        let name = partition_name(table, month);
+        let (from, to) = (bound(month), bound(month + Months::new(1)));
+        let mut tx = conn.begin().await.context("begin partition creation")?;
+
+        // Writers wait rather than add rows the attach would reject
+        if let Some(default) = default {
+            sqlx::query(&format!("LOCK TABLE {} IN ACCESS EXCLUSIVE MODE", quote_ident(default)))
+                .execute(&mut *tx)
+                .await
+                .entity_context("lock default partition", default)?;
+        }
+        sqlx::query(&format!(
+            "CREATE TABLE {} (LIKE {} INCLUDING DEFAULTS INCLUDING CONSTRAINTS)",
+            quote_ident(&name),
+            quote_ident(table),
+        ))
+        .execute(&mut *tx)
+        .await
+        .entity_context("create partition", &name)?;
+        if let Some(default) = default {
+            sqlx::query(&format!(
+                "WITH moved AS (DELETE FROM {default} WHERE {key} >= {from} AND {key} < {to} RETURNING *) \
+                 INSERT INTO {name} SELECT * FROM moved",
+                default = quote_ident(default),
+                key = quote_ident(key),
+                name = quote_ident(&name),
+            ))
+            .execute(&mut *tx)
+            .await
+            .entity_context("move rows out of the default partition", &name)?;
+        }
+        sqlx::query(&format!(
+            "ALTER TABLE {} ATTACH PARTITION {} FOR VALUES FROM ({}) TO ({})",
+            quote_ident(table),
+            quote_ident(&name),
+            from,
+            to,
+        ))
+        .execute(&mut *tx)
+        .await
+        .entity_context("attach partition", &name)?;
+
+        tx.commit().await.context("commit partition creation")?;
+        tracing::info!("Created partition {}", name);
+        Ok(name)
+    }
+}
+
+/// Maintains partitions from startup until shutdown. Nothing when
+/// `partitions.enabled` is off.
+pub fn register_hooks(lifecycle: &mut Lifecycle, state: &Arc<AppState>) {
+    if !state.settings.partitions.enabled {
+        return;
+    }
+
+    let manager = state.partition_manager.clone();
+    let (stop, stopped) = oneshot::channel::<()>();
+    let (started, maintaining) = oneshot::channel();
+    lifecycle.on_start(Hook::new("partitions", move || async move {
+        let stopped = async move {
+            let _ = stopped.await;
+        };
+        let _ = started.send(tokio::spawn(async move { manager.maintain_until(stopped).await }));
+        Ok(())
+    }));
+    lifecycle.on_shutdown(Hook::new("partitions", move || async move {
+        let _ = stop.send(());
+        if let Ok(maintaining) = maintaining.await {
+            maintaining.await?;
+        }
+        Ok(())
+    }));
+}
+
+/// `table_pYYYYMM`.
+pub fn partition_name(table: &str, month: NaiveDate) -> String {
+    format!("{}_p{:04}{:02}", table, month.year(), month.month())
+}
+
+/// The month of a partition named by [`partition_name`].
+fn partition_month(table: &str, name: &str) -> Option<NaiveDate> {
+    let suffix = name.strip_prefix(table)?.strip_prefix("_p")?;
+    if suffix.len() != 6 || !suffix.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(suffix[..4].parse().ok()?, suffix[4..].parse().ok()?, 1)
+}
+
+fn month_of(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).expect("every month has a first day")
+}
+
+/// The start of `month` in UTC as an SQL literal.
+fn bound(month: NaiveDate) -> String {
+    format!("'{} 00:00:00+00'", month.format("%Y-%m-%d"))
+}
+
+/// The column of a `RANGE (column)` partition key definition.
+fn range_column(definition: &str) -> Option<&str> {
+    let column = definition.strip_prefix("RANGE (")?.strip_suffix(')')?;
+    column
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+        .then_some(column)
+}
@@ -0,0 +1,183 @@
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use config::ConfigError;
+use futures_util::future::BoxFuture;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::config::PushSettings;
+use crate::egress::EgressPolicy;
+use crate::errors::AppResult;
+use crate::health::{HealthCheck, HttpDependency};
+use crate::models::push_device::PushPlatform;
+use crate::push::{delivery_failed, PushNotification, PushOutcome, PushSender};
+
+/// Apple rejects provider tokens older than an hour and throttles ones
+/// refreshed more often than every 20 minutes.
+const PROVIDER_TOKEN_TTL_MINUTES: i64 = 50;
+
+/// Reasons APNs answers for tokens that will never work again.
+const REJECTED_TOKEN_REASONS: &[&str] = &["BadDeviceToken", "DeviceTokenNotForTopic", "Unregistered"];
+
+#[derive(Serialize)]
+struct ProviderClaims<'a> {
+    iss: &'a str,
+    iat: i64,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    reason: String,
+}
+
+struct ProviderToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Sends through the APNs HTTP/2 API with token-based authentication.
+pub struct ApnsPushSender {
+    client: Client,
+    api_url: String,
+    key_id: String,
+    team_id: String,
+    topic: String,
+    key: EncodingKey,
+    provider_token: Mutex<Option<ProviderToken>>,
+}
+
+impl ApnsPushSender {
+    pub fn from_settings(settings: &PushSettings, egress: &EgressPolicy) -> Result<Self, ConfigError> {
+        let required = |value: &Option<String>, key: &str| {
+            value
+                .clone()
+                .filter(|value| !value.is_empty())
+                .ok_or_else(|| ConfigError::Message(format!("push.{} is required for APNs", key)))
+        };
+        let key_path = required(&settings.apns_key_path, "apns_key_path")?;
+        let key_id = required(&settings.apns_key_id, "apns_key_id")?;
+        let team_id = required(&settings.apns_team_id, "apns_team_id")?;
+        let topic = required(&settings.apns_topic, "apns_topic")?;
+
+        let pem = std::fs::read(&key_path)
+            .map_err(|e| ConfigError::Message(format!("failed to read APNs key {}: {}", key_path, e)))?;
+        let key = EncodingKey::from_ec_pem(&pem)
+            .map_err(|e| ConfigError::Message(format!("invalid APNs key {}: {}", key_path, e)))?;
+
+        // APNs only speaks HTTP/2, negotiated over ALPN
+        egress.check_endpoint("push.apns_api_url", &settings.apns_api_url)?;
+        let client = egress
+            .client_builder()
+            .use_rustls_tls()
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .build()
+            .map_err(|e| ConfigError::Message(format!("failed to build the APNs client: {}", e)))?;
+
+        Ok(Self {
+            client,
+            api_url: settings.apns_api_url.trim_end_matches('/').to_string(),
+            key_id,
+            team_id,
+            topic,
+            key,
+            provider_token: Mutex::new(None),
+        })
+    }
+
+    async fn provider_token(&self) -> AppResult<String> {
+        let mut cached = self.provider_token.lock().await;
+        let now = Utc::now();
+        if let Some(token) = cached.as_ref().filter(|token| token.expires_at > now) {
+            return Ok(token.token.clone());
+        }
+
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(self.key_id.clone());
+        let claims = ProviderClaims {
+            iss: &self.team_id,
+            iat: now.timestamp(),
+        };
+        let token = encode(&header, &claims, &self.key)
+            .map_err(|e| delivery_failed("APNs", format!("signing the provider token: {}", e)))?;
+
+        *cached = Some(ProviderToken {
+            token: token.clone(),
+            expires_at: now + ChronoDuration::minutes(PROVIDER_TOKEN_TTL_MINUTES),
+        });
+        Ok(token)
+    }
+
+    fn payload(notification: &PushNotification) -> Value {
+        let aps = if notification.is_silent() {
+            json!({ "content-available": 1 })
+        } else {
+            json!({ "alert": { "title": notification.title, "body": notification.body } })
+        };
+
+        let mut payload = json!({ "aps": aps });
+        for (key, value) in &notification.data {
+            payload[key] = json!(value);
+        }
+        payload
+    }
+}
+
+impl PushSender for ApnsPushSender {
+    fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+        vec![Arc::new(HttpDependency::new("apns", self.client.clone(), &self.api_url))]
+    }
+
+    fn supports(&self, platform: PushPlatform) -> bool {
+        platform == PushPlatform::Apns
+    }
+
+    fn send<'a>(
+        &'a self,
+        _platform: PushPlatform,
+        token: &'a str,
+        notification: &'a PushNotification,
+    ) -> BoxFuture<'a, AppResult<PushOutcome>> {
+        Box::pin(async move {
+            let provider_token = self.provider_token().await?;
+            let (push_type, priority) = if notification.is_silent() {
+                ("background", "5")
+            } else {
+                ("alert", "10")
+            };
+
+            let response = self
+                .client
+                .post(format!("{}/3/device/{}", self.api_url, token))
+                .bearer_auth(provider_token)
+                .header("apns-topic", &self.topic)
+                .header("apns-push-type", push_type)
+                .header("apns-priority", priority)
+                .json(&Self::payload(notification))
+                .send()
+                .await
+                .map_err(|e| delivery_failed("APNs", e))?;
+
+            let status = response.status();
+            if status.is_success() {
+                return Ok(PushOutcome::Delivered);
+            }
+
+            let reason = response
+                .json::<ErrorBody>()
+                .await
+                .map(|body| body.reason)
+                .unwrap_or_default();
+            if status == StatusCode::GONE || REJECTED_TOKEN_REASONS.contains(&reason.as_str()) {
+                return Ok(PushOutcome::TokenRejected);
+            }
+            if reason == "ExpiredProviderToken" {
+                *self.provider_token.lock().await = None;
+            }
+            Err(delivery_failed("APNs", format!("{} {}", status, reason)))
+        })
+    }
+}
@@ -0,0 +1,201 @@
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use config::ConfigError;
+use futures_util::future::BoxFuture;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::config::PushSettings;
+use crate::egress::EgressPolicy;
+use crate::errors::AppResult;
+use crate::health::{HealthCheck, HttpDependency};
+use crate::models::push_device::PushPlatform;
+use crate::push::{delivery_failed, PushNotification, PushOutcome, PushSender};
+
+const MESSAGING_SCOPE: &str = "https://www.googleapis.com/auth/firebase.messaging";
+
+/// Error codes FCM answers for tokens that will never work again.
+const REJECTED_TOKEN_ERRORS: &[&str] = &["UNREGISTERED", "SENDER_ID_MISMATCH"];
+
+/// The fields of a Google service account key file the sender needs.
+#[derive(Debug, Deserialize)]
+struct ServiceAccount {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+#[derive(Serialize)]
+struct AssertionClaims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Deserialize)]
+struct AccessTokenResponse {
+    access_token: String,
+    expires_in: i64,
+}
+
+struct AccessToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Sends through the FCM HTTP v1 API, authenticating as a service account.
+pub struct FcmPushSender {
+    client: Client,
+    api_url: String,
+    send_url: String,
+    account: ServiceAccount,
+    key: EncodingKey,
+    access_token: Mutex<Option<AccessToken>>,
+}
+
+impl FcmPushSender {
+    pub fn from_settings(settings: &PushSettings, egress: &EgressPolicy) -> Result<Self, ConfigError> {
+        let project_id = settings
+            .fcm_project_id
+            .clone()
+            .ok_or_else(|| ConfigError::Message("push.fcm_project_id is required for FCM".to_string()))?;
+        let path = settings
+            .fcm_service_account_path
+            .as_deref()
+            .ok_or_else(|| ConfigError::Message("push.fcm_service_account_path is required for FCM".to_string()))?;
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::Message(format!("failed to read FCM service account {}: {}", path, e)))?;
+        let account: ServiceAccount = serde_json::from_str(&contents)
+            .map_err(|e| ConfigError::Message(format!("invalid FCM service account {}: {}", path, e)))?;
+        let key = EncodingKey::from_rsa_pem(account.private_key.as_bytes())
+            .map_err(|e| ConfigError::Message(format!("invalid FCM service account key: {}", e)))?;
+
+        egress.check_endpoint("push.fcm_api_url", &settings.fcm_api_url)?;
+        egress.check_endpoint("the FCM service account's token_uri", &account.token_uri)?;
+        let client = egress
+            .client_builder()
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .build()
+            .map_err(|e| ConfigError::Message(format!("failed to build the FCM client: {}", e)))?;
+
+        let api_url = settings.fcm_api_url.trim_end_matches('/').to_string();
+
+        Ok(Self {
+            client,
+            send_url: format!("{}/v1/projects/{}/messages:send", api_url, project_id),
+            api_url,
+            account,
+            key,
+            access_token: Mutex::new(None),
+        })
+    }
+
+    /// An OAuth access token, exchanged for a signed assertion and reused
+    /// until shortly before it expires.
+    async fn access_token(&self) -> AppResult<String> {
+        let mut cached = self.access_token.lock().await;
+        let now = Utc::now();
+        if let Some(token) = cached.as_ref().filter(|token| token.expires_at > now) {
+            return Ok(token.token.clone());
+        }
+
+        let claims = AssertionClaims {
+            iss: &self.account.client_email,
+            scope: MESSAGING_SCOPE,
+            aud: &self.account.token_uri,
+            iat: now.timestamp(),
+            exp: (now + ChronoDuration::hours(1)).timestamp(),
+        };
+        let assertion = encode(&Header::new(Algorithm::RS256), &claims, &self.key)
+            .map_err(|e| delivery_failed("FCM", format!("signing the token assertion: {}", e)))?;
+
+        let response: AccessTokenResponse = self
+            .client
+            .post(&self.account.token_uri)
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", assertion.as_str()),
+            ])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| delivery_failed("FCM", e))?
+            .json()
+            .await
+            .map_err(|e| delivery_failed("FCM", e))?;
+
+        let token = response.access_token.clone();
+        *cached = Some(AccessToken {
+            token: response.access_token,
+            expires_at: now + ChronoDuration::seconds(response.expires_in - 60),
+        });
+        Ok(token)
+    }
+
+    fn message(token: &str, notification: &PushNotification) -> Value {
+        let mut message = json!({ "token": token, "data": notification.data });
+        if !notification.is_silent() {
+            message["notification"] = json!({ "title": notification.title, "body": notification.body });
+        }
+        json!({ "message": message })
+    }
+}
+
+/// Pulls the FCM error code out of an error body's `details`.
+fn error_code(body: &Value) -> Option<&str> {
+    body["error"]["details"]
+        .as_array()?
+        .iter()
+        .find_map(|detail| detail["errorCode"].as_str())
+}
+
+impl PushSender for FcmPushSender {
+    fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+        vec![Arc::new(HttpDependency::new("fcm", self.client.clone(), &self.api_url))]
+    }
+
+    fn supports(&self, platform: PushPlatform) -> bool {
+        platform == PushPlatform::Fcm
+    }
+
+    fn send<'a>(
+        &'a self,
+        _platform: PushPlatform,
+        token: &'a str,
+        notification: &'a PushNotification,
+    ) -> BoxFuture<'a, AppResult<PushOutcome>> {
+        Box::pin(async move {
+            let access_token = self.access_token().await?;
+            let response = self
+                .client
+                .post(&self.send_url)
+                .bearer_auth(access_token)
+                .json(&Self::message(token, notification))
+                .send()
+                .await
+                .map_err(|e| delivery_failed("FCM", e))?;
+
+            let status = response.status();
+            if status.is_success() {
+                return Ok(PushOutcome::Delivered);
+            }
+            if status == StatusCode::UNAUTHORIZED {
+                // Revoked or rotated credentials; sign a fresh assertion on retry
+                *self.access_token.lock().await = None;
+            }
+
+            let body: Value = response.json().await.unwrap_or_default();
+            match error_code(&body) {
+                Some(code) if REJECTED_TOKEN_ERRORS.contains(&code) => Ok(PushOutcome::TokenRejected),
+                code => Err(delivery_failed("FCM", format!("{} {}", status, code.unwrap_or("")))),
+            }
+        })
+    }
+}
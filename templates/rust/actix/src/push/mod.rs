@@ -0,0 +1,271 @@
+//! Push notifications to registered devices.
+//!
+//! [`PushService::notify_user`](crate::services::PushService::notify_user)
+//! enqueues a fan-out job, which enqueues one delivery job per device so
+//! each is retried on its own. Delivery goes through a [`PushSender`],
+//! picked by `push.provider`: [`ConsolePushSender`] logs notifications for
+//! development, [`NativePushSender`] sends them through FCM and APNs.
+//! Tokens the provider rejects as unregistered are removed.
+
+use config::ConfigError;
+use futures_util::future::BoxFuture;
+use platform_core::domain::UserId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::config::{PushProvider, PushSettings};
+use crate::egress::EgressPolicy;
+use crate::errors::{AppError, AppResult, ErrorCode, RetryHint};
+use crate::health::HealthCheck;
+use crate::jobs::{JobHandler, Worker};
+use crate::models::job::Job;
+use crate::models::push_device::PushPlatform;
+use crate::services::PushService;
+use crate::AppState;
+
+pub mod apns;
+pub mod fcm;
+
+pub use apns::ApnsPushSender;
+pub use fcm::FcmPushSender;
+
+pub const FAN_OUT_JOB: &str = "push.fan_out";
+pub const DELIVER_JOB: &str = "push.deliver";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PushNotification {
+    pub title: Option<String>,
+    pub body: Option<String>,
+    /// Custom key-value pairs handed to the app.
+    #[serde(default)]
+    pub data: BTreeMap<String, String>,
+}
+
+impl PushNotification {
+    /// A notification shown to the user.
+    pub fn alert(title: impl Into<String>, body: impl Into<String>) -> Self {
+        Self {
+            title: Some(title.into()),
+            body: Some(body.into()),
+            data: BTreeMap::new(),
+        }
+    }
+
+    /// A notification that only wakes the app up with `data`.
+    pub fn silent() -> Self {
+        Self::default()
+    }
+
+    pub fn with_data(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.data.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.title.is_none() && self.body.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushOutcome {
+    Delivered,
+    /// The provider no longer knows the token; the device should be
+    /// forgotten.
+    TokenRejected,
+}
+
+pub trait PushSender: Send + Sync {
+    fn supports(&self, platform: PushPlatform) -> bool;
+
+    /// Sends `notification` to the device `token`. Errors are transient and
+    /// the delivery is retried.
+    fn send<'a>(
+        &'a self,
+        platform: PushPlatform,
+        token: &'a str,
+        notification: &'a PushNotification,
+    ) -> BoxFuture<'a, AppResult<PushOutcome>>;
+
+    /// Readiness checks of the providers this sender calls.
+    fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+        Vec::new()
+    }
+}
+
+/// Builds the sender selected by `push.provider`, sending through `egress`.
+pub fn from_settings(settings: &PushSettings, egress: &EgressPolicy) -> Result<Arc<dyn PushSender>, ConfigError> {
+    match settings.provider {
+        PushProvider::Console => Ok(Arc::new(ConsolePushSender::default())),
+        PushProvider::Native => Ok(Arc::new(NativePushSender::from_settings(settings, egress)?)),
+    }
+}
+
+/// A delivery the [`ConsolePushSender`] logged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentPush {
+    pub platform: PushPlatform,
+    pub token: String,
+    pub notification: PushNotification,
+}
+
+/// How many notifications [`ConsolePushSender`] keeps.
+const OUTBOX_CAPACITY: usize = 100;
+
+/// Logs notifications instead of sending them and keeps the latest ones.
+/// Tokens starting with `rejected` are treated as unregistered, to exercise
+/// cleanup.
+#[derive(Default)]
+pub struct ConsolePushSender {
+    outbox: Mutex<VecDeque<SentPush>>,
+}
+
+impl ConsolePushSender {
+    /// Notifications sent so far, oldest first.
+    pub fn outbox(&self) -> Vec<SentPush> {
+        self.outbox.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl PushSender for ConsolePushSender {
+    fn supports(&self, _platform: PushPlatform) -> bool {
+        true
+    }
+
+    fn send<'a>(
+        &'a self,
+        platform: PushPlatform,
+        token: &'a str,
+        notification: &'a PushNotification,
+    ) -> BoxFuture<'a, AppResult<PushOutcome>> {
+        Box::pin(async move {
+            if token.starts_with("rejected") {
+                return Ok(PushOutcome::TokenRejected);
+            }
+            tracing::info!(%platform, "Push: {:?}", notification);
+
+            let mut outbox = self.outbox.lock().unwrap();
+            if outbox.len() == OUTBOX_CAPACITY {
+                outbox.pop_front();
+            }
+            outbox.push_back(SentPush {
+                platform,
+                token: token.to_string(),
+                notification: notification.clone(),
+            });
+            Ok(PushOutcome::Delivered)
+        })
+    }
+}
+
+/// Routes FCM tokens to FCM and APNs tokens to APNs; platforms without
+/// credentials are unsupported.
+pub struct NativePushSender {
+    fcm: Option<FcmPushSender>,
+    apns: Option<ApnsPushSender>,
+}
+
+impl NativePushSender {
+    pub fn from_settings(settings: &PushSettings, egress: &EgressPolicy) -> Result<Self, ConfigError> {
+        let fcm = match (&settings.fcm_project_id, &settings.fcm_service_account_path) {
+            (Some(_), Some(_)) => Some(FcmPushSender::from_settings(settings, egress)?),
+            _ => None,
+        };
+        let apns = match &settings.apns_key_path {
+            Some(_) => Some(ApnsPushSender::from_settings(settings, egress)?),
+            None => None,
+        };
+        if fcm.is_none() && apns.is_none() {
+            return Err(ConfigError::Message(
+                "push.provider = native needs FCM or APNs credentials".to_string(),
+            ));
+        }
+
+        Ok(Self { fcm, apns })
+    }
+}
+
+impl PushSender for NativePushSender {
+    fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+        let fcm = self.fcm.iter().flat_map(|fcm| fcm.health_checks());
+        let apns = self.apns.iter().flat_map(|apns| apns.health_checks());
+        fcm.chain(apns).collect()
+    }
+
+    fn supports(&self, platform: PushPlatform) -> bool {
+        match platform {
+            PushPlatform::Fcm => self.fcm.is_some(),
+            PushPlatform::Apns => self.apns.is_some(),
+        }
+    }
+
+    fn send<'a>(
+        &'a self,
+        platform: PushPlatform,
+        token: &'a str,
+        notification: &'a PushNotification,
+    ) -> BoxFuture<'a, AppResult<PushOutcome>> {
+        match (platform, &self.fcm, &self.apns) {
+            (PushPlatform::Fcm, Some(fcm), _) => fcm.send(platform, token, notification),
+            (PushPlatform::Apns, _, Some(apns)) => apns.send(platform, token, notification),
+            _ => Box::pin(async move { Err(unsupported_platform(platform)) }),
+        }
+    }
+}
+
+pub(crate) fn unsupported_platform(platform: PushPlatform) -> AppError {
+    AppError::BadRequest(format!("Push notifications are not configured for {}", platform))
+        .with_code(ErrorCode::PushPlatformUnsupported)
+}
+
+/// A provider error worth retrying.
+pub(crate) fn delivery_failed(provider: &str, detail: impl std::fmt::Display) -> AppError {
+    tracing::warn!("{} rejected push notification: {}", provider, detail);
+    AppError::Unavailable {
+        message: format!("{} delivery failed", provider),
+        retry: RetryHint::after(Duration::from_secs(30)),
+    }
+    .with_code(ErrorCode::PushDeliveryFailed)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FanOutPayload {
+    pub user_id: UserId,
+    pub notification: PushNotification,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeliverPayload {
+    pub device_id: Uuid,
+    pub notification: PushNotification,
+}
+
+struct FanOut(Arc<PushService>);
+
+impl JobHandler for FanOut {
+    fn handle<'a>(&'a self, job: &'a Job) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let payload: FanOutPayload = job.payload()?;
+            self.0.fan_out(&job.id.to_string(), payload.user_id, &payload.notification).await
+        })
+    }
+}
+
+struct Deliver(Arc<PushService>);
+
+impl JobHandler for Deliver {
+    fn handle<'a>(&'a self, job: &'a Job) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let payload: DeliverPayload = job.payload()?;
+            self.0.deliver(payload.device_id, &payload.notification).await
+        })
+    }
+}
+
+pub fn register_jobs(worker: Worker, state: &AppState) -> Worker {
+    worker
+        .handler(FAN_OUT_JOB, FanOut(state.push_service.clone()))
+        .handler(DELIVER_JOB, Deliver(state.push_service.clone()))
+}
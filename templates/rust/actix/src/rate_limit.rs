@@ -0,0 +1,145 @@
+//! Token bucket limits per client IP and route: `rate_limit.routes` hold
+//! sensitive routes such as sign-in to a trickle, and the default covers
+//! the rest of the API. Unlike the auth throttle, which counts failures per
+//! account, these count every request. Buckets live in the
+//! `rate_limit.backend`; see [`platform_core::rate_limit`].
+//!
+//! Behind a load balancer every request comes from the balancer, so for
+//! peers in `rate_limit.trusted_gateways` the client is the right-most
+//! `X-Forwarded-For` hop that isn't a trusted gateway itself; see
+//! [`RateLimits::client_ip`].
+//! [`RateLimiter`](crate::middleware::RateLimiter) applies them to
+//! `/api/v1`.
+
+use actix_web::http::header::HeaderMap;
+use actix_web::http::Method;
+use platform_core::rate_limit::{self, Quota, SharedRateLimitBackend};
+use std::net::{IpAddr, SocketAddr};
+
+use crate::config::{RateLimitSettings, RouteLimitSettings};
+use crate::errors::AppResult;
+use crate::utils::ip_in_cidr;
+
+struct RouteRule {
+    settings: RouteLimitSettings,
+    quota: Option<Quota>,
+}
+
+impl RouteRule {
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        let method_matches = self
+            .settings
+            .method
+            .as_deref()
+            .is_none_or(|listed| listed.eq_ignore_ascii_case(method.as_str()));
+        let path_matches = match self.settings.path.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == self.settings.path,
+        };
+        method_matches && path_matches
+    }
+
+    /// Names the rule's buckets.
+    fn scope(&self) -> String {
+        match &self.settings.method {
+            Some(method) => format!("{} {}", method.to_ascii_uppercase(), self.settings.path),
+            None => self.settings.path.clone(),
+        }
+    }
+}
+
+/// The configured limits and the backend holding their buckets.
+pub struct RateLimits {
+    enabled: bool,
+    default_quota: Option<Quota>,
+    routes: Vec<RouteRule>,
+    trusted_gateways: Vec<String>,
+    backend: SharedRateLimitBackend,
+}
+
+impl RateLimits {
+    /// Connects to the backend `settings` name.
+    pub async fn from_settings(settings: &RateLimitSettings) -> anyhow::Result<Self> {
+        let backend = rate_limit::from_settings(&settings.backend).await?;
+        Ok(Self::new(settings, backend))
+    }
+
+    pub fn new(settings: &RateLimitSettings, backend: SharedRateLimitBackend) -> Self {
+        let routes = settings
+            .routes
+            .iter()
+            .map(|route| RouteRule {
+                settings: route.clone(),
+                quota: Quota::new(
+                    route.rate_per_second,
+                    route.burst.unwrap_or(route.rate_per_second.ceil() as u32),
+                ),
+            })
+            .collect();
+
+        Self {
+            enabled: settings.enabled,
+            default_quota: Quota::new(settings.default_rate_per_second, settings.default_burst),
+            routes,
+            trusted_gateways: settings.trusted_gateways.clone(),
+            backend,
+        }
+    }
+
+    /// The client a request from `peer` is counted against. Requests from
+    /// trusted gateways count against the right-most `X-Forwarded-For` hop
+    /// that isn't a trusted gateway too: hops further left were added by
+    /// the client and can't be believed. Without such a hop the request
+    /// counts against the last gateway, so it's limited all the same.
+    pub fn client_ip(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        let peer = peer?;
+        if !self.is_trusted_gateway(peer) {
+            return Some(peer);
+        }
+
+        let forwarded_for: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+        let mut client = peer;
+        for hop in forwarded_for.into_iter().rev() {
+            let hop = hop.trim();
+            // Some gateways append the port
+            let parsed = hop.parse::<IpAddr>().or_else(|_| hop.parse::<SocketAddr>().map(|addr| addr.ip()));
+            let Ok(ip) = parsed else {
+                break;
+            };
+            client = ip;
+            if !self.is_trusted_gateway(ip) {
+                break;
+            }
+        }
+        Some(client)
+    }
+
+    fn is_trusted_gateway(&self, ip: IpAddr) -> bool {
+        self.trusted_gateways.iter().any(|cidr| ip_in_cidr(ip, cidr))
+    }
+
+    /// Takes a token for a request from `ip`, as [`client_ip`](Self::client_ip)
+    /// finds it, failing with 429 and a `Retry-After` once its bucket is
+    /// empty. The first matching route's rule applies, and requests no rule
+    /// matches share the client's default bucket.
+    pub async fn check(&self, method: &Method, path: &str, ip: Option<IpAddr>) -> AppResult<()> {
+        let Some(ip) = ip.filter(|_| self.enabled) else {
+            return Ok(());
+        };
+
+        let (scope, quota) = match self.routes.iter().find(|route| route.matches(method, path)) {
+            Some(route) => (route.scope(), route.quota),
+            None => ("*".to_string(), self.default_quota),
+        };
+        let Some(quota) = quota else {
+            return Ok(());
+        };
+
+        let key = format!("http:{}:{}", scope, ip);
+        self.backend.take(&key, quota).await.map_err(rate_limit::rejection)
+    }
+}
@@ -0,0 +1,388 @@
+//! Deletes rows once their record type's TTL in `retention.policies` has
+//! passed. Record types are declared in [`RECORD_TYPES`]:
+//!
+//! - `audit_events`, counted from when the event happened;
+//! - `sessions`, refresh tokens counted from when they expired or were
+//!   revoked;
+//! - `deactivated_users`, accounts counted from their deactivation, deleted
+//!   with everything tied to them. Guests expire on their own;
+//! - `processed_messages`, the ids an
+//!   [`IdempotentConsumer`](crate::consumer::IdempotentConsumer) skips
+//!   redeliveries of, counted from when the message was processed.
+//!
+//! With `retention.enabled`, the server looks for policies due every
+//! `retention.poll_interval_secs` and enqueues an [`ENFORCE_JOB`] for each,
+//! once per `retention.interval_secs`. The job deletes expired rows
+//! `retention.batch_size` at a time, counting them in
+//! `retention_rows_deleted_total` and in `retention_runs` as it goes. In
+//! dry-run mode (`retention.dry_run`, or a policy's own `dry_run`) it only
+//! counts the rows it would delete, into `retention_rows_expired`.
+//!
+//! ```toml
+//! [retention.policies.sessions]
+//! ttl_days = 30
+//! dry_run = true
+//! ```
+
+use chrono::{DateTime, Duration, Utc};
+use config::ConfigError;
+use futures_util::future::BoxFuture;
+use platform_core::lifecycle::{Hook, Lifecycle};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+
+use crate::config::RetentionSettings;
+use crate::errors::{AppError, AppResult, ResultExt};
+use crate::jobs::{JobHandler, JobQueue, Worker};
+use crate::models::job::Job;
+use crate::utils::SharedClock;
+use crate::AppState;
+
+pub const ENFORCE_JOB: &str = "retention.enforce";
+
+/// Rows a policy can apply to.
+pub struct RecordType {
+    pub name: &'static str,
+    pub table: &'static str,
+    /// When a row's TTL starts.
+    pub since: &'static str,
+    /// Rows the record type covers.
+    pub filter: &'static str,
+}
+
+pub const RECORD_TYPES: &[RecordType] = &[
+    RecordType {
+        name: "audit_events",
+        table: "audit_events",
+        since: "created_at",
+        filter: "TRUE",
+    },
+    RecordType {
+        name: "sessions",
+        table: "refresh_tokens",
+        since: "LEAST(expires_at, revoked_at)",
+        filter: "TRUE",
+    },
+    RecordType {
+        name: "deactivated_users",
+        table: "users",
+        since: "deactivated_at",
+        filter: "NOT is_active AND NOT is_guest",
+    },
+    RecordType {
+        name: "processed_messages",
+        table: "processed_messages",
+        since: "processed_at",
+        filter: "TRUE",
+    },
+];
+
+struct Policy {
+    record_type: &'static RecordType,
+    ttl: Duration,
+    dry_run: bool,
+}
+
+/// Outcome of enforcing a policy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RetentionReport {
+    pub policy: String,
+    /// Rows older than this were expired.
+    pub cutoff: DateTime<Utc>,
+    pub dry_run: bool,
+    /// Rows deleted, or that would have been in dry-run mode.
+    pub rows: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnforcePayload {
+    pub policy: String,
+}
+
+pub struct RetentionEngine {
+    db: PgPool,
+    settings: RetentionSettings,
+    policies: BTreeMap<String, Policy>,
+    job_queue: Arc<JobQueue>,
+    clock: SharedClock,
+}
+
+impl RetentionEngine {
+    pub fn new(
+        db: PgPool,
+        settings: RetentionSettings,
+        job_queue: Arc<JobQueue>,
+        clock: SharedClock,
+    ) -> Result<Self, ConfigError> {
+        if settings.batch_size <= 0 {
+            return Err(ConfigError::Message("retention.batch_size must be positive".to_string()));
+        }
+        let policies = settings
+            .policies
+            .iter()
+            .map(|(name, policy)| {
+                let record_type = RECORD_TYPES.iter().find(|record_type| record_type.name == name).ok_or_else(|| {
+                    let known: Vec<&str> = RECORD_TYPES.iter().map(|record_type| record_type.name).collect();
+                    ConfigError::Message(format!(
+                        "retention policy {} names no record type; known ones are {}",
+                        name,
+                        known.join(", ")
+                    ))
+                })?;
+                if policy.ttl_days <= 0 {
+                    return Err(ConfigError::Message(format!(
+                        "retention policy {} needs a positive ttl_days",
+                        name
+                    )));
+                }
+                let policy = Policy {
+                    record_type,
+                    ttl: Duration::days(policy.ttl_days),
+                    dry_run: settings.dry_run || policy.dry_run,
+                };
+                Ok((name.clone(), policy))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            db,
+            settings,
+            policies,
+            job_queue,
+            clock,
+        })
+    }
+
+    /// Enqueues enforcement of every policy that is due and returns their
+    /// names. Policies are due when first configured and
+    /// `retention.interval_secs` after each time they were scheduled.
+    pub async fn schedule_due(&self) -> AppResult<Vec<String>> {
+        let now = self.clock.now();
+        let mut scheduled = Vec::new();
+
+        for name in self.policies.keys() {
+            let mut tx = self.db.begin().await.context("begin transaction")?;
+            sqlx::query("INSERT INTO retention_runs (policy, next_run_at) VALUES ($1, $2) ON CONFLICT (policy) DO NOTHING")
+                .bind(name)
+                .bind(now)
+                .execute(&mut *tx)
+                .await
+                .entity_context("register retention policy", name)?;
+
+            let claimed = sqlx::query(
+                r#"
+                UPDATE retention_runs SET next_run_at = $3, updated_at = $2
+                WHERE policy = $1 AND next_run_at <= $2
+                "#,
+            )
+            .bind(name)
+            .bind(now)
+            .bind(now + Duration::seconds(self.settings.interval_secs))
+            .execute(&mut *tx)
+            .await
+            .entity_context("claim retention policy", name)?
+            .rows_affected()
+                == 1;
+
+            if claimed {
+                let payload = EnforcePayload { policy: name.clone() };
+                self.job_queue.enqueue_with(&mut *tx, ENFORCE_JOB, &payload).await?;
+                scheduled.push(name.clone());
+            }
+            tx.commit().await.context("commit transaction")?;
+        }
+
+        Ok(scheduled)
+    }
+
+    /// Calls [`schedule_due`](Self::schedule_due) every
+    /// `retention.poll_interval_secs` until `shutdown` completes.
+    pub async fn schedule_until(&self, shutdown: impl Future<Output = ()>) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(self.settings.poll_interval_secs));
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.schedule_due().await {
+                        tracing::warn!("Failed to schedule retention policies: {}", e);
+                    }
+                }
+                _ = &mut shutdown => break,
+            }
+        }
+    }
+
+    /// Deletes the rows `policy` has expired, or counts them in dry-run
+    /// mode, recording progress in `retention_runs`.
+    pub async fn enforce(&self, name: &str) -> AppResult<RetentionReport> {
+        let policy = self
+            .policies
+            .get(name)
+            .ok_or_else(|| AppError::NotFound(format!("Retention policy {} is not configured", name)))?;
+        let now = self.clock.now();
+        let mut report = RetentionReport {
+            policy: name.to_string(),
+            cutoff: now - policy.ttl,
+            dry_run: policy.dry_run,
+            rows: 0,
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO retention_runs (policy, next_run_at, last_run_at, last_cutoff, last_rows, last_dry_run, updated_at)
+            VALUES ($1, $2, $2, $3, 0, $4, $2)
+            ON CONFLICT (policy) DO UPDATE
+            SET last_run_at = $2, last_cutoff = $3, last_rows = 0, last_dry_run = $4, last_finished_at = NULL,
+                last_error = NULL, updated_at = $2
+            "#,
+        )
+        .bind(name)
+        .bind(now)
+        .bind(report.cutoff)
+        .bind(report.dry_run)
+        .execute(&self.db)
+        .await
+        .entity_context("start retention run", name)?;
+
+        let result = if policy.dry_run {
+            self.count_expired(policy, &mut report).await
+        } else {
+            self.delete_expired(policy, &mut report).await
+        };
+
+        match result {
+            Ok(()) => {
+                sqlx::query("UPDATE retention_runs SET last_finished_at = $2, updated_at = $2 WHERE policy = $1")
+                    .bind(name)
+                    .bind(self.clock.now())
+                    .execute(&self.db)
+                    .await
+                    .entity_context("finish retention run", name)?;
+                tracing::info!(
+                    policy = name,
+                    dry_run = report.dry_run,
+                    "Retention expired {} rows older than {}",
+                    report.rows,
+                    report.cutoff
+                );
+                Ok(report)
+            }
+            Err(e) => {
+                sqlx::query("UPDATE retention_runs SET last_error = $2, updated_at = $3 WHERE policy = $1")
+                    .bind(name)
+                    .bind(e.source_chain())
+                    .bind(self.clock.now())
+                    .execute(&self.db)
+                    .await
+                    .entity_context("record retention run", name)?;
+                Err(e)
+            }
+        }
+    }
+
+    async fn count_expired(&self, policy: &Policy, report: &mut RetentionReport) -> AppResult<()> {
+        let record_type = policy.record_type;
+        let rows: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM {} WHERE {} AND {} < $1",
+            record_type.table, record_type.filter, record_type.since
+        ))
+        .bind(report.cutoff)
+        .fetch_one(&self.db)
+        .await
+        .entity_context("count expired rows", record_type.name)?;
+
+        report.rows = rows as u64;
+        metrics::gauge!("retention_rows_expired", "policy" => report.policy.clone()).set(rows as f64);
+        self.record_progress(report).await
+    }
+
+    /// Deletes a batch at a time until a batch comes up short.
+    async fn delete_expired(&self, policy: &Policy, report: &mut RetentionReport) -> AppResult<()> {
+        let record_type = policy.record_type;
+        let delete = format!(
+            r#"
+            DELETE FROM {table} WHERE id IN (
+                SELECT id FROM {table} WHERE {filter} AND {since} < $1 LIMIT $2 FOR UPDATE SKIP LOCKED
+            )
+            "#,
+            table = record_type.table,
+            filter = record_type.filter,
+            since = record_type.since,
+        );
+
+        loop {
+            let deleted = sqlx::query(&delete)
+                .bind(report.cutoff)
+                .bind(self.settings.batch_size)
+                .execute(&self.db)
+                .await
+                .entity_context("delete expired rows", record_type.name)?
+                .rows_affected();
+
+            report.rows += deleted;
+            metrics::counter!("retention_rows_deleted_total", "policy" => report.policy.clone()).increment(deleted);
+            self.record_progress(report).await?;
+            if deleted < self.settings.batch_size as u64 {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn record_progress(&self, report: &RetentionReport) -> AppResult<()> {
+        sqlx::query("UPDATE retention_runs SET last_rows = $2, updated_at = $3 WHERE policy = $1")
+            .bind(&report.policy)
+            .bind(report.rows as i64)
+            .bind(self.clock.now())
+            .execute(&self.db)
+            .await
+            .entity_context("record retention progress", &report.policy)?;
+        Ok(())
+    }
+}
+
+/// Schedules enforcement from startup until shutdown. Nothing when
+/// `retention.enabled` is off; enforcement itself runs as jobs.
+pub fn register_hooks(lifecycle: &mut Lifecycle, state: &Arc<AppState>) {
+    if !state.settings.retention.enabled {
+        return;
+    }
+
+    let engine = state.retention.clone();
+    let (stop, stopped) = oneshot::channel::<()>();
+    let (started, scheduling) = oneshot::channel();
+    lifecycle.on_start(Hook::new("retention", move || async move {
+        let stopped = async move {
+            let _ = stopped.await;
+        };
+        let _ = started.send(tokio::spawn(async move { engine.schedule_until(stopped).await }));
+        Ok(())
+    }));
+    lifecycle.on_shutdown(Hook::new("retention", move || async move {
+        let _ = stop.send(());
+        if let Ok(scheduling) = scheduling.await {
+            scheduling.await?;
+        }
+        Ok(())
+    }));
+}
+
+struct Enforce(Arc<RetentionEngine>);
+
+impl JobHandler for Enforce {
+    fn handle<'a>(&'a self, job: &'a Job) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let payload: EnforcePayload = job.payload()?;
+            self.0.enforce(&payload.policy).await?;
+            Ok(())
+        })
+    }
+}
+
+pub fn register_jobs(worker: Worker, state: &AppState) -> Worker {
+    worker.handler(ENFORCE_JOB, Enforce(state.retention.clone()))
+}
@@ -0,0 +1,93 @@
+//! The tokio runtime `main` runs the server on, built from
+//! [`RuntimeSettings`] rather than `#[actix_web::main]` so containers can be
+//! sized to their CPU and memory limits.
+
+use core_affinity::CoreId;
+use std::cell::Cell;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::config::{CpuPinningSettings, RuntimeSettings};
+
+/// A multi-threaded tokio runtime as configured.
+pub fn build(settings: &RuntimeSettings) -> io::Result<tokio::runtime::Runtime> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder
+        .enable_all()
+        .max_blocking_threads(settings.max_blocking_threads)
+        .thread_name(&settings.thread_name);
+    if let Some(threads) = settings.worker_threads {
+        builder.worker_threads(threads);
+    }
+    if let Some(size) = settings.thread_stack_size {
+        builder.thread_stack_size(size);
+    }
+    builder.build()
+}
+
+/// An actix system on the configured runtime, for `HttpServer` and the
+/// lifecycle hooks to run in.
+pub fn system(settings: &RuntimeSettings) -> io::Result<actix_rt::SystemRunner> {
+    let runtime = build(settings)?;
+    Ok(actix_rt::System::with_tokio_rt(|| runtime))
+}
+
+thread_local! {
+    static PINNED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Pins actix workers to CPU cores. Only consider it when the server has
+/// the machine, or its cpuset, to itself, since pinned workers can't escape
+/// a busy core, and benchmark it first (`cargo xtask bench`).
+#[derive(Debug)]
+pub struct CpuPinning {
+    /// Empty when pinning is off.
+    cores: Vec<CoreId>,
+    next: AtomicUsize,
+}
+
+impl CpuPinning {
+    /// Fails if `settings.cores` lists a core this process may not run on.
+    pub fn new(settings: &CpuPinningSettings) -> io::Result<Self> {
+        let mut cores = Vec::new();
+        if settings.enabled {
+            let available = core_affinity::get_core_ids()
+                .ok_or_else(|| io::Error::other("CPU pinning isn't supported on this platform"))?;
+            cores = if settings.cores.is_empty() {
+                available
+            } else {
+                settings
+                    .cores
+                    .iter()
+                    .map(|&id| {
+                        available.iter().copied().find(|core| core.id == id).ok_or_else(|| {
+                            io::Error::other(format!("server.cpu_pinning.cores lists unavailable core {}", id))
+                        })
+                    })
+                    .collect::<io::Result<_>>()?
+            };
+        }
+
+        Ok(Self {
+            cores,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Pins the calling thread to the next core, round robin, and returns
+    /// it. Threads are pinned once; repeat calls return `None`, as do all
+    /// calls with pinning off.
+    pub fn pin_current(&self) -> Option<usize> {
+        if self.cores.is_empty() || PINNED.get() {
+            return None;
+        }
+        PINNED.set(true);
+
+        let core = self.cores[self.next.fetch_add(1, Ordering::Relaxed) % self.cores.len()];
+        if !core_affinity::set_for_current(core) {
+            tracing::warn!("Failed to pin {:?} to core {}", std::thread::current().name(), core.id);
+            return None;
+        }
+        Some(core.id)
+    }
+}
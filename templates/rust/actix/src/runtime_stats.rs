@@ -0,0 +1,59 @@
+//! Tokio runtime statistics as `/api/v1/admin/runtime` serves them.
+//!
+//! actix serves each worker from its own single-threaded runtime, so every
+//! runtime that serves requests registers itself with [`Runtimes`]; the
+//! figures come from `platform_observability::runtime_stats`.
+
+use platform_observability::runtime_stats::{self, Runtimes};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuntimeStats {
+    /// The server and thread that registered the runtime, e.g.
+    /// `api/actix-server worker 0`.
+    pub name: String,
+    pub workers: usize,
+    pub alive_tasks: usize,
+    /// Tasks scheduled from outside the runtime and not yet picked up.
+    pub global_queue_depth: usize,
+    /// Time each worker spent polling tasks since the runtime started.
+    pub worker_busy_ms: Vec<u128>,
+    /// Times each worker parked for lack of work; a count that stops
+    /// moving under load points at a worker blocked inside a task.
+    pub worker_park_count: Vec<u64>,
+    /// Only with `--cfg tokio_unstable`.
+    pub blocking_pool: Option<BlockingPoolStats>,
+    /// Mean time a poll takes on each worker, with `--cfg tokio_unstable`.
+    pub worker_mean_poll_us: Option<Vec<u128>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockingPoolStats {
+    pub threads: usize,
+    pub idle_threads: usize,
+    pub queue_depth: usize,
+}
+
+impl RuntimeStats {
+    pub fn new(name: String, stats: runtime_stats::RuntimeStats) -> Self {
+        Self {
+            name,
+            workers: stats.workers,
+            alive_tasks: stats.alive_tasks,
+            global_queue_depth: stats.global_queue_depth,
+            worker_busy_ms: stats.worker_busy.iter().map(|busy| busy.as_millis()).collect(),
+            worker_park_count: stats.worker_park_count,
+            blocking_pool: stats.blocking_pool.map(|pool| BlockingPoolStats {
+                threads: pool.threads,
+                idle_threads: pool.idle_threads,
+                queue_depth: pool.queue_depth,
+            }),
+            worker_mean_poll_us: cfg!(tokio_unstable)
+                .then(|| stats.worker_mean_poll_time.iter().map(|poll| poll.as_micros()).collect()),
+        }
+    }
+
+    pub fn all(runtimes: &Runtimes) -> Vec<Self> {
+        runtimes.stats().into_iter().map(|(name, stats)| Self::new(name, stats)).collect()
+    }
+}
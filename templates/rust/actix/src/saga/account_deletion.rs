@@ -0,0 +1,117 @@
+//! Deletes an account: signs the user out everywhere, removes what is tied
+//! to them and finally requests the user's deletion, which finishes once
+//! downstream services confirm; see [`deletion_fanout`](super::deletion_fanout).
+//! Until the last step nothing is lost, so a failure puts the account back
+//! the way it was, minus its sessions and push devices.
+
+use futures_util::future::BoxFuture;
+use platform_core::domain::UserId;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::errors::AppResult;
+use crate::saga::deletion_fanout::DeletionFanout;
+use crate::saga::{Saga, SagaContext, SagaStep};
+use crate::services::{ApiTokenService, PushService, SessionService, UserCommands, UserQueries};
+
+pub const ACCOUNT_DELETION: &str = "account.deletion";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountDeletion {
+    pub user_id: UserId,
+}
+
+pub fn saga(
+    user_queries: Arc<dyn UserQueries>,
+    user_commands: Arc<dyn UserCommands>,
+    session_service: Arc<SessionService>,
+    api_token_service: Arc<ApiTokenService>,
+    push_service: Arc<PushService>,
+    deletion_fanout: Arc<DeletionFanout>,
+) -> Saga {
+    Saga::new(ACCOUNT_DELETION)
+        .step(Deactivate(user_queries, user_commands))
+        .step(RevokeCredentials(session_service, api_token_service))
+        .step(RemoveDevices(push_service))
+        .step(RequestDeletion(deletion_fanout))
+}
+
+/// Blocks sign-in while the account is being deleted.
+struct Deactivate(Arc<dyn UserQueries>, Arc<dyn UserCommands>);
+
+impl SagaStep for Deactivate {
+    fn name(&self) -> &'static str {
+        "deactivate"
+    }
+
+    fn execute<'a>(&'a self, context: &'a mut SagaContext) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let AccountDeletion { user_id } = context.parse()?;
+            // Kept from the first attempt, so a resumed run doesn't record
+            // the state this step itself left behind
+            if context.get("was_active").is_none() {
+                let user = self.0.get_user_by_id(user_id).await?;
+                context.set("was_active", user.is_active);
+            }
+            self.1.set_active(user_id, false).await
+        })
+    }
+
+    fn compensate<'a>(&'a self, context: &'a mut SagaContext) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let AccountDeletion { user_id } = context.parse()?;
+            let was_active = context.get("was_active").and_then(|v| v.as_bool()).unwrap_or(true);
+            self.1.set_active(user_id, was_active).await
+        })
+    }
+}
+
+/// Signs the user out everywhere. Not undone; they can sign in again.
+struct RevokeCredentials(Arc<SessionService>, Arc<ApiTokenService>);
+
+impl SagaStep for RevokeCredentials {
+    fn name(&self) -> &'static str {
+        "revoke_credentials"
+    }
+
+    fn execute<'a>(&'a self, context: &'a mut SagaContext) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let AccountDeletion { user_id } = context.parse()?;
+            self.0.revoke_all(user_id).await?;
+            self.1.revoke_all(user_id).await
+        })
+    }
+}
+
+/// Stops notifications. Not undone; apps register again on next launch.
+struct RemoveDevices(Arc<PushService>);
+
+impl SagaStep for RemoveDevices {
+    fn name(&self) -> &'static str {
+        "remove_devices"
+    }
+
+    fn execute<'a>(&'a self, context: &'a mut SagaContext) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let AccountDeletion { user_id } = context.parse()?;
+            self.0.unregister_all(user_id).await
+        })
+    }
+}
+
+/// Hands the user over to the fan-out, keyed by the saga id so a resumed
+/// run doesn't request it twice.
+struct RequestDeletion(Arc<DeletionFanout>);
+
+impl SagaStep for RequestDeletion {
+    fn name(&self) -> &'static str {
+        "request_deletion"
+    }
+
+    fn execute<'a>(&'a self, context: &'a mut SagaContext) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let AccountDeletion { user_id } = context.parse()?;
+            self.0.request(context.saga_id, user_id).await
+        })
+    }
+}
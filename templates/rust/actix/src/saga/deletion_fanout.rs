@@ -0,0 +1,579 @@
+//! Finishing a user's deletion only once every downstream service that
+//! keeps data about them has confirmed removing it.
+//!
+//! The account deletion saga ends by [`DeletionFanout::request`]ing the
+//! deletion: a `user_deletions` row tracks it and a `user_deletion_acks`
+//! row each service configured in `deletion_fanout.services`, and a
+//! [`UserDeletionRequested`] event is published to every one of them.
+//! Services confirm with `POST /api/v1/internal/user-deletions/{id}/ack`,
+//! authenticated as their workload identity with the
+//! [`ACK_ROLE`] role. Once all have, the user is deleted and the deletion
+//! `completed`.
+//!
+//! Every `deletion_fanout.poll_interval_secs`, [`DeletionFanout::run_due`]
+//! publishes the event again to services that haven't confirmed for
+//! `deletion_fanout.retry_interval_secs`. Deletions still unconfirmed
+//! `deletion_fanout.timeout_secs` after they were requested are moved to
+//! `manual_review`, audited and counted in `user_deletions_stalled_total`;
+//! an admin finishes them through
+//! `POST /api/v1/admin/user-deletions/{id}/finalize`, or a late confirmation
+//! does.
+//!
+//! Without services configured, deletions finish as soon as they are
+//! requested.
+
+use chrono::{DateTime, Duration, Utc};
+use config::ConfigError;
+use futures_util::future::BoxFuture;
+use platform_core::domain::UserId;
+use platform_core::lifecycle::{Hook, Lifecycle};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::PgPool;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::cache::ResponseCache;
+use crate::config::{DeletionFanoutSettings, DeletionPublisherProvider};
+use crate::egress::EgressPolicy;
+use crate::errors::{AppError, AppResult, ResultExt, RetryHint};
+use crate::models::audit_event::{AuditEvent, ACCOUNT_DELETED, ACCOUNT_DELETION_STALLED};
+use crate::models::user_deletion::{
+    DeletionAck, UserDeletion, DELETION_COMPLETED, DELETION_MANUAL_REVIEW, DELETION_PENDING,
+};
+use crate::services::AuditService;
+use crate::utils::{with_trace_context, SharedClock};
+use crate::AppState;
+
+/// Workload role needed to confirm deletions.
+pub const ACK_ROLE: &str = "user_deletions.ack";
+
+/// Unconfirmed services reminded per [`DeletionFanout::run_due`].
+const PUBLISH_BATCH: i64 = 500;
+
+/// Deletions [`DeletionFanout::list`] returns at most.
+const LIST_LIMIT: i64 = 100;
+
+/// Asks a downstream service to delete what it keeps about a user. Sent
+/// again until the service confirms, so handling it must be idempotent;
+/// `deletion_id` serves as the idempotency key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserDeletionRequested {
+    pub deletion_id: Uuid,
+    pub user_id: UserId,
+    pub requested_at: DateTime<Utc>,
+}
+
+/// A deletion with where each service stands.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeletionStatus {
+    #[serde(flatten)]
+    pub deletion: UserDeletion,
+    pub acks: Vec<DeletionAck>,
+}
+
+pub struct DeletionFanout {
+    db: PgPool,
+    settings: DeletionFanoutSettings,
+    publisher: Arc<dyn DeletionPublisher>,
+    audit_service: Arc<AuditService>,
+    response_cache: Arc<ResponseCache>,
+    clock: SharedClock,
+}
+
+impl DeletionFanout {
+    pub fn new(
+        db: PgPool,
+        settings: DeletionFanoutSettings,
+        publisher: Arc<dyn DeletionPublisher>,
+        audit_service: Arc<AuditService>,
+        response_cache: Arc<ResponseCache>,
+        clock: SharedClock,
+    ) -> Self {
+        Self {
+            db,
+            settings,
+            publisher,
+            audit_service,
+            response_cache,
+            clock,
+        }
+    }
+
+    /// Starts deleting `user_id`, tracked as `deletion_id`, and publishes
+    /// the event to every service. Safe to call again with the same id;
+    /// services already sent the event aren't sent it again until due.
+    pub async fn request(&self, deletion_id: Uuid, user_id: UserId) -> AppResult<()> {
+        let now = self.clock.now();
+        let services: Vec<&str> = self.settings.services.keys().map(String::as_str).collect();
+
+        let mut tx = self.db.begin().await.context("begin user deletion")?;
+        sqlx::query(
+            r#"
+            INSERT INTO user_deletions (id, user_id, status, requested_at, deadline_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (id) DO NOTHING
+            "#,
+        )
+        .bind(deletion_id)
+        .bind(user_id)
+        .bind(DELETION_PENDING)
+        .bind(now)
+        .bind(now + Duration::seconds(self.settings.timeout_secs))
+        .execute(&mut *tx)
+        .await
+        .entity_context("request user deletion", deletion_id)?;
+        sqlx::query(
+            r#"
+            INSERT INTO user_deletion_acks (deletion_id, service, next_attempt_at)
+            SELECT $1, UNNEST($2::text[]), $3
+            ON CONFLICT (deletion_id, service) DO NOTHING
+            "#,
+        )
+        .bind(deletion_id)
+        .bind(&services)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .entity_context("track user deletion acks", deletion_id)?;
+        tx.commit().await.context("commit user deletion")?;
+
+        if services.is_empty() {
+            self.finish(deletion_id, None).await?;
+        } else {
+            self.publish_due(Some(deletion_id)).await?;
+        }
+        Ok(())
+    }
+
+    /// Records that the service authenticated as `subject` deleted its data,
+    /// finishing the deletion if it was the last one. Workloads that aren't
+    /// a configured service are refused.
+    pub async fn acknowledge(&self, deletion_id: Uuid, subject: &str) -> AppResult<UserDeletion> {
+        let service = self
+            .settings
+            .services
+            .iter()
+            .find(|(_, service)| service.identity == subject)
+            .map(|(name, _)| name)
+            .ok_or(AppError::Forbidden)?;
+
+        let result = sqlx::query(
+            r#"
+            UPDATE user_deletion_acks SET confirmed_at = COALESCE(confirmed_at, $3), last_error = NULL
+            WHERE deletion_id = $1 AND service = $2
+            "#,
+        )
+        .bind(deletion_id)
+        .bind(service)
+        .bind(self.clock.now())
+        .execute(&self.db)
+        .await
+        .entity_context("acknowledge user deletion", deletion_id)?;
+        if result.rows_affected() == 0 {
+            return Err(deletion_not_found());
+        }
+
+        let status = self.status(deletion_id).await?;
+        if status.deletion.status != DELETION_COMPLETED && status.acks.iter().all(|ack| ack.confirmed_at.is_some()) {
+            return self.finish(deletion_id, None).await;
+        }
+        Ok(status.deletion)
+    }
+
+    /// Deletes the user of a deletion whichever services haven't confirmed,
+    /// for an admin resolving it; audited with who did.
+    pub async fn finalize(&self, deletion_id: Uuid, admin: UserId) -> AppResult<UserDeletion> {
+        self.finish(deletion_id, Some(admin)).await
+    }
+
+    /// Moves deletions past their deadline to manual review, finishes those
+    /// every service confirmed and reminds services that are due.
+    pub async fn run_due(&self) -> AppResult<()> {
+        // Past their deadline first, so they aren't reminded again
+        let stalled: Vec<UserDeletion> = sqlx::query_as(
+            r#"
+            UPDATE user_deletions SET status = $2
+            WHERE status = $1 AND deadline_at <= $3
+            RETURNING *
+            "#,
+        )
+        .bind(DELETION_PENDING)
+        .bind(DELETION_MANUAL_REVIEW)
+        .bind(self.clock.now())
+        .fetch_all(&self.db)
+        .await
+        .context("stall user deletions")?;
+        for deletion in stalled {
+            let unconfirmed = self.unconfirmed(deletion.id).await?;
+            tracing::error!(
+                deletion_id = %deletion.id,
+                user_id = %deletion.user_id,
+                "User deletion unconfirmed by {} past its deadline, left for manual review",
+                unconfirmed.join(", ")
+            );
+            metrics::counter!("user_deletions_stalled_total").increment(1);
+            self.audit_service
+                .record(AuditEvent {
+                    event_type: ACCOUNT_DELETION_STALLED,
+                    user_id: None,
+                    ip_address: None,
+                    device: None,
+                    metadata: json!({ "user_id": deletion.user_id, "saga_id": deletion.id, "unconfirmed": unconfirmed }),
+                })
+                .await?;
+        }
+
+        // Confirmed, but finishing failed when the last ack came in
+        let confirmed: Vec<Uuid> = sqlx::query_scalar(
+            r#"
+            SELECT id FROM user_deletions d
+            WHERE status = $1
+              AND NOT EXISTS (SELECT 1 FROM user_deletion_acks a WHERE a.deletion_id = d.id AND a.confirmed_at IS NULL)
+            "#,
+        )
+        .bind(DELETION_PENDING)
+        .fetch_all(&self.db)
+        .await
+        .context("load confirmed user deletions")?;
+        for deletion_id in confirmed {
+            self.finish(deletion_id, None).await?;
+        }
+
+        self.publish_due(None).await
+    }
+
+    /// The deletion with its acks.
+    pub async fn status(&self, deletion_id: Uuid) -> AppResult<DeletionStatus> {
+        let deletion: UserDeletion = sqlx::query_as("SELECT * FROM user_deletions WHERE id = $1")
+            .bind(deletion_id)
+            .fetch_optional(&self.db)
+            .await
+            .entity_context("load user deletion", deletion_id)?
+            .ok_or_else(deletion_not_found)?;
+        let acks = sqlx::query_as("SELECT * FROM user_deletion_acks WHERE deletion_id = $1 ORDER BY service")
+            .bind(deletion_id)
+            .fetch_all(&self.db)
+            .await
+            .entity_context("load user deletion acks", deletion_id)?;
+        Ok(DeletionStatus { deletion, acks })
+    }
+
+    /// The latest deletions, optionally only those with `status`, newest
+    /// first.
+    pub async fn list(&self, status: Option<&str>) -> AppResult<Vec<DeletionStatus>> {
+        let deletions: Vec<UserDeletion> = sqlx::query_as(
+            r#"
+            SELECT * FROM user_deletions
+            WHERE ($1::text IS NULL OR status = $1)
+            ORDER BY requested_at DESC, id
+            LIMIT $2
+            "#,
+        )
+        .bind(status)
+        .bind(LIST_LIMIT)
+        .fetch_all(&self.db)
+        .await
+        .context("list user deletions")?;
+
+        let ids: Vec<Uuid> = deletions.iter().map(|deletion| deletion.id).collect();
+        let acks: Vec<DeletionAck> =
+            sqlx::query_as("SELECT * FROM user_deletion_acks WHERE deletion_id = ANY($1) ORDER BY service")
+                .bind(&ids)
+                .fetch_all(&self.db)
+                .await
+                .context("list user deletion acks")?;
+        let mut acks_by_deletion: HashMap<Uuid, Vec<DeletionAck>> = HashMap::new();
+        for ack in acks {
+            acks_by_deletion.entry(ack.deletion_id).or_default().push(ack);
+        }
+
+        Ok(deletions
+            .into_iter()
+            .map(|deletion| DeletionStatus {
+                acks: acks_by_deletion.remove(&deletion.id).unwrap_or_default(),
+                deletion,
+            })
+            .collect())
+    }
+
+    /// Runs [`run_due`](Self::run_due) every
+    /// `deletion_fanout.poll_interval_secs` until `shutdown` completes.
+    pub async fn run_until(&self, shutdown: impl Future<Output = ()>) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(self.settings.poll_interval_secs));
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.run_due().await {
+                        tracing::warn!("Failed to advance user deletions: {}", e);
+                    }
+                }
+                _ = &mut shutdown => break,
+            }
+        }
+    }
+
+    /// Publishes the event to unconfirmed services whose next attempt is
+    /// due, of one deletion or all pending ones. Failures are kept on the
+    /// ack and retried after `deletion_fanout.retry_interval_secs`.
+    async fn publish_due(&self, deletion_id: Option<Uuid>) -> AppResult<()> {
+        let now = self.clock.now();
+        // Locked so replicas remind disjoint services
+        let mut tx = self.db.begin().await.context("begin user deletion reminders")?;
+        let due: Vec<(Uuid, String, UserId, DateTime<Utc>)> = sqlx::query_as(
+            r#"
+            SELECT a.deletion_id, a.service, d.user_id, d.requested_at
+            FROM user_deletion_acks a JOIN user_deletions d ON d.id = a.deletion_id
+            WHERE a.confirmed_at IS NULL AND a.next_attempt_at <= $1 AND d.status = $2
+              AND ($3::uuid IS NULL OR a.deletion_id = $3)
+            ORDER BY a.next_attempt_at
+            LIMIT $4
+            FOR UPDATE OF a SKIP LOCKED
+            "#,
+        )
+        .bind(now)
+        .bind(DELETION_PENDING)
+        .bind(deletion_id)
+        .bind(PUBLISH_BATCH)
+        .fetch_all(&mut *tx)
+        .await
+        .context("load due user deletion reminders")?;
+
+        for (deletion_id, service, user_id, requested_at) in due {
+            let event = UserDeletionRequested {
+                deletion_id,
+                user_id,
+                requested_at,
+            };
+            let error = self.publisher.publish(&service, &event).await.err().map(|e| e.to_string());
+            sqlx::query(
+                r#"
+                UPDATE user_deletion_acks SET attempts = attempts + 1, next_attempt_at = $3, last_error = $4
+                WHERE deletion_id = $1 AND service = $2
+                "#,
+            )
+            .bind(deletion_id)
+            .bind(&service)
+            .bind(now + Duration::seconds(self.settings.retry_interval_secs))
+            .bind(error)
+            .execute(&mut *tx)
+            .await
+            .entity_context("record user deletion reminder", deletion_id)?;
+        }
+        tx.commit().await.context("commit user deletion reminders")
+    }
+
+    /// Deletes the user and completes the deletion, unless it already is.
+    async fn finish(&self, deletion_id: Uuid, admin: Option<UserId>) -> AppResult<UserDeletion> {
+        let mut tx = self.db.begin().await.context("begin finishing user deletion")?;
+        let deletion: UserDeletion = sqlx::query_as("SELECT * FROM user_deletions WHERE id = $1 FOR UPDATE")
+            .bind(deletion_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .entity_context("lock user deletion", deletion_id)?
+            .ok_or_else(deletion_not_found)?;
+        if deletion.status == DELETION_COMPLETED {
+            return Ok(deletion);
+        }
+
+        // Already gone if the user was deleted some other way
+        sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(deletion.user_id)
+            .execute(&mut *tx)
+            .await
+            .entity_context("delete user", deletion.user_id)?;
+        let deletion: UserDeletion = sqlx::query_as(
+            "UPDATE user_deletions SET status = $2, finished_at = $3 WHERE id = $1 RETURNING *",
+        )
+        .bind(deletion_id)
+        .bind(DELETION_COMPLETED)
+        .bind(self.clock.now())
+        .fetch_one(&mut *tx)
+        .await
+        .entity_context("complete user deletion", deletion_id)?;
+        tx.commit().await.context("commit finishing user deletion")?;
+
+        self.response_cache.invalidate_tag(&format!("user:{}", deletion.user_id)).await;
+        self.response_cache.invalidate_tag("users:list").await;
+
+        let unconfirmed = self.unconfirmed(deletion_id).await?;
+        self.audit_service
+            .record(AuditEvent {
+                event_type: ACCOUNT_DELETED,
+                // The admin who forced it, if any; the deleted user's id
+                // stays in the metadata
+                user_id: admin,
+                ip_address: None,
+                device: None,
+                metadata: json!({
+                    "user_id": deletion.user_id,
+                    "saga_id": deletion_id,
+                    "unconfirmed": unconfirmed,
+                }),
+            })
+            .await?;
+        Ok(deletion)
+    }
+
+    async fn unconfirmed(&self, deletion_id: Uuid) -> AppResult<Vec<String>> {
+        sqlx::query_scalar(
+            "SELECT service FROM user_deletion_acks WHERE deletion_id = $1 AND confirmed_at IS NULL ORDER BY service",
+        )
+        .bind(deletion_id)
+        .fetch_all(&self.db)
+        .await
+        .entity_context("load unconfirmed user deletion acks", deletion_id)
+    }
+}
+
+fn deletion_not_found() -> AppError {
+    AppError::NotFound("User deletion not found".to_string())
+}
+
+/// Reminds services and advances deletions from startup until shutdown.
+/// Nothing when no `deletion_fanout.services` are configured.
+pub fn register_hooks(lifecycle: &mut Lifecycle, state: &Arc<AppState>) {
+    if state.settings.deletion_fanout.services.is_empty() {
+        return;
+    }
+
+    let fanout = state.deletion_fanout.clone();
+    let (stop, stopped) = oneshot::channel::<()>();
+    let (started, running) = oneshot::channel();
+    lifecycle.on_start(Hook::new("deletion fanout", move || async move {
+        let stopped = async move {
+            let _ = stopped.await;
+        };
+        let _ = started.send(tokio::spawn(async move { fanout.run_until(stopped).await }));
+        Ok(())
+    }));
+    lifecycle.on_shutdown(Hook::new("deletion fanout", move || async move {
+        let _ = stop.send(());
+        if let Ok(running) = running.await {
+            running.await?;
+        }
+        Ok(())
+    }));
+}
+
+/// How [`UserDeletionRequested`] reaches downstream services.
+pub trait DeletionPublisher: Send + Sync {
+    /// Delivers `event` to `service`, a key of `deletion_fanout.services`.
+    fn publish<'a>(&'a self, service: &'a str, event: &'a UserDeletionRequested) -> BoxFuture<'a, AppResult<()>>;
+}
+
+/// Builds the publisher selected by `deletion_fanout.publisher`; webhooks
+/// are sent through `egress`.
+pub fn from_settings(
+    settings: &DeletionFanoutSettings,
+    egress: &EgressPolicy,
+) -> Result<Arc<dyn DeletionPublisher>, ConfigError> {
+    match settings.publisher {
+        DeletionPublisherProvider::Console => Ok(Arc::new(ConsoleDeletionPublisher::default())),
+        DeletionPublisherProvider::Webhook => Ok(Arc::new(WebhookDeletionPublisher::from_settings(settings, egress)?)),
+    }
+}
+
+/// How many events [`ConsoleDeletionPublisher`] keeps.
+const OUTBOX_CAPACITY: usize = 1000;
+
+/// Logs events instead of sending them and keeps the latest ones with the
+/// service they were for, for development and [`outbox`](Self::outbox) in
+/// tests.
+#[derive(Default)]
+pub struct ConsoleDeletionPublisher {
+    outbox: Mutex<VecDeque<(String, UserDeletionRequested)>>,
+}
+
+impl ConsoleDeletionPublisher {
+    /// Events published so far, oldest first.
+    pub fn outbox(&self) -> Vec<(String, UserDeletionRequested)> {
+        self.outbox.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl DeletionPublisher for ConsoleDeletionPublisher {
+    fn publish<'a>(&'a self, service: &'a str, event: &'a UserDeletionRequested) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            tracing::info!(
+                target: "deletion_fanout",
+                service,
+                deletion_id = %event.deletion_id,
+                user_id = %event.user_id,
+                "UserDeletionRequested published"
+            );
+            let mut outbox = self.outbox.lock().unwrap();
+            if outbox.len() == OUTBOX_CAPACITY {
+                outbox.pop_front();
+            }
+            outbox.push_back((service.to_string(), event.clone()));
+            Ok(())
+        })
+    }
+}
+
+/// POSTs `{"type": "UserDeletionRequested", ...}` to each service's `url`,
+/// with its `token` as a bearer token if set.
+pub struct WebhookDeletionPublisher {
+    client: Client,
+    /// URL and token by service.
+    endpoints: HashMap<String, (String, Option<String>)>,
+}
+
+impl WebhookDeletionPublisher {
+    pub fn from_settings(settings: &DeletionFanoutSettings, egress: &EgressPolicy) -> Result<Self, ConfigError> {
+        let mut endpoints = HashMap::new();
+        for (name, service) in &settings.services {
+            let url = service.url.clone().filter(|url| !url.is_empty()).ok_or_else(|| {
+                ConfigError::Message(format!(
+                    "deletion_fanout.services.{}.url is required for the webhook publisher",
+                    name
+                ))
+            })?;
+            egress.check_endpoint(&format!("deletion_fanout.services.{}.url", name), &url)?;
+            endpoints.insert(name.clone(), (url, service.token.clone()));
+        }
+        let client = egress
+            .client_builder()
+            .timeout(std::time::Duration::from_secs(settings.request_timeout_secs))
+            .build()
+            .map_err(|e| ConfigError::Message(format!("failed to build the deletion fanout client: {}", e)))?;
+
+        Ok(Self { client, endpoints })
+    }
+}
+
+impl DeletionPublisher for WebhookDeletionPublisher {
+    fn publish<'a>(&'a self, service: &'a str, event: &'a UserDeletionRequested) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let (url, token) = self
+                .endpoints
+                .get(service)
+                .ok_or_else(|| AppError::BadRequest(format!("Unknown deletion fanout service {}", service)))?;
+            let mut request = self.client.post(url).json(&json!({
+                "type": "UserDeletionRequested",
+                "deletion_id": event.deletion_id,
+                "user_id": event.user_id,
+                "requested_at": event.requested_at,
+            }));
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+
+            let response = with_trace_context(request).send().await.and_then(|response| response.error_for_status());
+            response.map(|_| ()).map_err(|e| {
+                tracing::warn!(service, deletion_id = %event.deletion_id, "Publishing UserDeletionRequested failed: {}", e);
+                AppError::Unavailable {
+                    message: format!("Publishing to {} failed", service),
+                    retry: RetryHint::after(std::time::Duration::from_secs(60)),
+                }
+            })
+        })
+    }
+}
@@ -0,0 +1,434 @@
+//! Multi-step processes that undo themselves when a step fails.
+//!
+//! A [`Saga`] is a list of [`SagaStep`]s, each with an action and a
+//! compensation. [`SagaEngine::start`] persists a run and enqueues a job that
+//! executes the steps in order, saving progress after each one. When a step
+//! fails the completed steps are compensated in reverse; failed
+//! compensations are retried with the job's backoff until
+//! `sagas.max_compensation_attempts`, after which the saga is left `failed`
+//! for manual review.
+//!
+//! A run holds a lease on its saga while it executes. Sagas whose lease ran
+//! out without their job finishing, e.g. because the process crashed, are
+//! picked up again by [`SagaEngine::resume_stalled`] and continue with the
+//! step they were on, so steps must be safe to run twice. Steps talking to
+//! other services should pass the saga id along as an idempotency key.
+//!
+//! ```ignore
+//! let saga = Saga::new("order.checkout").step(ReserveStock(..)).step(ChargeCard(..));
+//! let engine = SagaEngine::new(db, job_queue, settings, clock).register(saga);
+//! engine.start("order.checkout", &json!({ "order_id": order_id })).await?;
+//! ```
+//!
+//! Runs record `sagas_started_total`, `sagas_finished_total`,
+//! `saga_step_failures_total` and `saga_step_duration_seconds` through the
+//! `metrics` facade.
+
+use chrono::Duration;
+use futures_util::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+use uuid::Uuid;
+
+use crate::config::SagaSettings;
+use crate::errors::{AppError, AppResult, ResultExt};
+use crate::jobs::{JobHandler, JobQueue, Worker};
+use crate::models::job::{Job, JOB_COMPLETED, JOB_DEAD, JOB_RUNNING};
+use crate::models::saga::{SagaRecord, SAGA_COMPENSATED, SAGA_COMPENSATING, SAGA_COMPLETED, SAGA_FAILED, SAGA_RUNNING};
+use crate::utils::SharedClock;
+use crate::AppState;
+
+pub mod account_deletion;
+pub mod deletion_fanout;
+
+pub const RUN_JOB: &str = "saga.run";
+
+/// What a saga's steps share; saved after every step.
+#[derive(Debug, Clone)]
+pub struct SagaContext {
+    pub saga_id: Uuid,
+    /// The data the saga was started with, plus whatever steps add for later
+    /// steps and compensations.
+    pub data: serde_json::Value,
+}
+
+impl SagaContext {
+    /// The data as the type the saga was started with.
+    pub fn parse<T: DeserializeOwned>(&self) -> AppResult<T> {
+        serde_json::from_value(self.data.clone())
+            .map_err(|e| AppError::BadRequest(format!("Invalid saga data: {}", e)))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&serde_json::Value> {
+        self.data.get(key)
+    }
+
+    pub fn set(&mut self, key: &str, value: impl Into<serde_json::Value>) {
+        if let serde_json::Value::Object(fields) = &mut self.data {
+            fields.insert(key.to_string(), value.into());
+        }
+    }
+}
+
+pub trait SagaStep: Send + Sync {
+    /// Identifies the step in logs and metrics.
+    fn name(&self) -> &'static str;
+
+    /// Does the step's work; an error starts compensation.
+    fn execute<'a>(&'a self, context: &'a mut SagaContext) -> BoxFuture<'a, AppResult<()>>;
+
+    /// Undoes [`execute`](Self::execute) after a later step failed. Nothing
+    /// by default, for steps that can't or needn't be undone.
+    fn compensate<'a>(&'a self, context: &'a mut SagaContext) -> BoxFuture<'a, AppResult<()>> {
+        let _ = context;
+        Box::pin(async { Ok(()) })
+    }
+}
+
+pub struct Saga {
+    kind: String,
+    steps: Vec<Box<dyn SagaStep>>,
+}
+
+impl Saga {
+    pub fn new(kind: &str) -> Self {
+        Self {
+            kind: kind.to_string(),
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn step(mut self, step: impl SagaStep + 'static) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RunPayload {
+    saga_id: Uuid,
+}
+
+pub struct SagaEngine {
+    db: PgPool,
+    job_queue: Arc<JobQueue>,
+    settings: SagaSettings,
+    clock: SharedClock,
+    sagas: HashMap<String, Saga>,
+}
+
+impl SagaEngine {
+    pub fn new(db: PgPool, job_queue: Arc<JobQueue>, settings: SagaSettings, clock: SharedClock) -> Self {
+        Self {
+            db,
+            job_queue,
+            settings,
+            clock,
+            sagas: HashMap::new(),
+        }
+    }
+
+    pub fn register(mut self, saga: Saga) -> Self {
+        self.sagas.insert(saga.kind.clone(), saga);
+        self
+    }
+
+    /// Persists a run of the `kind` saga and enqueues the job executing it.
+    pub async fn start(&self, kind: &str, data: &impl Serialize) -> AppResult<Uuid> {
+        if !self.sagas.contains_key(kind) {
+            return Err(AppError::BadRequest(format!("Unknown saga kind {}", kind)));
+        }
+        let data = serde_json::to_value(data)
+            .map_err(|e| AppError::BadRequest(format!("Invalid {} saga data: {}", kind, e)))?;
+
+        let saga_id = Uuid::new_v4();
+        let now = self.clock.now();
+        let mut tx = self.db.begin().await.context("begin transaction")?;
+        let job_id = self
+            .job_queue
+            .enqueue_with(&mut *tx, RUN_JOB, &RunPayload { saga_id })
+            .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO sagas (id, kind, status, data, job_id, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $6)
+            "#,
+        )
+        .bind(saga_id)
+        .bind(kind)
+        .bind(SAGA_RUNNING)
+        .bind(data)
+        .bind(job_id)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .entity_context("start saga", saga_id)?;
+        tx.commit().await.context("commit transaction")?;
+
+        metrics::counter!("sagas_started_total", "saga" => kind.to_string()).increment(1);
+        Ok(saga_id)
+    }
+
+    pub async fn get_saga(&self, saga_id: Uuid) -> AppResult<Option<SagaRecord>> {
+        sqlx::query_as::<_, SagaRecord>("SELECT * FROM sagas WHERE id = $1")
+            .bind(saga_id)
+            .fetch_optional(&self.db)
+            .await
+            .entity_context("load saga", saga_id)
+    }
+
+    /// Executes the saga from where it left off. Does nothing if it is
+    /// finished or another run holds its lease. Errs when a compensation
+    /// failed and should be retried.
+    pub async fn run(&self, saga_id: Uuid) -> AppResult<()> {
+        let Some(mut record) = self.claim(saga_id).await? else {
+            return Ok(());
+        };
+        let Some(saga) = self.sagas.get(&record.kind) else {
+            tracing::error!(saga_id = %saga_id, kind = %record.kind, "No saga registered for kind");
+            record.status = SAGA_FAILED.to_string();
+            record.last_error = Some(format!("no saga registered for kind {}", record.kind));
+            return self.finish(&record).await;
+        };
+        let mut context = SagaContext {
+            saga_id,
+            data: record.data.clone(),
+        };
+
+        while record.status == SAGA_RUNNING {
+            let Some(step) = saga.steps.get(record.current_step as usize) else {
+                record.status = SAGA_COMPLETED.to_string();
+                return self.finish(&record).await;
+            };
+
+            let started = Instant::now();
+            let result = step.execute(&mut context).await;
+            metrics::histogram!(
+                "saga_step_duration_seconds",
+                "saga" => saga.kind.clone(),
+                "step" => step.name()
+            )
+            .record(started.elapsed().as_secs_f64());
+            record.data = context.data.clone();
+
+            match result {
+                Ok(()) => record.current_step += 1,
+                Err(e) => {
+                    tracing::warn!(saga_id = %saga_id, kind = %saga.kind, step = step.name(), "Saga step failed, compensating: {}", e);
+                    step_failed(saga, step.as_ref(), "execute");
+                    record.status = SAGA_COMPENSATING.to_string();
+                    record.last_error = Some(e.to_string());
+                }
+            }
+            self.save(&record).await?;
+        }
+
+        // Only completed steps are undone; the one that failed is expected
+        // to have left nothing behind
+        while record.current_step > 0 {
+            let step = &saga.steps[record.current_step as usize - 1];
+
+            match step.compensate(&mut context).await {
+                Ok(()) => {
+                    record.current_step -= 1;
+                    record.data = context.data.clone();
+                    self.save(&record).await?;
+                }
+                Err(e) => {
+                    step_failed(saga, step.as_ref(), "compensate");
+                    record.compensation_attempts += 1;
+                    record.last_error = Some(e.to_string());
+
+                    if record.compensation_attempts >= self.settings.max_compensation_attempts {
+                        tracing::error!(saga_id = %saga_id, kind = %saga.kind, step = step.name(), "Saga compensation failed for good: {}", e);
+                        record.status = SAGA_FAILED.to_string();
+                        return self.finish(&record).await;
+                    }
+                    tracing::warn!(saga_id = %saga_id, kind = %saga.kind, step = step.name(), "Saga compensation failed, retrying: {}", e);
+                    self.release(&record).await?;
+                    return Err(e);
+                }
+            }
+        }
+
+        record.status = SAGA_COMPENSATED.to_string();
+        self.finish(&record).await
+    }
+
+    /// Re-enqueues active sagas whose run died: their lease ran out and
+    /// their job is gone, finished or stuck running. Returns their ids.
+    pub async fn resume_stalled(&self) -> AppResult<Vec<Uuid>> {
+        let now = self.clock.now();
+        let mut tx = self.db.begin().await.context("begin transaction")?;
+
+        let stalled: Vec<(Uuid, Option<Uuid>)> = sqlx::query_as(
+            r#"
+            SELECT s.id, s.job_id FROM sagas s
+            LEFT JOIN jobs j ON j.id = s.job_id
+            WHERE s.status IN ($1, $2)
+              AND (s.locked_until IS NULL OR s.locked_until <= $3)
+              AND (j.id IS NULL OR j.status IN ($4, $5) OR (j.status = $6 AND j.locked_at <= $7))
+            FOR UPDATE OF s SKIP LOCKED
+            "#,
+        )
+        .bind(SAGA_RUNNING)
+        .bind(SAGA_COMPENSATING)
+        .bind(now)
+        .bind(JOB_COMPLETED)
+        .bind(JOB_DEAD)
+        .bind(JOB_RUNNING)
+        .bind(now - self.lease())
+        .fetch_all(&mut *tx)
+        .await
+        .context("find stalled sagas")?;
+
+        for (saga_id, job_id) in &stalled {
+            if let Some(job_id) = job_id {
+                // The worker that claimed it is gone and won't finish it
+                sqlx::query(
+                    "UPDATE jobs SET status = $2, locked_at = NULL, finished_at = $3, last_error = 'abandoned; saga resumed' WHERE id = $1 AND status = $4",
+                )
+                .bind(job_id)
+                .bind(JOB_DEAD)
+                .bind(now)
+                .bind(JOB_RUNNING)
+                .execute(&mut *tx)
+                .await
+                .entity_context("abandon job", job_id)?;
+            }
+
+            let job_id = self
+                .job_queue
+                .enqueue_with(&mut *tx, RUN_JOB, &RunPayload { saga_id: *saga_id })
+                .await?;
+            sqlx::query("UPDATE sagas SET job_id = $2, updated_at = $3 WHERE id = $1")
+                .bind(saga_id)
+                .bind(job_id)
+                .bind(now)
+                .execute(&mut *tx)
+                .await
+                .entity_context("resume saga", saga_id)?;
+            tracing::warn!(saga_id = %saga_id, "Resuming stalled saga");
+        }
+
+        tx.commit().await.context("commit transaction")?;
+        Ok(stalled.into_iter().map(|(saga_id, _)| saga_id).collect())
+    }
+
+    /// Calls [`resume_stalled`](Self::resume_stalled) every
+    /// `sagas.resume_interval_secs` until `shutdown` completes.
+    pub async fn resume_stalled_until(&self, shutdown: impl Future<Output = ()>) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(self.settings.resume_interval_secs));
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.resume_stalled().await {
+                        tracing::warn!("Failed to resume stalled sagas: {}", e);
+                    }
+                }
+                _ = &mut shutdown => break,
+            }
+        }
+    }
+
+    fn lease(&self) -> Duration {
+        Duration::seconds(self.settings.lease_secs)
+    }
+
+    async fn claim(&self, saga_id: Uuid) -> AppResult<Option<SagaRecord>> {
+        let now = self.clock.now();
+
+        sqlx::query_as::<_, SagaRecord>(
+            r#"
+            UPDATE sagas SET locked_until = $4, updated_at = $3
+            WHERE id = $1 AND status IN ($2, $5) AND (locked_until IS NULL OR locked_until <= $3)
+            RETURNING *
+            "#,
+        )
+        .bind(saga_id)
+        .bind(SAGA_RUNNING)
+        .bind(now)
+        .bind(now + self.lease())
+        .bind(SAGA_COMPENSATING)
+        .fetch_optional(&self.db)
+        .await
+        .entity_context("claim saga", saga_id)
+    }
+
+    /// Saves progress and extends the lease.
+    async fn save(&self, record: &SagaRecord) -> AppResult<()> {
+        let now = self.clock.now();
+        self.update(record, Some(now + self.lease())).await
+    }
+
+    /// Saves progress and gives the lease up, for a retry to pick it up.
+    async fn release(&self, record: &SagaRecord) -> AppResult<()> {
+        self.update(record, None).await
+    }
+
+    async fn finish(&self, record: &SagaRecord) -> AppResult<()> {
+        self.release(record).await?;
+        metrics::counter!(
+            "sagas_finished_total",
+            "saga" => record.kind.clone(),
+            "status" => record.status.clone()
+        )
+        .increment(1);
+        Ok(())
+    }
+
+    async fn update(&self, record: &SagaRecord, locked_until: Option<chrono::DateTime<chrono::Utc>>) -> AppResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE sagas SET status = $2, current_step = $3, data = $4, last_error = $5,
+                compensation_attempts = $6, locked_until = $7, updated_at = $8
+            WHERE id = $1
+            "#,
+        )
+        .bind(record.id)
+        .bind(&record.status)
+        .bind(record.current_step)
+        .bind(&record.data)
+        .bind(&record.last_error)
+        .bind(record.compensation_attempts)
+        .bind(locked_until)
+        .bind(self.clock.now())
+        .execute(&self.db)
+        .await
+        .entity_context("save saga", record.id)?;
+
+        Ok(())
+    }
+}
+
+fn step_failed(saga: &Saga, step: &dyn SagaStep, phase: &'static str) {
+    metrics::counter!(
+        "saga_step_failures_total",
+        "saga" => saga.kind.clone(),
+        "step" => step.name(),
+        "phase" => phase
+    )
+    .increment(1);
+}
+
+struct Run(Arc<SagaEngine>);
+
+impl JobHandler for Run {
+    fn handle<'a>(&'a self, job: &'a Job) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let payload: RunPayload = job.payload()?;
+            self.0.run(payload.saga_id).await
+        })
+    }
+}
+
+pub fn register_jobs(worker: Worker, state: &AppState) -> Worker {
+    worker.handler(RUN_JOB, Run(state.saga_engine.clone()))
+}
@@ -0,0 +1,224 @@
+//! Secure-by-default preset: body limits, hardening headers, CSRF checks and
+//! strict CORS.
+//!
+//! `ServiceConfig` can't wrap middleware, so the preset comes in two parts:
+//!
+//! ```ignore
+//! App::new()
+//!     .configure(security::defaults(&settings))
+//!     .wrap(Security::new(&settings.security))
+//! ```
+//!
+//! Auth throttling and maintenance mode are scoped to their routes in
+//! [`handlers::routes`](crate::handlers::routes). Every protection is
+//! tuned or switched off through [`SecuritySettings`].
+
+use actix_cors::{Cors, CorsMiddleware};
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderName, HeaderValue},
+    http::Method,
+    error::InternalError,
+    web, Error, HttpRequest,
+};
+use futures_util::future::LocalBoxFuture;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use crate::config::{SecuritySettings, Settings};
+use crate::dry_run::DRY_RUN_HEADER;
+use crate::errors::{AppError, ErrorCode};
+use crate::handlers::{json_config, path_config, query_config};
+
+/// Registers request body limits: JSON bodies are capped at
+/// `server.max_json_body_bytes`, raw payloads and forms at
+/// `server.max_payload_bytes`. Paths and query strings that don't parse
+/// get the standard error body too.
+pub fn defaults(settings: &Settings) -> impl FnOnce(&mut web::ServiceConfig) {
+    let max_json_body_bytes = settings.server.max_json_body_bytes;
+    let max_payload_bytes = settings.server.max_payload_bytes;
+
+    move |cfg| {
+        cfg.app_data(json_config(max_json_body_bytes))
+            .app_data(web::PayloadConfig::new(max_payload_bytes))
+            .app_data(web::FormConfig::default().limit(max_payload_bytes))
+            .app_data(path_config())
+            .app_data(query_config());
+    }
+}
+
+/// Middleware bundle adding hardening headers, rejecting cross-origin
+/// changes to cookie-authenticated state and enforcing the CORS allow-list.
+///
+/// Errors from inner services get the headers too.
+pub struct Security {
+    settings: Rc<SecuritySettings>,
+}
+
+impl Security {
+    pub fn new(settings: &SecuritySettings) -> Self {
+        Self {
+            settings: Rc::new(settings.clone()),
+        }
+    }
+
+    /// CORS limited to `allowed_origins`; with none, browsers may only call
+    /// the API from its own origin.
+    fn cors(&self) -> Cors {
+        // Browsers send `Origin` on same-origin writes too
+        let same_origin = Cors::default().allowed_origin_fn(|origin, head| {
+            let host = head.headers().get(header::HOST).and_then(|host| host.to_str().ok());
+            let authority = origin
+                .to_str()
+                .ok()
+                .and_then(origin_of)
+                .and_then(|origin| origin.split_once("://"))
+                .map(|(_, authority)| authority);
+            matches!((authority, host), (Some(authority), Some(host)) if authority.eq_ignore_ascii_case(host))
+        });
+        let cors = self
+            .settings
+            .allowed_origins
+            .iter()
+            .fold(same_origin, |cors, origin| cors.allowed_origin(origin));
+
+        cors.allowed_methods(["GET", "POST", "PUT", "PATCH", "DELETE"])
+            .allowed_headers([header::AUTHORIZATION, header::CONTENT_TYPE, header::IF_MATCH, DRY_RUN_HEADER])
+            .expose_headers([header::ETAG, header::RETRY_AFTER, HeaderName::from_static("x-request-id"), DRY_RUN_HEADER])
+            .max_age(self.settings.cors_max_age_secs)
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Security
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = SecurityMiddleware<CorsMiddleware<S>>;
+    type Future = LocalBoxFuture<'static, Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let cors = self.cors().new_transform(service);
+        let settings = self.settings.clone();
+
+        Box::pin(async move {
+            Ok(SecurityMiddleware {
+                service: Rc::new(cors.await?),
+                settings,
+            })
+        })
+    }
+}
+
+pub struct SecurityMiddleware<S> {
+    service: Rc<S>,
+    settings: Rc<SecuritySettings>,
+}
+
+impl<S, B> Service<ServiceRequest> for SecurityMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let settings = self.settings.clone();
+
+        Box::pin(async move {
+            let result = if settings.csrf && !csrf_safe(req.request(), &settings.allowed_origins) {
+                Err(AppError::Forbidden.with_code(ErrorCode::CsrfOriginMismatch).into())
+            } else {
+                service.call(req).await
+            };
+
+            if !settings.headers {
+                return result;
+            }
+            match result {
+                Ok(mut res) => {
+                    add_security_headers(res.headers_mut(), &settings);
+                    Ok(res)
+                }
+                Err(error) => {
+                    let mut response = error.error_response();
+                    add_security_headers(response.headers_mut(), &settings);
+                    Err(InternalError::from_response(error, response).into())
+                }
+            }
+        })
+    }
+}
+
+/// Whether the request can't be a cross-site forgery: it is safe, carries no
+/// cookies, authenticates with a header browsers don't attach on their own,
+/// or comes from this origin or an allowed one.
+fn csrf_safe(req: &HttpRequest, allowed_origins: &[String]) -> bool {
+    let safe_method = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE);
+    let headers = req.headers();
+    if safe_method || !headers.contains_key(header::COOKIE) || headers.contains_key(header::AUTHORIZATION) {
+        return true;
+    }
+
+    // Prefer `Origin`, falling back to the origin part of `Referer`
+    let origin = headers
+        .get(header::ORIGIN)
+        .or_else(|| headers.get(header::REFERER))
+        .and_then(|value| value.to_str().ok())
+        .and_then(origin_of);
+    let Some(origin) = origin else {
+        return false;
+    };
+
+    let info = req.connection_info();
+    let own_origin = format!("{}://{}", info.scheme(), info.host());
+    origin.eq_ignore_ascii_case(&own_origin)
+        || allowed_origins
+            .iter()
+            .any(|allowed| origin.eq_ignore_ascii_case(allowed.trim_end_matches('/')))
+}
+
+/// `scheme://host[:port]` of a URL; `None` for opaque origins like `null`.
+fn origin_of(url: &str) -> Option<&str> {
+    let (scheme, rest) = url.split_once("://")?;
+    if scheme.is_empty() || rest.is_empty() {
+        return None;
+    }
+    let end = rest.find(['/', '?', '#']).map_or(url.len(), |index| scheme.len() + 3 + index);
+    Some(&url[..end])
+}
+
+/// Adds the hardening headers, keeping any a handler already set.
+fn add_security_headers(headers: &mut header::HeaderMap, settings: &SecuritySettings) {
+    let mut set = |name: HeaderName, value: &str| {
+        if let (false, Ok(value)) = (headers.contains_key(&name), HeaderValue::from_str(value)) {
+            headers.insert(name, value);
+        }
+    };
+
+    set(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+    set(header::X_FRAME_OPTIONS, &settings.frame_options);
+    set(header::REFERRER_POLICY, &settings.referrer_policy);
+    set(header::CONTENT_SECURITY_POLICY, &settings.content_security_policy);
+    set(HeaderName::from_static("cross-origin-opener-policy"), "same-origin");
+    set(header::CACHE_CONTROL, "no-store");
+    if settings.hsts_max_age_secs > 0 {
+        set(
+            header::STRICT_TRANSPORT_SECURITY,
+            &format!("max-age={}; includeSubDomains", settings.hsts_max_age_secs),
+        );
+    }
+}
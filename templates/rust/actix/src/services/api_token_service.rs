@@ -0,0 +1,196 @@
+use crate::dry_run::DryRun;
+use crate::errors::{AppError, AppResult, ErrorCode, ResultExt};
+use crate::models::api_token::{ApiToken, CreateApiToken};
+use crate::utils::request_signing::{self, SignatureHeader};
+use crate::utils::{api_token_prefix, generate_api_token, generate_secret, hash_token, SharedClock, SigningKeys};
+use chrono::Duration;
+use platform_core::domain::UserId;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const SIGNING_KEY_LENGTH: usize = 48;
+const SIGNING_KEY_PURPOSE: &str = "api-token-signing-key";
+/// Sealed keys live as long as their token; expiry and revocation are
+/// enforced on the token row.
+const SEALED_KEY_TTL: Duration = Duration::days(100 * 365);
+
+pub struct ApiTokenService {
+    db: PgPool,
+    clock: SharedClock,
+    signing_keys: SigningKeys,
+}
+
+/// A freshly minted token with the secrets only available at this point.
+pub struct CreatedApiToken {
+    pub api_token: ApiToken,
+    pub token: String,
+    pub signing_key: Option<String>,
+}
+
+impl ApiTokenService {
+    pub fn new(db: PgPool, clock: SharedClock, signing_keys: SigningKeys) -> Self {
+        Self { db, clock, signing_keys }
+    }
+
+    /// Mints a new personal access token, plus a request signing key if
+    /// asked for.
+    pub async fn create_token(
+        &self,
+        user_id: UserId,
+        create_token: CreateApiToken,
+        dry_run: DryRun,
+    ) -> AppResult<CreatedApiToken> {
+        let (token, prefix) = generate_api_token();
+        let token_hash = hash_token(&token);
+        let scopes: Vec<String> = create_token
+            .scopes
+            .iter()
+            .map(|scope| scope.to_string())
+            .collect();
+        let expires_at = create_token
+            .expires_in_days
+            .map(|days| self.clock.now() + Duration::days(days as i64));
+
+        let signing_key = create_token.signing.then(|| generate_secret(SIGNING_KEY_LENGTH));
+        let sealed_signing_key = signing_key
+            .as_ref()
+            .map(|key| self.signing_keys.encrypt(SIGNING_KEY_PURPOSE, key, SEALED_KEY_TTL))
+            .transpose()?;
+
+        let mut tx = self.db.begin().await.context("begin api token creation")?;
+        let api_token = sqlx::query_as::<_, ApiToken>(
+            r#"
+            INSERT INTO api_tokens (user_id, name, prefix, token_hash, scopes, expires_at, signing_key)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING *
+            "#
+        )
+        .bind(user_id)
+        .bind(&create_token.name)
+        .bind(&prefix)
+        .bind(&token_hash)
+        .bind(&scopes)
+        .bind(expires_at)
+        .bind(&sealed_signing_key)
+        .fetch_one(&mut *tx)
+        .await
+        .entity_context("insert api token", user_id)?;
+        dry_run.finish(tx).await?;
+
+        Ok(CreatedApiToken {
+            api_token,
+            token,
+            signing_key,
+        })
+    }
+
+    pub async fn list_tokens(&self, user_id: UserId) -> AppResult<Vec<ApiToken>> {
+        let tokens = sqlx::query_as::<_, ApiToken>(
+            "SELECT * FROM api_tokens WHERE user_id = $1 AND revoked_at IS NULL ORDER BY created_at DESC"
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await
+        .entity_context("list api tokens", user_id)?;
+
+        Ok(tokens)
+    }
+
+    pub async fn revoke_token(&self, user_id: UserId, token_id: Uuid, dry_run: DryRun) -> AppResult<()> {
+        let mut tx = self.db.begin().await.context("begin api token revocation")?;
+        let result = sqlx::query(
+            "UPDATE api_tokens SET revoked_at = $3 WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL"
+        )
+        .bind(token_id)
+        .bind(user_id)
+        .bind(self.clock.now())
+        .execute(&mut *tx)
+        .await
+        .entity_context("revoke api token", token_id)?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound("Token not found".to_string()).with_code(ErrorCode::ApiTokenNotFound));
+        }
+
+        dry_run.finish(tx).await
+    }
+
+    pub async fn revoke_all(&self, user_id: UserId) -> AppResult<()> {
+        sqlx::query("UPDATE api_tokens SET revoked_at = $2 WHERE user_id = $1 AND revoked_at IS NULL")
+            .bind(user_id)
+            .bind(self.clock.now())
+            .execute(&self.db)
+            .await
+            .entity_context("revoke api tokens", user_id)?;
+
+        Ok(())
+    }
+
+    /// Resolves a plaintext token to its active record, recording its use.
+    pub async fn authenticate(&self, token: &str) -> AppResult<ApiToken> {
+        let prefix = api_token_prefix(token).ok_or(AppError::Unauthorized)?;
+
+        let api_token = sqlx::query_as::<_, ApiToken>(
+            r#"
+            UPDATE api_tokens SET last_used_at = $3
+            WHERE prefix = $1
+              AND token_hash = $2
+              AND revoked_at IS NULL
+              AND (expires_at IS NULL OR expires_at > $3)
+            RETURNING *
+            "#
+        )
+        .bind(prefix)
+        .bind(hash_token(token))
+        .bind(self.clock.now())
+        .fetch_optional(&self.db)
+        .await
+        .entity_context("authenticate api token", prefix)?
+        .ok_or(AppError::Unauthorized)?;
+
+        Ok(api_token)
+    }
+
+    /// Verifies a signed request against the signing key of the token named
+    /// by its credential, and returns the token's active record.
+    pub async fn authenticate_signed(
+        &self,
+        signature: &SignatureHeader,
+        date: &str,
+        canonical_request: &str,
+    ) -> AppResult<ApiToken> {
+        let invalid = || AppError::Unauthorized.with_code(ErrorCode::AuthSignatureInvalid);
+
+        let api_token = sqlx::query_as::<_, ApiToken>(
+            r#"
+            SELECT * FROM api_tokens
+            WHERE prefix = $1
+              AND signing_key IS NOT NULL
+              AND revoked_at IS NULL
+              AND (expires_at IS NULL OR expires_at > $2)
+            "#
+        )
+        .bind(&signature.credential)
+        .bind(self.clock.now())
+        .fetch_optional(&self.db)
+        .await
+        .entity_context("authenticate signed request", &signature.credential)?
+        .ok_or_else(invalid)?;
+
+        let sealed = api_token.signing_key.as_deref().ok_or_else(invalid)?;
+        let signing_key: String = self.signing_keys.decrypt(SIGNING_KEY_PURPOSE, sealed)?;
+        if !request_signing::verify(&signing_key, date, canonical_request, &signature.signature) {
+            return Err(invalid());
+        }
+
+        // Only recorded once the signature checks out
+        sqlx::query("UPDATE api_tokens SET last_used_at = $2 WHERE id = $1")
+            .bind(api_token.id)
+            .bind(self.clock.now())
+            .execute(&self.db)
+            .await
+            .entity_context("record api token use", api_token.id)?;
+
+        Ok(api_token)
+    }
+}
@@ -0,0 +1,51 @@
+use crate::errors::AppResult;
+use crate::models::audit_event::AuditEvent;
+use crate::olap::{DomainEvent, EventBuffer, OlapEvent};
+use crate::utils::SharedClock;
+use sqlx::types::Json;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+pub struct AuditService {
+    db: PgPool,
+    /// Audit events are also domain events for the OLAP store.
+    olap_events: Arc<EventBuffer>,
+    clock: SharedClock,
+}
+
+impl AuditService {
+    pub fn new(db: PgPool, olap_events: Arc<EventBuffer>, clock: SharedClock) -> Self {
+        Self { db, olap_events, clock }
+    }
+
+    pub async fn record(&self, event: AuditEvent) -> AppResult<()> {
+        tracing::info!(
+            target: "audit",
+            event_type = event.event_type,
+            user_id = ?event.user_id,
+            ip_address = ?event.ip_address,
+            device = ?event.device,
+            metadata = %event.metadata,
+            "Audit event recorded"
+        );
+
+        sqlx::query(
+            "INSERT INTO audit_events (event_type, user_id, ip_address, device, metadata) VALUES ($1, $2, $3, $4, $5)"
+        )
+        .bind(event.event_type)
+        .bind(event.user_id)
+        .bind(&event.ip_address)
+        .bind(event.device.map(Json))
+        .bind(&event.metadata)
+        .execute(&self.db)
+        .await?;
+
+        self.olap_events.record(OlapEvent::Domain(DomainEvent {
+            timestamp: self.clock.now(),
+            event_type: event.event_type.to_string(),
+            user_id: event.user_id,
+            metadata: event.metadata.to_string(),
+        }));
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+use crate::config::AuthThrottleSettings;
+use crate::errors::{AppError, AppResult, ErrorCode, RetryHint};
+use crate::utils::{hash_token, ip_in_cidr};
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Script};
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Increments the counter at `KEYS[1]` and starts its window of `ARGV[1]`
+/// seconds in the same step, so a counter can't be left without an expiry
+/// and lock its IP or account out for good. Counters already missing one
+/// get it on their next hit.
+const INCREMENT: &str = r"
+    local count = redis.call('INCR', KEYS[1])
+    if redis.call('TTL', KEYS[1]) < 0 then
+        redis.call('EXPIRE', KEYS[1], ARGV[1])
+    end
+    return count
+";
+
+/// Redis-backed counters protecting the `/auth/*` routes.
+///
+/// Every request counts against its client IP; failed logins count against
+/// both the IP and the account. Failures beyond a threshold delay responses
+/// progressively, and too many failures for an account lock it for the rest
+/// of the window. Counters are shared by all replicas.
+pub struct AuthThrottleService {
+    redis: Option<ConnectionManager>,
+    settings: AuthThrottleSettings,
+    increment: Script,
+}
+
+impl AuthThrottleService {
+    /// Connects to Redis unless throttling is disabled.
+    pub async fn new(redis_url: &str, settings: AuthThrottleSettings) -> redis::RedisResult<Self> {
+        let redis = if settings.enabled {
+            let client = redis::Client::open(redis_url)?;
+            Some(ConnectionManager::new(client).await?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            redis,
+            settings,
+            increment: Script::new(INCREMENT),
+        })
+    }
+
+    pub fn is_trusted_gateway(&self, ip: IpAddr) -> bool {
+        self.settings
+            .trusted_gateways
+            .iter()
+            .any(|cidr| ip_in_cidr(ip, cidr))
+    }
+
+    /// Counts a request against the client IP.
+    pub async fn hit_ip(&self, ip: IpAddr) -> AppResult<()> {
+        if self.is_trusted_gateway(ip) {
+            return Ok(());
+        }
+
+        let key = ip_requests_key(ip);
+        let requests = self.increment(&key).await;
+        if requests > self.settings.ip_limit {
+            return Err(self.too_many_requests(&key).await);
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the account isn't locked and returns how long the
+    /// response should be delayed, based on prior failures.
+    pub async fn check_login(&self, ip: Option<IpAddr>, account: &str) -> AppResult<Duration> {
+        let key = account_failures_key(account);
+        let account_failures = self.get(&key).await;
+        if account_failures >= self.settings.account_failure_limit {
+            return Err(self.too_many_requests(&key).await);
+        }
+
+        let ip_failures = match ip {
+            Some(ip) if !self.is_trusted_gateway(ip) => self.get(&ip_failures_key(ip)).await,
+            _ => 0,
+        };
+
+        Ok(self.delay_for(account_failures.max(ip_failures)))
+    }
+
+    pub async fn record_failure(&self, ip: Option<IpAddr>, account: &str) {
+        self.increment(&account_failures_key(account)).await;
+        if let Some(ip) = ip.filter(|ip| !self.is_trusted_gateway(*ip)) {
+            self.increment(&ip_failures_key(ip)).await;
+        }
+    }
+
+    pub async fn record_success(&self, account: &str) {
+        let Some(mut redis) = self.redis.clone() else {
+            return;
+        };
+        if let Err(e) = redis.del::<_, ()>(account_failures_key(account)).await {
+            tracing::warn!("Failed to reset auth throttle counter: {}", e);
+        }
+    }
+
+    fn delay_for(&self, failures: u64) -> Duration {
+        let excess = failures.saturating_sub(self.settings.delay_after_failures);
+        let delay_ms = excess
+            .saturating_mul(self.settings.delay_step_ms)
+            .min(self.settings.max_delay_ms);
+        Duration::from_millis(delay_ms)
+    }
+
+    /// Rejection hinting at when the counter at `key` resets.
+    async fn too_many_requests(&self, key: &str) -> AppError {
+        let retry_secs = self.remaining_window(key).await.unwrap_or(self.settings.window_secs);
+        AppError::Throttled {
+            message: format!("Too many authentication attempts, retry in {} seconds", retry_secs),
+            retry: RetryHint::after(Duration::from_secs(retry_secs)),
+        }
+        .with_code(ErrorCode::AuthTooManyAttempts)
+    }
+
+    async fn remaining_window(&self, key: &str) -> Option<u64> {
+        let mut redis = self.redis.clone()?;
+        match redis.ttl::<_, i64>(key).await {
+            // Negative values mean the key is gone or has no expiry
+            Ok(ttl) => u64::try_from(ttl).ok().filter(|ttl| *ttl > 0),
+            Err(e) => {
+                tracing::warn!("Failed to read auth throttle window: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Counter errors are logged and treated as zero, so a Redis outage
+    /// degrades throttling rather than authentication itself.
+    async fn increment(&self, key: &str) -> u64 {
+        let Some(mut redis) = self.redis.clone() else {
+            return 0;
+        };
+
+        let result: redis::RedisResult<u64> = self
+            .increment
+            .key(key)
+            .arg(self.settings.window_secs)
+            .invoke_async(&mut redis)
+            .await;
+
+        match result {
+            Ok(count) => count,
+            Err(e) => {
+                tracing::warn!("Failed to update auth throttle counter: {}", e);
+                0
+            }
+        }
+    }
+
+    async fn get(&self, key: &str) -> u64 {
+        let Some(mut redis) = self.redis.clone() else {
+            return 0;
+        };
+
+        match redis.get::<_, Option<u64>>(key).await {
+            Ok(count) => count.unwrap_or(0),
+            Err(e) => {
+                tracing::warn!("Failed to read auth throttle counter: {}", e);
+                0
+            }
+        }
+    }
+}
+
+fn ip_requests_key(ip: IpAddr) -> String {
+    format!("auth_throttle:ip:{}:requests", ip)
+}
+
+fn ip_failures_key(ip: IpAddr) -> String {
+    format!("auth_throttle:ip:{}:failures", ip)
+}
+
+/// Accounts are keyed by a hash of the normalized identifier, keeping
+/// emails out of Redis.
+fn account_failures_key(account: &str) -> String {
+    format!("auth_throttle:account:{}:failures", hash_token(&account.trim().to_lowercase()))
+}
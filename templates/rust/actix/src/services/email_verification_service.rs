@@ -0,0 +1,148 @@
+use crate::config::{EmailSettings, EmailVerificationSettings};
+use crate::errors::{AppError, AppResult, ErrorCode, ResultExt, RetryHint};
+use crate::mail::{EmailMessage, Mailer};
+use crate::models::email_verification::EmailVerificationClaims;
+use crate::models::user::User;
+use crate::templates::{EmailTemplate, VerificationEmail};
+use crate::utils::{SharedClock, SigningKeys};
+use chrono::Duration;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+/// Signing purpose of email verification tokens.
+pub const EMAIL_VERIFICATION_PURPOSE: &str = "email_verification";
+
+/// Confirms that users own their email address.
+///
+/// New accounts are sent a link carrying a signed token that names the
+/// user and the address; following it sets `is_verified`. Nothing is
+/// stored per link, so any unexpired link works, until the user changes
+/// their address. Verification emails to one account are at least
+/// `resend_interval_secs` apart.
+pub struct EmailVerificationService {
+    db: PgPool,
+    mailer: Arc<dyn Mailer>,
+    signing_keys: SigningKeys,
+    settings: EmailVerificationSettings,
+    brand: EmailSettings,
+    clock: SharedClock,
+}
+
+impl EmailVerificationService {
+    pub fn new(
+        db: PgPool,
+        mailer: Arc<dyn Mailer>,
+        signing_keys: SigningKeys,
+        settings: EmailVerificationSettings,
+        brand: EmailSettings,
+        clock: SharedClock,
+    ) -> Self {
+        Self {
+            db,
+            mailer,
+            signing_keys,
+            settings,
+            brand,
+            clock,
+        }
+    }
+
+    /// Emails `user` a verification link; nothing if they're verified.
+    pub async fn send(&self, user: &User) -> AppResult<()> {
+        if user.is_verified {
+            return Ok(());
+        }
+        let now = self.clock.now();
+        let resend_interval = Duration::seconds(self.settings.resend_interval_secs);
+
+        // Claimed up front, so concurrent resends send one email
+        let claimed = sqlx::query(
+            r#"
+            UPDATE users SET verification_sent_at = $2
+            WHERE id = $1 AND NOT is_verified AND (verification_sent_at IS NULL OR verification_sent_at <= $3)
+            "#,
+        )
+        .bind(user.id)
+        .bind(now)
+        .bind(now - resend_interval)
+        .execute(&self.db)
+        .await
+        .entity_context("claim verification email", user.id)?;
+        if claimed.rows_affected() == 0 {
+            return Err(AppError::Throttled {
+                message: "A verification email was sent recently".to_string(),
+                retry: RetryHint::after(std::time::Duration::from_secs(self.settings.resend_interval_secs as u64)),
+            }
+            .with_code(ErrorCode::EmailVerificationRateLimited));
+        }
+
+        let claims = EmailVerificationClaims {
+            user_id: user.id,
+            email: user.email.clone(),
+        };
+        let token = self.signing_keys.sign(
+            EMAIL_VERIFICATION_PURPOSE,
+            &claims,
+            Duration::seconds(self.settings.token_ttl_secs),
+        )?;
+        let content = VerificationEmail {
+            name: user.full_name.clone().unwrap_or_else(|| user.username.to_string()),
+            verify_url: format!("{}?token={}", self.settings.verify_url, token),
+            expires_in_hours: self.settings.token_ttl_secs / 3600,
+        }
+        .render(&self.brand)?;
+
+        let sent = self
+            .mailer
+            .send(&EmailMessage {
+                to: user.email.clone(),
+                content,
+            })
+            .await;
+        if let Err(e) = sent {
+            // Undelivered, so the user may ask again right away
+            sqlx::query("UPDATE users SET verification_sent_at = NULL WHERE id = $1 AND verification_sent_at = $2")
+                .bind(user.id)
+                .bind(now)
+                .execute(&self.db)
+                .await
+                .entity_context("release verification email", user.id)?;
+            return Err(e);
+        }
+        tracing::info!(user_id = %user.id, "Sent verification email");
+
+        Ok(())
+    }
+
+    /// Marks the user the token was issued to as verified, if the token is
+    /// for their current address, and returns them.
+    pub async fn verify(&self, token: &str) -> AppResult<User> {
+        let claims: EmailVerificationClaims = self
+            .signing_keys
+            .verify(EMAIL_VERIFICATION_PURPOSE, token)
+            .map_err(|_| invalid_token())?;
+
+        // Following a link twice is harmless and leaves updated_at alone
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            UPDATE users SET is_verified = TRUE, updated_at = CASE WHEN is_verified THEN updated_at ELSE $3 END
+            WHERE id = $1 AND email = $2
+            RETURNING *
+            "#,
+        )
+        .bind(claims.user_id)
+        .bind(&claims.email)
+        .bind(self.clock.now())
+        .fetch_optional(&self.db)
+        .await
+        .entity_context("verify email", claims.user_id)?
+        .ok_or_else(invalid_token)?;
+        tracing::info!(user_id = %user.id, "Verified email address");
+
+        Ok(user)
+    }
+}
+
+fn invalid_token() -> AppError {
+    AppError::Unauthorized.with_code(ErrorCode::EmailVerificationInvalid)
+}
@@ -0,0 +1,165 @@
+use crate::config::{EmailSettings, MagicLinkSettings};
+use crate::errors::{AppError, AppResult, ErrorCode, ResultExt, RetryHint};
+use crate::mail::{EmailMessage, Mailer};
+use crate::models::magic_link::{MagicLink, MagicLinkClaims};
+use crate::models::session::ClientContext;
+use crate::models::user::User;
+use crate::templates::{EmailTemplate, MagicLinkEmail};
+use crate::utils::{hash_fingerprint, SharedClock, SigningKeys};
+use chrono::Duration;
+use platform_core::domain::UserId;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Signing purpose of magic link tokens.
+pub const MAGIC_LINK_PURPOSE: &str = "magic_link";
+
+/// Password-less login with single-use links sent by email.
+///
+/// A link carries a signed token naming a row in `magic_links`, which
+/// records who it was sent to and the client that asked for it. Sending a
+/// new link voids the previous ones, a link works once, and each email
+/// address gets at most `send_limit` links per window.
+pub struct MagicLinkService {
+    db: PgPool,
+    mailer: Arc<dyn Mailer>,
+    signing_keys: SigningKeys,
+    settings: MagicLinkSettings,
+    brand: EmailSettings,
+    clock: SharedClock,
+}
+
+impl MagicLinkService {
+    pub fn new(
+        db: PgPool,
+        mailer: Arc<dyn Mailer>,
+        signing_keys: SigningKeys,
+        settings: MagicLinkSettings,
+        brand: EmailSettings,
+        clock: SharedClock,
+    ) -> Self {
+        Self {
+            db,
+            mailer,
+            signing_keys,
+            settings,
+            brand,
+            clock,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    /// Emails `user` a link that signs in the requesting `client`.
+    pub async fn send_link(&self, user: &User, client: &ClientContext) -> AppResult<()> {
+        let now = self.clock.now();
+        let window_start = now - Duration::seconds(self.settings.send_window_secs);
+
+        let sent: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM magic_links WHERE email = $1 AND created_at > $2")
+            .bind(&user.email)
+            .bind(window_start)
+            .fetch_one(&self.db)
+            .await
+            .entity_context("count magic links", user.id)?;
+        if sent >= self.settings.send_limit {
+            return Err(AppError::Throttled {
+                message: "Too many sign-in links sent to this email address".to_string(),
+                retry: RetryHint::after(std::time::Duration::from_secs(self.settings.send_window_secs as u64)),
+            }
+            .with_code(ErrorCode::MagicLinkRateLimited));
+        }
+
+        let id = Uuid::new_v4();
+        let ttl = Duration::seconds(self.settings.link_ttl_secs);
+        let mut tx = self.db.begin().await.context("begin magic link")?;
+        sqlx::query("UPDATE magic_links SET consumed_at = $2 WHERE user_id = $1 AND consumed_at IS NULL")
+            .bind(user.id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .entity_context("void magic links", user.id)?;
+        sqlx::query(
+            r#"
+            INSERT INTO magic_links (id, user_id, email, fingerprint_hash, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(id)
+        .bind(user.id)
+        .bind(&user.email)
+        .bind(hash_fingerprint(&client.user_agent, client.device_id.as_deref()))
+        .bind(now + ttl)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .entity_context("insert magic link", user.id)?;
+        tx.commit().await.context("commit magic link")?;
+
+        let token = self.signing_keys.sign(MAGIC_LINK_PURPOSE, &MagicLinkClaims { id }, ttl)?;
+        let content = MagicLinkEmail {
+            name: user.full_name.clone().unwrap_or_else(|| user.username.to_string()),
+            login_url: format!("{}?token={}", self.settings.verify_url, token),
+            expires_in_minutes: self.settings.link_ttl_secs / 60,
+            requested_from: client.ip.map(|ip| ip.to_string()),
+        }
+        .render(&self.brand)?;
+
+        self.mailer
+            .send(&EmailMessage {
+                to: user.email.clone(),
+                content,
+            })
+            .await?;
+        tracing::info!(user_id = %user.id, "Sent magic link");
+
+        Ok(())
+    }
+
+    /// Consumes the link carrying `token`, returning the user it was sent
+    /// to.
+    pub async fn verify_link(&self, token: &str, client: &ClientContext) -> AppResult<UserId> {
+        let claims: MagicLinkClaims = self
+            .signing_keys
+            .verify(MAGIC_LINK_PURPOSE, token)
+            .map_err(|_| invalid_link())?;
+        let now = self.clock.now();
+
+        let link = sqlx::query_as::<_, MagicLink>(
+            "SELECT * FROM magic_links WHERE id = $1 AND consumed_at IS NULL AND expires_at > $2",
+        )
+        .bind(claims.id)
+        .bind(now)
+        .fetch_optional(&self.db)
+        .await
+        .entity_context("load magic link", claims.id)?
+        .ok_or_else(invalid_link)?;
+
+        // A link opened elsewhere stays usable on the requesting device
+        if self.settings.bind_device
+            && hash_fingerprint(&client.user_agent, client.device_id.as_deref()) != link.fingerprint_hash
+        {
+            tracing::warn!(user_id = %link.user_id, "Magic link opened on another device");
+            return Err(AppError::Forbidden.with_code(ErrorCode::MagicLinkDeviceMismatch));
+        }
+
+        let consumed = sqlx::query("UPDATE magic_links SET consumed_at = $2 WHERE id = $1 AND consumed_at IS NULL")
+            .bind(link.id)
+            .bind(now)
+            .execute(&self.db)
+            .await
+            .entity_context("consume magic link", link.id)?;
+        // Lost a race with another click
+        if consumed.rows_affected() == 0 {
+            return Err(invalid_link());
+        }
+
+        Ok(link.user_id)
+    }
+}
+
+fn invalid_link() -> AppError {
+    AppError::Unauthorized.with_code(ErrorCode::MagicLinkInvalid)
+}
@@ -1,3 +1,31 @@
-pub mod user_service;
+pub mod api_token_service;
+pub mod audit_service;
+pub mod auth_throttle_service;
+pub mod email_verification_service;
+pub mod magic_link_service;
+pub mod operation_service;
+pub mod passkey_service;
+pub mod password_reset_service;
+pub mod phone_otp_service;
+pub mod push_service;
+pub mod role_service;
+pub mod session_service;
+pub mod stats_service;
+pub mod user_commands;
+pub mod user_queries;
 
-pub use user_service::UserService;
\ No newline at end of file
+pub use api_token_service::{ApiTokenService, CreatedApiToken};
+pub use audit_service::AuditService;
+pub use auth_throttle_service::AuthThrottleService;
+pub use email_verification_service::EmailVerificationService;
+pub use magic_link_service::MagicLinkService;
+pub use operation_service::OperationService;
+pub use passkey_service::PasskeyService;
+pub use password_reset_service::PasswordResetService;
+pub use phone_otp_service::PhoneOtpService;
+pub use push_service::PushService;
+pub use role_service::RoleService;
+pub use session_service::SessionService;
+pub use stats_service::StatsService;
+pub use user_commands::{PgUserCommands, UserCommands};
+pub use user_queries::{PgUserQueries, UserQueries};
\ No newline at end of file
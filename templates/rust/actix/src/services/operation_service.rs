@@ -0,0 +1,198 @@
+use crate::config::OperationSettings;
+use crate::errors::{AppError, AppResult, ErrorCode, ResultExt};
+use crate::models::operation::Operation;
+use crate::utils::SharedClock;
+use futures_util::stream::{self, Stream};
+use platform_core::domain::UserId;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+/// Updates buffered per subscriber before it lags and re-reads instead.
+const EVENT_CAPACITY: usize = 256;
+
+/// Long-running operations (imports, exports, deletions) that clients poll
+/// or watch until done.
+///
+/// Whoever does the work, typically a job handler, reports progress and the
+/// outcome here. Updates are broadcast to watchers in this process; watchers
+/// also re-read the operation every `operations.watch_poll_interval_ms`, so
+/// updates made on other replicas reach them too.
+pub struct OperationService {
+    db: PgPool,
+    settings: OperationSettings,
+    updates: broadcast::Sender<Uuid>,
+    clock: SharedClock,
+}
+
+impl OperationService {
+    pub fn new(db: PgPool, settings: OperationSettings, clock: SharedClock) -> Self {
+        let (updates, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            db,
+            settings,
+            updates,
+            clock,
+        }
+    }
+
+    pub fn settings(&self) -> &OperationSettings {
+        &self.settings
+    }
+
+    pub async fn start(&self, user_id: UserId, kind: &str, metadata: serde_json::Value) -> AppResult<Operation> {
+        let now = self.clock.now();
+        sqlx::query_as::<_, Operation>(
+            r#"
+            INSERT INTO operations (user_id, kind, metadata, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $4)
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(kind)
+        .bind(metadata)
+        .bind(now)
+        .fetch_one(&self.db)
+        .await
+        .entity_context("start operation", user_id)
+    }
+
+    /// Looks up an operation of `user_id`; other users' operations are
+    /// reported as missing.
+    pub async fn get_operation(&self, user_id: UserId, operation_id: Uuid) -> AppResult<Operation> {
+        sqlx::query_as::<_, Operation>("SELECT * FROM operations WHERE id = $1 AND user_id = $2")
+            .bind(operation_id)
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await
+            .entity_context("load operation", operation_id)?
+            .ok_or_else(|| {
+                AppError::NotFound("Operation not found".to_string()).with_code(ErrorCode::OperationNotFound)
+            })
+    }
+
+    /// Records progress; `percent` is clamped to 0-100. Ignored once the
+    /// operation is done.
+    pub async fn report_progress(&self, operation_id: Uuid, percent: i32, message: Option<&str>) -> AppResult<()> {
+        self.update(
+            operation_id,
+            sqlx::query(
+                r#"
+                UPDATE operations SET progress_percent = $2, progress_message = $3, updated_at = $4
+                WHERE id = $1 AND NOT done
+                "#,
+            )
+            .bind(operation_id)
+            .bind(percent.clamp(0, 100))
+            .bind(message)
+            .bind(self.clock.now()),
+        )
+        .await
+    }
+
+    pub async fn succeed(&self, operation_id: Uuid, response: serde_json::Value) -> AppResult<()> {
+        self.update(
+            operation_id,
+            sqlx::query(
+                r#"
+                UPDATE operations SET done = true, progress_percent = 100, response = $2, updated_at = $3
+                WHERE id = $1 AND NOT done
+                "#,
+            )
+            .bind(operation_id)
+            .bind(response)
+            .bind(self.clock.now()),
+        )
+        .await
+    }
+
+    /// Finishes the operation with `error`. Server errors are recorded with
+    /// their catalog description only, so internals don't reach clients.
+    pub async fn fail(&self, operation_id: Uuid, error: &AppError) -> AppResult<()> {
+        let code = error.code();
+        let message = if error.is_server_error() {
+            code.description().to_string()
+        } else {
+            error.to_string()
+        };
+
+        self.update(
+            operation_id,
+            sqlx::query(
+                r#"
+                UPDATE operations SET done = true, error_code = $2, error_message = $3, updated_at = $4
+                WHERE id = $1 AND NOT done
+                "#,
+            )
+            .bind(operation_id)
+            .bind(code.as_str())
+            .bind(message)
+            .bind(self.clock.now()),
+        )
+        .await
+    }
+
+    async fn update(
+        &self,
+        operation_id: Uuid,
+        query: sqlx::query::Query<'_, sqlx::Postgres, sqlx::postgres::PgArguments>,
+    ) -> AppResult<()> {
+        query
+            .execute(&self.db)
+            .await
+            .entity_context("update operation", operation_id)?;
+
+        // Nobody watching is fine
+        let _ = self.updates.send(operation_id);
+        Ok(())
+    }
+
+    /// The operation now and after every change, ending once it is done.
+    pub fn watch(self: Arc<Self>, user_id: UserId, operation_id: Uuid) -> impl Stream<Item = AppResult<Operation>> {
+        let updates = self.updates.subscribe();
+        let poll_interval = Duration::from_millis(self.settings.watch_poll_interval_ms);
+
+        stream::unfold(Some((self, updates, None)), move |state| async move {
+            let (service, mut updates, mut last) = state?;
+            loop {
+                if last.is_some() {
+                    tokio::select! {
+                        update = updates.recv() => match update {
+                            Ok(id) if id != operation_id => continue,
+                            Err(RecvError::Closed) => return None,
+                            // Ours, or missed while lagging
+                            _ => {}
+                        },
+                        _ = tokio::time::sleep(poll_interval) => {}
+                    }
+                }
+
+                let operation = match service.get_operation(user_id, operation_id).await {
+                    Ok(operation) => operation,
+                    Err(e) => return Some((Err(e), None)),
+                };
+                let seen = Some(revision(&operation));
+                if seen == last {
+                    continue;
+                }
+                last = seen;
+
+                let next = (!operation.done).then_some((service, updates, last));
+                return Some((Ok(operation), next));
+            }
+        })
+    }
+}
+
+/// What watchers care about changing.
+fn revision(operation: &Operation) -> (chrono::DateTime<chrono::Utc>, i32, Option<String>, bool) {
+    (
+        operation.updated_at,
+        operation.progress_percent,
+        operation.progress_message.clone(),
+        operation.done,
+    )
+}
@@ -0,0 +1,256 @@
+use crate::config::WebauthnSettings;
+use crate::dry_run::DryRun;
+use crate::errors::{AppError, AppResult, ErrorCode, ResultExt};
+use crate::models::passkey::{CeremonyChallenge, FinishPasskeyLogin, FinishPasskeyRegistration, StoredPasskey};
+use crate::models::user::User;
+use crate::utils::SharedClock;
+use crate::webauthn::{encode_credential_id, verification_failed, Ceremony};
+use chrono::Duration;
+use platform_core::domain::UserId;
+use sqlx::types::Json;
+use sqlx::PgPool;
+use uuid::Uuid;
+use webauthn_rs::prelude::{CreationChallengeResponse, RequestChallengeResponse};
+use webauthn_rs::Webauthn;
+
+/// Passkey registration, login and management.
+///
+/// Users register any number of passkeys and log in with whichever the
+/// browser offers; see [`webauthn`](crate::webauthn) for how ceremonies are
+/// kept between their two steps.
+pub struct PasskeyService {
+    db: PgPool,
+    webauthn: Webauthn,
+    settings: WebauthnSettings,
+    clock: SharedClock,
+}
+
+impl PasskeyService {
+    pub fn new(db: PgPool, webauthn: Webauthn, settings: WebauthnSettings, clock: SharedClock) -> Self {
+        Self {
+            db,
+            webauthn,
+            settings,
+            clock,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    /// Challenges an authenticator to create a passkey for `user`.
+    pub async fn start_registration(&self, user: &User) -> AppResult<CeremonyChallenge<CreationChallengeResponse>> {
+        // Authenticators refuse to register a second passkey for the account
+        let existing = self
+            .list_passkeys(user.id)
+            .await?
+            .into_iter()
+            .map(|passkey| passkey.credential.cred_id().clone())
+            .collect();
+        let display_name = user.full_name.as_deref().unwrap_or(user.username.as_str());
+
+        let (options, state) = self
+            .webauthn
+            .start_passkey_registration(user.id.into(), user.email.as_str(), display_name, Some(existing))
+            .map_err(|e| {
+                tracing::error!("Failed to start passkey registration: {}", e);
+                AppError::InternalServerError
+            })?;
+        let ceremony_id = self.save_ceremony(Some(user.id), &Ceremony::Registration(state)).await?;
+
+        Ok(CeremonyChallenge { ceremony_id, options })
+    }
+
+    /// Checks the authenticator's answer and stores the new passkey.
+    pub async fn finish_registration(
+        &self,
+        user_id: UserId,
+        registration: FinishPasskeyRegistration,
+    ) -> AppResult<StoredPasskey> {
+        let Ceremony::Registration(state) = self.take_ceremony(registration.ceremony_id, Some(user_id)).await? else {
+            return Err(ceremony_expired());
+        };
+        let passkey = self
+            .webauthn
+            .finish_passkey_registration(&registration.credential, &state)
+            .map_err(verification_failed)?;
+        let credential_id = encode_credential_id(passkey.cred_id());
+
+        // Credential IDs are unique across accounts
+        let taken: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM passkeys WHERE credential_id = $1)")
+            .bind(&credential_id)
+            .fetch_one(&self.db)
+            .await
+            .entity_context("check passkey", user_id)?;
+        if taken {
+            return Err(AppError::Conflict("Passkey is already registered".to_string())
+                .with_code(ErrorCode::PasskeyAlreadyRegistered));
+        }
+
+        sqlx::query_as::<_, StoredPasskey>(
+            r#"
+            INSERT INTO passkeys (user_id, credential_id, name, credential, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(&credential_id)
+        .bind(&registration.name)
+        .bind(Json(&passkey))
+        .bind(self.clock.now())
+        .fetch_one(&self.db)
+        .await
+        .entity_context("insert passkey", user_id)
+    }
+
+    /// Challenges the browser to sign in with any passkey it holds.
+    pub async fn start_login(&self) -> AppResult<CeremonyChallenge<RequestChallengeResponse>> {
+        let (options, state) = self.webauthn.start_discoverable_authentication().map_err(|e| {
+            tracing::error!("Failed to start passkey login: {}", e);
+            AppError::InternalServerError
+        })?;
+        let ceremony_id = self.save_ceremony(None, &Ceremony::Authentication(state)).await?;
+
+        Ok(CeremonyChallenge { ceremony_id, options })
+    }
+
+    /// Checks the authenticator's answer, returning the user whose passkey
+    /// signed it.
+    pub async fn finish_login(&self, login: FinishPasskeyLogin) -> AppResult<UserId> {
+        let Ceremony::Authentication(state) = self.take_ceremony(login.ceremony_id, None).await? else {
+            return Err(ceremony_expired());
+        };
+        let (user_id, credential_id) = self
+            .webauthn
+            .identify_discoverable_authentication(&login.credential)
+            .map_err(verification_failed)?;
+
+        let stored = sqlx::query_as::<_, StoredPasskey>("SELECT * FROM passkeys WHERE credential_id = $1 AND user_id = $2")
+            .bind(encode_credential_id(credential_id))
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await
+            .entity_context("load passkey", user_id)?
+            .ok_or_else(|| AppError::Unauthorized.with_code(ErrorCode::PasskeyVerificationFailed))?;
+
+        let Json(mut passkey) = stored.credential;
+        let result = self
+            .webauthn
+            .finish_discoverable_authentication(&login.credential, state, &[(&passkey).into()])
+            .map_err(verification_failed)?;
+
+        // Keep the signature counter current so cloned authenticators show up
+        passkey.update_credential(&result);
+        sqlx::query("UPDATE passkeys SET credential = $2, last_used_at = $3 WHERE id = $1")
+            .bind(stored.id)
+            .bind(Json(&passkey))
+            .bind(self.clock.now())
+            .execute(&self.db)
+            .await
+            .entity_context("update passkey", stored.id)?;
+
+        Ok(stored.user_id)
+    }
+
+    pub async fn list_passkeys(&self, user_id: UserId) -> AppResult<Vec<StoredPasskey>> {
+        sqlx::query_as::<_, StoredPasskey>("SELECT * FROM passkeys WHERE user_id = $1 ORDER BY created_at")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .entity_context("list passkeys", user_id)
+    }
+
+    pub async fn rename_passkey(
+        &self,
+        user_id: UserId,
+        passkey_id: Uuid,
+        name: &str,
+        dry_run: DryRun,
+    ) -> AppResult<StoredPasskey> {
+        let mut tx = self.db.begin().await.context("begin passkey rename")?;
+        let passkey = sqlx::query_as::<_, StoredPasskey>("UPDATE passkeys SET name = $3 WHERE id = $1 AND user_id = $2 RETURNING *")
+            .bind(passkey_id)
+            .bind(user_id)
+            .bind(name)
+            .fetch_optional(&mut *tx)
+            .await
+            .entity_context("rename passkey", passkey_id)?
+            .ok_or_else(passkey_not_found)?;
+        dry_run.finish(tx).await?;
+
+        Ok(passkey)
+    }
+
+    pub async fn delete_passkey(&self, user_id: UserId, passkey_id: Uuid, dry_run: DryRun) -> AppResult<()> {
+        let mut tx = self.db.begin().await.context("begin passkey deletion")?;
+        let result = sqlx::query("DELETE FROM passkeys WHERE id = $1 AND user_id = $2")
+            .bind(passkey_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .entity_context("delete passkey", passkey_id)?;
+
+        if result.rows_affected() == 0 {
+            return Err(passkey_not_found());
+        }
+        dry_run.finish(tx).await
+    }
+
+    async fn save_ceremony(&self, user_id: Option<UserId>, ceremony: &Ceremony) -> AppResult<Uuid> {
+        let now = self.clock.now();
+
+        // Abandoned ceremonies are cleared as new ones start
+        sqlx::query("DELETE FROM webauthn_ceremonies WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&self.db)
+            .await
+            .context("delete expired webauthn ceremonies")?;
+
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO webauthn_ceremonies (user_id, state, expires_at, created_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#,
+        )
+        .bind(user_id)
+        .bind(Json(ceremony))
+        .bind(now + Duration::seconds(self.settings.ceremony_ttl_secs))
+        .bind(now)
+        .fetch_one(&self.db)
+        .await
+        .context("insert webauthn ceremony")
+    }
+
+    /// Removes and returns a live ceremony started by `user_id`, so each
+    /// challenge is answered at most once.
+    async fn take_ceremony(&self, ceremony_id: Uuid, user_id: Option<UserId>) -> AppResult<Ceremony> {
+        let Json(ceremony) = sqlx::query_scalar::<_, Json<Ceremony>>(
+            r#"
+            DELETE FROM webauthn_ceremonies
+            WHERE id = $1 AND user_id IS NOT DISTINCT FROM $2 AND expires_at > $3
+            RETURNING state
+            "#,
+        )
+        .bind(ceremony_id)
+        .bind(user_id)
+        .bind(self.clock.now())
+        .fetch_optional(&self.db)
+        .await
+        .entity_context("take webauthn ceremony", ceremony_id)?
+        .ok_or_else(ceremony_expired)?;
+
+        Ok(ceremony)
+    }
+}
+
+fn ceremony_expired() -> AppError {
+    AppError::BadRequest("Passkey challenge is unknown or expired".to_string())
+        .with_code(ErrorCode::PasskeyCeremonyExpired)
+}
+
+fn passkey_not_found() -> AppError {
+    AppError::NotFound("Passkey not found".to_string()).with_code(ErrorCode::PasskeyNotFound)
+}
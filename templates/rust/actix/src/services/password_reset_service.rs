@@ -0,0 +1,175 @@
+use crate::config::{EmailSettings, PasswordResetSettings};
+use crate::errors::{AppError, AppResult, ErrorCode, ResultExt, RetryHint};
+use crate::mail::{EmailMessage, Mailer};
+use crate::models::password_reset::{PasswordReset, PasswordResetClaims};
+use crate::models::session::ClientContext;
+use crate::models::user::User;
+use crate::services::SessionService;
+use crate::templates::{EmailTemplate, PasswordResetEmail};
+use crate::utils::{HashedPassword, SharedClock, SigningKeys};
+use chrono::Duration;
+use platform_core::domain::UserId;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Signing purpose of password reset tokens.
+pub const PASSWORD_RESET_PURPOSE: &str = "password_reset";
+
+/// Resets forgotten passwords with single-use links sent by email.
+///
+/// A link carries a signed token naming a row in `password_resets`, which
+/// records who it was sent to. Sending a new link voids the previous ones,
+/// a link works once and only while the account still has the address it
+/// was sent to, and each email address gets at most `send_limit` links per
+/// window. Resetting signs the user out of every session.
+pub struct PasswordResetService {
+    db: PgPool,
+    mailer: Arc<dyn Mailer>,
+    signing_keys: SigningKeys,
+    session_service: Arc<SessionService>,
+    settings: PasswordResetSettings,
+    brand: EmailSettings,
+    clock: SharedClock,
+}
+
+impl PasswordResetService {
+    pub fn new(
+        db: PgPool,
+        mailer: Arc<dyn Mailer>,
+        signing_keys: SigningKeys,
+        session_service: Arc<SessionService>,
+        settings: PasswordResetSettings,
+        brand: EmailSettings,
+        clock: SharedClock,
+    ) -> Self {
+        Self {
+            db,
+            mailer,
+            signing_keys,
+            session_service,
+            settings,
+            brand,
+            clock,
+        }
+    }
+
+    /// Emails `user` a reset link, naming the `client` that asked for it.
+    pub async fn send_link(&self, user: &User, client: &ClientContext) -> AppResult<()> {
+        let now = self.clock.now();
+        let window_start = now - Duration::seconds(self.settings.send_window_secs);
+
+        let sent: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM password_resets WHERE email = $1 AND created_at > $2")
+            .bind(&user.email)
+            .bind(window_start)
+            .fetch_one(&self.db)
+            .await
+            .entity_context("count password resets", user.id)?;
+        if sent >= self.settings.send_limit {
+            return Err(AppError::Throttled {
+                message: "Too many password reset emails sent to this email address".to_string(),
+                retry: RetryHint::after(std::time::Duration::from_secs(self.settings.send_window_secs as u64)),
+            }
+            .with_code(ErrorCode::PasswordResetRateLimited));
+        }
+
+        let id = Uuid::new_v4();
+        let ttl = Duration::seconds(self.settings.link_ttl_secs);
+        let mut tx = self.db.begin().await.context("begin password reset")?;
+        sqlx::query("UPDATE password_resets SET consumed_at = $2 WHERE user_id = $1 AND consumed_at IS NULL")
+            .bind(user.id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .entity_context("void password resets", user.id)?;
+        sqlx::query(
+            r#"
+            INSERT INTO password_resets (id, user_id, email, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(id)
+        .bind(user.id)
+        .bind(&user.email)
+        .bind(now + ttl)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .entity_context("insert password reset", user.id)?;
+        tx.commit().await.context("commit password reset")?;
+
+        let token = self.signing_keys.sign(PASSWORD_RESET_PURPOSE, &PasswordResetClaims { id }, ttl)?;
+        let content = PasswordResetEmail {
+            name: user.full_name.clone().unwrap_or_else(|| user.username.to_string()),
+            reset_url: format!("{}?token={}", self.settings.reset_url, token),
+            expires_in_minutes: self.settings.link_ttl_secs / 60,
+            requested_from: client.ip.map(|ip| ip.to_string()),
+        }
+        .render(&self.brand)?;
+
+        self.mailer
+            .send(&EmailMessage {
+                to: user.email.clone(),
+                content,
+            })
+            .await?;
+        tracing::info!(user_id = %user.id, "Sent password reset link");
+
+        Ok(())
+    }
+
+    /// Consumes the link carrying `token`, sets `new_password` on the user
+    /// it was sent to and revokes their refresh tokens, returning the user.
+    pub async fn reset(&self, token: &str, new_password: &str) -> AppResult<UserId> {
+        let claims: PasswordResetClaims = self
+            .signing_keys
+            .verify(PASSWORD_RESET_PURPOSE, token)
+            .map_err(|_| invalid_link())?;
+        // Hashed before the transaction, which would otherwise hold the
+        // link's row locked for the whole bcrypt run
+        let password_hash = HashedPassword::new(new_password)?;
+        let now = self.clock.now();
+
+        let mut tx = self.db.begin().await.context("begin password reset")?;
+        // Consuming the row first makes concurrent uses of one link wait
+        // here, and all but the first find it consumed
+        let reset = sqlx::query_as::<_, PasswordReset>(
+            r#"
+            UPDATE password_resets SET consumed_at = $2
+            WHERE id = $1 AND consumed_at IS NULL AND expires_at > $2
+            RETURNING *
+            "#,
+        )
+        .bind(claims.id)
+        .bind(now)
+        .fetch_optional(&mut *tx)
+        .await
+        .entity_context("consume password reset", claims.id)?
+        .ok_or_else(invalid_link)?;
+
+        let updated = sqlx::query(
+            "UPDATE users SET password_hash = $3, updated_at = $4 WHERE id = $1 AND email = $2 AND is_active",
+        )
+        .bind(reset.user_id)
+        .bind(&reset.email)
+        .bind(&password_hash)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .entity_context("reset password", reset.user_id)?;
+        // The address changed or the account was disabled since
+        if updated.rows_affected() == 0 {
+            return Err(invalid_link());
+        }
+
+        self.session_service.revoke_all_in(&mut *tx, reset.user_id).await?;
+        tx.commit().await.context("commit password reset")?;
+        tracing::info!(user_id = %reset.user_id, "Reset password");
+
+        Ok(reset.user_id)
+    }
+}
+
+fn invalid_link() -> AppError {
+    AppError::Unauthorized.with_code(ErrorCode::PasswordResetInvalid)
+}
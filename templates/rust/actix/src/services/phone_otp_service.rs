@@ -0,0 +1,157 @@
+use crate::config::{EmailSettings, PhoneOtpSettings};
+use crate::errors::{AppError, AppResult, ErrorCode, ResultExt, RetryHint};
+use crate::models::phone_otp::{OtpPurpose, PhoneOtp};
+use crate::sms::{SmsMessage, SmsSender};
+use crate::utils::{hash_token, mask_phone_number, SharedClock};
+use chrono::Duration;
+use platform_core::domain::UserId;
+use rand::Rng;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+/// One-time codes sent by SMS.
+///
+/// Only a hash of each code is stored. Sending a new code voids the
+/// previous ones for the same number and purpose, a code is burned after
+/// `max_attempts` wrong guesses, and each number gets at most `send_limit`
+/// codes per window.
+pub struct PhoneOtpService {
+    db: PgPool,
+    sms_sender: Arc<dyn SmsSender>,
+    settings: PhoneOtpSettings,
+    product_name: String,
+    clock: SharedClock,
+}
+
+impl PhoneOtpService {
+    pub fn new(
+        db: PgPool,
+        sms_sender: Arc<dyn SmsSender>,
+        settings: PhoneOtpSettings,
+        brand: &EmailSettings,
+        clock: SharedClock,
+    ) -> Self {
+        Self {
+            db,
+            sms_sender,
+            settings,
+            product_name: brand.product_name.clone(),
+            clock,
+        }
+    }
+
+    pub fn login_enabled(&self) -> bool {
+        self.settings.login_enabled
+    }
+
+    /// Generates a code for `user_id` and texts it to `phone_number`.
+    pub async fn send_code(&self, user_id: UserId, phone_number: &str, purpose: OtpPurpose) -> AppResult<()> {
+        let now = self.clock.now();
+        let window_start = now - Duration::seconds(self.settings.send_window_secs);
+
+        let sent: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM phone_otps WHERE phone_number = $1 AND created_at > $2")
+            .bind(phone_number)
+            .bind(window_start)
+            .fetch_one(&self.db)
+            .await
+            .entity_context("count phone otps", user_id)?;
+        if sent >= self.settings.send_limit {
+            return Err(AppError::Throttled {
+                message: "Too many codes sent to this phone number".to_string(),
+                retry: RetryHint::after(std::time::Duration::from_secs(self.settings.send_window_secs as u64)),
+            }
+            .with_code(ErrorCode::PhoneOtpRateLimited));
+        }
+
+        let code = self.generate_code();
+        let mut tx = self.db.begin().await.context("begin phone otp")?;
+        sqlx::query(
+            "UPDATE phone_otps SET consumed_at = $3 WHERE phone_number = $1 AND purpose = $2 AND consumed_at IS NULL",
+        )
+        .bind(phone_number)
+        .bind(purpose.as_str())
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .entity_context("void phone otps", user_id)?;
+        sqlx::query(
+            r#"
+            INSERT INTO phone_otps (user_id, phone_number, purpose, code_hash, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(user_id)
+        .bind(phone_number)
+        .bind(purpose.as_str())
+        .bind(hash_token(&code))
+        .bind(now + Duration::seconds(self.settings.code_ttl_secs))
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .entity_context("insert phone otp", user_id)?;
+        tx.commit().await.context("commit phone otp")?;
+
+        let message = SmsMessage {
+            to: phone_number.to_string(),
+            body: format!(
+                "{} is your {} code. It expires in {} minutes.",
+                code,
+                self.product_name,
+                self.settings.code_ttl_secs / 60
+            ),
+        };
+        self.sms_sender.send(&message).await?;
+        tracing::info!(phone_number = %mask_phone_number(phone_number), purpose = purpose.as_str(), "Sent phone OTP");
+
+        Ok(())
+    }
+
+    /// Consumes the code sent to `phone_number` for `purpose`, returning the
+    /// user it was sent for.
+    pub async fn verify_code(&self, phone_number: &str, purpose: OtpPurpose, code: &str) -> AppResult<UserId> {
+        let invalid = || AppError::BadRequest("Invalid or expired code".to_string()).with_code(ErrorCode::PhoneOtpInvalid);
+        let now = self.clock.now();
+
+        // Counting the attempt up front keeps concurrent guesses within the limit
+        let otp = sqlx::query_as::<_, PhoneOtp>(
+            r#"
+            UPDATE phone_otps SET attempts = attempts + 1
+            WHERE phone_number = $1 AND purpose = $2 AND consumed_at IS NULL AND expires_at > $3 AND attempts < $4
+            RETURNING *
+            "#,
+        )
+        .bind(phone_number)
+        .bind(purpose.as_str())
+        .bind(now)
+        .bind(self.settings.max_attempts)
+        .fetch_optional(&self.db)
+        .await
+        .context("attempt phone otp")?
+        .ok_or_else(invalid)?;
+
+        // Comparing hashes leaks nothing usable about the code
+        if hash_token(code) != otp.code_hash {
+            return Err(invalid());
+        }
+
+        let consumed = sqlx::query("UPDATE phone_otps SET consumed_at = $2 WHERE id = $1 AND consumed_at IS NULL")
+            .bind(otp.id)
+            .bind(now)
+            .execute(&self.db)
+            .await
+            .entity_context("consume phone otp", otp.id)?;
+        // Lost a race with another correct guess
+        if consumed.rows_affected() == 0 {
+            return Err(invalid());
+        }
+
+        Ok(otp.user_id)
+    }
+
+    fn generate_code(&self) -> String {
+        let mut rng = rand::thread_rng();
+        (0..self.settings.code_length)
+            .map(|_| char::from(b'0' + rng.gen_range(0..10)))
+            .collect()
+    }
+}
@@ -0,0 +1,160 @@
+use crate::consumer::IdempotentConsumer;
+use crate::dry_run::DryRun;
+use crate::errors::{AppError, AppResult, ErrorCode, ResultExt};
+use crate::jobs::JobQueue;
+use crate::models::push_device::{PushDevice, PushPlatform, RegisterPushDevice};
+use crate::push::{
+    unsupported_platform, DeliverPayload, FanOutPayload, PushNotification, PushOutcome, PushSender, DELIVER_JOB,
+    FAN_OUT_JOB,
+};
+use crate::utils::SharedClock;
+use platform_core::domain::UserId;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Device registration and push delivery.
+///
+/// A token belongs to the user who registered it last, so a phone that
+/// changes hands stops getting the previous owner's notifications. Tokens
+/// the provider rejects are deleted without surfacing an error.
+pub struct PushService {
+    db: PgPool,
+    job_queue: Arc<JobQueue>,
+    sender: Arc<dyn PushSender>,
+    clock: SharedClock,
+    /// Skips fan-outs whose delivery jobs were already queued, e.g. when a
+    /// fan-out job lost with its worker is run again.
+    fan_outs: IdempotentConsumer,
+}
+
+impl PushService {
+    pub fn new(db: PgPool, job_queue: Arc<JobQueue>, sender: Arc<dyn PushSender>, clock: SharedClock) -> Self {
+        Self {
+            fan_outs: IdempotentConsumer::new(db.clone(), FAN_OUT_JOB, clock.clone()),
+            db,
+            job_queue,
+            sender,
+            clock,
+        }
+    }
+
+    pub async fn register_device(
+        &self,
+        user_id: UserId,
+        device: RegisterPushDevice,
+        dry_run: DryRun,
+    ) -> AppResult<PushDevice> {
+        if !self.sender.supports(device.platform) {
+            return Err(unsupported_platform(device.platform));
+        }
+
+        let mut tx = self.db.begin().await.context("begin push device registration")?;
+        let device = sqlx::query_as::<_, PushDevice>(
+            r#"
+            INSERT INTO push_devices (user_id, platform, token, created_at, last_seen_at)
+            VALUES ($1, $2, $3, $4, $4)
+            ON CONFLICT (token) DO UPDATE
+            SET user_id = EXCLUDED.user_id, platform = EXCLUDED.platform, last_seen_at = EXCLUDED.last_seen_at
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(device.platform.as_str())
+        .bind(&device.token)
+        .bind(self.clock.now())
+        .fetch_one(&mut *tx)
+        .await
+        .entity_context("register push device", user_id)?;
+        dry_run.finish(tx).await?;
+
+        Ok(device)
+    }
+
+    pub async fn list_devices(&self, user_id: UserId) -> AppResult<Vec<PushDevice>> {
+        sqlx::query_as::<_, PushDevice>("SELECT * FROM push_devices WHERE user_id = $1 ORDER BY created_at")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .entity_context("list push devices", user_id)
+    }
+
+    pub async fn unregister_device(&self, user_id: UserId, device_id: Uuid, dry_run: DryRun) -> AppResult<()> {
+        let mut tx = self.db.begin().await.context("begin push device unregistration")?;
+        let result = sqlx::query("DELETE FROM push_devices WHERE id = $1 AND user_id = $2")
+            .bind(device_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .entity_context("unregister push device", device_id)?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound("Device not found".to_string()).with_code(ErrorCode::PushDeviceNotFound));
+        }
+        dry_run.finish(tx).await
+    }
+
+    pub async fn unregister_all(&self, user_id: UserId) -> AppResult<()> {
+        sqlx::query("DELETE FROM push_devices WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&self.db)
+            .await
+            .entity_context("unregister push devices", user_id)?;
+
+        Ok(())
+    }
+
+    /// Queues `notification` for every device of `user_id`.
+    pub async fn notify_user(&self, user_id: UserId, notification: PushNotification) -> AppResult<Uuid> {
+        self.job_queue
+            .enqueue(FAN_OUT_JOB, &FanOutPayload { user_id, notification })
+            .await
+    }
+
+    /// Queues one delivery job per device, so a failing device doesn't hold
+    /// up or repeat the others. `message_id` (the fan-out job's id) makes
+    /// it idempotent: a second run with it queues nothing.
+    pub async fn fan_out(&self, message_id: &str, user_id: UserId, notification: &PushNotification) -> AppResult<()> {
+        let devices = self.list_devices(user_id).await?;
+
+        let Some(mut tx) = self.fan_outs.claim(message_id).await? else {
+            return Ok(());
+        };
+        for device in devices {
+            let payload = DeliverPayload {
+                device_id: device.id,
+                notification: notification.clone(),
+            };
+            self.job_queue.enqueue_with(&mut *tx, DELIVER_JOB, &payload).await?;
+        }
+        tx.commit().await.context("commit push fan-out")
+    }
+
+    /// Sends `notification` to one device, forgetting the device if the
+    /// provider rejects its token.
+    pub async fn deliver(&self, device_id: Uuid, notification: &PushNotification) -> AppResult<()> {
+        let device = sqlx::query_as::<_, PushDevice>("SELECT * FROM push_devices WHERE id = $1")
+            .bind(device_id)
+            .fetch_optional(&self.db)
+            .await
+            .entity_context("load push device", device_id)?;
+        // Unregistered since the fan-out
+        let Some(device) = device else {
+            return Ok(());
+        };
+        let platform: PushPlatform = device.platform.parse().map_err(AppError::BadRequest)?;
+
+        match self.sender.send(platform, &device.token, notification).await? {
+            PushOutcome::Delivered => Ok(()),
+            PushOutcome::TokenRejected => {
+                tracing::info!(device_id = %device.id, %platform, "Removing push device rejected by the provider");
+                sqlx::query("DELETE FROM push_devices WHERE id = $1")
+                    .bind(device.id)
+                    .execute(&self.db)
+                    .await
+                    .entity_context("remove rejected push device", device.id)?;
+                Ok(())
+            }
+        }
+    }
+}
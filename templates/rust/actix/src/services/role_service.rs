@@ -0,0 +1,121 @@
+use platform_core::domain::UserId;
+use sqlx::PgPool;
+
+use crate::errors::{AppError, AppResult, ResultExt};
+use crate::models::role::{Grants, Role, UserRole};
+use crate::utils::SharedClock;
+
+/// Roles, what they permit and who has them.
+///
+/// Roles and permissions are rows of `roles`, `permissions` and
+/// `role_permissions`, added by migrations; users are granted roles at
+/// runtime. Access tokens carry the holder's [`Grants`] as of when they
+/// were issued, so a change reaches a user's requests once their access
+/// token is refreshed.
+pub struct RoleService {
+    db: PgPool,
+    clock: SharedClock,
+}
+
+impl RoleService {
+    pub fn new(db: PgPool, clock: SharedClock) -> Self {
+        Self { db, clock }
+    }
+
+    /// What `user_id`'s access tokens should carry.
+    pub async fn grants_of(&self, user_id: UserId) -> AppResult<Grants> {
+        let roles = sqlx::query_scalar("SELECT role FROM user_roles WHERE user_id = $1 ORDER BY role")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .entity_context("load user roles", user_id)?;
+        let permissions = sqlx::query_scalar(
+            r#"
+            SELECT DISTINCT rp.permission FROM role_permissions rp
+            JOIN user_roles ur ON ur.role = rp.role
+            WHERE ur.user_id = $1
+            ORDER BY rp.permission
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await
+        .entity_context("load user permissions", user_id)?;
+        Ok(Grants { roles, permissions })
+    }
+
+    /// Every role with its permissions, by name.
+    pub async fn list_roles(&self) -> AppResult<Vec<Role>> {
+        let mut roles: Vec<Role> = sqlx::query_as("SELECT * FROM roles ORDER BY name")
+            .fetch_all(&self.db)
+            .await
+            .context("list roles")?;
+        let permissions: Vec<(String, String)> =
+            sqlx::query_as("SELECT role, permission FROM role_permissions ORDER BY permission")
+                .fetch_all(&self.db)
+                .await
+                .context("list role permissions")?;
+        for role in &mut roles {
+            role.permissions = permissions
+                .iter()
+                .filter(|(name, _)| *name == role.name)
+                .map(|(_, permission)| permission.clone())
+                .collect();
+        }
+        Ok(roles)
+    }
+
+    pub async fn roles_of(&self, user_id: UserId) -> AppResult<Vec<UserRole>> {
+        sqlx::query_as("SELECT * FROM user_roles WHERE user_id = $1 ORDER BY role")
+            .bind(user_id)
+            .fetch_all(&self.db)
+            .await
+            .entity_context("load user roles", user_id)
+    }
+
+    /// Grants `role` to `user_id`; granting it again changes nothing.
+    pub async fn grant(&self, user_id: UserId, role: &str, granted_by: Option<UserId>) -> AppResult<()> {
+        self.ensure_exists(role).await?;
+        sqlx::query(
+            r#"
+            INSERT INTO user_roles (user_id, role, granted_by, granted_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id, role) DO NOTHING
+            "#,
+        )
+        .bind(user_id)
+        .bind(role)
+        .bind(granted_by)
+        .bind(self.clock.now())
+        .execute(&self.db)
+        .await
+        .entity_context("grant role", user_id)?;
+        Ok(())
+    }
+
+    /// Takes `role` away from `user_id`; fails if they don't have it.
+    pub async fn revoke(&self, user_id: UserId, role: &str) -> AppResult<()> {
+        let result = sqlx::query("DELETE FROM user_roles WHERE user_id = $1 AND role = $2")
+            .bind(user_id)
+            .bind(role)
+            .execute(&self.db)
+            .await
+            .entity_context("revoke role", user_id)?;
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("User {} doesn't have role {}", user_id, role)));
+        }
+        Ok(())
+    }
+
+    async fn ensure_exists(&self, role: &str) -> AppResult<()> {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM roles WHERE name = $1)")
+            .bind(role)
+            .fetch_one(&self.db)
+            .await
+            .context("check role")?;
+        if !exists {
+            return Err(AppError::NotFound(format!("Role {} not found", role)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use crate::config::{BindingMode, SessionSettings};
+use crate::db;
+use crate::errors::{AppError, AppResult, ErrorCode, ResultExt};
+use crate::models::audit_event::{AuditEvent, REFRESH_TOKEN_BINDING_MISMATCH, REFRESH_TOKEN_REUSED};
+use crate::models::session::{ClientContext, RefreshToken, Session};
+use crate::services::AuditService;
+use crate::utils::{generate_secret, hash_fingerprint, hash_token, ip_subnet, SharedClock};
+use chrono::Duration;
+use platform_core::domain::UserId;
+use serde_json::json;
+use sqlx::types::Json;
+use sqlx::{PgExecutor, PgPool};
+use std::sync::Arc;
+use uuid::Uuid;
+
+const REFRESH_TOKEN_LENGTH: usize = 48;
+
+/// Issues and rotates opaque refresh tokens.
+///
+/// Every login starts a token family; each refresh consumes the presented
+/// token and issues its successor in the same family. Presenting an already
+/// consumed token, or one from a client that doesn't match its binding,
+/// revokes the whole family.
+pub struct SessionService {
+    db: PgPool,
+    settings: SessionSettings,
+    refresh_token_expiry: i64,
+    audit_service: Arc<AuditService>,
+    clock: SharedClock,
+}
+
+impl SessionService {
+    pub fn new(
+        db: PgPool,
+        settings: SessionSettings,
+        refresh_token_expiry: i64,
+        audit_service: Arc<AuditService>,
+        clock: SharedClock,
+    ) -> Self {
+        Self {
+            db,
+            settings,
+            refresh_token_expiry,
+            audit_service,
+            clock,
+        }
+    }
+
+    /// Starts a new token family for `user_id` and returns its first token.
+    pub async fn issue_refresh_token(&self, user_id: UserId, client: &ClientContext) -> AppResult<String> {
+        self.insert_refresh_token(&self.db, Uuid::new_v4(), user_id, client).await
+    }
+
+    /// Consumes `token` and returns its owner together with the next token
+    /// of the family.
+    pub async fn rotate_refresh_token(
+        &self,
+        token: &str,
+        client: &ClientContext,
+    ) -> AppResult<(UserId, String)> {
+        let refresh_token = sqlx::query_as::<_, RefreshToken>(
+            "SELECT * FROM refresh_tokens WHERE token_hash = $1"
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.db)
+        .await
+        .context("load refresh token")?
+        .ok_or_else(invalid_refresh_token)?;
+
+        if refresh_token.revoked_at.is_some() || refresh_token.expires_at <= self.clock.now() {
+            return Err(invalid_refresh_token());
+        }
+
+        if refresh_token.used_at.is_some() {
+            return self.reject_reuse(&refresh_token, client).await;
+        }
+
+        let mismatches = self.binding_mismatches(&refresh_token, client);
+        if !mismatches.is_empty() {
+            self.audit_service
+                .record(AuditEvent {
+                    event_type: REFRESH_TOKEN_BINDING_MISMATCH,
+                    user_id: Some(refresh_token.user_id),
+                    ip_address: client.ip.map(|ip| ip.to_string()),
+                    device: Some(client.device()),
+                    metadata: json!({
+                        "family_id": refresh_token.family_id,
+                        "mismatches": mismatches,
+                        "enforced": self.settings.binding_mode == BindingMode::Enforce,
+                    }),
+                })
+                .await?;
+
+            if self.settings.binding_mode == BindingMode::Enforce {
+                self.revoke_family(refresh_token.family_id).await?;
+                return Err(invalid_refresh_token());
+            }
+        }
+
+        // Consume the token and issue its successor together. The lock
+        // serializes concurrent presentations: the first wins, the others
+        // then find the token used
+        let next_token = db::retry_conflicts(|| self.consume_refresh_token(&refresh_token, client)).await?;
+        match next_token {
+            Some(next_token) => Ok((refresh_token.user_id, next_token)),
+            None => self.reject_reuse(&refresh_token, client).await,
+        }
+    }
+
+    /// The next token of the family, or `None` if `refresh_token` was
+    /// consumed meanwhile.
+    async fn consume_refresh_token(
+        &self,
+        refresh_token: &RefreshToken,
+        client: &ClientContext,
+    ) -> AppResult<Option<String>> {
+        let mut tx = self.db.begin().await.context("begin refresh token rotation")?;
+        let locked: RefreshToken = db::find_for_update(&mut tx, refresh_token.id)
+            .await?
+            .ok_or_else(invalid_refresh_token)?;
+        if locked.revoked_at.is_some() {
+            return Err(invalid_refresh_token());
+        }
+        if locked.used_at.is_some() {
+            return Ok(None);
+        }
+
+        sqlx::query("UPDATE refresh_tokens SET used_at = $2 WHERE id = $1")
+            .bind(refresh_token.id)
+            .bind(self.clock.now())
+            .execute(&mut *tx)
+            .await
+            .entity_context("consume refresh token", refresh_token.id)?;
+        let next_token = self
+            .insert_refresh_token(&mut *tx, refresh_token.family_id, refresh_token.user_id, client)
+            .await?;
+        tx.commit().await.context("commit refresh token rotation")?;
+
+        Ok(Some(next_token))
+    }
+
+    pub async fn revoke_family(&self, family_id: Uuid) -> AppResult<()> {
+        sqlx::query(
+            "UPDATE refresh_tokens SET revoked_at = $2 WHERE family_id = $1 AND revoked_at IS NULL"
+        )
+        .bind(family_id)
+        .bind(self.clock.now())
+        .execute(&self.db)
+        .await
+        .entity_context("revoke token family", family_id)?;
+
+        Ok(())
+    }
+
+    /// The user's signed-in sessions, most recently active first.
+    pub async fn list_sessions(&self, user_id: UserId) -> AppResult<Vec<Session>> {
+        // A live family has exactly one token that is neither used nor revoked
+        let sessions = sqlx::query_as::<_, Session>(
+            r#"
+            SELECT t.family_id AS id, t.device, f.started_at, t.created_at AS last_active_at
+            FROM refresh_tokens t
+            JOIN (
+                SELECT family_id, MIN(created_at) AS started_at
+                FROM refresh_tokens WHERE user_id = $1 GROUP BY family_id
+            ) f USING (family_id)
+            WHERE t.user_id = $1 AND t.used_at IS NULL AND t.revoked_at IS NULL AND t.expires_at > $2
+            ORDER BY t.created_at DESC
+            "#
+        )
+        .bind(user_id)
+        .bind(self.clock.now())
+        .fetch_all(&self.db)
+        .await
+        .entity_context("list sessions", user_id)?;
+
+        Ok(sessions)
+    }
+
+    /// Revokes the family `token` belongs to, signing out its session.
+    /// Unknown tokens are ignored.
+    pub async fn revoke_token(&self, token: &str) -> AppResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE refresh_tokens SET revoked_at = $2
+            WHERE family_id = (SELECT family_id FROM refresh_tokens WHERE token_hash = $1) AND revoked_at IS NULL
+            "#
+        )
+        .bind(hash_token(token))
+        .bind(self.clock.now())
+        .execute(&self.db)
+        .await
+        .context("revoke refresh token")?;
+
+        Ok(())
+    }
+
+    /// Revokes every refresh token of `user_id`, signing out all sessions.
+    pub async fn revoke_all(&self, user_id: UserId) -> AppResult<()> {
+        self.revoke_all_in(&self.db, user_id).await
+    }
+
+    /// [`revoke_all`](Self::revoke_all) as part of the caller's transaction.
+    pub async fn revoke_all_in(&self, executor: impl PgExecutor<'_>, user_id: UserId) -> AppResult<()> {
+        sqlx::query(
+            "UPDATE refresh_tokens SET revoked_at = $2 WHERE user_id = $1 AND revoked_at IS NULL"
+        )
+        .bind(user_id)
+        .bind(self.clock.now())
+        .execute(executor)
+        .await
+        .entity_context("revoke sessions", user_id)?;
+
+        Ok(())
+    }
+
+    async fn reject_reuse<T>(&self, refresh_token: &RefreshToken, client: &ClientContext) -> AppResult<T> {
+        self.revoke_family(refresh_token.family_id).await?;
+        self.audit_service
+            .record(AuditEvent {
+                event_type: REFRESH_TOKEN_REUSED,
+                user_id: Some(refresh_token.user_id),
+                ip_address: client.ip.map(|ip| ip.to_string()),
+                device: Some(client.device()),
+                metadata: json!({ "family_id": refresh_token.family_id }),
+            })
+            .await?;
+
+        Err(AppError::Unauthorized.with_code(ErrorCode::AuthRefreshTokenReused))
+    }
+
+    fn binding_mismatches(&self, refresh_token: &RefreshToken, client: &ClientContext) -> Vec<&'static str> {
+        let mut mismatches = Vec::new();
+
+        if self.settings.binding_mode == BindingMode::Off {
+            return mismatches;
+        }
+
+        if self.settings.bind_fingerprint
+            && hash_fingerprint(&client.user_agent, client.device_id.as_deref()) != refresh_token.fingerprint_hash
+        {
+            mismatches.push("fingerprint");
+        }
+
+        if self.settings.bind_ip_subnet {
+            if let (Some(bound), Some(current)) = (&refresh_token.ip_subnet, self.subnet_of(client)) {
+                if *bound != current {
+                    mismatches.push("ip_subnet");
+                }
+            }
+        }
+
+        mismatches
+    }
+
+    fn subnet_of(&self, client: &ClientContext) -> Option<String> {
+        client.ip.map(|ip| {
+            ip_subnet(
+                ip,
+                self.settings.ipv4_subnet_prefix,
+                self.settings.ipv6_subnet_prefix,
+            )
+        })
+    }
+
+    async fn insert_refresh_token(
+        &self,
+        executor: impl PgExecutor<'_>,
+        family_id: Uuid,
+        user_id: UserId,
+        client: &ClientContext,
+    ) -> AppResult<String> {
+        let token = generate_secret(REFRESH_TOKEN_LENGTH);
+        let expires_at = self.clock.now() + Duration::seconds(self.refresh_token_expiry);
+
+        sqlx::query(
+            r#"
+            INSERT INTO refresh_tokens (family_id, user_id, token_hash, fingerprint_hash, ip_subnet, device, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#
+        )
+        .bind(family_id)
+        .bind(user_id)
+        .bind(hash_token(&token))
+        .bind(hash_fingerprint(&client.user_agent, client.device_id.as_deref()))
+        .bind(self.subnet_of(client))
+        .bind(Json(client.device()))
+        .bind(expires_at)
+        .execute(executor)
+        .await
+        .entity_context("insert refresh token", family_id)?;
+
+        Ok(token)
+    }
+}
+
+fn invalid_refresh_token() -> AppError {
+    AppError::Unauthorized.with_code(ErrorCode::AuthRefreshTokenInvalid)
+}
@@ -0,0 +1,108 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::PgPool;
+use std::sync::{Arc, Mutex};
+
+use crate::config::StatsSettings;
+use crate::errors::{AppError, AppResult, ResultExt};
+use crate::materialized_views::MaterializedViews;
+use crate::models::stats::{DailySignups, StatsSummary};
+use crate::utils::SharedClock;
+
+/// The view signups per day are read from while it's fresh enough.
+const SIGNUPS_VIEW: &str = "daily_signups";
+
+/// User counts and signups per day for `GET /stats`.
+///
+/// A summary is served for up to `stats.max_staleness_secs` after the
+/// oldest figure in it was computed. Signups come from the `daily_signups`
+/// view while its last refresh is within that budget, and are counted from
+/// `users` otherwise; totals are always counted, then cached with the rest.
+pub struct StatsService {
+    db: PgPool,
+    settings: StatsSettings,
+    views: Arc<MaterializedViews>,
+    cached: Mutex<Option<StatsSummary>>,
+    clock: SharedClock,
+}
+
+impl StatsService {
+    pub fn new(db: PgPool, settings: StatsSettings, views: Arc<MaterializedViews>, clock: SharedClock) -> Self {
+        Self {
+            db,
+            settings,
+            views,
+            cached: Mutex::new(None),
+            clock,
+        }
+    }
+
+    pub async fn summary(&self) -> AppResult<StatsSummary> {
+        let now = self.clock.now();
+        let budget = Duration::seconds(self.settings.max_staleness_secs);
+        if let Some(summary) = self.cached.lock().unwrap().as_ref() {
+            if now - summary.as_of <= budget {
+                return Ok(summary.clone());
+            }
+        }
+
+        let (total_users, active_users, verified_users): (i64, i64, i64) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*), COUNT(*) FILTER (WHERE is_active), COUNT(*) FILTER (WHERE is_verified)
+            FROM users WHERE NOT is_guest
+            "#,
+        )
+        .fetch_one(&self.db)
+        .await
+        .context("count users")?;
+
+        let since = (now - Duration::days(self.settings.signup_days - 1)).date_naive();
+        let (signups_per_day, as_of) = match self.view_refreshed_at().await? {
+            Some(refreshed_at) if now - refreshed_at <= budget => {
+                let signups = sqlx::query_as(&format!(
+                    "SELECT day, signups FROM {} WHERE day >= $1 ORDER BY day DESC",
+                    SIGNUPS_VIEW
+                ))
+                .bind(since)
+                .fetch_all(&self.db)
+                .await
+                .context("read signups per day")?;
+                (signups, refreshed_at)
+            }
+            _ => (self.count_signups(since).await?, now),
+        };
+
+        let summary = StatsSummary {
+            total_users,
+            active_users,
+            verified_users,
+            signups_per_day,
+            as_of,
+        };
+        *self.cached.lock().unwrap() = Some(summary.clone());
+        Ok(summary)
+    }
+
+    /// `None` when the view isn't configured or hasn't been refreshed.
+    async fn view_refreshed_at(&self) -> AppResult<Option<DateTime<Utc>>> {
+        match self.views.refreshed_at(SIGNUPS_VIEW).await {
+            Err(AppError::NotFound(_)) => Ok(None),
+            result => result,
+        }
+    }
+
+    async fn count_signups(&self, since: NaiveDate) -> AppResult<Vec<DailySignups>> {
+        sqlx::query_as(
+            r#"
+            SELECT date_trunc('day', created_at)::date AS day, COUNT(*) AS signups
+            FROM users
+            WHERE NOT is_guest AND created_at >= $1
+            GROUP BY 1
+            ORDER BY 1 DESC
+            "#,
+        )
+        .bind(since)
+        .fetch_all(&self.db)
+        .await
+        .context("count signups per day")
+    }
+}
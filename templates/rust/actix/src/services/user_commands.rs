@@ -0,0 +1,349 @@
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use platform_core::domain::{Email, UserId, Username};
+use serde_json::{json, Value};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db;
+use crate::dry_run::DryRun;
+use crate::errors::{AppError, AppResult, ErrorCode, Jitter, ResultExt, RetryHint};
+use crate::models::user::{CreateUser, UpdateUser, User};
+use crate::olap::{DomainEvent, EventBuffer, OlapEvent};
+use crate::services::user_queries::user_not_found;
+use crate::utils::{generate_secret, HashedPassword, SharedClock};
+
+pub const USER_CREATED: &str = "user.created";
+pub const GUEST_UPGRADED: &str = "user.guest_upgraded";
+pub const USER_UPDATED: &str = "user.updated";
+pub const USER_DELETED: &str = "user.deleted";
+
+/// Changes to users. Implementations own their transactions and, once a
+/// change is committed, record it as a domain event; reads go through
+/// [`UserQueries`](super::UserQueries).
+pub trait UserCommands: Send + Sync {
+    fn create_user(&self, create_user: CreateUser, dry_run: DryRun) -> BoxFuture<'_, AppResult<User>>;
+
+    /// Creates a guest with placeholder credentials nobody knows, so it can
+    /// only use the guest token it's issued. Expired guests are deleted
+    /// first.
+    fn create_guest(&self, now: DateTime<Utc>, expires_at: DateTime<Utc>) -> BoxFuture<'_, AppResult<User>>;
+
+    /// Turns a live guest into a full account. The user keeps its id, so
+    /// everything the guest created now belongs to the account.
+    fn upgrade_guest(&self, guest_id: UserId, create_user: CreateUser, now: DateTime<Utc>) -> BoxFuture<'_, AppResult<User>>;
+
+    /// Marks `phone_number` verified, unless the user changed it meanwhile.
+    fn mark_phone_verified<'a>(
+        &'a self,
+        user_id: UserId,
+        phone_number: &'a str,
+        at: DateTime<Utc>,
+    ) -> BoxFuture<'a, AppResult<User>>;
+
+    /// Applies a partial update. With `expected_updated_at` (from `If-Match`)
+    /// the update only happens if the user hasn't changed since it was read.
+    fn update_user(
+        &self,
+        user_id: UserId,
+        update_user: UpdateUser,
+        expected_updated_at: Option<DateTime<Utc>>,
+        dry_run: DryRun,
+    ) -> BoxFuture<'_, AppResult<User>>;
+
+    /// Turns sign-in for `user_id` off or back on.
+    fn set_active(&self, user_id: UserId, active: bool) -> BoxFuture<'_, AppResult<()>>;
+
+    fn delete_user(&self, user_id: UserId) -> BoxFuture<'_, AppResult<()>>;
+}
+
+/// [`UserCommands`] against the primary Postgres pool.
+pub struct PgUserCommands {
+    db: PgPool,
+    events: Arc<EventBuffer>,
+    clock: SharedClock,
+}
+
+impl PgUserCommands {
+    pub fn new(db: PgPool, events: Arc<EventBuffer>, clock: SharedClock) -> Self {
+        Self { db, events, clock }
+    }
+
+    /// Fails with a conflict if the email or username is in use. Checked
+    /// on the primary, as a replica may not have seen the latest sign-up.
+    async fn ensure_available(&self, create_user: &CreateUser) -> AppResult<()> {
+        let existing: Option<String> = sqlx::query_scalar("SELECT email FROM users WHERE email = $1 OR username = $2")
+            .bind(&create_user.email)
+            .bind(&create_user.username)
+            .fetch_optional(&self.db)
+            .await
+            .context("check existing user")?;
+
+        if let Some(email) = existing {
+            let code = if create_user.email == email.as_str() {
+                ErrorCode::UserEmailTaken
+            } else {
+                ErrorCode::UserUsernameTaken
+            };
+            return Err(AppError::Conflict("User with this email or username already exists".to_string()).with_code(code));
+        }
+
+        Ok(())
+    }
+
+    /// Checks the precondition and updates under a lock on the user's row,
+    /// so no other write lands in between.
+    async fn apply_update(
+        &self,
+        user_id: UserId,
+        update_user: &UpdateUser,
+        expected_updated_at: Option<DateTime<Utc>>,
+        dry_run: DryRun,
+    ) -> AppResult<User> {
+        let mut tx = self.db.begin().await.context("begin user update")?;
+        let current: User = db::find_for_update(&mut tx, user_id).await?.ok_or_else(user_not_found)?;
+        if expected_updated_at.is_some_and(|expected| expected != current.updated_at) {
+            return Err(AppError::ConcurrentModification {
+                message: "User was modified since it was read".to_string(),
+                retry: RetryHint::after(Duration::from_millis(200)).with_jitter(Jitter::Full),
+            });
+        }
+
+        // Build dynamic update query
+        let mut query = QueryBuilder::<Postgres>::new("UPDATE users SET updated_at = NOW()");
+
+        if let Some(email) = &update_user.email {
+            // Evaluated against the old row, so only a new address resets
+            // verification and the resend interval
+            query
+                .push(", is_verified = is_verified AND email = ")
+                .push_bind(email)
+                .push(", verification_sent_at = CASE WHEN email = ")
+                .push_bind(email)
+                .push(" THEN verification_sent_at END, email = ")
+                .push_bind(email);
+        }
+
+        if let Some(username) = &update_user.username {
+            query.push(", username = ").push_bind(username);
+        }
+
+        if let Some(full_name) = &update_user.full_name {
+            query.push(", full_name = ").push_bind(full_name);
+        }
+
+        if let Some(phone_number) = &update_user.phone_number {
+            // Evaluated against the old row, so only a new number resets verification
+            query
+                .push(", phone_verified_at = CASE WHEN phone_number IS NOT DISTINCT FROM ")
+                .push_bind(phone_number)
+                .push(" THEN phone_verified_at END, phone_number = ")
+                .push_bind(phone_number);
+        }
+
+        if let Some(is_active) = &update_user.is_active {
+            query.push(", is_active = ").push_bind(is_active);
+        }
+
+        query.push(" WHERE id = ").push_bind(user_id);
+        query.push(" RETURNING *");
+
+        let user = query.build_query_as::<User>()
+            .fetch_one(&mut *tx)
+            .await
+            .entity_context("update user", user_id)?;
+        dry_run.finish(tx).await?;
+
+        Ok(user)
+    }
+
+    fn record(&self, event_type: &str, user_id: UserId, metadata: Value) {
+        self.events.record(OlapEvent::Domain(DomainEvent {
+            timestamp: self.clock.now(),
+            event_type: event_type.to_string(),
+            user_id: Some(user_id),
+            metadata: metadata.to_string(),
+        }));
+    }
+}
+
+impl UserCommands for PgUserCommands {
+    fn create_user(&self, create_user: CreateUser, dry_run: DryRun) -> BoxFuture<'_, AppResult<User>> {
+        Box::pin(async move {
+            self.ensure_available(&create_user).await?;
+
+            // Hash password
+            let password_hash = HashedPassword::new(&create_user.password)?;
+
+            // Insert user
+            let mut tx = self.db.begin().await.context("begin user creation")?;
+            let user = sqlx::query_as::<_, User>(
+                r#"
+                INSERT INTO users (email, username, password_hash, full_name)
+                VALUES ($1, $2, $3, $4)
+                RETURNING *
+                "#
+            )
+            .bind(&create_user.email)
+            .bind(&create_user.username)
+            .bind(&password_hash)
+            .bind(&create_user.full_name)
+            .fetch_one(&mut *tx)
+            .await
+            .context("insert user")?;
+            dry_run.finish(tx).await?;
+
+            if !dry_run.is_dry_run() {
+                self.record(USER_CREATED, user.id, json!({}));
+            }
+            Ok(user)
+        })
+    }
+
+    fn create_guest(&self, now: DateTime<Utc>, expires_at: DateTime<Utc>) -> BoxFuture<'_, AppResult<User>> {
+        Box::pin(async move {
+            sqlx::query("DELETE FROM users WHERE is_guest AND guest_expires_at <= $1")
+                .bind(now)
+                .execute(&self.db)
+                .await
+                .context("delete expired guests")?;
+
+            let id = UserId::new();
+            let tag = id.as_uuid().simple();
+            let email = Email::parse(format!("guest-{}@guest.invalid", tag))?;
+            let username = Username::parse(format!("guest_{}", tag))?;
+            let password_hash = HashedPassword::new(&generate_secret(32))?;
+
+            sqlx::query_as::<_, User>(
+                r#"
+                INSERT INTO users (id, email, username, password_hash, is_guest, guest_expires_at)
+                VALUES ($1, $2, $3, $4, true, $5)
+                RETURNING *
+                "#,
+            )
+            .bind(id)
+            .bind(&email)
+            .bind(&username)
+            .bind(&password_hash)
+            .bind(expires_at)
+            .fetch_one(&self.db)
+            .await
+            .context("insert guest")
+        })
+    }
+
+    fn upgrade_guest(&self, guest_id: UserId, create_user: CreateUser, now: DateTime<Utc>) -> BoxFuture<'_, AppResult<User>> {
+        Box::pin(async move {
+            self.ensure_available(&create_user).await?;
+
+            let password_hash = HashedPassword::new(&create_user.password)?;
+
+            let user = sqlx::query_as::<_, User>(
+                r#"
+                UPDATE users
+                SET email = $2, username = $3, password_hash = $4, full_name = COALESCE($5, full_name),
+                    is_guest = false, guest_expires_at = NULL, updated_at = NOW()
+                WHERE id = $1 AND is_guest AND guest_expires_at > $6
+                RETURNING *
+                "#,
+            )
+            .bind(guest_id)
+            .bind(&create_user.email)
+            .bind(&create_user.username)
+            .bind(&password_hash)
+            .bind(&create_user.full_name)
+            .bind(now)
+            .fetch_optional(&self.db)
+            .await
+            .entity_context("upgrade guest", guest_id)?
+            .ok_or_else(|| AppError::Conflict("Only an unexpired guest can be upgraded".to_string()).with_code(ErrorCode::GuestUpgradeUnavailable))?;
+
+            self.record(GUEST_UPGRADED, user.id, json!({}));
+            Ok(user)
+        })
+    }
+
+    fn mark_phone_verified<'a>(
+        &'a self,
+        user_id: UserId,
+        phone_number: &'a str,
+        at: DateTime<Utc>,
+    ) -> BoxFuture<'a, AppResult<User>> {
+        Box::pin(async move {
+            sqlx::query_as::<_, User>(
+                "UPDATE users SET phone_verified_at = $3, updated_at = NOW() WHERE id = $1 AND phone_number = $2 RETURNING *",
+            )
+            .bind(user_id)
+            .bind(phone_number)
+            .bind(at)
+            .fetch_optional(&self.db)
+            .await
+            .entity_context("verify phone number", user_id)?
+            .ok_or_else(|| {
+                AppError::BadRequest("The phone number changed since the code was sent".to_string())
+                    .with_code(ErrorCode::PhoneOtpInvalid)
+            })
+        })
+    }
+
+    fn update_user(
+        &self,
+        user_id: UserId,
+        update_user: UpdateUser,
+        expected_updated_at: Option<DateTime<Utc>>,
+        dry_run: DryRun,
+    ) -> BoxFuture<'_, AppResult<User>> {
+        Box::pin(async move {
+            if let Some(Some(phone_number)) = &update_user.phone_number {
+                let taken: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE phone_number = $1 AND id <> $2)")
+                    .bind(phone_number)
+                    .bind(user_id)
+                    .fetch_one(&self.db)
+                    .await
+                    .entity_context("check phone number", user_id)?;
+                if taken {
+                    return Err(AppError::Conflict("Phone number is already in use".to_string())
+                        .with_code(ErrorCode::PhoneNumberTaken));
+                }
+            }
+
+            let user =
+                db::retry_conflicts(|| self.apply_update(user_id, &update_user, expected_updated_at, dry_run)).await?;
+            if !dry_run.is_dry_run() {
+                self.record(USER_UPDATED, user_id, json!({ "fields": update_user.fields() }));
+            }
+            Ok(user)
+        })
+    }
+
+    fn set_active(&self, user_id: UserId, active: bool) -> BoxFuture<'_, AppResult<()>> {
+        Box::pin(async move {
+            sqlx::query("UPDATE users SET is_active = $2, updated_at = NOW() WHERE id = $1")
+                .bind(user_id)
+                .bind(active)
+                .execute(&self.db)
+                .await
+                .entity_context("update user", user_id)?;
+
+            Ok(())
+        })
+    }
+
+    fn delete_user(&self, user_id: UserId) -> BoxFuture<'_, AppResult<()>> {
+        Box::pin(async move {
+            let result = sqlx::query("DELETE FROM users WHERE id = $1")
+                .bind(user_id)
+                .execute(&self.db)
+                .await
+                .entity_context("delete user", user_id)?;
+
+            if result.rows_affected() == 0 {
+                return Err(user_not_found());
+            }
+
+            self.record(USER_DELETED, user_id, json!({}));
+            Ok(())
+        })
+    }
+}
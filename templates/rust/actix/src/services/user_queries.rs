@@ -0,0 +1,167 @@
+use futures_util::future::BoxFuture;
+use platform_core::domain::{Email, UserId};
+use sqlx::{PgPool, Row};
+
+use crate::config::CountMode;
+use crate::errors::{AppError, AppResult, ErrorCode, ResultExt};
+use crate::models::user::{PaginatedResponse, User, UserResponse};
+
+/// Reads of users, which change nothing. Kept apart from
+/// [`UserCommands`](super::UserCommands) so reads can be served from a
+/// replica or a cache without touching the write path.
+pub trait UserQueries: Send + Sync {
+    fn get_user_by_id(&self, user_id: UserId) -> BoxFuture<'_, AppResult<User>>;
+
+    fn get_user_by_email<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, AppResult<User>>;
+
+    /// The active user with `email`, if any. Guests don't count; they can't
+    /// sign in.
+    fn get_active_user_by_email<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, AppResult<Option<User>>>;
+
+    /// The active user owning `phone_number`, if they verified it. Guests
+    /// don't count.
+    fn get_user_by_verified_phone<'a>(&'a self, phone_number: &'a str) -> BoxFuture<'a, AppResult<Option<User>>>;
+
+    fn get_users(&self, page: u32, limit: u32, count: CountMode) -> BoxFuture<'_, AppResult<PaginatedResponse<UserResponse>>>;
+
+    fn verify_user_credentials<'a>(&'a self, email: &'a Email, password: &'a str) -> BoxFuture<'a, AppResult<User>>;
+}
+
+/// [`UserQueries`] against a Postgres pool, the primary unless it's given
+/// a replica's.
+pub struct PgUserQueries {
+    db: PgPool,
+}
+
+impl PgUserQueries {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+
+    /// Counts the listed users as `count` asks. The estimate is the
+    /// planner's row count for the listing query, which it derives from
+    /// `pg_class.reltuples` and the column statistics, so no rows are read.
+    async fn count_users(&self, count: CountMode) -> AppResult<Option<i64>> {
+        match count {
+            CountMode::Exact => {
+                let total: i64 = sqlx::query("SELECT COUNT(*) FROM users WHERE NOT is_guest")
+                    .fetch_one(&self.db)
+                    .await
+                    .context("count users")?
+                    .get(0);
+                Ok(Some(total))
+            }
+            CountMode::Estimated => {
+                let plan: serde_json::Value = sqlx::query_scalar("EXPLAIN (FORMAT JSON) SELECT 1 FROM users WHERE NOT is_guest")
+                    .fetch_one(&self.db)
+                    .await
+                    .context("estimate user count")?;
+                let rows = plan[0]["Plan"]["Plan Rows"].as_f64().unwrap_or(0.0);
+                Ok(Some(rows.round() as i64))
+            }
+            CountMode::None => Ok(None),
+        }
+    }
+}
+
+impl UserQueries for PgUserQueries {
+    fn get_user_by_id(&self, user_id: UserId) -> BoxFuture<'_, AppResult<User>> {
+        Box::pin(async move {
+            sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+                .bind(user_id)
+                .fetch_optional(&self.db)
+                .await
+                .entity_context("load user", user_id)?
+                .ok_or_else(user_not_found)
+        })
+    }
+
+    fn get_user_by_email<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, AppResult<User>> {
+        Box::pin(async move {
+            sqlx::query_as::<_, User>("SELECT * FROM users WHERE email = $1")
+                .bind(email)
+                .fetch_optional(&self.db)
+                .await
+                .context("load user by email")?
+                .ok_or_else(user_not_found)
+        })
+    }
+
+    fn get_active_user_by_email<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, AppResult<Option<User>>> {
+        Box::pin(async move {
+            sqlx::query_as::<_, User>("SELECT * FROM users WHERE email = $1 AND is_active AND NOT is_guest")
+                .bind(email)
+                .fetch_optional(&self.db)
+                .await
+                .context("load active user by email")
+        })
+    }
+
+    fn get_user_by_verified_phone<'a>(&'a self, phone_number: &'a str) -> BoxFuture<'a, AppResult<Option<User>>> {
+        Box::pin(async move {
+            sqlx::query_as::<_, User>(
+                "SELECT * FROM users WHERE phone_number = $1 AND phone_verified_at IS NOT NULL AND is_active AND NOT is_guest",
+            )
+            .bind(phone_number)
+            .fetch_optional(&self.db)
+            .await
+            .context("load user by phone number")
+        })
+    }
+
+    fn get_users(&self, page: u32, limit: u32, count: CountMode) -> BoxFuture<'_, AppResult<PaginatedResponse<UserResponse>>> {
+        Box::pin(async move {
+            let offset = (page - 1) * limit;
+
+            let total = self.count_users(count).await?;
+
+            let users = sqlx::query_as::<_, User>(
+                "SELECT * FROM users WHERE NOT is_guest ORDER BY created_at DESC LIMIT $1 OFFSET $2"
+            )
+            .bind(limit as i64)
+            .bind(offset as i64)
+            .fetch_all(&self.db)
+            .await
+            .context("list users")?;
+
+            let user_responses: Vec<UserResponse> = users.into_iter().map(|u| u.into()).collect();
+            let total_pages = total.map(|total| ((total as f64) / (limit as f64)).ceil() as u32);
+
+            Ok(PaginatedResponse {
+                data: user_responses,
+                total,
+                page,
+                limit,
+                total_pages,
+                count,
+            })
+        })
+    }
+
+    fn verify_user_credentials<'a>(&'a self, email: &'a Email, password: &'a str) -> BoxFuture<'a, AppResult<User>> {
+        Box::pin(async move {
+            let invalid_credentials = || AppError::Unauthorized.with_code(ErrorCode::AuthInvalidCredentials);
+            let user = self
+                .get_user_by_email(email)
+                .await
+                .map_err(|e| match e.code() {
+                    ErrorCode::UserNotFound => invalid_credentials(),
+                    _ => e,
+                })?;
+
+            if !user.is_active {
+                return Err(AppError::Forbidden.with_code(ErrorCode::AuthAccountDisabled));
+            }
+
+            if !user.password_hash.verify(password)? {
+                return Err(invalid_credentials());
+            }
+
+            Ok(user)
+        })
+    }
+}
+
+pub(crate) fn user_not_found() -> AppError {
+    AppError::NotFound("User not found".to_string()).with_code(ErrorCode::UserNotFound)
+}
@@ -0,0 +1,142 @@
+//! Outbound SMS behind the [`SmsSender`] trait, picked by `sms.provider`:
+//! [`ConsoleSmsSender`] logs messages for development and
+//! [`TwilioSmsSender`] sends them through Twilio's Messages API.
+
+use config::ConfigError;
+use futures_util::future::BoxFuture;
+use reqwest::Client;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::{SmsProvider, SmsSettings};
+use crate::egress::EgressPolicy;
+use crate::errors::{AppError, AppResult, ErrorCode, RetryHint};
+use crate::health::{HealthCheck, HttpDependency};
+use crate::utils::mask_phone_number;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmsMessage {
+    /// Recipient in E.164 format.
+    pub to: String,
+    pub body: String,
+}
+
+pub trait SmsSender: Send + Sync {
+    fn send<'a>(&'a self, message: &'a SmsMessage) -> BoxFuture<'a, AppResult<()>>;
+
+    /// Readiness checks of the provider this sender calls.
+    fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+        Vec::new()
+    }
+}
+
+/// Builds the sender selected by `sms.provider`, sending through `egress`.
+pub fn from_settings(settings: &SmsSettings, egress: &EgressPolicy) -> Result<Arc<dyn SmsSender>, ConfigError> {
+    match settings.provider {
+        SmsProvider::Console => Ok(Arc::new(ConsoleSmsSender::default())),
+        SmsProvider::Twilio => Ok(Arc::new(TwilioSmsSender::from_settings(settings, egress)?)),
+    }
+}
+
+/// How many messages [`ConsoleSmsSender`] keeps.
+const OUTBOX_CAPACITY: usize = 100;
+
+/// Logs messages instead of sending them and keeps the latest ones, so
+/// codes can be read from the log in development and from
+/// [`outbox`](Self::outbox) in tests.
+#[derive(Default)]
+pub struct ConsoleSmsSender {
+    outbox: Mutex<VecDeque<SmsMessage>>,
+}
+
+impl ConsoleSmsSender {
+    /// Messages sent so far, oldest first.
+    pub fn outbox(&self) -> Vec<SmsMessage> {
+        self.outbox.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl SmsSender for ConsoleSmsSender {
+    fn send<'a>(&'a self, message: &'a SmsMessage) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            tracing::info!(to = %message.to, "SMS: {}", message.body);
+
+            let mut outbox = self.outbox.lock().unwrap();
+            if outbox.len() == OUTBOX_CAPACITY {
+                outbox.pop_front();
+            }
+            outbox.push_back(message.clone());
+            Ok(())
+        })
+    }
+}
+
+pub struct TwilioSmsSender {
+    client: Client,
+    api_url: String,
+    messages_url: String,
+    account_sid: String,
+    auth_token: String,
+    from_number: String,
+}
+
+impl TwilioSmsSender {
+    pub fn from_settings(settings: &SmsSettings, egress: &EgressPolicy) -> Result<Self, ConfigError> {
+        let required = |value: &Option<String>, key: &str| {
+            value
+                .clone()
+                .filter(|value| !value.is_empty())
+                .ok_or_else(|| ConfigError::Message(format!("sms.{} is required for the twilio provider", key)))
+        };
+        let account_sid = required(&settings.twilio_account_sid, "twilio_account_sid")?;
+        let auth_token = required(&settings.twilio_auth_token, "twilio_auth_token")?;
+        let from_number = required(&settings.from_number, "from_number")?;
+
+        egress.check_endpoint("sms.twilio_api_url", &settings.twilio_api_url)?;
+        let client = egress
+            .client_builder()
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .build()
+            .map_err(|e| ConfigError::Message(format!("failed to build the Twilio client: {}", e)))?;
+
+        let api_url = settings.twilio_api_url.trim_end_matches('/').to_string();
+
+        Ok(Self {
+            client,
+            messages_url: format!("{}/2010-04-01/Accounts/{}/Messages.json", api_url, account_sid),
+            api_url,
+            account_sid,
+            auth_token,
+            from_number,
+        })
+    }
+}
+
+impl SmsSender for TwilioSmsSender {
+    fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+        vec![Arc::new(HttpDependency::new("twilio", self.client.clone(), &self.api_url))]
+    }
+
+    fn send<'a>(&'a self, message: &'a SmsMessage) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(&self.messages_url)
+                .basic_auth(&self.account_sid, Some(&self.auth_token))
+                .form(&[("To", message.to.as_str()), ("From", &self.from_number), ("Body", &message.body)])
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+
+            response.map(|_| ()).map_err(|e| {
+                tracing::warn!(to = %mask_phone_number(&message.to), "Twilio rejected SMS: {}", e);
+                AppError::Unavailable {
+                    message: "SMS delivery failed".to_string(),
+                    retry: RetryHint::after(Duration::from_secs(30)),
+                }
+                .with_code(ErrorCode::SmsDeliveryFailed)
+            })
+        })
+    }
+}
@@ -0,0 +1,199 @@
+//! Heartbeats to the platform control plane.
+//!
+//! With `telemetry.enabled`, a [`Reporter`] POSTs a [`Heartbeat`] to
+//! `telemetry.endpoint` every `telemetry.interval_secs` from when the server
+//! is ready, so the platform inventories running services without anyone
+//! registering them. A heartbeat says which service and version this is,
+//! how long the instance has been up, how healthy it is and a few runtime
+//! figures. Failed heartbeats are retried with exponential backoff, and a
+//! last one with status `stopping` is sent on shutdown.
+
+use chrono::{DateTime, Utc};
+use config::ConfigError;
+use platform_core::lifecycle::{Hook, Lifecycle};
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::config::TelemetrySettings;
+use crate::health::HealthReport;
+use crate::manifest::Manifest;
+use crate::AppState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstanceStatus {
+    Running,
+    /// Serving, with a non-critical dependency down.
+    Degraded,
+    /// A critical dependency is down.
+    Unready,
+    Stopping,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub service: String,
+    pub version: String,
+    pub environment: String,
+    /// Random per process, so restarts show up as new instances.
+    pub instance_id: Uuid,
+    pub status: InstanceStatus,
+    pub started_at: DateTime<Utc>,
+    pub uptime_secs: i64,
+    pub reported_at: DateTime<Utc>,
+    pub health: HealthReport,
+    pub metrics: HeartbeatMetrics,
+    /// What the service was built from.
+    pub manifest: Manifest,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeartbeatMetrics {
+    /// Tasks alive across the runtimes serving requests.
+    pub alive_tasks: usize,
+    pub runtimes: usize,
+}
+
+pub struct Reporter {
+    settings: TelemetrySettings,
+    endpoint: Url,
+    client: Client,
+    state: Arc<AppState>,
+    instance_id: Uuid,
+    started_at: DateTime<Utc>,
+}
+
+impl Reporter {
+    pub fn new(state: Arc<AppState>) -> Result<Self, ConfigError> {
+        let settings = state.settings.telemetry.clone();
+        let endpoint = Url::parse(&settings.endpoint)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .ok_or_else(|| ConfigError::Message("telemetry.endpoint must be an http(s) URL".to_string()))?;
+        state.egress.check_endpoint("telemetry.endpoint", endpoint.as_str())?;
+        let client = state
+            .egress
+            .client_builder()
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .build()
+            .map_err(|e| ConfigError::Message(format!("failed to build the telemetry client: {}", e)))?;
+
+        Ok(Self {
+            settings,
+            endpoint,
+            client,
+            instance_id: Uuid::new_v4(),
+            started_at: state.clock.now(),
+            state,
+        })
+    }
+
+    pub async fn heartbeat(&self, stopping: bool) -> Heartbeat {
+        let health = self.state.health.report().await;
+        let status = match (stopping, health.ready, health.degraded) {
+            (true, _, _) => InstanceStatus::Stopping,
+            (false, false, _) => InstanceStatus::Unready,
+            (false, true, true) => InstanceStatus::Degraded,
+            (false, true, false) => InstanceStatus::Running,
+        };
+        let runtimes = self.state.runtimes.stats();
+        let now = self.state.clock.now();
+
+        Heartbeat {
+            service: self.settings.service_name.clone(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            environment: self.settings.environment.clone(),
+            instance_id: self.instance_id,
+            status,
+            started_at: self.started_at,
+            uptime_secs: (now - self.started_at).num_seconds(),
+            reported_at: now,
+            health,
+            metrics: HeartbeatMetrics {
+                alive_tasks: runtimes.iter().map(|(_, stats)| stats.alive_tasks).sum(),
+                runtimes: runtimes.len(),
+            },
+            manifest: Manifest::current(),
+        }
+    }
+
+    pub async fn send(&self, heartbeat: &Heartbeat) -> anyhow::Result<()> {
+        let mut request = self.client.post(self.endpoint.clone()).json(heartbeat);
+        if let Some(token) = &self.settings.token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    /// Delay before the next heartbeat after `failures` failed ones in a
+    /// row: `interval_secs` doubled per failure, capped at
+    /// `max_backoff_secs`.
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let secs = self
+            .settings
+            .interval_secs
+            .saturating_mul(1 << failures.min(30))
+            .min(self.settings.max_backoff_secs.max(self.settings.interval_secs));
+        Duration::from_secs(secs)
+    }
+
+    /// Sends heartbeats until `shutdown` completes, then a last one saying
+    /// the instance is stopping.
+    pub async fn run(self, shutdown: impl Future<Output = ()>) {
+        tokio::pin!(shutdown);
+        let mut failures = 0;
+
+        loop {
+            match self.send(&self.heartbeat(false).await).await {
+                Ok(()) => failures = 0,
+                Err(e) => {
+                    failures += 1;
+                    tracing::warn!(failures, "Telemetry heartbeat failed: {:#}", e);
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(self.backoff(failures)) => {}
+                _ = &mut shutdown => break,
+            }
+        }
+
+        if let Err(e) = self.send(&self.heartbeat(true).await).await {
+            tracing::warn!("Final telemetry heartbeat failed: {:#}", e);
+        }
+    }
+}
+
+/// Reports from when the server is ready until shutdown. Nothing when
+/// `telemetry.enabled` is off.
+pub fn register_hooks(lifecycle: &mut Lifecycle, state: &Arc<AppState>) -> Result<(), ConfigError> {
+    if !state.settings.telemetry.enabled {
+        return Ok(());
+    }
+    let reporter = Reporter::new(state.clone())?;
+
+    let (stop, stopped) = oneshot::channel::<()>();
+    let (started, reporting) = oneshot::channel();
+    lifecycle.on_ready(Hook::new("telemetry", move || async move {
+        let shutdown = async {
+            let _ = stopped.await;
+        };
+        let _ = started.send(tokio::spawn(reporter.run(shutdown)));
+        Ok(())
+    }));
+    lifecycle.on_shutdown(Hook::new("telemetry", move || async move {
+        let _ = stop.send(());
+        if let Ok(reporting) = reporting.await {
+            reporting.await?;
+        }
+        Ok(())
+    }));
+
+    Ok(())
+}
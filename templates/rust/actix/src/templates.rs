@@ -0,0 +1,136 @@
+//! Email rendering with askama, so templates are parsed and checked against
+//! their context structs at compile time.
+//!
+//! Templates live in `templates/`: `layouts/` holds the HTML and plain-text
+//! frames, `partials/` the pieces they share and `emails/` one `.html` and
+//! one `.txt` per email. HTML output is escaped, plain text isn't.
+//!
+//! ```ignore
+//! let email = VerificationEmail { name, verify_url, expires_in_hours: 24 }
+//!     .render(&state.settings.email)?;
+//! ```
+
+use askama::Template;
+
+use crate::config::EmailSettings;
+use crate::errors::{AppError, AppResult};
+
+/// A rendered email, ready to hand to a mailer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// The context of an email with an HTML and a plain-text template.
+pub trait EmailTemplate {
+    fn subject(&self, brand: &EmailSettings) -> String;
+
+    fn render(&self, brand: &EmailSettings) -> AppResult<RenderedEmail>;
+}
+
+/// Implements [`EmailTemplate`] for a context struct, rendering it with a
+/// pair of templates that see it as `email` next to `brand` and `subject`.
+macro_rules! email_template {
+    ($context:ident, $html:literal, $text:literal, |$email:ident, $brand:ident| $subject:expr) => {
+        const _: () = {
+            #[derive(Template)]
+            #[template(path = $html)]
+            struct Html<'a> {
+                email: &'a $context,
+                brand: &'a EmailSettings,
+                subject: &'a str,
+            }
+
+            #[derive(Template)]
+            #[template(path = $text)]
+            struct Text<'a> {
+                email: &'a $context,
+                brand: &'a EmailSettings,
+            }
+
+            impl EmailTemplate for $context {
+                fn subject(&self, $brand: &EmailSettings) -> String {
+                    let $email = self;
+                    $subject
+                }
+
+                fn render(&self, brand: &EmailSettings) -> AppResult<RenderedEmail> {
+                    let subject = self.subject(brand);
+                    let html = Html { email: self, brand, subject: &subject }.render().map_err(render_error)?;
+                    let text = Text { email: self, brand }.render().map_err(render_error)?;
+
+                    Ok(RenderedEmail { subject, html, text })
+                }
+            }
+        };
+    };
+}
+
+#[derive(Debug, Clone)]
+pub struct VerificationEmail {
+    pub name: String,
+    pub verify_url: String,
+    pub expires_in_hours: i64,
+}
+
+email_template!(
+    VerificationEmail,
+    "emails/verification.html",
+    "emails/verification.txt",
+    |_email, brand| format!("Verify your {} email address", brand.product_name)
+);
+
+#[derive(Debug, Clone)]
+pub struct PasswordResetEmail {
+    pub name: String,
+    pub reset_url: String,
+    pub expires_in_minutes: i64,
+    /// Address the reset was requested from, shown so users can spot
+    /// requests they didn't make.
+    pub requested_from: Option<String>,
+}
+
+email_template!(
+    PasswordResetEmail,
+    "emails/password_reset.html",
+    "emails/password_reset.txt",
+    |_email, brand| format!("Reset your {} password", brand.product_name)
+);
+
+#[derive(Debug, Clone)]
+pub struct InviteEmail {
+    pub inviter_name: String,
+    pub invite_url: String,
+    pub expires_in_days: i64,
+}
+
+email_template!(
+    InviteEmail,
+    "emails/invite.html",
+    "emails/invite.txt",
+    |email, brand| format!("{} invited you to {}", email.inviter_name, brand.product_name)
+);
+
+#[derive(Debug, Clone)]
+pub struct MagicLinkEmail {
+    pub name: String,
+    pub login_url: String,
+    pub expires_in_minutes: i64,
+    /// Address the link was requested from, shown so users can spot
+    /// requests they didn't make.
+    pub requested_from: Option<String>,
+}
+
+email_template!(
+    MagicLinkEmail,
+    "emails/magic_link.html",
+    "emails/magic_link.txt",
+    |_email, brand| format!("Sign in to {}", brand.product_name)
+);
+
+fn render_error(error: askama::Error) -> AppError {
+    tracing::error!("Failed to render email template: {}", error);
+    AppError::InternalServerError
+}
@@ -0,0 +1,139 @@
+//! Where login responses put tokens, selected by `token_delivery.mode`.
+//!
+//! In `body` mode (the default) tokens are returned in JSON and sent back as
+//! `Authorization: Bearer`. In `cookies` mode, meant for browser apps, they
+//! are set as httpOnly cookies instead, so an XSS payload can't read them:
+//! the access token on the API path, the refresh token only on `/auth`,
+//! where `/auth/refresh` rotates it and `/auth/logout` clears it.
+//!
+//! Browsers attach cookies to cross-site requests too, so cookie delivery
+//! relies on the `security` middleware's CSRF check; [`check_csrf`] refuses
+//! to start without it.
+
+use actix_web::{
+    cookie::{time::Duration, Cookie, SameSite},
+    http::StatusCode,
+    HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use config::ConfigError;
+use serde::Serialize;
+
+use crate::config::{CookieSameSite, MiddlewareSettings, Settings, TokenDeliveryMode, TokenDeliverySettings};
+use crate::models::user::{GuestResponse, LoginResponse, UserResponse};
+
+const ACCESS_COOKIE_PATH: &str = "/api/v1";
+const REFRESH_COOKIE_PATH: &str = "/api/v1/auth";
+
+/// The body of a login response in cookie mode: the tokens are in
+/// `Set-Cookie`.
+#[derive(Debug, Serialize)]
+pub struct CookieSessionResponse {
+    pub expires_in: i64,
+    pub user: UserResponse,
+}
+
+/// Fails unless cookie delivery is covered by the CSRF check.
+pub fn check_csrf(settings: &Settings) -> Result<(), ConfigError> {
+    if settings.token_delivery.mode == TokenDeliveryMode::Body {
+        return Ok(());
+    }
+
+    let security = settings.server.middleware.iter().any(|m| matches!(m, MiddlewareSettings::Security));
+    if !(security && settings.security.csrf) {
+        return Err(ConfigError::Message(
+            "token_delivery.mode = cookies needs the security middleware with security.csrf on".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Answers a login with `status`, delivering its tokens as configured.
+pub fn login(settings: &Settings, status: StatusCode, response: LoginResponse) -> HttpResponse {
+    let refresh_expires_in = settings.jwt.refresh_token_expiry;
+    let settings = &settings.token_delivery;
+    if settings.mode == TokenDeliveryMode::Body {
+        return HttpResponseBuilder::new(status).json(response);
+    }
+
+    HttpResponseBuilder::new(status)
+        .cookie(access_cookie(settings, response.access_token, response.expires_in))
+        .cookie(refresh_cookie(settings, response.refresh_token, refresh_expires_in))
+        .json(CookieSessionResponse {
+            expires_in: response.expires_in,
+            user: response.user,
+        })
+}
+
+/// Answers a guest login; guests get no refresh token.
+pub fn guest(settings: &Settings, response: GuestResponse) -> HttpResponse {
+    let settings = &settings.token_delivery;
+    if settings.mode == TokenDeliveryMode::Body {
+        return HttpResponse::Created().json(response);
+    }
+
+    HttpResponse::Created()
+        .cookie(access_cookie(settings, response.access_token, response.expires_in))
+        .json(CookieSessionResponse {
+            expires_in: response.expires_in,
+            user: response.user,
+        })
+}
+
+/// Expires both cookies on `response`; a no-op in body mode.
+pub fn clear(settings: &Settings, response: &mut HttpResponseBuilder) {
+    let settings = &settings.token_delivery;
+    if settings.mode == TokenDeliveryMode::Body {
+        return;
+    }
+
+    for (name, path) in [
+        (&settings.access_cookie, ACCESS_COOKIE_PATH),
+        (&settings.refresh_cookie, REFRESH_COOKIE_PATH),
+    ] {
+        let mut cookie = cookie(settings, name.clone(), String::new(), path);
+        cookie.make_removal();
+        response.cookie(cookie);
+    }
+}
+
+/// The access token from its cookie, in cookie mode.
+pub fn access_token(settings: &Settings, req: &HttpRequest) -> Option<String> {
+    cookie_value(&settings.token_delivery, req, &settings.token_delivery.access_cookie)
+}
+
+/// The refresh token from its cookie, in cookie mode.
+pub fn refresh_token(settings: &Settings, req: &HttpRequest) -> Option<String> {
+    cookie_value(&settings.token_delivery, req, &settings.token_delivery.refresh_cookie)
+}
+
+fn cookie_value(settings: &TokenDeliverySettings, req: &HttpRequest, name: &str) -> Option<String> {
+    if settings.mode == TokenDeliveryMode::Body {
+        return None;
+    }
+    req.cookie(name).map(|cookie| cookie.value().to_string())
+}
+
+fn access_cookie(settings: &TokenDeliverySettings, token: String, max_age_secs: i64) -> Cookie<'static> {
+    let mut cookie = cookie(settings, settings.access_cookie.clone(), token, ACCESS_COOKIE_PATH);
+    cookie.set_max_age(Duration::seconds(max_age_secs));
+    cookie
+}
+
+fn refresh_cookie(settings: &TokenDeliverySettings, token: String, max_age_secs: i64) -> Cookie<'static> {
+    let mut cookie = cookie(settings, settings.refresh_cookie.clone(), token, REFRESH_COOKIE_PATH);
+    cookie.set_max_age(Duration::seconds(max_age_secs));
+    cookie
+}
+
+fn cookie(settings: &TokenDeliverySettings, name: String, value: String, path: &'static str) -> Cookie<'static> {
+    Cookie::build(name, value)
+        .path(path)
+        .http_only(true)
+        .secure(settings.secure_cookies)
+        .same_site(match settings.same_site {
+            CookieSameSite::Strict => SameSite::Strict,
+            CookieSameSite::Lax => SameSite::Lax,
+        })
+        .finish()
+}
@@ -0,0 +1,32 @@
+use platform_auth::generate_secret;
+
+pub const API_TOKEN_PREFIX: &str = "dxp_";
+
+const PREFIX_ID_LENGTH: usize = 8;
+const SECRET_LENGTH: usize = 32;
+
+/// Generates a new personal access token of the form `dxp_<id>_<secret>`.
+///
+/// Returns the plaintext token together with its lookup prefix (`dxp_<id>`).
+pub fn generate_api_token() -> (String, String) {
+    let id = generate_secret(PREFIX_ID_LENGTH);
+    let secret = generate_secret(SECRET_LENGTH);
+
+    let prefix = format!("{}{}", API_TOKEN_PREFIX, id);
+    let token = format!("{}_{}", prefix, secret);
+
+    (token, prefix)
+}
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(API_TOKEN_PREFIX)
+}
+
+/// Extracts the lookup prefix (`dxp_<id>`) from a plaintext token.
+pub fn api_token_prefix(token: &str) -> Option<&str> {
+    let prefix_length = API_TOKEN_PREFIX.len() + PREFIX_ID_LENGTH;
+    if !is_api_token(token) || token.len() <= prefix_length {
+        return None;
+    }
+    token.get(..prefix_length)
+}
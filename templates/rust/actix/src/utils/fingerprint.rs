@@ -0,0 +1,26 @@
+use crate::utils::hash_token;
+use std::net::IpAddr;
+
+/// Hashes the client characteristics a refresh token is bound to.
+pub fn hash_fingerprint(user_agent: &str, device_id: Option<&str>) -> String {
+    hash_token(&format!("{}|{}", user_agent, device_id.unwrap_or_default()))
+}
+
+/// Masks an address down to its subnet (e.g. `203.0.113.0/24`), so clients
+/// moving within the same network keep their binding.
+pub fn ip_subnet(ip: IpAddr, ipv4_prefix: u8, ipv6_prefix: u8) -> String {
+    match ip {
+        IpAddr::V4(addr) => {
+            let prefix = ipv4_prefix.min(32);
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            let network = std::net::Ipv4Addr::from(u32::from(addr) & mask);
+            format!("{}/{}", network, prefix)
+        }
+        IpAddr::V6(addr) => {
+            let prefix = ipv6_prefix.min(128);
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            let network = std::net::Ipv6Addr::from(u128::from(addr) & mask);
+            format!("{}/{}", network, prefix)
+        }
+    }
+}
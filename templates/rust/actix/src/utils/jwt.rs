@@ -1,40 +1,217 @@
-use crate::errors::AppResult;
-use crate::models::user::Claims;
-use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
-use uuid::Uuid;
+use crate::config::JwtSettings;
+use crate::errors::{AppError, AppResult};
+use crate::utils::Clock;
+use crate::models::role::Grants;
+use crate::models::user::{Acr, Actor, Claims, User};
+use chrono::Duration;
+use config::ConfigError;
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{decode, decode_header, encode, DecodingKey, EncodingKey, Header, Validation};
+use platform_core::domain::{Email, UserId};
+use std::collections::HashMap;
+
+/// Key id assigned to the legacy single `jwt.secret`.
+pub const DEFAULT_KID: &str = "default";
+
+/// The set of keys tokens are signed and validated with.
+///
+/// Exactly one key signs new tokens; every configured key validates, so a
+/// key can be introduced, promoted and later retired without invalidating
+/// sessions signed with its predecessor.
+#[derive(Clone)]
+pub struct JwtKeys {
+    active_kid: String,
+    secrets: HashMap<String, String>,
+}
+
+impl JwtKeys {
+    pub fn from_settings(settings: &JwtSettings) -> Result<Self, ConfigError> {
+        if settings.keys.is_empty() {
+            let secret = settings.secret.clone().ok_or_else(|| {
+                ConfigError::Message("either jwt.secret or jwt.keys must be configured".into())
+            })?;
+            return Ok(Self::single(secret));
+        }
+
+        let secrets: HashMap<String, String> = settings
+            .keys
+            .iter()
+            .map(|key| (key.kid.clone(), key.secret.clone()))
+            .collect();
+
+        let active_kid = settings.active_kid.clone().ok_or_else(|| {
+            ConfigError::Message("jwt.active_kid is required when jwt.keys is set".into())
+        })?;
+
+        if !secrets.contains_key(&active_kid) {
+            return Err(ConfigError::Message(format!(
+                "jwt.active_kid '{}' does not match any configured key",
+                active_kid
+            )));
+        }
+
+        Ok(Self {
+            active_kid,
+            secrets,
+        })
+    }
+
+    pub fn single(secret: String) -> Self {
+        Self {
+            active_kid: DEFAULT_KID.to_string(),
+            secrets: HashMap::from([(DEFAULT_KID.to_string(), secret)]),
+        }
+    }
+
+    pub fn active_kid(&self) -> &str {
+        &self.active_kid
+    }
+
+    /// Iterates over `(kid, secret)` pairs, e.g. to derive other key material.
+    pub fn secrets(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.secrets.iter().map(|(kid, secret)| (kid.as_str(), secret.as_str()))
+    }
+
+    fn encoding_key(&self) -> EncodingKey {
+        EncodingKey::from_secret(self.secrets[&self.active_kid].as_ref())
+    }
+
+    /// Tokens issued before `kid` headers were introduced are validated
+    /// against the active key.
+    fn decoding_key(&self, kid: Option<&str>) -> Option<DecodingKey> {
+        self.secrets
+            .get(kid.unwrap_or(&self.active_kid))
+            .map(|secret| DecodingKey::from_secret(secret.as_ref()))
+    }
+}
 
 pub fn create_jwt_token(
-    user_id: Uuid,
-    email: &str,
-    secret: &str,
+    user_id: UserId,
+    email: &Email,
+    grants: Grants,
+    keys: &JwtKeys,
     expiry_hours: i64,
+    clock: &dyn Clock,
 ) -> AppResult<String> {
-    let now = Utc::now();
+    let now = clock.now();
     let expires_at = now + Duration::hours(expiry_hours);
-    
+
+    let claims = Claims {
+        sub: user_id,
+        email: email.clone(),
+        exp: expires_at.timestamp() as usize,
+        iat: now.timestamp() as usize,
+        guest: false,
+        act: None,
+        auth_time: None,
+        acr: None,
+        grants,
+    };
+
+    sign_claims(&claims, keys)
+}
+
+/// An elevated token for a user who just re-authenticated with `acr`.
+pub fn create_step_up_token(
+    user_id: UserId,
+    email: &Email,
+    grants: Grants,
+    acr: Acr,
+    keys: &JwtKeys,
+    max_age_secs: i64,
+    clock: &dyn Clock,
+) -> AppResult<String> {
+    let now = clock.now();
+    let expires_at = now + Duration::seconds(max_age_secs);
+
     let claims = Claims {
         sub: user_id,
-        email: email.to_string(),
+        email: email.clone(),
         exp: expires_at.timestamp() as usize,
         iat: now.timestamp() as usize,
+        guest: false,
+        act: None,
+        auth_time: Some(now.timestamp() as usize),
+        acr: Some(acr),
+        grants,
     };
-    
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )?;
-    
+
+    sign_claims(&claims, keys)
+}
+
+/// A guest token; it expires with the guest.
+pub fn create_guest_token(guest: &User, keys: &JwtKeys, clock: &dyn Clock) -> AppResult<String> {
+    let expires_at = guest.guest_expires_at.ok_or(AppError::InternalServerError)?;
+
+    let claims = Claims {
+        sub: guest.id,
+        email: guest.email.clone(),
+        exp: expires_at.timestamp() as usize,
+        iat: clock.now().timestamp() as usize,
+        guest: true,
+        act: None,
+        auth_time: None,
+        acr: None,
+        grants: Grants::default(),
+    };
+
+    sign_claims(&claims, keys)
+}
+
+/// A token for `actor` to act as `subject`, carrying both identities.
+pub fn create_impersonation_token(
+    subject: &User,
+    actor: UserId,
+    keys: &JwtKeys,
+    ttl_secs: i64,
+    clock: &dyn Clock,
+) -> AppResult<String> {
+    let now = clock.now();
+    let expires_at = now + Duration::seconds(ttl_secs);
+
+    let claims = Claims {
+        sub: subject.id,
+        email: subject.email.clone(),
+        exp: expires_at.timestamp() as usize,
+        iat: now.timestamp() as usize,
+        guest: subject.is_guest,
+        act: Some(Actor { sub: actor }),
+        auth_time: None,
+        acr: None,
+        grants: Grants::default(),
+    };
+
+    sign_claims(&claims, keys)
+}
+
+fn sign_claims(claims: &Claims, keys: &JwtKeys) -> AppResult<String> {
+    let header = Header {
+        kid: Some(keys.active_kid().to_string()),
+        ..Header::default()
+    };
+
+    let token = encode(&header, claims, &keys.encoding_key())?;
+
     Ok(token)
 }
 
-pub fn decode_jwt_token(token: &str, secret: &str) -> AppResult<Claims> {
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    )?;
-    
+/// Expiry is checked against `clock` rather than by `jsonwebtoken`, which
+/// always reads the system time.
+pub fn decode_jwt_token(token: &str, keys: &JwtKeys, clock: &dyn Clock) -> AppResult<Claims> {
+    let header = decode_header(token)?;
+    let decoding_key = keys
+        .decoding_key(header.kid.as_deref())
+        .ok_or(AppError::Unauthorized)?;
+
+    let mut validation = Validation::default();
+    validation.validate_exp = false;
+    let token_data = decode::<Claims>(token, &decoding_key, &validation)?;
+
+    // Same leeway jsonwebtoken would allow
+    let exp = token_data.claims.exp as i64;
+    if exp + (validation.leeway as i64) < clock.now().timestamp() {
+        return Err(jsonwebtoken::errors::Error::from(ErrorKind::ExpiredSignature).into());
+    }
+
     Ok(token_data.claims)
 }
\ No newline at end of file
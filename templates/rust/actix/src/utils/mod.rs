@@ -1,5 +1,20 @@
+pub mod api_token;
+pub mod fingerprint;
 pub mod jwt;
-pub mod hash;
+pub mod net;
+pub mod phone;
+pub mod request_signing;
+pub mod signing;
+pub mod trace_context;
 
-pub use jwt::{create_jwt_token, decode_jwt_token};
-pub use hash::{hash_password, verify_password};
\ No newline at end of file
+pub use api_token::{api_token_prefix, generate_api_token, is_api_token};
+pub use fingerprint::{hash_fingerprint, ip_subnet};
+pub use jwt::{create_guest_token, create_impersonation_token, create_jwt_token, create_step_up_token, decode_jwt_token, JwtKeys};
+pub use net::ip_in_cidr;
+pub use phone::{is_e164, mask_phone_number, validate_e164};
+pub use request_signing::{is_signed_request, SignatureHeader};
+pub use signing::SigningKeys;
+pub use trace_context::with_trace_context;
+
+pub use platform_auth::{generate_secret, hash_password, hash_token, verify_password, HashedPassword};
+pub use platform_core::clock::{Clock, MockClock, SharedClock, SystemClock};
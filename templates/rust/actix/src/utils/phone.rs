@@ -0,0 +1,32 @@
+use validator::ValidationError;
+
+/// Whether `number` is in E.164 format: `+`, a country code not starting
+/// with 0 and at most 15 digits in total, without separators.
+pub fn is_e164(number: &str) -> bool {
+    let Some(digits) = number.strip_prefix('+') else {
+        return false;
+    };
+    (2..=15).contains(&digits.len())
+        && !digits.starts_with('0')
+        && digits.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+/// `validator` adapter for [`is_e164`].
+pub fn validate_e164(number: &str) -> Result<(), ValidationError> {
+    if is_e164(number) {
+        Ok(())
+    } else {
+        let mut error = ValidationError::new("e164");
+        error.message = Some("Phone number must be in E.164 format, e.g. +14155550123".into());
+        Err(error)
+    }
+}
+
+/// `+14155550123` as `+*******0123`, for logs.
+pub fn mask_phone_number(number: &str) -> String {
+    let visible = number.len().saturating_sub(4);
+    number
+        .char_indices()
+        .map(|(index, c)| if index == 0 || index >= visible { c } else { '*' })
+        .collect()
+}
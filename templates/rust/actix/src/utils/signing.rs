@@ -0,0 +1,269 @@
+//! Signed and encrypted payloads for values handed to clients and expected
+//! back unchanged: email links, presigned internal URLs and cookie values.
+//!
+//! Every payload is bound to a `purpose`, so a token minted for one flow
+//! can't be replayed in another, and carries its own expiry. Tokens name the
+//! key they were produced with, so keys can be rotated like the JWT keys.
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Duration;
+use config::ConfigError;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::SigningSettings;
+use crate::errors::{AppError, AppResult};
+use crate::utils::{JwtKeys, SharedClock};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const NONCE_LENGTH: usize = 12;
+const DERIVATION_CONTEXT: &[u8] = b"devxplatform-signing-v1";
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    purpose: String,
+    exp: i64,
+    data: T,
+}
+
+struct SigningKey {
+    mac_key: [u8; 32],
+    cipher_key: [u8; 32],
+}
+
+impl SigningKey {
+    fn new(secret: &[u8]) -> Self {
+        Self {
+            mac_key: Sha256::new().chain_update(b"mac:").chain_update(secret).finalize().into(),
+            cipher_key: Sha256::new().chain_update(b"enc:").chain_update(secret).finalize().into(),
+        }
+    }
+
+    fn mac(&self, message: &[u8]) -> HmacSha256 {
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(&self.mac_key).expect("HMAC accepts any key length");
+        mac.update(message);
+        mac
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(&self.cipher_key.into())
+    }
+}
+
+/// Versioned keys for signing and encrypting payloads.
+#[derive(Clone)]
+pub struct SigningKeys {
+    active_kid: String,
+    keys: Arc<HashMap<String, SigningKey>>,
+    clock: SharedClock,
+}
+
+impl SigningKeys {
+    /// Uses the configured signing keys, or derives one per JWT key (with
+    /// domain separation) when none are configured.
+    pub fn from_settings(
+        settings: &SigningSettings,
+        jwt_keys: &JwtKeys,
+        clock: SharedClock,
+    ) -> Result<Self, ConfigError> {
+        if settings.keys.is_empty() {
+            let keys = jwt_keys
+                .secrets()
+                .map(|(kid, secret)| {
+                    let derived = <HmacSha256 as Mac>::new_from_slice(secret.as_bytes())
+                        .expect("HMAC accepts any key length")
+                        .chain_update(DERIVATION_CONTEXT)
+                        .finalize()
+                        .into_bytes();
+                    (kid.to_string(), SigningKey::new(&derived))
+                })
+                .collect();
+            return Ok(Self {
+                active_kid: jwt_keys.active_kid().to_string(),
+                keys: Arc::new(keys),
+                clock,
+            });
+        }
+
+        let keys: HashMap<String, SigningKey> = settings
+            .keys
+            .iter()
+            .map(|key| (key.kid.clone(), SigningKey::new(key.secret.as_bytes())))
+            .collect();
+
+        let active_kid = settings.active_kid.clone().ok_or_else(|| {
+            ConfigError::Message("signing.active_kid is required when signing.keys is set".into())
+        })?;
+
+        if !keys.contains_key(&active_kid) {
+            return Err(ConfigError::Message(format!(
+                "signing.active_kid '{}' does not match any configured key",
+                active_kid
+            )));
+        }
+
+        Ok(Self {
+            active_kid,
+            keys: Arc::new(keys),
+            clock,
+        })
+    }
+
+    /// Produces `<kid>.<payload>.<signature>`; the payload is readable by
+    /// the client but can't be altered.
+    pub fn sign<T: Serialize>(&self, purpose: &str, data: &T, ttl: Duration) -> AppResult<String> {
+        let payload = URL_SAFE_NO_PAD.encode(self.envelope(purpose, data, ttl)?);
+        let signed = format!("{}.{}", self.active_kid, payload);
+        let signature = self.active_key().mac(signed.as_bytes()).finalize().into_bytes();
+
+        Ok(format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(signature)))
+    }
+
+    pub fn verify<T: DeserializeOwned>(&self, purpose: &str, token: &str) -> AppResult<T> {
+        let (signed, signature) = token.rsplit_once('.').ok_or(AppError::Unauthorized)?;
+        let (kid, payload) = signed.split_once('.').ok_or(AppError::Unauthorized)?;
+        let key = self.keys.get(kid).ok_or(AppError::Unauthorized)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| AppError::Unauthorized)?;
+
+        // Constant-time comparison
+        key.mac(signed.as_bytes())
+            .verify_slice(&signature)
+            .map_err(|_| AppError::Unauthorized)?;
+
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| AppError::Unauthorized)?;
+        self.open_envelope(purpose, &payload)
+    }
+
+    /// Produces `<kid>.<nonce + ciphertext>`; the payload is opaque to the
+    /// client and authenticated by AES-256-GCM.
+    pub fn encrypt<T: Serialize>(&self, purpose: &str, data: &T, ttl: Duration) -> AppResult<String> {
+        let plaintext = self.envelope(purpose, data, ttl)?;
+
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let ciphertext = self
+            .active_key()
+            .cipher()
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: self.active_kid.as_bytes(),
+                },
+            )
+            .map_err(|_| AppError::InternalServerError)?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+
+        Ok(format!("{}.{}", self.active_kid, URL_SAFE_NO_PAD.encode(sealed)))
+    }
+
+    pub fn decrypt<T: DeserializeOwned>(&self, purpose: &str, token: &str) -> AppResult<T> {
+        let (kid, sealed) = token.split_once('.').ok_or(AppError::Unauthorized)?;
+        let key = self.keys.get(kid).ok_or(AppError::Unauthorized)?;
+        let sealed = URL_SAFE_NO_PAD.decode(sealed).map_err(|_| AppError::Unauthorized)?;
+
+        if sealed.len() < NONCE_LENGTH {
+            return Err(AppError::Unauthorized);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+
+        let plaintext = key
+            .cipher()
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: kid.as_bytes(),
+                },
+            )
+            .map_err(|_| AppError::Unauthorized)?;
+
+        self.open_envelope(purpose, &plaintext)
+    }
+
+    /// Appends `expires`, `kid` and `signature` query parameters to
+    /// `path_and_query`, presigning it for `ttl`.
+    pub fn sign_url(&self, path_and_query: &str, ttl: Duration) -> String {
+        let separator = if path_and_query.contains('?') { '&' } else { '?' };
+        let unsigned = format!(
+            "{}{}expires={}&kid={}",
+            path_and_query,
+            separator,
+            (self.clock.now() + ttl).timestamp(),
+            self.active_kid
+        );
+        let signature = self.active_key().mac(unsigned.as_bytes()).finalize().into_bytes();
+
+        format!("{}&signature={}", unsigned, URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    /// Verifies a URL produced by [`SigningKeys::sign_url`]; `signature`
+    /// must be its last query parameter.
+    pub fn verify_url(&self, path_and_query: &str) -> AppResult<()> {
+        let (unsigned, signature) = path_and_query
+            .rsplit_once("&signature=")
+            .ok_or(AppError::Unauthorized)?;
+        let query = unsigned.split_once('?').map(|(_, query)| query).unwrap_or_default();
+        let param = |name: &str| {
+            query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .filter(|(key, _)| *key == name)
+                .map(|(_, value)| value)
+                .next_back()
+        };
+
+        let kid = param("kid").ok_or(AppError::Unauthorized)?;
+        let key = self.keys.get(kid).ok_or(AppError::Unauthorized)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| AppError::Unauthorized)?;
+
+        // Constant-time comparison
+        key.mac(unsigned.as_bytes())
+            .verify_slice(&signature)
+            .map_err(|_| AppError::Unauthorized)?;
+
+        let expires: i64 = param("expires")
+            .and_then(|expires| expires.parse().ok())
+            .ok_or(AppError::Unauthorized)?;
+        if expires <= self.clock.now().timestamp() {
+            return Err(AppError::Unauthorized);
+        }
+
+        Ok(())
+    }
+
+    fn active_key(&self) -> &SigningKey {
+        &self.keys[&self.active_kid]
+    }
+
+    fn envelope<T: Serialize>(&self, purpose: &str, data: &T, ttl: Duration) -> AppResult<Vec<u8>> {
+        serde_json::to_vec(&Envelope {
+            purpose: purpose.to_string(),
+            exp: (self.clock.now() + ttl).timestamp(),
+            data,
+        })
+        .map_err(|_| AppError::InternalServerError)
+    }
+
+    fn open_envelope<T: DeserializeOwned>(&self, purpose: &str, bytes: &[u8]) -> AppResult<T> {
+        let envelope: Envelope<T> = serde_json::from_slice(bytes).map_err(|_| AppError::Unauthorized)?;
+
+        if envelope.purpose != purpose || envelope.exp <= self.clock.now().timestamp() {
+            return Err(AppError::Unauthorized);
+        }
+
+        Ok(envelope.data)
+    }
+}
@@ -0,0 +1,27 @@
+use platform_observability::otel::{self, Injector};
+use platform_observability::propagation;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::RequestBuilder;
+
+/// Headers of an outgoing request, written by [`otel::inject`].
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(&value)) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+/// Adds the current span's `traceparent` and the mesh headers of the
+/// request being handled (see [`Propagation`](crate::middleware::Propagation))
+/// to a request to another of the platform's services, so its spans join
+/// the trace. Leave it off requests to third parties.
+pub fn with_trace_context(request: RequestBuilder) -> RequestBuilder {
+    let mut headers = HeaderMap::new();
+    propagation::inject(&mut HeaderInjector(&mut headers));
+    // With otel on, this service's span replaces the caller's as the parent
+    otel::inject(&tracing::Span::current(), &mut HeaderInjector(&mut headers));
+    request.headers(headers)
+}
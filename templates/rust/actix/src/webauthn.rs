@@ -0,0 +1,53 @@
+//! Passkeys with [webauthn-rs](https://docs.rs/webauthn-rs).
+//!
+//! Registration and login are two-step ceremonies: the server issues a
+//! challenge, the browser has an authenticator answer it, and the server
+//! checks the answer against the state it kept from the first step. That
+//! state is a [`Ceremony`] stored in `webauthn_ceremonies`, so either step
+//! may hit any instance and a challenge can be answered only once.
+//!
+//! Logins use discoverable credentials: the browser offers the passkeys it
+//! holds for the relying party, so no email is asked for and the login
+//! challenge doesn't reveal whether an account exists.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use config::ConfigError;
+use serde::{Deserialize, Serialize};
+use webauthn_rs::prelude::{DiscoverableAuthentication, PasskeyRegistration, Url, WebauthnError};
+use webauthn_rs::{Webauthn, WebauthnBuilder};
+
+use crate::config::WebauthnSettings;
+use crate::errors::{AppError, ErrorCode};
+
+/// Builds the relying party from `webauthn.*`.
+pub fn from_settings(settings: &WebauthnSettings) -> Result<Webauthn, ConfigError> {
+    let invalid = |e: WebauthnError| ConfigError::Message(format!("invalid webauthn settings: {}", e));
+    let origin = Url::parse(&settings.rp_origin)
+        .map_err(|e| ConfigError::Message(format!("webauthn.rp_origin is not a URL: {}", e)))?;
+
+    WebauthnBuilder::new(&settings.rp_id, &origin)
+        .map_err(invalid)?
+        .rp_name(&settings.rp_name)
+        .build()
+        .map_err(invalid)
+}
+
+/// Server-side state of a ceremony between its two steps.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", content = "state", rename_all = "snake_case")]
+pub enum Ceremony {
+    Registration(PasskeyRegistration),
+    Authentication(DiscoverableAuthentication),
+}
+
+/// The text form credential IDs are stored and looked up by.
+pub fn encode_credential_id(credential_id: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(credential_id)
+}
+
+/// Maps a rejected authenticator response; the details only go to the log.
+pub fn verification_failed(error: WebauthnError) -> AppError {
+    tracing::warn!("WebAuthn verification failed: {}", error);
+    AppError::Unauthorized.with_code(ErrorCode::PasskeyVerificationFailed)
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+
+use actix_template::dry_run::DryRun;
+use actix_template::errors::ErrorCode;
+use actix_template::factories::UserFactory;
+use actix_template::jobs;
+use actix_template::models::push_device::{PushPlatform, RegisterPushDevice};
+use common::{assert_status, authorized, TestApp};
+
+// One test, so no other test's worker picks up these jobs
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn accounts_are_deleted_in_the_background() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let token = app.token_for_user(&user);
+    let device = RegisterPushDevice {
+        platform: PushPlatform::Fcm,
+        token: format!("device-{}", user.id),
+    };
+    app.state.push_service.register_device(user.id, device, DryRun::default()).await.unwrap();
+
+    let request = authorized(TestRequest::delete().uri(&format!("/api/v1/users/{}", user.id)), &token);
+    assert_status(app.request(request).await, StatusCode::ACCEPTED);
+
+    jobs::worker(&app.state).drain().await.unwrap();
+
+    let error = app.state.user_queries.get_user_by_id(user.id).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::UserNotFound);
+    assert!(app.state.push_service.list_devices(user.id).await.unwrap().is_empty());
+
+    let deleted: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM audit_events WHERE event_type = 'account.deleted' AND metadata->>'user_id' = $1",
+    )
+    .bind(user.id.to_string())
+    .fetch_one(&app.state.db)
+    .await
+    .unwrap();
+    assert_eq!(deleted, 1);
+}
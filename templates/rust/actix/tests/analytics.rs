@@ -0,0 +1,129 @@
+mod common;
+
+use arrow_array::{Array, BooleanArray, RecordBatch, StringArray};
+use arrow_schema::DataType;
+use chrono::Duration;
+use futures_util::TryStreamExt;
+use object_store::path::Path;
+use parquet::arrow::async_reader::ParquetObjectReader;
+use parquet::arrow::ParquetRecordBatchStreamBuilder;
+
+use actix_template::config::DatasetSettings;
+use actix_template::factories::UserFactory;
+use actix_template::jobs;
+use common::{test_epoch, TestApp};
+
+#[test]
+fn datasets_export_a_table_or_a_query() {
+    let dataset = |table: Option<&str>, query: Option<&str>| DatasetSettings {
+        table: table.map(str::to_string),
+        query: query.map(str::to_string),
+        interval_secs: 60,
+    };
+
+    assert_eq!(dataset(Some("users"), None).select().as_deref(), Some(r#"SELECT * FROM "users""#));
+    assert_eq!(
+        dataset(None, Some(" SELECT id FROM users; ")).select().as_deref(),
+        Some("SELECT id FROM users")
+    );
+    assert_eq!(dataset(Some("users"), Some("SELECT 1")).select(), None);
+    assert_eq!(dataset(None, None).select(), None);
+}
+
+#[actix_web::test]
+#[should_panic(expected = "needs exactly one of table and query")]
+async fn misconfigured_datasets_are_rejected_at_startup() {
+    TestApp::spawn_with(|config| config.set_override("analytics.datasets.broken.interval_secs", 60).unwrap()).await;
+}
+
+async fn read_export(app: &TestApp, path: &str) -> Vec<RecordBatch> {
+    let reader = ParquetObjectReader::new(app.state.object_store.clone(), Path::from(path));
+    let stream = ParquetRecordBatchStreamBuilder::new(reader).await.unwrap().build().unwrap();
+    stream.try_collect().await.unwrap()
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn due_datasets_are_exported_as_parquet() {
+    let objects = std::env::temp_dir().join(format!("analytics-{}", uuid::Uuid::new_v4()));
+    let app = TestApp::spawn_with(|config| {
+        config
+            .set_override("object_storage.path", objects.to_str().unwrap())
+            .and_then(|config| {
+                config.set_override(
+                    "analytics.datasets.analytics_signups.query",
+                    "SELECT username, is_verified, created_at FROM users WHERE username LIKE 'analytics_%'",
+                )
+            })
+            .and_then(|config| config.set_override("analytics.datasets.analytics_signups.interval_secs", 3600))
+            .unwrap()
+    })
+    .await;
+    app.insert_user(UserFactory::build().username("analytics_one")).await;
+    app.insert_user(UserFactory::build().username("analytics_two").verified()).await;
+
+    // Due as soon as configured, then once per interval
+    assert_eq!(app.state.analytics.schedule_due().await.unwrap(), ["analytics_signups"]);
+    assert!(app.state.analytics.schedule_due().await.unwrap().is_empty());
+    jobs::worker(&app.state).drain().await.unwrap();
+
+    let (path, rows, error): (String, i64, Option<String>) = sqlx::query_as(
+        "SELECT last_path, last_rows, last_error FROM analytics_exports WHERE dataset = 'analytics_signups'",
+    )
+    .fetch_one(&app.state.db)
+    .await
+    .unwrap();
+    assert_eq!(
+        path,
+        "analytics/analytics_signups/date=2024-01-01/analytics_signups-20240101T000000Z.parquet"
+    );
+    assert_eq!((rows, error), (2, None));
+
+    let batches = read_export(&app, &path).await;
+    let batch = &batches[0];
+    let usernames = batch.column_by_name("username").unwrap().as_any().downcast_ref::<StringArray>().unwrap();
+    let verified = batch.column_by_name("is_verified").unwrap().as_any().downcast_ref::<BooleanArray>().unwrap();
+    let mut exported: Vec<(&str, bool)> =
+        (0..batch.num_rows()).map(|i| (usernames.value(i), verified.value(i))).collect();
+    exported.sort();
+    assert_eq!(exported, [("analytics_one", false), ("analytics_two", true)]);
+    assert!(matches!(batch.column_by_name("created_at").unwrap().data_type(), DataType::Timestamp(..)));
+
+    app.clock.advance(Duration::hours(1));
+    assert_eq!(app.state.analytics.schedule_due().await.unwrap(), ["analytics_signups"]);
+    jobs::worker(&app.state).drain().await.unwrap();
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn exports_cannot_write() {
+    let app = TestApp::spawn_with(|config| {
+        config
+            .set_override("analytics.datasets.analytics_sneaky.query", "SELECT nextval('analytics_sneaky')")
+            .and_then(|config| config.set_override("analytics.datasets.analytics_sneaky.interval_secs", 3600))
+            .unwrap()
+    })
+    .await;
+    sqlx::query("CREATE SEQUENCE IF NOT EXISTS analytics_sneaky")
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO analytics_exports (dataset, next_run_at) VALUES ('analytics_sneaky', $1)")
+        .bind(test_epoch())
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+
+    app.state.analytics.export("analytics_sneaky", test_epoch()).await.unwrap_err();
+    let error: Option<String> =
+        sqlx::query_scalar("SELECT last_error FROM analytics_exports WHERE dataset = 'analytics_sneaky'")
+            .fetch_one(&app.state.db)
+            .await
+            .unwrap();
+    assert!(error.unwrap().contains("read-only transaction"));
+    let called: bool = sqlx::query_scalar("SELECT is_called FROM analytics_sneaky")
+        .fetch_one(&app.state.db)
+        .await
+        .unwrap();
+    assert!(!called);
+}
@@ -0,0 +1,527 @@
+//! Schema-aware fuzzing of every operation in the OpenAPI document: each
+//! parameter and body field is sent with values of the wrong type, at and
+//! past its bounds, or left out, and secured operations are called without
+//! valid credentials. Whatever the input, the answer must be a structured
+//! error (or a success, for boundary values that turn out valid), never a
+//! 5xx, and it must come promptly.
+//!
+//! Probes are derived from the document, so new endpoints are covered once
+//! they are documented. Without `TEST_DATABASE_URL`, inputs that pass
+//! validation fail on the database; those `DATABASE_ERROR`s are expected.
+
+mod common;
+
+use actix_web::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use actix_web::http::{Method, StatusCode};
+use actix_web::test::TestRequest;
+use futures_util::{future, stream, StreamExt};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+use uuid::Uuid;
+
+use common::{assert_status, TestApp, TestResponse};
+
+/// A request taking longer than this counts as hung.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Probes in flight at once.
+const CONCURRENCY: usize = 16;
+
+/// Length of the overlong strings sent, within the JSON body limit.
+const LONG: usize = 10_000;
+
+const METHODS: &[&str] = &["get", "put", "post", "delete", "patch"];
+
+struct Operation {
+    method: Method,
+    path: String,
+    parameters: Vec<Parameter>,
+    body: Option<Value>,
+    secured: bool,
+}
+
+struct Parameter {
+    name: String,
+    location: String,
+    required: bool,
+    schema: Value,
+}
+
+#[derive(Clone)]
+enum Payload {
+    Json(Value),
+    Raw(&'static str, String),
+}
+
+/// The parts of a request a probe varies.
+#[derive(Clone, Default)]
+struct Parts {
+    path: BTreeMap<String, String>,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    body: Option<Payload>,
+}
+
+/// Sets query parameter or header `name`, replacing any value it had.
+fn set(list: &mut Vec<(String, String)>, name: &str, value: String) {
+    list.retain(|(existing, _)| existing != name);
+    list.push((name.to_string(), value));
+}
+
+struct Probe {
+    operation: String,
+    mutation: String,
+    method: Method,
+    uri: String,
+    parts: Parts,
+    /// The status the probe must get, for credential probes.
+    expected: Option<StatusCode>,
+}
+
+impl Probe {
+    fn new(operation: &Operation, mutation: impl Into<String>, parts: Parts) -> Self {
+        let mut uri = operation.path.clone();
+        for (name, value) in &parts.path {
+            uri = uri.replace(&format!("{{{}}}", name), value);
+        }
+        if !parts.query.is_empty() {
+            let query: Vec<String> = parts.query.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+            uri = format!("{}?{}", uri, query.join("&"));
+        }
+
+        Self {
+            operation: format!("{} {}", operation.method, operation.path),
+            mutation: mutation.into(),
+            method: operation.method.clone(),
+            uri,
+            parts,
+            expected: None,
+        }
+    }
+
+    fn expecting(mut self, status: StatusCode) -> Self {
+        self.expected = Some(status);
+        self
+    }
+
+    fn request(&self) -> TestRequest {
+        let mut request = TestRequest::default().method(self.method.clone()).uri(&self.uri);
+        for (name, value) in &self.parts.headers {
+            request = request.insert_header((name.as_str(), value.as_str()));
+        }
+        match &self.parts.body {
+            Some(Payload::Json(value)) => request.set_json(value),
+            Some(Payload::Raw(content_type, payload)) => {
+                request.insert_header((CONTENT_TYPE, *content_type)).set_payload(payload.clone())
+            }
+            None => request,
+        }
+    }
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} with {}", self.operation, self.mutation)
+    }
+}
+
+async fn spec(app: &TestApp) -> Value {
+    let response = app.request(TestRequest::get().uri("/api-docs/openapi.json")).await;
+    assert_status(response, StatusCode::OK).body
+}
+
+/// Follows `$ref`s, and takes the first alternative of a composition.
+fn resolve(spec: &Value, schema: &Value) -> Value {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.trim_start_matches("#/components/schemas/");
+        return resolve(spec, &spec["components"]["schemas"][name]);
+    }
+    for composition in ["allOf", "oneOf", "anyOf"] {
+        if let Some(first) = schema[composition].as_array().and_then(|alternatives| alternatives.first()) {
+            return resolve(spec, first);
+        }
+    }
+    schema.clone()
+}
+
+fn schema_type(schema: &Value) -> &str {
+    match &schema["type"] {
+        Value::String(kind) => kind.as_str(),
+        Value::Array(kinds) => kinds.iter().filter_map(Value::as_str).find(|kind| *kind != "null").unwrap_or(""),
+        _ if schema["properties"].is_object() => "object",
+        _ => "",
+    }
+}
+
+fn operations(spec: &Value) -> Vec<Operation> {
+    let mut operations = Vec::new();
+    for (path, item) in spec["paths"].as_object().unwrap() {
+        for (method, operation) in item.as_object().unwrap() {
+            if !METHODS.contains(&method.as_str()) {
+                continue;
+            }
+            let parameters = operation["parameters"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|parameter| Parameter {
+                    name: parameter["name"].as_str().unwrap().to_string(),
+                    location: parameter["in"].as_str().unwrap().to_string(),
+                    required: parameter["required"].as_bool().unwrap_or(false),
+                    schema: resolve(spec, &parameter["schema"]),
+                })
+                .collect();
+            let body = &operation["requestBody"]["content"]["application/json"]["schema"];
+            let secured = operation["security"].as_array().is_some_and(|requirements| {
+                let nonempty = |requirement: &Value| requirement.as_object().is_some_and(|schemes| !schemes.is_empty());
+                requirements.iter().any(nonempty)
+            });
+
+            operations.push(Operation {
+                method: method.to_uppercase().parse().unwrap(),
+                path: path.clone(),
+                parameters,
+                body: (!body.is_null()).then(|| resolve(spec, body)),
+                secured,
+            });
+        }
+    }
+    operations
+}
+
+/// A valid value for `schema`; IDs are `id`, so paths name the caller.
+fn example(spec: &Value, schema: &Value, id: &str) -> Value {
+    let schema = resolve(spec, schema);
+    if schema["format"] == "uuid" {
+        return json!(id);
+    }
+    if let Some(example) = schema.get("example") {
+        return example.clone();
+    }
+    if let Some(first) = schema["enum"].as_array().and_then(|variants| variants.first()) {
+        return first.clone();
+    }
+    match schema_type(&schema) {
+        "string" => match schema["format"].as_str() {
+            Some("email") => json!("fuzz@example.com"),
+            Some("date-time") => json!("2024-01-01T00:00:00Z"),
+            Some("date") => json!("2024-01-01"),
+            _ => json!("f".repeat(schema["minLength"].as_u64().unwrap_or(8).max(1) as usize)),
+        },
+        "integer" => json!(schema["minimum"].as_f64().map_or(1, |minimum| minimum as i64)),
+        "number" => json!(schema["minimum"].as_f64().unwrap_or(1.0)),
+        "boolean" => json!(false),
+        "array" => json!([example(spec, &schema["items"], id)]),
+        _ => Value::Object(
+            schema["properties"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(name, property)| (name.clone(), example(spec, property, id)))
+                .collect(),
+        ),
+    }
+}
+
+/// Body values of the wrong type, or at and past the schema's bounds.
+fn invalid_values(spec: &Value, schema: &Value) -> Vec<Value> {
+    let schema = resolve(spec, schema);
+    let mut values = match schema_type(&schema) {
+        "integer" => vec![
+            json!("1"),
+            json!(true),
+            json!(1.5),
+            json!(-1),
+            json!(0),
+            json!(i64::MAX),
+            json!(i64::MIN),
+            json!(u64::MAX),
+            json!(1e308),
+        ],
+        "number" => vec![json!("1"), json!(true), json!(-1e308), json!(1e308)],
+        "boolean" => vec![json!("true"), json!(1)],
+        "array" => vec![json!({}), json!("fuzz"), json!([null])],
+        "object" => vec![json!([]), json!("fuzz")],
+        _ => vec![
+            json!(0),
+            json!(true),
+            json!([]),
+            json!({}),
+            json!(""),
+            json!("f".repeat(LONG)),
+            json!("fuzz\u{0000}fuzz"),
+            json!("\u{202e}\u{fffd}"),
+            json!("'; DROP TABLE users; --"),
+        ],
+    };
+    values.push(Value::Null);
+
+    if let Some(minimum) = schema["minimum"].as_f64() {
+        values.push(json!(minimum as i64 - 1));
+    }
+    if let Some(maximum) = schema["maximum"].as_f64() {
+        values.push(json!(maximum as i64 + 1));
+    }
+    if let Some(min_length) = schema["minLength"].as_u64().filter(|length| *length > 0) {
+        values.push(json!("f".repeat(min_length as usize - 1)));
+    }
+    if let Some(max_length) = schema["maxLength"].as_u64() {
+        values.push(json!("f".repeat(max_length as usize + 1)));
+    }
+    if schema["enum"].is_array() {
+        values.push(json!("not-a-variant"));
+    }
+    match schema["format"].as_str() {
+        Some("uuid") => values.push(json!("not-a-uuid")),
+        Some("email") => values.extend([json!("not-an-email"), json!(format!("{}@example.com", "f".repeat(LONG)))]),
+        Some("date-time") => values.push(json!("yesterday")),
+        _ => {}
+    }
+    values
+}
+
+/// Parameter values of the wrong type or out of bounds, percent-encoded
+/// for paths and query strings and left out of headers where they can't be
+/// sent.
+fn invalid_parameters(parameter: &Parameter) -> Vec<String> {
+    let schema = &parameter.schema;
+    let mut values: Vec<String> = match schema_type(schema) {
+        "integer" | "number" => {
+            ["abc", "-1", "0", "1.5", "99999999999999999999", "1e400", "NaN"].map(String::from).to_vec()
+        }
+        "boolean" => ["maybe", "2"].map(String::from).to_vec(),
+        _ => vec![
+            "f".repeat(LONG),
+            "%00".to_string(),
+            "%FF%FE".to_string(),
+            "..%2F..%2Fetc%2Fpasswd".to_string(),
+            "'%20OR%201=1--".to_string(),
+        ],
+    };
+    if parameter.location != "path" {
+        values.push(String::new());
+    }
+
+    if let Some(minimum) = schema["minimum"].as_f64() {
+        values.push((minimum as i64 - 1).to_string());
+    }
+    if let Some(maximum) = schema["maximum"].as_f64() {
+        values.push((maximum as i64 + 1).to_string());
+    }
+    if schema["enum"].is_array() {
+        values.push("not-a-variant".to_string());
+    }
+    if schema["format"].as_str() == Some("uuid") {
+        values.extend(["not-a-uuid", "00000000-0000-0000-0000-00000000000g"].map(String::from));
+    }
+
+    if parameter.location == "header" {
+        values.retain(|value| !value.contains('%'));
+    }
+    values
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::String(string) if string.len() > 32 => format!("<{} chars>", string.chars().count()),
+        value => value.to_string(),
+    }
+}
+
+/// A request every mutation starts from: required parameters and the body
+/// set to valid values, and a token if the operation needs one.
+fn baseline(spec: &Value, operation: &Operation, token: &str, id: &str) -> Parts {
+    let mut parts = Parts::default();
+    for parameter in &operation.parameters {
+        let value = match example(spec, &parameter.schema, id) {
+            Value::String(value) => value,
+            value => value.to_string(),
+        };
+        match parameter.location.as_str() {
+            "path" => {
+                parts.path.insert(parameter.name.clone(), value);
+            }
+            "query" if parameter.required => parts.query.push((parameter.name.clone(), value)),
+            "header" if parameter.required => parts.headers.push((parameter.name.clone(), value)),
+            _ => {}
+        }
+    }
+    // Path parameters the document leaves out
+    for segment in operation.path.split('/') {
+        if let Some(name) = segment.strip_prefix('{').and_then(|segment| segment.strip_suffix('}')) {
+            parts.path.entry(name.to_string()).or_insert_with(|| id.to_string());
+        }
+    }
+    if operation.secured {
+        parts.headers.push((AUTHORIZATION.to_string(), format!("Bearer {}", token)));
+    }
+    if let Some(body) = &operation.body {
+        parts.body = Some(Payload::Json(example(spec, body, id)));
+    }
+    parts
+}
+
+/// Probes for calling a secured operation without valid credentials.
+fn credential_probes(spec: &Value, operation: &Operation, id: &str) -> Vec<Probe> {
+    if !operation.secured {
+        return Vec::new();
+    }
+    let mut base = baseline(spec, operation, "", id);
+    base.headers.retain(|(name, _)| name != AUTHORIZATION.as_str());
+
+    let mut probes = vec![Probe::new(operation, "no credentials", base.clone())];
+    for value in [
+        "Bearer ",
+        "Bearer not-a-jwt",
+        // An unsigned token
+        "Bearer eyJhbGciOiJub25lIiwidHlwIjoiSldUIn0.eyJzdWIiOiJmdXp6In0.",
+        "Basic ZnV6ejpmdXp6",
+        "bearer",
+    ] {
+        let mut parts = base.clone();
+        set(&mut parts.headers, AUTHORIZATION.as_str(), value.to_string());
+        probes.push(Probe::new(operation, format!("Authorization: {}", value), parts));
+    }
+    probes.into_iter().map(|probe| probe.expecting(StatusCode::UNAUTHORIZED)).collect()
+}
+
+/// Probes for malformed parameters and bodies, made with valid credentials.
+fn input_probes(spec: &Value, operation: &Operation, token: &str, id: &str) -> Vec<Probe> {
+    let base = baseline(spec, operation, token, id);
+    let mut probes = Vec::new();
+
+    for parameter in &operation.parameters {
+        for value in invalid_parameters(parameter) {
+            let mut parts = base.clone();
+            match parameter.location.as_str() {
+                "path" => {
+                    parts.path.insert(parameter.name.clone(), value.clone());
+                }
+                "query" => set(&mut parts.query, &parameter.name, value.clone()),
+                "header" => set(&mut parts.headers, &parameter.name, value.clone()),
+                _ => continue,
+            }
+            let mutation = format!("{} {} = {}", parameter.location, parameter.name, describe(&json!(value)));
+            probes.push(Probe::new(operation, mutation, parts));
+        }
+    }
+
+    let Some(schema) = &operation.body else {
+        return probes;
+    };
+    let Some(Payload::Json(valid)) = &base.body else {
+        return probes;
+    };
+    let with_body = |body: Payload| Parts {
+        body: Some(body),
+        ..base.clone()
+    };
+
+    for (mutation, body) in [
+        ("malformed JSON", Payload::Raw("application/json", "{\"".to_string())),
+        ("an empty body", Payload::Raw("application/json", String::new())),
+        ("deeply nested JSON", Payload::Raw("application/json", "[".repeat(LONG))),
+        ("a text/plain body", Payload::Raw("text/plain", valid.to_string())),
+    ] {
+        probes.push(Probe::new(operation, mutation, with_body(body)));
+    }
+    for value in [json!([]), json!("fuzz"), json!(0), Value::Null, json!({})] {
+        probes.push(Probe::new(operation, format!("body = {}", value), with_body(Payload::Json(value))));
+    }
+
+    let required: Vec<&str> = schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+    for (name, property) in schema["properties"].as_object().into_iter().flatten() {
+        if required.contains(&name.as_str()) {
+            let mut body = valid.clone();
+            body.as_object_mut().unwrap().remove(name);
+            probes.push(Probe::new(operation, format!("no body.{}", name), with_body(Payload::Json(body))));
+        }
+        for value in invalid_values(spec, property) {
+            let mutation = format!("body.{} = {}", name, describe(&value));
+            let mut body = valid.clone();
+            body[name] = value;
+            probes.push(Probe::new(operation, mutation, with_body(Payload::Json(body))));
+        }
+    }
+    probes
+}
+
+/// Checks that `probe` got an answer in time and, if it was an error, that
+/// it was a structured client error. `database` says whether queries can
+/// succeed.
+fn check(probe: &Probe, response: Option<TestResponse>, database: bool) -> Result<(), String> {
+    let response = response.ok_or_else(|| format!("{}: no response within {:?}", probe, TIMEOUT))?;
+    let (status, body) = (response.status, &response.body);
+
+    if let Some(expected) = probe.expected.filter(|expected| *expected != status) {
+        return Err(format!("{}: expected {}, got {}: {}", probe, expected, status, body));
+    }
+    if !status.is_client_error() && !status.is_server_error() {
+        return Ok(());
+    }
+
+    let structured = body["code"] == status.as_u16() && body["error_code"].is_string() && body["message"].is_string();
+    if !structured {
+        return Err(format!("{}: {} without a structured error: {}", probe, status, body));
+    }
+    let database_down = matches!(body["error_code"].as_str(), Some("DATABASE_ERROR" | "SERVICE_UNAVAILABLE"));
+    if status.is_server_error() && (database || !database_down) {
+        return Err(format!("{}: {}", probe, body));
+    }
+    Ok(())
+}
+
+/// Sends every probe, a few at a time, and lists the failures.
+async fn run(app: &TestApp, probes: &[Probe]) -> Vec<String> {
+    let database = std::env::var("TEST_DATABASE_URL").is_ok();
+    stream::iter(probes)
+        .map(|probe| async move {
+            let response = tokio::time::timeout(TIMEOUT, app.request(probe.request())).await.ok();
+            check(probe, response, database)
+        })
+        .buffer_unordered(CONCURRENCY)
+        .filter_map(|result| future::ready(result.err()))
+        .collect()
+        .await
+}
+
+/// The app under fuzzing. Load shedding would answer the concurrent probes
+/// with 503s of its own, so it's off.
+async fn spawn() -> TestApp {
+    TestApp::spawn_with(|config| config.set_override("concurrency_limit.enabled", false).unwrap()).await
+}
+
+#[actix_web::test]
+async fn secured_operations_reject_missing_and_invalid_credentials() {
+    let app = spawn().await;
+    let spec = spec(&app).await;
+    let id = Uuid::new_v4().to_string();
+
+    let probes: Vec<Probe> = operations(&spec)
+        .iter()
+        .flat_map(|operation| credential_probes(&spec, operation, &id))
+        .collect();
+    assert!(!probes.is_empty(), "no secured operations are documented");
+
+    let failures = run(&app, &probes).await;
+    assert!(failures.is_empty(), "{} of {} probes failed:\n{}", failures.len(), probes.len(), failures.join("\n"));
+}
+
+#[actix_web::test]
+async fn malformed_inputs_get_structured_errors() {
+    let app = spawn().await;
+    let spec = spec(&app).await;
+    let user_id = Uuid::new_v4();
+    let token = app.token_for(user_id);
+
+    let probes: Vec<Probe> = operations(&spec)
+        .iter()
+        .flat_map(|operation| input_probes(&spec, operation, &token, &user_id.to_string()))
+        .collect();
+    assert!(!probes.is_empty(), "no operations take input");
+
+    let failures = run(&app, &probes).await;
+    assert!(failures.is_empty(), "{} of {} probes failed:\n{}", failures.len(), probes.len(), failures.join("\n"));
+}
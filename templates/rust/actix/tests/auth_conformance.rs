@@ -0,0 +1,279 @@
+//! Security invariants of authentication, asserted end to end.
+//!
+//! Tests marked `#[ignore]` need Postgres (`TEST_DATABASE_URL`) and, for
+//! lockout, Redis (`TEST_REDIS_URL`); run them with `cargo test -- --ignored`.
+
+mod common;
+
+use actix_web::http::{Method, StatusCode};
+use actix_web::test::TestRequest;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Duration;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+use actix_template::factories::{UserFactory, FACTORY_PASSWORD};
+use actix_template::models::user::{LoginResponse, UserResponse};
+use common::{assert_status, authorized, TestApp};
+
+/// Every route under `/users` and `/tokens`, with a valid body where one is
+/// expected so that only authentication can reject it.
+fn protected_routes() -> Vec<(Method, String, Option<Value>)> {
+    let id = Uuid::from_u128(1);
+    vec![
+        (Method::GET, "/api/v1/users".to_string(), None),
+        (Method::GET, format!("/api/v1/users/{}", id), None),
+        (
+            Method::POST,
+            "/api/v1/users".to_string(),
+            Some(json!({ "email": "new@example.com", "username": "new", "password": "long enough" })),
+        ),
+        (Method::PUT, format!("/api/v1/users/{}", id), Some(json!({ "full_name": "New" }))),
+        (Method::PATCH, format!("/api/v1/users/{}", id), Some(json!({ "full_name": "New" }))),
+        (Method::DELETE, format!("/api/v1/users/{}", id), None),
+        (Method::GET, "/api/v1/tokens".to_string(), None),
+        (Method::POST, "/api/v1/tokens".to_string(), Some(json!({ "name": "ci", "scopes": ["read:users"] }))),
+        (Method::DELETE, format!("/api/v1/tokens/{}", id), None),
+    ]
+}
+
+fn request(method: &Method, uri: &str, body: &Option<Value>) -> TestRequest {
+    let request = TestRequest::default().method(method.clone()).uri(uri);
+    match body {
+        Some(body) => request.set_json(body),
+        None => request,
+    }
+}
+
+/// Replaces segment `index` of a JWT.
+fn replace_segment(token: &str, index: usize, segment: &str) -> String {
+    let mut segments: Vec<&str> = token.split('.').collect();
+    segments[index] = segment;
+    segments.join(".")
+}
+
+/// Decodes, edits and re-encodes the payload of a JWT, keeping the
+/// original signature.
+fn edit_claims(token: &str, edit: impl FnOnce(&mut Value)) -> String {
+    let payload = token.split('.').nth(1).unwrap();
+    let mut claims: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap();
+    edit(&mut claims);
+    replace_segment(token, 1, &URL_SAFE_NO_PAD.encode(claims.to_string()))
+}
+
+#[actix_web::test]
+async fn every_protected_route_requires_a_token() {
+    let app = TestApp::spawn().await;
+
+    for (method, uri, body) in protected_routes() {
+        let response = app.request(request(&method, &uri, &body)).await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED, "{} {}", method, uri);
+    }
+}
+
+#[actix_web::test]
+async fn every_protected_route_rejects_a_malformed_token() {
+    let app = TestApp::spawn().await;
+
+    for (method, uri, body) in protected_routes() {
+        let response = app.request(authorized(request(&method, &uri, &body), "not-a-jwt")).await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED, "{} {}", method, uri);
+    }
+}
+
+#[actix_web::test]
+async fn non_bearer_schemes_are_rejected() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::from_u128(1));
+
+    let response = app
+        .request(TestRequest::get().uri("/api/v1/users").insert_header(("Authorization", token)))
+        .await;
+
+    assert_status(response, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn expired_token_is_rejected() {
+    let app = TestApp::spawn().await;
+    let user_id = Uuid::from_u128(1);
+    let token = app.token_for(user_id);
+
+    // Still authenticated just before expiry, so deleting another user gets
+    // as far as the ownership check
+    app.clock.advance(Duration::minutes(59));
+    let response = app
+        .request(authorized(TestRequest::delete().uri(&format!("/api/v1/users/{}", Uuid::from_u128(2))), &token))
+        .await;
+    assert_status(response, StatusCode::FORBIDDEN);
+
+    // Leeway is a minute, so two hours is well past it
+    app.clock.advance(Duration::hours(2));
+    for (method, uri, body) in protected_routes() {
+        let response = app.request(authorized(request(&method, &uri, &body), &token)).await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED, "{} {}", method, uri);
+    }
+}
+
+#[actix_web::test]
+async fn tampered_signature_is_rejected() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::from_u128(1));
+
+    let signature = token.split('.').nth(2).unwrap();
+    let mut bytes = URL_SAFE_NO_PAD.decode(signature).unwrap();
+    bytes[0] ^= 0x01;
+    let tampered = replace_segment(&token, 2, &URL_SAFE_NO_PAD.encode(bytes));
+
+    let response = app.request(authorized(TestRequest::get().uri("/api/v1/users"), &tampered)).await;
+    assert_status(response, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn tampered_claims_are_rejected() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::from_u128(1));
+
+    let escalated = edit_claims(&token, |claims| claims["sub"] = json!(Uuid::from_u128(2)));
+    let extended = edit_claims(&token, |claims| claims["exp"] = json!(u32::MAX));
+
+    for token in [escalated, extended] {
+        let response = app.request(authorized(TestRequest::get().uri("/api/v1/users"), &token)).await;
+        assert_status(response, StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[actix_web::test]
+async fn unsigned_token_is_rejected() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::from_u128(1));
+
+    let header = URL_SAFE_NO_PAD.encode(json!({ "alg": "none", "typ": "JWT" }).to_string());
+    let unsigned = replace_segment(&replace_segment(&token, 0, &header), 2, "");
+
+    let response = app.request(authorized(TestRequest::get().uri("/api/v1/users"), &unsigned)).await;
+    assert_status(response, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn token_signed_with_another_secret_is_rejected() {
+    let app = TestApp::spawn().await;
+    let other = TestApp::spawn_with(|config| config.set_override("jwt.secret", "another-secret").unwrap()).await;
+    let token = other.token_for(Uuid::from_u128(1));
+
+    let response = app.request(authorized(TestRequest::get().uri("/api/v1/users"), &token)).await;
+    assert_status(response, StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn password_hash_is_never_serialized() {
+    let user = UserFactory::build().into_user();
+    let hash = user.password_hash.as_str().to_string();
+
+    let bodies = [
+        serde_json::to_string(&user).unwrap(),
+        serde_json::to_string(&UserResponse::from(user.clone())).unwrap(),
+        serde_json::to_string(&LoginResponse {
+            access_token: String::new(),
+            refresh_token: String::new(),
+            token_type: "Bearer".to_string(),
+            expires_in: 3600,
+            user: user.into(),
+        })
+        .unwrap(),
+    ];
+
+    for body in bodies {
+        assert!(!body.contains("password"), "{}", body);
+        assert!(!body.contains(&hash), "{}", body);
+    }
+}
+
+fn client() -> SocketAddr {
+    "203.0.113.7:40000".parse().unwrap()
+}
+
+async fn login(app: &TestApp, email: &str, password: &str) -> common::TestResponse {
+    app.request(
+        TestRequest::post()
+            .uri("/api/v1/auth/login")
+            .peer_addr(client())
+            .set_json(json!({ "email": email, "password": password })),
+    )
+    .await
+}
+
+async fn refresh(app: &TestApp, refresh_token: &str) -> common::TestResponse {
+    app.request(
+        TestRequest::post()
+            .uri("/api/v1/auth/refresh")
+            .peer_addr(client())
+            .set_json(json!({ "refresh_token": refresh_token })),
+    )
+    .await
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn responses_never_contain_the_password() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+
+    let response = assert_status(login(&app, user.email.as_str(), FACTORY_PASSWORD).await, StatusCode::OK);
+    let token = response.body["access_token"].as_str().unwrap().to_string();
+    let fetched = app
+        .request(authorized(TestRequest::get().uri(&format!("/api/v1/users/{}", user.id)), &token))
+        .await;
+
+    for body in [response.body.to_string(), fetched.body.to_string()] {
+        assert!(!body.contains("password"), "{}", body);
+        assert!(!body.contains(user.password_hash.as_str()), "{}", body);
+    }
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn refresh_token_is_single_use() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+
+    let response = assert_status(login(&app, user.email.as_str(), FACTORY_PASSWORD).await, StatusCode::OK);
+    let first = response.body["refresh_token"].as_str().unwrap().to_string();
+
+    let rotated = assert_status(refresh(&app, &first).await, StatusCode::OK);
+    let second = rotated.body["refresh_token"].as_str().unwrap().to_string();
+    assert_ne!(first, second);
+
+    // Replaying the used token is taken as theft and revokes the family,
+    // including the token it was rotated into
+    let replayed = assert_status(refresh(&app, &first).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(replayed.body["error_code"], "AUTH_REFRESH_TOKEN_REUSED");
+    assert_status(refresh(&app, &second).await, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL and TEST_REDIS_URL"]
+async fn account_locks_after_repeated_failures() {
+    let redis_url = std::env::var("TEST_REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+    let app = TestApp::spawn_with(|config| {
+        config
+            .set_override("redis.url", redis_url.as_str())
+            .and_then(|config| config.set_override("auth_throttle.enabled", true))
+            .and_then(|config| config.set_override("auth_throttle.account_failure_limit", 3))
+            .and_then(|config| config.set_override("auth_throttle.delay_after_failures", 100))
+            .unwrap()
+    })
+    .await;
+    let user = app.insert_user(UserFactory::build()).await;
+
+    for _ in 0..3 {
+        assert_status(login(&app, user.email.as_str(), "wrong password").await, StatusCode::UNAUTHORIZED);
+    }
+
+    // Locked: even the right password is refused until the window ends
+    let locked = assert_status(login(&app, user.email.as_str(), FACTORY_PASSWORD).await, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(locked.body["error_code"], "AUTH_TOO_MANY_ATTEMPTS");
+    assert!(locked.headers.contains_key("retry-after"));
+}
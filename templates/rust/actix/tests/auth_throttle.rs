@@ -0,0 +1,53 @@
+use redis::AsyncCommands;
+use std::net::{IpAddr, Ipv6Addr};
+use uuid::Uuid;
+
+use actix_template::config::AuthThrottleSettings;
+use actix_template::services::AuthThrottleService;
+
+fn redis_url() -> String {
+    std::env::var("TEST_REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string())
+}
+
+fn settings() -> AuthThrottleSettings {
+    AuthThrottleSettings {
+        enabled: true,
+        window_secs: 900,
+        ip_limit: 100,
+        account_failure_limit: 10,
+        delay_after_failures: 3,
+        delay_step_ms: 500,
+        max_delay_ms: 5000,
+        trusted_gateways: vec![],
+    }
+}
+
+/// An address no other test counts against.
+fn fresh_ip() -> IpAddr {
+    IpAddr::V6(Ipv6Addr::from(Uuid::new_v4().as_u128()))
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_REDIS_URL"]
+async fn counters_always_expire() {
+    let throttle = AuthThrottleService::new(&redis_url(), settings()).await.unwrap();
+    let mut redis = redis::Client::open(redis_url()).unwrap().get_multiplexed_async_connection().await.unwrap();
+
+    let ip = fresh_ip();
+    let key = format!("auth_throttle:ip:{}:requests", ip);
+    for _ in 0..3 {
+        throttle.hit_ip(ip).await.unwrap();
+        let ttl: i64 = redis.ttl(&key).await.unwrap();
+        assert!((1..=900).contains(&ttl), "ttl {}", ttl);
+    }
+    let count: u64 = redis.get(&key).await.unwrap();
+    assert_eq!(count, 3);
+
+    // A counter left without an expiry gets one on its next hit
+    let ip = fresh_ip();
+    let key = format!("auth_throttle:ip:{}:requests", ip);
+    redis.set::<_, _, ()>(&key, 5).await.unwrap();
+    throttle.hit_ip(ip).await.unwrap();
+    let ttl: i64 = redis.ttl(&key).await.unwrap();
+    assert!((1..=900).contains(&ttl), "ttl {}", ttl);
+}
@@ -0,0 +1,225 @@
+//! Test kit for exercising routes end to end without binding ports: every
+//! request is served by the full route table against a test `AppState`.
+//!
+//! The database pool is lazy and points nowhere, and auth throttling is
+//! disabled so Redis isn't needed; tests should stick to code paths that
+//! don't query the database unless `TEST_DATABASE_URL` is set. Egress may
+//! reach private addresses, so outbound clients can call local mock servers.
+
+#![allow(dead_code)]
+
+use actix_web::http::header::{HeaderMap, AUTHORIZATION};
+use actix_web::http::StatusCode;
+use actix_web::{body, test, web, App};
+use chrono::{DateTime, TimeZone, Utc};
+use config::builder::{ConfigBuilder, DefaultState};
+use platform_core::domain::{Email, UserId};
+use sqlx::postgres::PgPoolOptions;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_template::config::{EgressSettings, Settings};
+use actix_template::egress::EgressPolicy;
+use actix_template::factories::UserFactory;
+use actix_template::handlers;
+use actix_template::models::role::{Grants, ROLE_ADMIN, USERS_DELETE, USERS_READ, USERS_WRITE};
+use actix_template::models::user::User;
+use actix_template::security;
+use actix_template::middleware::Pipeline;
+use actix_template::utils::{create_jwt_token, MockClock};
+use actix_template::AppState;
+
+pub const TEST_JWT_SECRET: &str = "test-secret";
+
+/// The instant the test clock starts at.
+pub fn test_epoch() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+}
+
+/// What the `admin` role grants, as the migrations seed it.
+pub fn admin_grants() -> Grants {
+    Grants {
+        roles: vec![ROLE_ADMIN.to_string()],
+        permissions: [USERS_READ, USERS_WRITE, USERS_DELETE].map(String::from).to_vec(),
+    }
+}
+
+/// An egress policy that lets clients reach local mock servers.
+pub fn local_egress() -> EgressPolicy {
+    EgressPolicy::new(EgressSettings {
+        allowed_schemes: vec!["http".to_string(), "https".to_string()],
+        allowed_ports: vec![],
+        allowed_hosts: vec![],
+        blocked_cidrs: vec![],
+        allowed_cidrs: vec![],
+        allow_private: true,
+        timeout_secs: 10,
+    })
+}
+
+/// Application state shared by the requests of one test.
+pub struct TestApp {
+    pub state: web::Data<AppState>,
+    pub clock: Arc<MockClock>,
+}
+
+/// A response with its body parsed as JSON. Empty bodies are `Null` and
+/// non-JSON bodies are kept as a string.
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: serde_json::Value,
+}
+
+impl TestApp {
+    pub async fn spawn() -> Self {
+        Self::spawn_with(|config| config).await
+    }
+
+    /// Spawns the app with extra configuration, e.g.
+    /// `config.set_override("maintenance.enabled", true)`.
+    pub async fn spawn_with(
+        configure: impl FnOnce(ConfigBuilder<DefaultState>) -> ConfigBuilder<DefaultState>,
+    ) -> Self {
+        let database_url =
+            std::env::var("TEST_DATABASE_URL").unwrap_or_else(|_| "postgres://127.0.0.1:1/test".to_string());
+        let objects = std::env::temp_dir().join("actix-template-objects");
+
+        let config = Settings::defaults()
+            .and_then(|config| config.set_override("jwt.secret", TEST_JWT_SECRET))
+            .and_then(|config| config.set_override("database.url", database_url.as_str()))
+            .and_then(|config| config.set_override("redis.url", "redis://127.0.0.1:1"))
+            .and_then(|config| config.set_override("auth_throttle.enabled", false))
+            .and_then(|config| config.set_override("object_storage.path", objects.to_str().unwrap()))
+            .and_then(|config| config.set_override("egress.allow_private", true))
+            .expect("valid test configuration");
+        let settings: Settings = configure(config)
+            .build()
+            .and_then(|config| config.try_deserialize())
+            .expect("valid test settings");
+
+        let db = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_secs(1))
+            .connect_lazy(&settings.database.url)
+            .expect("valid database url");
+        let clock = Arc::new(MockClock::new(test_epoch()));
+        let state = AppState::new(db, settings, clock.clone()).await.expect("app state");
+
+        Self {
+            state: web::Data::new(state),
+            clock,
+        }
+    }
+
+    /// Serves `request` with the same app data, middleware and routes as
+    /// the server.
+    pub async fn request(&self, request: test::TestRequest) -> TestResponse {
+        self.serve(request, handlers::routes).await
+    }
+
+    /// Serves `request` as an admin listener would.
+    pub async fn admin_request(&self, request: test::TestRequest) -> TestResponse {
+        self.serve(request, handlers::admin_routes).await
+    }
+
+    /// Serves `request` with `routes` in place of the server's, for routes
+    /// only a test defines.
+    pub async fn serve(&self, request: test::TestRequest, routes: fn(&mut web::ServiceConfig)) -> TestResponse {
+        let app = test::init_service(
+            App::new()
+                .app_data(self.state.clone())
+                .configure(security::defaults(&self.state.settings))
+                .wrap(Pipeline::from_settings(&self.state.settings).expect("invalid server.middleware"))
+                .configure(routes),
+        )
+        .await;
+
+        // Errors raised by middleware are rendered as the server would
+        let response = match test::try_call_service(&app, request.to_request()).await {
+            Ok(response) => response.into_parts().1.map_into_boxed_body(),
+            Err(error) => error.error_response(),
+        };
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = body::to_bytes(response.into_body()).await.expect("response body");
+        let body = if bytes.is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::from_slice(&bytes)
+                .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+        };
+
+        TestResponse { status, headers, body }
+    }
+
+    /// Inserts a factory-built user; needs `TEST_DATABASE_URL`.
+    pub async fn insert_user(&self, factory: UserFactory) -> User {
+        factory.insert(&self.state.db).await.expect("insert user")
+    }
+
+    /// A valid access token for `user`.
+    pub fn token_for_user(&self, user: &User) -> String {
+        self.token_with_grants(user, Grants::default())
+    }
+
+    /// A valid access token for `user` carrying `grants`, whatever roles
+    /// the database has them hold.
+    pub fn token_with_grants(&self, user: &User, grants: Grants) -> String {
+        create_jwt_token(user.id, &user.email, grants, &self.state.jwt_keys, 1, self.state.clock.as_ref())
+            .expect("token")
+    }
+
+    /// A valid access token for a (not necessarily existing) user.
+    pub fn token_for(&self, user_id: impl Into<UserId>) -> String {
+        self.token_for_with_grants(user_id, Grants::default())
+    }
+
+    /// A valid access token for a (not necessarily existing) admin.
+    pub fn admin_token_for(&self, user_id: impl Into<UserId>) -> String {
+        self.token_for_with_grants(user_id, admin_grants())
+    }
+
+    fn token_for_with_grants(&self, user_id: impl Into<UserId>, grants: Grants) -> String {
+        create_jwt_token(
+            user_id.into(),
+            &Email::parse("test@example.com").unwrap(),
+            grants,
+            &self.state.jwt_keys,
+            1,
+            self.state.clock.as_ref(),
+        )
+        .expect("token")
+    }
+
+    /// Inserts a user holding the admin role and returns them with a token
+    /// carrying it; needs `TEST_DATABASE_URL`.
+    pub async fn insert_admin(&self) -> (User, String) {
+        let admin = self.insert_user(UserFactory::build()).await;
+        self.state.role_service.grant(admin.id, ROLE_ADMIN, None).await.expect("grant admin");
+        let grants = self.state.role_service.grants_of(admin.id).await.expect("admin grants");
+        let token = self.token_with_grants(&admin, grants);
+        (admin, token)
+    }
+}
+
+impl TestResponse {
+    /// Status and body, for snapshotting the wire format.
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "status": self.status.as_u16(),
+            "body": self.body,
+        })
+    }
+}
+
+/// Adds a bearer token to `request`.
+pub fn authorized(request: test::TestRequest, token: &str) -> test::TestRequest {
+    request.insert_header((AUTHORIZATION, format!("Bearer {}", token)))
+}
+
+/// Asserts the response status and returns the response for further checks.
+#[track_caller]
+pub fn assert_status(response: TestResponse, status: StatusCode) -> TestResponse {
+    assert_eq!(response.status, status, "unexpected response: {}", response.body);
+    response
+}
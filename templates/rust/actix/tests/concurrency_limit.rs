@@ -0,0 +1,100 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use config::{File, FileFormat};
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_template::concurrency_limit::AdaptiveLimiter;
+use actix_template::config::{ConcurrencyLimitSettings, LimitAlgorithm};
+use common::{assert_status, TestApp};
+
+fn limiter(algorithm: LimitAlgorithm) -> Arc<AdaptiveLimiter> {
+    Arc::new(AdaptiveLimiter::new(ConcurrencyLimitSettings {
+        enabled: true,
+        algorithm,
+        initial_limit: 10,
+        min_limit: 2,
+        max_limit: 20,
+        latency_threshold_ms: 100,
+        backoff_ratio: 0.5,
+        tolerance: 2.0,
+        smoothing: 0.5,
+        retry_after_secs: 1,
+    }))
+}
+
+/// Runs `concurrency` requests at once `rounds` times, each taking `latency`.
+fn load(limiter: &Arc<AdaptiveLimiter>, concurrency: usize, rounds: usize, latency: Duration) {
+    for _ in 0..rounds {
+        let permits: Vec<_> = (0..concurrency).filter_map(|_| limiter.try_acquire()).collect();
+        for permit in permits {
+            permit.finish(latency, false);
+        }
+    }
+}
+
+#[test]
+fn aimd_grows_while_saturated_and_backs_off_when_slow() {
+    let limiter = limiter(LimitAlgorithm::Aimd);
+
+    // A few requests at a time don't need a higher limit
+    load(&limiter, 2, 5, Duration::from_millis(10));
+    assert_eq!(limiter.limit(), 10);
+
+    // One more per request finishing while at least half the limit was busy
+    load(&limiter, 20, 1, Duration::from_millis(10));
+    assert_eq!(limiter.limit(), 16);
+
+    let permit = limiter.try_acquire().unwrap();
+    permit.finish(Duration::from_millis(500), false);
+    assert_eq!(limiter.limit(), 8);
+    let permit = limiter.try_acquire().unwrap();
+    permit.finish(Duration::from_millis(10), true);
+    assert_eq!(limiter.limit(), 4);
+    load(&limiter, 1, 1, Duration::from_secs(1));
+    assert_eq!(limiter.limit(), 2);
+}
+
+#[test]
+fn gradient_shrinks_when_latency_rises() {
+    let limiter = limiter(LimitAlgorithm::Gradient);
+    load(&limiter, 10, 20, Duration::from_millis(10));
+    let settled = limiter.limit();
+    assert!(settled >= 10, "{}", settled);
+
+    // Latency far beyond the long-term average shrinks the limit until
+    // little more than the allowance for a queue is left
+    load(&limiter, 20, 10, Duration::from_millis(200));
+    assert_eq!(limiter.limit(), 4);
+    assert_eq!(limiter.inflight(), 0);
+}
+
+#[test]
+fn requests_beyond_the_limit_are_rejected() {
+    let limiter = limiter(LimitAlgorithm::Gradient);
+    let permits: Vec<_> = (0..10).map(|_| limiter.try_acquire().unwrap()).collect();
+    assert!(limiter.try_acquire().is_none());
+
+    // Abandoned requests free their place without a sample
+    drop(permits);
+    assert_eq!(limiter.inflight(), 0);
+    assert_eq!(limiter.limit(), 10);
+}
+
+#[actix_web::test]
+async fn overloaded_scopes_answer_503() {
+    let settings = "[concurrency_limit]\ninitial_limit = 1\nmin_limit = 1\nmax_limit = 1";
+    let app = TestApp::spawn_with(|config| config.add_source(File::from_str(settings, FileFormat::Toml))).await;
+    let held = app.state.concurrency_limiter.try_acquire().unwrap();
+
+    let response = assert_status(app.request(TestRequest::get().uri("/api/v1/users")).await, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.body["error_code"], "OVERLOADED");
+    assert!(response.headers.contains_key("retry-after"));
+    // Health checks aren't limited
+    assert_status(app.request(TestRequest::get().uri("/api/v1/health")).await, StatusCode::OK);
+
+    drop(held);
+    assert_status(app.request(TestRequest::get().uri("/api/v1/users")).await, StatusCode::UNAUTHORIZED);
+}
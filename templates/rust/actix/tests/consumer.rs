@@ -0,0 +1,111 @@
+mod common;
+
+use chrono::Duration;
+
+use actix_template::consumer::{Consumed, IdempotentConsumer};
+use actix_template::errors::AppError;
+use common::TestApp;
+use uuid::Uuid;
+
+fn consumer(app: &TestApp, name: &str) -> IdempotentConsumer {
+    IdempotentConsumer::new(app.state.db.clone(), name, app.clock.clone())
+}
+
+/// Records a delivery of `message_id` in `deliveries`, in the handler's
+/// transaction.
+async fn deliver(consumer: &IdempotentConsumer, message_id: &str, fail: bool) -> Result<Consumed<()>, AppError> {
+    let message_id = message_id.to_string();
+    consumer
+        .consume(&message_id.clone(), move |conn| {
+            Box::pin(async move {
+                sqlx::query("INSERT INTO consumer_test_deliveries (message_id) VALUES ($1)")
+                    .bind(&message_id)
+                    .execute(&mut *conn)
+                    .await?;
+                if fail {
+                    return Err(AppError::Conflict("handler failed".to_string()));
+                }
+                Ok(())
+            })
+        })
+        .await
+}
+
+async fn deliveries(app: &TestApp, message_id: &str) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM consumer_test_deliveries WHERE message_id = $1")
+        .bind(message_id)
+        .fetch_one(&app.state.db)
+        .await
+        .unwrap()
+}
+
+async fn spawn() -> TestApp {
+    let app = TestApp::spawn().await;
+    sqlx::query("CREATE TABLE IF NOT EXISTS consumer_test_deliveries (message_id TEXT NOT NULL)")
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+    app
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn redeliveries_are_skipped() {
+    let app = spawn().await;
+    let invoices = consumer(&app, "invoices");
+    let message_id = Uuid::new_v4().to_string();
+
+    assert_eq!(deliver(&invoices, &message_id, false).await.unwrap(), Consumed::Processed(()));
+    assert_eq!(deliver(&invoices, &message_id, false).await.unwrap(), Consumed::Duplicate);
+    assert_eq!(deliveries(&app, &message_id).await, 1);
+
+    // Another consumer of the same message handles it too
+    let emails = consumer(&app, "emails");
+    assert_eq!(deliver(&emails, &message_id, false).await.unwrap(), Consumed::Processed(()));
+    assert_eq!(deliveries(&app, &message_id).await, 2);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn failed_deliveries_are_handled_again() {
+    let app = spawn().await;
+    let invoices = consumer(&app, "invoices");
+    let message_id = Uuid::new_v4().to_string();
+
+    assert!(deliver(&invoices, &message_id, true).await.is_err());
+    assert_eq!(deliveries(&app, &message_id).await, 0);
+
+    assert_eq!(deliver(&invoices, &message_id, false).await.unwrap(), Consumed::Processed(()));
+    assert_eq!(deliveries(&app, &message_id).await, 1);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn concurrent_deliveries_run_the_handler_once() {
+    let app = spawn().await;
+    let invoices = consumer(&app, "invoices");
+    let message_id = Uuid::new_v4().to_string();
+
+    let (first, second) =
+        futures_util::join!(deliver(&invoices, &message_id, false), deliver(&invoices, &message_id, false));
+
+    let mut outcomes = [first.unwrap(), second.unwrap()];
+    outcomes.sort_by_key(|outcome| *outcome == Consumed::Duplicate);
+    assert_eq!(outcomes, [Consumed::Processed(()), Consumed::Duplicate]);
+    assert_eq!(deliveries(&app, &message_id).await, 1);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn processed_ids_expire_with_their_retention_policy() {
+    let app = spawn().await;
+    let invoices = consumer(&app, "invoices");
+    let message_id = Uuid::new_v4().to_string();
+    deliver(&invoices, &message_id, false).await.unwrap();
+
+    let ttl_days = app.state.settings.retention.policies["processed_messages"].ttl_days;
+    app.clock.advance(Duration::days(ttl_days as i64 + 1));
+    app.state.retention.enforce("processed_messages").await.unwrap();
+
+    assert_eq!(deliver(&invoices, &message_id, false).await.unwrap(), Consumed::Processed(()));
+}
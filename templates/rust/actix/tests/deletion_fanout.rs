@@ -0,0 +1,195 @@
+//! Deletions finishing only once the downstream services in
+//! `deletion_fanout.services` confirm, as workloads verified against the
+//! key sets in `fixtures/workload`.
+
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use chrono::Duration;
+use config::{File, FileFormat};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use actix_template::errors::ErrorCode;
+use actix_template::factories::UserFactory;
+use actix_template::saga::deletion_fanout::{ConsoleDeletionPublisher, DeletionFanout};
+use common::{assert_status, authorized, test_epoch, TestApp};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/workload");
+const CLUSTER: &str = "https://kubernetes.default.svc";
+
+async fn spawn() -> TestApp {
+    let settings = format!(
+        r#"
+        [workload_identity]
+        roles = [
+            {{ identity = "system:serviceaccount:billing:*", roles = ["user_deletions.ack"] }},
+        ]
+
+        [[workload_identity.issuers]]
+        kind = "kubernetes"
+        issuer = "{CLUSTER}"
+        audience = "actix-template"
+        keys = {{ path = "{FIXTURES}/kubernetes-jwks.json" }}
+
+        [deletion_fanout.services.invoices]
+        identity = "system:serviceaccount:billing:invoicer"
+
+        [deletion_fanout.services.payments]
+        identity = "system:serviceaccount:billing:payer"
+        "#
+    );
+    TestApp::spawn_with(|config| config.add_source(File::from_str(&settings, FileFormat::Toml))).await
+}
+
+fn service_account(name: &str) -> String {
+    let pem = std::fs::read(format!("{}/kubernetes.pem", FIXTURES)).unwrap();
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = Some("kube-1".to_string());
+    let claims = json!({
+        "iss": CLUSTER,
+        "sub": format!("system:serviceaccount:billing:{}", name),
+        "aud": ["actix-template"],
+        "exp": test_epoch().timestamp() + 600,
+    });
+    encode(&header, &claims, &EncodingKey::from_ec_pem(&pem).unwrap()).unwrap()
+}
+
+/// A fan-out publishing to an outbox the test can read.
+fn fanout(app: &TestApp) -> (DeletionFanout, Arc<ConsoleDeletionPublisher>) {
+    let publisher = Arc::new(ConsoleDeletionPublisher::default());
+    let fanout = DeletionFanout::new(
+        app.state.db.clone(),
+        app.state.settings.deletion_fanout.clone(),
+        publisher.clone(),
+        app.state.audit_service.clone(),
+        app.state.response_cache.clone(),
+        app.clock.clone(),
+    );
+    (fanout, publisher)
+}
+
+fn published_to(publisher: &ConsoleDeletionPublisher, deletion_id: Uuid) -> Vec<String> {
+    publisher
+        .outbox()
+        .into_iter()
+        .filter(|(_, event)| event.deletion_id == deletion_id)
+        .map(|(service, _)| service)
+        .collect()
+}
+
+fn acknowledge(deletion_id: Uuid, service_account_name: &str) -> TestRequest {
+    authorized(
+        TestRequest::post().uri(&format!("/api/v1/internal/user-deletions/{}/ack", deletion_id)),
+        &service_account(service_account_name),
+    )
+}
+
+async fn audited(app: &TestApp, event_type: &str, deletion_id: Uuid) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM audit_events WHERE event_type = $1 AND metadata->>'saga_id' = $2")
+        .bind(event_type)
+        .bind(deletion_id.to_string())
+        .fetch_one(&app.state.db)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn users_are_deleted_once_every_service_confirms() {
+    let app = spawn().await;
+    let (fanout, publisher) = fanout(&app);
+    let user = app.insert_user(UserFactory::build()).await;
+    let deletion_id = Uuid::new_v4();
+
+    fanout.request(deletion_id, user.id).await.unwrap();
+    // Requesting again, as a resumed saga does, doesn't publish again
+    fanout.request(deletion_id, user.id).await.unwrap();
+    assert_eq!(published_to(&publisher, deletion_id), ["invoices", "payments"]);
+
+    assert_status(app.request(acknowledge(deletion_id, "invoicer")).await, StatusCode::NO_CONTENT);
+    assert!(app.state.user_queries.get_user_by_id(user.id).await.is_ok());
+    let status = fanout.status(deletion_id).await.unwrap();
+    let confirmed: Vec<_> = status.acks.iter().map(|ack| (ack.service.as_str(), ack.confirmed_at.is_some())).collect();
+    assert_eq!(confirmed, [("invoices", true), ("payments", false)]);
+
+    assert_status(app.request(acknowledge(deletion_id, "payer")).await, StatusCode::NO_CONTENT);
+    let error = app.state.user_queries.get_user_by_id(user.id).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::UserNotFound);
+    assert_eq!(fanout.status(deletion_id).await.unwrap().deletion.status, "completed");
+    assert_eq!(audited(&app, "account.deleted", deletion_id).await, 1);
+
+    // Late duplicates are harmless
+    assert_status(app.request(acknowledge(deletion_id, "payer")).await, StatusCode::NO_CONTENT);
+    assert_eq!(audited(&app, "account.deleted", deletion_id).await, 1);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn laggards_are_reminded_then_left_for_manual_review() {
+    let app = spawn().await;
+    let (fanout, publisher) = fanout(&app);
+    let user = app.insert_user(UserFactory::build()).await;
+    let deletion_id = Uuid::new_v4();
+    fanout.request(deletion_id, user.id).await.unwrap();
+    fanout.acknowledge(deletion_id, "system:serviceaccount:billing:invoicer").await.unwrap();
+
+    fanout.run_due().await.unwrap();
+    assert_eq!(published_to(&publisher, deletion_id).len(), 2);
+    app.clock.advance(Duration::seconds(app.state.settings.deletion_fanout.retry_interval_secs));
+    fanout.run_due().await.unwrap();
+    assert_eq!(published_to(&publisher, deletion_id), ["invoices", "payments", "payments"]);
+
+    app.clock.advance(Duration::seconds(app.state.settings.deletion_fanout.timeout_secs));
+    fanout.run_due().await.unwrap();
+    let status = fanout.status(deletion_id).await.unwrap();
+    assert_eq!(status.deletion.status, "manual_review");
+    assert_eq!(status.acks[1].attempts, 2);
+    assert!(app.state.user_queries.get_user_by_id(user.id).await.is_ok());
+    assert_eq!(audited(&app, "account.deletion_stalled", deletion_id).await, 1);
+    // No more reminders once it's up to an admin
+    app.clock.advance(Duration::seconds(app.state.settings.deletion_fanout.retry_interval_secs));
+    fanout.run_due().await.unwrap();
+    assert_eq!(published_to(&publisher, deletion_id).len(), 3);
+
+    let (admin, token) = app.insert_admin().await;
+    let response = app
+        .admin_request(authorized(TestRequest::get().uri("/api/v1/admin/user-deletions?status=manual_review"), &token))
+        .await;
+    let response = assert_status(response, StatusCode::OK);
+    let listed = response.body["deletions"].as_array().unwrap();
+    let listed = listed.iter().find(|deletion| deletion["id"] == deletion_id.to_string()).unwrap();
+    assert_eq!(listed["acks"][1]["service"], "payments");
+    assert_eq!(listed["acks"][1]["confirmed_at"], serde_json::Value::Null);
+
+    let finalize = TestRequest::post().uri(&format!("/api/v1/admin/user-deletions/{}/finalize", deletion_id));
+    let response = assert_status(app.admin_request(authorized(finalize, &token)).await, StatusCode::OK);
+    assert_eq!(response.body["status"], "completed");
+    let error = app.state.user_queries.get_user_by_id(user.id).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::UserNotFound);
+    let finalized_by: Option<Uuid> = sqlx::query_scalar(
+        "SELECT user_id FROM audit_events WHERE event_type = 'account.deleted' AND metadata->>'saga_id' = $1",
+    )
+    .bind(deletion_id.to_string())
+    .fetch_one(&app.state.db)
+    .await
+    .unwrap();
+    assert_eq!(finalized_by, Some(admin.id.into()));
+}
+
+#[actix_web::test]
+async fn only_configured_services_confirm_deletions() {
+    let app = spawn().await;
+
+    // Granted the role, but no service confirms as it
+    let response = app.request(acknowledge(Uuid::new_v4(), "auditor")).await;
+    assert_status(response, StatusCode::FORBIDDEN);
+
+    let response = app
+        .request(TestRequest::post().uri(&format!("/api/v1/internal/user-deletions/{}/ack", Uuid::new_v4())))
+        .await;
+    assert_status(response, StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use serde_json::json;
+use uuid::Uuid;
+
+use actix_template::device::{parse, DeviceInfo, DeviceKind, Software};
+use actix_template::factories::{UserFactory, FACTORY_PASSWORD};
+use common::{assert_status, authorized, TestApp};
+
+const MAC_CHROME: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/114.0.0.0 Safari/537.36";
+const IPHONE_SAFARI: &str = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_4 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4 Mobile/15E148 Safari/604.1";
+const ANDROID_TABLET: &str = "Mozilla/5.0 (Linux; Android 13; SM-X710) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+const GOOGLEBOT: &str = "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)";
+
+fn software(name: &str, version: &str) -> Option<Software> {
+    Some(Software {
+        name: name.to_string(),
+        version: Some(version.to_string()),
+    })
+}
+
+#[test]
+fn user_agents_are_parsed_into_devices() {
+    let mac = parse(MAC_CHROME);
+    assert_eq!(mac.kind, DeviceKind::Desktop);
+    assert_eq!(mac.browser, software("Chrome", "114.0"));
+    assert_eq!(mac.os, software("Mac OS X", "10.15"));
+    assert_eq!(mac.label(), "Chrome 114.0 on Mac OS X 10.15");
+
+    let iphone = parse(IPHONE_SAFARI);
+    assert_eq!(iphone.kind, DeviceKind::Mobile);
+    assert_eq!(iphone.browser, software("Mobile Safari", "17.4"));
+    assert_eq!(iphone.device.as_deref(), Some("iPhone"));
+
+    assert_eq!(parse(ANDROID_TABLET).kind, DeviceKind::Tablet);
+    assert_eq!(parse(GOOGLEBOT).kind, DeviceKind::Bot);
+}
+
+#[test]
+fn unknown_user_agents_give_an_unknown_device() {
+    assert_eq!(parse(""), DeviceInfo::default());
+
+    let unknown = parse("curl-but-not-quite");
+    assert_eq!(unknown.kind, DeviceKind::Unknown);
+    assert!(unknown.os.is_none());
+    assert_eq!(DeviceInfo::default().label(), "Unknown device");
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn sessions_show_their_devices() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+
+    for user_agent in [MAC_CHROME, IPHONE_SAFARI] {
+        let request = TestRequest::post()
+            .uri("/api/v1/auth/login")
+            .insert_header(("User-Agent", user_agent))
+            .set_json(json!({ "email": user.email, "password": FACTORY_PASSWORD }));
+        assert_status(app.request(request).await, StatusCode::OK);
+    }
+
+    let request = authorized(TestRequest::get().uri("/api/v1/me/sessions"), &app.token_for_user(&user));
+    let sessions = assert_status(app.request(request).await, StatusCode::OK).body;
+    let sessions = sessions.as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    let labels: Vec<&str> = sessions.iter().map(|session| session["label"].as_str().unwrap()).collect();
+    assert!(labels.contains(&"Chrome 114.0 on Mac OS X 10.15"), "{:?}", labels);
+    let iphone = sessions.iter().find(|session| session["device"]["kind"] == "mobile").unwrap();
+    assert_eq!(iphone["device"]["device"], "iPhone");
+    assert!(iphone.get("user_agent").is_none());
+
+    // Other users' sessions aren't listed
+    let request = authorized(TestRequest::get().uri("/api/v1/me/sessions"), &app.token_for(Uuid::new_v4()));
+    let sessions = assert_status(app.request(request).await, StatusCode::OK).body;
+    assert_eq!(sessions, json!([]));
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn audit_events_record_the_device() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .insert_header(("User-Agent", IPHONE_SAFARI))
+        .set_json(json!({ "email": user.email, "password": FACTORY_PASSWORD }));
+    let refresh_token = assert_status(app.request(request).await, StatusCode::OK).body["refresh_token"].clone();
+
+    // Presenting a consumed token is audited
+    for _ in 0..2 {
+        let request = TestRequest::post()
+            .uri("/api/v1/auth/refresh")
+            .insert_header(("User-Agent", IPHONE_SAFARI))
+            .set_json(json!({ "refresh_token": refresh_token }));
+        app.request(request).await;
+    }
+
+    let (device,): (serde_json::Value,) = sqlx::query_as(
+        "SELECT device FROM audit_events WHERE user_id = $1 AND event_type = 'auth.refresh_token_reused'",
+    )
+    .bind(user.id)
+    .fetch_one(&app.state.db)
+    .await
+    .unwrap();
+    assert_eq!(device["kind"], "mobile");
+    assert_eq!(device["os"]["name"], "iOS");
+}
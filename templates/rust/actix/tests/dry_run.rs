@@ -0,0 +1,112 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use serde_json::json;
+use uuid::Uuid;
+
+use actix_template::dry_run::{DryRun, DRY_RUN_HEADER};
+use actix_template::errors::ErrorCode;
+use actix_template::factories::UserFactory;
+use common::{assert_status, authorized, TestApp};
+
+fn dry_run(request: TestRequest) -> Result<bool, ErrorCode> {
+    DryRun::from_request(&request.to_http_request())
+        .map(DryRun::is_dry_run)
+        .map_err(|e| e.code())
+}
+
+#[test]
+fn dry_runs_are_asked_for_in_the_query_or_a_header() {
+    assert_eq!(dry_run(TestRequest::post().uri("/api/v1/users")), Ok(false));
+    assert_eq!(dry_run(TestRequest::post().uri("/api/v1/users?dry_run=true")), Ok(true));
+    assert_eq!(dry_run(TestRequest::post().uri("/api/v1/users?page=2&dry_run=1")), Ok(true));
+    assert_eq!(dry_run(TestRequest::post().uri("/api/v1/users?dry_run=false")), Ok(false));
+    assert_eq!(dry_run(TestRequest::post().insert_header((DRY_RUN_HEADER, "TRUE"))), Ok(true));
+    assert_eq!(dry_run(TestRequest::post().insert_header((DRY_RUN_HEADER, "0"))), Ok(false));
+
+    // Either one asking is enough
+    let both = TestRequest::post().uri("/?dry_run=false").insert_header((DRY_RUN_HEADER, "true"));
+    assert_eq!(dry_run(both), Ok(true));
+}
+
+#[test]
+fn unclear_dry_run_values_are_refused() {
+    assert!(dry_run(TestRequest::post().uri("/api/v1/users?dry_run=yes")).is_err());
+    assert!(dry_run(TestRequest::post().uri("/api/v1/users?dry_run=")).is_err());
+    assert!(dry_run(TestRequest::post().insert_header((DRY_RUN_HEADER, "on"))).is_err());
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn dry_runs_answer_as_the_real_thing_without_changing_anything() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let token = app.token_for_user(&user);
+    let profile = format!("/api/v1/users/{}", user.id);
+
+    let update = authorized(TestRequest::patch().uri(&format!("{}?dry_run=true", profile)), &token)
+        .set_json(json!({ "full_name": "Dry Run" }));
+    let response = assert_status(app.request(update).await, StatusCode::OK);
+    assert_eq!(response.headers.get(DRY_RUN_HEADER).unwrap(), "true");
+    assert_eq!(response.body["full_name"], "Dry Run");
+    let stored = app.state.user_queries.get_user_by_id(user.id).await.unwrap();
+    assert_eq!(stored.full_name, user.full_name);
+
+    // Validation still applies
+    let invalid = authorized(TestRequest::patch().uri(&profile), &token)
+        .insert_header((DRY_RUN_HEADER, "true"))
+        .set_json(json!({ "email": "not-an-email" }));
+    assert_status(app.request(invalid).await, StatusCode::BAD_REQUEST);
+
+    // And so do permission checks
+    let other = format!("/api/v1/users/{}?dry_run=true", Uuid::new_v4());
+    let forbidden = authorized(TestRequest::delete().uri(&other), &token);
+    assert_status(app.request(forbidden).await, StatusCode::FORBIDDEN);
+
+    let device = json!({ "platform": "fcm", "token": format!("dry-run-{}", user.id) });
+    let register = authorized(TestRequest::post().uri("/api/v1/devices?dry_run=true"), &token).set_json(&device);
+    let response = assert_status(app.request(register).await, StatusCode::CREATED);
+    assert_eq!(response.body["platform"], "fcm");
+    assert!(app.state.push_service.list_devices(user.id).await.unwrap().is_empty());
+
+    let register = authorized(TestRequest::post().uri("/api/v1/devices"), &token).set_json(&device);
+    let response = assert_status(app.request(register).await, StatusCode::CREATED);
+    assert!(response.headers.get(DRY_RUN_HEADER).is_none());
+    let unregister = format!("/api/v1/devices/{}?dry_run=true", response.body["id"].as_str().unwrap());
+    let response = app.request(authorized(TestRequest::delete().uri(&unregister), &token)).await;
+    assert_status(response, StatusCode::NO_CONTENT);
+    assert_eq!(app.state.push_service.list_devices(user.id).await.unwrap().len(), 1);
+
+    // Account deletion stops at checking there is an account to delete
+    let delete = authorized(TestRequest::delete().uri(&format!("{}?dry_run=true", profile)), &token);
+    assert_status(app.request(delete).await, StatusCode::ACCEPTED);
+    app.state.user_queries.get_user_by_id(user.id).await.unwrap();
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn admin_dry_runs_leave_the_user_intact() {
+    let app = TestApp::spawn().await;
+    let (_, token) = app.insert_admin().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let uri = format!("/api/v1/admin/users/{}?dry_run=true", user.id);
+
+    let update = authorized(TestRequest::patch().uri(&uri), &token).set_json(json!({ "full_name": "Dry Run" }));
+    let response = assert_status(app.admin_request(update).await, StatusCode::OK);
+    assert_eq!(response.headers.get(DRY_RUN_HEADER).unwrap(), "true");
+    assert_eq!(response.body["full_name"], "Dry Run");
+
+    let delete = authorized(TestRequest::delete().uri(&uri), &token);
+    let response = assert_status(app.admin_request(delete).await, StatusCode::ACCEPTED);
+    assert_eq!(response.headers.get(DRY_RUN_HEADER).unwrap(), "true");
+
+    let stored = app.state.user_queries.get_user_by_id(user.id).await.unwrap();
+    assert_eq!(stored.full_name, user.full_name);
+    let deletions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sagas WHERE data->>'user_id' = $1")
+        .bind(user.id.to_string())
+        .fetch_one(&app.state.db)
+        .await
+        .unwrap();
+    assert_eq!(deletions, 0);
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use chrono::Duration;
+use serde_json::json;
+use std::sync::Arc;
+
+use actix_template::errors::ErrorCode;
+use actix_template::factories::UserFactory;
+use actix_template::mail::ConsoleMailer;
+use actix_template::models::magic_link::MagicLinkClaims;
+use actix_template::services::magic_link_service::MAGIC_LINK_PURPOSE;
+use actix_template::services::EmailVerificationService;
+use common::{assert_status, TestApp};
+
+fn verify(token: &str) -> TestRequest {
+    TestRequest::post()
+        .uri("/api/v1/auth/verify-email")
+        .set_json(json!({ "token": token }))
+}
+
+fn email_verification_service(app: &TestApp, mailer: Arc<ConsoleMailer>) -> EmailVerificationService {
+    EmailVerificationService::new(
+        app.state.db.clone(),
+        mailer,
+        app.state.signing_keys.clone(),
+        app.state.settings.email_verification.clone(),
+        app.state.settings.email.clone(),
+        app.clock.clone(),
+    )
+}
+
+fn last_token(mailer: &ConsoleMailer) -> String {
+    let email = mailer.outbox().pop().expect("an email");
+    let (_, token) = email.content.text.split_once("?token=").expect("a link");
+    token.split_whitespace().next().unwrap().to_string()
+}
+
+#[actix_web::test]
+async fn forged_and_misdirected_tokens_are_rejected_before_the_database() {
+    let app = TestApp::spawn().await;
+
+    let response = assert_status(app.request(verify("v1.e30.c2lnbmF0dXJl")).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "EMAIL_VERIFICATION_INVALID");
+
+    // Signed, but for signing in
+    let claims = MagicLinkClaims { id: uuid::Uuid::new_v4() };
+    let token = app.state.signing_keys.sign(MAGIC_LINK_PURPOSE, &claims, Duration::minutes(5)).unwrap();
+    let response = assert_status(app.request(verify(&token)).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "EMAIL_VERIFICATION_INVALID");
+}
+
+#[actix_web::test]
+async fn resends_reject_malformed_emails() {
+    let app = TestApp::spawn().await;
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/resend-verification")
+        .set_json(json!({ "email": "not-an-email" }));
+    let response = assert_status(app.request(request).await, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error_code"], "VALIDATION_FAILED");
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn links_verify_the_address_they_were_sent_to() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    assert!(!user.is_verified);
+    let mailer = Arc::new(ConsoleMailer::default());
+    email_verification_service(&app, mailer.clone()).send(&user).await.unwrap();
+    assert_eq!(mailer.outbox()[0].to, user.email);
+    let token = last_token(&mailer);
+
+    let response = assert_status(app.request(verify(&token)).await, StatusCode::OK);
+    assert_eq!(response.body["id"], user.id.to_string());
+    assert_eq!(response.body["is_verified"], true);
+
+    // Following it again is harmless
+    assert_status(app.request(verify(&token)).await, StatusCode::OK);
+
+    // Verified users aren't sent more links
+    let verified = app.state.user_queries.get_user_by_id(user.id).await.unwrap();
+    email_verification_service(&app, mailer.clone()).send(&verified).await.unwrap();
+    assert_eq!(mailer.outbox().len(), 1);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn links_expire_and_die_with_a_changed_address() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let mailer = Arc::new(ConsoleMailer::default());
+    let service = email_verification_service(&app, mailer.clone());
+
+    service.send(&user).await.unwrap();
+    let expired = last_token(&mailer);
+    app.clock.advance(Duration::seconds(app.state.settings.email_verification.token_ttl_secs + 1));
+    let error = service.verify(&expired).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::EmailVerificationInvalid);
+
+    service.send(&user).await.unwrap();
+    let token = last_token(&mailer);
+    sqlx::query("UPDATE users SET email = $2 WHERE id = $1")
+        .bind(user.id)
+        .bind(format!("new-{}", user.email))
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+    let error = service.verify(&token).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::EmailVerificationInvalid);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn resends_wait_for_the_interval() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let mailer = Arc::new(ConsoleMailer::default());
+    let service = email_verification_service(&app, mailer.clone());
+
+    service.send(&user).await.unwrap();
+    let error = service.send(&user).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::EmailVerificationRateLimited);
+
+    // Accepted all the same, so the endpoint doesn't reveal the account
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/resend-verification")
+        .set_json(json!({ "email": user.email }));
+    assert_status(app.request(request).await, StatusCode::ACCEPTED);
+
+    app.clock.advance(Duration::seconds(app.state.settings.email_verification.resend_interval_secs));
+    service.send(&user).await.unwrap();
+    assert_eq!(mailer.outbox().len(), 2);
+}
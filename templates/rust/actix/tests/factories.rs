@@ -0,0 +1,57 @@
+//! Factory-built data is unique within and across tests, and reproducible.
+
+use actix_template::factories::UserFactory;
+
+#[test]
+fn same_thread_builds_unique_users() {
+    let first = UserFactory::build().into_user();
+    let second = UserFactory::build().into_user();
+
+    assert_ne!(first.id, second.id);
+    assert_ne!(first.email, second.email);
+    assert_ne!(first.username, second.username);
+    assert!(second.created_at > first.created_at);
+}
+
+#[test]
+fn sequences_are_reproducible() {
+    let build = || {
+        std::thread::Builder::new()
+            .name("factory".to_string())
+            .spawn(|| UserFactory::build().verified().into_user())
+            .unwrap()
+            .join()
+            .unwrap()
+    };
+
+    let (first, second) = (build(), build());
+
+    assert_eq!(first.id, second.id);
+    assert_eq!(first.email, second.email);
+    assert_eq!(first.password_hash, second.password_hash);
+    assert!(first.is_verified);
+}
+
+#[test]
+fn threads_get_distinct_users() {
+    let build = |name: &str| {
+        std::thread::Builder::new()
+            .name(name.to_string())
+            .spawn(|| UserFactory::build().into_user())
+            .unwrap()
+            .join()
+            .unwrap()
+    };
+
+    let (first, second) = (build("first"), build("second"));
+
+    assert_ne!(first.id, second.id);
+    assert_ne!(first.email, second.email);
+}
+
+#[test]
+fn password_verifies() {
+    let user = UserFactory::build().password("hunter22").into_user();
+
+    assert!(user.password_hash.verify("hunter22").unwrap());
+}
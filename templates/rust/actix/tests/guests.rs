@@ -0,0 +1,166 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use chrono::Duration;
+use platform_core::domain::UserId;
+use serde_json::json;
+use uuid::Uuid;
+
+use actix_template::errors::ErrorCode;
+use actix_template::factories::UserFactory;
+use actix_template::utils::{create_guest_token, decode_jwt_token, Clock};
+use common::{assert_status, authorized, TestApp};
+
+async fn spawn() -> TestApp {
+    TestApp::spawn_with(|config| config.set_override("guests.enabled", true).unwrap()).await
+}
+
+fn registration(email: &str, username: &str) -> serde_json::Value {
+    json!({ "email": email, "username": username, "password": "correct horse battery" })
+}
+
+/// A guest token for a guest that needn't exist, for requests that are
+/// turned away before the database is reached.
+fn guest_token(app: &TestApp) -> String {
+    let mut guest = UserFactory::build().into_user();
+    guest.is_guest = true;
+    guest.guest_expires_at = Some(app.clock.now() + Duration::hours(1));
+    create_guest_token(&guest, &app.state.jwt_keys, app.clock.as_ref()).unwrap()
+}
+
+#[actix_web::test]
+async fn guests_are_off_by_default() {
+    let app = TestApp::spawn().await;
+
+    assert_status(app.request(TestRequest::post().uri("/api/v1/auth/guest")).await, StatusCode::NOT_FOUND);
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/guest/upgrade")
+        .set_json(registration("ada@example.test", "ada"));
+    assert_status(app.request(authorized(request, &guest_token(&app))).await, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn guest_tokens_carry_the_guest_claim() {
+    let app = spawn().await;
+
+    let claims = decode_jwt_token(&guest_token(&app), &app.state.jwt_keys, app.clock.as_ref()).unwrap();
+    assert!(claims.guest);
+
+    let token = app.token_for(Uuid::from_u128(1));
+    let claims = decode_jwt_token(&token, &app.state.jwt_keys, app.clock.as_ref()).unwrap();
+    assert!(!claims.guest);
+}
+
+#[actix_web::test]
+async fn guests_have_restricted_scopes() {
+    let app = spawn().await;
+    let token = guest_token(&app);
+
+    let response = assert_status(
+        app.request(authorized(TestRequest::get().uri("/api/v1/users"), &token)).await,
+        StatusCode::FORBIDDEN,
+    );
+    assert_eq!(response.body["error_code"], "AUTH_INSUFFICIENT_SCOPE");
+
+    // Guests can't mint tokens that would outlive them
+    assert_status(
+        app.request(authorized(TestRequest::get().uri("/api/v1/tokens"), &token)).await,
+        StatusCode::FORBIDDEN,
+    );
+}
+
+#[actix_web::test]
+async fn upgrading_needs_a_token() {
+    let app = spawn().await;
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/guest/upgrade")
+        .set_json(registration("ada@example.test", "ada"));
+    assert_status(app.request(request).await, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn guests_upgrade_to_accounts_keeping_their_data() {
+    let app = spawn().await;
+
+    let guest = assert_status(app.request(TestRequest::post().uri("/api/v1/auth/guest")).await, StatusCode::CREATED).body;
+    assert_eq!(guest["user"]["is_guest"], true);
+    assert_eq!(guest["expires_in"], app.state.settings.guests.ttl_secs);
+    assert!(guest.get("refresh_token").is_none());
+    let guest_id = guest["user"]["id"].clone();
+    let token = guest["access_token"].as_str().unwrap().to_string();
+
+    // Something the guest creates
+    let device = json!({ "platform": "apns", "token": format!("token-{}", Uuid::new_v4()) });
+    let request = TestRequest::post().uri("/api/v1/devices").set_json(&device);
+    assert_status(app.request(authorized(request, &token)).await, StatusCode::CREATED);
+
+    // Guests don't show up among users
+    let admin = app.insert_user(UserFactory::build()).await;
+    let request = authorized(TestRequest::get().uri("/api/v1/users?limit=100"), &app.token_for_user(&admin));
+    let users = assert_status(app.request(request).await, StatusCode::OK).body;
+    assert!(users["data"].as_array().unwrap().iter().all(|user| user["id"] != guest_id));
+
+    // Taken credentials are refused and the guest stays a guest
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/guest/upgrade")
+        .set_json(registration(admin.email.as_str(), "ada_lovelace"));
+    let response = assert_status(app.request(authorized(request, &token)).await, StatusCode::CONFLICT);
+    assert_eq!(response.body["error_code"], "USER_EMAIL_TAKEN");
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/guest/upgrade")
+        .set_json(registration("ada@example.test", "ada_lovelace"));
+    let account = assert_status(app.request(authorized(request, &token)).await, StatusCode::OK).body;
+    assert_eq!(account["user"]["id"], guest_id);
+    assert_eq!(account["user"]["is_guest"], false);
+    assert_eq!(account["user"]["email"], "ada@example.test");
+    assert!(account["refresh_token"].is_string());
+
+    // The account has what the guest created, and full scopes
+    let token = account["access_token"].as_str().unwrap();
+    let devices = assert_status(
+        app.request(authorized(TestRequest::get().uri("/api/v1/devices"), token)).await,
+        StatusCode::OK,
+    )
+    .body;
+    assert_eq!(devices.as_array().unwrap().len(), 1);
+    assert_status(app.request(authorized(TestRequest::get().uri("/api/v1/tokens"), token)).await, StatusCode::OK);
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/guest/upgrade")
+        .set_json(registration("ada2@example.test", "ada_lovelace2"));
+    let response = assert_status(app.request(authorized(request, token)).await, StatusCode::CONFLICT);
+    assert_eq!(response.body["error_code"], "GUEST_UPGRADE_UNAVAILABLE");
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({ "email": "ada@example.test", "password": "correct horse battery" }));
+    assert_status(app.request(request).await, StatusCode::OK);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn expired_guests_are_deleted() {
+    let app = spawn().await;
+
+    let guest = assert_status(app.request(TestRequest::post().uri("/api/v1/auth/guest")).await, StatusCode::CREATED).body;
+    let guest_id: UserId = guest["user"]["id"].as_str().unwrap().parse().unwrap();
+    let token = guest["access_token"].as_str().unwrap().to_string();
+
+    // Within the token's leeway, but the guest is gone
+    app.clock.advance(Duration::seconds(app.state.settings.guests.ttl_secs + 1));
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/guest/upgrade")
+        .set_json(registration("late@example.test", "late_guest"));
+    let response = assert_status(app.request(authorized(request, &token)).await, StatusCode::CONFLICT);
+    assert_eq!(response.body["error_code"], "GUEST_UPGRADE_UNAVAILABLE");
+
+    // Issuing a guest clears out expired ones
+    assert_status(app.request(TestRequest::post().uri("/api/v1/auth/guest")).await, StatusCode::CREATED);
+    let error = app.state.user_queries.get_user_by_id(guest_id).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::UserNotFound);
+}
@@ -0,0 +1,222 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use actix_web::{web, App, HttpResponse};
+use chrono::Duration;
+use futures_util::future::BoxFuture;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use actix_template::config::HealthSettings;
+use actix_template::health::{CircuitState, HealthCheck, HealthRegistry, HttpDependency};
+use actix_template::utils::MockClock;
+use common::{assert_status, test_epoch, TestApp};
+
+/// Counts checks; fails while `failing` is set.
+#[derive(Default)]
+struct Probe {
+    critical: bool,
+    failing: AtomicBool,
+    checks: AtomicUsize,
+}
+
+impl Probe {
+    fn critical() -> Arc<Self> {
+        Arc::new(Self {
+            critical: true,
+            ..Self::default()
+        })
+    }
+
+    fn optional() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    fn fail(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+
+    fn checks(&self) -> usize {
+        self.checks.load(Ordering::SeqCst)
+    }
+}
+
+impl HealthCheck for Probe {
+    fn name(&self) -> &str {
+        if self.critical {
+            "primary"
+        } else {
+            "provider"
+        }
+    }
+
+    fn critical(&self) -> bool {
+        self.critical
+    }
+
+    fn check(&self) -> BoxFuture<'_, Result<(), String>> {
+        Box::pin(async move {
+            self.checks.fetch_add(1, Ordering::SeqCst);
+            if self.failing.load(Ordering::SeqCst) {
+                Err("unreachable".to_string())
+            } else {
+                Ok(())
+            }
+        })
+    }
+}
+
+/// Never answers.
+struct Hanging;
+
+impl HealthCheck for Hanging {
+    fn name(&self) -> &str {
+        "hanging"
+    }
+
+    fn check(&self) -> BoxFuture<'_, Result<(), String>> {
+        Box::pin(std::future::pending())
+    }
+}
+
+fn settings() -> HealthSettings {
+    HealthSettings {
+        cache_ttl_ms: 1000,
+        check_timeout_ms: 50,
+        failure_threshold: 2,
+        open_secs: 30,
+    }
+}
+
+fn registry(clock: &Arc<MockClock>, checks: Vec<Arc<dyn HealthCheck>>) -> HealthRegistry {
+    HealthRegistry::new(settings(), clock.clone()).register_all(checks)
+}
+
+#[actix_web::test]
+async fn results_are_cached() {
+    let clock = Arc::new(MockClock::new(test_epoch()));
+    let probe = Probe::critical();
+    let registry = registry(&clock, vec![probe.clone()]);
+
+    assert!(registry.report().await.ready);
+    probe.fail(true);
+    assert!(registry.report().await.ready);
+    assert_eq!(probe.checks(), 1);
+
+    clock.advance(Duration::seconds(1));
+    let report = registry.report().await;
+    assert!(!report.ready);
+    assert_eq!(report.dependency("primary").unwrap().error.as_deref(), Some("unreachable"));
+    assert_eq!(probe.checks(), 2);
+}
+
+#[actix_web::test]
+async fn circuits_open_after_repeated_failures() {
+    let clock = Arc::new(MockClock::new(test_epoch()));
+    let probe = Probe::critical();
+    let registry = registry(&clock, vec![probe.clone()]);
+    let circuit = |report: &actix_template::health::HealthReport| report.dependency("primary").unwrap().circuit;
+
+    probe.fail(true);
+    assert_eq!(circuit(&registry.report().await), CircuitState::Closed);
+    clock.advance(Duration::seconds(1));
+    assert_eq!(circuit(&registry.report().await), CircuitState::Open);
+    assert_eq!(probe.checks(), 2);
+
+    // Reported down without checking while open
+    probe.fail(false);
+    clock.advance(Duration::seconds(10));
+    let report = registry.report().await;
+    assert_eq!(circuit(&report), CircuitState::Open);
+    assert!(!report.ready);
+    assert_eq!(probe.checks(), 2);
+
+    // One trial once the open period is over; failing it reopens
+    probe.fail(true);
+    clock.advance(Duration::seconds(30));
+    assert_eq!(circuit(&registry.report().await), CircuitState::Open);
+    assert_eq!(probe.checks(), 3);
+
+    probe.fail(false);
+    clock.advance(Duration::seconds(30));
+    let report = registry.report().await;
+    assert_eq!(circuit(&report), CircuitState::Closed);
+    assert!(report.ready);
+    assert_eq!(probe.checks(), 4);
+}
+
+#[actix_web::test]
+async fn only_critical_dependencies_decide_readiness() {
+    let clock = Arc::new(MockClock::new(test_epoch()));
+    let provider = Probe::optional();
+    provider.fail(true);
+
+    let report = registry(&clock, vec![Probe::critical(), provider.clone()]).report().await;
+    assert!(report.ready);
+    assert!(report.degraded);
+
+    let report = registry(&clock, vec![Probe::critical(), Arc::new(Hanging)]).report().await;
+    assert!(!report.ready);
+    assert!(!report.degraded);
+    assert_eq!(report.dependency("hanging").unwrap().error.as_deref(), Some("timed out after 50ms"));
+}
+
+#[actix_web::test]
+async fn http_dependencies_are_up_unless_they_fail_server_side() {
+    let server = actix_test::start(|| {
+        App::new()
+            .route("/up", web::to(|| async { HttpResponse::Unauthorized().finish() }))
+            .route("/down", web::to(|| async { HttpResponse::ServiceUnavailable().finish() }))
+    });
+    let client = reqwest::Client::new();
+
+    assert!(HttpDependency::new("up", client.clone(), &server.url("/up")).check().await.is_ok());
+    let error = HttpDependency::new("down", client.clone(), &server.url("/down")).check().await.unwrap_err();
+    assert!(error.contains("503"), "{}", error);
+    assert!(HttpDependency::new("gone", client, "http://127.0.0.1:1").check().await.is_err());
+}
+
+#[actix_web::test]
+async fn readiness_lists_configured_providers() {
+    let app = TestApp::spawn_with(|config| {
+        config
+            .set_override("sms.provider", "twilio")
+            .and_then(|config| config.set_override("sms.twilio_account_sid", "AC123"))
+            .and_then(|config| config.set_override("sms.twilio_auth_token", "secret"))
+            .and_then(|config| config.set_override("sms.from_number", "+15005550006"))
+            .and_then(|config| config.set_override("sms.twilio_api_url", "http://127.0.0.1:1"))
+            .unwrap()
+    })
+    .await;
+
+    let response = app.request(TestRequest::get().uri("/api/v1/ready")).await;
+
+    let names: Vec<_> = response.body["dependencies"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|dependency| dependency["name"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(names, ["database", "twilio"]);
+    assert_eq!(response.body["dependencies"][1]["critical"], false);
+    assert_eq!(response.body["dependencies"][1]["up"], false);
+    if std::env::var("TEST_DATABASE_URL").is_ok() {
+        let response = assert_status(response, StatusCode::OK);
+        assert_eq!(response.body["status"], "degraded");
+    }
+}
+
+#[actix_web::test]
+async fn readiness_fails_while_draining() {
+    let app = TestApp::spawn().await;
+    app.state.draining.start();
+
+    let response = app.request(TestRequest::get().uri("/api/v1/ready")).await;
+    let response = assert_status(response, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.body["status"], "draining");
+
+    // Liveness is unaffected, so the pod isn't restarted mid-drain
+    let response = app.request(TestRequest::get().uri("/api/v1/health")).await;
+    assert_status(response, StatusCode::OK);
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use serde_json::json;
+use uuid::Uuid;
+
+use actix_template::errors::ErrorCode;
+use actix_template::factories::UserFactory;
+use actix_template::utils::{create_impersonation_token, decode_jwt_token};
+use common::{assert_status, authorized, TestApp};
+
+fn impersonation_token(app: &TestApp, actor: Uuid) -> String {
+    let subject = UserFactory::build().into_user();
+    create_impersonation_token(&subject, actor.into(), &app.state.jwt_keys, 900, app.clock.as_ref()).unwrap()
+}
+
+#[actix_web::test]
+async fn impersonation_tokens_carry_both_identities() {
+    let app = TestApp::spawn().await;
+    let actor = Uuid::new_v4();
+
+    let claims = decode_jwt_token(&impersonation_token(&app, actor), &app.state.jwt_keys, app.clock.as_ref()).unwrap();
+    assert_eq!(claims.act.unwrap().sub, actor.into());
+
+    let claims = decode_jwt_token(&app.token_for(actor), &app.state.jwt_keys, app.clock.as_ref()).unwrap();
+    assert!(claims.act.is_none());
+}
+
+#[actix_web::test]
+async fn impersonating_needs_an_admin_session() {
+    let app = TestApp::spawn().await;
+    let body = json!({ "user_id": Uuid::new_v4(), "reason": "Ticket #42" });
+
+    let request = TestRequest::post().uri("/api/v1/admin/impersonate").set_json(&body);
+    assert_status(app.admin_request(request).await, StatusCode::UNAUTHORIZED);
+
+    // Public listeners don't serve it at all
+    let request = TestRequest::post().uri("/api/v1/admin/impersonate").set_json(&body);
+    assert_status(app.request(authorized(request, &app.token_for(Uuid::new_v4()))).await, StatusCode::NOT_FOUND);
+
+    let admin = Uuid::new_v4();
+    let request = TestRequest::post()
+        .uri("/api/v1/admin/impersonate")
+        .set_json(json!({ "user_id": admin, "reason": "" }));
+    assert_status(app.admin_request(authorized(request, &app.admin_token_for(admin))).await, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn only_admins_impersonate() {
+    let app = TestApp::spawn().await;
+    let body = json!({ "user_id": Uuid::new_v4(), "reason": "Ticket #42" });
+
+    let request = TestRequest::post().uri("/api/v1/admin/impersonate").set_json(&body);
+    let response = app.admin_request(authorized(request, &app.token_for(Uuid::new_v4()))).await;
+    let response = assert_status(response, StatusCode::FORBIDDEN);
+    assert_eq!(response.body["error_code"], ErrorCode::AuthRoleMissing.as_str());
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn admins_cant_be_impersonated() {
+    let app = TestApp::spawn().await;
+    let (_, token) = app.insert_admin().await;
+    let (other_admin, _) = app.insert_admin().await;
+
+    let request = TestRequest::post()
+        .uri("/api/v1/admin/impersonate")
+        .set_json(json!({ "user_id": other_admin.id, "reason": "Ticket #42" }));
+    assert_status(app.admin_request(authorized(request, &token)).await, StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn impersonated_requests_are_flagged_and_restricted() {
+    let app = TestApp::spawn().await;
+    let actor = Uuid::new_v4();
+    let token = impersonation_token(&app, actor);
+
+    // Support can't mint credentials as the user
+    let response = app.request(authorized(TestRequest::get().uri("/api/v1/tokens"), &token)).await;
+    let response = assert_status(response, StatusCode::FORBIDDEN);
+    assert_eq!(response.headers.get("x-impersonated-by").unwrap(), actor.to_string().as_str());
+
+    // Ordinary requests aren't flagged
+    let request = authorized(TestRequest::get().uri("/api/v1/tokens"), &app.token_for(actor));
+    let response = app.request(request).await;
+    assert!(!response.headers.contains_key("x-impersonated-by"));
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn impersonation_is_audited() {
+    let app = TestApp::spawn().await;
+    let (admin, admin_token) = app.insert_admin().await;
+    let user = app.insert_user(UserFactory::build()).await;
+
+    let request = TestRequest::post()
+        .uri("/api/v1/admin/impersonate")
+        .set_json(json!({ "user_id": Uuid::new_v4(), "reason": "Ticket #42" }));
+    assert_status(app.admin_request(authorized(request, &admin_token)).await, StatusCode::NOT_FOUND);
+
+    let request = TestRequest::post()
+        .uri("/api/v1/admin/impersonate")
+        .set_json(json!({ "user_id": user.id, "reason": "Ticket #42" }));
+    let response = assert_status(app.admin_request(authorized(request, &admin_token)).await, StatusCode::CREATED);
+    assert_eq!(response.body["user"]["id"], user.id.to_string());
+    assert_eq!(response.body["expires_in"], 900);
+    let token = response.body["access_token"].as_str().unwrap().to_string();
+
+    // Reads act as the user
+    let uri = format!("/api/v1/users/{}", user.id);
+    let response = assert_status(
+        app.request(authorized(TestRequest::get().uri(&uri), &token)).await,
+        StatusCode::OK,
+    );
+    assert_eq!(response.headers.get("x-impersonated-by").unwrap(), admin.id.to_string().as_str());
+
+    // Changes are recorded first
+    let request = TestRequest::patch().uri(&uri).set_json(json!({ "full_name": "Ada" }));
+    let response = assert_status(app.request(authorized(request, &token)).await, StatusCode::OK);
+    assert_eq!(response.body["full_name"], "Ada");
+
+    // Impersonation doesn't chain
+    let request = TestRequest::post()
+        .uri("/api/v1/admin/impersonate")
+        .set_json(json!({ "user_id": admin.id, "reason": "Ticket #42" }));
+    assert_status(app.admin_request(authorized(request, &token)).await, StatusCode::FORBIDDEN);
+
+    let events: Vec<(String, serde_json::Value)> = sqlx::query_as(
+        "SELECT event_type, metadata FROM audit_events WHERE user_id = $1 ORDER BY created_at, id",
+    )
+    .bind(admin.id)
+    .fetch_all(&app.state.db)
+    .await
+    .unwrap();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0].0, "admin.impersonation_started");
+    assert_eq!(events[0].1["subject"], user.id.to_string());
+    assert_eq!(events[0].1["reason"], "Ticket #42");
+    assert_eq!(events[1].0, "admin.impersonated_request");
+    assert_eq!(events[1].1["method"], "PATCH");
+    assert_eq!(events[1].1["path"], uri);
+    assert_eq!(events[2].1["path"], "/api/v1/admin/impersonate");
+}
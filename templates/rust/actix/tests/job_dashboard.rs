@@ -0,0 +1,104 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use config::{File, FileFormat};
+use futures_util::future::BoxFuture;
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use actix_template::errors::{AppError, AppResult};
+use actix_template::jobs::dashboard::redact;
+use actix_template::jobs::{JobHandler, Worker};
+use actix_template::models::job::Job;
+use common::{assert_status, authorized, TestApp};
+
+/// Fails every job it runs, counting them.
+#[derive(Clone, Default)]
+struct Failing(Arc<AtomicUsize>);
+
+impl JobHandler for Failing {
+    fn handle<'a>(&'a self, _job: &'a Job) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Err(AppError::BadRequest("mailbox unavailable".to_string()))
+        })
+    }
+}
+
+// One test, so no other test's worker picks up these jobs
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn admins_inspect_retry_and_purge_dead_jobs() {
+    let settings = "[jobs]\nmax_attempts = 1";
+    let app = TestApp::spawn_with(|config| config.add_source(File::from_str(settings, FileFormat::Toml))).await;
+    let queue = &app.state.job_queue;
+    let failing = Failing::default();
+    let worker = Worker::new(app.state.db.clone(), app.state.settings.jobs.clone(), app.clock.clone())
+        .handler("test.dashboard", failing.clone());
+    let (admin, token) = app.insert_admin().await;
+
+    let payload = json!({ "to": { "Email": "ada@example.com", "name": "Ada" }, "reset_token": "t0k3n" });
+    let job_id = queue.enqueue("test.dashboard", &payload).await.unwrap();
+    worker.drain().await.unwrap();
+    assert_eq!(failing.0.load(Ordering::SeqCst), 1);
+
+    let response = app
+        .admin_request(authorized(TestRequest::get().uri("/api/v1/admin/jobs?status=dead&kind=test.dashboard"), &token))
+        .await;
+    let response = assert_status(response, StatusCode::OK);
+    let listed = response.body["jobs"].as_array().unwrap();
+    let listed = listed.iter().find(|job| job["id"] == job_id.to_string()).unwrap();
+    assert_eq!(listed["payload"], json!({ "to": { "Email": "[REDACTED]", "name": "Ada" }, "reset_token": "[REDACTED]" }));
+
+    let inspect = TestRequest::get().uri(&format!("/api/v1/admin/jobs/{}", job_id));
+    let response = assert_status(app.admin_request(authorized(inspect, &token)).await, StatusCode::OK);
+    assert_eq!(response.body["payload"]["reset_token"], "[REDACTED]");
+    assert!(response.body["last_error"].as_str().unwrap().contains("mailbox unavailable"));
+
+    let response = app.admin_request(authorized(TestRequest::get().uri("/api/v1/admin/jobs/stats"), &token)).await;
+    let response = assert_status(response, StatusCode::OK);
+    let kinds = response.body["kinds"].as_array().unwrap();
+    assert!(kinds.iter().any(|kind| kind["kind"] == "test.dashboard" && kind["dead"].as_i64() >= Some(1)));
+    let failures = response.body["failures"].as_array().unwrap();
+    assert!(failures.iter().any(|failure| failure["kind"] == "test.dashboard"));
+
+    // Retried jobs run again with their attempts reset
+    let retry = TestRequest::post().uri(&format!("/api/v1/admin/jobs/{}/retry", job_id));
+    let response = assert_status(app.admin_request(authorized(retry, &token)).await, StatusCode::OK);
+    assert_eq!(response.body["status"], "pending");
+    assert_eq!(response.body["attempts"], 0);
+    worker.drain().await.unwrap();
+    assert_eq!(failing.0.load(Ordering::SeqCst), 2);
+    assert_eq!(queue.get_job(job_id).await.unwrap().unwrap().status, "dead");
+
+    let purge = TestRequest::delete().uri("/api/v1/admin/jobs/dead?kind=test.dashboard");
+    let response = assert_status(app.admin_request(authorized(purge, &token)).await, StatusCode::OK);
+    assert!(response.body["purged"].as_u64() >= Some(1));
+    assert!(queue.get_job(job_id).await.unwrap().is_none());
+
+    let scheduled = queue.enqueue_in("test.dashboard", &(), chrono::Duration::hours(1)).await.unwrap();
+    let cancel = || TestRequest::delete().uri(&format!("/api/v1/admin/jobs/{}", scheduled));
+    assert_status(app.admin_request(authorized(cancel(), &token)).await, StatusCode::NO_CONTENT);
+    assert_status(app.admin_request(authorized(cancel(), &token)).await, StatusCode::NOT_FOUND);
+
+    let audited: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM audit_events WHERE user_id = $1 AND event_type IN ('admin.job_retried', 'admin.job_cancelled', 'admin.dead_jobs_purged')",
+    )
+    .bind(admin.id)
+    .fetch_one(&app.state.db)
+    .await
+    .unwrap();
+    assert_eq!(audited, 3);
+}
+
+#[test]
+fn redaction_reaches_nested_fields() {
+    let fields = vec!["password".to_string(), "token".to_string()];
+    let mut payload = json!({ "users": [{ "id": 1, "Password": "p" }], "accessToken": { "value": "t" }, "kind": "x" });
+
+    redact(&mut payload, &fields);
+
+    assert_eq!(payload, json!({ "users": [{ "id": 1, "Password": "[REDACTED]" }], "accessToken": "[REDACTED]", "kind": "x" }));
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use chrono::Duration;
+use config::{File, FileFormat};
+use futures_util::future::BoxFuture;
+use std::sync::{Arc, Mutex};
+
+use actix_template::errors::AppResult;
+use actix_template::jobs::{JobHandler, JobOptions, Worker};
+use actix_template::models::job::Job;
+use common::{assert_status, authorized, TestApp};
+
+const SETTINGS: &str = r#"
+[jobs.queues.emails]
+kinds = ["test.email"]
+priority = -10
+
+[jobs.queues.webhooks]
+kinds = ["test.webhook"]
+priority = 10
+rate_limit = { max = 2, period_secs = 60 }
+"#;
+
+/// Records every job it runs.
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Vec<Job>>>);
+
+impl JobHandler for Recorder {
+    fn handle<'a>(&'a self, job: &'a Job) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            self.0.lock().unwrap().push(job.clone());
+            Ok(())
+        })
+    }
+}
+
+// One test, so no other test's worker picks up these jobs
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn queues_are_served_by_priority_within_rate_limits_unless_paused() {
+    let app = TestApp::spawn_with(|config| config.add_source(File::from_str(SETTINGS, FileFormat::Toml))).await;
+    let queue = &app.state.job_queue;
+    let recorder = Recorder::default();
+    let worker = Worker::new(app.state.db.clone(), app.state.settings.jobs.clone(), app.clock.clone())
+        .handler("test.email", recorder.clone())
+        .handler("test.webhook", recorder.clone());
+    let webhook = |endpoint: &str| JobOptions {
+        rate_key: Some(endpoint.to_string()),
+        ..Default::default()
+    };
+
+    let email = queue.enqueue("test.email", &()).await.unwrap();
+    for endpoint in ["a.example", "a.example", "a.example", "b.example"] {
+        queue
+            .enqueue_with_options(&app.state.db, "test.webhook", &(), &webhook(endpoint))
+            .await
+            .unwrap();
+    }
+    assert_eq!(queue.get_job(email).await.unwrap().unwrap().queue, "emails");
+
+    // Webhooks first, two per endpoint a minute
+    assert_eq!(worker.drain().await.unwrap(), 4);
+    let ran = std::mem::take(&mut *recorder.0.lock().unwrap());
+    assert!(ran[..3].iter().all(|job| job.kind == "test.webhook"));
+    assert_eq!(ran[3].kind, "test.email");
+    assert_eq!(ran.iter().filter(|job| job.rate_key.as_deref() == Some("a.example")).count(), 2);
+    assert_eq!(worker.drain().await.unwrap(), 0);
+    app.clock.advance(Duration::seconds(60));
+    assert_eq!(worker.drain().await.unwrap(), 1);
+
+    // Paused queues keep their jobs pending
+    let (_, token) = app.insert_admin().await;
+    let pause = TestRequest::post().uri("/api/v1/admin/job-queues/emails/pause");
+    assert_status(app.admin_request(authorized(pause, &token)).await, StatusCode::NO_CONTENT);
+    queue.enqueue("test.email", &()).await.unwrap();
+    assert_eq!(worker.drain().await.unwrap(), 0);
+
+    let response = app.admin_request(authorized(TestRequest::get().uri("/api/v1/admin/job-queues"), &token)).await;
+    let response = assert_status(response, StatusCode::OK);
+    let names: Vec<_> = response.body["queues"].as_array().unwrap().iter().map(|q| q["name"].clone()).collect();
+    assert_eq!(names, ["webhooks", "default", "emails"]);
+    assert_eq!(response.body["queues"][0]["rate_limit"]["max"], 2);
+    assert_eq!(response.body["queues"][2]["paused"], true);
+    assert_eq!(response.body["queues"][2]["pending"], 1);
+
+    let resume = TestRequest::post().uri("/api/v1/admin/job-queues/emails/resume");
+    assert_status(app.admin_request(authorized(resume, &token)).await, StatusCode::NO_CONTENT);
+    assert_eq!(worker.drain().await.unwrap(), 1);
+
+    let unknown = TestRequest::post().uri("/api/v1/admin/job-queues/unknown/pause");
+    assert_status(app.admin_request(authorized(unknown, &token)).await, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use chrono::Duration;
+use futures_util::future::BoxFuture;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use actix_template::errors::{AppError, AppResult};
+use actix_template::jobs::{JobHandler, Worker};
+use actix_template::models::job::Job;
+use actix_template::utils::Clock;
+use common::TestApp;
+
+/// Fails the first `failures` runs, then succeeds.
+struct Flaky {
+    runs: Arc<AtomicUsize>,
+    failures: usize,
+}
+
+impl JobHandler for Flaky {
+    fn handle<'a>(&'a self, _job: &'a Job) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            if self.runs.fetch_add(1, Ordering::SeqCst) < self.failures {
+                Err(AppError::BadRequest("not yet".to_string()))
+            } else {
+                Ok(())
+            }
+        })
+    }
+}
+
+// One test, so no other test's worker picks up these jobs
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn jobs_are_retried_with_backoff_until_dead() {
+    let app = TestApp::spawn().await;
+    let settings = app.state.settings.jobs.clone();
+    let queue = &app.state.job_queue;
+    let runs = Arc::new(AtomicUsize::new(0));
+    let worker = Worker::new(app.state.db.clone(), settings.clone(), app.clock.clone())
+        .handler("test.flaky", Flaky { runs: runs.clone(), failures: 1 })
+        .handler("test.broken", Flaky { runs: Arc::default(), failures: usize::MAX });
+
+    // Succeeds on the retry, once the backoff has passed
+    let flaky = queue.enqueue("test.flaky", &()).await.unwrap();
+    assert_eq!(worker.drain().await.unwrap(), 1);
+    let job = queue.get_job(flaky).await.unwrap().unwrap();
+    assert_eq!(job.status, "pending");
+    assert_eq!(job.last_error.as_deref(), Some("Bad Request: not yet"));
+    assert_eq!(job.run_at, app.clock.now() + Duration::seconds(settings.retry_base_secs as i64));
+
+    assert_eq!(worker.drain().await.unwrap(), 0);
+    app.clock.advance(Duration::seconds(settings.retry_base_secs as i64));
+    assert_eq!(worker.drain().await.unwrap(), 1);
+    let job = queue.get_job(flaky).await.unwrap().unwrap();
+    assert_eq!(job.status, "completed");
+    assert_eq!(job.attempts, 2);
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+    // Dead after max_attempts
+    let broken = queue.enqueue("test.broken", &()).await.unwrap();
+    for _ in 0..settings.max_attempts {
+        assert_eq!(worker.drain().await.unwrap(), 1);
+        app.clock.advance(Duration::seconds(settings.retry_max_secs as i64));
+    }
+    let job = queue.get_job(broken).await.unwrap().unwrap();
+    assert_eq!(job.status, "dead");
+    assert_eq!(job.attempts, settings.max_attempts);
+
+    // Unknown kinds aren't retried
+    let unknown = queue.enqueue("test.unknown", &()).await.unwrap();
+    assert_eq!(worker.drain().await.unwrap(), 1);
+    let job = queue.get_job(unknown).await.unwrap().unwrap();
+    assert_eq!(job.status, "dead");
+    assert!(job.last_error.unwrap().contains("test.unknown"));
+}
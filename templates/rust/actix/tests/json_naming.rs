@@ -0,0 +1,104 @@
+mod common;
+
+use actix_web::dev::ServiceResponse;
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::future::ready;
+
+use actix_template::errors::AppError;
+use actix_template::middleware::json_naming::{rename_keys, to_camel_case, to_snake_case};
+use common::{assert_status, TestApp};
+
+#[derive(Serialize, Deserialize)]
+struct Profile {
+    full_name: String,
+    phone_number: Option<String>,
+}
+
+async fn echo(profile: web::Json<Profile>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "profile": profile.into_inner(), "tags": [{ "tag_name": "a" }] }))
+}
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/echo", web::post().to(echo)).service(
+        web::resource("/guarded")
+            .wrap_fn(|_, _| ready(Err::<ServiceResponse, _>(AppError::Forbidden.into())))
+            .route(web::get().to(HttpResponse::Ok)),
+    );
+}
+
+async fn camel_case_app() -> TestApp {
+    TestApp::spawn_with(|config| config.set_override("server.json_naming", "camel_case").unwrap()).await
+}
+
+#[test]
+fn keys_are_converted_between_cases() {
+    assert_eq!(to_camel_case("full_name"), "fullName");
+    assert_eq!(to_camel_case("retry_after_ms"), "retryAfterMs");
+    assert_eq!(to_camel_case("id"), "id");
+    assert_eq!(to_snake_case("retryAfterMs"), "retry_after_ms");
+    assert_eq!(to_snake_case("id"), "id");
+
+    let renamed = rename_keys(json!({ "user_id": 1, "items": [{ "last_seen_at": "x_y" }] }), &to_camel_case);
+    assert_eq!(renamed, json!({ "userId": 1, "items": [{ "lastSeenAt": "x_y" }] }));
+}
+
+#[actix_web::test]
+async fn bodies_keep_snake_case_by_default() {
+    let app = TestApp::spawn().await;
+
+    let request = TestRequest::post().uri("/echo").set_json(json!({ "full_name": "Ada" }));
+    let response = assert_status(app.serve(request, routes).await, StatusCode::OK);
+    assert_eq!(response.body["profile"]["full_name"], "Ada");
+}
+
+#[actix_web::test]
+async fn camel_case_bodies_are_read_and_written() {
+    let app = camel_case_app().await;
+
+    let request = TestRequest::post()
+        .uri("/echo")
+        .set_json(json!({ "fullName": "Ada", "phoneNumber": "+15550100" }));
+    let response = assert_status(app.serve(request, routes).await, StatusCode::OK);
+    assert_eq!(
+        response.body,
+        json!({
+            "profile": { "fullName": "Ada", "phoneNumber": "+15550100" },
+            "tags": [{ "tagName": "a" }],
+        })
+    );
+}
+
+#[actix_web::test]
+async fn errors_are_renamed_wherever_they_are_raised() {
+    let app = camel_case_app().await;
+
+    // Rejected by the JSON extractor
+    let request = TestRequest::post().uri("/echo").set_json(json!({ "phoneNumber": null }));
+    let response = assert_status(app.serve(request, routes).await, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["errorCode"], "BAD_REQUEST");
+    assert!(response.body.get("error_code").is_none());
+
+    // Rejected by a middleware
+    let request = TestRequest::get().uri("/guarded");
+    let response = assert_status(app.serve(request, routes).await, StatusCode::FORBIDDEN);
+    assert_eq!(response.body["errorCode"], "PERMISSION_DENIED");
+}
+
+#[actix_web::test]
+async fn oversized_bodies_are_refused_before_renaming() {
+    let app = TestApp::spawn_with(|config| {
+        config
+            .set_override("server.json_naming", "camel_case")
+            .and_then(|config| config.set_override("server.max_json_body_bytes", 64))
+            .unwrap()
+    })
+    .await;
+
+    let request = TestRequest::post().uri("/echo").set_json(json!({ "fullName": "a".repeat(100) }));
+    let response = assert_status(app.serve(request, routes).await, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(response.body["errorCode"], "PAYLOAD_TOO_LARGE");
+}
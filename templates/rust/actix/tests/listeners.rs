@@ -0,0 +1,356 @@
+mod common;
+
+use reqwest::StatusCode;
+use std::net::{Ipv6Addr, SocketAddr, TcpListener};
+use std::time::Duration;
+
+use actix_template::config::{CpuPinningSettings, ListenerRole, ListenerSettings, ServerSettings, TlsSettings};
+use actix_template::listeners::{self, Listener};
+use actix_template::middleware::Pipeline;
+use actix_template::runtime::CpuPinning;
+use common::TestApp;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+
+fn listener(address: &str, role: ListenerRole, tls: bool) -> ListenerSettings {
+    ListenerSettings {
+        address: address.to_string(),
+        role,
+        tls: tls.then(|| TlsSettings {
+            cert_path: format!("{}/server.pem", FIXTURES),
+            key_path: format!("{}/server.key", FIXTURES),
+        }),
+        http3: false,
+    }
+}
+
+async fn server_settings(listeners: Vec<ListenerSettings>) -> ServerSettings {
+    let app = TestApp::spawn().await;
+    ServerSettings {
+        listeners,
+        ..app.state.settings.server.clone()
+    }
+}
+
+fn address(listener: &Listener) -> SocketAddr {
+    listener.socket.local_addr().unwrap()
+}
+
+fn client() -> reqwest::Client {
+    let ca = std::fs::read(format!("{}/ca.pem", FIXTURES)).unwrap();
+    reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(&ca).unwrap())
+        .build()
+        .unwrap()
+}
+
+async fn status(url: String) -> StatusCode {
+    client().get(url).send().await.unwrap().status()
+}
+
+#[actix_web::test]
+async fn host_and_port_are_the_default_listener() {
+    let settings = server_settings(vec![]).await;
+
+    let listeners = settings.listeners();
+    assert_eq!(listeners.len(), 1);
+    assert_eq!(listeners[0].address, format!("{}:{}", settings.host, settings.port));
+    assert_eq!(listeners[0].role, ListenerRole::Public);
+    assert!(listeners[0].tls.is_none());
+}
+
+#[actix_web::test]
+async fn invalid_listeners_are_rejected_at_startup() {
+    let admin_only = server_settings(vec![listener("127.0.0.1:0", ListenerRole::Admin, false)]).await;
+    let error = listeners::bind(&admin_only).err().unwrap().to_string();
+    assert!(error.contains("needs a public listener"), "{}", error);
+
+    let bad_address = server_settings(vec![listener("localhost", ListenerRole::Public, false)]).await;
+    let error = listeners::bind(&bad_address).err().unwrap().to_string();
+    assert!(error.contains("invalid listener address localhost"), "{}", error);
+
+    let mut missing_key = listener("127.0.0.1:0", ListenerRole::Public, true);
+    missing_key.tls.as_mut().unwrap().key_path = format!("{}/missing.key", FIXTURES);
+    let error = listeners::bind(&server_settings(vec![missing_key]).await).err().unwrap().to_string();
+    assert!(error.contains("reading TLS key"), "{}", error);
+}
+
+#[actix_web::test]
+async fn ipv4_and_ipv6_can_share_a_port() {
+    let port = TcpListener::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+    let settings = server_settings(vec![
+        listener(&format!("0.0.0.0:{}", port), ListenerRole::Public, false),
+        listener(&format!("[::]:{}", port), ListenerRole::Public, false),
+    ])
+    .await;
+
+    let listeners = listeners::bind(&settings).unwrap();
+    assert_eq!(address(&listeners[1]).ip(), Ipv6Addr::UNSPECIFIED);
+    assert_eq!(address(&listeners[1]).port(), port);
+}
+
+#[actix_web::test]
+async fn listeners_serve_by_role_and_scheme() {
+    let app = TestApp::spawn().await;
+    let settings = ServerSettings {
+        listeners: vec![
+            listener("127.0.0.1:0", ListenerRole::Public, false),
+            listener("127.0.0.1:0", ListenerRole::Public, true),
+            listener("127.0.0.1:0", ListenerRole::Admin, false),
+        ],
+        ..app.state.settings.server.clone()
+    };
+    let bound = listeners::bind(&settings).unwrap();
+    let [plain, tls, admin] = [&bound[0], &bound[1], &bound[2]].map(address);
+
+    let pipeline = Pipeline::from_settings(&app.state.settings).unwrap();
+    let handles: Vec<_> = listeners::start(app.state.clone(), pipeline, bound)
+        .unwrap()
+        .into_iter()
+        .map(|server| {
+            let handle = server.handle();
+            actix_web::rt::spawn(server);
+            handle
+        })
+        .collect();
+
+    assert_eq!(status(format!("http://{}/api/v1/health", plain)).await, StatusCode::OK);
+    assert_eq!(status(format!("https://localhost:{}/api/v1/health", tls.port())).await, StatusCode::OK);
+    assert_eq!(status(format!("http://{}/api/v1/users", plain)).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(status(format!("https://localhost:{}/api/v1/users", tls.port())).await, StatusCode::UNAUTHORIZED);
+
+    // Admin listeners only serve probes and admin endpoints
+    assert_eq!(status(format!("http://{}/api/v1/health", admin)).await, StatusCode::OK);
+    assert_eq!(status(format!("http://{}/api/v1/users", admin)).await, StatusCode::NOT_FOUND);
+    assert_eq!(status(format!("http://{}/api/v1/admin/log-level", admin)).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(status(format!("http://{}/api/v1/admin/log-level", plain)).await, StatusCode::NOT_FOUND);
+
+    // Each server's workers report their runtime
+    let names: Vec<_> = app.state.runtimes.stats().into_iter().map(|(name, _)| name).collect();
+    assert!(names.iter().any(|name| name.starts_with("api/")), "{:?}", names);
+    assert!(names.iter().any(|name| name.starts_with("admin/")), "{:?}", names);
+
+    for handle in handles {
+        handle.stop(false).await;
+    }
+}
+
+#[actix_web::test]
+async fn acceptors_bind_public_listeners_to_one_port() {
+    let settings = ServerSettings {
+        acceptors: 3,
+        ..server_settings(vec![
+            listener("127.0.0.1:0", ListenerRole::Public, false),
+            listener("127.0.0.1:0", ListenerRole::Admin, false),
+        ])
+        .await
+    };
+
+    let bound = listeners::bind(&settings).unwrap();
+    let public: Vec<_> = bound.iter().filter(|listener| listener.role == ListenerRole::Public).collect();
+    assert_eq!(public.iter().map(|listener| listener.acceptor).collect::<Vec<_>>(), [0, 1, 2]);
+    assert!(public.iter().all(|listener| address(listener) == address(public[0])));
+    assert_eq!(bound.len(), 4);
+
+    let none = ServerSettings { acceptors: 0, ..settings };
+    let error = listeners::bind(&none).err().unwrap().to_string();
+    assert!(error.contains("at least 1"), "{}", error);
+}
+
+#[cfg(unix)]
+#[actix_web::test]
+async fn reuse_port_lets_servers_share_a_port() {
+    let settings = ServerSettings {
+        reuse_port: true,
+        ..server_settings(vec![listener("127.0.0.1:0", ListenerRole::Public, false)]).await
+    };
+    let first = listeners::bind(&settings).unwrap();
+
+    // Another process on the same port, as a second replica would be
+    let same_port = ServerSettings {
+        listeners: vec![listener(&address(&first[0]).to_string(), ListenerRole::Public, false)],
+        ..settings
+    };
+    let second = listeners::bind(&same_port).unwrap();
+    assert_eq!(address(&second[0]), address(&first[0]));
+
+    let exclusive = ServerSettings {
+        reuse_port: false,
+        ..same_port
+    };
+    assert!(listeners::bind(&exclusive).is_err());
+}
+
+#[actix_web::test]
+async fn acceptors_serve_on_pinned_workers() {
+    let app = TestApp::spawn_with(|config| {
+        config
+            .set_override("server.workers", 2)
+            .unwrap()
+            .set_override("server.acceptors", 2)
+            .unwrap()
+            .set_override("server.cpu_pinning.enabled", true)
+            .unwrap()
+    })
+    .await;
+    let settings = ServerSettings {
+        listeners: vec![listener("127.0.0.1:0", ListenerRole::Public, false)],
+        ..app.state.settings.server.clone()
+    };
+    let bound = listeners::bind(&settings).unwrap();
+    let url = format!("http://{}/api/v1/health", address(&bound[0]));
+
+    let pipeline = Pipeline::from_settings(&app.state.settings).unwrap();
+    let servers = listeners::start(app.state.clone(), pipeline, bound).unwrap();
+    assert_eq!(servers.len(), 2);
+    let handles: Vec<_> = servers
+        .into_iter()
+        .map(|server| {
+            let handle = server.handle();
+            actix_web::rt::spawn(server);
+            handle
+        })
+        .collect();
+
+    for _ in 0..10 {
+        assert_eq!(status(url.clone()).await, StatusCode::OK);
+    }
+
+    for handle in handles {
+        handle.stop(false).await;
+    }
+}
+
+#[actix_web::test]
+async fn shutdown_fails_readiness_before_closing_listeners() {
+    let app = TestApp::spawn_with(|config| config.set_override("server.drain_delay_secs", 1).unwrap()).await;
+    let settings = ServerSettings {
+        listeners: vec![listener("127.0.0.1:0", ListenerRole::Public, false)],
+        ..app.state.settings.server.clone()
+    };
+    let bound = listeners::bind(&settings).unwrap();
+    let base = format!("http://{}/api/v1", address(&bound[0]));
+
+    let pipeline = Pipeline::from_settings(&app.state.settings).unwrap();
+    let servers = listeners::start(app.state.clone(), pipeline, bound).unwrap();
+    let (signal, signalled) = tokio::sync::oneshot::channel::<()>();
+    let state = app.state.clone();
+    let running = actix_web::rt::spawn(async move {
+        listeners::run_until(servers, &state, async {
+            let _ = signalled.await;
+        })
+        .await
+    });
+    assert_eq!(status(format!("{}/health", base)).await, StatusCode::OK);
+
+    signal.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    // Still accepting during the delay, but no longer ready
+    assert_eq!(status(format!("{}/health", base)).await, StatusCode::OK);
+    let ready = client().get(format!("{}/ready", base)).send().await.unwrap();
+    assert_eq!(ready.json::<serde_json::Value>().await.unwrap()["status"], "draining");
+
+    running.await.unwrap().unwrap();
+    assert!(client().get(format!("{}/health", base)).send().await.is_err());
+}
+
+#[actix_web::test]
+async fn http3_needs_a_public_tls_listener() {
+    let mut plaintext = listener("127.0.0.1:0", ListenerRole::Public, false);
+    plaintext.http3 = true;
+    let error = listeners::bind(&server_settings(vec![plaintext]).await).err().unwrap().to_string();
+    assert!(error.contains("needs to be public and have tls"), "{}", error);
+
+    let mut admin = listener("127.0.0.1:0", ListenerRole::Admin, true);
+    admin.http3 = true;
+    let settings = server_settings(vec![listener("127.0.0.1:0", ListenerRole::Public, false), admin]).await;
+    let error = listeners::bind(&settings).err().unwrap().to_string();
+    assert!(error.contains("needs to be public and have tls"), "{}", error);
+
+    if cfg!(not(feature = "http3")) {
+        let mut tls = listener("127.0.0.1:0", ListenerRole::Public, true);
+        tls.http3 = true;
+        let error = listeners::bind(&server_settings(vec![tls]).await).err().unwrap().to_string();
+        assert!(error.contains("need the http3 feature"), "{}", error);
+    }
+}
+
+#[cfg(feature = "http3")]
+#[actix_web::test]
+async fn http3_listeners_serve_the_api_and_are_advertised() {
+    use quinn::crypto::rustls::QuicClientConfig;
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::CertificateDer;
+    use std::sync::Arc;
+
+    let app = TestApp::spawn().await;
+    let mut tls = listener("127.0.0.1:0", ListenerRole::Public, true);
+    tls.http3 = true;
+    let settings = ServerSettings {
+        listeners: vec![tls],
+        ..app.state.settings.server.clone()
+    };
+    let bound = listeners::bind(&settings).unwrap();
+    let port = address(&bound[0]).port();
+    assert_eq!(bound[0].quic.as_ref().unwrap().local_addr().unwrap().port(), port);
+
+    let pipeline = Pipeline::from_settings(&app.state.settings).unwrap();
+    let handles: Vec<_> = listeners::start(app.state.clone(), pipeline, bound)
+        .unwrap()
+        .into_iter()
+        .map(|server| {
+            let handle = server.handle();
+            actix_web::rt::spawn(server);
+            handle
+        })
+        .collect();
+
+    let response = client().get(format!("https://localhost:{}/api/v1/health", port)).send().await.unwrap();
+    assert_eq!(response.headers()["alt-svc"], format!("h3=\":{}\"; ma=86400", port));
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(CertificateDer::from_pem_file(format!("{}/ca.pem", FIXTURES)).unwrap()).unwrap();
+    let mut crypto = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    crypto.alpn_protocols = vec![b"h3".to_vec()];
+    let mut endpoint = quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(
+        QuicClientConfig::try_from(crypto).unwrap(),
+    )));
+    let connection = endpoint
+        .connect(SocketAddr::from(([127, 0, 0, 1], port)), "localhost")
+        .unwrap()
+        .await
+        .unwrap();
+    let (mut driver, mut requests) = h3::client::new(h3_quinn::Connection::new(connection)).await.unwrap();
+    actix_web::rt::spawn(async move { std::future::poll_fn(|cx| driver.poll_close(cx)).await });
+
+    let request = http1::Request::get(format!("https://localhost:{}/api/v1/health", port)).body(()).unwrap();
+    let mut stream = requests.send_request(request).await.unwrap();
+    stream.finish().await.unwrap();
+    let response = stream.recv_response().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.version(), http1::Version::HTTP_3);
+
+    for handle in handles {
+        handle.stop(false).await;
+    }
+}
+
+#[test]
+fn unavailable_cores_are_rejected() {
+    let settings = CpuPinningSettings {
+        enabled: true,
+        cores: vec![usize::MAX],
+    };
+    assert!(CpuPinning::new(&settings).is_err());
+
+    let off = CpuPinningSettings {
+        enabled: false,
+        cores: vec![usize::MAX],
+    };
+    assert_eq!(CpuPinning::new(&off).unwrap().pin_current(), None);
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use futures_util::future::join_all;
+use std::sync::atomic::{AtomicU32, Ordering};
+use tokio::sync::Barrier;
+
+use actix_template::db::{find_for_update, retry_conflicts};
+use actix_template::errors::{AppResult, ErrorCode};
+use actix_template::factories::UserFactory;
+use actix_template::models::session::ClientContext;
+use actix_template::models::user::User;
+use common::TestApp;
+use platform_core::domain::UserId;
+
+fn client() -> ClientContext {
+    ClientContext {
+        user_agent: "locking-test".to_string(),
+        device_id: None,
+        ip: None,
+    }
+}
+
+/// Reads the counter kept in the user's full name and writes it back
+/// incremented, under a row lock.
+async fn increment(app: &TestApp, user_id: UserId) -> AppResult<()> {
+    let mut tx = app.state.db.begin().await?;
+    let user: User = find_for_update(&mut tx, user_id).await?.unwrap();
+    let count: u32 = user.full_name.unwrap().parse().unwrap();
+    // Give the other writers every chance to interleave
+    tokio::task::yield_now().await;
+    sqlx::query("UPDATE users SET full_name = $2 WHERE id = $1")
+        .bind(user_id)
+        .bind((count + 1).to_string())
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn locked_read_modify_writes_dont_lose_updates() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build().full_name(Some("0"))).await;
+
+    let results = join_all((0..10).map(|_| increment(&app, user.id))).await;
+    assert!(results.iter().all(Result::is_ok), "{:?}", results);
+
+    let user = app.state.user_queries.get_user_by_id(user.id).await.unwrap();
+    assert_eq!(user.full_name.as_deref(), Some("10"));
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn deadlocked_transactions_are_retried() {
+    let app = TestApp::spawn().await;
+    let first = app.insert_user(UserFactory::build()).await;
+    let second = app.insert_user(UserFactory::build()).await;
+    let barrier = Barrier::new(2);
+    let attempts = AtomicU32::new(0);
+
+    // Each locks both rows, in opposite orders; after both took their first
+    // lock Postgres aborts one of them, which then runs again
+    let lock_both = |a: UserId, b: UserId| {
+        let (app, barrier, attempts) = (&app, &barrier, &attempts);
+        retry_conflicts(move || async move {
+            let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+            let mut tx = app.state.db.begin().await?;
+            let _: Option<User> = find_for_update(&mut tx, a).await?;
+            if attempt < 2 {
+                barrier.wait().await;
+            }
+            let _: Option<User> = find_for_update(&mut tx, b).await?;
+            tx.commit().await?;
+            AppResult::Ok(())
+        })
+    };
+
+    let (one, other) = tokio::join!(lock_both(first.id, second.id), lock_both(second.id, first.id));
+    assert!(one.is_ok() && other.is_ok(), "{:?} {:?}", one, other);
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn concurrent_refreshes_rotate_a_token_once() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let sessions = &app.state.session_service;
+    let token = sessions.issue_refresh_token(user.id, &client()).await.unwrap();
+
+    let client = client();
+    let results = join_all((0..5).map(|_| sessions.rotate_refresh_token(&token, &client))).await;
+
+    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1, "{:?}", results);
+    // Losers find the token used, or its family already revoked by another
+    // loser
+    for error in results.iter().filter_map(|result| result.as_ref().err()) {
+        assert!(
+            matches!(error.code(), ErrorCode::AuthRefreshTokenReused | ErrorCode::AuthRefreshTokenInvalid),
+            "{:?}",
+            error
+        );
+    }
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use serde_json::json;
+use std::time::Duration;
+use uuid::Uuid;
+
+use common::{assert_status, authorized, test_epoch, TestApp};
+
+#[actix_web::test]
+async fn overrides_revert_to_the_default() {
+    let app = TestApp::spawn().await;
+    let log_level = &app.state.log_level;
+
+    let status = log_level.set("info,sqlx=debug", Some(Duration::from_millis(50))).unwrap();
+    assert_eq!(status.filter, "info,sqlx=debug");
+    assert_eq!(status.default_filter, "info");
+    assert_eq!(status.reverts_at, Some(test_epoch() + chrono::Duration::milliseconds(50)));
+    assert_eq!(log_level.status(), status);
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let status = log_level.status();
+    assert_eq!(status.filter, "info");
+    assert_eq!(status.reverts_at, None);
+}
+
+#[actix_web::test]
+async fn a_newer_override_outlives_the_one_it_replaced() {
+    let app = TestApp::spawn().await;
+    let log_level = &app.state.log_level;
+
+    log_level.set("debug", Some(Duration::from_millis(50))).unwrap();
+    log_level.set("info,sqlx=debug", None).unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let status = log_level.status();
+    assert_eq!(status.filter, "info,sqlx=debug");
+    assert_eq!(status.reverts_at, Some(test_epoch() + chrono::Duration::seconds(900)));
+
+    assert_eq!(log_level.reset().unwrap().filter, "info");
+}
+
+#[actix_web::test]
+async fn admin_routes_require_a_valid_filter_and_duration() {
+    let app = TestApp::spawn().await;
+    let token = app.admin_token_for(Uuid::new_v4());
+    let set = |body| authorized(TestRequest::put().uri("/api/v1/admin/log-level").set_json(body), &token);
+
+    let request = TestRequest::get().uri("/api/v1/admin/log-level");
+    assert_status(app.admin_request(request).await, StatusCode::UNAUTHORIZED);
+    // Public listeners don't serve them at all
+    let request = authorized(TestRequest::get().uri("/api/v1/admin/log-level"), &token);
+    assert_status(app.request(request).await, StatusCode::NOT_FOUND);
+
+    let response = app.admin_request(set(json!({ "filter": "sqlx=loud" }))).await;
+    assert_status(response, StatusCode::BAD_REQUEST);
+    let response = app.admin_request(set(json!({ "filter": "debug", "revert_after_secs": 0 }))).await;
+    assert_status(response, StatusCode::BAD_REQUEST);
+    let response = app.admin_request(set(json!({ "filter": "debug", "revert_after_secs": 86401 }))).await;
+    assert_status(response, StatusCode::BAD_REQUEST);
+
+    let request = authorized(TestRequest::get().uri("/api/v1/admin/log-level"), &token);
+    let response = assert_status(app.admin_request(request).await, StatusCode::OK);
+    assert_eq!(response.body, json!({ "filter": "info", "default_filter": "info", "reverts_at": null }));
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn log_level_changes_are_audited() {
+    let app = TestApp::spawn().await;
+    let (user, token) = app.insert_admin().await;
+
+    let body = json!({ "filter": "info,sqlx=debug", "revert_after_secs": 600 });
+    let request = authorized(TestRequest::put().uri("/api/v1/admin/log-level").set_json(body), &token);
+    let response = assert_status(app.admin_request(request).await, StatusCode::OK);
+    assert_eq!(response.body["filter"], "info,sqlx=debug");
+    assert_eq!(response.body["reverts_at"], json!(test_epoch() + chrono::Duration::seconds(600)));
+
+    let request = authorized(TestRequest::delete().uri("/api/v1/admin/log-level"), &token);
+    let response = assert_status(app.admin_request(request).await, StatusCode::OK);
+    assert_eq!(response.body["filter"], "info");
+
+    let changes: Vec<serde_json::Value> = sqlx::query_scalar(
+        "SELECT metadata FROM audit_events WHERE event_type = 'admin.log_level_changed' AND user_id = $1 ORDER BY created_at",
+    )
+    .bind(user.id)
+    .fetch_all(&app.state.db)
+    .await
+    .unwrap();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0]["previous"], "info");
+    assert_eq!(changes[0]["filter"], "info,sqlx=debug");
+    assert_eq!(changes[1]["previous"], "info,sqlx=debug");
+    assert_eq!(changes[1]["reverts_at"], json!(null));
+}
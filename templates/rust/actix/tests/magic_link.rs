@@ -0,0 +1,273 @@
+mod common;
+
+use actix_web::http::header::USER_AGENT;
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use actix_web::{web, App, HttpRequest, HttpResponse};
+use chrono::Duration;
+use platform_core::domain::Email;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+
+use actix_template::config::{MailProvider, MailSettings, SmtpTls};
+use actix_template::errors::ErrorCode;
+use actix_template::factories::UserFactory;
+use actix_template::mail::{self, ConsoleMailer, EmailMessage, Mailer, PostmarkMailer};
+use actix_template::models::session::ClientContext;
+use actix_template::services::MagicLinkService;
+use actix_template::templates::RenderedEmail;
+use common::{assert_status, local_egress, TestApp};
+
+fn mail_settings(provider: MailProvider) -> MailSettings {
+    MailSettings {
+        provider,
+        from_address: Some("Acme <no-reply@acme.test>".to_string()),
+        postmark_server_token: Some("server-token".to_string()),
+        postmark_api_url: "https://api.postmarkapp.com".to_string(),
+        smtp_host: Some("smtp.acme.test".to_string()),
+        smtp_port: 587,
+        smtp_username: Some("apikey".to_string()),
+        smtp_password: Some("secret".to_string()),
+        smtp_tls: SmtpTls::Starttls,
+        timeout_secs: 5,
+    }
+}
+
+fn message() -> EmailMessage {
+    EmailMessage {
+        to: Email::parse("ada@acme.test").unwrap(),
+        content: RenderedEmail {
+            subject: "Sign in to Acme".to_string(),
+            html: "<p>Hi</p>".to_string(),
+            text: "Hi".to_string(),
+        },
+    }
+}
+
+#[actix_web::test]
+async fn console_mailer_keeps_emails() {
+    let mailer = ConsoleMailer::default();
+
+    mailer.send(&message()).await.unwrap();
+
+    assert_eq!(mailer.outbox(), vec![message()]);
+}
+
+#[test]
+fn postmark_requires_credentials() {
+    let settings = MailSettings {
+        postmark_server_token: None,
+        ..mail_settings(MailProvider::Postmark)
+    };
+
+    let error = mail::from_settings(&settings, &local_egress()).err().unwrap();
+    assert!(error.to_string().contains("mail.postmark_server_token"), "{}", error);
+    assert!(mail::from_settings(&mail_settings(MailProvider::Console), &local_egress()).is_ok());
+}
+
+#[actix_web::test]
+async fn smtp_requires_a_host_and_both_credentials() {
+    let settings = MailSettings {
+        smtp_host: None,
+        ..mail_settings(MailProvider::Smtp)
+    };
+    let error = mail::from_settings(&settings, &local_egress()).err().unwrap();
+    assert!(error.to_string().contains("mail.smtp_host"), "{}", error);
+
+    let settings = MailSettings {
+        smtp_password: None,
+        ..mail_settings(MailProvider::Smtp)
+    };
+    let error = mail::from_settings(&settings, &local_egress()).err().unwrap();
+    assert!(error.to_string().contains("mail.smtp_password"), "{}", error);
+
+    // Doesn't connect until it sends
+    assert!(mail::from_settings(&mail_settings(MailProvider::Smtp), &local_egress()).is_ok());
+}
+
+#[actix_web::test]
+async fn postmark_posts_to_the_email_api() {
+    type Captured = Arc<Mutex<Option<(String, String, serde_json::Value)>>>;
+    let captured: Captured = Arc::default();
+
+    let server = {
+        let captured = captured.clone();
+        actix_test::start(move || {
+            let captured = captured.clone();
+            App::new().default_service(web::to(move |req: HttpRequest, body: web::Json<serde_json::Value>| {
+                let token = req.headers().get("X-Postmark-Server-Token").unwrap().to_str().unwrap().to_string();
+                *captured.lock().unwrap() = Some((req.path().to_string(), token, body.into_inner()));
+                async { HttpResponse::Ok().json(json!({ "ErrorCode": 0 })) }
+            }))
+        })
+    };
+    let mailer = PostmarkMailer::from_settings(
+        &MailSettings {
+            postmark_api_url: server.url(""),
+            ..mail_settings(MailProvider::Postmark)
+        },
+        &local_egress(),
+    )
+    .unwrap();
+
+    mailer.send(&message()).await.unwrap();
+
+    let (path, token, body) = captured.lock().unwrap().take().unwrap();
+    assert_eq!(path, "/email");
+    assert_eq!(token, "server-token");
+    assert_eq!(body["From"], "Acme <no-reply@acme.test>");
+    assert_eq!(body["To"], "ada@acme.test");
+    assert_eq!(body["Subject"], "Sign in to Acme");
+    assert_eq!(body["HtmlBody"], "<p>Hi</p>");
+    assert_eq!(body["TextBody"], "Hi");
+}
+
+#[actix_web::test]
+async fn postmark_errors_are_reported_as_delivery_failures() {
+    let server = actix_test::start(|| App::new().default_service(web::to(HttpResponse::UnprocessableEntity)));
+    let mailer = PostmarkMailer::from_settings(
+        &MailSettings {
+            postmark_api_url: server.url(""),
+            ..mail_settings(MailProvider::Postmark)
+        },
+        &local_egress(),
+    )
+    .unwrap();
+
+    let error = mailer.send(&message()).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::EmailDeliveryFailed);
+}
+
+#[actix_web::test]
+async fn magic_links_are_off_by_default() {
+    let app = TestApp::spawn().await;
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/magic-link")
+        .set_json(json!({ "email": "ada@acme.test" }));
+    assert_status(app.request(request).await, StatusCode::NOT_FOUND);
+
+    let request = TestRequest::get().uri("/api/v1/auth/magic-link/verify?token=abc");
+    assert_status(app.request(request).await, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn magic_link_requests_reject_malformed_emails() {
+    let app = TestApp::spawn_with(|config| config.set_override("magic_link.enabled", true).unwrap()).await;
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/magic-link")
+        .set_json(json!({ "email": "not-an-email" }));
+    let response = assert_status(app.request(request).await, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error_code"], "VALIDATION_FAILED");
+}
+
+#[actix_web::test]
+async fn forged_tokens_are_rejected_before_the_database() {
+    let app = TestApp::spawn_with(|config| config.set_override("magic_link.enabled", true).unwrap()).await;
+
+    let request = TestRequest::get().uri("/api/v1/auth/magic-link/verify?token=v1.e30.c2lnbmF0dXJl");
+    let response = assert_status(app.request(request).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "MAGIC_LINK_INVALID");
+}
+
+fn client(user_agent: &str) -> ClientContext {
+    ClientContext {
+        user_agent: user_agent.to_string(),
+        device_id: None,
+        ip: None,
+    }
+}
+
+fn magic_link_service(app: &TestApp, mailer: Arc<ConsoleMailer>) -> MagicLinkService {
+    MagicLinkService::new(
+        app.state.db.clone(),
+        mailer,
+        app.state.signing_keys.clone(),
+        app.state.settings.magic_link.clone(),
+        app.state.settings.email.clone(),
+        app.clock.clone(),
+    )
+}
+
+fn last_token(mailer: &ConsoleMailer) -> String {
+    let email = mailer.outbox().pop().expect("an email");
+    let (_, token) = email.content.text.split_once("?token=").expect("a link");
+    token.split_whitespace().next().unwrap().to_string()
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn links_are_single_use_device_bound_and_rate_limited() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let mailer = Arc::new(ConsoleMailer::default());
+    let service = magic_link_service(&app, mailer.clone());
+    let (laptop, phone) = (client("laptop"), client("phone"));
+
+    // Once, and only on the requesting device
+    service.send_link(&user, &laptop).await.unwrap();
+    assert_eq!(mailer.outbox()[0].to, user.email);
+    let token = last_token(&mailer);
+    let error = service.verify_link(&token, &phone).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::MagicLinkDeviceMismatch);
+    assert_eq!(service.verify_link(&token, &laptop).await.unwrap(), user.id);
+    let error = service.verify_link(&token, &laptop).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::MagicLinkInvalid);
+
+    // A new link voids the previous one
+    service.send_link(&user, &laptop).await.unwrap();
+    let voided = last_token(&mailer);
+    service.send_link(&user, &laptop).await.unwrap();
+    assert!(service.verify_link(&voided, &laptop).await.is_err());
+
+    // Three links per window
+    let error = service.send_link(&user, &laptop).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::MagicLinkRateLimited);
+
+    // Expired
+    app.clock.advance(Duration::seconds(app.state.settings.magic_link.send_window_secs + 1));
+    service.send_link(&user, &laptop).await.unwrap();
+    let token = last_token(&mailer);
+    app.clock.advance(Duration::seconds(app.state.settings.magic_link.link_ttl_secs + 1));
+    assert!(service.verify_link(&token, &laptop).await.is_err());
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn links_are_exchanged_for_tokens() {
+    let app = TestApp::spawn_with(|config| config.set_override("magic_link.enabled", true).unwrap()).await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let mailer = Arc::new(ConsoleMailer::default());
+    magic_link_service(&app, mailer.clone())
+        .send_link(&user, &client("laptop"))
+        .await
+        .unwrap();
+    let verify = |user_agent: &str| {
+        TestRequest::get()
+            .uri(&format!("/api/v1/auth/magic-link/verify?token={}", last_token(&mailer)))
+            .insert_header((USER_AGENT, user_agent))
+    };
+
+    let response = assert_status(app.request(verify("phone")).await, StatusCode::FORBIDDEN);
+    assert_eq!(response.body["error_code"], "MAGIC_LINK_DEVICE_MISMATCH");
+
+    let response = assert_status(app.request(verify("laptop")).await, StatusCode::OK);
+    assert_eq!(response.body["token_type"], "Bearer");
+    assert_eq!(response.body["user"]["id"], user.id.to_string());
+    assert!(response.body["refresh_token"].is_string());
+
+    let response = assert_status(app.request(verify("laptop")).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "MAGIC_LINK_INVALID");
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn requests_for_unknown_addresses_are_accepted() {
+    let app = TestApp::spawn_with(|config| config.set_override("magic_link.enabled", true).unwrap()).await;
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/magic-link")
+        .set_json(json!({ "email": "nobody@acme.test" }));
+    assert_status(app.request(request).await, StatusCode::ACCEPTED);
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+
+use actix_template::commands;
+use actix_template::manifest::{Manifest, TEMPLATE_NAME};
+use common::{assert_status, TestApp};
+
+#[actix_web::test]
+async fn info_serves_the_manifest_on_every_listener() {
+    let app = TestApp::spawn().await;
+    let manifest = serde_json::to_value(Manifest::current()).unwrap();
+
+    let response = assert_status(app.request(TestRequest::get().uri("/api/v1/info")).await, StatusCode::OK);
+    assert_eq!(response.body, manifest);
+    let response = assert_status(app.admin_request(TestRequest::get().uri("/api/v1/info")).await, StatusCode::OK);
+    assert_eq!(response.body, manifest);
+}
+
+#[test]
+fn the_manifest_is_filled_in_at_build_time() {
+    let manifest = Manifest::current();
+
+    assert_eq!(manifest.template, TEMPLATE_NAME);
+    assert_eq!(manifest.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(manifest.features.contains(&"console".to_string()), cfg!(feature = "console"));
+    assert_eq!(manifest.api_hash.len(), 64);
+    assert!(manifest.api_hash.bytes().all(|byte| byte.is_ascii_hexdigit()));
+}
+
+#[actix_web::test]
+async fn the_manifest_flag_runs_instead_of_the_server() {
+    assert!(matches!(commands::run(&["--manifest".to_string()]).await, Some(Ok(()))));
+    assert!(commands::run(&[]).await.is_none());
+}
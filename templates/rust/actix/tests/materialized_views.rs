@@ -0,0 +1,110 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use chrono::Duration;
+
+use actix_template::config::MaterializedViewSettings;
+use actix_template::errors::AppError;
+use actix_template::factories::UserFactory;
+use actix_template::materialized_views::MaterializedViews;
+use common::{assert_status, authorized, TestApp};
+
+fn views(app: &TestApp, settings: MaterializedViewSettings) -> Result<MaterializedViews, config::ConfigError> {
+    MaterializedViews::new(app.state.db.clone(), settings, app.state.job_queue.clone(), app.clock.clone())
+}
+
+#[actix_web::test]
+async fn views_must_be_plain_names_with_an_interval() {
+    let app = TestApp::spawn().await;
+
+    let mut settings = app.state.settings.materialized_views.clone();
+    let view = settings.views["daily_signups"].clone();
+    settings.views.insert("signups; DROP TABLE users".to_string(), view.clone());
+    assert!(views(&app, settings).is_err());
+
+    let mut settings = app.state.settings.materialized_views.clone();
+    let mut view = view;
+    view.interval_secs = 0;
+    settings.views.insert("daily_signups".to_string(), view);
+    assert!(views(&app, settings).is_err());
+}
+
+#[actix_web::test]
+async fn unconfigured_views_are_neither_refreshed_nor_read() {
+    let app = TestApp::spawn().await;
+    let mut settings = app.state.settings.materialized_views.clone();
+    settings.views.remove("daily_signups");
+    let views = views(&app, settings).unwrap();
+
+    // Rejected before anything touches the database
+    assert!(matches!(views.refresh("daily_signups").await.unwrap_err(), AppError::NotFound(_)));
+    assert!(matches!(views.rows("users", 10).await.unwrap_err(), AppError::NotFound(_)));
+}
+
+#[actix_web::test]
+async fn stats_limits_are_bounded() {
+    let app = TestApp::spawn().await;
+    let user = UserFactory::build().into_user();
+    let token = app.token_for_user(&user);
+
+    for limit in [0, 1001] {
+        let uri = format!("/api/v1/stats/daily_signups?limit={}", limit);
+        let response = app.request(authorized(TestRequest::get().uri(&uri), &token)).await;
+        assert_status(response, StatusCode::BAD_REQUEST);
+    }
+
+    let response = app.request(TestRequest::get().uri("/api/v1/stats/daily_signups")).await;
+    assert_status(response, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn views_are_scheduled_once_per_interval() {
+    let app = TestApp::spawn().await;
+    sqlx::query("DELETE FROM materialized_view_refreshes").execute(&app.state.db).await.unwrap();
+    let views = &app.state.materialized_views;
+
+    assert_eq!(views.schedule_due().await.unwrap(), ["daily_active_users", "daily_signups"]);
+    assert!(views.schedule_due().await.unwrap().is_empty());
+
+    app.clock.advance(Duration::seconds(
+        app.state.settings.materialized_views.views["daily_signups"].interval_secs,
+    ));
+    assert_eq!(views.schedule_due().await.unwrap().len(), 2);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn refreshed_views_are_served_as_stats() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let token = app.token_for_user(&user);
+
+    app.state.materialized_views.refresh("daily_signups").await.unwrap();
+
+    let (duration_ms, error): (Option<i64>, Option<String>) = sqlx::query_as(
+        "SELECT last_duration_ms, last_error FROM materialized_view_refreshes WHERE view_name = 'daily_signups'",
+    )
+    .fetch_one(&app.state.db)
+    .await
+    .unwrap();
+    assert!(duration_ms.is_some());
+    assert_eq!(error, None);
+
+    let response = app
+        .request(authorized(TestRequest::get().uri("/api/v1/stats/daily_signups?limit=1"), &token))
+        .await;
+    let response = assert_status(response, StatusCode::OK);
+    assert_eq!(response.body["view"], "daily_signups");
+    assert!(response.body["refreshed_at"].is_string());
+    let rows = response.body["rows"].as_array().unwrap();
+    assert_eq!(rows.len(), 1);
+    assert!(rows[0]["day"].is_string());
+    assert!(rows[0]["signups"].as_i64().unwrap() >= 1);
+
+    let response = app
+        .request(authorized(TestRequest::get().uri("/api/v1/stats/users"), &token))
+        .await;
+    assert_status(response, StatusCode::NOT_FOUND);
+}
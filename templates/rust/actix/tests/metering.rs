@@ -0,0 +1,147 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use chrono::Duration;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use actix_template::dry_run::DryRun;
+use actix_template::factories::UserFactory;
+use actix_template::metering::{ConsoleUsageExporter, Meter, Metric};
+use actix_template::models::api_token::{CreateApiToken, Scope};
+use actix_template::models::job::Job;
+use common::{assert_status, authorized, test_epoch, TestApp};
+
+#[actix_web::test]
+async fn nothing_is_counted_unless_enabled() {
+    let app = TestApp::spawn().await;
+
+    app.state.meter.record(Uuid::new_v4().into(), None, Metric::Requests, 1);
+    // Nothing to write, so no database needed
+    assert_eq!(app.state.meter.flush().await.unwrap(), 0);
+}
+
+#[actix_web::test]
+async fn usage_is_read_a_bounded_span_at_a_time() {
+    let app = TestApp::spawn().await;
+    let token = app.admin_token_for(Uuid::new_v4());
+    let usage = |query: &str| authorized(TestRequest::get().uri(&format!("/api/v1/admin/usage{}", query)), &token);
+
+    let request = TestRequest::get().uri("/api/v1/admin/usage");
+    assert_status(app.admin_request(request).await, StatusCode::UNAUTHORIZED);
+
+    let backwards = usage("?from=2024-01-02T00:00:00Z&to=2024-01-01T00:00:00Z");
+    assert_status(app.admin_request(backwards).await, StatusCode::BAD_REQUEST);
+    let too_long = usage("?from=2023-01-01T00:00:00Z&to=2024-01-01T00:00:00Z");
+    assert_status(app.admin_request(too_long).await, StatusCode::BAD_REQUEST);
+    let unknown_metric = usage("?metric=bandwidth");
+    assert_status(app.admin_request(unknown_metric).await, StatusCode::BAD_REQUEST);
+}
+
+fn completed_job(payload: Value) -> Job {
+    Job {
+        id: Uuid::new_v4(),
+        kind: "push.fan_out".to_string(),
+        queue: "default".to_string(),
+        rate_key: None,
+        payload,
+        status: "completed".to_string(),
+        attempts: 1,
+        max_attempts: 5,
+        run_at: test_epoch(),
+        locked_at: None,
+        started_at: Some(test_epoch()),
+        last_error: None,
+        finished_at: Some(test_epoch()),
+        created_at: test_epoch(),
+    }
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn usage_is_aggregated_by_hour_and_exported_once_closed() {
+    let app = TestApp::spawn_with(|config| config.set_override("metering.enabled", true).unwrap()).await;
+    let meter = &app.state.meter;
+    let user = app.insert_user(UserFactory::build()).await;
+    let api_token = app
+        .state
+        .api_token_service
+        .create_token(
+            user.id,
+            CreateApiToken {
+                name: "metered".to_string(),
+                scopes: vec![Scope::ReadUsers],
+                expires_in_days: None,
+                signing: false,
+            },
+            DryRun::default(),
+        )
+        .await
+        .unwrap();
+
+    let profile = format!("/api/v1/users/{}", user.id);
+    for token in [&api_token.token, &api_token.token, &app.token_for_user(&user)] {
+        let response = app.request(authorized(TestRequest::get().uri(&profile), token)).await;
+        assert_status(response, StatusCode::OK);
+    }
+    meter.record_job(&completed_job(json!({ "user_id": user.id })));
+    meter.record_job(&completed_job(json!({ "saga_id": Uuid::new_v4() })));
+    meter.flush().await.unwrap();
+    assert!(meter.measure_storage().await.unwrap());
+    assert!(!meter.measure_storage().await.unwrap());
+
+    let admin = app.admin_token_for(Uuid::new_v4());
+    let request = authorized(
+        TestRequest::get().uri(&format!("/api/v1/admin/usage?user_id={}", user.id)),
+        &admin,
+    );
+    let response = assert_status(app.admin_request(request).await, StatusCode::OK);
+    let records = response.body["records"].as_array().unwrap();
+    let usage: Vec<(&str, Option<&str>, i64)> = records
+        .iter()
+        .map(|record| {
+            assert_eq!(record["hour"], "2024-01-01T00:00:00Z");
+            (
+                record["metric"].as_str().unwrap(),
+                record["api_token_id"].as_str(),
+                record["quantity"].as_i64().unwrap(),
+            )
+        })
+        .filter(|(metric, ..)| *metric != "storage_bytes")
+        .collect();
+    let token_id = api_token.api_token.id.to_string();
+    assert_eq!(
+        usage,
+        [("jobs_executed", None, 1), ("requests", None, 1), ("requests", Some(token_id.as_str()), 2)]
+    );
+    let storage = records.iter().find(|record| record["metric"] == "storage_bytes").unwrap();
+    assert!(storage["quantity"].as_i64().unwrap() > 0);
+
+    // Counts made later in the hour add to its records
+    let response = app.request(authorized(TestRequest::get().uri(&profile), &api_token.token)).await;
+    assert_status(response, StatusCode::OK);
+    meter.flush().await.unwrap();
+
+    // Hours are exported once over and every replica has flushed them
+    let exporter = Arc::new(ConsoleUsageExporter::default());
+    let exporting = Meter::new(
+        app.state.db.clone(),
+        app.state.settings.metering.clone(),
+        Some(exporter.clone()),
+        app.clock.clone(),
+    );
+    assert_eq!(exporting.export_closed().await.unwrap(), 0);
+
+    app.clock.advance(Duration::hours(2));
+    assert!(exporting.export_closed().await.unwrap() >= 4);
+    let exported: Vec<_> = exporter.outbox().into_iter().filter(|record| record.user_id == user.id).collect();
+    assert_eq!(exported.len(), 4);
+    let requests = exported
+        .iter()
+        .find(|record| record.metric == "requests" && record.api_token_id == Some(api_token.api_token.id))
+        .unwrap();
+    assert_eq!(requests.quantity, 3);
+    assert_eq!(exporting.export_closed().await.unwrap(), 0);
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use actix_web::http::header::X_FRAME_OPTIONS;
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use serde_json::json;
+use std::collections::HashMap;
+
+use actix_template::config::MiddlewareSettings;
+use actix_template::middleware::Pipeline;
+use common::{assert_status, TestApp};
+
+fn middleware(names: &[&str]) -> Vec<MiddlewareSettings> {
+    names
+        .iter()
+        .map(|name| serde_json::from_value(json!({ "name": name })).unwrap())
+        .collect()
+}
+
+async fn pipeline(names: &[&str]) -> Result<Pipeline, String> {
+    let app = TestApp::spawn().await;
+    Pipeline::new(middleware(names), app.state.settings.security.clone()).map_err(|e| e.to_string())
+}
+
+#[actix_web::test]
+async fn the_default_stack_is_complete() {
+    let app = TestApp::spawn().await;
+
+    let pipeline = Pipeline::from_settings(&app.state.settings).unwrap();
+    assert_eq!(pipeline.names(), ["tracing", "request_id", "logger", "security"]);
+
+    let response = assert_status(app.request(TestRequest::get().uri("/api/v1/health")).await, StatusCode::OK);
+    assert!(response.headers.contains_key("x-request-id"));
+    assert_eq!(response.headers.get(X_FRAME_OPTIONS).unwrap(), "DENY");
+}
+
+#[actix_web::test]
+async fn only_listed_middleware_is_installed() {
+    let app = TestApp::spawn_with(|config| {
+        let middleware = vec![HashMap::from([("name".to_string(), "request_id")])];
+        config.set_override("server.middleware", middleware).unwrap()
+    })
+    .await;
+
+    let response = assert_status(app.request(TestRequest::get().uri("/api/v1/health")).await, StatusCode::OK);
+    assert!(response.headers.contains_key("x-request-id"));
+    assert!(!response.headers.contains_key(X_FRAME_OPTIONS));
+}
+
+#[actix_web::test]
+async fn logger_takes_a_format() {
+    let logger: MiddlewareSettings = serde_json::from_value(json!({ "name": "logger", "format": "%r %s" })).unwrap();
+    assert_eq!(logger, MiddlewareSettings::Logger { format: Some("%r %s".to_string()) });
+
+    let app = TestApp::spawn().await;
+    let pipeline = Pipeline::new(vec![MiddlewareSettings::RequestId, logger], app.state.settings.security.clone());
+    assert!(pipeline.is_ok());
+}
+
+#[actix_web::test]
+async fn invalid_stacks_are_rejected() {
+    let duplicate = pipeline(&["request_id", "security", "request_id"]).await.unwrap_err();
+    assert!(duplicate.contains("request_id is listed more than once"), "{}", duplicate);
+
+    let late_tracing = pipeline(&["request_id", "tracing"]).await.unwrap_err();
+    assert!(late_tracing.contains("tracing must come first"), "{}", late_tracing);
+
+    let misordered = pipeline(&["security", "request_id"]).await.unwrap_err();
+    assert!(misordered.contains("request_id must come before security"), "{}", misordered);
+
+    let anonymous_events = pipeline(&["request_events", "request_id"]).await.unwrap_err();
+    assert!(anonymous_events.contains("request_id must come before request_events"), "{}", anonymous_events);
+
+    assert!(pipeline(&[]).await.is_ok());
+    assert!(pipeline(&["security"]).await.is_ok());
+
+    let unknown = serde_json::from_value::<MiddlewareSettings>(json!({ "name": "compression" }));
+    assert!(unknown.is_err());
+}
@@ -0,0 +1,178 @@
+mod common;
+
+use actix_web::{test::TestRequest, web, App, HttpRequest, HttpResponse};
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use actix_template::config::OlapSettings;
+use actix_template::errors::{AppError, AppResult};
+use actix_template::olap::{DomainEvent, EventBuffer, EventSink, OlapEvent};
+use common::TestApp;
+
+type Received = Arc<Mutex<Vec<(HashMap<String, String>, Option<String>, String)>>>;
+
+/// A ClickHouse stand-in recording inserts with their query parameters,
+/// user and body.
+fn clickhouse() -> (actix_test::TestServer, Received) {
+    let received: Received = Arc::default();
+    let server = {
+        let received = received.clone();
+        actix_test::start(move || {
+            let received = received.clone();
+            App::new().route(
+                "/",
+                web::post().to(move |req: HttpRequest, query: web::Query<HashMap<String, String>>, body: String| {
+                    let user = req
+                        .headers()
+                        .get("x-clickhouse-user")
+                        .map(|value| value.to_str().unwrap().to_string());
+                    received.lock().unwrap().push((query.into_inner(), user, body));
+                    async { HttpResponse::Ok().finish() }
+                }),
+            )
+        })
+    };
+    (server, received)
+}
+
+#[actix_web::test]
+async fn requests_are_written_to_clickhouse() {
+    let (server, received) = clickhouse();
+    let url = server.url("");
+    let app = TestApp::spawn_with(move |config| {
+        let middleware = vec![
+            HashMap::from([("name".to_string(), "request_id")]),
+            HashMap::from([("name".to_string(), "request_events")]),
+        ];
+        config
+            .set_override("server.middleware", middleware)
+            .unwrap()
+            .set_override("olap.provider", "clickhouse")
+            .unwrap()
+            .set_override("olap.clickhouse_url", url)
+            .unwrap()
+            .set_override("olap.clickhouse_database", "telemetry")
+            .unwrap()
+            .set_override("olap.clickhouse_user", "writer")
+            .unwrap()
+    })
+    .await;
+
+    app.request(TestRequest::get().uri("/api/v1/health")).await;
+    app.request(TestRequest::get().uri("/api/v1/nowhere")).await;
+    assert_eq!(app.state.olap_events.flush().await, 2);
+
+    let received = received.lock().unwrap();
+    let [(query, user, body)] = received.as_slice() else {
+        panic!("expected one insert, got {:?}", received);
+    };
+    assert_eq!(query["query"], "INSERT INTO `telemetry`.`request_events` FORMAT JSONEachRow");
+    assert_eq!(user.as_deref(), Some("writer"));
+
+    let rows: Vec<Value> = body.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(rows[0]["route"], "/api/v1/health");
+    assert_eq!(rows[0]["method"], "GET");
+    assert_eq!(rows[0]["status"], 200);
+    assert!(rows[0]["request_id"].is_string());
+    assert!(rows[1]["route"].is_null());
+    assert_eq!(rows[1]["status"], 404);
+}
+
+#[actix_web::test]
+async fn nothing_is_recorded_without_a_provider() {
+    let app = TestApp::spawn().await;
+    assert!(!app.state.olap_events.enabled());
+
+    app.state.olap_events.record(domain_event("user.created"));
+    assert_eq!(app.state.olap_events.flush().await, 0);
+    assert_eq!(app.state.olap_events.dropped(), 0);
+}
+
+/// Fails the first `failures` writes, then records the rows it gets.
+#[derive(Default)]
+struct FlakySink {
+    failures: u32,
+    attempts: AtomicU32,
+    written: Mutex<Vec<(String, usize)>>,
+}
+
+impl EventSink for FlakySink {
+    fn write<'a>(&'a self, table: &'a str, rows: &'a [Value]) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            if self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(AppError::InternalServerError);
+            }
+            self.written.lock().unwrap().push((table.to_string(), rows.len()));
+            Ok(())
+        })
+    }
+}
+
+fn domain_event(event_type: &str) -> OlapEvent {
+    OlapEvent::Domain(DomainEvent {
+        timestamp: Utc::now(),
+        event_type: event_type.to_string(),
+        user_id: None,
+        metadata: json!({}).to_string(),
+    })
+}
+
+async fn settings() -> OlapSettings {
+    TestApp::spawn().await.state.settings.olap.clone()
+}
+
+#[actix_web::test]
+async fn events_are_dropped_once_the_buffer_is_full() {
+    let sink = Arc::new(FlakySink::default());
+    let buffer = EventBuffer::new(
+        Some(sink.clone()),
+        OlapSettings {
+            buffer_size: 3,
+            batch_size: 2,
+            ..settings().await
+        },
+    );
+
+    for _ in 0..5 {
+        buffer.record(domain_event("user.created"));
+    }
+    assert_eq!(buffer.dropped(), 2);
+
+    assert_eq!(buffer.flush().await, 3);
+    assert_eq!(
+        *sink.written.lock().unwrap(),
+        [("domain_events".to_string(), 2), ("domain_events".to_string(), 1)]
+    );
+}
+
+#[actix_web::test]
+async fn failed_batches_are_retried_then_dropped() {
+    let settings = OlapSettings {
+        max_attempts: 2,
+        ..settings().await
+    };
+
+    let recovering = Arc::new(FlakySink {
+        failures: 1,
+        ..FlakySink::default()
+    });
+    let buffer = EventBuffer::new(Some(recovering.clone()), settings.clone());
+    buffer.record(domain_event("user.created"));
+    assert_eq!(buffer.flush().await, 1);
+    assert_eq!(recovering.attempts.load(Ordering::SeqCst), 2);
+
+    let failing = Arc::new(FlakySink {
+        failures: u32::MAX,
+        ..FlakySink::default()
+    });
+    let buffer = EventBuffer::new(Some(failing.clone()), settings);
+    buffer.record(domain_event("user.created"));
+    buffer.record(domain_event("user.deleted"));
+    assert_eq!(buffer.flush().await, 0);
+    assert_eq!(failing.attempts.load(Ordering::SeqCst), 2);
+    assert_eq!(buffer.dropped(), 2);
+}
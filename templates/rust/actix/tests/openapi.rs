@@ -0,0 +1,107 @@
+mod common;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::test::TestRequest;
+use std::collections::BTreeSet;
+
+use actix_template::factories::UserFactory;
+use actix_template::handlers::health::HealthResponse;
+use actix_template::models::user::UserResponse;
+use common::{assert_status, TestApp};
+
+async fn spec(app: &TestApp) -> serde_json::Value {
+    let response = app.request(TestRequest::get().uri("/api-docs/openapi.json")).await;
+    assert_status(response, StatusCode::OK).body
+}
+
+fn properties(spec: &serde_json::Value, schema: &str) -> BTreeSet<String> {
+    spec["components"]["schemas"][schema]["properties"]
+        .as_object()
+        .unwrap_or_else(|| panic!("no schema for {}", schema))
+        .keys()
+        .cloned()
+        .collect()
+}
+
+fn keys(value: impl serde::Serialize) -> BTreeSet<String> {
+    serde_json::to_value(value).unwrap().as_object().unwrap().keys().cloned().collect()
+}
+
+#[actix_web::test]
+async fn documents_the_user_and_health_endpoints() {
+    let app = TestApp::spawn().await;
+    let spec = spec(&app).await;
+
+    let operations: BTreeSet<String> = spec["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .flat_map(|(path, item)| item.as_object().unwrap().keys().map(move |method| format!("{} {}", method, path)))
+        .collect();
+    for operation in [
+        "get /api/v1/health",
+        "get /api/v1/ready",
+        "post /api/v1/auth/register",
+        "post /api/v1/auth/login",
+        "post /api/v1/auth/refresh",
+        "post /api/v1/auth/logout",
+        "post /api/v1/auth/verify-email",
+        "post /api/v1/auth/resend-verification",
+        "post /api/v1/auth/forgot-password",
+        "post /api/v1/auth/reset-password",
+        "get /api/v1/users",
+        "post /api/v1/users",
+        "get /api/v1/users/{id}",
+        "put /api/v1/users/{id}",
+        "delete /api/v1/users/{id}",
+    ] {
+        assert!(operations.contains(operation), "{} is not documented", operation);
+    }
+    assert_eq!(spec["components"]["securitySchemes"]["bearer_auth"]["scheme"], "bearer");
+}
+
+#[actix_web::test]
+async fn documented_operations_are_routed() {
+    let app = TestApp::spawn().await;
+    let spec = spec(&app).await;
+
+    for (path, item) in spec["paths"].as_object().unwrap() {
+        let uri = path.replace("{id}", &uuid::Uuid::new_v4().to_string());
+        for method in item.as_object().unwrap().keys() {
+            let request = TestRequest::default().method(method.to_uppercase().parse().unwrap()).uri(&uri);
+            let status = app.request(request).await.status;
+            assert!(
+                status != StatusCode::NOT_FOUND && status != StatusCode::METHOD_NOT_ALLOWED,
+                "{} {} is documented but not routed",
+                method,
+                path
+            );
+        }
+    }
+}
+
+#[actix_web::test]
+async fn schemas_match_the_serialized_models() {
+    let app = TestApp::spawn().await;
+    let spec = spec(&app).await;
+
+    let user = UserResponse::from(UserFactory::build().into_user());
+    assert_eq!(properties(&spec, "UserResponse"), keys(user));
+
+    let health = HealthResponse {
+        status: "healthy".to_string(),
+        version: "0.1.0".to_string(),
+        timestamp: "2024-05-01T12:00:00Z".to_string(),
+    };
+    assert_eq!(properties(&spec, "HealthResponse"), keys(health));
+}
+
+#[actix_web::test]
+async fn swagger_ui_may_load_its_assets() {
+    let app = TestApp::spawn().await;
+
+    let response = app.request(TestRequest::get().uri("/swagger-ui/")).await;
+    let response = assert_status(response, StatusCode::OK);
+    let csp = response.headers[header::CONTENT_SECURITY_POLICY].to_str().unwrap();
+    assert!(csp.starts_with("default-src 'self'"));
+}
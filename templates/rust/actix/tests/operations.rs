@@ -0,0 +1,105 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use actix_template::errors::{AppError, ErrorCode};
+use actix_template::factories::UserFactory;
+use actix_template::models::operation::OperationResponse;
+use common::{assert_status, authorized, TestApp};
+
+/// The `data` of every `operation` event in an SSE body.
+fn operation_events(body: &Value) -> Vec<OperationResponse> {
+    body.as_str()
+        .expect("an event stream")
+        .split("\n\n")
+        .filter_map(|event| event.strip_prefix("event: operation\ndata: "))
+        .map(|data| serde_json::from_str(data).unwrap())
+        .collect()
+}
+
+#[actix_web::test]
+async fn operations_require_authentication() {
+    let app = TestApp::spawn().await;
+    let id = Uuid::new_v4();
+
+    for uri in [format!("/api/v1/operations/{}", id), format!("/api/v1/operations/{}/events", id)] {
+        assert_status(app.request(TestRequest::get().uri(&uri)).await, StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn operations_are_served_in_the_aip_shape() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let service = &app.state.operation_service;
+    let operation = service.start(user.id, "export", json!({ "format": "csv" })).await.unwrap();
+    let get = |token: &str| authorized(TestRequest::get().uri(&format!("/api/v1/operations/{}", operation.id)), token);
+
+    let response = assert_status(app.request(get(&app.token_for_user(&user))).await, StatusCode::OK);
+    assert_eq!(response.body["name"], format!("operations/{}", operation.id));
+    assert_eq!(response.body["done"], false);
+    assert_eq!(response.body["metadata"]["kind"], "export");
+    assert_eq!(response.body["metadata"]["details"], json!({ "format": "csv" }));
+    assert!(response.body.get("response").is_none());
+
+    service.report_progress(operation.id, 150, Some("Almost")).await.unwrap();
+    let response = assert_status(app.request(get(&app.token_for_user(&user))).await, StatusCode::OK);
+    assert_eq!(response.body["metadata"]["progress_percent"], 100);
+
+    service.fail(operation.id, &AppError::InternalServerError).await.unwrap();
+    let response = assert_status(app.request(get(&app.token_for_user(&user))).await, StatusCode::OK);
+    assert_eq!(response.body["done"], true);
+    assert_eq!(response.body["error"]["code"], ErrorCode::Internal.as_str());
+    assert_eq!(response.body["error"]["message"], ErrorCode::Internal.description());
+
+    let other = app.insert_user(UserFactory::build()).await;
+    let response = assert_status(app.request(get(&app.token_for_user(&other))).await, StatusCode::NOT_FOUND);
+    assert_eq!(response.body["error_code"], "OPERATION_NOT_FOUND");
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn progress_is_streamed_until_done() {
+    let app = TestApp::spawn_with(|config| config.set_override("operations.watch_poll_interval_ms", 50).unwrap()).await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let service = app.state.operation_service.clone();
+    let operation = service.start(user.id, "import", json!({})).await.unwrap();
+
+    let mut updates = service.clone().watch(user.id, operation.id).boxed();
+    assert_eq!(updates.next().await.unwrap().unwrap().progress_percent, 0);
+    service.report_progress(operation.id, 50, Some("Halfway")).await.unwrap();
+    let update = updates.next().await.unwrap().unwrap();
+    assert_eq!(update.progress_message.as_deref(), Some("Halfway"));
+
+    let worker = {
+        let service = Arc::clone(&service);
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            service.succeed(operation.id, json!({ "imported": 10 })).await.unwrap();
+        })
+    };
+
+    let request = authorized(
+        TestRequest::get().uri(&format!("/api/v1/operations/{}/events", operation.id)),
+        &app.token_for_user(&user),
+    );
+    let response = assert_status(app.request(request).await, StatusCode::OK);
+    worker.await.unwrap();
+
+    assert_eq!(response.headers.get("content-type").unwrap(), "text/event-stream");
+    let events = operation_events(&response.body);
+    assert_eq!(events.first().unwrap().metadata.progress_percent, 50);
+    let last = events.last().unwrap();
+    assert!(last.done);
+    assert_eq!(last.response, Some(json!({ "imported": 10 })));
+
+    // The watch above ends with the same update
+    assert!(updates.next().await.unwrap().unwrap().done);
+    assert!(updates.next().await.is_none());
+}
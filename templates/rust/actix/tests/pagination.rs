@@ -0,0 +1,58 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use serde_json::Value;
+
+use actix_template::factories::UserFactory;
+use common::{assert_status, authorized, TestApp};
+
+async fn list_users(app: &TestApp, token: &str, query: &str) -> Value {
+    let request = authorized(TestRequest::get().uri(&format!("/api/v1/users{}", query)), token);
+    assert_status(app.request(request).await, StatusCode::OK).body
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn totals_are_counted_as_requested() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let token = app.token_for_user(&user);
+
+    let exact = list_users(&app, &token, "?limit=1").await;
+    assert_eq!(exact["count"], "exact");
+    let total = exact["total"].as_i64().unwrap();
+    assert!(total >= 1);
+    assert_eq!(exact["total_pages"], total);
+
+    // Estimates come from table statistics
+    sqlx::query("ANALYZE users").execute(&app.state.db).await.unwrap();
+    let estimated = list_users(&app, &token, "?limit=1&count=estimated").await;
+    assert_eq!(estimated["count"], "estimated");
+    let estimate = estimated["total"].as_i64().unwrap();
+    assert!((estimate - total).abs() <= total / 10 + 1, "estimated {} of {}", estimate, total);
+
+    let none = list_users(&app, &token, "?limit=1&count=none").await;
+    assert_eq!(none["count"], "none");
+    assert_eq!(none["total"], Value::Null);
+    assert_eq!(none["total_pages"], Value::Null);
+    assert_eq!(none["data"].as_array().unwrap().len(), 1);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn the_server_default_applies_without_a_count_parameter() {
+    let app = TestApp::spawn_with(|config| config.set_override("pagination.count", "none").unwrap()).await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let token = app.token_for_user(&user);
+
+    let users = list_users(&app, &token, "").await;
+    assert_eq!(users["count"], "none");
+    assert_eq!(users["total"], Value::Null);
+
+    let users = list_users(&app, &token, "?count=exact").await;
+    assert!(users["total"].as_i64().unwrap() >= 1);
+
+    let request = authorized(TestRequest::get().uri("/api/v1/users?count=approximate"), &token);
+    assert_status(app.request(request).await, StatusCode::BAD_REQUEST);
+}
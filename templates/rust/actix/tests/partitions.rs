@@ -0,0 +1,118 @@
+mod common;
+
+use arrow_array::{Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, TimeUnit};
+use chrono::{DateTime, TimeZone, Utc};
+use futures_util::TryStreamExt;
+use object_store::path::Path;
+use parquet::arrow::async_reader::ParquetObjectReader;
+use parquet::arrow::ParquetRecordBatchStreamBuilder;
+use uuid::Uuid;
+
+use actix_template::partitions::MaintenanceReport;
+use common::TestApp;
+
+fn at(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(year, month, day, 12, 0, 0).unwrap()
+}
+
+async fn spawn_app() -> TestApp {
+    let objects = std::env::temp_dir().join(format!("partitions-{}", Uuid::new_v4()));
+    TestApp::spawn_with(|config| {
+        config
+            .set_override("object_storage.path", objects.to_str().unwrap())
+            .and_then(|config| config.set_override("partitions.premake_months", 2))
+            .and_then(|config| config.set_override("partitions.tables.audit_events.retention_months", 12))
+            .unwrap()
+    })
+    .await
+}
+
+async fn insert_event(app: &TestApp, event_type: &str, created_at: DateTime<Utc>) {
+    sqlx::query("INSERT INTO audit_events (event_type, created_at) VALUES ($1, $2)")
+        .bind(event_type)
+        .bind(created_at)
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+}
+
+async fn read_archive(app: &TestApp, path: &str) -> Vec<RecordBatch> {
+    let reader = ParquetObjectReader::new(app.state.object_store.clone(), Path::from(path));
+    let stream = ParquetRecordBatchStreamBuilder::new(reader).await.unwrap().build().unwrap();
+    stream.try_collect().await.unwrap()
+}
+
+fn event_types(batches: &[RecordBatch]) -> Vec<String> {
+    let mut event_types: Vec<String> = batches
+        .iter()
+        .flat_map(|batch| {
+            let column = batch.column_by_name("event_type").unwrap();
+            let column = column.as_any().downcast_ref::<StringArray>().unwrap();
+            column.iter().map(|value| value.unwrap().to_string()).collect::<Vec<_>>()
+        })
+        .collect();
+    event_types.sort();
+    event_types
+}
+
+// Partition maintenance takes a database-wide lock, so the scenarios share
+// one test rather than skipping each other's runs
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn partitions_are_created_ahead_and_expired_ones_archived() {
+    let app = spawn_app().await;
+    let manager = &app.state.partition_manager;
+
+    // Months without a partition land in the default one
+    insert_event(&app, "partitions.old", at(2019, 3, 5)).await;
+    insert_event(&app, "partitions.old", at(2019, 3, 20)).await;
+    insert_event(&app, "partitions.recent", at(2020, 5, 2)).await;
+
+    app.clock.set(at(2020, 6, 15));
+    let report = manager.maintain().await.unwrap().expect("lock taken");
+    assert_eq!(
+        report.created,
+        ["201903", "202005", "202006", "202007", "202008"].map(|month| format!("audit_events_p{}", month))
+    );
+    assert_eq!(report.detached, ["audit_events_p201903"]);
+    assert_eq!(report.archived, ["archive/audit_events/audit_events_p201903.parquet"]);
+    assert_eq!(report.dropped, ["audit_events_p201903"]);
+
+    let rows: Vec<(String, String)> = sqlx::query_as(
+        "SELECT event_type, tableoid::regclass::text FROM audit_events WHERE event_type LIKE 'partitions.%'",
+    )
+    .fetch_all(&app.state.db)
+    .await
+    .unwrap();
+    assert_eq!(rows, [("partitions.recent".to_string(), "audit_events_p202005".to_string())]);
+
+    let batches = read_archive(&app, "archive/audit_events/audit_events_p201903.parquet").await;
+    assert_eq!(event_types(&batches), ["partitions.old", "partitions.old"]);
+    let created_at = batches[0].column_by_name("created_at").unwrap();
+    assert_eq!(
+        created_at.data_type(),
+        &DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+    );
+
+    // Nothing left to do
+    assert_eq!(manager.maintain().await.unwrap(), Some(MaintenanceReport::default()));
+
+    // A partition left detached by a failed upload is archived next time
+    sqlx::query("CREATE TABLE audit_events_p201801 (LIKE audit_events)")
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO audit_events_p201801 (id, event_type, metadata, created_at) VALUES ($1, 'partitions.stranded', '{}', $2)")
+        .bind(Uuid::new_v4())
+        .bind(at(2018, 1, 10))
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+
+    let report = manager.maintain().await.unwrap().expect("lock taken");
+    assert!(report.created.is_empty() && report.detached.is_empty());
+    assert_eq!(report.dropped, ["audit_events_p201801"]);
+    let batches = read_archive(&app, "archive/audit_events/audit_events_p201801.parquet").await;
+    assert_eq!(event_types(&batches), ["partitions.stranded"]);
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde_json::json;
+use webauthn_authenticator_rs::softpasskey::SoftPasskey;
+use webauthn_authenticator_rs::WebauthnAuthenticator;
+use webauthn_rs::prelude::{CreationChallengeResponse, RequestChallengeResponse, Url};
+
+use actix_template::config::Settings;
+use actix_template::factories::UserFactory;
+use actix_template::webauthn;
+use common::{assert_status, authorized, TestApp};
+
+async fn spawn() -> TestApp {
+    TestApp::spawn_with(|config| config.set_override("webauthn.enabled", true).unwrap()).await
+}
+
+fn origin() -> Url {
+    Url::parse("http://localhost:8080").unwrap()
+}
+
+#[test]
+fn the_origin_must_belong_to_the_relying_party() {
+    let settings = |origin: &str| {
+        let settings: Settings = Settings::defaults()
+            .and_then(|config| config.set_override("jwt.secret", "secret"))
+            .and_then(|config| config.set_override("database.url", "postgres://localhost/test"))
+            .and_then(|config| config.set_override("redis.url", "redis://localhost"))
+            .and_then(|config| config.set_override("webauthn.rp_id", "example.com"))
+            .and_then(|config| config.set_override("webauthn.rp_origin", origin))
+            .and_then(|config| config.build())
+            .and_then(|config| config.try_deserialize())
+            .unwrap();
+        settings.webauthn
+    };
+
+    assert!(webauthn::from_settings(&settings("https://app.example.com")).is_ok());
+    assert!(webauthn::from_settings(&settings("https://example.org")).is_err());
+    assert!(webauthn::from_settings(&settings("not a url")).is_err());
+}
+
+#[actix_web::test]
+async fn passkeys_are_off_by_default() {
+    let app = TestApp::spawn().await;
+
+    assert_status(app.request(TestRequest::post().uri("/api/v1/auth/passkey/start")).await, StatusCode::NOT_FOUND);
+
+    let request = authorized(TestRequest::get().uri("/api/v1/me/passkeys"), &app.token_for(uuid::Uuid::from_u128(1)));
+    assert_status(app.request(request).await, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn managing_passkeys_needs_a_token() {
+    let app = spawn().await;
+
+    assert_status(
+        app.request(TestRequest::post().uri("/api/v1/me/passkeys/register/start")).await,
+        StatusCode::UNAUTHORIZED,
+    );
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn passkeys_are_registered_used_and_managed() {
+    let app = spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let token = app.token_for_user(&user);
+    let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+
+    // Register
+    let request = TestRequest::post().uri("/api/v1/me/passkeys/register/start");
+    let challenge = assert_status(app.request(authorized(request, &token)).await, StatusCode::OK).body;
+    let options: CreationChallengeResponse = serde_json::from_value(challenge["options"].clone()).unwrap();
+    assert_eq!(options.public_key.user.name, user.email.as_str());
+    let credential = authenticator.do_registration(origin(), options).unwrap();
+    let finish = json!({ "ceremony_id": challenge["ceremony_id"], "name": "Laptop", "credential": credential });
+
+    let request = TestRequest::post().uri("/api/v1/me/passkeys/register/finish").set_json(&finish);
+    let passkey = assert_status(app.request(authorized(request, &token)).await, StatusCode::CREATED).body;
+    assert_eq!(passkey["name"], "Laptop");
+    assert!(passkey["last_used_at"].is_null());
+
+    let request = TestRequest::post().uri("/api/v1/me/passkeys/register/finish").set_json(&finish);
+    let response = assert_status(app.request(authorized(request, &token)).await, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error_code"], "PASSKEY_CEREMONY_EXPIRED");
+
+    // Log in. The soft authenticator doesn't do discoverable credentials, so
+    // the test names the credential and adds the user handle a browser would
+    let challenge = assert_status(
+        app.request(TestRequest::post().uri("/api/v1/auth/passkey/start")).await,
+        StatusCode::OK,
+    )
+    .body;
+    assert_eq!(challenge["options"]["mediation"], "conditional");
+    let mut options = challenge["options"].clone();
+    options["publicKey"]["allowCredentials"] = json!([{ "type": "public-key", "id": credential.id }]);
+    let options: RequestChallengeResponse = serde_json::from_value(options).unwrap();
+    let mut assertion = serde_json::to_value(authenticator.do_authentication(origin(), options).unwrap()).unwrap();
+    assertion["response"]["userHandle"] = json!(URL_SAFE_NO_PAD.encode(user.id.as_uuid().as_bytes()));
+    let finish = json!({ "ceremony_id": challenge["ceremony_id"], "credential": assertion });
+
+    let request = TestRequest::post().uri("/api/v1/auth/passkey/finish").set_json(&finish);
+    let response = assert_status(app.request(request).await, StatusCode::OK);
+    assert_eq!(response.body["user"]["id"], user.id.to_string());
+    assert!(response.body["refresh_token"].is_string());
+
+    let request = TestRequest::post().uri("/api/v1/auth/passkey/finish").set_json(&finish);
+    let response = assert_status(app.request(request).await, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error_code"], "PASSKEY_CEREMONY_EXPIRED");
+
+    // Manage
+    let passkeys = assert_status(
+        app.request(authorized(TestRequest::get().uri("/api/v1/me/passkeys"), &token)).await,
+        StatusCode::OK,
+    )
+    .body;
+    assert_eq!(passkeys.as_array().unwrap().len(), 1);
+    assert!(passkeys[0]["last_used_at"].is_string());
+    let uri = format!("/api/v1/me/passkeys/{}", passkey["id"].as_str().unwrap());
+
+    let request = TestRequest::patch().uri(&uri).set_json(json!({ "name": "Work laptop" }));
+    let response = assert_status(app.request(authorized(request, &token)).await, StatusCode::OK);
+    assert_eq!(response.body["name"], "Work laptop");
+
+    let other = app.insert_user(UserFactory::build()).await;
+    let request = authorized(TestRequest::delete().uri(&uri), &app.token_for_user(&other));
+    let response = assert_status(app.request(request).await, StatusCode::NOT_FOUND);
+    assert_eq!(response.body["error_code"], "PASSKEY_NOT_FOUND");
+
+    assert_status(app.request(authorized(TestRequest::delete().uri(&uri), &token)).await, StatusCode::NO_CONTENT);
+
+    // A deleted passkey no longer logs in
+    let challenge = assert_status(
+        app.request(TestRequest::post().uri("/api/v1/auth/passkey/start")).await,
+        StatusCode::OK,
+    )
+    .body;
+    let finish = json!({ "ceremony_id": challenge["ceremony_id"], "credential": finish["credential"] });
+    let request = TestRequest::post().uri("/api/v1/auth/passkey/finish").set_json(&finish);
+    let response = assert_status(app.request(request).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "PASSKEY_VERIFICATION_FAILED");
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn ceremonies_expire() {
+    let app = spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let token = app.token_for_user(&user);
+    let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+
+    let request = TestRequest::post().uri("/api/v1/me/passkeys/register/start");
+    let challenge = assert_status(app.request(authorized(request, &token)).await, StatusCode::OK).body;
+    let options: CreationChallengeResponse = serde_json::from_value(challenge["options"].clone()).unwrap();
+    let credential = authenticator.do_registration(origin(), options).unwrap();
+
+    app.clock.advance(chrono::Duration::seconds(app.state.settings.webauthn.ceremony_ttl_secs + 1));
+    let request = TestRequest::post()
+        .uri("/api/v1/me/passkeys/register/finish")
+        .set_json(json!({ "ceremony_id": challenge["ceremony_id"], "name": "Laptop", "credential": credential }));
+    let response = assert_status(app.request(authorized(request, &token)).await, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error_code"], "PASSKEY_CEREMONY_EXPIRED");
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use chrono::Duration;
+use serde_json::json;
+use std::sync::Arc;
+
+use actix_template::errors::ErrorCode;
+use actix_template::factories::{UserFactory, FACTORY_PASSWORD};
+use actix_template::mail::ConsoleMailer;
+use actix_template::models::magic_link::MagicLinkClaims;
+use actix_template::models::session::ClientContext;
+use actix_template::services::magic_link_service::MAGIC_LINK_PURPOSE;
+use actix_template::services::PasswordResetService;
+use common::{assert_status, TestApp};
+
+const NEW_PASSWORD: &str = "a-new-password";
+
+fn reset(token: &str, new_password: &str) -> TestRequest {
+    TestRequest::post()
+        .uri("/api/v1/auth/reset-password")
+        .set_json(json!({ "token": token, "new_password": new_password }))
+}
+
+fn login(email: &str, password: &str) -> TestRequest {
+    TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({ "email": email, "password": password }))
+}
+
+fn client() -> ClientContext {
+    ClientContext {
+        user_agent: "laptop".to_string(),
+        device_id: None,
+        ip: Some("203.0.113.7".parse().unwrap()),
+    }
+}
+
+fn password_reset_service(app: &TestApp, mailer: Arc<ConsoleMailer>) -> PasswordResetService {
+    PasswordResetService::new(
+        app.state.db.clone(),
+        mailer,
+        app.state.signing_keys.clone(),
+        app.state.session_service.clone(),
+        app.state.settings.password_reset.clone(),
+        app.state.settings.email.clone(),
+        app.clock.clone(),
+    )
+}
+
+fn last_token(mailer: &ConsoleMailer) -> String {
+    let email = mailer.outbox().pop().expect("an email");
+    let (_, token) = email.content.text.split_once("?token=").expect("a link");
+    token.split_whitespace().next().unwrap().to_string()
+}
+
+#[actix_web::test]
+async fn forged_and_misdirected_tokens_are_rejected_before_the_database() {
+    let app = TestApp::spawn().await;
+
+    let response = assert_status(app.request(reset("v1.e30.c2lnbmF0dXJl", NEW_PASSWORD)).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "PASSWORD_RESET_INVALID");
+
+    // Signed, but for signing in
+    let claims = MagicLinkClaims { id: uuid::Uuid::new_v4() };
+    let token = app.state.signing_keys.sign(MAGIC_LINK_PURPOSE, &claims, Duration::minutes(5)).unwrap();
+    let response = assert_status(app.request(reset(&token, NEW_PASSWORD)).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "PASSWORD_RESET_INVALID");
+}
+
+#[actix_web::test]
+async fn malformed_requests_are_rejected() {
+    let app = TestApp::spawn().await;
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/forgot-password")
+        .set_json(json!({ "email": "not-an-email" }));
+    let response = assert_status(app.request(request).await, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error_code"], "VALIDATION_FAILED");
+
+    let response = assert_status(app.request(reset("v1.e30.c2lnbmF0dXJl", "short")).await, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error_code"], "VALIDATION_FAILED");
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn resetting_changes_the_password_and_signs_out_every_session() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let session = assert_status(app.request(login(user.email.as_str(), FACTORY_PASSWORD)).await, StatusCode::OK);
+    let mailer = Arc::new(ConsoleMailer::default());
+    password_reset_service(&app, mailer.clone()).send_link(&user, &client()).await.unwrap();
+    let email = mailer.outbox()[0].clone();
+    assert_eq!(email.to, user.email);
+    assert!(email.content.text.contains("203.0.113.7"));
+    let token = last_token(&mailer);
+
+    assert_status(app.request(reset(&token, NEW_PASSWORD)).await, StatusCode::NO_CONTENT);
+
+    // The link works once
+    let response = assert_status(app.request(reset(&token, "another-password")).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "PASSWORD_RESET_INVALID");
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/refresh")
+        .set_json(json!({ "refresh_token": session.body["refresh_token"] }));
+    assert_status(app.request(request).await, StatusCode::UNAUTHORIZED);
+    assert_status(app.request(login(user.email.as_str(), FACTORY_PASSWORD)).await, StatusCode::UNAUTHORIZED);
+    assert_status(app.request(login(user.email.as_str(), NEW_PASSWORD)).await, StatusCode::OK);
+
+    let (events,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM audit_events WHERE user_id = $1 AND event_type = 'auth.password_reset'")
+            .bind(user.id)
+            .fetch_one(&app.state.db)
+            .await
+            .unwrap();
+    assert_eq!(events, 1);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn links_expire_are_voided_by_newer_ones_and_are_rate_limited() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let mailer = Arc::new(ConsoleMailer::default());
+    let service = password_reset_service(&app, mailer.clone());
+
+    service.send_link(&user, &client()).await.unwrap();
+    let voided = last_token(&mailer);
+    service.send_link(&user, &client()).await.unwrap();
+    let error = service.reset(&voided, NEW_PASSWORD).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::PasswordResetInvalid);
+
+    service.send_link(&user, &client()).await.unwrap();
+    let error = service.send_link(&user, &client()).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::PasswordResetRateLimited);
+
+    // Accepted all the same, so the endpoint doesn't reveal the account
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/forgot-password")
+        .set_json(json!({ "email": user.email }));
+    assert_status(app.request(request).await, StatusCode::ACCEPTED);
+
+    let expired = last_token(&mailer);
+    app.clock.advance(Duration::seconds(app.state.settings.password_reset.link_ttl_secs + 1));
+    let error = service.reset(&expired, NEW_PASSWORD).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::PasswordResetInvalid);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn links_die_with_a_changed_address() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let mailer = Arc::new(ConsoleMailer::default());
+    let service = password_reset_service(&app, mailer.clone());
+
+    service.send_link(&user, &client()).await.unwrap();
+    let token = last_token(&mailer);
+    sqlx::query("UPDATE users SET email = $2 WHERE id = $1")
+        .bind(user.id)
+        .bind(format!("new-{}", user.email))
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+
+    let error = service.reset(&token, NEW_PASSWORD).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::PasswordResetInvalid);
+}
@@ -0,0 +1,259 @@
+mod common;
+
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use actix_web::{web, App, HttpRequest, HttpResponse};
+use chrono::Duration;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use actix_template::config::{SmsProvider, SmsSettings};
+use actix_template::errors::ErrorCode;
+use actix_template::factories::UserFactory;
+use actix_template::models::phone_otp::OtpPurpose;
+use actix_template::services::PhoneOtpService;
+use actix_template::sms::{self, ConsoleSmsSender, SmsMessage, SmsSender, TwilioSmsSender};
+use actix_template::utils::{is_e164, mask_phone_number};
+use common::{assert_status, authorized, local_egress, TestApp};
+
+fn sms_settings(provider: SmsProvider) -> SmsSettings {
+    SmsSettings {
+        provider,
+        from_number: Some("+15005550006".to_string()),
+        twilio_account_sid: Some("AC123".to_string()),
+        twilio_auth_token: Some("auth-token".to_string()),
+        twilio_api_url: "https://api.twilio.com".to_string(),
+        timeout_secs: 5,
+    }
+}
+
+#[test]
+fn phone_numbers_must_be_e164() {
+    for valid in ["+14155550123", "+442071838750", "+12"] {
+        assert!(is_e164(valid), "{}", valid);
+    }
+    for invalid in ["14155550123", "+1 415 555 0123", "+1-415-555-0123", "+04155550123", "+1234567890123456", "+"] {
+        assert!(!is_e164(invalid), "{}", invalid);
+    }
+
+    assert_eq!(mask_phone_number("+14155550123"), "+*******0123");
+}
+
+#[actix_web::test]
+async fn console_sender_keeps_messages() {
+    let sender = ConsoleSmsSender::default();
+    let message = SmsMessage {
+        to: "+14155550123".to_string(),
+        body: "123456 is your code".to_string(),
+    };
+
+    sender.send(&message).await.unwrap();
+
+    assert_eq!(sender.outbox(), vec![message]);
+}
+
+#[test]
+fn twilio_requires_credentials() {
+    let settings = SmsSettings {
+        twilio_auth_token: None,
+        ..sms_settings(SmsProvider::Twilio)
+    };
+
+    let error = sms::from_settings(&settings, &local_egress()).err().unwrap();
+    assert!(error.to_string().contains("sms.twilio_auth_token"), "{}", error);
+    assert!(sms::from_settings(&sms_settings(SmsProvider::Console), &local_egress()).is_ok());
+}
+
+#[actix_web::test]
+async fn twilio_posts_to_the_messages_api() {
+    type Captured = Arc<Mutex<Option<(String, String, HashMap<String, String>)>>>;
+    let captured: Captured = Arc::default();
+
+    let server = {
+        let captured = captured.clone();
+        actix_test::start(move || {
+            let captured = captured.clone();
+            App::new().default_service(web::to(move |req: HttpRequest, form: web::Form<HashMap<String, String>>| {
+                let auth = req.headers().get(AUTHORIZATION).unwrap().to_str().unwrap().to_string();
+                *captured.lock().unwrap() = Some((req.path().to_string(), auth, form.into_inner()));
+                async { HttpResponse::Created().json(json!({ "sid": "SM123" })) }
+            }))
+        })
+    };
+    let sender = TwilioSmsSender::from_settings(
+        &SmsSettings {
+            twilio_api_url: server.url(""),
+            ..sms_settings(SmsProvider::Twilio)
+        },
+        &local_egress(),
+    )
+    .unwrap();
+
+    let message = SmsMessage {
+        to: "+14155550123".to_string(),
+        body: "123456 is your code".to_string(),
+    };
+    sender.send(&message).await.unwrap();
+
+    let (path, auth, form) = captured.lock().unwrap().take().unwrap();
+    assert_eq!(path, "/2010-04-01/Accounts/AC123/Messages.json");
+    assert_eq!(auth, "Basic QUMxMjM6YXV0aC10b2tlbg==");
+    assert_eq!(form["To"], "+14155550123");
+    assert_eq!(form["From"], "+15005550006");
+    assert_eq!(form["Body"], "123456 is your code");
+}
+
+#[actix_web::test]
+async fn twilio_errors_are_reported_as_delivery_failures() {
+    let server = actix_test::start(|| App::new().default_service(web::to(HttpResponse::BadRequest)));
+    let sender = TwilioSmsSender::from_settings(
+        &SmsSettings {
+            twilio_api_url: server.url(""),
+            ..sms_settings(SmsProvider::Twilio)
+        },
+        &local_egress(),
+    )
+    .unwrap();
+
+    let message = SmsMessage {
+        to: "+14155550123".to_string(),
+        body: "hi".to_string(),
+    };
+    let error = sender.send(&message).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::SmsDeliveryFailed);
+}
+
+#[actix_web::test]
+async fn otp_login_is_off_by_default() {
+    let app = TestApp::spawn().await;
+
+    for (uri, body) in [
+        ("/api/v1/auth/otp", json!({ "phone_number": "+14155550123" })),
+        ("/api/v1/auth/otp/verify", json!({ "phone_number": "+14155550123", "code": "123456" })),
+    ] {
+        assert_status(
+            app.request(TestRequest::post().uri(uri).set_json(body)).await,
+            StatusCode::NOT_FOUND,
+        );
+    }
+}
+
+#[actix_web::test]
+async fn otp_login_rejects_malformed_phone_numbers() {
+    let app = TestApp::spawn_with(|config| config.set_override("phone_otp.login_enabled", true).unwrap()).await;
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/otp")
+        .set_json(json!({ "phone_number": "415-555-0123" }));
+    let response = assert_status(app.request(request).await, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error_code"], "VALIDATION_FAILED");
+}
+
+#[actix_web::test]
+async fn profile_phone_numbers_are_validated() {
+    let app = TestApp::spawn().await;
+    let user_id = Uuid::from_u128(1);
+    let token = app.token_for(user_id);
+
+    let request = TestRequest::patch()
+        .uri(&format!("/api/v1/users/{}", user_id))
+        .set_json(json!({ "phone_number": "0123" }));
+    let response = assert_status(app.request(authorized(request, &token)).await, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error_code"], "VALIDATION_FAILED");
+}
+
+#[actix_web::test]
+async fn phone_verification_is_limited_to_the_owner() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::from_u128(1));
+
+    let request = TestRequest::post().uri(&format!("/api/v1/users/{}/phone/verification", Uuid::from_u128(2)));
+    assert_status(app.request(authorized(request, &token)).await, StatusCode::FORBIDDEN);
+}
+
+fn last_code(sender: &ConsoleSmsSender) -> String {
+    let message = sender.outbox().pop().expect("an SMS");
+    message.body.split_whitespace().next().unwrap().to_string()
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn codes_are_single_use_limited_and_rate_limited() {
+    let app = TestApp::spawn().await;
+    let phone_number = format!("+1555{:07}", rand::random::<u32>() % 10_000_000);
+    let user = app.insert_user(UserFactory::build().verified_phone(&phone_number)).await;
+    let sender = Arc::new(ConsoleSmsSender::default());
+    let service = PhoneOtpService::new(
+        app.state.db.clone(),
+        sender.clone(),
+        app.state.settings.phone_otp.clone(),
+        &app.state.settings.email,
+        app.clock.clone(),
+    );
+
+    // Right code once
+    service.send_code(user.id, &phone_number, OtpPurpose::Login).await.unwrap();
+    let code = last_code(&sender);
+    assert_eq!(code.len(), 6);
+    let error = service.verify_code(&phone_number, OtpPurpose::VerifyPhone, &code).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::PhoneOtpInvalid);
+    assert_eq!(service.verify_code(&phone_number, OtpPurpose::Login, &code).await.unwrap(), user.id);
+    assert!(service.verify_code(&phone_number, OtpPurpose::Login, &code).await.is_err());
+
+    // Burned after too many wrong guesses
+    service.send_code(user.id, &phone_number, OtpPurpose::Login).await.unwrap();
+    let code = last_code(&sender);
+    for _ in 0..app.state.settings.phone_otp.max_attempts {
+        assert!(service.verify_code(&phone_number, OtpPurpose::Login, "000000x").await.is_err());
+    }
+    assert!(service.verify_code(&phone_number, OtpPurpose::Login, &code).await.is_err());
+
+    // Three codes per window
+    service.send_code(user.id, &phone_number, OtpPurpose::Login).await.unwrap();
+    let error = service.send_code(user.id, &phone_number, OtpPurpose::Login).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::PhoneOtpRateLimited);
+
+    // Expired
+    app.clock.advance(Duration::seconds(app.state.settings.phone_otp.send_window_secs + 1));
+    service.send_code(user.id, &phone_number, OtpPurpose::Login).await.unwrap();
+    let code = last_code(&sender);
+    app.clock.advance(Duration::seconds(app.state.settings.phone_otp.code_ttl_secs + 1));
+    assert!(service.verify_code(&phone_number, OtpPurpose::Login, &code).await.is_err());
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn changing_the_phone_number_resets_verification() {
+    let app = TestApp::spawn().await;
+    let phone_number = format!("+1555{:07}", rand::random::<u32>() % 10_000_000);
+    let user = app.insert_user(UserFactory::build().verified_phone(&phone_number)).await;
+    let token = app.token_for_user(&user);
+    let patch = |phone_number: &str| {
+        authorized(
+            TestRequest::patch()
+                .uri(&format!("/api/v1/users/{}", user.id))
+                .set_json(json!({ "phone_number": phone_number })),
+            &token,
+        )
+    };
+
+    let response = assert_status(app.request(patch(&phone_number)).await, StatusCode::OK);
+    assert_eq!(response.body["phone_verified"], true);
+
+    let response = assert_status(app.request(patch("+14155550199")).await, StatusCode::OK);
+    assert_eq!(response.body["phone_number"], "+14155550199");
+    assert_eq!(response.body["phone_verified"], false);
+
+    let other = app.insert_user(UserFactory::build()).await;
+    let request = authorized(
+        TestRequest::patch()
+            .uri(&format!("/api/v1/users/{}", other.id))
+            .set_json(json!({ "phone_number": "+14155550199" })),
+        &app.token_for_user(&other),
+    );
+    let response = assert_status(app.request(request).await, StatusCode::CONFLICT);
+    assert_eq!(response.body["error_code"], "PHONE_NUMBER_TAKEN");
+}
@@ -0,0 +1,64 @@
+//! Mesh and tracing headers of incoming requests handed on by
+//! `with_trace_context` to calls made while handling them.
+
+use actix_web::test::{self, TestRequest};
+use actix_web::{web, App, HttpResponse};
+use std::collections::HashMap;
+
+use actix_template::middleware::Propagation;
+use actix_template::utils::with_trace_context;
+use platform_observability::propagation::PropagationSettings;
+
+/// Answers with the headers a call to another service would carry.
+async fn outgoing_headers() -> HttpResponse {
+    let request = with_trace_context(reqwest::Client::new().get("http://billing.internal/invoices"))
+        .build()
+        .unwrap();
+    let headers: HashMap<String, String> = request
+        .headers()
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_str().unwrap().to_string()))
+        .collect();
+    HttpResponse::Ok().json(headers)
+}
+
+async fn call(settings: PropagationSettings, request: TestRequest) -> HashMap<String, String> {
+    let app = test::init_service(
+        App::new()
+            .wrap(Propagation::new(&settings))
+            .route("/", web::get().to(outgoing_headers)),
+    )
+    .await;
+    test::call_and_read_body_json(&app, request.uri("/").to_request()).await
+}
+
+#[actix_web::test]
+async fn allowed_headers_reach_outgoing_calls() {
+    let request = TestRequest::get()
+        .insert_header(("X-B3-TraceId", "80f198ee56343ba864fe8b2a57d3eff7"))
+        .insert_header(("x-b3-sampled", "1"))
+        .insert_header(("baggage", "tenant=acme"))
+        .insert_header(("l5d-ctx-trace", "opaque"))
+        .insert_header(("authorization", "Bearer secret"));
+
+    let outgoing = call(PropagationSettings::default(), request).await;
+    assert_eq!(outgoing["x-b3-traceid"], "80f198ee56343ba864fe8b2a57d3eff7");
+    assert_eq!(outgoing["x-b3-sampled"], "1");
+    assert_eq!(outgoing["baggage"], "tenant=acme");
+    assert_eq!(outgoing["l5d-ctx-trace"], "opaque");
+    assert!(!outgoing.contains_key("authorization"));
+}
+
+#[actix_web::test]
+async fn the_allow_list_is_configurable() {
+    let settings = PropagationSettings {
+        headers: vec!["x-tenant".to_string()],
+    };
+    let request = TestRequest::get()
+        .insert_header(("x-tenant", "acme"))
+        .insert_header(("baggage", "tenant=acme"));
+
+    let outgoing = call(settings, request).await;
+    assert_eq!(outgoing["x-tenant"], "acme");
+    assert!(!outgoing.contains_key("baggage"));
+}
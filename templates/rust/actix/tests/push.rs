@@ -0,0 +1,446 @@
+mod common;
+
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use actix_web::{web, App, HttpRequest, HttpResponse};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use actix_template::config::{PushProvider, PushSettings};
+use actix_template::dry_run::DryRun;
+use actix_template::errors::ErrorCode;
+use actix_template::factories::UserFactory;
+use actix_template::jobs;
+use actix_template::models::push_device::{PushPlatform, RegisterPushDevice};
+use actix_template::push::{
+    self, ApnsPushSender, ConsolePushSender, FcmPushSender, PushNotification, PushOutcome, PushSender,
+};
+use actix_template::services::PushService;
+use common::{assert_status, authorized, local_egress, TestApp};
+
+const FCM_KEY: &str = include_str!("fixtures/push/fcm_service_account_key.pem");
+
+fn fixture(name: &str) -> String {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/push")
+        .join(name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn push_settings(provider: PushProvider) -> PushSettings {
+    PushSettings {
+        provider,
+        fcm_project_id: None,
+        fcm_service_account_path: None,
+        fcm_api_url: "https://fcm.googleapis.com".to_string(),
+        apns_key_path: None,
+        apns_key_id: Some("KEY123".to_string()),
+        apns_team_id: Some("TEAM123".to_string()),
+        apns_topic: Some("com.example.app".to_string()),
+        apns_api_url: "https://api.push.apple.com".to_string(),
+        timeout_secs: 5,
+    }
+}
+
+/// Writes a service account file whose token endpoint is `token_uri`.
+fn service_account(token_uri: &str) -> String {
+    let path = std::env::temp_dir().join(format!("fcm-service-account-{}.json", Uuid::new_v4()));
+    let account = json!({
+        "type": "service_account",
+        "client_email": "push@example.iam.gserviceaccount.com",
+        "private_key": FCM_KEY,
+        "token_uri": token_uri,
+    });
+    std::fs::write(&path, account.to_string()).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+type Requests = Arc<Mutex<Vec<(String, HashMap<String, String>, String)>>>;
+
+/// A provider stand-in that records requests and answers `send_response`
+/// to everything but the OAuth token endpoint.
+fn provider(send_response: fn() -> HttpResponse) -> (actix_test::TestServer, Requests) {
+    let requests: Requests = Arc::default();
+    let server = {
+        let requests = requests.clone();
+        actix_test::start(move || {
+            let requests = requests.clone();
+            App::new().default_service(web::to(move |req: HttpRequest, body: String| {
+                let headers = req
+                    .headers()
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_str().unwrap().to_string()))
+                    .collect();
+                requests.lock().unwrap().push((req.path().to_string(), headers, body));
+                let response = if req.path() == "/token" {
+                    HttpResponse::Ok().json(json!({ "access_token": "ya29.access", "expires_in": 3600 }))
+                } else {
+                    send_response()
+                };
+                async move { response }
+            }))
+        })
+    };
+    (server, requests)
+}
+
+fn fcm_sender(server: &actix_test::TestServer) -> FcmPushSender {
+    FcmPushSender::from_settings(
+        &PushSettings {
+            fcm_project_id: Some("demo-project".to_string()),
+            fcm_service_account_path: Some(service_account(&server.url("/token"))),
+            fcm_api_url: server.url(""),
+            ..push_settings(PushProvider::Native)
+        },
+        &local_egress(),
+    )
+    .unwrap()
+}
+
+fn apns_sender(server: &actix_test::TestServer) -> ApnsPushSender {
+    ApnsPushSender::from_settings(
+        &PushSettings {
+            apns_key_path: Some(fixture("apns_auth_key.p8")),
+            apns_api_url: server.url(""),
+            ..push_settings(PushProvider::Native)
+        },
+        &local_egress(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn native_provider_requires_credentials() {
+    let error = push::from_settings(&push_settings(PushProvider::Native), &local_egress()).err().unwrap();
+    assert!(error.to_string().contains("credentials"), "{}", error);
+
+    let error = ApnsPushSender::from_settings(
+        &PushSettings {
+            apns_key_path: Some(fixture("apns_auth_key.p8")),
+            apns_topic: None,
+            ..push_settings(PushProvider::Native)
+        },
+        &local_egress(),
+    )
+    .err()
+    .unwrap();
+    assert!(error.to_string().contains("push.apns_topic"), "{}", error);
+
+    assert!(push::from_settings(&push_settings(PushProvider::Console), &local_egress()).is_ok());
+}
+
+#[test]
+fn native_provider_only_supports_configured_platforms() {
+    let sender = push::NativePushSender::from_settings(
+        &PushSettings {
+            apns_key_path: Some(fixture("apns_auth_key.p8")),
+            ..push_settings(PushProvider::Native)
+        },
+        &local_egress(),
+    )
+    .unwrap();
+
+    assert!(sender.supports(PushPlatform::Apns));
+    assert!(!sender.supports(PushPlatform::Fcm));
+}
+
+#[actix_web::test]
+async fn console_sender_keeps_notifications() {
+    let sender = ConsolePushSender::default();
+    let notification = PushNotification::alert("Hello", "World").with_data("order_id", "42");
+
+    let outcome = sender.send(PushPlatform::Fcm, "device-token", &notification).await.unwrap();
+    assert_eq!(outcome, PushOutcome::Delivered);
+    let outcome = sender.send(PushPlatform::Fcm, "rejected-token", &notification).await.unwrap();
+    assert_eq!(outcome, PushOutcome::TokenRejected);
+
+    let outbox = sender.outbox();
+    assert_eq!(outbox.len(), 1);
+    assert_eq!(outbox[0].token, "device-token");
+    assert_eq!(outbox[0].notification, notification);
+}
+
+#[actix_web::test]
+async fn fcm_sends_through_the_v1_api() {
+    let (server, requests) = provider(|| HttpResponse::Ok().json(json!({ "name": "projects/demo-project/messages/1" })));
+    let sender = fcm_sender(&server);
+
+    let notification = PushNotification::alert("Hello", "World").with_data("order_id", "42");
+    for _ in 0..2 {
+        let outcome = sender.send(PushPlatform::Fcm, "fcm-token", &notification).await.unwrap();
+        assert_eq!(outcome, PushOutcome::Delivered);
+    }
+
+    let requests = requests.lock().unwrap();
+    // The access token is reused for the second notification
+    let paths: Vec<&str> = requests.iter().map(|(path, _, _)| path.as_str()).collect();
+    assert_eq!(
+        paths,
+        ["/token", "/v1/projects/demo-project/messages:send", "/v1/projects/demo-project/messages:send"]
+    );
+
+    let (_, _, token_request) = &requests[0];
+    assert!(token_request.contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer"));
+    let assertion = token_request.split("assertion=").nth(1).unwrap();
+    assert_eq!(jsonwebtoken::decode_header(assertion).unwrap().alg, jsonwebtoken::Algorithm::RS256);
+
+    let (_, headers, body) = &requests[1];
+    assert_eq!(headers[AUTHORIZATION.as_str()], "Bearer ya29.access");
+    let body: Value = serde_json::from_str(body).unwrap();
+    assert_eq!(
+        body,
+        json!({
+            "message": {
+                "token": "fcm-token",
+                "notification": { "title": "Hello", "body": "World" },
+                "data": { "order_id": "42" },
+            }
+        })
+    );
+}
+
+#[actix_web::test]
+async fn fcm_reports_unregistered_tokens() {
+    let (server, _) = provider(|| {
+        HttpResponse::NotFound().json(json!({
+            "error": {
+                "code": 404,
+                "status": "NOT_FOUND",
+                "details": [{ "@type": "type.googleapis.com/google.firebase.fcm.v1.FcmError", "errorCode": "UNREGISTERED" }],
+            }
+        }))
+    });
+    let sender = fcm_sender(&server);
+
+    let outcome = sender.send(PushPlatform::Fcm, "stale", &PushNotification::silent()).await.unwrap();
+    assert_eq!(outcome, PushOutcome::TokenRejected);
+}
+
+#[actix_web::test]
+async fn fcm_server_errors_are_retryable_failures() {
+    let (server, _) = provider(|| HttpResponse::ServiceUnavailable().finish());
+    let sender = fcm_sender(&server);
+
+    let error = sender.send(PushPlatform::Fcm, "token", &PushNotification::silent()).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::PushDeliveryFailed);
+}
+
+#[actix_web::test]
+async fn apns_sends_alerts_and_background_pushes() {
+    let (server, requests) = provider(|| HttpResponse::Ok().finish());
+    let sender = apns_sender(&server);
+
+    sender
+        .send(PushPlatform::Apns, "apns-token", &PushNotification::alert("Hello", "World"))
+        .await
+        .unwrap();
+    sender
+        .send(PushPlatform::Apns, "apns-token", &PushNotification::silent().with_data("sync", "inbox"))
+        .await
+        .unwrap();
+
+    let requests = requests.lock().unwrap();
+    let (path, headers, body) = &requests[0];
+    assert_eq!(path, "/3/device/apns-token");
+    assert_eq!(headers["apns-topic"], "com.example.app");
+    assert_eq!(headers["apns-push-type"], "alert");
+    assert_eq!(headers["apns-priority"], "10");
+    let body: Value = serde_json::from_str(body).unwrap();
+    assert_eq!(body, json!({ "aps": { "alert": { "title": "Hello", "body": "World" } } }));
+
+    let provider_token = headers[AUTHORIZATION.as_str()].strip_prefix("Bearer ").unwrap();
+    let header = jsonwebtoken::decode_header(provider_token).unwrap();
+    assert_eq!(header.alg, jsonwebtoken::Algorithm::ES256);
+    assert_eq!(header.kid.as_deref(), Some("KEY123"));
+
+    let (_, headers, body) = &requests[1];
+    assert_eq!(headers["apns-push-type"], "background");
+    assert_eq!(headers["apns-priority"], "5");
+    let body: Value = serde_json::from_str(body).unwrap();
+    assert_eq!(body, json!({ "aps": { "content-available": 1 }, "sync": "inbox" }));
+}
+
+#[actix_web::test]
+async fn apns_reports_rejected_tokens() {
+    let (server, _) = provider(|| HttpResponse::Gone().json(json!({ "reason": "Unregistered" })));
+    let sender = apns_sender(&server);
+    let outcome = sender.send(PushPlatform::Apns, "stale", &PushNotification::silent()).await.unwrap();
+    assert_eq!(outcome, PushOutcome::TokenRejected);
+
+    let (server, _) = provider(|| HttpResponse::BadRequest().json(json!({ "reason": "BadDeviceToken" })));
+    let sender = apns_sender(&server);
+    let outcome = sender.send(PushPlatform::Apns, "garbage", &PushNotification::silent()).await.unwrap();
+    assert_eq!(outcome, PushOutcome::TokenRejected);
+
+    let (server, _) = provider(|| HttpResponse::TooManyRequests().json(json!({ "reason": "TooManyRequests" })));
+    let sender = apns_sender(&server);
+    let error = sender.send(PushPlatform::Apns, "busy", &PushNotification::silent()).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::PushDeliveryFailed);
+}
+
+#[actix_web::test]
+async fn device_endpoints_require_authentication() {
+    let app = TestApp::spawn().await;
+
+    assert_status(app.request(TestRequest::get().uri("/api/v1/devices")).await, StatusCode::UNAUTHORIZED);
+    let request = TestRequest::post()
+        .uri("/api/v1/devices")
+        .set_json(json!({ "platform": "fcm", "token": "abc" }));
+    assert_status(app.request(request).await, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn device_tokens_are_validated() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::from_u128(1));
+
+    let request = TestRequest::post()
+        .uri("/api/v1/devices")
+        .set_json(json!({ "platform": "fcm", "token": "" }));
+    let response = assert_status(app.request(authorized(request, &token)).await, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error_code"], "VALIDATION_FAILED");
+
+    let request = TestRequest::post()
+        .uri("/api/v1/devices")
+        .set_json(json!({ "platform": "windows", "token": "abc" }));
+    assert_status(app.request(authorized(request, &token)).await, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn unsupported_platforms_are_rejected() {
+    let app = TestApp::spawn().await;
+    let sender = push::NativePushSender::from_settings(
+        &PushSettings {
+            apns_key_path: Some(fixture("apns_auth_key.p8")),
+            ..push_settings(PushProvider::Native)
+        },
+        &local_egress(),
+    )
+    .unwrap();
+    let service = PushService::new(
+        app.state.db.clone(),
+        app.state.job_queue.clone(),
+        Arc::new(sender),
+        app.clock.clone(),
+    );
+
+    let device = RegisterPushDevice {
+        platform: PushPlatform::Fcm,
+        token: "fcm-token".to_string(),
+    };
+    let error = service.register_device(Uuid::from_u128(1).into(), device, DryRun::default()).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::PushPlatformUnsupported);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn devices_are_registered_per_user() {
+    let app = TestApp::spawn().await;
+    let first = app.insert_user(UserFactory::build()).await;
+    let second = app.insert_user(UserFactory::build()).await;
+    let device_token = format!("token-{}", Uuid::new_v4());
+    let register = |user_token: &str| {
+        authorized(
+            TestRequest::post()
+                .uri("/api/v1/devices")
+                .set_json(json!({ "platform": "apns", "token": device_token })),
+            user_token,
+        )
+    };
+
+    let response = assert_status(app.request(register(&app.token_for_user(&first))).await, StatusCode::CREATED);
+    assert_eq!(response.body["platform"], "apns");
+    assert!(response.body.get("token").is_none());
+    let device_id = response.body["id"].as_str().unwrap().to_string();
+
+    // Re-registering on another account moves the device over
+    let response = assert_status(app.request(register(&app.token_for_user(&second))).await, StatusCode::CREATED);
+    assert_eq!(response.body["id"], device_id.as_str());
+
+    let request = authorized(TestRequest::get().uri("/api/v1/devices"), &app.token_for_user(&first));
+    let response = assert_status(app.request(request).await, StatusCode::OK);
+    assert_eq!(response.body, json!([]));
+
+    let request = authorized(
+        TestRequest::delete().uri(&format!("/api/v1/devices/{}", device_id)),
+        &app.token_for_user(&first),
+    );
+    let response = assert_status(app.request(request).await, StatusCode::NOT_FOUND);
+    assert_eq!(response.body["error_code"], "PUSH_DEVICE_NOT_FOUND");
+
+    let request = authorized(
+        TestRequest::delete().uri(&format!("/api/v1/devices/{}", device_id)),
+        &app.token_for_user(&second),
+    );
+    assert_status(app.request(request).await, StatusCode::NO_CONTENT);
+}
+
+async fn queued_deliveries(app: &TestApp, device_id: Uuid) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM jobs WHERE kind = $1 AND payload->>'device_id' = $2")
+        .bind(push::DELIVER_JOB)
+        .bind(device_id.to_string())
+        .fetch_one(&app.state.db)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn notifications_fan_out_and_rejected_tokens_are_removed() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let sender = Arc::new(ConsolePushSender::default());
+    let service = PushService::new(
+        app.state.db.clone(),
+        app.state.job_queue.clone(),
+        sender.clone(),
+        app.clock.clone(),
+    );
+
+    let good = format!("token-{}", Uuid::new_v4());
+    let rejected = format!("rejected-{}", Uuid::new_v4());
+    for (platform, token) in [(PushPlatform::Fcm, &good), (PushPlatform::Apns, &rejected)] {
+        let device = RegisterPushDevice {
+            platform,
+            token: token.clone(),
+        };
+        service.register_device(user.id, device, DryRun::default()).await.unwrap();
+    }
+
+    let notification = PushNotification::alert("Hello", "World");
+    let fan_out_id = Uuid::new_v4().to_string();
+    service.fan_out(&fan_out_id, user.id, &notification).await.unwrap();
+    // A second run, as of a reclaimed job, queues nothing more
+    service.fan_out(&fan_out_id, user.id, &notification).await.unwrap();
+    let devices = service.list_devices(user.id).await.unwrap();
+    for device in &devices {
+        assert_eq!(queued_deliveries(&app, device.id).await, 1);
+        service.deliver(device.id, &notification).await.unwrap();
+    }
+
+    let outbox = sender.outbox();
+    assert_eq!(outbox.len(), 1);
+    assert_eq!(outbox[0].token, good);
+    let remaining: Vec<String> = service
+        .list_devices(user.id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|device| device.token)
+        .collect();
+    assert_eq!(remaining, [good]);
+
+    // The same through the queue with the app's sender
+    let job_id = app.state.push_service.notify_user(user.id, notification).await.unwrap();
+    // The fan-out job and the leftover delivery jobs from above, then the
+    // delivery job the fan-out enqueued
+    let worker = jobs::worker(&app.state);
+    assert!(worker.drain().await.unwrap() >= 2);
+    let job = app.state.job_queue.get_job(job_id).await.unwrap().unwrap();
+    assert_eq!(job.status, "completed");
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+use actix_web::http::{Method, StatusCode};
+use actix_web::test::TestRequest;
+use config::{File, FileFormat};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use actix_template::config::{RateLimitSettings, RouteLimitSettings};
+use actix_template::errors::ErrorCode;
+use actix_template::rate_limit::RateLimits;
+use common::{assert_status, TestApp};
+use platform_core::rate_limit::{BackendSettings, MemoryBackend};
+
+fn limits(routes: Vec<RouteLimitSettings>) -> RateLimits {
+    let settings = RateLimitSettings {
+        enabled: true,
+        default_rate_per_second: 1.0,
+        default_burst: 2,
+        routes,
+        trusted_gateways: vec!["10.0.0.0/8".to_string()],
+        backend: BackendSettings::Memory,
+    };
+    RateLimits::new(&settings, Arc::new(MemoryBackend::default()))
+}
+
+fn rule(path: &str, method: Option<&str>) -> RouteLimitSettings {
+    RouteLimitSettings {
+        path: path.to_string(),
+        method: method.map(str::to_string),
+        rate_per_second: 0.1,
+        burst: Some(1),
+    }
+}
+
+fn client(ip: &str) -> Option<std::net::IpAddr> {
+    Some(ip.parse().unwrap())
+}
+
+#[actix_web::test]
+async fn routes_with_a_rule_have_their_own_bucket() {
+    let limits = limits(vec![rule("/api/v1/auth/login", Some("post"))]);
+    let ip = client("203.0.113.7");
+
+    assert!(limits.check(&Method::POST, "/api/v1/auth/login", ip).await.is_ok());
+    let error = limits.check(&Method::POST, "/api/v1/auth/login", ip).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::RateLimited);
+
+    // Other methods and routes draw from the client's default bucket
+    assert!(limits.check(&Method::GET, "/api/v1/auth/login", ip).await.is_ok());
+    assert!(limits.check(&Method::GET, "/api/v1/users", ip).await.is_ok());
+    assert!(limits.check(&Method::GET, "/api/v1/users", ip).await.is_err());
+    assert!(limits.check(&Method::GET, "/api/v1/users", client("203.0.113.8")).await.is_ok());
+}
+
+#[actix_web::test]
+async fn prefixes_match() {
+    let limits = limits(vec![rule("/api/v1/auth/*", None)]);
+
+    assert!(limits.check(&Method::POST, "/api/v1/auth/register", client("203.0.113.7")).await.is_ok());
+    assert!(limits.check(&Method::POST, "/api/v1/auth/login", client("203.0.113.7")).await.is_err());
+}
+
+#[test]
+fn clients_behind_trusted_gateways_are_read_from_forwarded_for() {
+    let limits = limits(vec![]);
+    let forwarded_for = |values: &[&str]| {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(HeaderName::from_static("x-forwarded-for"), HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    };
+
+    // Other peers are the client, whatever they claim
+    let headers = forwarded_for(&["198.51.100.1"]);
+    assert_eq!(limits.client_ip(client("203.0.113.7"), &headers), client("203.0.113.7"));
+
+    // The right-most hop that isn't a gateway; hops left of it are the
+    // client's own claims
+    let headers = forwarded_for(&["192.0.2.66, 203.0.113.7, 10.0.0.2"]);
+    assert_eq!(limits.client_ip(client("10.0.0.1"), &headers), client("203.0.113.7"));
+    let headers = forwarded_for(&["192.0.2.66", "203.0.113.7:51234"]);
+    assert_eq!(limits.client_ip(client("10.0.0.1"), &headers), client("203.0.113.7"));
+
+    // Without a usable hop the gateway is limited itself
+    assert_eq!(limits.client_ip(client("10.0.0.1"), &HeaderMap::new()), client("10.0.0.1"));
+    let headers = forwarded_for(&["unknown"]);
+    assert_eq!(limits.client_ip(client("10.0.0.1"), &headers), client("10.0.0.1"));
+    assert_eq!(limits.client_ip(None, &headers), None);
+}
+
+#[actix_web::test]
+async fn forwarded_clients_behind_one_gateway_get_their_own_buckets() {
+    let settings = r#"
+        [rate_limit]
+        trusted_gateways = ["10.0.0.0/8"]
+
+        [[rate_limit.routes]]
+        path = "/api/v1/health"
+        rate_per_second = 0.1
+        burst = 1
+    "#;
+    let app = TestApp::spawn_with(|config| config.add_source(File::from_str(settings, FileFormat::Toml))).await;
+    let gateway: SocketAddr = "10.0.0.1:40000".parse().unwrap();
+    let health = |client: &str| {
+        TestRequest::get()
+            .uri("/api/v1/health")
+            .peer_addr(gateway)
+            .insert_header(("X-Forwarded-For", client))
+    };
+
+    assert_status(app.request(health("203.0.113.7")).await, StatusCode::OK);
+    assert_status(app.request(health("203.0.113.8")).await, StatusCode::OK);
+    assert_status(app.request(health("203.0.113.7")).await, StatusCode::TOO_MANY_REQUESTS);
+    // A client can't escape its bucket by claiming another address
+    assert_status(app.request(health("198.51.100.1, 203.0.113.8")).await, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_web::test]
+async fn limited_requests_get_429_with_retry_after() {
+    let settings = r#"
+        [[rate_limit.routes]]
+        path = "/api/v1/health"
+        rate_per_second = 0.1
+        burst = 1
+    "#;
+    let app = TestApp::spawn_with(|config| config.add_source(File::from_str(settings, FileFormat::Toml))).await;
+    let peer: SocketAddr = "203.0.113.7:40000".parse().unwrap();
+    let health = || TestRequest::get().uri("/api/v1/health").peer_addr(peer);
+
+    assert_status(app.request(health()).await, StatusCode::OK);
+    let response = assert_status(app.request(health()).await, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.body["error_code"], "RATE_LIMITED");
+    let retry_after: u64 = response.headers.get(RETRY_AFTER).unwrap().to_str().unwrap().parse().unwrap();
+    assert!((1..=10).contains(&retry_after));
+}
+
+#[actix_web::test]
+async fn sign_in_and_sign_up_are_limited_by_default() {
+    let app = TestApp::spawn().await;
+    let routes: Vec<_> = app.state.settings.rate_limit.routes.iter().map(|route| route.path.as_str()).collect();
+
+    assert_eq!(routes, ["/api/v1/auth/login", "/api/v1/auth/register"]);
+    assert_eq!(app.state.settings.rate_limit.backend, BackendSettings::Memory);
+}
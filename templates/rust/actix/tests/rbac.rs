@@ -0,0 +1,146 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use serde_json::json;
+
+use actix_template::errors::ErrorCode;
+use actix_template::factories::UserFactory;
+use actix_template::models::role::{Grants, ROLE_ADMIN, ROLE_SUPPORT, USERS_READ};
+use actix_template::utils::decode_jwt_token;
+use common::{assert_status, authorized, TestApp};
+
+fn support() -> Grants {
+    Grants {
+        roles: vec![ROLE_SUPPORT.to_string()],
+        permissions: vec![USERS_READ.to_string()],
+    }
+}
+
+#[actix_web::test]
+async fn admin_user_endpoints_need_the_permission() {
+    let app = TestApp::spawn().await;
+    let user = UserFactory::build().into_user();
+    let token = app.token_for_user(&user);
+
+    let uri = format!("/api/v1/admin/users/{}", uuid::Uuid::new_v4());
+    let response = app.admin_request(authorized(TestRequest::delete().uri(&uri), &token)).await;
+    let response = assert_status(response, StatusCode::FORBIDDEN);
+    assert_eq!(response.body["error_code"], ErrorCode::AuthRoleMissing.as_str());
+}
+
+#[actix_web::test]
+async fn operational_endpoints_need_the_admin_role() {
+    let app = TestApp::spawn().await;
+    let user = UserFactory::build().into_user();
+    let id = uuid::Uuid::new_v4();
+
+    let requests = [
+        TestRequest::get().uri("/api/v1/admin/log-level"),
+        TestRequest::delete().uri("/api/v1/admin/log-level"),
+        TestRequest::get().uri("/api/v1/admin/runtime"),
+        TestRequest::get().uri("/api/v1/admin/usage"),
+        TestRequest::get().uri("/api/v1/admin/job-queues"),
+        TestRequest::post().uri("/api/v1/admin/job-queues/default/pause"),
+        TestRequest::get().uri("/api/v1/admin/jobs"),
+        TestRequest::get().uri("/api/v1/admin/jobs/stats"),
+        TestRequest::delete().uri("/api/v1/admin/jobs/dead"),
+        TestRequest::post().uri(&format!("/api/v1/admin/jobs/{}/retry", id)),
+        TestRequest::get().uri("/api/v1/admin/user-deletions"),
+        TestRequest::post().uri(&format!("/api/v1/admin/user-deletions/{}/finalize", id)),
+    ];
+    for request in requests {
+        let response = app.admin_request(authorized(request, &app.token_with_grants(&user, support()))).await;
+        let response = assert_status(response, StatusCode::FORBIDDEN);
+        assert_eq!(response.body["error_code"], ErrorCode::AuthRoleMissing.as_str());
+    }
+}
+
+#[actix_web::test]
+async fn granting_roles_needs_the_admin_role() {
+    let app = TestApp::spawn().await;
+    let user = UserFactory::build().into_user();
+    let token = app.token_with_grants(&user, support());
+
+    let uri = format!("/api/v1/admin/users/{}/roles/{}", uuid::Uuid::new_v4(), ROLE_ADMIN);
+    let response = app.admin_request(authorized(TestRequest::put().uri(&uri), &token)).await;
+    let response = assert_status(response, StatusCode::FORBIDDEN);
+    assert_eq!(response.body["error_code"], ErrorCode::AuthRoleMissing.as_str());
+}
+
+#[actix_web::test]
+async fn tokens_carry_grants_only_when_there_are_some() {
+    let app = TestApp::spawn().await;
+    let user = UserFactory::build().into_user();
+
+    let claims = decode_jwt_token(&app.token_for_user(&user), &app.state.jwt_keys, app.clock.as_ref()).unwrap();
+    assert_eq!(claims.grants, Grants::default());
+
+    let claims = decode_jwt_token(&app.token_with_grants(&user, support()), &app.state.jwt_keys, app.clock.as_ref());
+    assert_eq!(claims.unwrap().grants, support());
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn admins_grant_roles_and_manage_other_users() {
+    let app = TestApp::spawn().await;
+    let admin = app.insert_user(UserFactory::build()).await;
+    let target = app.insert_user(UserFactory::build()).await;
+    app.state.role_service.grant(admin.id, ROLE_ADMIN, None).await.unwrap();
+    let grants = app.state.role_service.grants_of(admin.id).await.unwrap();
+    assert!(grants.has_role(ROLE_ADMIN));
+    let token = app.token_with_grants(&admin, grants);
+
+    let grant = TestRequest::put().uri(&format!("/api/v1/admin/users/{}/roles/{}", target.id, ROLE_SUPPORT));
+    let response = assert_status(app.admin_request(authorized(grant, &token)).await, StatusCode::OK);
+    assert_eq!(response.body["roles"][0]["role"], ROLE_SUPPORT);
+    assert_eq!(response.body["roles"][0]["granted_by"], admin.id.to_string());
+    let grants = app.state.role_service.grants_of(target.id).await.unwrap();
+    assert_eq!(grants, support());
+
+    let unknown = TestRequest::put().uri(&format!("/api/v1/admin/users/{}/roles/owner", target.id));
+    assert_status(app.admin_request(authorized(unknown, &token)).await, StatusCode::NOT_FOUND);
+    let own = TestRequest::delete().uri(&format!("/api/v1/admin/users/{}/roles/{}", admin.id, ROLE_ADMIN));
+    assert_status(app.admin_request(authorized(own, &token)).await, StatusCode::BAD_REQUEST);
+
+    let update = TestRequest::patch()
+        .uri(&format!("/api/v1/admin/users/{}", target.id))
+        .set_json(json!({ "full_name": "Renamed by support" }));
+    let response = assert_status(app.admin_request(authorized(update, &token)).await, StatusCode::OK);
+    assert_eq!(response.body["full_name"], "Renamed by support");
+
+    let get = TestRequest::get().uri(&format!("/api/v1/admin/users/{}", target.id));
+    let response = assert_status(app.admin_request(authorized(get, &token)).await, StatusCode::OK);
+    assert_eq!(response.body["user"]["full_name"], "Renamed by support");
+    assert_eq!(response.body["roles"][0]["role"], ROLE_SUPPORT);
+
+    let revoke = || TestRequest::delete().uri(&format!("/api/v1/admin/users/{}/roles/{}", target.id, ROLE_SUPPORT));
+    assert_status(app.admin_request(authorized(revoke(), &token)).await, StatusCode::NO_CONTENT);
+    assert_status(app.admin_request(authorized(revoke(), &token)).await, StatusCode::NOT_FOUND);
+
+    let audited: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM audit_events WHERE user_id = $1 AND event_type IN ('admin.role_granted', 'admin.role_revoked', 'admin.user_updated')",
+    )
+    .bind(admin.id)
+    .fetch_one(&app.state.db)
+    .await
+    .unwrap();
+    assert_eq!(audited, 3);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn sign_in_issues_tokens_with_the_users_grants() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    app.state.role_service.grant(user.id, ROLE_SUPPORT, None).await.unwrap();
+
+    let login = TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({ "email": user.email, "password": actix_template::factories::FACTORY_PASSWORD }));
+    let response = assert_status(app.request(login).await, StatusCode::OK);
+
+    let token = response.body["access_token"].as_str().unwrap();
+    let claims = decode_jwt_token(token, &app.state.jwt_keys, app.clock.as_ref()).unwrap();
+    assert_eq!(claims.grants, support());
+}
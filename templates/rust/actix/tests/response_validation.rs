@@ -0,0 +1,82 @@
+mod common;
+
+use actix_web::http::{Method, StatusCode};
+use actix_web::test::TestRequest;
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use std::collections::HashMap;
+
+use actix_template::config::{MiddlewareSettings, OnMismatch};
+use actix_template::middleware::response_validation::mismatches;
+use common::{assert_status, TestApp};
+
+async fn spawn(on_mismatch: &'static str) -> TestApp {
+    TestApp::spawn_with(move |config| {
+        let middleware = vec![
+            HashMap::from([("name".to_string(), "request_id")]),
+            HashMap::from([("name".to_string(), "response_validation"), ("on_mismatch".to_string(), on_mismatch)]),
+        ];
+        config.set_override("server.middleware", middleware).unwrap()
+    })
+    .await
+}
+
+/// `/api/v1/health` as if `HealthResponse::version` had become an `Option`.
+fn drifted_health(cfg: &mut web::ServiceConfig) {
+    cfg.route(
+        "/api/v1/health",
+        web::get().to(|| async {
+            HttpResponse::Ok().json(json!({ "status": "healthy", "version": null, "timestamp": "2024-01-01T00:00:00Z" }))
+        }),
+    );
+}
+
+#[test]
+fn mismatches_default_to_being_logged() {
+    let settings: MiddlewareSettings = serde_json::from_value(json!({ "name": "response_validation" })).unwrap();
+    assert_eq!(settings, MiddlewareSettings::ResponseValidation { on_mismatch: OnMismatch::Log });
+}
+
+#[actix_web::test]
+async fn documented_responses_pass() {
+    let app = spawn("fail").await;
+
+    let response = assert_status(app.request(TestRequest::get().uri("/api/v1/health")).await, StatusCode::OK);
+    assert_eq!(response.body["status"], "healthy");
+
+    // Not ready without a database, which is documented too
+    let response = app.request(TestRequest::get().uri("/api/v1/ready")).await;
+    assert_ne!(response.status, StatusCode::INTERNAL_SERVER_ERROR, "{}", response.body);
+}
+
+#[actix_web::test]
+async fn drifted_responses_fail_when_configured_to() {
+    let app = spawn("fail").await;
+
+    let response = app.serve(TestRequest::get().uri("/api/v1/health"), drifted_health).await;
+    let response = assert_status(response, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(response.body["error_code"], "INTERNAL");
+}
+
+#[actix_web::test]
+async fn drifted_responses_are_sent_when_only_logged() {
+    let app = spawn("log").await;
+
+    let response = app.serve(TestRequest::get().uri("/api/v1/health"), drifted_health).await;
+    let response = assert_status(response, StatusCode::OK);
+    assert!(response.body["version"].is_null());
+}
+
+#[actix_web::test]
+async fn mismatches_point_at_the_drifted_fields() {
+    let body = json!({ "status": "healthy", "version": null, "uptime": 3 });
+    let found = mismatches(&Method::GET, "/api/v1/health", StatusCode::OK, &body).unwrap();
+    assert_eq!(found, ["/timestamp: missing required property", "/version: expected string, got null"]);
+
+    let body = json!({ "status": "healthy", "version": "1.0.0", "timestamp": "2024-01-01T00:00:00Z" });
+    assert_eq!(mismatches(&Method::GET, "/api/v1/health", StatusCode::OK, &body), Some(vec![]));
+
+    // Nothing to check against
+    assert_eq!(mismatches(&Method::GET, "/api/v1/health", StatusCode::IM_A_TEAPOT, &body), None);
+    assert_eq!(mismatches(&Method::GET, "/api/v1/nope", StatusCode::OK, &body), None);
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use chrono::Duration;
+
+use actix_template::config::RetentionSettings;
+use actix_template::errors::AppError;
+use actix_template::factories::UserFactory;
+use actix_template::models::user::User;
+use actix_template::retention::RetentionEngine;
+use common::{test_epoch, TestApp};
+
+fn engine(app: &TestApp, configure: impl FnOnce(&mut RetentionSettings)) -> RetentionEngine {
+    let mut settings = app.state.settings.retention.clone();
+    configure(&mut settings);
+    RetentionEngine::new(
+        app.state.db.clone(),
+        settings,
+        app.state.job_queue.clone(),
+        app.clock.clone(),
+    )
+    .unwrap()
+}
+
+#[actix_web::test]
+async fn policies_must_name_a_record_type_and_a_ttl() {
+    let app = TestApp::spawn().await;
+    let new = |settings| RetentionEngine::new(app.state.db.clone(), settings, app.state.job_queue.clone(), app.clock.clone());
+
+    let mut settings = app.state.settings.retention.clone();
+    let mut policy = settings.policies["sessions"].clone();
+    settings.policies.insert("webhooks".to_string(), policy.clone());
+    assert!(new(settings).is_err());
+
+    let mut settings = app.state.settings.retention.clone();
+    policy.ttl_days = 0;
+    settings.policies.insert("sessions".to_string(), policy);
+    assert!(new(settings).is_err());
+
+    let mut settings = app.state.settings.retention.clone();
+    settings.batch_size = 0;
+    assert!(new(settings).is_err());
+}
+
+#[actix_web::test]
+async fn unconfigured_policies_are_not_enforced() {
+    let app = TestApp::spawn().await;
+    let engine = engine(&app, |settings| {
+        settings.policies.remove("audit_events");
+    });
+
+    // Rejected before anything touches the database
+    let error = engine.enforce("audit_events").await.unwrap_err();
+    assert!(matches!(error, AppError::NotFound(_)));
+}
+
+async fn deactivated_user(app: &TestApp, days_ago: i64) -> User {
+    let user = app.insert_user(UserFactory::build().inactive()).await;
+    sqlx::query("UPDATE users SET deactivated_at = $2 WHERE id = $1")
+        .bind(user.id)
+        .bind(test_epoch() - Duration::days(days_ago))
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+    user
+}
+
+async fn user_exists(app: &TestApp, user: &User) -> bool {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users WHERE id = $1)")
+        .bind(user.id)
+        .fetch_one(&app.state.db)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn expired_rows_are_counted_in_dry_run_and_deleted_in_batches() {
+    let app = TestApp::spawn().await;
+    let expired = [
+        deactivated_user(&app, 15).await,
+        deactivated_user(&app, 20).await,
+        deactivated_user(&app, 400).await,
+    ];
+    let recent = deactivated_user(&app, 13).await;
+    let active = app.insert_user(UserFactory::build()).await;
+
+    // Reactivation clears the deactivation time
+    let reactivated = deactivated_user(&app, 30).await;
+    sqlx::query("UPDATE users SET is_active = true WHERE id = $1")
+        .bind(reactivated.id)
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+
+    let dry_run = engine(&app, |settings| settings.dry_run = true);
+    let report = dry_run.enforce("deactivated_users").await.unwrap();
+    assert!(report.dry_run);
+    assert_eq!(report.cutoff, test_epoch() - Duration::days(14));
+    assert!(report.rows >= 3);
+    for user in &expired {
+        assert!(user_exists(&app, user).await);
+    }
+
+    let engine = engine(&app, |settings| settings.batch_size = 2);
+    let report = engine.enforce("deactivated_users").await.unwrap();
+    assert!(!report.dry_run);
+    assert!(report.rows >= 3);
+    for user in &expired {
+        assert!(!user_exists(&app, user).await);
+    }
+    for user in [&recent, &active, &reactivated] {
+        assert!(user_exists(&app, user).await);
+    }
+
+    let (rows, finished): (i64, bool) = sqlx::query_as(
+        "SELECT last_rows, last_finished_at IS NOT NULL FROM retention_runs WHERE policy = 'deactivated_users'",
+    )
+    .fetch_one(&app.state.db)
+    .await
+    .unwrap();
+    assert_eq!(rows as u64, report.rows);
+    assert!(finished);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn policies_are_scheduled_once_per_interval() {
+    let app = TestApp::spawn().await;
+    sqlx::query("DELETE FROM retention_runs").execute(&app.state.db).await.unwrap();
+    let engine = &app.state.retention;
+
+    assert_eq!(
+        engine.schedule_due().await.unwrap(),
+        ["audit_events", "deactivated_users", "processed_messages", "sessions"]
+    );
+    assert!(engine.schedule_due().await.unwrap().is_empty());
+
+    app.clock.advance(Duration::seconds(app.state.settings.retention.interval_secs));
+    assert_eq!(engine.schedule_due().await.unwrap().len(), 4);
+}
@@ -0,0 +1,166 @@
+//! `#[cached]` and `#[authorize]` on handlers defined here, counting how
+//! often each actually runs.
+
+mod common;
+
+use actix_template_macros::{authorize, cached};
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use actix_web::{web, HttpResponse};
+use chrono::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use uuid::Uuid;
+
+use actix_template::errors::AppResult;
+use actix_template::middleware::AuthMiddleware;
+use common::{assert_status, authorized, TestApp, TestResponse};
+
+static CACHED_CALLS: AtomicUsize = AtomicUsize::new(0);
+static FAILING_CALLS: AtomicUsize = AtomicUsize::new(0);
+static UNAUTHENTICATED_CALLS: AtomicUsize = AtomicUsize::new(0);
+static TAGGED_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+// Authorization is written first here; it still runs before the lookup
+#[authorize("read:users")]
+#[cached(ttl = "30s")]
+async fn cached_handler() -> AppResult<HttpResponse> {
+    let calls = CACHED_CALLS.fetch_add(1, Ordering::SeqCst) + 1;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "calls": calls })))
+}
+
+#[cached(ttl = "30s")]
+#[authorize("read:users")]
+async fn failing_handler() -> AppResult<HttpResponse> {
+    FAILING_CALLS.fetch_add(1, Ordering::SeqCst);
+    Ok(HttpResponse::ServiceUnavailable().finish())
+}
+
+#[cached(ttl = "30s")]
+#[authorize("read:users")]
+async fn unauthenticated_handler() -> AppResult<HttpResponse> {
+    UNAUTHENTICATED_CALLS.fetch_add(1, Ordering::SeqCst);
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[cached(ttl = "30s", tags = ["items:list", "item:{id}"])]
+#[authorize("read:users")]
+async fn tagged_handler() -> AppResult<HttpResponse> {
+    let calls = TAGGED_CALLS.fetch_add(1, Ordering::SeqCst) + 1;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "calls": calls })))
+}
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/cached").wrap(AuthMiddleware).route(web::get().to(cached_handler)))
+        .service(web::resource("/items/{id}").wrap(AuthMiddleware).route(web::get().to(tagged_handler)))
+        .service(web::resource("/failing").wrap(AuthMiddleware).route(web::get().to(failing_handler)))
+        // No middleware, so no claims for `#[authorize]` to find
+        .service(web::resource("/unauthenticated").route(web::get().to(unauthenticated_handler)));
+}
+
+#[actix_web::test]
+async fn responses_are_cached_per_caller_until_they_expire() {
+    let app = TestApp::spawn().await;
+    let (alice, bob) = (app.token_for(Uuid::new_v4()), app.token_for(Uuid::new_v4()));
+    let get = |token: &str| authorized(TestRequest::get().uri("/cached"), token);
+
+    let first = assert_status(app.serve(get(&alice), routes).await, StatusCode::OK);
+    assert_eq!(first.headers.get("x-cache").unwrap(), "miss");
+
+    let second = assert_status(app.serve(get(&alice), routes).await, StatusCode::OK);
+    assert_eq!(second.headers.get("x-cache").unwrap(), "hit");
+    assert_eq!(second.body, first.body);
+
+    let other_caller = assert_status(app.serve(get(&bob), routes).await, StatusCode::OK);
+    assert_eq!(other_caller.headers.get("x-cache").unwrap(), "miss");
+    assert_ne!(other_caller.body, first.body);
+
+    app.clock.advance(Duration::seconds(31));
+    let expired = assert_status(app.serve(get(&alice), routes).await, StatusCode::OK);
+    assert_eq!(expired.headers.get("x-cache").unwrap(), "miss");
+}
+
+#[actix_web::test]
+async fn invalidated_paths_are_served_fresh() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::new_v4());
+    let get = || authorized(TestRequest::get().uri("/cached?page=2"), &token);
+
+    app.serve(get(), routes).await;
+    assert_eq!(app.state.response_cache.len(), 1);
+
+    app.state.response_cache.invalidate("/cached");
+    let response = assert_status(app.serve(get(), routes).await, StatusCode::OK);
+    assert_eq!(response.headers.get("x-cache").unwrap(), "miss");
+}
+
+#[actix_web::test]
+async fn invalidated_tags_drop_exactly_their_responses() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::new_v4());
+    let get = |id: u32| authorized(TestRequest::get().uri(&format!("/items/{}", id)), &token);
+    let cache_status = |response: TestResponse| response.headers.get("x-cache").unwrap().clone();
+
+    app.serve(get(1), routes).await;
+    app.serve(get(2), routes).await;
+
+    // Route parameters fill in the tag
+    app.state.response_cache.invalidate_tag("item:1").await;
+    assert_eq!(cache_status(app.serve(get(1), routes).await), "miss");
+    assert_eq!(cache_status(app.serve(get(2), routes).await), "hit");
+
+    app.state.response_cache.invalidate_tag("items:list").await;
+    assert_eq!(cache_status(app.serve(get(1), routes).await), "miss");
+    assert_eq!(cache_status(app.serve(get(2), routes).await), "miss");
+}
+
+#[actix_web::test]
+async fn invalidations_from_other_replicas_are_applied() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::new_v4());
+    let get = || authorized(TestRequest::get().uri("/items/7"), &token);
+
+    app.serve(get(), routes).await;
+    app.state.response_cache.receive("malformed");
+    app.state.response_cache.receive("another-replica item:8");
+    assert_eq!(app.state.response_cache.len(), 1);
+
+    app.state.response_cache.receive("another-replica item:7");
+    assert!(app.state.response_cache.is_empty());
+}
+
+#[actix_web::test]
+async fn unsuccessful_responses_are_not_cached() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::new_v4());
+    let before = FAILING_CALLS.load(Ordering::SeqCst);
+
+    for _ in 0..2 {
+        let request = authorized(TestRequest::get().uri("/failing"), &token);
+        assert_status(app.serve(request, routes).await, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    assert_eq!(FAILING_CALLS.load(Ordering::SeqCst) - before, 2);
+    assert!(app.state.response_cache.is_empty());
+}
+
+#[actix_web::test]
+async fn authorization_runs_before_the_handler() {
+    let app = TestApp::spawn().await;
+
+    let response = app.serve(TestRequest::get().uri("/unauthenticated"), routes).await;
+
+    assert_status(response, StatusCode::UNAUTHORIZED);
+    assert_eq!(UNAUTHENTICATED_CALLS.load(Ordering::SeqCst), 0);
+}
+
+#[actix_web::test]
+async fn caching_can_be_switched_off() {
+    let app = TestApp::spawn_with(|config| config.set_override("cache.enabled", false).unwrap()).await;
+    let token = app.token_for(Uuid::new_v4());
+
+    for _ in 0..2 {
+        let response = app.serve(authorized(TestRequest::get().uri("/cached"), &token), routes).await;
+        let response = assert_status(response, StatusCode::OK);
+        assert!(response.headers.get("x-cache").is_none());
+    }
+}
@@ -0,0 +1,36 @@
+use actix_template::config::RuntimeSettings;
+use actix_template::runtime;
+
+#[test]
+fn runtimes_are_built_as_configured() {
+    let settings = RuntimeSettings {
+        worker_threads: Some(3),
+        max_blocking_threads: 8,
+        thread_stack_size: Some(4 * 1024 * 1024),
+        thread_name: "tuned".to_string(),
+    };
+
+    let runtime = runtime::build(&settings).unwrap();
+
+    assert_eq!(runtime.metrics().num_workers(), 3);
+    let name = runtime.block_on(async {
+        tokio::spawn(async { std::thread::current().name().map(str::to_string) }).await.unwrap()
+    });
+    assert_eq!(name.as_deref(), Some("tuned"));
+}
+
+#[test]
+fn systems_run_on_the_configured_runtime() {
+    let settings = RuntimeSettings {
+        worker_threads: Some(2),
+        max_blocking_threads: 4,
+        thread_stack_size: None,
+        thread_name: "tuned".to_string(),
+    };
+
+    let workers = runtime::system(&settings)
+        .unwrap()
+        .block_on(async { tokio::runtime::Handle::current().metrics().num_workers() });
+
+    assert_eq!(workers, 2);
+}
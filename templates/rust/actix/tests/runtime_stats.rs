@@ -0,0 +1,34 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use uuid::Uuid;
+
+use common::{assert_status, authorized, TestApp};
+
+#[actix_web::test]
+async fn runtime_stats_cover_registered_runtimes() {
+    let app = TestApp::spawn().await;
+    let token = app.admin_token_for(Uuid::new_v4());
+
+    let request = TestRequest::get().uri("/api/v1/admin/runtime");
+    assert_status(app.admin_request(request).await, StatusCode::UNAUTHORIZED);
+
+    app.state.runtimes.register_current("test");
+    app.state.runtimes.register_current("test");
+    tokio::spawn(std::future::pending::<()>());
+
+    let request = authorized(TestRequest::get().uri("/api/v1/admin/runtime"), &token);
+    let response = assert_status(app.admin_request(request).await, StatusCode::OK);
+    assert_eq!(response.body["console"], false);
+
+    let runtimes = response.body["runtimes"].as_array().unwrap();
+    assert_eq!(runtimes.len(), 1);
+    let runtime = &runtimes[0];
+    assert!(runtime["name"].as_str().unwrap().starts_with("test/"), "{}", runtime);
+    assert_eq!(runtime["workers"], 1);
+    assert!(runtime["alive_tasks"].as_u64().unwrap() >= 1, "{}", runtime);
+    assert_eq!(runtime["worker_busy_ms"].as_array().unwrap().len(), 1);
+    // Only reported with --cfg tokio_unstable
+    assert_eq!(runtime["blocking_pool"].is_null(), !cfg!(tokio_unstable));
+}
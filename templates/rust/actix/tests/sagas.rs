@@ -0,0 +1,205 @@
+mod common;
+
+use chrono::Duration;
+use futures_util::future::BoxFuture;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+
+use actix_template::config::SagaSettings;
+use actix_template::errors::{AppError, AppResult};
+use actix_template::models::saga::SagaRecord;
+use actix_template::saga::{Saga, SagaContext, SagaEngine, SagaStep};
+use actix_template::utils::Clock;
+use common::TestApp;
+use uuid::Uuid;
+
+type Log = Arc<Mutex<Vec<String>>>;
+
+/// Records what it does in a shared log; fails where told to.
+struct Step {
+    name: &'static str,
+    log: Log,
+    fail_execute: bool,
+    fail_compensate: bool,
+}
+
+impl Step {
+    fn new(name: &'static str, log: &Log) -> Self {
+        Self {
+            name,
+            log: log.clone(),
+            fail_execute: false,
+            fail_compensate: false,
+        }
+    }
+
+    fn failing(mut self) -> Self {
+        self.fail_execute = true;
+        self
+    }
+
+    fn failing_compensation(mut self) -> Self {
+        self.fail_compensate = true;
+        self
+    }
+}
+
+impl SagaStep for Step {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn execute<'a>(&'a self, context: &'a mut SagaContext) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            if self.fail_execute {
+                return Err(AppError::BadRequest(format!("{} failed", self.name)));
+            }
+            self.log.lock().unwrap().push(self.name.to_string());
+            context.set(self.name, true);
+            Ok(())
+        })
+    }
+
+    fn compensate<'a>(&'a self, context: &'a mut SagaContext) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            assert_eq!(context.get(self.name), Some(&json!(true)), "sees what execute saved");
+            if self.fail_compensate {
+                return Err(AppError::BadRequest(format!("undo {} failed", self.name)));
+            }
+            self.log.lock().unwrap().push(format!("undo {}", self.name));
+            Ok(())
+        })
+    }
+}
+
+fn engine(app: &TestApp, saga: Saga) -> SagaEngine {
+    let settings = SagaSettings {
+        lease_secs: 60,
+        max_compensation_attempts: 2,
+        resume_interval_secs: 1,
+    };
+    SagaEngine::new(app.state.db.clone(), app.state.job_queue.clone(), settings, app.clock.clone()).register(saga)
+}
+
+async fn saga(engine: &SagaEngine, saga_id: Uuid) -> SagaRecord {
+    engine.get_saga(saga_id).await.unwrap().unwrap()
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn steps_run_in_order() {
+    let app = TestApp::spawn().await;
+    let log = Log::default();
+    let engine = engine(&app, Saga::new("test.ok").step(Step::new("a", &log)).step(Step::new("b", &log)));
+
+    let saga_id = engine.start("test.ok", &json!({ "order": 1 })).await.unwrap();
+    assert_eq!(saga(&engine, saga_id).await.status, "running");
+    engine.run(saga_id).await.unwrap();
+
+    let record = saga(&engine, saga_id).await;
+    assert_eq!(record.status, "completed");
+    assert_eq!(record.current_step, 2);
+    assert_eq!(record.data, json!({ "order": 1, "a": true, "b": true }));
+    assert!(record.locked_until.is_none());
+    assert_eq!(*log.lock().unwrap(), ["a", "b"]);
+
+    // Finished sagas aren't run again
+    engine.run(saga_id).await.unwrap();
+    assert_eq!(log.lock().unwrap().len(), 2);
+
+    assert!(engine.start("test.unknown", &json!({})).await.is_err());
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn failures_compensate_completed_steps_in_reverse() {
+    let app = TestApp::spawn().await;
+    let log = Log::default();
+    let engine = engine(
+        &app,
+        Saga::new("test.compensated")
+            .step(Step::new("a", &log))
+            .step(Step::new("b", &log))
+            .step(Step::new("c", &log).failing()),
+    );
+
+    let saga_id = engine.start("test.compensated", &json!({})).await.unwrap();
+    engine.run(saga_id).await.unwrap();
+
+    let record = saga(&engine, saga_id).await;
+    assert_eq!(record.status, "compensated");
+    assert_eq!(record.current_step, 0);
+    assert_eq!(record.last_error.as_deref(), Some("Bad Request: c failed"));
+    assert_eq!(*log.lock().unwrap(), ["a", "b", "undo b", "undo a"]);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn failed_compensations_are_retried_then_given_up() {
+    let app = TestApp::spawn().await;
+    let log = Log::default();
+    let engine = engine(
+        &app,
+        Saga::new("test.stuck")
+            .step(Step::new("a", &log).failing_compensation())
+            .step(Step::new("b", &log).failing()),
+    );
+
+    let saga_id = engine.start("test.stuck", &json!({})).await.unwrap();
+    // The error makes the job retry
+    assert!(engine.run(saga_id).await.is_err());
+    let record = saga(&engine, saga_id).await;
+    assert_eq!(record.status, "compensating");
+    assert_eq!(record.compensation_attempts, 1);
+    assert!(record.locked_until.is_none());
+
+    engine.run(saga_id).await.unwrap();
+    let record = saga(&engine, saga_id).await;
+    assert_eq!(record.status, "failed");
+    assert_eq!(record.compensation_attempts, 2);
+    assert_eq!(record.last_error.as_deref(), Some("Bad Request: undo a failed"));
+    assert_eq!(*log.lock().unwrap(), ["a"]);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn stalled_sagas_are_resumed() {
+    let app = TestApp::spawn().await;
+    let log = Log::default();
+    let engine = engine(&app, Saga::new("test.resumed").step(Step::new("a", &log)));
+
+    // A worker claimed the job and the saga, then died
+    let saga_id = engine.start("test.resumed", &json!({})).await.unwrap();
+    let job_id = saga(&engine, saga_id).await.job_id.unwrap();
+    sqlx::query("UPDATE jobs SET status = 'running', locked_at = $2 WHERE id = $1")
+        .bind(job_id)
+        .bind(app.clock.now())
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE sagas SET locked_until = $2 WHERE id = $1")
+        .bind(saga_id)
+        .bind(app.clock.now() + Duration::seconds(60))
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+
+    // Leased sagas are left alone, and can't be run twice
+    assert!(!engine.resume_stalled().await.unwrap().contains(&saga_id));
+    engine.run(saga_id).await.unwrap();
+    assert!(log.lock().unwrap().is_empty());
+
+    app.clock.advance(Duration::seconds(60));
+    assert!(engine.resume_stalled().await.unwrap().contains(&saga_id));
+    let resumed = saga(&engine, saga_id).await.job_id.unwrap();
+    assert_ne!(resumed, job_id);
+    assert_eq!(app.state.job_queue.get_job(job_id).await.unwrap().unwrap().status, "dead");
+    assert_eq!(app.state.job_queue.get_job(resumed).await.unwrap().unwrap().status, "pending");
+
+    // Once its new job is queued it isn't resumed again
+    assert!(!engine.resume_stalled().await.unwrap().contains(&saga_id));
+
+    engine.run(saga_id).await.unwrap();
+    assert_eq!(saga(&engine, saga_id).await.status, "completed");
+    assert_eq!(*log.lock().unwrap(), ["a"]);
+}
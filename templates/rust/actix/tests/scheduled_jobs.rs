@@ -0,0 +1,72 @@
+mod common;
+
+use chrono::Duration;
+use futures_util::future::BoxFuture;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use actix_template::errors::{AppResult, ErrorCode};
+use actix_template::jobs::{JobHandler, Worker};
+use actix_template::models::job::Job;
+use actix_template::utils::Clock;
+use common::TestApp;
+
+#[derive(Clone, Default)]
+struct Counter(Arc<AtomicUsize>);
+
+impl JobHandler for Counter {
+    fn handle<'a>(&'a self, _job: &'a Job) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+    }
+}
+
+// One test, so no other test's worker picks up these jobs. Runs are
+// counted by the handler, since the worker also runs jobs other test
+// binaries left behind.
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn scheduled_jobs_wait_until_due_and_lost_ones_are_claimed_again() {
+    let app = TestApp::spawn().await;
+    let queue = &app.state.job_queue;
+    let runs = Counter::default();
+    let worker = Worker::new(app.state.db.clone(), app.state.settings.jobs.clone(), app.clock.clone())
+        .handler("test.reminder", runs.clone());
+
+    let reminder = queue.enqueue_in("test.reminder", &(), Duration::hours(24)).await.unwrap();
+    let purge = queue.enqueue_at("test.reminder", &(), app.clock.now() + Duration::days(30)).await.unwrap();
+    worker.drain().await.unwrap();
+    assert_eq!(runs.0.load(Ordering::SeqCst), 0);
+
+    // Moved up, or called off before it's due
+    let job = queue.reschedule(reminder, app.clock.now() + Duration::hours(1)).await.unwrap();
+    assert_eq!(job.run_at, app.clock.now() + Duration::hours(1));
+    queue.cancel(purge).await.unwrap();
+    assert_eq!(queue.cancel(purge).await.unwrap_err().code(), ErrorCode::NotFound);
+
+    app.clock.advance(Duration::hours(1));
+    worker.drain().await.unwrap();
+    assert_eq!(runs.0.load(Ordering::SeqCst), 1);
+    let error = queue.reschedule(reminder, app.clock.now()).await.unwrap_err();
+    assert_eq!(error.code(), ErrorCode::Conflict);
+
+    // A job whose worker died is claimed again once its visibility timeout
+    // has passed
+    let lost = queue.enqueue("test.reminder", &()).await.unwrap();
+    sqlx::query("UPDATE jobs SET status = 'running', locked_at = $2, attempts = 1 WHERE id = $1")
+        .bind(lost)
+        .bind(app.clock.now())
+        .execute(&app.state.db)
+        .await
+        .unwrap();
+    worker.drain().await.unwrap();
+    assert_eq!(runs.0.load(Ordering::SeqCst), 1);
+    app.clock.advance(Duration::seconds(app.state.settings.jobs.visibility_timeout_secs));
+    worker.drain().await.unwrap();
+    assert_eq!(runs.0.load(Ordering::SeqCst), 2);
+    let job = queue.get_job(lost).await.unwrap().unwrap();
+    assert_eq!(job.status, "completed");
+    assert_eq!(job.attempts, 2);
+}
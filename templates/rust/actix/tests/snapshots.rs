@@ -0,0 +1,341 @@
+//! Snapshots of response bodies, so wire-format changes show up in review.
+//! Dynamic fields (timestamps, versions, retry delays) are redacted.
+//!
+//! Validation cases have a single invalid field: validator reports several
+//! in no particular order.
+//!
+//! Review changes with `cargo insta review`.
+
+mod common;
+
+use actix_web::http::header::{CONTENT_TYPE, IF_MATCH};
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use chrono::Duration;
+use insta::assert_json_snapshot;
+use serde_json::json;
+use uuid::Uuid;
+
+use actix_template::config::CountMode;
+use actix_template::factories::UserFactory;
+use actix_template::models::api_token::{ApiTokenResponse, CreatedApiTokenResponse};
+use actix_template::models::user::{LoginResponse, PaginatedResponse, UserResponse};
+use common::{assert_status, authorized, test_epoch, TestApp};
+
+fn user_id() -> Uuid {
+    Uuid::from_u128(1)
+}
+
+fn other_user_id() -> Uuid {
+    Uuid::from_u128(2)
+}
+
+// Routes without a database
+
+#[actix_web::test]
+async fn health() {
+    let app = TestApp::spawn().await;
+
+    let response = app.request(TestRequest::get().uri("/api/v1/health")).await;
+
+    assert_json_snapshot!(response.snapshot(), {
+        ".body.timestamp" => "[timestamp]",
+        ".body.version" => "[version]",
+    });
+}
+
+#[actix_web::test]
+async fn readiness_without_database() {
+    if std::env::var("TEST_DATABASE_URL").is_ok() {
+        return;
+    }
+    let app = TestApp::spawn().await;
+
+    let response = app.request(TestRequest::get().uri("/api/v1/ready")).await;
+
+    assert_json_snapshot!(response.snapshot(), {
+        ".body.timestamp" => "[timestamp]",
+        ".body.dependencies[].latency_ms" => "[latency]",
+    });
+}
+
+#[actix_web::test]
+async fn error_catalog() {
+    let app = TestApp::spawn().await;
+
+    let response = app.request(TestRequest::get().uri("/api/v1/errors")).await;
+
+    assert_json_snapshot!(response.snapshot());
+}
+
+// Authentication errors
+
+#[actix_web::test]
+async fn missing_token() {
+    let app = TestApp::spawn().await;
+
+    let response = app.request(TestRequest::get().uri("/api/v1/users")).await;
+
+    assert_json_snapshot!(assert_status(response, StatusCode::UNAUTHORIZED).snapshot());
+}
+
+#[actix_web::test]
+async fn invalid_token() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .request(authorized(TestRequest::get().uri("/api/v1/users"), "not-a-jwt"))
+        .await;
+
+    assert_json_snapshot!(assert_status(response, StatusCode::UNAUTHORIZED).snapshot());
+}
+
+#[actix_web::test]
+async fn expired_token() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(user_id());
+    app.clock.advance(Duration::hours(2));
+
+    let response = app
+        .request(authorized(TestRequest::get().uri("/api/v1/tokens"), &token))
+        .await;
+
+    assert_json_snapshot!(assert_status(response, StatusCode::UNAUTHORIZED).snapshot());
+}
+
+// Request errors
+
+#[actix_web::test]
+async fn create_user_validation_error() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(user_id());
+
+    let response = app
+        .request(authorized(
+            TestRequest::post().uri("/api/v1/users").set_json(json!({
+                "email": "not-an-email",
+                "username": "alice",
+                "password": "password",
+            })),
+            &token,
+        ))
+        .await;
+
+    assert_json_snapshot!(response.snapshot());
+}
+
+#[actix_web::test]
+async fn update_other_user_is_forbidden() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(user_id());
+
+    let response = app
+        .request(authorized(
+            TestRequest::patch()
+                .uri(&format!("/api/v1/users/{}", other_user_id()))
+                .set_json(json!({ "full_name": "Mallory" })),
+            &token,
+        ))
+        .await;
+
+    assert_json_snapshot!(assert_status(response, StatusCode::FORBIDDEN).snapshot());
+}
+
+#[actix_web::test]
+async fn update_user_with_invalid_if_match() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(user_id());
+
+    let response = app
+        .request(authorized(
+            TestRequest::patch()
+                .uri(&format!("/api/v1/users/{}", user_id()))
+                .insert_header((IF_MATCH, "not-an-etag"))
+                .set_json(json!({ "full_name": "Alice" })),
+            &token,
+        ))
+        .await;
+
+    assert_json_snapshot!(assert_status(response, StatusCode::BAD_REQUEST).snapshot());
+}
+
+#[actix_web::test]
+async fn delete_other_user_is_forbidden() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(user_id());
+
+    let response = app
+        .request(authorized(
+            TestRequest::delete().uri(&format!("/api/v1/users/{}", other_user_id())),
+            &token,
+        ))
+        .await;
+
+    assert_json_snapshot!(assert_status(response, StatusCode::FORBIDDEN).snapshot());
+}
+
+#[actix_web::test]
+async fn create_token_validation_error() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(user_id());
+
+    let response = app
+        .request(authorized(
+            TestRequest::post().uri("/api/v1/tokens").set_json(json!({
+                "name": "ci",
+                "scopes": ["read:users"],
+                "expires_in_days": 0,
+            })),
+            &token,
+        ))
+        .await;
+
+    assert_json_snapshot!(response.snapshot());
+}
+
+#[actix_web::test]
+async fn register_validation_error() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .request(TestRequest::post().uri("/api/v1/auth/register").set_json(json!({
+            "email": "alice@example.com",
+            "username": "alice",
+            "password": "short",
+        })))
+        .await;
+
+    assert_json_snapshot!(response.snapshot());
+}
+
+#[actix_web::test]
+async fn login_validation_error() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .request(TestRequest::post().uri("/api/v1/auth/login").set_json(json!({
+            "email": "not-an-email",
+            "password": "password",
+        })))
+        .await;
+
+    assert_json_snapshot!(response.snapshot());
+}
+
+#[actix_web::test]
+async fn malformed_json() {
+    let app = TestApp::spawn().await;
+
+    let response = app
+        .request(
+            TestRequest::post()
+                .uri("/api/v1/auth/login")
+                .insert_header((CONTENT_TYPE, "application/json"))
+                .set_payload("{\"email\":"),
+        )
+        .await;
+
+    assert_json_snapshot!(assert_status(response, StatusCode::BAD_REQUEST).snapshot());
+}
+
+#[actix_web::test]
+async fn oversized_json() {
+    let app = TestApp::spawn_with(|config| {
+        config
+            .set_override("server.max_json_body_bytes", 64)
+            .expect("body limit override")
+    })
+    .await;
+
+    let response = app
+        .request(TestRequest::post().uri("/api/v1/auth/login").set_json(json!({
+            "email": "alice@example.com",
+            "password": "x".repeat(128),
+        })))
+        .await;
+
+    assert_json_snapshot!(assert_status(response, StatusCode::PAYLOAD_TOO_LARGE).snapshot());
+}
+
+#[actix_web::test]
+async fn maintenance_mode() {
+    let app = TestApp::spawn_with(|config| {
+        config
+            .set_override("maintenance.enabled", true)
+            .expect("maintenance override")
+    })
+    .await;
+
+    let response = app
+        .request(TestRequest::post().uri("/api/v1/auth/login").set_json(json!({
+            "email": "alice@example.com",
+            "password": "password",
+        })))
+        .await;
+
+    assert!(response.headers.contains_key("retry-after"));
+    assert_json_snapshot!(
+        assert_status(response, StatusCode::SERVICE_UNAVAILABLE).snapshot(),
+        { ".body.retry_after_ms" => "[delay]" }
+    );
+}
+
+// Success bodies, which need a database to produce end to end
+
+fn user_response() -> UserResponse {
+    UserFactory::build().into_user().into()
+}
+
+fn api_token_response() -> ApiTokenResponse {
+    ApiTokenResponse {
+        id: other_user_id(),
+        name: "ci".to_string(),
+        prefix: "dxp_abcd1234".to_string(),
+        scopes: vec!["read:users".to_string()],
+        expires_at: Some(test_epoch() + Duration::days(30)),
+        last_used_at: None,
+        created_at: test_epoch(),
+    }
+}
+
+#[test]
+fn user_body() {
+    assert_json_snapshot!(user_response());
+}
+
+#[test]
+fn login_body() {
+    assert_json_snapshot!(LoginResponse {
+        access_token: "[access token]".to_string(),
+        refresh_token: "[refresh token]".to_string(),
+        token_type: "Bearer".to_string(),
+        expires_in: 3600,
+        user: user_response(),
+    });
+}
+
+#[test]
+fn user_page_body() {
+    assert_json_snapshot!(PaginatedResponse {
+        data: vec![user_response()],
+        total: Some(21),
+        page: 1,
+        limit: 20,
+        total_pages: Some(2),
+        count: CountMode::Exact,
+    });
+}
+
+#[test]
+fn api_token_body() {
+    assert_json_snapshot!(api_token_response());
+}
+
+#[test]
+fn created_api_token_body() {
+    assert_json_snapshot!(CreatedApiTokenResponse {
+        token: "[token]".to_string(),
+        signing_key: Some("[signing key]".to_string()),
+        details: api_token_response(),
+    });
+}
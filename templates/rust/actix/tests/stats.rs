@@ -0,0 +1,61 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use chrono::Duration;
+
+use actix_template::factories::UserFactory;
+use actix_template::utils::{create_guest_token, Clock};
+use common::{assert_status, authorized, TestApp};
+
+#[actix_web::test]
+async fn stats_need_the_read_stats_scope() {
+    let app = TestApp::spawn_with(|config| config.set_override("guests.enabled", true).unwrap()).await;
+    let mut guest = UserFactory::build().into_user();
+    guest.is_guest = true;
+    guest.guest_expires_at = Some(app.clock.now() + Duration::hours(1));
+    let token = create_guest_token(&guest, &app.state.jwt_keys, app.clock.as_ref()).unwrap();
+
+    for uri in ["/api/v1/stats", "/api/v1/stats/daily_signups"] {
+        let response = app.request(authorized(TestRequest::get().uri(uri), &token)).await;
+        let response = assert_status(response, StatusCode::FORBIDDEN);
+        assert_eq!(response.body["error_code"], "AUTH_INSUFFICIENT_SCOPE");
+    }
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn summaries_are_served_until_their_staleness_budget_runs_out() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build().verified()).await;
+    let token = app.token_for_user(&user);
+    let stats = || async {
+        let response = app.request(authorized(TestRequest::get().uri("/api/v1/stats"), &token)).await;
+        assert_status(response, StatusCode::OK).body
+    };
+
+    let first = stats().await;
+    assert!(first["total_users"].as_i64().unwrap() >= 1);
+    assert!(first["verified_users"].as_i64().unwrap() >= 1);
+    assert!(first["signups_per_day"].is_array());
+
+    // Within the budget the same figures come back
+    app.insert_user(UserFactory::build()).await;
+    assert_eq!(stats().await, first);
+
+    app.clock.advance(Duration::seconds(app.state.settings.stats.max_staleness_secs + 1));
+    let second = stats().await;
+    assert!(second["total_users"].as_i64().unwrap() > first["total_users"].as_i64().unwrap());
+    assert_ne!(second["as_of"], first["as_of"]);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn signups_come_from_the_view_while_it_is_fresh() {
+    let app = TestApp::spawn().await;
+    app.state.materialized_views.refresh("daily_signups").await.unwrap();
+    let refreshed_at = app.state.materialized_views.refreshed_at("daily_signups").await.unwrap();
+
+    let summary = app.state.stats_service.summary().await.unwrap();
+    assert_eq!(Some(summary.as_of), refreshed_at);
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use actix_web::{web, HttpResponse};
+use chrono::Duration;
+use serde_json::json;
+use uuid::Uuid;
+
+use actix_template::factories::UserFactory;
+use actix_template::middleware::{AuthMiddleware, RequireStepUp};
+use actix_template::models::role::Grants;
+use actix_template::models::user::Acr;
+use actix_template::utils::{create_step_up_token, decode_jwt_token};
+use common::{assert_status, authorized, TestApp};
+
+async fn spawn() -> TestApp {
+    TestApp::spawn_with(|config| config.set_override("step_up.enabled", true).unwrap()).await
+}
+
+fn step_up_token(app: &TestApp, acr: Acr) -> String {
+    let user = UserFactory::build().into_user();
+    create_step_up_token(user.id, &user.email, Grants::default(), acr, &app.state.jwt_keys, 300, app.clock.as_ref()).unwrap()
+}
+
+async fn ok() -> HttpResponse {
+    HttpResponse::Ok().finish()
+}
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/recent")
+            .wrap(RequireStepUp::recent())
+            .wrap(AuthMiddleware)
+            .route(web::get().to(ok)),
+    )
+    .service(
+        web::resource("/multi-factor")
+            .wrap(RequireStepUp::multi_factor())
+            .wrap(AuthMiddleware)
+            .route(web::get().to(ok)),
+    );
+}
+
+#[actix_web::test]
+async fn step_up_is_off_by_default() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::new_v4());
+
+    assert_status(app.serve(authorized(TestRequest::get().uri("/recent"), &token), routes).await, StatusCode::OK);
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/step-up")
+        .set_json(json!({ "method": "password", "password": "secret" }));
+    assert_status(app.request(authorized(request, &token)).await, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn step_up_tokens_record_the_re_authentication() {
+    let app = spawn().await;
+
+    let claims = decode_jwt_token(&step_up_token(&app, Acr::Passkey), &app.state.jwt_keys, app.clock.as_ref()).unwrap();
+    assert_eq!(claims.auth_time, Some(claims.iat));
+    assert_eq!(claims.acr, Some(Acr::Passkey));
+    assert_eq!(claims.exp, claims.iat + 300);
+
+    let claims = decode_jwt_token(&app.token_for(Uuid::new_v4()), &app.state.jwt_keys, app.clock.as_ref()).unwrap();
+    assert!(claims.auth_time.is_none());
+}
+
+#[actix_web::test]
+async fn marked_routes_need_a_recent_re_authentication() {
+    let app = spawn().await;
+    let get = |uri: &str, token: &str| authorized(TestRequest::get().uri(uri), token);
+
+    let token = app.token_for(Uuid::new_v4());
+    let response = assert_status(app.serve(get("/recent", &token), routes).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "STEP_UP_REQUIRED");
+
+    let password = step_up_token(&app, Acr::Password);
+    assert_status(app.serve(get("/recent", &password), routes).await, StatusCode::OK);
+    let response = assert_status(app.serve(get("/multi-factor", &password), routes).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "STEP_UP_REQUIRED");
+
+    let passkey = step_up_token(&app, Acr::Passkey);
+    assert_status(app.serve(get("/multi-factor", &passkey), routes).await, StatusCode::OK);
+
+    // Within the token's leeway, but no longer recent
+    app.clock.advance(Duration::seconds(301));
+    let response = assert_status(app.serve(get("/multi-factor", &passkey), routes).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "STEP_UP_REQUIRED");
+}
+
+#[actix_web::test]
+async fn token_management_needs_step_up() {
+    let app = spawn().await;
+    let token = app.token_for(Uuid::new_v4());
+
+    let request = TestRequest::post()
+        .uri("/api/v1/tokens")
+        .set_json(json!({ "name": "ci", "scopes": ["read:profile"] }));
+    let response = assert_status(app.request(authorized(request, &token)).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "STEP_UP_REQUIRED");
+
+    let request = TestRequest::delete().uri(&format!("/api/v1/tokens/{}", Uuid::new_v4()));
+    let response = assert_status(app.request(authorized(request, &token)).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "STEP_UP_REQUIRED");
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn re_authenticating_unlocks_sensitive_changes() {
+    let app = spawn().await;
+    let user = app.insert_user(UserFactory::build().password("correct horse battery")).await;
+    let token = app.token_for_user(&user);
+    let uri = format!("/api/v1/users/{}", user.id);
+    let email = format!("{}@example.test", Uuid::new_v4());
+
+    // Ordinary changes don't need it
+    let request = TestRequest::patch().uri(&uri).set_json(json!({ "full_name": "Ada" }));
+    assert_status(app.request(authorized(request, &token)).await, StatusCode::OK);
+
+    let request = TestRequest::patch().uri(&uri).set_json(json!({ "email": email }));
+    let response = assert_status(app.request(authorized(request, &token)).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "STEP_UP_REQUIRED");
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/step-up")
+        .set_json(json!({ "method": "password", "password": "wrong" }));
+    let response = assert_status(app.request(authorized(request, &token)).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "AUTH_INVALID_CREDENTIALS");
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/step-up")
+        .set_json(json!({ "method": "password", "password": "correct horse battery" }));
+    let response = assert_status(app.request(authorized(request, &token)).await, StatusCode::OK);
+    assert_eq!(response.body["acr"], "password");
+    assert_eq!(response.body["expires_in"], 300);
+    let elevated = response.body["access_token"].as_str().unwrap().to_string();
+
+    let request = TestRequest::patch().uri(&uri).set_json(json!({ "email": email }));
+    let response = assert_status(app.request(authorized(request, &elevated)).await, StatusCode::OK);
+    assert_eq!(response.body["email"], email);
+
+    // Re-authenticating needs a session to elevate
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/step-up")
+        .set_json(json!({ "method": "password", "password": "correct horse battery" }));
+    assert_status(app.request(request).await, StatusCode::UNAUTHORIZED);
+
+    let request = TestRequest::delete().uri(&uri);
+    assert_status(app.request(authorized(request, &token)).await, StatusCode::UNAUTHORIZED);
+    assert_status(app.request(authorized(TestRequest::delete().uri(&uri), &elevated)).await, StatusCode::ACCEPTED);
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use actix_web::{web, App, HttpRequest, HttpResponse};
+use platform_core::lifecycle::Lifecycle;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_template::manifest::Manifest;
+use actix_template::telemetry::{self, Heartbeat, InstanceStatus, Reporter};
+use common::TestApp;
+
+type Received = Arc<Mutex<Vec<(Option<String>, Heartbeat)>>>;
+
+/// A control plane stand-in recording heartbeats and their bearer tokens.
+fn control_plane() -> (actix_test::TestServer, Received) {
+    let received: Received = Arc::default();
+    let server = {
+        let received = received.clone();
+        actix_test::start(move || {
+            let received = received.clone();
+            App::new().route(
+                "/heartbeats",
+                web::post().to(move |req: HttpRequest, heartbeat: web::Json<Heartbeat>| {
+                    let token = req
+                        .headers()
+                        .get("authorization")
+                        .map(|value| value.to_str().unwrap().to_string());
+                    received.lock().unwrap().push((token, heartbeat.into_inner()));
+                    async { HttpResponse::NoContent().finish() }
+                }),
+            )
+        })
+    };
+    (server, received)
+}
+
+async fn app_reporting_to(endpoint: &str) -> TestApp {
+    let endpoint = endpoint.to_string();
+    TestApp::spawn_with(move |config| {
+        config
+            .set_override("telemetry.enabled", true)
+            .unwrap()
+            .set_override("telemetry.endpoint", endpoint)
+            .unwrap()
+            .set_override("telemetry.token", "platform-token")
+            .unwrap()
+            .set_override("telemetry.service_name", "billing")
+            .unwrap()
+    })
+    .await
+}
+
+#[actix_web::test]
+async fn heartbeats_identify_the_instance() {
+    let (server, received) = control_plane();
+    let app = app_reporting_to(&server.url("/heartbeats")).await;
+    app.state.runtimes.register_current("test");
+    let reporter = Reporter::new(app.state.clone().into_inner()).unwrap();
+
+    app.clock.advance(chrono::Duration::seconds(90));
+    let heartbeat = reporter.heartbeat(false).await;
+    reporter.send(&heartbeat).await.unwrap();
+
+    let received = received.lock().unwrap();
+    let (token, heartbeat) = &received[0];
+    assert_eq!(token.as_deref(), Some("Bearer platform-token"));
+    assert_eq!(heartbeat.service, "billing");
+    assert_eq!(heartbeat.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(heartbeat.uptime_secs, 90);
+    // The test database isn't reachable
+    assert_eq!(heartbeat.status, InstanceStatus::Unready);
+    assert!(heartbeat.health.dependency("database").is_some());
+    assert_eq!(heartbeat.metrics.runtimes, 1);
+    assert_eq!(heartbeat.manifest, Manifest::current());
+}
+
+#[actix_web::test]
+async fn the_last_heartbeat_says_the_instance_is_stopping() {
+    let (server, received) = control_plane();
+    let app = app_reporting_to(&server.url("/heartbeats")).await;
+
+    let mut lifecycle = Lifecycle::new(&app.state.settings.lifecycle);
+    telemetry::register_hooks(&mut lifecycle, &app.state.clone().into_inner()).unwrap();
+    lifecycle.ready().await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    lifecycle.shutdown().await;
+
+    let statuses: Vec<_> = received.lock().unwrap().iter().map(|(_, heartbeat)| heartbeat.status).collect();
+    assert_eq!(statuses, [InstanceStatus::Unready, InstanceStatus::Stopping]);
+    let instances: Vec<_> = received.lock().unwrap().iter().map(|(_, heartbeat)| heartbeat.instance_id).collect();
+    assert_eq!(instances[0], instances[1]);
+}
+
+#[actix_web::test]
+async fn failed_heartbeats_back_off() {
+    let app = app_reporting_to("http://127.0.0.1:1/heartbeats").await;
+    let reporter = Reporter::new(app.state.clone().into_inner()).unwrap();
+
+    assert!(reporter.send(&reporter.heartbeat(false).await).await.is_err());
+    let delays: Vec<_> = [0, 1, 2, 3, 4, 40].map(|failures| reporter.backoff(failures).as_secs()).into();
+    assert_eq!(delays, [60, 120, 240, 480, 900, 900]);
+}
+
+#[actix_web::test]
+async fn reporting_needs_an_http_endpoint() {
+    for endpoint in ["", "not a url", "ftp://platform.example.com/heartbeats"] {
+        let app = app_reporting_to(endpoint).await;
+        let error = Reporter::new(app.state.clone().into_inner()).err().unwrap();
+        assert!(error.to_string().contains("telemetry.endpoint"), "{}", error);
+    }
+}
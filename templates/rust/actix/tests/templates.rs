@@ -0,0 +1,105 @@
+//! Rendered emails, snapshotted so copy and layout changes show up in
+//! review.
+
+use insta::assert_snapshot;
+
+use actix_template::config::EmailSettings;
+use actix_template::templates::{
+    EmailTemplate, InviteEmail, MagicLinkEmail, PasswordResetEmail, RenderedEmail, VerificationEmail,
+};
+
+fn brand() -> EmailSettings {
+    EmailSettings {
+        product_name: "Acme".to_string(),
+        support_email: "support@acme.test".to_string(),
+    }
+}
+
+fn snapshot(email: &RenderedEmail) -> String {
+    format!("Subject: {}\n\n{}\n\n=== text ===\n\n{}", email.subject, email.html, email.text)
+}
+
+#[test]
+fn verification_email() {
+    let email = VerificationEmail {
+        name: "Ada".to_string(),
+        verify_url: "https://acme.test/verify?token=abc".to_string(),
+        expires_in_hours: 24,
+    }
+    .render(&brand())
+    .unwrap();
+
+    assert_snapshot!(snapshot(&email));
+}
+
+#[test]
+fn password_reset_email() {
+    let email = PasswordResetEmail {
+        name: "Ada".to_string(),
+        reset_url: "https://acme.test/reset?token=abc".to_string(),
+        expires_in_minutes: 30,
+        requested_from: Some("203.0.113.7".to_string()),
+    }
+    .render(&brand())
+    .unwrap();
+
+    assert_snapshot!(snapshot(&email));
+}
+
+#[test]
+fn invite_email() {
+    let email = InviteEmail {
+        inviter_name: "Grace".to_string(),
+        invite_url: "https://acme.test/invite/abc".to_string(),
+        expires_in_days: 7,
+    }
+    .render(&brand())
+    .unwrap();
+
+    assert_snapshot!(snapshot(&email));
+}
+
+#[test]
+fn magic_link_email() {
+    let email = MagicLinkEmail {
+        name: "Ada".to_string(),
+        login_url: "https://acme.test/login/magic-link?token=abc".to_string(),
+        expires_in_minutes: 15,
+        requested_from: Some("203.0.113.7".to_string()),
+    }
+    .render(&brand())
+    .unwrap();
+
+    assert_snapshot!(snapshot(&email));
+}
+
+#[test]
+fn html_escapes_user_input_and_text_keeps_it() {
+    let email = VerificationEmail {
+        name: "<script>alert(1)</script>".to_string(),
+        verify_url: "https://acme.test/verify?a=1&b=2".to_string(),
+        expires_in_hours: 24,
+    }
+    .render(&brand())
+    .unwrap();
+
+    assert!(!email.html.contains("<script>"));
+    assert!(email.html.contains("&lt;script&gt;"));
+    assert!(email.text.contains("Hi <script>alert(1)</script>,"));
+    assert!(email.text.contains("?a=1&b=2"));
+}
+
+#[test]
+fn optional_fields_can_be_left_out() {
+    let email = PasswordResetEmail {
+        name: "Ada".to_string(),
+        reset_url: "https://acme.test/reset?token=abc".to_string(),
+        expires_in_minutes: 30,
+        requested_from: None,
+    }
+    .render(&brand())
+    .unwrap();
+
+    assert!(email.text.contains("Acme account. Choose"));
+    assert!(!email.html.contains(" from "));
+}
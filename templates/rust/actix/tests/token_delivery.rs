@@ -0,0 +1,176 @@
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::http::header::SET_COOKIE;
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use actix_template::factories::{UserFactory, FACTORY_PASSWORD};
+use actix_template::middleware::{AuthMiddleware, Pipeline};
+use common::{assert_status, TestApp, TestResponse};
+
+async fn spawn() -> TestApp {
+    TestApp::spawn_with(|config| {
+        config
+            .set_override("token_delivery.mode", "cookies")
+            .unwrap()
+            .set_override("token_delivery.secure_cookies", false)
+            .unwrap()
+    })
+    .await
+}
+
+/// The cookies `response` sets.
+fn cookies(response: &TestResponse) -> Vec<Cookie<'static>> {
+    response
+        .headers
+        .get_all(SET_COOKIE)
+        .map(|value| Cookie::parse(value.to_str().unwrap().to_string()).unwrap())
+        .collect()
+}
+
+fn cookie(response: &TestResponse, name: &str) -> Cookie<'static> {
+    cookies(response).into_iter().find(|cookie| cookie.name() == name).unwrap()
+}
+
+/// Marks `request` as coming from the API's own origin, as a browser would
+/// for a change.
+fn same_origin(request: TestRequest) -> TestRequest {
+    request
+        .insert_header(("Host", "api.example.test"))
+        .insert_header(("Origin", "http://api.example.test"))
+}
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/api/v1/protected")
+            .wrap(AuthMiddleware)
+            .route(web::get().to(HttpResponse::Ok))
+            .route(web::post().to(HttpResponse::Ok)),
+    );
+}
+
+#[actix_web::test]
+async fn cookies_need_the_csrf_check() {
+    let app = TestApp::spawn_with(|config| {
+        config
+            .set_override("token_delivery.mode", "cookies")
+            .unwrap()
+            .set_override("security.csrf", false)
+            .unwrap()
+    })
+    .await;
+    let error = Pipeline::from_settings(&app.state.settings).unwrap_err();
+    assert!(error.to_string().contains("security.csrf"), "{}", error);
+
+    let app = TestApp::spawn_with(|config| {
+        let middleware = vec![HashMap::from([("name".to_string(), "request_id")])];
+        config
+            .set_override("token_delivery.mode", "cookies")
+            .unwrap()
+            .set_override("server.middleware", middleware)
+            .unwrap()
+    })
+    .await;
+    assert!(Pipeline::from_settings(&app.state.settings).is_err());
+
+    assert!(Pipeline::from_settings(&spawn().await.state.settings).is_ok());
+}
+
+#[actix_web::test]
+async fn the_access_cookie_authenticates_in_cookie_mode_only() {
+    let app = spawn().await;
+    let token = app.token_for(Uuid::new_v4());
+    let request = || TestRequest::get().uri("/api/v1/protected").cookie(Cookie::new("dxp_access", token.clone()));
+
+    assert_status(app.serve(request(), routes).await, StatusCode::OK);
+
+    let body_mode = TestApp::spawn().await;
+    assert_status(body_mode.serve(request(), routes).await, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn cookie_authenticated_changes_are_checked_for_csrf() {
+    let app = spawn().await;
+    let token = app.token_for(Uuid::new_v4());
+    let request = || TestRequest::post().uri("/api/v1/protected").cookie(Cookie::new("dxp_access", token.clone()));
+
+    let forged = request()
+        .insert_header(("Host", "api.example.test"))
+        .insert_header(("Origin", "https://evil.example"));
+    let response = assert_status(app.serve(forged, routes).await, StatusCode::FORBIDDEN);
+    assert_eq!(response.body["error_code"], "CSRF_ORIGIN_MISMATCH");
+
+    assert_status(app.serve(same_origin(request()), routes).await, StatusCode::OK);
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn cookie_sessions_log_in_refresh_and_log_out() {
+    let app = spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({ "email": user.email, "password": FACTORY_PASSWORD }));
+    let response = assert_status(app.request(request).await, StatusCode::OK);
+    assert!(response.body.get("access_token").is_none());
+    assert!(response.body.get("refresh_token").is_none());
+    assert_eq!(response.body["user"]["id"], user.id.to_string());
+
+    let access = cookie(&response, "dxp_access");
+    assert_eq!(access.http_only(), Some(true));
+    assert_eq!(access.path(), Some("/api/v1"));
+    let refresh = cookie(&response, "dxp_refresh");
+    assert_eq!(refresh.path(), Some("/api/v1/auth"));
+
+    let uri = format!("/api/v1/users/{}", user.id);
+    let request = TestRequest::get().uri(&uri).cookie(access.clone());
+    assert_status(app.request(request).await, StatusCode::OK);
+
+    // Refreshing rotates the refresh cookie
+    let request = same_origin(TestRequest::post().uri("/api/v1/auth/refresh").cookie(refresh.clone()));
+    let response = assert_status(app.request(request).await, StatusCode::OK);
+    let rotated = cookie(&response, "dxp_refresh");
+    assert_ne!(rotated.value(), refresh.value());
+
+    let request = same_origin(TestRequest::post().uri("/api/v1/auth/logout").cookie(rotated.clone()));
+    let response = assert_status(app.request(request).await, StatusCode::NO_CONTENT);
+    assert!(cookies(&response).iter().all(|cookie| cookie.value().is_empty()));
+
+    let request = same_origin(TestRequest::post().uri("/api/v1/auth/refresh").cookie(rotated));
+    let response = assert_status(app.request(request).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "AUTH_REFRESH_TOKEN_INVALID");
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn logging_out_revokes_body_mode_sessions() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({ "email": user.email, "password": FACTORY_PASSWORD }));
+    let response = assert_status(app.request(request).await, StatusCode::OK);
+    assert!(cookies(&response).is_empty());
+    let refresh_token = response.body["refresh_token"].clone();
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/logout")
+        .set_json(json!({ "refresh_token": refresh_token }));
+    assert_status(app.request(request).await, StatusCode::NO_CONTENT);
+
+    let request = TestRequest::post()
+        .uri("/api/v1/auth/refresh")
+        .set_json(json!({ "refresh_token": refresh_token }));
+    assert_status(app.request(request).await, StatusCode::UNAUTHORIZED);
+
+    // A missing token is refused like an unknown one
+    let request = TestRequest::post().uri("/api/v1/auth/refresh");
+    assert_status(app.request(request).await, StatusCode::UNAUTHORIZED);
+}
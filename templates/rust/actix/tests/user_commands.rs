@@ -0,0 +1,72 @@
+mod common;
+
+use futures_util::future::BoxFuture;
+use platform_core::domain::{Email, Username};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+
+use actix_template::dry_run::DryRun;
+use actix_template::errors::AppResult;
+use actix_template::models::user::{CreateUser, UpdateUser};
+use actix_template::olap::{EventBuffer, EventSink};
+use actix_template::services::{PgUserCommands, PgUserQueries, UserCommands, UserQueries};
+use common::TestApp;
+
+/// Keeps the domain events it's sent.
+#[derive(Default)]
+struct Recorder(Mutex<Vec<Value>>);
+
+impl EventSink for Recorder {
+    fn write<'a>(&'a self, _table: &'a str, rows: &'a [Value]) -> BoxFuture<'a, AppResult<()>> {
+        self.0.lock().unwrap().extend_from_slice(rows);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+fn create_user(name: &str) -> CreateUser {
+    CreateUser {
+        email: Email::parse(format!("{}@example.com", name)).unwrap(),
+        username: Username::parse(name).unwrap(),
+        password: "correct-horse".to_string(),
+        full_name: None,
+    }
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn committed_changes_are_recorded_as_domain_events() {
+    let app = TestApp::spawn().await;
+    let recorder = Arc::new(Recorder::default());
+    let events = Arc::new(EventBuffer::new(Some(recorder.clone()), app.state.settings.olap.clone()));
+    let commands = PgUserCommands::new(app.state.db.clone(), events.clone(), app.clock.clone());
+    let queries = PgUserQueries::new(app.state.db.clone());
+
+    commands.create_user(create_user("rehearsal"), DryRun(true)).await.unwrap();
+    let user = commands.create_user(create_user("cqrs_user"), DryRun::default()).await.unwrap();
+    let update = UpdateUser {
+        full_name: Some(Some("Ada".to_string())),
+        ..UpdateUser::default()
+    };
+    commands.update_user(user.id, update, None, DryRun::default()).await.unwrap();
+    assert_eq!(queries.get_user_by_id(user.id).await.unwrap().full_name.as_deref(), Some("Ada"));
+    commands.delete_user(user.id).await.unwrap();
+
+    assert_eq!(events.flush().await, 3);
+    let recorded = recorder.0.lock().unwrap();
+    let types: Vec<&str> = recorded.iter().map(|row| row["event_type"].as_str().unwrap()).collect();
+    assert_eq!(types, ["user.created", "user.updated", "user.deleted"]);
+    assert!(recorded.iter().all(|row| row["user_id"] == user.id.to_string()));
+    assert_eq!(recorded[1]["metadata"], r#"{"fields":["full_name"]}"#);
+}
+
+#[test]
+fn updates_name_the_fields_they_set() {
+    let update = UpdateUser {
+        email: Some(Email::parse("ada@example.com").unwrap()),
+        phone_number: Some(None),
+        ..UpdateUser::default()
+    };
+
+    assert_eq!(update.fields(), ["email", "phone_number"]);
+    assert!(UpdateUser::default().fields().is_empty());
+}
@@ -0,0 +1,159 @@
+//! Other services calling with Kubernetes service-account tokens or SPIFFE
+//! JWT-SVIDs, verified against the key sets in `fixtures/workload`.
+
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use actix_web::{web, HttpRequest, HttpResponse};
+use config::{File, FileFormat};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde_json::json;
+use uuid::Uuid;
+
+use actix_template::errors::AppResult;
+use actix_template::middleware::auth::{require_workload_role, AuthMiddleware};
+use common::{assert_status, authorized, test_epoch, TestApp};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/workload");
+const CLUSTER: &str = "https://kubernetes.default.svc";
+
+async fn spawn() -> TestApp {
+    let settings = format!(
+        r#"
+        [workload_identity]
+        roles = [
+            {{ identity = "system:serviceaccount:billing:invoicer", roles = ["users.read"] }},
+            {{ identity = "spiffe://example.org/ns/billing/*", roles = ["users.read"] }},
+        ]
+
+        [[workload_identity.issuers]]
+        kind = "kubernetes"
+        issuer = "{CLUSTER}"
+        audience = "actix-template"
+        keys = {{ path = "{FIXTURES}/kubernetes-jwks.json" }}
+
+        [[workload_identity.issuers]]
+        kind = "spiffe"
+        trust_domain = "example.org"
+        audience = "actix-template"
+        keys = {{ path = "{FIXTURES}/spire-bundle.json" }}
+        "#
+    );
+    TestApp::spawn_with(|config| config.add_source(File::from_str(&settings, FileFormat::Toml))).await
+}
+
+fn sign(key: &str, kid: &str, claims: serde_json::Value) -> String {
+    let pem = std::fs::read(format!("{}/{}.pem", FIXTURES, key)).unwrap();
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = Some(kid.to_string());
+    encode(&header, &claims, &EncodingKey::from_ec_pem(&pem).unwrap()).unwrap()
+}
+
+fn service_account(name: &str) -> String {
+    sign(
+        "kubernetes",
+        "kube-1",
+        json!({
+            "iss": CLUSTER,
+            "sub": format!("system:serviceaccount:billing:{}", name),
+            "aud": ["actix-template"],
+            "exp": test_epoch().timestamp() + 600,
+        }),
+    )
+}
+
+fn svid(spiffe_id: &str) -> String {
+    sign(
+        "spire",
+        "spire-1",
+        json!({ "sub": spiffe_id, "aud": "actix-template", "exp": test_epoch().timestamp() + 300 }),
+    )
+}
+
+/// An internal route only workloads with `users.read` may call.
+fn internal_routes(cfg: &mut web::ServiceConfig) {
+    async fn whoami(req: HttpRequest) -> AppResult<HttpResponse> {
+        let identity = require_workload_role(&req, "users.read")?;
+        Ok(HttpResponse::Ok().json(identity))
+    }
+
+    cfg.service(web::resource("/internal/whoami").wrap(AuthMiddleware).route(web::get().to(whoami)));
+}
+
+#[actix_web::test]
+async fn service_account_tokens_reach_workload_routes() {
+    let app = spawn().await;
+
+    let response = app
+        .serve(authorized(TestRequest::get().uri("/internal/whoami"), &service_account("invoicer")), internal_routes)
+        .await;
+
+    let response = assert_status(response, StatusCode::OK);
+    assert_eq!(response.body["kind"], "kubernetes");
+    assert_eq!(response.body["subject"], "system:serviceaccount:billing:invoicer");
+    assert_eq!(response.body["roles"], json!(["users.read"]));
+}
+
+#[actix_web::test]
+async fn svids_reach_workload_routes() {
+    let app = spawn().await;
+
+    let response = app
+        .serve(
+            authorized(TestRequest::get().uri("/internal/whoami"), &svid("spiffe://example.org/ns/billing/sa/worker")),
+            internal_routes,
+        )
+        .await;
+
+    let response = assert_status(response, StatusCode::OK);
+    assert_eq!(response.body["kind"], "spiffe");
+}
+
+#[actix_web::test]
+async fn workloads_without_the_role_are_forbidden() {
+    let app = spawn().await;
+
+    let response = app
+        .serve(authorized(TestRequest::get().uri("/internal/whoami"), &service_account("reporter")), internal_routes)
+        .await;
+
+    let response = assert_status(response, StatusCode::FORBIDDEN);
+    assert_eq!(response.body["error_code"], "AUTH_WORKLOAD_ROLE_MISSING");
+}
+
+#[actix_web::test]
+async fn expired_workload_tokens_are_rejected() {
+    let app = spawn().await;
+    app.clock.advance(chrono::Duration::hours(1));
+
+    let response = app
+        .serve(authorized(TestRequest::get().uri("/internal/whoami"), &service_account("invoicer")), internal_routes)
+        .await;
+
+    let response = assert_status(response, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["error_code"], "AUTH_TOKEN_EXPIRED");
+}
+
+#[actix_web::test]
+async fn user_sessions_are_not_workloads() {
+    let app = spawn().await;
+    let token = app.token_for(Uuid::new_v4());
+
+    let response = app
+        .serve(authorized(TestRequest::get().uri("/internal/whoami"), &token), internal_routes)
+        .await;
+
+    assert_status(response, StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn workloads_are_not_users() {
+    let app = spawn().await;
+
+    let response = app
+        .request(authorized(TestRequest::get().uri("/api/v1/users"), &service_account("invoicer")))
+        .await;
+
+    assert_status(response, StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,9 @@
+-- When each configured schedule next enqueues its pipeline; claimed with a
+-- conditional update so only one replica enqueues a run. See
+-- src/scheduler.rs
+CREATE TABLE IF NOT EXISTS schedules (
+    name VARCHAR(100) PRIMARY KEY,
+    next_run_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    last_enqueued_at TIMESTAMP WITH TIME ZONE,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,10 @@
+-- How far each pipeline got, saved in the transaction of the batch it
+-- covers, so an interrupted or failed run resumes after the last committed
+-- batch. The row is locked while a batch runs, so runs of one pipeline
+-- never overlap. See src/checkpoint.rs
+CREATE TABLE IF NOT EXISTS checkpoints (
+    pipeline VARCHAR(100) PRIMARY KEY,
+    position JSONB,
+    rows_processed BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
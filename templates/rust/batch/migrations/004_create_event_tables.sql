@@ -0,0 +1,14 @@
+-- Source and destination of the example daily_event_counts pipeline; see
+-- src/pipelines/daily_event_counts.rs. Replace with your own.
+CREATE TABLE IF NOT EXISTS events (
+    id BIGSERIAL PRIMARY KEY,
+    kind VARCHAR(100) NOT NULL,
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS daily_event_counts (
+    day DATE NOT NULL,
+    kind VARCHAR(100) NOT NULL,
+    events BIGINT NOT NULL,
+    PRIMARY KEY (day, kind)
+);
@@ -0,0 +1,116 @@
+//! How far each pipeline got.
+//!
+//! A pipeline's checkpoint is a JSON position of its choosing, e.g. the
+//! last id it read, saved in the same transaction as the batch it covers:
+//! either both commit or neither does, so a run that fails or is
+//! interrupted resumes after the last committed batch without redoing or
+//! skipping rows. The row is locked for the length of each batch, so two
+//! runs of one pipeline never overlap; the second is refused with
+//! [`ErrorCode::Conflict`](crate::errors::ErrorCode) and retried later.
+
+use chrono::{DateTime, Utc};
+use platform_core::clock::SharedClock;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::{FromRow, PgConnection, PgPool};
+
+use crate::errors::{AppError, AppResult, ResultExt};
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Checkpoint {
+    pub pipeline: String,
+    /// `None` until the first batch commits.
+    pub position: Option<serde_json::Value>,
+    /// Rows processed over every run, until the checkpoint is reset.
+    pub rows_processed: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Checkpoint {
+    /// The position as the type the pipeline saved it as.
+    pub fn position<T: DeserializeOwned>(&self) -> AppResult<Option<T>> {
+        self.position
+            .clone()
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(|e| AppError::BadRequest(format!("Invalid {} checkpoint: {}", self.pipeline, e)))
+    }
+}
+
+pub struct Checkpoints {
+    db: PgPool,
+    clock: SharedClock,
+}
+
+impl Checkpoints {
+    pub fn new(db: PgPool, clock: SharedClock) -> Self {
+        Self { db, clock }
+    }
+
+    /// Locks the checkpoint of `pipeline` in the transaction on `conn`,
+    /// creating it empty the first time. Fails with a conflict while another
+    /// run holds it.
+    pub async fn lock(&self, conn: &mut PgConnection, pipeline: &str) -> AppResult<Checkpoint> {
+        sqlx::query("INSERT INTO checkpoints (pipeline, updated_at) VALUES ($1, $2) ON CONFLICT (pipeline) DO NOTHING")
+            .bind(pipeline)
+            .bind(self.clock.now())
+            .execute(&mut *conn)
+            .await
+            .entity_context("create checkpoint", pipeline)?;
+
+        sqlx::query_as::<_, Checkpoint>("SELECT * FROM checkpoints WHERE pipeline = $1 FOR UPDATE SKIP LOCKED")
+            .bind(pipeline)
+            .fetch_optional(&mut *conn)
+            .await
+            .entity_context("lock checkpoint", pipeline)?
+            .ok_or_else(|| AppError::Conflict(format!("Pipeline {} is already running", pipeline)))
+    }
+
+    /// Moves the checkpoint of `pipeline` to `position`, counting `rows`
+    /// more, in the transaction that processed them.
+    pub async fn save(
+        &self,
+        conn: &mut PgConnection,
+        pipeline: &str,
+        position: &impl Serialize,
+        rows: i64,
+    ) -> AppResult<()> {
+        let position = serde_json::to_value(position)
+            .map_err(|e| AppError::BadRequest(format!("Invalid {} checkpoint: {}", pipeline, e)))?;
+
+        sqlx::query(
+            r#"
+            UPDATE checkpoints SET position = $2, rows_processed = rows_processed + $3, updated_at = $4
+            WHERE pipeline = $1
+            "#,
+        )
+        .bind(pipeline)
+        .bind(position)
+        .bind(rows)
+        .bind(self.clock.now())
+        .execute(conn)
+        .await
+        .entity_context("save checkpoint", pipeline)?;
+        Ok(())
+    }
+
+    pub async fn get(&self, pipeline: &str) -> AppResult<Option<Checkpoint>> {
+        sqlx::query_as::<_, Checkpoint>("SELECT * FROM checkpoints WHERE pipeline = $1")
+            .bind(pipeline)
+            .fetch_optional(&self.db)
+            .await
+            .entity_context("load checkpoint", pipeline)
+    }
+
+    /// Forgets how far `pipeline` got, so its next run starts from the
+    /// beginning, e.g. to backfill after fixing a bug. Waits for a running
+    /// batch to commit.
+    pub async fn reset(&self, pipeline: &str) -> AppResult<()> {
+        sqlx::query("DELETE FROM checkpoints WHERE pipeline = $1")
+            .bind(pipeline)
+            .execute(&self.db)
+            .await
+            .entity_context("reset checkpoint", pipeline)?;
+        Ok(())
+    }
+}
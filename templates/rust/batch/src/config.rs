@@ -0,0 +1,137 @@
+use config::builder::{ConfigBuilder, DefaultState};
+use config::{Config, ConfigError};
+use platform_core::lifecycle::LifecycleSettings;
+use platform_observability::log_level::LogLevelSettings;
+use platform_observability::otel::OtelSettings;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Settings {
+    pub database: DatabaseSettings,
+    pub scheduler: SchedulerSettings,
+    pub jobs: JobSettings,
+    pub pipelines: PipelineSettings,
+    pub metrics: MetricsSettings,
+    pub lifecycle: LifecycleSettings,
+    pub log_level: LogLevelSettings,
+    /// Span export and trace context propagation.
+    pub otel: OtelSettings,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DatabaseSettings {
+    pub url: String,
+    pub max_connections: u32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SchedulerSettings {
+    /// Enqueue scheduled runs from this process. Turn it off on replicas
+    /// that should only work through the queue.
+    pub enabled: bool,
+    /// How often to look for schedules that are due.
+    pub poll_interval_secs: u64,
+    #[serde(default)]
+    pub schedules: BTreeMap<String, ScheduleSettings>,
+}
+
+/// A pipeline run every `interval_secs`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScheduleSettings {
+    /// Name the pipeline is registered under.
+    pub pipeline: String,
+    pub interval_secs: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct JobSettings {
+    /// Jobs a worker runs at once.
+    pub concurrency: usize,
+    pub poll_interval_ms: u64,
+    pub max_attempts: i32,
+    /// Delay before the first retry, doubled for each further one.
+    pub retry_base_secs: u64,
+    pub retry_max_secs: u64,
+    /// A running job whose worker hasn't finished it this long after
+    /// claiming it, e.g. because the process was killed, is claimed again.
+    /// Pipelines commit a checkpoint per batch, so batches must finish well
+    /// within it; whole runs needn't.
+    pub visibility_timeout_secs: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PipelineSettings {
+    /// Rows a pipeline reads per batch, and so per checkpoint.
+    pub batch_size: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MetricsSettings {
+    /// Where metrics are pushed; batch processes aren't around to be
+    /// scraped.
+    pub push: MetricsPush,
+    /// How often to push while running. Metrics are pushed once more on the
+    /// way out.
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+    /// Base URL of the Prometheus Pushgateway.
+    pub pushgateway_url: String,
+    /// The `job` grouping key on the Pushgateway.
+    pub job: String,
+    /// The `instance` grouping key, so replicas don't overwrite each
+    /// other's metrics. Empty uses `HOSTNAME`.
+    pub instance: String,
+    /// OTLP/gRPC endpoint of the collector.
+    pub otlp_endpoint: String,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsPush {
+    None,
+    Pushgateway,
+    Otlp,
+}
+
+impl Settings {
+    pub fn new() -> Result<Self, ConfigError> {
+        platform_core::config::load(Self::defaults()?, "BATCH")
+    }
+
+    /// Builder holding only the default values, e.g. for tests to add
+    /// overrides to.
+    pub fn defaults() -> Result<ConfigBuilder<DefaultState>, ConfigError> {
+        Config::builder()
+            .set_default("database.max_connections", 10)?
+            .set_default("scheduler.enabled", true)?
+            .set_default("scheduler.poll_interval_secs", 30)?
+            .set_default("scheduler.schedules.daily_event_counts.pipeline", "daily_event_counts")?
+            .set_default("scheduler.schedules.daily_event_counts.interval_secs", 3600)?
+            .set_default("jobs.concurrency", 2)?
+            .set_default("jobs.poll_interval_ms", 1000)?
+            .set_default("jobs.max_attempts", 5)?
+            .set_default("jobs.retry_base_secs", 30)?
+            .set_default("jobs.retry_max_secs", 3600)?
+            .set_default("jobs.visibility_timeout_secs", 900)?
+            .set_default("pipelines.batch_size", 1000)?
+            .set_default("metrics.push", "none")?
+            .set_default("metrics.interval_secs", 15)?
+            .set_default("metrics.timeout_secs", 10)?
+            .set_default("metrics.pushgateway_url", "http://localhost:9091")?
+            .set_default("metrics.job", "batch-template")?
+            .set_default("metrics.instance", "")?
+            .set_default("metrics.otlp_endpoint", "http://localhost:4317")?
+            .set_default("lifecycle.hook_timeout_secs", 10)?
+            .set_default("lifecycle.shutdown_timeout_secs", 60)?
+            .set_default("log_level.default", "info")?
+            .set_default("log_level.revert_after_secs", 900)?
+            .set_default("log_level.max_revert_after_secs", 86400)?
+            .set_default("otel.enabled", false)?
+            .set_default("otel.endpoint", "http://localhost:4317")?
+            .set_default("otel.service_name", "batch-template")?
+            .set_default("otel.sample_ratio", 1.0)?
+            .set_default("otel.filter", "info")?
+            .set_default("otel.timeout_secs", 10)
+    }
+}
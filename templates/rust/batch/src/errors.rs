@@ -0,0 +1,4 @@
+//! Errors come from the shared `platform-core` crate, so batch jobs report
+//! the same variants and stable codes as the services.
+
+pub use platform_core::errors::{AppError, AppResult, ErrorCode, ErrorContext, ResultExt};
@@ -0,0 +1,95 @@
+//! Graceful interruption.
+//!
+//! The first SIGTERM or Ctrl-C triggers the process's [`Interrupt`]: the
+//! scheduler and the worker stop taking on work, and running pipelines stop
+//! after the batch in hand, with its checkpoint committed, and put their
+//! job back in the queue for the next run to resume. A second signal exits
+//! at once; nothing is lost beyond the uncommitted batch, which is rolled
+//! back with the connection.
+
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::warn;
+
+/// Exit status after a second signal, as shells report SIGINT.
+pub const FORCED_EXIT_CODE: i32 = 130;
+
+#[derive(Clone)]
+pub struct Interrupt {
+    sender: Arc<watch::Sender<bool>>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl Interrupt {
+    pub fn new() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        Self {
+            sender: Arc::new(sender),
+            receiver,
+        }
+    }
+
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Completes once the interrupt is triggered.
+    pub async fn triggered(&self) {
+        let mut receiver = self.receiver.clone();
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+}
+
+impl Default for Interrupt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Triggers `interrupt` on the first Ctrl-C or SIGTERM and exits the
+/// process on the second.
+pub async fn on_signals(interrupt: Interrupt) {
+    signal().await;
+    warn!("Interrupted; finishing the running batches (signal again to exit now)");
+    interrupt.trigger();
+
+    signal().await;
+    warn!("Interrupted again; exiting without waiting");
+    std::process::exit(FORCED_EXIT_CODE);
+}
+
+/// Ctrl-C, or SIGTERM from the orchestrator.
+async fn signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("installing the SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn clones_share_the_interrupt() {
+        let interrupt = Interrupt::new();
+        let clone = interrupt.clone();
+        assert!(!clone.is_triggered());
+
+        let waiting = tokio::spawn(async move { clone.triggered().await });
+        interrupt.trigger();
+        waiting.await.unwrap();
+        assert!(interrupt.is_triggered());
+    }
+}
@@ -0,0 +1,129 @@
+//! Work queued in Postgres: pipeline runs enqueued by the
+//! [scheduler](crate::scheduler) or by hand, and anything else a batch
+//! service wants retried until it succeeds.
+//!
+//! Jobs are enqueued with a kind and a JSON payload, possibly in the
+//! transaction that makes them necessary, and run by a [`Worker`] with the
+//! [`JobHandler`] registered for their kind. Failed jobs are retried with
+//! exponential backoff until `jobs.max_attempts`, then left `dead` with
+//! their last error. A job still running `jobs.visibility_timeout_secs`
+//! after it was claimed is presumed lost with its process and claimed
+//! again.
+//!
+//! A handler that stops early because the process was
+//! [interrupted](crate::interrupt) returns [`Completion::Interrupted`]; its
+//! job goes back to `pending` without using up an attempt.
+//!
+//! Workers record `jobs_processed_total{kind, outcome}`, `jobs_running` and
+//! `job_duration_seconds{kind}` through the `metrics` facade.
+
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use platform_core::clock::SharedClock;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::{FromRow, PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::config::JobSettings;
+use crate::errors::{AppError, AppResult, ResultExt};
+
+pub mod worker;
+
+pub use worker::Worker;
+
+pub const JOB_PENDING: &str = "pending";
+pub const JOB_RUNNING: &str = "running";
+pub const JOB_COMPLETED: &str = "completed";
+/// Out of attempts, or of a kind no handler knows.
+pub const JOB_DEAD: &str = "dead";
+
+#[derive(Debug, Serialize, FromRow, Clone)]
+pub struct Job {
+    pub id: Uuid,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub status: String,
+    /// Attempts so far, including the running one.
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: DateTime<Utc>,
+    pub locked_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Job {
+    /// The payload as the type it was enqueued with.
+    pub fn payload<T: DeserializeOwned>(&self) -> AppResult<T> {
+        serde_json::from_value(self.payload.clone())
+            .map_err(|e| AppError::BadRequest(format!("Invalid {} job payload: {}", self.kind, e)))
+    }
+}
+
+/// How a handler left its job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Completion {
+    Done,
+    /// Stopped early for an interrupt; the job runs again, picking up where
+    /// it left off.
+    Interrupted,
+}
+
+pub trait JobHandler: Send + Sync {
+    /// Runs `job`; an error schedules a retry.
+    fn handle<'a>(&'a self, job: &'a Job) -> BoxFuture<'a, AppResult<Completion>>;
+}
+
+pub struct JobQueue {
+    db: PgPool,
+    settings: JobSettings,
+    clock: SharedClock,
+}
+
+impl JobQueue {
+    pub fn new(db: PgPool, settings: &JobSettings, clock: SharedClock) -> Self {
+        Self {
+            db,
+            settings: settings.clone(),
+            clock,
+        }
+    }
+
+    /// Enqueues a job to run as soon as a worker is free.
+    pub async fn enqueue(&self, kind: &str, payload: &impl Serialize) -> AppResult<Uuid> {
+        self.enqueue_with(&self.db, kind, payload).await
+    }
+
+    /// Enqueues a job through `executor`, e.g. a transaction, so it only
+    /// runs if the transaction commits.
+    pub async fn enqueue_with<'e>(
+        &self,
+        executor: impl PgExecutor<'e>,
+        kind: &str,
+        payload: &impl Serialize,
+    ) -> AppResult<Uuid> {
+        let payload = serde_json::to_value(payload)
+            .map_err(|e| AppError::BadRequest(format!("Invalid {} job payload: {}", kind, e)))?;
+
+        sqlx::query_scalar(
+            "INSERT INTO jobs (kind, payload, max_attempts, run_at) VALUES ($1, $2, $3, $4) RETURNING id",
+        )
+        .bind(kind)
+        .bind(payload)
+        .bind(self.settings.max_attempts)
+        .bind(self.clock.now())
+        .fetch_one(executor)
+        .await
+        .context("enqueue job")
+    }
+
+    pub async fn get_job(&self, job_id: Uuid) -> AppResult<Option<Job>> {
+        sqlx::query_as::<_, Job>("SELECT * FROM jobs WHERE id = $1")
+            .bind(job_id)
+            .fetch_optional(&self.db)
+            .await
+            .entity_context("load job", job_id)
+    }
+}
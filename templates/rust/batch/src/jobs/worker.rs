@@ -0,0 +1,264 @@
+use chrono::Duration;
+use platform_core::clock::SharedClock;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+
+use crate::config::JobSettings;
+use crate::errors::{AppResult, ResultExt};
+use crate::jobs::{Completion, Job, JobHandler, JOB_COMPLETED, JOB_DEAD, JOB_PENDING, JOB_RUNNING};
+
+/// The outcome of a job handed back for an interrupt; its status goes back
+/// to `pending`.
+const JOB_INTERRUPTED: &str = "interrupted";
+
+/// Claims due jobs and runs up to `jobs.concurrency` of them at a time.
+///
+/// Jobs are claimed with `FOR UPDATE SKIP LOCKED`, so any number of workers
+/// across replicas can share the table.
+pub struct Worker {
+    db: PgPool,
+    settings: JobSettings,
+    clock: SharedClock,
+    handlers: HashMap<String, Arc<dyn JobHandler>>,
+}
+
+impl Worker {
+    pub fn new(db: PgPool, settings: JobSettings, clock: SharedClock) -> Self {
+        Self {
+            db,
+            settings,
+            clock,
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Runs jobs of `kind` with `handler`.
+    pub fn handler(mut self, kind: &str, handler: impl JobHandler + 'static) -> Self {
+        self.handlers.insert(kind.to_string(), Arc::new(handler));
+        self
+    }
+
+    /// Processes jobs until `shutdown` completes, then waits for the running
+    /// ones to finish or hand their job back.
+    pub async fn run(self, shutdown: impl Future<Output = ()>) {
+        let worker = Arc::new(self);
+        let permits = Arc::new(Semaphore::new(worker.settings.concurrency));
+        let poll_interval = std::time::Duration::from_millis(worker.settings.poll_interval_ms);
+        tokio::pin!(shutdown);
+
+        loop {
+            let permit = tokio::select! {
+                permit = permits.clone().acquire_owned() => permit.expect("semaphore is never closed"),
+                _ = &mut shutdown => break,
+            };
+
+            match worker.claim().await {
+                Ok(Some(job)) => {
+                    let worker = worker.clone();
+                    tokio::spawn(async move {
+                        worker.process(job).await;
+                        drop(permit);
+                    });
+                }
+                result => {
+                    if let Err(e) = result {
+                        tracing::warn!("Failed to claim job: {}", e);
+                    }
+                    drop(permit);
+                    tokio::select! {
+                        _ = tokio::time::sleep(poll_interval) => {}
+                        _ = &mut shutdown => break,
+                    }
+                }
+            }
+        }
+
+        // Every permit back means every running job finished
+        let _ = permits.acquire_many(worker.settings.concurrency as u32).await;
+    }
+
+    /// Runs every due job, one after the other; for tests and one-off
+    /// commands. Returns how many ran. Stops at a job handed back for an
+    /// interrupt, which would only be handed back again.
+    pub async fn drain(&self) -> AppResult<usize> {
+        let mut processed = 0;
+        while let Some(job) = self.claim().await? {
+            let outcome = self.process(job).await;
+            processed += 1;
+            if outcome == JOB_INTERRUPTED {
+                break;
+            }
+        }
+        Ok(processed)
+    }
+
+    async fn claim(&self) -> AppResult<Option<Job>> {
+        let now = self.clock.now();
+
+        sqlx::query_as::<_, Job>(
+            r#"
+            UPDATE jobs SET status = $1, locked_at = $2, attempts = attempts + 1
+            WHERE id = (
+                SELECT id FROM jobs
+                WHERE (status = $3 AND run_at <= $2) OR (status = $1 AND locked_at <= $4)
+                ORDER BY run_at
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING *
+            "#,
+        )
+        .bind(JOB_RUNNING)
+        .bind(now)
+        .bind(JOB_PENDING)
+        .bind(now - Duration::seconds(self.settings.visibility_timeout_secs))
+        .fetch_optional(&self.db)
+        .await
+        .context("claim job")
+    }
+
+    async fn process(&self, job: Job) -> &'static str {
+        let running = metrics::gauge!("jobs_running");
+        running.increment(1.0);
+        let started = Instant::now();
+        let outcome = self.execute(&job).await;
+        running.decrement(1.0);
+        metrics::histogram!("job_duration_seconds", "kind" => job.kind.clone()).record(started.elapsed().as_secs_f64());
+        metrics::counter!("jobs_processed_total", "kind" => job.kind.clone(), "outcome" => outcome).increment(1);
+        outcome
+    }
+
+    /// Runs `job` and records its outcome: `completed`, `interrupted`,
+    /// `retried` or `dead`.
+    async fn execute(&self, job: &Job) -> &'static str {
+        // Claimed again after every attempt was lost with its process
+        if job.attempts > job.max_attempts {
+            tracing::error!(job_id = %job.id, kind = %job.kind, "Job lost with its process too often");
+            self.finish(
+                job,
+                JOB_DEAD,
+                Some("lost with its process after every attempt".to_string()),
+            )
+            .await;
+            return JOB_DEAD;
+        }
+
+        let result = match self.handlers.get(&job.kind) {
+            Some(handler) => handler.handle(job).await,
+            None => {
+                tracing::error!(job_id = %job.id, kind = %job.kind, "No handler for job kind");
+                self.finish(job, JOB_DEAD, Some(format!("no handler for job kind {}", job.kind)))
+                    .await;
+                return JOB_DEAD;
+            }
+        };
+
+        match result {
+            Ok(Completion::Done) => {
+                self.finish(job, JOB_COMPLETED, None).await;
+                JOB_COMPLETED
+            }
+            Ok(Completion::Interrupted) => {
+                tracing::info!(job_id = %job.id, kind = %job.kind, "Job interrupted, handing it back");
+                self.release(job).await;
+                JOB_INTERRUPTED
+            }
+            Err(e) if job.attempts >= job.max_attempts => {
+                tracing::error!(job_id = %job.id, kind = %job.kind, attempts = job.attempts, "Job failed for good: {}", e);
+                self.finish(job, JOB_DEAD, Some(e.to_string())).await;
+                JOB_DEAD
+            }
+            Err(e) => {
+                tracing::warn!(job_id = %job.id, kind = %job.kind, attempts = job.attempts, "Job failed, retrying: {}", e);
+                self.retry(job, e.to_string()).await;
+                "retried"
+            }
+        }
+    }
+
+    async fn finish(&self, job: &Job, status: &str, error: Option<String>) {
+        let result = sqlx::query(
+            r#"
+            UPDATE jobs SET status = $2, locked_at = NULL, finished_at = $3, last_error = COALESCE($4, last_error)
+            WHERE id = $1 AND locked_at = $5
+            "#,
+        )
+        .bind(job.id)
+        .bind(status)
+        .bind(self.clock.now())
+        .bind(error)
+        .bind(job.locked_at)
+        .execute(&self.db)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => reclaimed(job),
+            Ok(_) => {}
+            Err(e) => tracing::error!(job_id = %job.id, "Failed to record job outcome: {}", e),
+        }
+    }
+
+    async fn retry(&self, job: &Job, error: String) {
+        let run_at = self.clock.now() + self.backoff(job.attempts);
+        let result = sqlx::query(
+            "UPDATE jobs SET status = $2, locked_at = NULL, run_at = $3, last_error = $4 WHERE id = $1 AND locked_at = $5",
+        )
+        .bind(job.id)
+        .bind(JOB_PENDING)
+        .bind(run_at)
+        .bind(error)
+        .bind(job.locked_at)
+        .execute(&self.db)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => reclaimed(job),
+            Ok(_) => {}
+            Err(e) => tracing::error!(job_id = %job.id, "Failed to reschedule job: {}", e),
+        }
+    }
+
+    /// Puts an interrupted job back, due right away and with the attempt
+    /// given back.
+    async fn release(&self, job: &Job) {
+        let result = sqlx::query(
+            r#"
+            UPDATE jobs SET status = $2, locked_at = NULL, run_at = $3, attempts = attempts - 1
+            WHERE id = $1 AND locked_at = $4
+            "#,
+        )
+        .bind(job.id)
+        .bind(JOB_PENDING)
+        .bind(self.clock.now())
+        .bind(job.locked_at)
+        .execute(&self.db)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => reclaimed(job),
+            Ok(_) => {}
+            Err(e) => tracing::error!(job_id = %job.id, "Failed to hand back interrupted job: {}", e),
+        }
+    }
+
+    /// `retry_base_secs` doubled per attempt, capped at `retry_max_secs`.
+    fn backoff(&self, attempts: i32) -> Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 30) as u32;
+        let secs = self
+            .settings
+            .retry_base_secs
+            .saturating_mul(1 << exponent)
+            .min(self.settings.retry_max_secs);
+        Duration::seconds(secs as i64)
+    }
+}
+
+/// The job ran past `jobs.visibility_timeout_secs` and was claimed again;
+/// the outcome of the later run counts.
+fn reclaimed(job: &Job) {
+    tracing::warn!(job_id = %job.id, kind = %job.kind, "Job outlived its visibility timeout and was claimed again");
+}
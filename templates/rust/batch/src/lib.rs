@@ -0,0 +1,110 @@
+//! Template for scheduled, non-HTTP workloads: pipelines run on a schedule
+//! through a Postgres job queue, resume from checkpoints, push their
+//! metrics and stop cleanly when interrupted.
+
+use platform_core::clock::SharedClock;
+use platform_core::lifecycle::{Hook, Lifecycle};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+pub mod checkpoint;
+pub mod config;
+pub mod errors;
+pub mod interrupt;
+pub mod jobs;
+pub mod metrics_push;
+pub mod pipelines;
+pub mod scheduler;
+
+use crate::config::Settings;
+use crate::interrupt::Interrupt;
+use crate::jobs::{JobQueue, Worker};
+use crate::pipelines::{daily_event_counts, DailyEventCounts, Pipelines};
+use crate::scheduler::Scheduler;
+
+pub struct AppState {
+    pub db: PgPool,
+    pub settings: Settings,
+    pub clock: SharedClock,
+    pub interrupt: Interrupt,
+    pub job_queue: Arc<JobQueue>,
+    pub pipelines: Arc<Pipelines>,
+    pub scheduler: Arc<Scheduler>,
+}
+
+impl AppState {
+    /// Fails if a schedule is invalid.
+    pub fn new(db: PgPool, settings: Settings, clock: SharedClock, interrupt: Interrupt) -> anyhow::Result<Self> {
+        let job_queue = Arc::new(JobQueue::new(db.clone(), &settings.jobs, clock.clone()));
+        // Register your pipelines here
+        let pipelines = Pipelines::new(
+            db.clone(),
+            settings.pipelines.batch_size,
+            interrupt.clone(),
+            clock.clone(),
+        )
+        .register(daily_event_counts::NAME, DailyEventCounts);
+        let scheduler = Scheduler::new(
+            db.clone(),
+            settings.scheduler.clone(),
+            job_queue.clone(),
+            clock.clone(),
+            pipelines.names(),
+        )?;
+
+        Ok(Self {
+            db,
+            settings,
+            clock,
+            interrupt,
+            job_queue,
+            pipelines: Arc::new(pipelines),
+            scheduler: Arc::new(scheduler),
+        })
+    }
+}
+
+/// A worker with a handler for every job kind.
+pub fn worker(state: &AppState) -> Worker {
+    let worker = Worker::new(state.db.clone(), state.settings.jobs.clone(), state.clock.clone());
+    pipelines::register_jobs(worker, &state.pipelines)
+}
+
+/// Runs the scheduler, unless `scheduler.enabled` is off, and the worker
+/// until the process is interrupted or shuts down. Shutdown waits, up to
+/// `lifecycle.shutdown_timeout_secs`, for running pipelines to commit
+/// their batch and hand their job back.
+pub fn register_hooks(lifecycle: &mut Lifecycle, state: &Arc<AppState>) {
+    let (started, tasks) = oneshot::channel::<Vec<JoinHandle<()>>>();
+
+    let running = state.clone();
+    lifecycle.on_start(Hook::new("worker", move || async move {
+        let mut tasks = Vec::new();
+        if running.settings.scheduler.enabled {
+            let scheduler = running.scheduler.clone();
+            let interrupt = running.interrupt.clone();
+            tasks.push(tokio::spawn(async move {
+                scheduler.schedule_until(interrupt.triggered()).await
+            }));
+        }
+        let worker = worker(&running);
+        let interrupt = running.interrupt.clone();
+        tasks.push(tokio::spawn(async move { worker.run(interrupt.triggered()).await }));
+        let _ = started.send(tasks);
+        Ok(())
+    }));
+
+    let interrupt = state.interrupt.clone();
+    let stopping = Hook::new("worker", move || async move {
+        interrupt.trigger();
+        for task in tasks.await.unwrap_or_default() {
+            task.await?;
+        }
+        Ok(())
+    });
+    let timeout = Duration::from_secs(state.settings.lifecycle.shutdown_timeout_secs);
+    lifecycle.on_shutdown(stopping.timeout(timeout));
+}
@@ -0,0 +1,104 @@
+//! ```text
+//! batch                   # schedule and run pipelines until interrupted
+//! batch run <pipeline>    # run one pipeline from its checkpoint, then exit
+//! batch reset <pipeline>  # make the pipeline's next run start over
+//! ```
+
+use anyhow::{bail, Result};
+use dotenv::dotenv;
+use platform_core::clock::SystemClock;
+use platform_core::lifecycle::{Hook, Lifecycle};
+use platform_observability::{log_level, otel};
+use sqlx::postgres::PgPoolOptions;
+use std::sync::Arc;
+use tracing::info;
+
+use batch_template::config::Settings;
+use batch_template::interrupt::{self, Interrupt};
+use batch_template::{metrics_push, AppState};
+
+enum Command {
+    Serve,
+    Run(String),
+    Reset(String),
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Load environment variables
+    dotenv().ok();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match args.as_slice() {
+        [] => Command::Serve,
+        [command, pipeline] if command == "run" => Command::Run(pipeline.clone()),
+        [command, pipeline] if command == "reset" => Command::Reset(pipeline.clone()),
+        _ => bail!("usage: batch [run <pipeline> | reset <pipeline>]"),
+    };
+
+    // Load configuration, then tracing with the configured filter
+    let settings = Settings::new()?;
+    log_level::init(&settings.log_level, &settings.otel)?;
+    let pusher = Arc::new(metrics_push::install(&settings.metrics)?);
+
+    // Create database pool and run migrations
+    let db_pool = PgPoolOptions::new()
+        .max_connections(settings.database.max_connections)
+        .connect(&settings.database.url)
+        .await?;
+    sqlx::migrate!("./migrations").run(&db_pool).await?;
+
+    // The first signal stops work after the running batches, the second
+    // exits at once
+    let interrupt = Interrupt::new();
+    tokio::spawn(interrupt::on_signals(interrupt.clone()));
+
+    let state = Arc::new(AppState::new(
+        db_pool,
+        settings.clone(),
+        Arc::new(SystemClock),
+        interrupt.clone(),
+    )?);
+
+    let mut lifecycle = Lifecycle::new(&settings.lifecycle);
+    if let Command::Serve = command {
+        batch_template::register_hooks(&mut lifecycle, &state);
+    }
+    metrics_push::register_hooks(&mut lifecycle, pusher);
+    let db = state.db.clone();
+    let close_database = Hook::new("database", move || async move {
+        db.close().await;
+        Ok(())
+    });
+    lifecycle.on_shutdown(close_database.order(100));
+    lifecycle.start().await?;
+    lifecycle.ready().await;
+
+    let result = match command {
+        Command::Serve => {
+            info!(
+                "Running pipelines: {}",
+                state.pipelines.names().collect::<Vec<_>>().join(", ")
+            );
+            interrupt.triggered().await;
+            Ok(())
+        }
+        Command::Run(pipeline) => match state.pipelines.run(&pipeline).await {
+            Ok(run) if run.interrupted => Err(anyhow::anyhow!(
+                "{} interrupted after {} rows; run it again to resume",
+                pipeline,
+                run.rows
+            )),
+            Ok(run) => {
+                println!("{}: {} rows in {} batches", pipeline, run.rows, run.batches);
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        },
+        Command::Reset(pipeline) => state.pipelines.checkpoints().reset(&pipeline).await.map_err(Into::into),
+    };
+
+    lifecycle.shutdown().await;
+    tokio::task::spawn_blocking(otel::shutdown).await?;
+    result
+}
@@ -0,0 +1,341 @@
+//! Pushes metrics, since a batch process may be gone before anything
+//! scrapes it.
+//!
+//! Code records metrics through the `metrics` facade, as the services do;
+//! [`install`] sets the global recorder for `metrics.push`:
+//!
+//! - `pushgateway`: a Prometheus recorder whose rendered metrics are `PUT`
+//!   to `{pushgateway_url}/metrics/job/{job}/instance/{instance}` every
+//!   `metrics.interval_secs`, replacing the previous push of this instance.
+//! - `otlp`: a bridge to an OpenTelemetry meter provider exporting to
+//!   `metrics.otlp_endpoint` every `metrics.interval_secs`.
+//! - `none`: metrics are dropped.
+//!
+//! Either way, metrics are pushed once more on shutdown, so a run that
+//! finishes between two pushes still reports.
+
+use anyhow::Context as _;
+use metrics::{
+    Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use opentelemetry::metrics::{Meter, MeterProvider as _};
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use platform_core::lifecycle::{Hook, Lifecycle};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+use crate::config::{MetricsPush, MetricsSettings};
+
+pub struct MetricsPusher {
+    target: Target,
+    interval: Duration,
+}
+
+enum Target {
+    None,
+    Pushgateway {
+        client: reqwest::Client,
+        url: String,
+        handle: PrometheusHandle,
+    },
+    Otlp(SdkMeterProvider),
+}
+
+/// Installs the global recorder for `metrics.push` and returns what pushes
+/// its metrics. Call once, early: metrics recorded before are dropped.
+pub fn install(settings: &MetricsSettings) -> anyhow::Result<MetricsPusher> {
+    match settings.push {
+        MetricsPush::None => Ok(MetricsPusher {
+            target: Target::None,
+            interval: Duration::from_secs(settings.interval_secs),
+        }),
+        MetricsPush::Pushgateway => {
+            let recorder = PrometheusBuilder::new().build_recorder();
+            let handle = recorder.handle();
+            metrics::set_global_recorder(recorder).context("metrics recorder already installed")?;
+            MetricsPusher::pushgateway(settings, handle)
+        }
+        MetricsPush::Otlp => {
+            let provider = opentelemetry_otlp::new_pipeline()
+                .metrics(runtime::Tokio)
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(&settings.otlp_endpoint)
+                        .with_timeout(Duration::from_secs(settings.timeout_secs)),
+                )
+                .with_period(Duration::from_secs(settings.interval_secs))
+                .with_timeout(Duration::from_secs(settings.timeout_secs))
+                .with_resource(Resource::new([KeyValue::new("service.name", settings.job.clone())]))
+                .build()?;
+            let recorder = OtlpRecorder::new(provider.meter("batch"));
+            metrics::set_global_recorder(recorder).context("metrics recorder already installed")?;
+            Ok(MetricsPusher {
+                target: Target::Otlp(provider),
+                interval: Duration::from_secs(settings.interval_secs),
+            })
+        }
+    }
+}
+
+impl MetricsPusher {
+    /// Pushes what `handle` renders to the Pushgateway in `settings`; for
+    /// tests, which record into a local recorder.
+    pub fn pushgateway(settings: &MetricsSettings, handle: PrometheusHandle) -> anyhow::Result<Self> {
+        let instance = match settings.instance.as_str() {
+            "" => std::env::var("HOSTNAME").unwrap_or_else(|_| "unknown".to_string()),
+            instance => instance.to_string(),
+        };
+        let url = format!(
+            "{}/metrics/job/{}/instance/{}",
+            settings.pushgateway_url.trim_end_matches('/'),
+            settings.job,
+            instance
+        );
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .build()?;
+
+        Ok(Self {
+            target: Target::Pushgateway { client, url, handle },
+            interval: Duration::from_secs(settings.interval_secs),
+        })
+    }
+
+    /// Pushes the current metrics now.
+    pub async fn push(&self) -> anyhow::Result<()> {
+        match &self.target {
+            Target::None => Ok(()),
+            Target::Pushgateway { client, url, handle } => {
+                client
+                    .put(url)
+                    .header(reqwest::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+                    .body(handle.render())
+                    .send()
+                    .await?
+                    .error_for_status()
+                    .with_context(|| format!("pushing metrics to {}", url))?;
+                Ok(())
+            }
+            Target::Otlp(provider) => {
+                // Blocks until the export completes
+                let provider = provider.clone();
+                tokio::task::spawn_blocking(move || provider.force_flush()).await??;
+                Ok(())
+            }
+        }
+    }
+
+    /// Pushes every `metrics.interval_secs` until `shutdown` completes. The
+    /// OTLP provider exports on its own schedule, so this only waits.
+    pub async fn push_until(&self, shutdown: impl Future<Output = ()>) {
+        if !matches!(self.target, Target::Pushgateway { .. }) {
+            return shutdown.await;
+        }
+
+        let mut interval = tokio::time::interval(self.interval);
+        interval.tick().await;
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.push().await {
+                        tracing::warn!("Failed to push metrics: {:#}", e);
+                    }
+                }
+                _ = &mut shutdown => break,
+            }
+        }
+    }
+
+    /// Pushes one last time and stops the OTLP provider.
+    pub async fn finish(&self) -> anyhow::Result<()> {
+        self.push().await?;
+        if let Target::Otlp(provider) = &self.target {
+            let provider = provider.clone();
+            tokio::task::spawn_blocking(move || provider.shutdown()).await??;
+        }
+        Ok(())
+    }
+}
+
+/// Pushes from startup until shutdown, then once more after the hooks
+/// ordered before it, e.g. the worker's, have finished.
+pub fn register_hooks(lifecycle: &mut Lifecycle, pusher: Arc<MetricsPusher>) {
+    let (stop, stopped) = oneshot::channel::<()>();
+    let (started, pushing) = oneshot::channel();
+    let running = pusher.clone();
+    lifecycle.on_start(Hook::new("metrics_push", move || async move {
+        let stopped = async move {
+            let _ = stopped.await;
+        };
+        let _ = started.send(tokio::spawn(async move { running.push_until(stopped).await }));
+        Ok(())
+    }));
+    let finishing = Hook::new("metrics_push", move || async move {
+        let _ = stop.send(());
+        if let Ok(pushing) = pushing.await {
+            pushing.await?;
+        }
+        pusher.finish().await
+    });
+    lifecycle.on_shutdown(finishing.order(50));
+}
+
+/// Records `metrics` counters, gauges and histograms as OpenTelemetry
+/// instruments of the same name, with labels as attributes.
+struct OtlpRecorder {
+    meter: Meter,
+    counters: Mutex<HashMap<Key, Arc<OtlpCounter>>>,
+    gauges: Mutex<HashMap<Key, Arc<OtlpGauge>>>,
+    histograms: Mutex<HashMap<Key, Arc<OtlpHistogram>>>,
+}
+
+impl OtlpRecorder {
+    fn new(meter: Meter) -> Self {
+        Self {
+            meter,
+            counters: Mutex::default(),
+            gauges: Mutex::default(),
+            histograms: Mutex::default(),
+        }
+    }
+}
+
+fn attributes(key: &Key) -> Vec<KeyValue> {
+    key.labels()
+        .map(|label| KeyValue::new(label.key().to_string(), label.value().to_string()))
+        .collect()
+}
+
+impl Recorder for OtlpRecorder {
+    // Descriptions would have to be known before the instrument is created
+    fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+    fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+    fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+        let counter = self
+            .counters
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| {
+                Arc::new(OtlpCounter {
+                    counter: self.meter.u64_counter(key.name().to_string()).init(),
+                    attributes: attributes(key),
+                    total: AtomicU64::new(0),
+                })
+            })
+            .clone();
+        Counter::from_arc(counter)
+    }
+
+    fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+        let gauge = self
+            .gauges
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| {
+                let value = Arc::new(AtomicU64::new(0f64.to_bits()));
+                let observed = value.clone();
+                let attributes = attributes(key);
+                // Observed when the provider exports; kept alive by the meter
+                let _ = self
+                    .meter
+                    .f64_observable_gauge(key.name().to_string())
+                    .with_callback(move |observer| {
+                        observer.observe(f64::from_bits(observed.load(Ordering::Relaxed)), &attributes)
+                    })
+                    .init();
+                Arc::new(OtlpGauge { value })
+            })
+            .clone();
+        Gauge::from_arc(gauge)
+    }
+
+    fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+        let histogram = self
+            .histograms
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| {
+                Arc::new(OtlpHistogram {
+                    histogram: self.meter.f64_histogram(key.name().to_string()).init(),
+                    attributes: attributes(key),
+                })
+            })
+            .clone();
+        Histogram::from_arc(histogram)
+    }
+}
+
+struct OtlpCounter {
+    counter: opentelemetry::metrics::Counter<u64>,
+    attributes: Vec<KeyValue>,
+    /// For `absolute`, which OpenTelemetry counters can only take as a
+    /// delta.
+    total: AtomicU64,
+}
+
+impl CounterFn for OtlpCounter {
+    fn increment(&self, value: u64) {
+        self.total.fetch_add(value, Ordering::Relaxed);
+        self.counter.add(value, &self.attributes);
+    }
+
+    fn absolute(&self, value: u64) {
+        let previous = self.total.fetch_max(value, Ordering::Relaxed);
+        if value > previous {
+            self.counter.add(value - previous, &self.attributes);
+        }
+    }
+}
+
+struct OtlpGauge {
+    /// An `f64`, as bits.
+    value: Arc<AtomicU64>,
+}
+
+impl OtlpGauge {
+    fn update(&self, f: impl Fn(f64) -> f64) {
+        let _ = self.value.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some(f(f64::from_bits(bits)).to_bits())
+        });
+    }
+}
+
+impl GaugeFn for OtlpGauge {
+    fn increment(&self, value: f64) {
+        self.update(|current| current + value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.update(|current| current - value);
+    }
+
+    fn set(&self, value: f64) {
+        self.value.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+struct OtlpHistogram {
+    histogram: opentelemetry::metrics::Histogram<f64>,
+    attributes: Vec<KeyValue>,
+}
+
+impl HistogramFn for OtlpHistogram {
+    fn record(&self, value: f64) {
+        self.histogram.record(value, &self.attributes);
+    }
+}
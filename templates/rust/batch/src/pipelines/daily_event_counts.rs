@@ -0,0 +1,60 @@
+//! Example pipeline: counts `events` per UTC day and kind into
+//! `daily_event_counts`.
+//!
+//! The checkpoint is the id of the last event counted. That relies on
+//! `events` being append-only, with ids committed in order: an event
+//! inserted by a transaction that commits after a later id was counted is
+//! never picked up. Sources written by concurrent transactions should read
+//! only up to a lag, e.g. `occurred_at < now() - interval '5 minutes'`.
+
+use futures_util::future::BoxFuture;
+use sqlx::PgConnection;
+
+use crate::checkpoint::Checkpoint;
+use crate::errors::{AppResult, ResultExt};
+use crate::pipelines::{Pipeline, Step};
+
+pub const NAME: &str = "daily_event_counts";
+
+pub struct DailyEventCounts;
+
+impl Pipeline for DailyEventCounts {
+    fn step<'a>(
+        &'a self,
+        conn: &'a mut PgConnection,
+        checkpoint: &'a Checkpoint,
+        batch_size: i64,
+    ) -> BoxFuture<'a, AppResult<Option<Step>>> {
+        Box::pin(async move {
+            let after: i64 = checkpoint.position()?.unwrap_or(0);
+
+            // The insert runs whether or not the outer query reads it
+            let (last_id, rows): (Option<i64>, i64) = sqlx::query_as(
+                r#"
+                WITH batch AS (
+                    SELECT id, kind, (occurred_at AT TIME ZONE 'UTC')::date AS day
+                    FROM events
+                    WHERE id > $1
+                    ORDER BY id
+                    LIMIT $2
+                ), counted AS (
+                    INSERT INTO daily_event_counts (day, kind, events)
+                    SELECT day, kind, COUNT(*) FROM batch GROUP BY day, kind
+                    ON CONFLICT (day, kind) DO UPDATE SET events = daily_event_counts.events + EXCLUDED.events
+                )
+                SELECT MAX(id), COUNT(*) FROM batch
+                "#,
+            )
+            .bind(after)
+            .bind(batch_size)
+            .fetch_one(conn)
+            .await
+            .context("count events")?;
+
+            Ok(last_id.map(|last_id| Step {
+                position: last_id.into(),
+                rows: rows as u64,
+            }))
+        })
+    }
+}
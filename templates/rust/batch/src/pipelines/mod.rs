@@ -0,0 +1,189 @@
+//! Pipelines: resumable jobs that work through their input a batch at a
+//! time.
+//!
+//! A [`Pipeline`] only knows how to process the batch after a
+//! [`Checkpoint`]; [`Pipelines::run`] calls it until it reports nothing
+//! left, committing each batch together with its checkpoint. Between
+//! batches a run checks for an [interrupt](crate::interrupt) and stops
+//! cleanly if there is one.
+//!
+//! Pipelines run as [`RUN_JOB`] jobs, enqueued by the
+//! [scheduler](crate::scheduler) or by `batch run <pipeline>`. Runs record
+//! `pipeline_runs_total{pipeline, outcome}`,
+//! `pipeline_batch_duration_seconds{pipeline}`,
+//! `pipeline_rows_processed_total{pipeline}` and
+//! `pipeline_last_success_timestamp_seconds{pipeline}`, the last one for
+//! alerting on pipelines that stopped succeeding.
+
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use platform_core::clock::SharedClock;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{PgConnection, PgPool};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::checkpoint::{Checkpoint, Checkpoints};
+use crate::errors::{AppError, AppResult, ResultExt};
+use crate::interrupt::Interrupt;
+use crate::jobs::{Completion, Job, JobHandler, Worker};
+
+pub mod daily_event_counts;
+
+pub use daily_event_counts::DailyEventCounts;
+
+pub const RUN_JOB: &str = "pipelines.run";
+
+/// One batch's worth of progress.
+#[derive(Debug, Clone)]
+pub struct Step {
+    /// The checkpoint position after the batch.
+    pub position: Value,
+    pub rows: u64,
+}
+
+pub trait Pipeline: Send + Sync {
+    /// Processes up to `batch_size` rows after `checkpoint` through `conn`,
+    /// which is in the transaction the checkpoint is saved in. `None` once
+    /// there is nothing left.
+    fn step<'a>(
+        &'a self,
+        conn: &'a mut PgConnection,
+        checkpoint: &'a Checkpoint,
+        batch_size: i64,
+    ) -> BoxFuture<'a, AppResult<Option<Step>>>;
+}
+
+/// How a run went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Run {
+    pub batches: u64,
+    pub rows: u64,
+    /// Stopped for an interrupt rather than for running out of input.
+    pub interrupted: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunPayload {
+    pub pipeline: String,
+    /// When the scheduler enqueued the run; `None` for runs started by hand.
+    pub scheduled_at: Option<DateTime<Utc>>,
+}
+
+pub struct Pipelines {
+    db: PgPool,
+    checkpoints: Checkpoints,
+    pipelines: BTreeMap<String, Arc<dyn Pipeline>>,
+    batch_size: i64,
+    interrupt: Interrupt,
+    clock: SharedClock,
+}
+
+impl Pipelines {
+    pub fn new(db: PgPool, batch_size: i64, interrupt: Interrupt, clock: SharedClock) -> Self {
+        Self {
+            checkpoints: Checkpoints::new(db.clone(), clock.clone()),
+            db,
+            pipelines: BTreeMap::new(),
+            batch_size,
+            interrupt,
+            clock,
+        }
+    }
+
+    /// Makes `pipeline` available to schedules and `batch run` as `name`.
+    pub fn register(mut self, name: &str, pipeline: impl Pipeline + 'static) -> Self {
+        self.pipelines.insert(name.to_string(), Arc::new(pipeline));
+        self
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.pipelines.keys().map(String::as_str)
+    }
+
+    pub fn checkpoints(&self) -> &Checkpoints {
+        &self.checkpoints
+    }
+
+    /// Runs `name` from its checkpoint until its input runs out or the
+    /// process is interrupted.
+    pub async fn run(&self, name: &str) -> AppResult<Run> {
+        let pipeline = self
+            .pipelines
+            .get(name)
+            .ok_or_else(|| AppError::NotFound(format!("Pipeline {} is not registered", name)))?;
+
+        let result = self.run_batches(name, pipeline.as_ref()).await;
+        let outcome = match &result {
+            Ok(run) if run.interrupted => "interrupted",
+            Ok(_) => "completed",
+            Err(_) => "failed",
+        };
+        metrics::counter!("pipeline_runs_total", "pipeline" => name.to_string(), "outcome" => outcome).increment(1);
+
+        if let Ok(run) = &result {
+            if !run.interrupted {
+                metrics::gauge!("pipeline_last_success_timestamp_seconds", "pipeline" => name.to_string())
+                    .set(self.clock.now().timestamp() as f64);
+            }
+            tracing::info!(
+                pipeline = name,
+                batches = run.batches,
+                rows = run.rows,
+                interrupted = run.interrupted,
+                "Pipeline run finished"
+            );
+        }
+        result
+    }
+
+    async fn run_batches(&self, name: &str, pipeline: &dyn Pipeline) -> AppResult<Run> {
+        let mut run = Run::default();
+
+        loop {
+            if self.interrupt.is_triggered() {
+                run.interrupted = true;
+                return Ok(run);
+            }
+
+            let started = Instant::now();
+            let mut tx = self.db.begin().await.context("begin transaction")?;
+            let checkpoint = self.checkpoints.lock(&mut tx, name).await?;
+            let Some(step) = pipeline.step(&mut tx, &checkpoint, self.batch_size).await? else {
+                return Ok(run);
+            };
+            self.checkpoints
+                .save(&mut tx, name, &step.position, step.rows as i64)
+                .await?;
+            tx.commit().await.context("commit transaction")?;
+
+            metrics::histogram!("pipeline_batch_duration_seconds", "pipeline" => name.to_string())
+                .record(started.elapsed().as_secs_f64());
+            metrics::counter!("pipeline_rows_processed_total", "pipeline" => name.to_string()).increment(step.rows);
+            run.batches += 1;
+            run.rows += step.rows;
+        }
+    }
+}
+
+struct RunPipeline(Arc<Pipelines>);
+
+impl JobHandler for RunPipeline {
+    fn handle<'a>(&'a self, job: &'a Job) -> BoxFuture<'a, AppResult<Completion>> {
+        Box::pin(async move {
+            let payload: RunPayload = job.payload()?;
+            let run = self.0.run(&payload.pipeline).await?;
+            Ok(if run.interrupted {
+                Completion::Interrupted
+            } else {
+                Completion::Done
+            })
+        })
+    }
+}
+
+pub fn register_jobs(worker: Worker, pipelines: &Arc<Pipelines>) -> Worker {
+    worker.handler(RUN_JOB, RunPipeline(pipelines.clone()))
+}
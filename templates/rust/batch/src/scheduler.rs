@@ -0,0 +1,153 @@
+//! Runs pipelines on a schedule.
+//!
+//! Each entry under `scheduler.schedules` names a pipeline and how often it
+//! runs. Every `scheduler.poll_interval_secs` the scheduler claims the
+//! schedules that are due with a conditional update and enqueues a
+//! [`RUN_JOB`] for each in the same transaction, so however many replicas
+//! schedule, a run is enqueued once per `interval_secs`. A run that takes
+//! longer than its interval doesn't pile up: the next one fails to lock the
+//! checkpoint and is retried by the queue.
+//!
+//! ```toml
+//! [scheduler.schedules.daily_event_counts]
+//! pipeline = "daily_event_counts"
+//! interval_secs = 3600
+//! ```
+
+use chrono::Duration;
+use config::ConfigError;
+use platform_core::clock::SharedClock;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::config::SchedulerSettings;
+use crate::errors::{AppResult, ResultExt};
+use crate::jobs::JobQueue;
+use crate::pipelines::{RunPayload, RUN_JOB};
+
+struct Schedule {
+    pipeline: String,
+    interval: Duration,
+}
+
+pub struct Scheduler {
+    db: PgPool,
+    settings: SchedulerSettings,
+    schedules: BTreeMap<String, Schedule>,
+    job_queue: Arc<JobQueue>,
+    clock: SharedClock,
+}
+
+impl Scheduler {
+    /// Fails if a schedule has no positive interval or names a pipeline that
+    /// isn't among `pipelines`.
+    pub fn new<'a>(
+        db: PgPool,
+        settings: SchedulerSettings,
+        job_queue: Arc<JobQueue>,
+        clock: SharedClock,
+        pipelines: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, ConfigError> {
+        let pipelines: Vec<&str> = pipelines.into_iter().collect();
+        let schedules = settings
+            .schedules
+            .iter()
+            .map(|(name, schedule)| {
+                if schedule.interval_secs <= 0 {
+                    return Err(ConfigError::Message(format!(
+                        "schedule {} needs a positive interval_secs",
+                        name
+                    )));
+                }
+                if !pipelines.contains(&schedule.pipeline.as_str()) {
+                    return Err(ConfigError::Message(format!(
+                        "schedule {} runs pipeline {}, which is not registered",
+                        name, schedule.pipeline
+                    )));
+                }
+                let schedule = Schedule {
+                    pipeline: schedule.pipeline.clone(),
+                    interval: Duration::seconds(schedule.interval_secs),
+                };
+                Ok((name.clone(), schedule))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            db,
+            settings,
+            schedules,
+            job_queue,
+            clock,
+        })
+    }
+
+    /// Enqueues a run for every schedule that is due and returns their
+    /// names. Schedules are due when first configured and `interval_secs`
+    /// after each time they were enqueued.
+    pub async fn schedule_due(&self) -> AppResult<Vec<String>> {
+        let now = self.clock.now();
+        let mut scheduled = Vec::new();
+
+        for (name, schedule) in &self.schedules {
+            let mut tx = self.db.begin().await.context("begin transaction")?;
+            sqlx::query("INSERT INTO schedules (name, next_run_at) VALUES ($1, $2) ON CONFLICT (name) DO NOTHING")
+                .bind(name)
+                .bind(now)
+                .execute(&mut *tx)
+                .await
+                .entity_context("register schedule", name)?;
+
+            let claimed = sqlx::query(
+                r#"
+                UPDATE schedules SET next_run_at = $3, last_enqueued_at = $2, updated_at = $2
+                WHERE name = $1 AND next_run_at <= $2
+                "#,
+            )
+            .bind(name)
+            .bind(now)
+            .bind(now + schedule.interval)
+            .execute(&mut *tx)
+            .await
+            .entity_context("claim schedule", name)?
+            .rows_affected()
+                == 1;
+
+            if claimed {
+                let payload = RunPayload {
+                    pipeline: schedule.pipeline.clone(),
+                    scheduled_at: Some(now),
+                };
+                self.job_queue.enqueue_with(&mut *tx, RUN_JOB, &payload).await?;
+                scheduled.push(name.clone());
+            }
+            tx.commit().await.context("commit transaction")?;
+        }
+
+        Ok(scheduled)
+    }
+
+    /// Calls [`schedule_due`](Self::schedule_due) every
+    /// `scheduler.poll_interval_secs` until `shutdown` completes.
+    pub async fn schedule_until(&self, shutdown: impl Future<Output = ()>) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(self.settings.poll_interval_secs));
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    match self.schedule_due().await {
+                        Ok(scheduled) if !scheduled.is_empty() => {
+                            tracing::info!("Enqueued scheduled runs: {}", scheduled.join(", "));
+                        }
+                        Ok(_) => {}
+                        Err(e) => tracing::warn!("Failed to schedule pipeline runs: {}", e),
+                    }
+                }
+                _ = &mut shutdown => break,
+            }
+        }
+    }
+}
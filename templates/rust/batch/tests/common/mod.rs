@@ -0,0 +1,93 @@
+//! Test kit: an `AppState` on a mock clock whose database pool is lazy and
+//! points nowhere unless `TEST_DATABASE_URL` is set, so tests that don't
+//! query it run anywhere.
+
+#![allow(dead_code)]
+
+use chrono::{DateTime, TimeZone, Utc};
+use config::builder::{ConfigBuilder, DefaultState};
+use platform_core::clock::MockClock;
+use sqlx::postgres::PgPoolOptions;
+use std::sync::Arc;
+use std::time::Duration;
+
+use batch_template::config::Settings;
+use batch_template::interrupt::Interrupt;
+use batch_template::AppState;
+
+/// The instant the test clock starts at.
+pub fn test_epoch() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+}
+
+pub struct TestBatch {
+    pub state: Arc<AppState>,
+    pub clock: Arc<MockClock>,
+}
+
+impl TestBatch {
+    pub async fn spawn() -> Self {
+        Self::spawn_with(|config| config).await
+    }
+
+    /// Spawns with extra configuration, e.g.
+    /// `config.set_override("pipelines.batch_size", 2)`.
+    pub async fn spawn_with(
+        configure: impl FnOnce(ConfigBuilder<DefaultState>) -> ConfigBuilder<DefaultState>,
+    ) -> Self {
+        let database_url =
+            std::env::var("TEST_DATABASE_URL").unwrap_or_else(|_| "postgres://127.0.0.1:1/test".to_string());
+        let config = Settings::defaults()
+            .and_then(|config| config.set_override("database.url", database_url.as_str()))
+            .expect("valid test configuration");
+        let settings: Settings = configure(config)
+            .build()
+            .and_then(|config| config.try_deserialize())
+            .expect("valid test settings");
+
+        let db = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_secs(1))
+            .connect_lazy(&settings.database.url)
+            .expect("valid database url");
+        let clock = Arc::new(MockClock::new(test_epoch()));
+        let state = AppState::new(db, settings, clock.clone(), Interrupt::new()).expect("app state");
+
+        Self {
+            state: Arc::new(state),
+            clock,
+        }
+    }
+
+    /// Migrates the test database and empties the template's tables; needs
+    /// `TEST_DATABASE_URL`.
+    pub async fn reset_database(&self) {
+        sqlx::migrate!("./migrations")
+            .run(&self.state.db)
+            .await
+            .expect("migrations");
+        sqlx::query("TRUNCATE jobs, schedules, checkpoints, events, daily_event_counts RESTART IDENTITY")
+            .execute(&self.state.db)
+            .await
+            .expect("truncate");
+    }
+
+    /// Inserts `count` events of `kind` at `occurred_at`.
+    pub async fn insert_events(&self, kind: &str, occurred_at: DateTime<Utc>, count: i64) {
+        sqlx::query("INSERT INTO events (kind, occurred_at) SELECT $1, $2 FROM generate_series(1, $3)")
+            .bind(kind)
+            .bind(occurred_at)
+            .bind(count)
+            .execute(&self.state.db)
+            .await
+            .expect("insert events");
+    }
+
+    pub async fn daily_count(&self, kind: &str, day: &str) -> Option<i64> {
+        sqlx::query_scalar("SELECT events FROM daily_event_counts WHERE kind = $1 AND day = $2::date")
+            .bind(kind)
+            .bind(day)
+            .fetch_optional(&self.state.db)
+            .await
+            .expect("daily count")
+    }
+}
@@ -0,0 +1,62 @@
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use tokio::sync::mpsc;
+
+use batch_template::config::Settings;
+use batch_template::metrics_push::MetricsPusher;
+
+/// A Pushgateway that reports the method, path and body of each push.
+async fn pushgateway() -> (SocketAddr, mpsc::UnboundedReceiver<(Method, String, String)>) {
+    let (pushes, received) = mpsc::unbounded_channel();
+    let make_service = make_service_fn(move |_| {
+        let pushes = pushes.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let pushes = pushes.clone();
+                async move {
+                    let method = request.method().clone();
+                    let path = request.uri().path().to_string();
+                    let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                    let _ = pushes.send((method, path, String::from_utf8(body.to_vec()).unwrap()));
+                    Ok::<_, Infallible>(Response::new(Body::empty()))
+                }
+            }))
+        }
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    (addr, received)
+}
+
+#[tokio::test]
+async fn pushes_replace_this_instances_metrics() {
+    let (addr, mut pushes) = pushgateway().await;
+    let mut settings = Settings::defaults()
+        .and_then(|config| config.set_override("database.url", "postgres://127.0.0.1:1/test"))
+        .and_then(|config| config.build())
+        .and_then(|config| config.try_deserialize::<Settings>())
+        .unwrap()
+        .metrics;
+    settings.pushgateway_url = format!("http://{}/", addr);
+    settings.instance = "worker-1".to_string();
+
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let pusher = MetricsPusher::pushgateway(&settings, recorder.handle()).unwrap();
+    metrics::with_local_recorder(&recorder, || {
+        metrics::counter!("pipeline_rows_processed_total", "pipeline" => "daily_event_counts").increment(42);
+    });
+
+    pusher.finish().await.unwrap();
+    let (method, path, body) = pushes.recv().await.unwrap();
+    assert_eq!(method, Method::PUT);
+    assert_eq!(path, "/metrics/job/batch-template/instance/worker-1");
+    assert!(
+        body.contains(r#"pipeline_rows_processed_total{pipeline="daily_event_counts"} 42"#),
+        "{}",
+        body
+    );
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use chrono::{Duration, TimeZone, Utc};
+
+use batch_template::errors::AppError;
+use batch_template::jobs::{JOB_COMPLETED, JOB_PENDING};
+use batch_template::pipelines::{RunPayload, RUN_JOB};
+use common::TestBatch;
+
+async fn spawn() -> TestBatch {
+    let batch = TestBatch::spawn_with(|config| config.set_override("pipelines.batch_size", 2).unwrap()).await;
+    batch.reset_database().await;
+    batch
+}
+
+#[tokio::test]
+async fn unregistered_pipelines_are_not_run() {
+    let batch = TestBatch::spawn().await;
+
+    // Rejected before anything touches the database
+    let error = batch.state.pipelines.run("missing").await.unwrap_err();
+    assert!(matches!(error, AppError::NotFound(_)));
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn runs_count_every_event_a_batch_at_a_time() {
+    let batch = spawn().await;
+    let day = Utc.with_ymd_and_hms(2024, 3, 1, 23, 0, 0).unwrap();
+    batch.insert_events("signup", day, 3).await;
+    batch.insert_events("signup", day + Duration::hours(2), 1).await;
+    batch.insert_events("login", day, 1).await;
+
+    let run = batch.state.pipelines.run("daily_event_counts").await.unwrap();
+    assert_eq!((run.batches, run.rows, run.interrupted), (3, 5, false));
+    assert_eq!(batch.daily_count("signup", "2024-03-01").await, Some(3));
+    assert_eq!(batch.daily_count("signup", "2024-03-02").await, Some(1));
+    assert_eq!(batch.daily_count("login", "2024-03-01").await, Some(1));
+
+    let checkpoint = batch
+        .state
+        .pipelines
+        .checkpoints()
+        .get("daily_event_counts")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(checkpoint.position::<i64>().unwrap(), Some(5));
+    assert_eq!(checkpoint.rows_processed, 5);
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn runs_resume_from_the_checkpoint() {
+    let batch = spawn().await;
+    let day = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+    batch.insert_events("signup", day, 2).await;
+    batch.state.pipelines.run("daily_event_counts").await.unwrap();
+
+    batch.insert_events("signup", day, 1).await;
+    let run = batch.state.pipelines.run("daily_event_counts").await.unwrap();
+    assert_eq!(run.rows, 1);
+    assert_eq!(batch.daily_count("signup", "2024-03-01").await, Some(3));
+
+    // Reset, it counts everything again on top
+    batch
+        .state
+        .pipelines
+        .checkpoints()
+        .reset("daily_event_counts")
+        .await
+        .unwrap();
+    batch.state.pipelines.run("daily_event_counts").await.unwrap();
+    assert_eq!(batch.daily_count("signup", "2024-03-01").await, Some(6));
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn interrupted_runs_hand_their_job_back() {
+    let batch = spawn().await;
+    batch
+        .insert_events("signup", Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap(), 4)
+        .await;
+    let payload = RunPayload {
+        pipeline: "daily_event_counts".to_string(),
+        scheduled_at: None,
+    };
+    let job_id = batch.state.job_queue.enqueue(RUN_JOB, &payload).await.unwrap();
+
+    batch.state.interrupt.trigger();
+    batch_template::worker(&batch.state).drain().await.unwrap();
+    let job = batch.state.job_queue.get_job(job_id).await.unwrap().unwrap();
+    assert_eq!((job.status.as_str(), job.attempts), (JOB_PENDING, 0));
+    assert_eq!(batch.daily_count("signup", "2024-03-01").await, None);
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn scheduled_runs_complete_through_the_worker() {
+    let batch = spawn().await;
+    batch
+        .insert_events("signup", Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap(), 3)
+        .await;
+    batch.state.scheduler.schedule_due().await.unwrap();
+
+    assert_eq!(batch_template::worker(&batch.state).drain().await.unwrap(), 1);
+    let status: String = sqlx::query_scalar("SELECT status FROM jobs WHERE kind = $1")
+        .bind(RUN_JOB)
+        .fetch_one(&batch.state.db)
+        .await
+        .unwrap();
+    assert_eq!(status, JOB_COMPLETED);
+    assert_eq!(batch.daily_count("signup", "2024-03-01").await, Some(3));
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use chrono::Duration;
+
+use batch_template::config::{ScheduleSettings, SchedulerSettings};
+use batch_template::pipelines::{RunPayload, RUN_JOB};
+use batch_template::scheduler::Scheduler;
+use common::TestBatch;
+
+fn scheduler(batch: &TestBatch, settings: SchedulerSettings) -> Result<Scheduler, config::ConfigError> {
+    let state = &batch.state;
+    Scheduler::new(
+        state.db.clone(),
+        settings,
+        state.job_queue.clone(),
+        batch.clock.clone(),
+        state.pipelines.names(),
+    )
+}
+
+#[tokio::test]
+async fn schedules_need_an_interval_and_a_registered_pipeline() {
+    let batch = TestBatch::spawn().await;
+
+    let mut settings = batch.state.settings.scheduler.clone();
+    settings.schedules.get_mut("daily_event_counts").unwrap().interval_secs = 0;
+    assert!(scheduler(&batch, settings).is_err());
+
+    let mut settings = batch.state.settings.scheduler.clone();
+    let schedule = ScheduleSettings {
+        pipeline: "missing".to_string(),
+        interval_secs: 60,
+    };
+    settings.schedules.insert("missing".to_string(), schedule);
+    assert!(scheduler(&batch, settings).is_err());
+
+    assert!(scheduler(&batch, batch.state.settings.scheduler.clone()).is_ok());
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn runs_are_enqueued_once_per_interval() {
+    let batch = TestBatch::spawn().await;
+    batch.reset_database().await;
+    let scheduler = &batch.state.scheduler;
+
+    assert_eq!(scheduler.schedule_due().await.unwrap(), ["daily_event_counts"]);
+    assert!(scheduler.schedule_due().await.unwrap().is_empty());
+
+    let interval = batch.state.settings.scheduler.schedules["daily_event_counts"].interval_secs;
+    batch.clock.advance(Duration::seconds(interval));
+    assert_eq!(scheduler.schedule_due().await.unwrap(), ["daily_event_counts"]);
+
+    let payloads: Vec<serde_json::Value> = sqlx::query_scalar("SELECT payload FROM jobs WHERE kind = $1")
+        .bind(RUN_JOB)
+        .fetch_all(&batch.state.db)
+        .await
+        .unwrap();
+    assert_eq!(payloads.len(), 2);
+    let payload: RunPayload = serde_json::from_value(payloads[0].clone()).unwrap();
+    assert_eq!(payload.pipeline, "daily_event_counts");
+}
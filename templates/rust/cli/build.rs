@@ -0,0 +1,11 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The tonic template's protos, so the CLI calls what the services serve
+    tonic_build::configure()
+        .build_server(false)
+        .build_client(true)
+        .compile(
+            &["../tonic/proto/health.proto", "../tonic/proto/admin.proto"],
+            &["../tonic/proto"],
+        )?;
+    Ok(())
+}
@@ -0,0 +1,106 @@
+//! The command line, parsed with clap's derive API. Every command takes the
+//! global options, which override the matching settings.
+
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+#[derive(Debug, Parser)]
+#[command(name = "platform", version, about = "Operate the platform's services")]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Args)]
+pub struct GlobalArgs {
+    /// Settings file; defaults to the user's platform/config.toml
+    #[arg(long, global = true, env = "PLATFORM_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Base URL of the REST API
+    #[arg(long, global = true)]
+    pub api_url: Option<String>,
+
+    /// gRPC endpoint
+    #[arg(long, global = true)]
+    pub grpc_endpoint: Option<String>,
+
+    #[arg(long, short, global = true, value_enum, default_value_t = Output::Text)]
+    pub output: Output,
+
+    /// More logs on stderr; repeat for more
+    #[arg(long, short, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Only errors on stderr
+    #[arg(long, short, global = true)]
+    pub quiet: bool,
+
+    /// Don't look for a newer release
+    #[arg(long, global = true)]
+    pub no_update_check: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Output {
+    /// Aligned columns for people
+    Text,
+    /// One JSON document, for scripts
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Check a service's health
+    Health {
+        /// Ask the gRPC health service instead of the REST API
+        #[arg(long)]
+        grpc: bool,
+    },
+    /// Read users through the REST API
+    #[command(subcommand)]
+    Users(UsersCommand),
+    /// Read or override a gRPC service's tracing filter
+    #[command(subcommand)]
+    LogLevel(LogLevelCommand),
+    /// Check for a newer release of this CLI
+    Update,
+    /// Print a shell completion script
+    ///
+    /// e.g. `platform completions bash > /etc/bash_completion.d/platform`
+    Completions {
+        shell: Shell,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum UsersCommand {
+    List {
+        #[arg(long, default_value_t = 1)]
+        page: u32,
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+    },
+    Get {
+        id: Uuid,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LogLevelCommand {
+    Get,
+    /// Override the filter, e.g. `info,sqlx=debug`, until it reverts
+    Set {
+        filter: String,
+        /// Seconds until the default comes back; the service's default if unset
+        #[arg(long)]
+        revert_after: Option<u64>,
+    },
+    /// Restore the default filter now
+    Reset,
+}
@@ -0,0 +1,90 @@
+use prost_types::Duration as ProtoDuration;
+use std::time::Duration;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Request, Status};
+
+use crate::config::GrpcSettings;
+use crate::errors::{CliError, CliResult};
+use crate::proto::admin::v1::admin_service_client::AdminServiceClient;
+use crate::proto::admin::v1::{GetLogLevelRequest, LogLevel, ResetLogLevelRequest, SetLogLevelRequest};
+use crate::proto::health::v1::health_service_client::HealthServiceClient;
+use crate::proto::health::v1::{HealthCheckRequest, HealthCheckResponse};
+
+/// Adds the bearer token, when there is one, to every call.
+#[derive(Clone)]
+pub struct Authorization(Option<MetadataValue<Ascii>>);
+
+impl tonic::service::Interceptor for Authorization {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(token) = &self.0 {
+            request.metadata_mut().insert("authorization", token.clone());
+        }
+        Ok(request)
+    }
+}
+
+type AuthorizedChannel = InterceptedService<Channel, Authorization>;
+
+/// Client of a tonic service's health and admin services.
+#[derive(Clone)]
+pub struct GrpcClient {
+    health: HealthServiceClient<AuthorizedChannel>,
+    admin: AdminServiceClient<AuthorizedChannel>,
+}
+
+impl GrpcClient {
+    /// Connects lazily, so commands that fail before calling don't wait on
+    /// the connection.
+    pub fn connect(settings: &GrpcSettings) -> CliResult<Self> {
+        let channel = Endpoint::from_shared(settings.endpoint.clone())
+            .map_err(|e| CliError::Usage(format!("invalid gRPC endpoint {}: {}", settings.endpoint, e)))?
+            .connect_timeout(Duration::from_secs(settings.connect_timeout_secs))
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .connect_lazy();
+        let token = match settings.token.as_deref().filter(|token| !token.is_empty()) {
+            Some(token) => Some(
+                format!("Bearer {}", token)
+                    .parse()
+                    .map_err(|_| CliError::Usage("grpc.token isn't valid metadata".to_string()))?,
+            ),
+            None => None,
+        };
+        let authorization = Authorization(token);
+
+        Ok(Self {
+            health: HealthServiceClient::with_interceptor(channel.clone(), authorization.clone()),
+            admin: AdminServiceClient::with_interceptor(channel, authorization),
+        })
+    }
+
+    pub async fn health(&self) -> CliResult<HealthCheckResponse> {
+        let response = self.health.clone().check(HealthCheckRequest::default()).await?;
+        Ok(response.into_inner())
+    }
+
+    pub async fn get_log_level(&self) -> CliResult<LogLevel> {
+        let response = self.admin.clone().get_log_level(GetLogLevelRequest {}).await?;
+        Ok(response.into_inner())
+    }
+
+    /// Overrides the service's tracing filter until `revert_after`, or the
+    /// service's default duration.
+    pub async fn set_log_level(&self, filter: &str, revert_after: Option<Duration>) -> CliResult<LogLevel> {
+        let request = SetLogLevelRequest {
+            filter: filter.to_string(),
+            revert_after: revert_after.map(|revert_after| ProtoDuration {
+                seconds: revert_after.as_secs() as i64,
+                nanos: 0,
+            }),
+        };
+        let response = self.admin.clone().set_log_level(request).await?;
+        Ok(response.into_inner())
+    }
+
+    pub async fn reset_log_level(&self) -> CliResult<LogLevel> {
+        let response = self.admin.clone().reset_log_level(ResetLogLevelRequest {}).await?;
+        Ok(response.into_inner())
+    }
+}
@@ -0,0 +1,123 @@
+use chrono::{DateTime, Utc};
+use reqwest::{Client, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::config::ApiSettings;
+use crate::errors::{CliError, CliResult};
+
+/// Client of an actix service's REST API.
+#[derive(Clone)]
+pub struct ApiClient {
+    client: Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Health {
+    pub status: String,
+    pub version: String,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub id: Uuid,
+    pub email: String,
+    pub username: String,
+    pub full_name: Option<String>,
+    pub is_active: bool,
+    pub is_verified: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    /// `None` when the service skipped counting.
+    pub total: Option<i64>,
+    pub page: u32,
+    pub limit: u32,
+    pub total_pages: Option<u32>,
+}
+
+/// The services' error body; only the parts the CLI reports.
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    error_code: String,
+    message: String,
+}
+
+impl ApiClient {
+    pub fn new(settings: &ApiSettings) -> CliResult<Self> {
+        let client = Client::builder()
+            .user_agent(concat!("platform-cli/", env!("CARGO_PKG_VERSION")))
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .build()
+            .map_err(|e| CliError::Unavailable(format!("failed to build the HTTP client: {}", e)))?;
+
+        Ok(Self {
+            client,
+            base_url: settings.url.trim_end_matches('/').to_string(),
+            token: settings.token.clone().filter(|token| !token.is_empty()),
+        })
+    }
+
+    pub async fn health(&self) -> CliResult<Health> {
+        self.send(self.get("/api/v1/health")).await
+    }
+
+    pub async fn list_users(&self, page: u32, limit: u32) -> CliResult<Page<User>> {
+        self.send(self.get("/api/v1/users").query(&[("page", page), ("limit", limit)])).await
+    }
+
+    pub async fn get_user(&self, id: Uuid) -> CliResult<User> {
+        self.send(self.get(&format!("/api/v1/users/{}", id))).await
+    }
+
+    fn get(&self, path: &str) -> RequestBuilder {
+        let request = self.client.get(format!("{}{}", self.base_url, path));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> CliResult<T> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| CliError::Unavailable(format!("request failed: {}", e)))?;
+        tracing::debug!(status = %response.status(), url = %response.url(), "API response");
+
+        if response.status().is_success() {
+            return response
+                .json()
+                .await
+                .map_err(|e| CliError::Unavailable(format!("unexpected response: {}", e)));
+        }
+        Err(api_error(response).await)
+    }
+}
+
+/// Reads the error body, falling back to the status line for responses
+/// that didn't come from the service, e.g. a proxy's.
+async fn api_error(response: Response) -> CliError {
+    let status = response.status();
+    match response.json::<ErrorBody>().await {
+        Ok(body) => CliError::Api {
+            status: status.as_u16(),
+            code: body.error_code,
+            message: body.message,
+        },
+        Err(_) if status.is_server_error() => CliError::Unavailable(format!("service answered {}", status)),
+        Err(_) => CliError::Api {
+            status: status.as_u16(),
+            code: "UNKNOWN".to_string(),
+            message: status.to_string(),
+        },
+    }
+}
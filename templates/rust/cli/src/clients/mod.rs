@@ -0,0 +1,9 @@
+//! Clients of the platform's services: [`ApiClient`] for the actix
+//! template's REST API and [`GrpcClient`] for the tonic template's health
+//! and admin services.
+
+pub mod grpc;
+pub mod http;
+
+pub use grpc::GrpcClient;
+pub use http::ApiClient;
@@ -0,0 +1,141 @@
+//! What each command does. Commands return their output rather than
+//! printing it, so tests can run them against fake services.
+
+use chrono::{DateTime, Utc};
+use clap::CommandFactory;
+use platform_core::clock::SharedClock;
+use serde_json::json;
+use std::time::Duration;
+
+use crate::cli::{Cli, Command, LogLevelCommand, Output, UsersCommand};
+use crate::clients::{ApiClient, GrpcClient};
+use crate::config::Settings;
+use crate::errors::{CliError, CliResult};
+use crate::output::{render, table};
+use crate::proto::admin::v1::LogLevel;
+use crate::update::UpdateCheck;
+
+pub struct Context {
+    pub settings: Settings,
+    pub output: Output,
+    pub clock: SharedClock,
+}
+
+pub async fn run(command: Command, context: &Context) -> CliResult<String> {
+    let output = context.output;
+    match command {
+        Command::Health { grpc: false } => {
+            let health = ApiClient::new(&context.settings.api)?.health().await?;
+            Ok(render(output, &health, |health| {
+                format!("{} (version {}, {})", health.status, health.version, health.timestamp)
+            }))
+        }
+        Command::Health { grpc: true } => {
+            let health = GrpcClient::connect(&context.settings.grpc)?.health().await?;
+            let value = json!({
+                "status": health.status().as_str_name(),
+                "version": health.version,
+                "metadata": health.metadata,
+            });
+            Ok(render(output, &value, |_| {
+                let mut metadata: Vec<_> = health.metadata.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                metadata.sort();
+                format!("{} (version {}) {}", health.status().as_str_name(), health.version, metadata.join(" "))
+                    .trim_end()
+                    .to_string()
+            }))
+        }
+        Command::Users(UsersCommand::List { page, limit }) => {
+            let users = ApiClient::new(&context.settings.api)?.list_users(page, limit).await?;
+            Ok(render(output, &users, |users| {
+                let rows: Vec<Vec<String>> = users
+                    .data
+                    .iter()
+                    .map(|user| {
+                        vec![
+                            user.id.to_string(),
+                            user.username.clone(),
+                            user.email.clone(),
+                            if user.is_verified { "yes" } else { "no" }.to_string(),
+                        ]
+                    })
+                    .collect();
+                let pages = users.total_pages.map_or("?".to_string(), |pages| pages.to_string());
+                format!(
+                    "{}\npage {} of {}",
+                    table(&["ID", "USERNAME", "EMAIL", "VERIFIED"], &rows),
+                    users.page,
+                    pages
+                )
+            }))
+        }
+        Command::Users(UsersCommand::Get { id }) => {
+            let user = ApiClient::new(&context.settings.api)?.get_user(id).await?;
+            Ok(render(output, &user, |user| {
+                let rows = vec![
+                    vec!["id".to_string(), user.id.to_string()],
+                    vec!["username".to_string(), user.username.clone()],
+                    vec!["email".to_string(), user.email.clone()],
+                    vec!["full name".to_string(), user.full_name.clone().unwrap_or_default()],
+                    vec!["active".to_string(), user.is_active.to_string()],
+                    vec!["verified".to_string(), user.is_verified.to_string()],
+                    vec!["created".to_string(), user.created_at.to_rfc3339()],
+                ];
+                table(&["FIELD", "VALUE"], &rows)
+            }))
+        }
+        Command::LogLevel(command) => {
+            let client = GrpcClient::connect(&context.settings.grpc)?;
+            let level = match command {
+                LogLevelCommand::Get => client.get_log_level().await?,
+                LogLevelCommand::Set { filter, revert_after } => {
+                    client.set_log_level(&filter, revert_after.map(Duration::from_secs)).await?
+                }
+                LogLevelCommand::Reset => client.reset_log_level().await?,
+            };
+            Ok(render_log_level(output, &level))
+        }
+        Command::Update => {
+            let check = UpdateCheck::new(&context.settings.update, env!("CARGO_PKG_VERSION"), context.clock.clone())?;
+            let latest = check.latest().await?;
+            let value = json!({
+                "current": check.current().to_string(),
+                "latest": latest.to_string(),
+                "update_available": latest > *check.current(),
+            });
+            Ok(render(output, &value, |_| {
+                if latest > *check.current() {
+                    format!("{} is available (this is {})", latest, check.current())
+                } else {
+                    format!("{} is the latest release", check.current())
+                }
+            }))
+        }
+        Command::Completions { shell } => {
+            let mut script = Vec::new();
+            clap_complete::generate(shell, &mut Cli::command(), "platform", &mut script);
+            String::from_utf8(script).map_err(|e| CliError::Usage(e.to_string()))
+        }
+    }
+}
+
+fn render_log_level(output: Output, level: &LogLevel) -> String {
+    let reverts_at = level
+        .revert_time
+        .as_ref()
+        .and_then(|time| DateTime::<Utc>::from_timestamp(time.seconds, time.nanos as u32));
+    let value = json!({
+        "filter": level.filter,
+        "default_filter": level.default_filter,
+        "reverts_at": reverts_at,
+    });
+    render(output, &value, |_| match reverts_at {
+        Some(reverts_at) => format!(
+            "{} (reverts to {} at {})",
+            level.filter,
+            level.default_filter,
+            reverts_at.to_rfc3339()
+        ),
+        None => format!("{} (default)", level.filter),
+    })
+}
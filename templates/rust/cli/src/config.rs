@@ -0,0 +1,107 @@
+use config::builder::{ConfigBuilder, DefaultState};
+use config::{Config, ConfigError, File};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Settings {
+    pub api: ApiSettings,
+    pub grpc: GrpcSettings,
+    pub log: LogSettings,
+    pub update: UpdateSettings,
+}
+
+/// The REST API of an actix service.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApiSettings {
+    /// Base URL, without the `/api/v1` prefix.
+    pub url: String,
+    /// Session JWT or API token sent as `Authorization: Bearer`.
+    pub token: Option<String>,
+    pub timeout_secs: u64,
+}
+
+/// The gRPC endpoint of a tonic service.
+#[derive(Debug, Deserialize, Clone)]
+pub struct GrpcSettings {
+    pub endpoint: String,
+    /// Sent as `authorization: Bearer` metadata; the admin methods need it.
+    pub token: Option<String>,
+    pub connect_timeout_secs: u64,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LogSettings {
+    /// Filter directives for logs on stderr, e.g. `warn` or `warn,cli_template=debug`.
+    /// `-v` and `-q` replace it.
+    pub filter: String,
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct UpdateSettings {
+    /// Look for newer releases and mention them on stderr.
+    pub enabled: bool,
+    /// Endpoint answering with the latest release as `{"tag_name": "v1.2.3"}`,
+    /// like GitHub's `releases/latest`.
+    pub releases_url: String,
+    /// How long a check's result is reused before asking again.
+    pub interval_secs: i64,
+    pub timeout_secs: u64,
+    /// Where the last check is remembered. Empty uses the user's state or
+    /// cache directory.
+    pub state_file: String,
+}
+
+impl Settings {
+    /// Loads settings the way the services do (defaults, `config/default`,
+    /// `config/<RUN_MODE>`, then `PLATFORM_*` variables), with `file`, or the
+    /// user's `platform/config.toml` when there is one, between the defaults
+    /// and the rest.
+    pub fn new(file: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut builder = Self::defaults()?;
+        match file {
+            Some(file) => builder = builder.add_source(File::from(file)),
+            None => {
+                if let Some(file) = user_config_file() {
+                    builder = builder.add_source(File::from(file).required(false));
+                }
+            }
+        }
+        platform_core::config::load(builder, "PLATFORM")
+    }
+
+    /// Builder holding only the default values, e.g. for tests to add
+    /// overrides to.
+    pub fn defaults() -> Result<ConfigBuilder<DefaultState>, ConfigError> {
+        Config::builder()
+            .set_default("api.url", "http://localhost:8080")?
+            .set_default("api.timeout_secs", 30)?
+            .set_default("grpc.endpoint", "http://localhost:50051")?
+            .set_default("grpc.connect_timeout_secs", 10)?
+            .set_default("grpc.timeout_secs", 30)?
+            .set_default("log.filter", "warn")?
+            .set_default("log.format", "text")?
+            .set_default("update.enabled", true)?
+            .set_default(
+                "update.releases_url",
+                "https://api.github.com/repos/marcuspat/devxplatform/releases/latest",
+            )?
+            .set_default("update.interval_secs", 86400)?
+            .set_default("update.timeout_secs", 2)?
+            .set_default("update.state_file", "")
+    }
+}
+
+/// `$XDG_CONFIG_HOME/platform/config.toml` or the platform's equivalent.
+pub fn user_config_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("platform").join("config.toml"))
+}
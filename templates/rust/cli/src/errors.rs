@@ -0,0 +1,60 @@
+use config::ConfigError;
+use thiserror::Error;
+use tonic::Code;
+
+/// Why a command failed, and so which status the CLI exits with.
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("Invalid configuration: {0}")]
+    Config(#[from] ConfigError),
+
+    /// An error body from a platform service, as the error catalog
+    /// describes it.
+    #[error("{message} ({code}, HTTP {status})")]
+    Api { status: u16, code: String, message: String },
+
+    #[error("{code:?}: {message}")]
+    Grpc { code: Code, message: String },
+
+    /// The service couldn't be reached or answered with something other
+    /// than an API response.
+    #[error("{0}")]
+    Unavailable(String),
+
+    #[error("{0}")]
+    Usage(String),
+}
+
+impl CliError {
+    /// Exit status, following sysexits(3) so scripts can tell a bad
+    /// invocation from an outage.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Usage(_) => 64,
+            Self::Config(_) => 78,
+            Self::Api { status: 401 | 403, .. } => 77,
+            Self::Api { status, .. } if *status >= 500 => 69,
+            Self::Grpc {
+                code: Code::Unauthenticated | Code::PermissionDenied,
+                ..
+            } => 77,
+            Self::Grpc {
+                code: Code::Unavailable | Code::DeadlineExceeded,
+                ..
+            } => 69,
+            Self::Unavailable(_) => 69,
+            Self::Api { .. } | Self::Grpc { .. } => 1,
+        }
+    }
+}
+
+impl From<tonic::Status> for CliError {
+    fn from(status: tonic::Status) -> Self {
+        Self::Grpc {
+            code: status.code(),
+            message: status.message().to_string(),
+        }
+    }
+}
+
+pub type CliResult<T> = Result<T, CliError>;
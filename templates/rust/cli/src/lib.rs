@@ -0,0 +1,21 @@
+pub mod cli;
+pub mod clients;
+pub mod commands;
+pub mod config;
+pub mod errors;
+pub mod logging;
+pub mod output;
+pub mod update;
+
+pub mod proto {
+    pub mod admin {
+        pub mod v1 {
+            tonic::include_proto!("admin.v1");
+        }
+    }
+    pub mod health {
+        pub mod v1 {
+            tonic::include_proto!("health.v1");
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! Structured logs on stderr, leaving stdout to command output.
+
+use tracing_subscriber::layer::{Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter};
+
+use crate::config::{LogFormat, LogSettings};
+
+/// The filter `-v` and `-q` select, or `log.filter` without either.
+pub fn filter(settings: &LogSettings, verbose: u8, quiet: bool) -> String {
+    match (quiet, verbose) {
+        (true, _) => "error".to_string(),
+        (false, 0) => settings.filter.clone(),
+        (false, 1) => "info".to_string(),
+        (false, 2) => "debug".to_string(),
+        (false, _) => "trace".to_string(),
+    }
+}
+
+pub fn init(settings: &LogSettings, filter: &str) -> anyhow::Result<()> {
+    let filter = EnvFilter::try_new(filter)?;
+    let layer = fmt::layer().with_writer(std::io::stderr).with_target(false);
+    let layer = match settings.format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    };
+    tracing_subscriber::registry().with(filter).with(layer).try_init()?;
+    Ok(())
+}
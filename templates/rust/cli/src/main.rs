@@ -0,0 +1,87 @@
+//! ```text
+//! platform health [--grpc]                 # a service's health
+//! platform users list|get <id>             # users, through the REST API
+//! platform log-level get|set <filter>|reset  # a gRPC service's tracing filter
+//! platform update                          # check for a newer release
+//! platform completions <shell>             # shell completion script
+//! ```
+
+use clap::Parser;
+use config::ConfigError;
+use dotenv::dotenv;
+use platform_core::clock::{SharedClock, SystemClock};
+use std::io::IsTerminal;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use cli_template::cli::{Cli, Command};
+use cli_template::commands::{self, Context};
+use cli_template::config::Settings;
+use cli_template::errors::{CliError, CliResult};
+use cli_template::logging;
+use cli_template::update::UpdateCheck;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    // Load environment variables
+    dotenv().ok();
+
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+async fn run(cli: Cli) -> CliResult<()> {
+    // Settings load like the services' do; flags win over all of it
+    let global = cli.global;
+    let mut settings = Settings::new(global.config.as_deref())?;
+    if let Some(url) = global.api_url {
+        settings.api.url = url;
+    }
+    if let Some(endpoint) = global.grpc_endpoint {
+        settings.grpc.endpoint = endpoint;
+    }
+    let filter = logging::filter(&settings.log, global.verbose, global.quiet);
+    logging::init(&settings.log, &filter)
+        .map_err(|e| CliError::Config(ConfigError::Message(format!("invalid log filter: {}", e))))?;
+
+    // Checked while the command runs, and only mentioned to people at a
+    // terminal
+    let clock: SharedClock = Arc::new(SystemClock);
+    let wants_update_check = settings.update.enabled
+        && !global.no_update_check
+        && !matches!(cli.command, Command::Update | Command::Completions { .. })
+        && std::io::stderr().is_terminal();
+    let update_check = if wants_update_check {
+        let check = UpdateCheck::new(&settings.update, env!("CARGO_PKG_VERSION"), clock.clone())?;
+        Some(tokio::spawn(async move { check.newer().await }))
+    } else {
+        None
+    };
+
+    let context = Context {
+        settings,
+        output: global.output,
+        clock,
+    };
+    let result = commands::run(cli.command, &context).await;
+    if let Ok(output) = &result {
+        println!("{}", output);
+    }
+
+    if let Some(check) = update_check {
+        if let Ok(Some(latest)) = check.await {
+            eprintln!(
+                "platform {} is available (this is {}); run `platform update` for details",
+                latest,
+                env!("CARGO_PKG_VERSION")
+            );
+        }
+    }
+    result.map(|_| ())
+}
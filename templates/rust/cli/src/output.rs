@@ -0,0 +1,40 @@
+//! Command output on stdout, as text for people or JSON for scripts.
+
+use serde::Serialize;
+use std::fmt::Write;
+
+use crate::cli::Output;
+
+/// Renders `value` as pretty JSON, or as `text` renders it.
+pub fn render<T: Serialize>(output: Output, value: &T, text: impl FnOnce(&T) -> String) -> String {
+    match output {
+        Output::Json => serde_json::to_string_pretty(value).expect("output serializes"),
+        Output::Text => text(value),
+    }
+}
+
+/// Left-aligned columns under a header row.
+pub fn table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let widths: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            rows.iter()
+                .map(|row| row.get(i).map_or(0, |cell| cell.chars().count()))
+                .fold(header.len(), usize::max)
+        })
+        .collect();
+
+    let mut table = String::new();
+    let header_row: Vec<String> = headers.iter().map(|header| header.to_string()).collect();
+    for row in std::iter::once(&header_row).chain(rows) {
+        let mut line = String::new();
+        for (cell, width) in row.iter().zip(&widths) {
+            let _ = write!(line, "{:<width$}  ", cell, width = width);
+        }
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table.pop();
+    table
+}
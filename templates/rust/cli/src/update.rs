@@ -0,0 +1,134 @@
+//! Checks for newer releases of the CLI.
+//!
+//! Commands start a check in the background and mention a newer release on
+//! stderr once they're done. The answer is remembered in a state file for
+//! `update.interval_secs`, so most runs don't call out at all, and a slow
+//! or failing release endpoint only costs `update.timeout_secs`. `platform
+//! update` always asks.
+
+use chrono::{DateTime, Duration, Utc};
+use platform_core::clock::SharedClock;
+use reqwest::Client;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::config::UpdateSettings;
+use crate::errors::{CliError, CliResult};
+
+/// What the last check found.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct State {
+    checked_at: DateTime<Utc>,
+    latest: Version,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+}
+
+pub struct UpdateCheck {
+    client: Client,
+    releases_url: String,
+    interval: Duration,
+    state_file: Option<PathBuf>,
+    current: Version,
+    clock: SharedClock,
+}
+
+impl UpdateCheck {
+    /// Checks for releases newer than `current`, this build's version
+    /// unless testing.
+    pub fn new(settings: &UpdateSettings, current: &str, clock: SharedClock) -> CliResult<Self> {
+        let client = Client::builder()
+            .user_agent(concat!("platform-cli/", env!("CARGO_PKG_VERSION")))
+            .timeout(std::time::Duration::from_secs(settings.timeout_secs))
+            .build()
+            .map_err(|e| CliError::Unavailable(format!("failed to build the HTTP client: {}", e)))?;
+        let current = parse_version(current)?;
+        let state_file = match settings.state_file.as_str() {
+            "" => dirs::state_dir()
+                .or_else(dirs::cache_dir)
+                .map(|dir| dir.join("platform").join("update-check.json")),
+            path => Some(PathBuf::from(path)),
+        };
+
+        Ok(Self {
+            client,
+            releases_url: settings.releases_url.clone(),
+            interval: Duration::seconds(settings.interval_secs),
+            state_file,
+            current,
+            clock,
+        })
+    }
+
+    pub fn current(&self) -> &Version {
+        &self.current
+    }
+
+    /// The latest release, asking the release endpoint.
+    pub async fn latest(&self) -> CliResult<Version> {
+        let release: Release = self
+            .client
+            .get(&self.releases_url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| CliError::Unavailable(format!("release check failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| CliError::Unavailable(format!("unexpected release response: {}", e)))?;
+        let latest = parse_version(&release.tag_name)?;
+
+        self.save(&State {
+            checked_at: self.clock.now(),
+            latest: latest.clone(),
+        });
+        Ok(latest)
+    }
+
+    /// The latest release if it's newer than this one, asking the release
+    /// endpoint only once the remembered answer is `update.interval_secs`
+    /// old. Failures are logged, not returned: a check must never fail a
+    /// command.
+    pub async fn newer(&self) -> Option<Version> {
+        let latest = match self.load() {
+            Some(state) if self.clock.now() - state.checked_at < self.interval => state.latest,
+            _ => match self.latest().await {
+                Ok(latest) => latest,
+                Err(e) => {
+                    tracing::debug!("Update check failed: {}", e);
+                    return None;
+                }
+            },
+        };
+        (latest > self.current).then_some(latest)
+    }
+
+    fn load(&self) -> Option<State> {
+        let contents = std::fs::read(self.state_file.as_ref()?).ok()?;
+        serde_json::from_slice(&contents).ok()
+    }
+
+    /// Best effort; without a state file every run checks.
+    fn save(&self, state: &State) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(path, serde_json::to_vec(state).unwrap_or_default()));
+        if let Err(e) = written {
+            tracing::debug!("Failed to save the update check to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Parses `1.2.3` or a `v1.2.3` tag.
+fn parse_version(version: &str) -> CliResult<Version> {
+    Version::parse(version.trim_start_matches('v'))
+        .map_err(|e| CliError::Unavailable(format!("invalid release version {}: {}", version, e)))
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use platform_core::clock::SystemClock;
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use cli_template::cli::{Command, Output, UsersCommand};
+use cli_template::clients::ApiClient;
+use cli_template::commands::{self, Context};
+use cli_template::errors::CliError;
+use common::{json, serve, settings};
+
+fn user(id: Uuid) -> serde_json::Value {
+    json!({
+        "id": id,
+        "email": "ada@acme.test",
+        "username": "ada",
+        "full_name": "Ada Lovelace",
+        "is_active": true,
+        "is_verified": true,
+        "phone_number": null,
+        "phone_verified": false,
+        "is_guest": false,
+        "created_at": Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap(),
+    })
+}
+
+#[tokio::test]
+async fn requests_carry_the_token_and_parse_service_responses() {
+    let id = Uuid::new_v4();
+    let (addr, received) = serve(move |_| {
+        json(
+            200,
+            json!({ "data": [user(id)], "total": 21, "page": 2, "limit": 20, "total_pages": 2, "count": "exact" }),
+        )
+    })
+    .await;
+    let mut settings = settings();
+    settings.api.url = format!("http://{}/", addr);
+    settings.api.token = Some("session-token".to_string());
+
+    let users = ApiClient::new(&settings.api).unwrap().list_users(2, 20).await.unwrap();
+    assert_eq!(users.data[0].id, id);
+    assert_eq!(users.total, Some(21));
+    assert_eq!(
+        received.lock().unwrap()[0],
+        (
+            "/api/v1/users?page=2&limit=20".to_string(),
+            Some("Bearer session-token".to_string())
+        )
+    );
+
+    let context = Context {
+        settings,
+        output: Output::Text,
+        clock: Arc::new(SystemClock),
+    };
+    let output = commands::run(Command::Users(UsersCommand::List { page: 2, limit: 20 }), &context)
+        .await
+        .unwrap();
+    assert!(output.starts_with("ID"), "{}", output);
+    assert!(output.contains("ada@acme.test"), "{}", output);
+    assert!(output.ends_with("page 2 of 2"), "{}", output);
+}
+
+#[tokio::test]
+async fn error_bodies_become_error_codes_and_exit_statuses() {
+    let (addr, _) = serve(|request| match request.uri().path() {
+        "/api/v1/health" => json(503, json!({ "error": "down" })),
+        _ if request.headers().contains_key("authorization") => json(
+            404,
+            json!({ "code": 404, "error": "404 Not Found", "error_code": "NOT_FOUND", "message": "User not found" }),
+        ),
+        _ => json(
+            401,
+            json!({ "code": 401, "error": "401 Unauthorized", "error_code": "UNAUTHORIZED", "message": "Unauthorized" }),
+        ),
+    })
+    .await;
+    let mut settings = settings();
+    settings.api.url = format!("http://{}", addr);
+
+    let anonymous = ApiClient::new(&settings.api).unwrap();
+    let error = anonymous.get_user(Uuid::new_v4()).await.unwrap_err();
+    assert!(matches!(&error, CliError::Api { status: 401, code, .. } if code == "UNAUTHORIZED"), "{:?}", error);
+    assert_eq!(error.exit_code(), 77);
+
+    settings.api.token = Some("session-token".to_string());
+    let client = ApiClient::new(&settings.api).unwrap();
+    let error = client.get_user(Uuid::new_v4()).await.unwrap_err();
+    assert!(matches!(&error, CliError::Api { status: 404, code, .. } if code == "NOT_FOUND"), "{:?}", error);
+    assert_eq!(error.exit_code(), 1);
+
+    // Not an error body the services send
+    let error = client.health().await.unwrap_err();
+    assert!(matches!(error, CliError::Unavailable(_)), "{:?}", error);
+    assert_eq!(error.exit_code(), 69);
+}
+
+#[tokio::test]
+async fn unreachable_services_are_unavailable() {
+    let mut settings = settings();
+    settings.api.url = "http://127.0.0.1:1".to_string();
+
+    let error = ApiClient::new(&settings.api).unwrap().health().await.unwrap_err();
+    assert!(matches!(error, CliError::Unavailable(_)), "{:?}", error);
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use clap::{CommandFactory, Parser};
+use clap_complete::Shell;
+use platform_core::clock::SystemClock;
+use std::sync::Arc;
+
+use cli_template::cli::{Cli, Command, Output, UsersCommand};
+use cli_template::commands::{self, Context};
+use cli_template::logging;
+use common::settings;
+
+#[test]
+fn command_line_is_consistent() {
+    Cli::command().debug_assert();
+}
+
+#[test]
+fn global_options_go_anywhere() {
+    let cli = Cli::try_parse_from(["platform", "users", "list", "--limit", "5", "-o", "json", "-vv"]).unwrap();
+
+    assert!(matches!(cli.command, Command::Users(UsersCommand::List { page: 1, limit: 5 })));
+    assert_eq!(cli.global.output, Output::Json);
+    assert_eq!(cli.global.verbose, 2);
+    assert!(Cli::try_parse_from(["platform", "health", "-v", "--quiet"]).is_err());
+}
+
+#[test]
+fn verbosity_replaces_the_configured_filter() {
+    let settings = settings().log;
+
+    assert_eq!(logging::filter(&settings, 0, false), "warn");
+    assert_eq!(logging::filter(&settings, 1, false), "info");
+    assert_eq!(logging::filter(&settings, 5, false), "trace");
+    assert_eq!(logging::filter(&settings, 0, true), "error");
+}
+
+#[tokio::test]
+async fn completions_cover_the_subcommands() {
+    let context = Context {
+        settings: settings(),
+        output: Output::Text,
+        clock: Arc::new(SystemClock),
+    };
+
+    let script = commands::run(Command::Completions { shell: Shell::Bash }, &context).await.unwrap();
+    assert!(script.contains("log-level"), "{}", script);
+    assert!(script.contains("--grpc-endpoint"), "{}", script);
+}
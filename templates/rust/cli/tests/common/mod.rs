@@ -0,0 +1,60 @@
+//! Test kit: fake platform services on local ports, and the default
+//! settings to point at them.
+
+#![allow(dead_code)]
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use cli_template::config::Settings;
+
+/// The requests a fake service received, as `(path and query, authorization)`.
+pub type Received = Arc<Mutex<Vec<(String, Option<String>)>>>;
+
+/// A fake platform service answering every request with `respond`.
+pub async fn serve<F>(respond: F) -> (SocketAddr, Received)
+where
+    F: Fn(&Request<Body>) -> Response<Body> + Send + Sync + 'static,
+{
+    let respond = Arc::new(respond);
+    let received = Received::default();
+    let log = received.clone();
+    let make_service = make_service_fn(move |_| {
+        let respond = respond.clone();
+        let log = log.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let authorization = request
+                    .headers()
+                    .get("authorization")
+                    .map(|value| value.to_str().unwrap().to_string());
+                let path = request.uri().path_and_query().unwrap().to_string();
+                log.lock().unwrap().push((path, authorization));
+                let response = respond(&request);
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    (addr, received)
+}
+
+pub fn json(status: u16, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+pub fn settings() -> Settings {
+    Settings::defaults()
+        .and_then(|config| config.build())
+        .and_then(|config| config.try_deserialize())
+        .unwrap()
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use platform_core::clock::MockClock;
+use semver::Version;
+use serde_json::json;
+use std::sync::Arc;
+
+use cli_template::update::UpdateCheck;
+use common::{json, serve, settings};
+
+#[tokio::test]
+async fn newer_releases_are_found_and_remembered_for_the_interval() {
+    let (addr, received) = serve(|_| json(200, json!({ "tag_name": "v1.4.0", "name": "1.4.0" }))).await;
+    let state = tempfile::tempdir().unwrap();
+    let mut settings = settings().update;
+    settings.releases_url = format!("http://{}/releases/latest", addr);
+    settings.state_file = state.path().join("update-check.json").display().to_string();
+    let clock = Arc::new(MockClock::new(Utc::now()));
+
+    let check = UpdateCheck::new(&settings, "1.3.2", clock.clone()).unwrap();
+    assert_eq!(check.newer().await, Some(Version::new(1, 4, 0)));
+    assert_eq!(check.newer().await, Some(Version::new(1, 4, 0)));
+    assert_eq!(received.lock().unwrap().len(), 1);
+
+    // Remembered across runs, until the interval is over
+    let check = UpdateCheck::new(&settings, "1.4.0", clock.clone()).unwrap();
+    assert_eq!(check.newer().await, None);
+    assert_eq!(received.lock().unwrap().len(), 1);
+    clock.advance(Duration::seconds(settings.interval_secs));
+    assert_eq!(check.newer().await, None);
+    assert_eq!(received.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn failed_checks_are_quiet() {
+    let (addr, _) = serve(|_| json(200, json!({ "tag_name": "nightly" }))).await;
+    let state = tempfile::tempdir().unwrap();
+    let mut settings = settings().update;
+    settings.state_file = state.path().join("update-check.json").display().to_string();
+    let clock = Arc::new(MockClock::new(Utc::now()));
+
+    settings.releases_url = format!("http://{}/releases/latest", addr);
+    let check = UpdateCheck::new(&settings, "1.3.2", clock.clone()).unwrap();
+    assert_eq!(check.newer().await, None);
+    assert!(check.latest().await.is_err());
+
+    settings.releases_url = "http://127.0.0.1:1/releases/latest".to_string();
+    let check = UpdateCheck::new(&settings, "1.3.2", clock).unwrap();
+    assert_eq!(check.newer().await, None);
+}
@@ -0,0 +1,85 @@
+use bcrypt::{hash, verify, DEFAULT_COST};
+use platform_core::errors::AppResult;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+pub fn hash_password(password: &str) -> AppResult<String> {
+    let hashed = hash(password, DEFAULT_COST)?;
+    Ok(hashed)
+}
+
+pub fn verify_password(password: &str, hash: &str) -> AppResult<bool> {
+    let valid = verify(password, hash)?;
+    Ok(valid)
+}
+
+/// A bcrypt password hash. Only [`HashedPassword::new`] builds one from a
+/// password, so a plaintext password can't end up in the column by mistake;
+/// `Debug` doesn't print the hash.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(transparent))]
+#[serde(transparent)]
+pub struct HashedPassword(String);
+
+impl HashedPassword {
+    pub fn new(password: &str) -> AppResult<Self> {
+        hash_password(password).map(Self)
+    }
+
+    /// Wraps a bcrypt hash computed elsewhere, such as a fixture's
+    /// deterministic one. The hash isn't checked.
+    pub fn from_hash(hash: String) -> Self {
+        Self(hash)
+    }
+
+    pub fn verify(&self, password: &str) -> AppResult<bool> {
+        verify_password(password, &self.0)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for HashedPassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("HashedPassword(..)")
+    }
+}
+
+/// Hashes a high-entropy secret (API token, refresh token) for storage.
+///
+/// Such secrets carry enough entropy that a fast digest is sufficient; bcrypt
+/// would add latency to every authenticated request.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passwords_verify_against_their_hash_only() {
+        let hashed = hash_password("correct horse").unwrap();
+        assert!(verify_password("correct horse", &hashed).unwrap());
+        assert!(!verify_password("battery staple", &hashed).unwrap());
+    }
+
+    #[test]
+    fn hashed_passwords_verify_and_stay_out_of_logs() {
+        let hashed = HashedPassword::new("correct horse").unwrap();
+        assert!(hashed.verify("correct horse").unwrap());
+        assert!(!hashed.verify("battery staple").unwrap());
+        assert_ne!(hashed.as_str(), "correct horse");
+        assert_eq!(format!("{:?}", hashed), "HashedPassword(..)");
+    }
+
+    #[test]
+    fn token_hashes_are_stable_hex_digests() {
+        assert_eq!(hash_token("dxp_abc"), hash_token("dxp_abc"));
+        assert_ne!(hash_token("dxp_abc"), hash_token("dxp_abd"));
+        assert_eq!(hash_token("dxp_abc").len(), 64);
+    }
+}
@@ -0,0 +1,163 @@
+//! Issuers' public keys, fetched as JWKS documents and cached. Shared by
+//! the [`workload`](crate::workload) and [`oidc`](crate::oidc) verifiers.
+
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::jwk::{JwkSet, PublicKeyUse};
+use jsonwebtoken::{Algorithm, DecodingKey};
+use serde::Deserialize;
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+use platform_core::errors::{AppError, AppResult, RetryHint};
+
+/// Least time between two fetches prompted by an unknown `kid`, so tokens
+/// with made-up key ids can't hammer the JWKS endpoint.
+const MIN_REFETCH_SECS: i64 = 30;
+
+/// Where an issuer's public keys are published, as a JWKS document.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct KeySource {
+    /// e.g. `https://kubernetes.default.svc/openid/v1/jwks` or the SPIRE
+    /// server's bundle endpoint.
+    pub url: Option<String>,
+    /// A JWKS file, such as a bundle written by the SPIFFE helper; read
+    /// instead of `url` when set.
+    pub path: Option<String>,
+    /// A token file sent as bearer token to `url`; the API server wants one
+    /// unless its JWKS is public.
+    pub token_path: Option<String>,
+    /// A PEM file with an extra CA to trust for `url`, such as the cluster CA.
+    pub ca_path: Option<String>,
+}
+
+/// Only public keys are published; a token signed with a symmetric
+/// algorithm would have the key treated as a shared secret.
+pub(crate) fn is_symmetric(algorithm: Algorithm) -> bool {
+    matches!(algorithm, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512)
+}
+
+/// A client for fetching from `sources`, trusting their extra CAs.
+pub(crate) fn client<'a>(sources: impl IntoIterator<Item = &'a KeySource>) -> anyhow::Result<reqwest::Client> {
+    let mut client = reqwest::Client::builder().timeout(std::time::Duration::from_secs(10));
+    for source in sources {
+        if let Some(ca_path) = &source.ca_path {
+            let pem = std::fs::read(ca_path)?;
+            client = client.add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+        }
+    }
+    Ok(client.build()?)
+}
+
+/// One issuer's keys, fetched again once they're `refresh` old or a token
+/// names a key they don't include.
+pub(crate) struct KeyCache {
+    source: KeySource,
+    refresh: Duration,
+    cached: Mutex<CachedKeys>,
+}
+
+#[derive(Default)]
+struct CachedKeys {
+    keys: HashMap<String, DecodingKey>,
+    fetched_at: Option<DateTime<Utc>>,
+}
+
+impl KeyCache {
+    pub(crate) fn new(source: KeySource, refresh: Duration) -> Self {
+        Self {
+            source,
+            refresh,
+            cached: Mutex::new(CachedKeys::default()),
+        }
+    }
+
+    /// The key `kid`, fetching the keys again when they are stale or don't
+    /// include it. Keeps using the keys it has if a fetch fails.
+    pub(crate) async fn key(
+        &self,
+        client: &reqwest::Client,
+        kid: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> AppResult<Option<DecodingKey>> {
+        let mut cached = self.cached.lock().await;
+
+        let age = cached.fetched_at.map(|fetched_at| now - fetched_at);
+        let stale = age.is_none_or(|age| age >= self.refresh);
+        let unknown = cached.lookup(kid).is_none() && age.is_none_or(|age| age >= Duration::seconds(MIN_REFETCH_SECS));
+
+        if stale || unknown {
+            match fetch(client, &self.source).await {
+                Ok(keys) => {
+                    cached.keys = keys;
+                    cached.fetched_at = Some(now);
+                }
+                Err(error) if cached.fetched_at.is_some() => {
+                    tracing::warn!(error = %error, "Refreshing signing keys failed; using the cached ones");
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(cached.lookup(kid).cloned())
+    }
+}
+
+impl CachedKeys {
+    /// Tokens without a `kid` are accepted while the issuer has a single key.
+    fn lookup(&self, kid: Option<&str>) -> Option<&DecodingKey> {
+        match kid {
+            Some(kid) => self.keys.get(kid),
+            None if self.keys.len() == 1 => self.keys.values().next(),
+            None => None,
+        }
+    }
+}
+
+async fn fetch(client: &reqwest::Client, source: &KeySource) -> AppResult<HashMap<String, DecodingKey>> {
+    let unavailable = |message: String| AppError::Unavailable {
+        message,
+        retry: RetryHint::after(std::time::Duration::from_secs(MIN_REFETCH_SECS as u64)),
+    };
+
+    let document = match (&source.path, &source.url) {
+        (Some(path), _) => tokio::fs::read(path)
+            .await
+            .map_err(|e| unavailable(format!("Reading signing keys from {}: {}", path, e)))?,
+        (None, Some(url)) => {
+            let mut request = client.get(url);
+            if let Some(token_path) = &source.token_path {
+                let token = tokio::fs::read_to_string(token_path)
+                    .await
+                    .map_err(|e| unavailable(format!("Reading {}: {}", token_path, e)))?;
+                request = request.bearer_auth(token.trim());
+            }
+            request
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| unavailable(format!("Fetching signing keys from {}: {}", url, e)))?
+                .bytes()
+                .await
+                .map_err(|e| unavailable(format!("Fetching signing keys from {}: {}", url, e)))?
+                .to_vec()
+        }
+        (None, None) => return Err(unavailable("No url or path to fetch signing keys from".to_string())),
+    };
+
+    let jwks: JwkSet =
+        serde_json::from_slice(&document).map_err(|e| unavailable(format!("Invalid signing key set: {}", e)))?;
+    Ok(jwks
+        .keys
+        .iter()
+        // SPIFFE bundles list X.509-SVID roots next to the JWT-SVID keys
+        .filter(|jwk| match &jwk.common.public_key_use {
+            None | Some(PublicKeyUse::Signature) => true,
+            Some(PublicKeyUse::Other(other)) => other == "jwt-svid",
+            Some(_) => false,
+        })
+        .filter_map(|jwk| {
+            let key = DecodingKey::from_jwk(jwk).ok()?;
+            Some((jwk.common.key_id.clone().unwrap_or_default(), key))
+        })
+        .collect())
+}
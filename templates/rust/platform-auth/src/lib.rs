@@ -0,0 +1,14 @@
+//! Credential primitives shared by the templates: password hashing, digests
+//! of high-entropy tokens, random secrets, workload identity tokens and
+//! tokens of an external OpenID Connect provider.
+//!
+//! JWTs stay in each template for now; their claims and key handling differ.
+
+mod hash;
+pub mod jwks;
+pub mod oidc;
+mod secret;
+pub mod workload;
+
+pub use hash::{hash_password, hash_token, verify_password, HashedPassword};
+pub use secret::generate_secret;
@@ -0,0 +1,230 @@
+//! User tokens from an external OpenID Connect provider (Auth0, Keycloak,
+//! Entra ID and the like), for services that accept users' tokens without
+//! issuing their own. Tokens are checked against the provider's JWKS, and
+//! the granted scopes are read from a configurable claim.
+
+use chrono::Duration;
+use jsonwebtoken::{decode, decode_header, Validation};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use platform_core::clock::SharedClock;
+use platform_core::errors::{AppError, AppResult, ErrorCode};
+
+use crate::jwks::{self, KeyCache, KeySource};
+
+/// Tolerated clock skew between the provider and this service.
+const LEEWAY_SECS: i64 = 60;
+
+/// The `oidc` section of a template's settings.
+#[derive(Debug, Deserialize, Clone)]
+pub struct OidcSettings {
+    /// `iss` of accepted tokens, e.g. `https://example.eu.auth0.com/`.
+    pub issuer: String,
+    /// The `aud` tokens must be issued for, usually the API's identifier.
+    pub audience: String,
+    /// The provider's JWKS, e.g. `https://example.eu.auth0.com/.well-known/jwks.json`.
+    pub keys: KeySource,
+    /// Claim holding the granted scopes: a space-separated string like
+    /// `scope`, or a list like `scp` or `permissions`.
+    #[serde(default = "default_scope_claim")]
+    pub scope_claim: String,
+    /// How long fetched keys are used before they are fetched again.
+    #[serde(default = "default_jwks_refresh_secs")]
+    pub jwks_refresh_secs: u64,
+}
+
+fn default_scope_claim() -> String {
+    "scope".to_string()
+}
+
+fn default_jwks_refresh_secs() -> u64 {
+    300
+}
+
+/// A verified user token, added to the request extensions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OidcClaims {
+    /// The provider's id of the user.
+    pub subject: String,
+    pub email: Option<String>,
+    pub scopes: Vec<String>,
+}
+
+impl OidcClaims {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+
+    /// Succeeds if the token grants `scope`.
+    pub fn require_scope(&self, scope: &str) -> AppResult<()> {
+        if self.has_scope(scope) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden.with_code(ErrorCode::AuthInsufficientScope))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenClaims {
+    sub: String,
+    exp: i64,
+    nbf: Option<i64>,
+    email: Option<String>,
+    #[serde(flatten)]
+    other: serde_json::Map<String, Value>,
+}
+
+/// Verifies user tokens of one OIDC provider.
+pub struct OidcVerifier {
+    settings: OidcSettings,
+    keys: KeyCache,
+    client: reqwest::Client,
+    clock: SharedClock,
+}
+
+impl OidcVerifier {
+    pub fn from_settings(settings: &OidcSettings, clock: SharedClock) -> anyhow::Result<Self> {
+        Ok(Self {
+            settings: settings.clone(),
+            keys: KeyCache::new(
+                settings.keys.clone(),
+                Duration::seconds(settings.jwks_refresh_secs as i64),
+            ),
+            client: jwks::client([&settings.keys])?,
+            clock,
+        })
+    }
+
+    pub async fn verify(&self, token: &str) -> AppResult<OidcClaims> {
+        let invalid = || AppError::Unauthorized.with_code(ErrorCode::InvalidToken);
+
+        let header = decode_header(token).map_err(|_| invalid())?;
+        if jwks::is_symmetric(header.alg) {
+            return Err(invalid());
+        }
+        let key = self
+            .keys
+            .key(&self.client, header.kid.as_deref(), self.clock.now())
+            .await?
+            .ok_or_else(invalid)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.validate_exp = false;
+        validation.required_spec_claims.clear();
+        validation.set_issuer(&[&self.settings.issuer]);
+        validation.set_audience(&[&self.settings.audience]);
+        let claims = decode::<TokenClaims>(token, &key, &validation)
+            .map_err(|_| invalid())?
+            .claims;
+
+        // Checked here rather than by jsonwebtoken so a mock clock applies
+        let now = self.clock.now().timestamp();
+        if claims.exp + LEEWAY_SECS < now || claims.nbf.is_some_and(|nbf| nbf - LEEWAY_SECS > now) {
+            return Err(AppError::Unauthorized.with_code(ErrorCode::AuthTokenExpired));
+        }
+
+        let scopes = match claims.other.get(&self.settings.scope_claim) {
+            Some(Value::String(scopes)) => scopes.split_whitespace().map(str::to_string).collect(),
+            Some(Value::Array(scopes)) => scopes.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(OidcClaims {
+            subject: claims.sub,
+            email: claims.email,
+            scopes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use platform_core::clock::{Clock, MockClock};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    // The workload fixtures' cluster key stands in for a provider's
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/workload");
+    const ISSUER: &str = "https://example.eu.auth0.com/";
+
+    fn verifier(clock: Arc<MockClock>, scope_claim: &str) -> OidcVerifier {
+        let settings = OidcSettings {
+            issuer: ISSUER.to_string(),
+            audience: "https://api.example.com".to_string(),
+            keys: KeySource {
+                path: Some(format!("{}/kubernetes-jwks.json", FIXTURES)),
+                ..KeySource::default()
+            },
+            scope_claim: scope_claim.to_string(),
+            jwks_refresh_secs: 300,
+        };
+        OidcVerifier::from_settings(&settings, clock).unwrap()
+    }
+
+    fn sign(claims: serde_json::Value) -> String {
+        let pem = std::fs::read(format!("{}/kubernetes.pem", FIXTURES)).unwrap();
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some("kube-1".to_string());
+        encode(&header, &claims, &EncodingKey::from_ec_pem(&pem).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn provider_tokens_carry_their_scopes() {
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let exp = clock.now().timestamp() + 600;
+
+        let token = sign(json!({
+            "iss": ISSUER, "sub": "auth0|42", "aud": "https://api.example.com", "exp": exp,
+            "email": "ada@example.com", "scope": "read:items write:items",
+        }));
+        let claims = verifier(clock.clone(), "scope").verify(&token).await.unwrap();
+        assert_eq!(claims.subject, "auth0|42");
+        assert_eq!(claims.email.as_deref(), Some("ada@example.com"));
+        assert!(claims.has_scope("write:items"));
+        assert_eq!(
+            claims.require_scope("admin").unwrap_err().code(),
+            ErrorCode::AuthInsufficientScope
+        );
+
+        let token = sign(json!({
+            "iss": ISSUER, "sub": "auth0|42", "aud": ["https://api.example.com"], "exp": exp,
+            "permissions": ["read:items"],
+        }));
+        let claims = verifier(clock, "permissions").verify(&token).await.unwrap();
+        assert_eq!(claims.scopes, vec!["read:items"]);
+    }
+
+    #[tokio::test]
+    async fn other_issuers_audiences_and_expired_tokens_are_rejected() {
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let verifier = verifier(clock.clone(), "scope");
+        let exp = clock.now().timestamp() + 600;
+
+        let other_issuer = sign(json!({ "iss": "https://evil.example/", "sub": "x", "aud": "https://api.example.com", "exp": exp }));
+        assert_eq!(verifier.verify(&other_issuer).await.unwrap_err().code(), ErrorCode::InvalidToken);
+        let other_audience = sign(json!({ "iss": ISSUER, "sub": "x", "aud": "https://other.example.com", "exp": exp }));
+        assert_eq!(verifier.verify(&other_audience).await.unwrap_err().code(), ErrorCode::InvalidToken);
+
+        let token = sign(json!({ "iss": ISSUER, "sub": "x", "aud": "https://api.example.com", "exp": exp }));
+        clock.advance(Duration::seconds(600 + LEEWAY_SECS + 1));
+        assert_eq!(verifier.verify(&token).await.unwrap_err().code(), ErrorCode::AuthTokenExpired);
+    }
+
+    #[tokio::test]
+    async fn tokens_signed_with_a_shared_secret_are_rejected() {
+        let verifier = verifier(Arc::new(MockClock::new(Utc::now())), "scope");
+        let token = encode(
+            &Header::default(),
+            &json!({ "iss": ISSUER, "sub": "x", "aud": "https://api.example.com", "exp": 4102444800u64 }),
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+
+        assert_eq!(verifier.verify(&token).await.unwrap_err().code(), ErrorCode::InvalidToken);
+    }
+}
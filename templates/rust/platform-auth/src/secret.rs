@@ -0,0 +1,10 @@
+use rand::{distributions::Alphanumeric, Rng};
+
+/// Generates a random alphanumeric string suitable for opaque tokens.
+pub fn generate_secret(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
@@ -0,0 +1,427 @@
+//! Workload identity: other services authenticating with the token their
+//! platform issued them instead of a user's session or API token.
+//!
+//! Two kinds of issuer are understood:
+//!
+//! - **Kubernetes**: projected service-account tokens (`system:serviceaccount:<namespace>:<name>`),
+//!   checked against the cluster's OIDC JWKS.
+//! - **SPIFFE**: JWT-SVIDs (`spiffe://<trust domain>/<path>`), checked
+//!   against the trust domain's SPIRE bundle.
+//!
+//! [`WorkloadVerifier::recognizes`] tells workload tokens apart from the
+//! template's own JWTs by their issuer or subject, so both can arrive in the
+//! same `Authorization: Bearer` header. Verified identities are mapped to
+//! roles through `roles` bindings.
+
+use chrono::Duration;
+use jsonwebtoken::{decode, decode_header, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+
+use platform_core::clock::SharedClock;
+use platform_core::errors::{AppError, AppResult, ErrorCode};
+
+use crate::jwks::{self, KeyCache};
+pub use crate::jwks::KeySource;
+
+const SERVICE_ACCOUNT_PREFIX: &str = "system:serviceaccount:";
+
+/// Tolerated clock skew between the issuer and this service.
+const LEEWAY_SECS: i64 = 60;
+
+/// The `workload_identity` section of a template's settings.
+#[derive(Debug, Deserialize, Clone)]
+pub struct WorkloadIdentitySettings {
+    /// Trusted issuers; workload tokens are not accepted when empty.
+    #[serde(default)]
+    pub issuers: Vec<IssuerSettings>,
+    /// Roles granted to identities, in addition to being authenticated.
+    #[serde(default)]
+    pub roles: Vec<RoleBinding>,
+    /// How long fetched keys are used before they are fetched again.
+    #[serde(default = "default_jwks_refresh_secs")]
+    pub jwks_refresh_secs: u64,
+}
+
+impl Default for WorkloadIdentitySettings {
+    fn default() -> Self {
+        Self {
+            issuers: Vec::new(),
+            roles: Vec::new(),
+            jwks_refresh_secs: default_jwks_refresh_secs(),
+        }
+    }
+}
+
+fn default_jwks_refresh_secs() -> u64 {
+    300
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IssuerSettings {
+    Kubernetes {
+        /// The cluster's service-account issuer, e.g. `https://kubernetes.default.svc`.
+        issuer: String,
+        /// The audience the projected token was requested for.
+        audience: String,
+        keys: KeySource,
+    },
+    Spiffe {
+        /// e.g. `example.org`; SVIDs of other trust domains are not recognized.
+        trust_domain: String,
+        audience: String,
+        keys: KeySource,
+    },
+}
+
+/// Grants `roles` to `identity`: a subject such as
+/// `system:serviceaccount:billing:invoicer`, or a prefix ending in `*` like
+/// `spiffe://example.org/ns/billing/*`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RoleBinding {
+    pub identity: String,
+    pub roles: Vec<String>,
+}
+
+impl RoleBinding {
+    fn matches(&self, subject: &str) -> bool {
+        match self.identity.strip_suffix('*') {
+            Some(prefix) => subject.starts_with(prefix),
+            None => subject == self.identity,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkloadKind {
+    Kubernetes,
+    Spiffe,
+}
+
+/// A verified workload, added to the request extensions in place of a
+/// user's claims.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WorkloadIdentity {
+    pub kind: WorkloadKind,
+    /// The service account or SPIFFE ID.
+    pub subject: String,
+    pub roles: Vec<String>,
+}
+
+impl WorkloadIdentity {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|granted| granted == role)
+    }
+
+    /// Succeeds if the workload was granted `role`.
+    pub fn require_role(&self, role: &str) -> AppResult<()> {
+        if self.has_role(role) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden.with_code(ErrorCode::AuthWorkloadRoleMissing))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Unverified {
+    iss: Option<String>,
+    sub: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkloadClaims {
+    sub: String,
+    exp: i64,
+    nbf: Option<i64>,
+}
+
+/// Verifies workload tokens of the configured issuers.
+pub struct WorkloadVerifier {
+    issuers: Vec<Issuer>,
+    roles: Vec<RoleBinding>,
+    client: reqwest::Client,
+    clock: SharedClock,
+}
+
+struct Issuer {
+    settings: IssuerSettings,
+    keys: KeyCache,
+}
+
+impl WorkloadVerifier {
+    /// `None` when no issuer is configured.
+    pub fn from_settings(settings: &WorkloadIdentitySettings, clock: SharedClock) -> anyhow::Result<Option<Self>> {
+        if settings.issuers.is_empty() {
+            return Ok(None);
+        }
+
+        let refresh = Duration::seconds(settings.jwks_refresh_secs as i64);
+        Ok(Some(Self {
+            issuers: settings
+                .issuers
+                .iter()
+                .map(|settings| Issuer {
+                    settings: settings.clone(),
+                    keys: KeyCache::new(settings.keys().clone(), refresh),
+                })
+                .collect(),
+            roles: settings.roles.clone(),
+            client: jwks::client(settings.issuers.iter().map(IssuerSettings::keys))?,
+            clock,
+        }))
+    }
+
+    /// Whether `token` claims to come from one of the configured issuers.
+    /// Nothing is verified yet; the template's own tokens are not
+    /// recognized, so they can be handled as before.
+    pub fn recognizes(&self, token: &str) -> bool {
+        self.issuer_of(token).is_some()
+    }
+
+    pub async fn verify(&self, token: &str) -> AppResult<WorkloadIdentity> {
+        let invalid = || AppError::Unauthorized.with_code(ErrorCode::InvalidToken);
+
+        let issuer = self.issuer_of(token).ok_or_else(invalid)?;
+        let header = decode_header(token).map_err(|_| invalid())?;
+        if jwks::is_symmetric(header.alg) {
+            return Err(invalid());
+        }
+        let key = issuer
+            .keys
+            .key(&self.client, header.kid.as_deref(), self.clock.now())
+            .await?
+            .ok_or_else(invalid)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.validate_exp = false;
+        validation.required_spec_claims.clear();
+        let (kind, audience) = match &issuer.settings {
+            IssuerSettings::Kubernetes { issuer, audience, .. } => {
+                validation.set_issuer(&[issuer]);
+                (WorkloadKind::Kubernetes, audience)
+            }
+            IssuerSettings::Spiffe { audience, .. } => (WorkloadKind::Spiffe, audience),
+        };
+        validation.set_audience(&[audience]);
+        let claims = decode::<WorkloadClaims>(token, &key, &validation)
+            .map_err(|_| invalid())?
+            .claims;
+
+        // Checked here rather than by jsonwebtoken so a mock clock applies
+        let now = self.clock.now().timestamp();
+        if claims.exp + LEEWAY_SECS < now || claims.nbf.is_some_and(|nbf| nbf - LEEWAY_SECS > now) {
+            return Err(AppError::Unauthorized.with_code(ErrorCode::AuthTokenExpired));
+        }
+        if !issuer.subject_matches(&claims.sub) {
+            return Err(invalid());
+        }
+
+        let roles = self
+            .roles
+            .iter()
+            .filter(|binding| binding.matches(&claims.sub))
+            .flat_map(|binding| binding.roles.iter().cloned())
+            .collect();
+
+        Ok(WorkloadIdentity {
+            kind,
+            subject: claims.sub,
+            roles,
+        })
+    }
+
+    fn issuer_of(&self, token: &str) -> Option<&Issuer> {
+        let mut validation = Validation::default();
+        validation.insecure_disable_signature_validation();
+        validation.validate_exp = false;
+        validation.validate_aud = false;
+        validation.required_spec_claims.clear();
+        let claims = decode::<Unverified>(token, &DecodingKey::from_secret(&[]), &validation)
+            .ok()?
+            .claims;
+
+        self.issuers.iter().find(|issuer| match &issuer.settings {
+            IssuerSettings::Kubernetes { issuer, .. } => claims.iss.as_deref() == Some(issuer.as_str()),
+            IssuerSettings::Spiffe { .. } => claims.sub.as_deref().is_some_and(|sub| issuer.subject_matches(sub)),
+        })
+    }
+}
+
+impl Issuer {
+    fn subject_matches(&self, subject: &str) -> bool {
+        match &self.settings {
+            IssuerSettings::Kubernetes { .. } => subject.starts_with(SERVICE_ACCOUNT_PREFIX),
+            IssuerSettings::Spiffe { trust_domain, .. } => subject
+                .strip_prefix("spiffe://")
+                .and_then(|rest| rest.strip_prefix(trust_domain.as_str()))
+                .is_some_and(|path| path.starts_with('/')),
+        }
+    }
+}
+
+impl IssuerSettings {
+    fn keys(&self) -> &KeySource {
+        match self {
+            IssuerSettings::Kubernetes { keys, .. } | IssuerSettings::Spiffe { keys, .. } => keys,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use platform_core::clock::{Clock, MockClock};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/workload");
+    const CLUSTER: &str = "https://kubernetes.default.svc";
+
+    fn settings() -> WorkloadIdentitySettings {
+        WorkloadIdentitySettings {
+            issuers: vec![
+                IssuerSettings::Kubernetes {
+                    issuer: CLUSTER.to_string(),
+                    audience: "users".to_string(),
+                    keys: KeySource {
+                        path: Some(format!("{}/kubernetes-jwks.json", FIXTURES)),
+                        ..KeySource::default()
+                    },
+                },
+                IssuerSettings::Spiffe {
+                    trust_domain: "example.org".to_string(),
+                    audience: "users".to_string(),
+                    keys: KeySource {
+                        path: Some(format!("{}/spire-bundle.json", FIXTURES)),
+                        ..KeySource::default()
+                    },
+                },
+            ],
+            roles: vec![
+                RoleBinding {
+                    identity: "system:serviceaccount:billing:invoicer".to_string(),
+                    roles: vec!["users.read".to_string()],
+                },
+                RoleBinding {
+                    identity: "spiffe://example.org/ns/billing/*".to_string(),
+                    roles: vec!["users.read".to_string(), "users.write".to_string()],
+                },
+            ],
+            jwks_refresh_secs: 300,
+        }
+    }
+
+    fn verifier(clock: Arc<MockClock>) -> WorkloadVerifier {
+        WorkloadVerifier::from_settings(&settings(), clock).unwrap().unwrap()
+    }
+
+    fn sign(key: &str, kid: &str, claims: serde_json::Value) -> String {
+        let pem = std::fs::read(format!("{}/{}.pem", FIXTURES, key)).unwrap();
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(kid.to_string());
+        encode(&header, &claims, &EncodingKey::from_ec_pem(&pem).unwrap()).unwrap()
+    }
+
+    fn service_account(now: DateTime<Utc>, audience: &str) -> String {
+        sign(
+            "kubernetes",
+            "kube-1",
+            json!({
+                "iss": CLUSTER,
+                "sub": "system:serviceaccount:billing:invoicer",
+                "aud": [audience],
+                "exp": now.timestamp() + 600,
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn service_account_tokens_map_to_their_roles() {
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let verifier = verifier(clock.clone());
+        let token = service_account(clock.now(), "users");
+
+        assert!(verifier.recognizes(&token));
+        let identity = verifier.verify(&token).await.unwrap();
+        assert_eq!(identity.kind, WorkloadKind::Kubernetes);
+        assert_eq!(identity.subject, "system:serviceaccount:billing:invoicer");
+        assert!(identity.has_role("users.read"));
+        assert!(identity.require_role("users.write").is_err());
+    }
+
+    #[tokio::test]
+    async fn svids_of_the_trust_domain_verify_against_its_bundle() {
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let verifier = verifier(clock.clone());
+        let svid = |sub: &str| {
+            sign(
+                "spire",
+                "spire-1",
+                json!({ "sub": sub, "aud": "users", "exp": clock.now().timestamp() + 300 }),
+            )
+        };
+
+        let identity = verifier.verify(&svid("spiffe://example.org/ns/billing/sa/worker")).await.unwrap();
+        assert_eq!(identity.kind, WorkloadKind::Spiffe);
+        assert_eq!(identity.roles, vec!["users.read", "users.write"]);
+
+        assert!(!verifier.recognizes(&svid("spiffe://example.org.evil/ns/billing")));
+        assert!(!verifier.recognizes(&svid("spiffe://other.org/ns/billing")));
+    }
+
+    #[tokio::test]
+    async fn tokens_for_other_audiences_or_keys_are_rejected() {
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let verifier = verifier(clock.clone());
+
+        let other_audience = service_account(clock.now(), "payments");
+        assert_eq!(
+            verifier.verify(&other_audience).await.unwrap_err().code(),
+            ErrorCode::InvalidToken
+        );
+
+        // Signed by the SPIRE key but claiming to be the cluster's
+        let forged = sign(
+            "spire",
+            "kube-1",
+            json!({ "iss": CLUSTER, "sub": "system:serviceaccount:billing:invoicer", "aud": "users", "exp": clock.now().timestamp() + 600 }),
+        );
+        assert_eq!(verifier.verify(&forged).await.unwrap_err().code(), ErrorCode::InvalidToken);
+    }
+
+    #[tokio::test]
+    async fn expired_tokens_are_rejected() {
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let verifier = verifier(clock.clone());
+        let token = service_account(clock.now(), "users");
+
+        clock.advance(Duration::seconds(600 + LEEWAY_SECS + 1));
+        assert_eq!(verifier.verify(&token).await.unwrap_err().code(), ErrorCode::AuthTokenExpired);
+    }
+
+    #[test]
+    fn the_templates_own_tokens_are_not_recognized() {
+        let verifier = verifier(Arc::new(MockClock::new(Utc::now())));
+        let session = encode(
+            &Header::default(),
+            &json!({ "sub": "0b9f3c9e-6d1a-4f7b-9a65-2f5c1d1b8e21", "exp": 4102444800u64 }),
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+
+        assert!(!verifier.recognizes(&session));
+        assert!(!verifier.recognizes("dxp_not-a-jwt"));
+    }
+
+    #[test]
+    fn no_issuers_means_no_verifier() {
+        let settings = WorkloadIdentitySettings::default();
+        assert!(WorkloadVerifier::from_settings(&settings, Arc::new(MockClock::new(Utc::now())))
+            .unwrap()
+            .is_none());
+    }
+}
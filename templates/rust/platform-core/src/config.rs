@@ -0,0 +1,51 @@
+//! Settings loading shared by the templates.
+
+use config::builder::{ConfigBuilder, DefaultState};
+use config::{ConfigError, Environment, File};
+use serde::de::DeserializeOwned;
+
+/// Layers `config/default`, `config/<RUN_MODE>` (`development` unless set)
+/// and environment variables over `defaults`. Variables are named
+/// `<env_prefix>_<SECTION>__<KEY>`, so `ACTIX_AUTH_THROTTLE__ENABLED` sets
+/// `auth_throttle.enabled`.
+pub fn load<T: DeserializeOwned>(defaults: ConfigBuilder<DefaultState>, env_prefix: &str) -> Result<T, ConfigError> {
+    let run_mode = std::env::var("RUN_MODE").unwrap_or_else(|_| "development".into());
+
+    defaults
+        .add_source(File::with_name("config/default").required(false))
+        .add_source(File::with_name(&format!("config/{}", run_mode)).required(false))
+        .add_source(Environment::with_prefix(env_prefix).prefix_separator("_").separator("__"))
+        .build()?
+        .try_deserialize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Config;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Settings {
+        auth_throttle: Throttle,
+    }
+
+    #[derive(Deserialize)]
+    struct Throttle {
+        enabled: bool,
+        ip_limit: u64,
+    }
+
+    #[test]
+    fn variables_separate_sections_with_double_underscores() {
+        std::env::set_var("PLATFORM_CORE_TEST_AUTH_THROTTLE__ENABLED", "false");
+        let defaults = Config::builder()
+            .set_default("auth_throttle.enabled", true)
+            .and_then(|builder| builder.set_default("auth_throttle.ip_limit", 100))
+            .unwrap();
+
+        let settings: Settings = load(defaults, "PLATFORM_CORE_TEST").unwrap();
+        assert!(!settings.auth_throttle.enabled);
+        assert_eq!(settings.auth_throttle.ip_limit, 100);
+    }
+}
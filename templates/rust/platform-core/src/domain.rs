@@ -0,0 +1,226 @@
+//! Domain primitives that are validated when they are built, so a value of
+//! one of these types is always well-formed.
+//!
+//! Request bodies deserialize into them directly; a bad value fails
+//! deserialization with the same message validation used to give, e.g.
+//! `email: Invalid email format`. Values read back from the database were
+//! validated on the way in and are not checked again.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+use validator::ValidateEmail;
+
+use crate::errors::AppError;
+
+/// An email address, as `validator`'s email rule accepts it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(transparent))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema), schema(example = "alice@example.com"))]
+#[serde(try_from = "String", into = "String")]
+pub struct Email(String);
+
+impl Email {
+    pub fn parse(value: impl Into<String>) -> Result<Self, AppError> {
+        let value = value.into();
+        if !value.validate_email() {
+            return Err(AppError::ValidationError("email: Invalid email format".to_string()));
+        }
+        Ok(Self(value))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+/// A username of 3 to 50 characters, none of them control characters.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(transparent))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema), schema(example = "alice"))]
+#[serde(try_from = "String", into = "String")]
+pub struct Username(String);
+
+impl Username {
+    pub const MIN_CHARS: usize = 3;
+    pub const MAX_CHARS: usize = 50;
+
+    pub fn parse(value: impl Into<String>) -> Result<Self, AppError> {
+        let value = value.into();
+        let chars = value.chars().count();
+        if !(Self::MIN_CHARS..=Self::MAX_CHARS).contains(&chars) {
+            return Err(AppError::ValidationError(format!(
+                "username: Username must be between {} and {} characters",
+                Self::MIN_CHARS,
+                Self::MAX_CHARS
+            )));
+        }
+        if value.chars().any(char::is_control) {
+            return Err(AppError::ValidationError(
+                "username: Username must not contain control characters".to_string(),
+            ));
+        }
+        Ok(Self(value))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+/// Identifies a user; serialized as its UUID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(transparent))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(transparent)]
+pub struct UserId(Uuid);
+
+impl UserId {
+    /// A fresh random id.
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+
+    pub fn as_uuid(&self) -> Uuid {
+        self.0
+    }
+}
+
+impl Default for UserId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Uuid> for UserId {
+    fn from(id: Uuid) -> Self {
+        Self(id)
+    }
+}
+
+impl From<UserId> for Uuid {
+    fn from(id: UserId) -> Self {
+        id.0
+    }
+}
+
+impl FromStr for UserId {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(value)
+            .map(Self)
+            .map_err(|_| AppError::ValidationError("id: Invalid user id".to_string()))
+    }
+}
+
+macro_rules! string_newtype {
+    ($($name:ident),*) => {$(
+        impl TryFrom<String> for $name {
+            type Error = AppError;
+
+            fn try_from(value: String) -> Result<Self, Self::Error> {
+                Self::parse(value)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = AppError;
+
+            fn from_str(value: &str) -> Result<Self, Self::Err> {
+                Self::parse(value)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl PartialEq<str> for $name {
+            fn eq(&self, other: &str) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $name {
+            fn eq(&self, other: &&str) -> bool {
+                self.0 == *other
+            }
+        }
+    )*};
+}
+
+string_newtype!(Email, Username);
+
+macro_rules! display {
+    ($($name:ident),*) => {$(
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+    )*};
+}
+
+display!(Email, Username, UserId);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emails_are_validated_on_construction() {
+        assert_eq!(Email::parse("alice@example.com").unwrap(), "alice@example.com");
+        assert!(Email::parse("not-an-email").is_err());
+        assert!(Email::parse("").is_err());
+    }
+
+    #[test]
+    fn usernames_are_between_3_and_50_characters() {
+        assert!(Username::parse("al").is_err());
+        assert!(Username::parse("ali").is_ok());
+        assert!(Username::parse("é".repeat(50)).is_ok());
+        assert!(Username::parse("a".repeat(51)).is_err());
+    }
+
+    #[test]
+    fn usernames_may_not_contain_control_characters() {
+        assert!(Username::parse("ali\u{0}ce").is_err());
+        assert!(Username::parse("ali\nce").is_err());
+        assert!(Username::parse("alice smith").is_ok());
+    }
+
+    #[test]
+    fn deserializing_validates_with_the_field_message() {
+        let error = serde_json::from_str::<Email>("\"not-an-email\"").unwrap_err();
+        assert!(error.to_string().starts_with("Validation error: email: Invalid email format"));
+
+        let email: Email = serde_json::from_str("\"alice@example.com\"").unwrap();
+        assert_eq!(serde_json::to_string(&email).unwrap(), "\"alice@example.com\"");
+    }
+
+    #[test]
+    fn user_ids_round_trip_as_uuids() {
+        let id = UserId::new();
+        assert_eq!(id.to_string().parse::<UserId>().unwrap(), id);
+        assert_eq!(serde_json::to_string(&id).unwrap(), format!("\"{}\"", id.as_uuid()));
+        assert!("not-a-uuid".parse::<UserId>().is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use serde::{Serialize, Serializer};
+
+macro_rules! error_codes {
+    ($($variant:ident => $code:literal: $description:literal,)*) => {
+        /// Stable, machine-readable error identifiers. Clients branch on
+        /// these rather than on messages, so they are never renamed or
+        /// reused; new ones may be added.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum ErrorCode {
+            $(
+                #[doc = $description]
+                $variant,
+            )*
+        }
+
+        impl ErrorCode {
+            pub const ALL: &'static [ErrorCode] = &[$(ErrorCode::$variant),*];
+
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(ErrorCode::$variant => $code,)*
+                }
+            }
+
+            pub fn description(self) -> &'static str {
+                match self {
+                    $(ErrorCode::$variant => $description,)*
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    // Generic codes, one per `AppError` variant
+    Internal => "INTERNAL": "An unexpected server error.",
+    BadRequest => "BAD_REQUEST": "The request is malformed.",
+    Unauthenticated => "UNAUTHENTICATED": "Credentials are missing or invalid.",
+    PermissionDenied => "PERMISSION_DENIED": "The caller may not perform this operation.",
+    NotFound => "NOT_FOUND": "The requested resource does not exist.",
+    Conflict => "CONFLICT": "The request conflicts with the current state of a resource.",
+    Unprocessable => "UNPROCESSABLE": "The request is well-formed but cannot be processed.",
+    RateLimited => "RATE_LIMITED": "Too many requests; retry later.",
+    PayloadTooLarge => "PAYLOAD_TOO_LARGE": "The request body exceeds the size limit.",
+    DatabaseError => "DATABASE_ERROR": "The database failed to complete the operation.",
+    ValidationFailed => "VALIDATION_FAILED": "One or more fields are invalid.",
+    InvalidToken => "INVALID_TOKEN": "The token is malformed or its signature is invalid.",
+    StorageError => "STORAGE_ERROR": "File storage failed to complete the operation.",
+    ServiceUnavailable => "SERVICE_UNAVAILABLE": "The service is temporarily unavailable; retry later.",
+    ConcurrentModification => "CONCURRENT_MODIFICATION": "The resource changed since it was read; re-read it and retry.",
+
+    // Specific codes
+    AuthInvalidCredentials => "AUTH_INVALID_CREDENTIALS": "The email or password is incorrect.",
+    AuthAccountDisabled => "AUTH_ACCOUNT_DISABLED": "The account has been deactivated.",
+    AuthTokenExpired => "AUTH_TOKEN_EXPIRED": "The token has expired; refresh it or log in again.",
+    AuthRefreshTokenInvalid => "AUTH_REFRESH_TOKEN_INVALID": "The refresh token is unknown, expired or revoked.",
+    AuthRefreshTokenReused => "AUTH_REFRESH_TOKEN_REUSED": "The refresh token was already used; its session has been revoked.",
+    AuthTooManyAttempts => "AUTH_TOO_MANY_ATTEMPTS": "Too many authentication attempts; retry later.",
+    AuthInsufficientScope => "AUTH_INSUFFICIENT_SCOPE": "The API token does not grant the required scope.",
+    AuthSignatureInvalid => "AUTH_SIGNATURE_INVALID": "The request signature does not match or its timestamp is outside the allowed window.",
+    StepUpRequired => "STEP_UP_REQUIRED": "The operation needs a recent re-authentication; get an elevated token from /auth/step-up and retry with it.",
+    AuthWorkloadRoleMissing => "AUTH_WORKLOAD_ROLE_MISSING": "The calling workload is not bound to the required role.",
+    AuthRoleMissing => "AUTH_ROLE_MISSING": "The caller's roles don't grant the required role or permission.",
+    CsrfOriginMismatch => "CSRF_ORIGIN_MISMATCH": "The request's origin may not make cookie-authenticated changes.",
+    UserNotFound => "USER_NOT_FOUND": "The user does not exist.",
+    UserEmailTaken => "USER_EMAIL_TAKEN": "Another user already has this email address.",
+    UserUsernameTaken => "USER_USERNAME_TAKEN": "Another user already has this username.",
+    GuestUpgradeUnavailable => "GUEST_UPGRADE_UNAVAILABLE": "Only an unexpired guest can be upgraded to an account.",
+    PhoneNumberTaken => "PHONE_NUMBER_TAKEN": "Another user already has this phone number.",
+    PhoneNumberMissing => "PHONE_NUMBER_MISSING": "The user has no phone number to verify.",
+    PhoneOtpInvalid => "PHONE_OTP_INVALID": "The code is wrong, expired or already used.",
+    PhoneOtpRateLimited => "PHONE_OTP_RATE_LIMITED": "Too many codes were sent to this phone number; retry later.",
+    MagicLinkInvalid => "MAGIC_LINK_INVALID": "The sign-in link is invalid, expired or already used.",
+    MagicLinkDeviceMismatch => "MAGIC_LINK_DEVICE_MISMATCH": "The sign-in link must be opened on the device that requested it.",
+    MagicLinkRateLimited => "MAGIC_LINK_RATE_LIMITED": "Too many sign-in links were sent to this email address; retry later.",
+    EmailVerificationInvalid => "EMAIL_VERIFICATION_INVALID": "The verification link is invalid, expired or for a previous email address.",
+    EmailVerificationRateLimited => "EMAIL_VERIFICATION_RATE_LIMITED": "A verification email was sent recently; retry later.",
+    PasswordResetInvalid => "PASSWORD_RESET_INVALID": "The password reset link is invalid, expired or already used.",
+    PasswordResetRateLimited => "PASSWORD_RESET_RATE_LIMITED": "Too many password reset emails were sent to this email address; retry later.",
+    PasskeyNotFound => "PASSKEY_NOT_FOUND": "The passkey does not exist.",
+    PasskeyAlreadyRegistered => "PASSKEY_ALREADY_REGISTERED": "The authenticator's passkey is already registered.",
+    PasskeyCeremonyExpired => "PASSKEY_CEREMONY_EXPIRED": "The passkey challenge is unknown, expired or already used; start again.",
+    PasskeyVerificationFailed => "PASSKEY_VERIFICATION_FAILED": "The authenticator's response could not be verified.",
+    PushDeviceNotFound => "PUSH_DEVICE_NOT_FOUND": "The push device does not exist.",
+    PushPlatformUnsupported => "PUSH_PLATFORM_UNSUPPORTED": "Push notifications are not configured for this platform.",
+    PushDeliveryFailed => "PUSH_DELIVERY_FAILED": "The push provider could not deliver the notification; it will be retried.",
+    SmsDeliveryFailed => "SMS_DELIVERY_FAILED": "The SMS provider could not deliver the message; retry later.",
+    EmailDeliveryFailed => "EMAIL_DELIVERY_FAILED": "The email provider could not deliver the message; retry later.",
+    OperationNotFound => "OPERATION_NOT_FOUND": "The operation does not exist.",
+    ApiTokenNotFound => "API_TOKEN_NOT_FOUND": "The API token does not exist.",
+    FileNotFound => "FILE_NOT_FOUND": "The file does not exist.",
+    FileTooLarge => "FILE_TOO_LARGE": "The file exceeds the upload size limit.",
+    MessageTooLarge => "MESSAGE_TOO_LARGE": "A message exceeds the size limit after decompression.",
+    PageTokenInvalid => "PAGE_TOKEN_INVALID": "The page token is malformed or belongs to a different query.",
+    PageTokenExpired => "PAGE_TOKEN_EXPIRED": "The page token is too old; list again from the first page.",
+    EgressDenied => "EGRESS_DENIED": "The URL's scheme, port or destination is not allowed for outbound requests.",
+    MaintenanceMode => "MAINTENANCE_MODE": "The service is down for maintenance; retry later.",
+    Overloaded => "OVERLOADED": "The service is serving as many requests as it can; retry shortly.",
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// One entry of the published error catalog.
+#[derive(Debug, Serialize)]
+pub struct CatalogEntry {
+    pub code: ErrorCode,
+    pub description: &'static str,
+}
+
+/// Every error code with its description, for clients and documentation.
+pub fn catalog() -> Vec<CatalogEntry> {
+    ErrorCode::ALL
+        .iter()
+        .map(|&code| CatalogEntry {
+            code,
+            description: code.description(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn codes_are_unique_screaming_snake_case() {
+        let mut seen = HashSet::new();
+        for code in ErrorCode::ALL {
+            let value = code.as_str();
+            assert!(seen.insert(value), "duplicate code {}", value);
+            assert!(value.chars().all(|c| c.is_ascii_uppercase() || c == '_'), "{}", value);
+        }
+    }
+
+    #[test]
+    fn serializes_as_string() {
+        let json = serde_json::to_value(&catalog()[0]).unwrap();
+
+        assert_eq!(json["code"], "INTERNAL");
+        assert_eq!(json["description"], "An unexpected server error.");
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt::Display;
+
+use super::AppError;
+
+/// What the server was doing when an error occurred. Context is logged with
+/// the error's source chain but never sent to clients.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub operation: Option<&'static str>,
+    pub entity_id: Option<String>,
+    pub request_id: Option<String>,
+}
+
+impl ErrorContext {
+    /// Fills the fields `self` lacks from `other`. Context added closest to
+    /// the failure wins.
+    pub(crate) fn merge(&mut self, other: ErrorContext) {
+        self.operation = self.operation.or(other.operation);
+        self.entity_id = self.entity_id.take().or(other.entity_id);
+        self.request_id = self.request_id.take().or(other.request_id);
+    }
+}
+
+/// Adds [`ErrorContext`] to fallible results:
+///
+/// ```ignore
+/// let user = query.fetch_one(&db).await.entity_context("load user", user_id)?;
+/// ```
+pub trait ResultExt<T> {
+    fn context(self, operation: &'static str) -> Result<T, AppError>;
+
+    fn entity_context(self, operation: &'static str, entity_id: impl Display) -> Result<T, AppError>;
+}
+
+impl<T, E: Into<AppError>> ResultExt<T> for Result<T, E> {
+    fn context(self, operation: &'static str) -> Result<T, AppError> {
+        self.map_err(|e| {
+            e.into().with_context(ErrorContext {
+                operation: Some(operation),
+                ..ErrorContext::default()
+            })
+        })
+    }
+
+    fn entity_context(self, operation: &'static str, entity_id: impl Display) -> Result<T, AppError> {
+        self.map_err(|e| {
+            e.into().with_context(ErrorContext {
+                operation: Some(operation),
+                entity_id: Some(entity_id.to_string()),
+                ..ErrorContext::default()
+            })
+        })
+    }
+}
@@ -0,0 +1,94 @@
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+
+use super::AppError;
+
+/// `ErrorInfo.domain` attached to every status.
+pub const ERROR_DOMAIN: &str = "devxplatform";
+
+impl From<AppError> for Status {
+    /// Logs the error (see [`AppError::log`]) and converts it into a status
+    /// carrying only the sanitized message.
+    fn from(error: AppError) -> Self {
+        error.log(None);
+        let reason = error.code();
+        let retry = error.retry_hint();
+        let error = error.into_inner();
+        let (code, message) = match error {
+            AppError::InternalServerError => (Code::Internal, error.to_string()),
+            AppError::BadRequest(msg) => (Code::InvalidArgument, msg),
+            AppError::Unauthorized => (Code::Unauthenticated, error.to_string()),
+            AppError::Forbidden => (Code::PermissionDenied, error.to_string()),
+            AppError::NotFound(msg) => (Code::NotFound, msg),
+            AppError::Conflict(msg) => (Code::AlreadyExists, msg),
+            AppError::UnprocessableEntity(msg) => (Code::FailedPrecondition, msg),
+            AppError::TooManyRequests(msg) => (Code::ResourceExhausted, msg),
+            AppError::PayloadTooLarge(msg) => (Code::ResourceExhausted, msg),
+            AppError::Throttled { message, .. } => (Code::ResourceExhausted, message),
+            AppError::Unavailable { message, .. } => (Code::Unavailable, message),
+            AppError::ConcurrentModification { message, .. } => (Code::Aborted, message),
+            #[cfg(feature = "sqlx")]
+            AppError::DatabaseError(_) => (Code::Internal, "Database error".to_string()),
+            AppError::ValidationError(msg) => (Code::InvalidArgument, msg),
+            AppError::JwtError(_) => (Code::Unauthenticated, "Invalid token".to_string()),
+            AppError::HashError(_) => (Code::Internal, "Authentication error".to_string()),
+            AppError::IoError(_) => (Code::Internal, "Storage error".to_string()),
+            AppError::Annotated { .. } => unreachable!("into_inner removes annotations"),
+        };
+
+        let mut details = ErrorDetails::with_error_info(reason.as_str(), ERROR_DOMAIN, []);
+        if let Some(hint) = retry {
+            details.set_retry_info(Some(hint.delay()));
+        }
+        Status::with_error_details(code, message, details)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{ErrorCode, RetryHint};
+    use std::time::Duration;
+
+    #[test]
+    fn status_carries_stable_code() {
+        let status = Status::from(AppError::Conflict("Email already exists".to_string()));
+
+        assert_eq!(status.code(), Code::AlreadyExists);
+        assert_eq!(status.message(), "Email already exists");
+        let info = status.get_details_error_info().expect("error info");
+        assert_eq!(info.reason, "CONFLICT");
+        assert_eq!(info.domain, ERROR_DOMAIN);
+    }
+
+    #[test]
+    fn specific_code_becomes_reason() {
+        let error = AppError::NotFound("User not found".to_string()).with_code(ErrorCode::UserNotFound);
+        let status = Status::from(error);
+
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(status.get_details_error_info().unwrap().reason, "USER_NOT_FOUND");
+    }
+
+    #[test]
+    fn retryable_errors_carry_retry_info() {
+        let error = AppError::Unavailable {
+            message: "Down for maintenance".to_string(),
+            retry: RetryHint::after(Duration::from_secs(30)),
+        };
+        let status = Status::from(error);
+
+        assert_eq!(status.code(), Code::Unavailable);
+        let retry_info = status.get_details_retry_info().expect("retry info");
+        assert_eq!(retry_info.retry_delay, Some(Duration::from_secs(30)));
+    }
+
+    #[cfg(feature = "sqlx")]
+    #[test]
+    fn internal_errors_hide_their_source() {
+        let status = Status::from(AppError::DatabaseError(sqlx::Error::RowNotFound));
+
+        assert_eq!(status.code(), Code::Internal);
+        assert_eq!(status.message(), "Database error");
+    }
+}
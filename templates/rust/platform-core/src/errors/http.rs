@@ -0,0 +1,112 @@
+use actix_web::{error::ResponseError, http::header, http::StatusCode, HttpResponse};
+use serde::Serialize;
+
+use super::{AppError, ErrorCode};
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ErrorResponse {
+    pub code: u16,
+    pub error: String,
+    /// One of the codes listed by the error catalog.
+    #[cfg_attr(feature = "openapi", schema(value_type = String, example = "NOT_FOUND"))]
+    pub error_code: ErrorCode,
+    pub message: String,
+    /// Milliseconds to wait before retrying, when the error is retryable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+}
+
+impl ResponseError for AppError {
+    fn error_response(&self) -> HttpResponse {
+        let status_code = self.status_code();
+        let retry_after = self.retry_hint().map(|hint| hint.delay());
+        let error_response = ErrorResponse {
+            code: status_code.as_u16(),
+            error: status_code.to_string(),
+            error_code: self.code(),
+            message: self.to_string(),
+            retry_after_ms: retry_after.map(|delay| delay.as_millis() as u64),
+        };
+
+        let mut response = HttpResponse::build(status_code);
+        if let Some(delay) = retry_after {
+            // Retry-After only has whole seconds; round up so clients never
+            // retry early
+            let secs = delay.as_secs() + u64::from(delay.subsec_nanos() > 0);
+            response.insert_header((header::RETRY_AFTER, secs));
+        }
+        response.json(error_response)
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Throttled { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::ConcurrentModification { .. } => StatusCode::CONFLICT,
+            #[cfg(feature = "sqlx")]
+            AppError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            AppError::JwtError(_) => StatusCode::UNAUTHORIZED,
+            AppError::HashError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::IoError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Annotated { error, .. } => error.status_code(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::RetryHint;
+    use actix_web::body::to_bytes;
+    use std::time::Duration;
+
+    #[actix_web::test]
+    async fn renders_json_body_with_stable_code() {
+        let response = AppError::NotFound("User not found".to_string()).error_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], 404);
+        assert_eq!(body["error_code"], "NOT_FOUND");
+        assert_eq!(body["message"], "Not Found: User not found");
+    }
+
+    #[actix_web::test]
+    async fn retryable_errors_set_retry_after() {
+        let error = AppError::Throttled {
+            message: "Slow down".to_string(),
+            retry: RetryHint::after(Duration::from_millis(1500)),
+        };
+        let response = error.error_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "2");
+
+        let body = to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["retry_after_ms"], 1500);
+    }
+
+    #[actix_web::test]
+    async fn specific_code_keeps_variant_status() {
+        let error = AppError::Conflict("Email already exists".to_string()).with_code(ErrorCode::UserEmailTaken);
+        let response = error.error_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let body = to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error_code"], "USER_EMAIL_TAKEN");
+        assert_eq!(body["message"], "Conflict: Email already exists");
+    }
+}
@@ -0,0 +1,324 @@
+//! Error type shared by the actix and tonic templates.
+//!
+//! Every error carries a stable [`ErrorCode`] that both protocols expose
+//! (the `error_code` field of REST bodies and the `ErrorInfo` reason of gRPC
+//! statuses), so clients can handle errors the same way over either API.
+//! Variants have a generic code; call sites attach a specific one with
+//! [`AppError::with_code`].
+//!
+//! Client-facing messages never include internal details. Operation, entity
+//! and request ids attached through [`ResultExt`] are logged together with
+//! the full source chain by [`AppError::log`] instead.
+
+use std::error::Error as StdError;
+use thiserror::Error;
+
+mod code;
+mod context;
+mod retry;
+#[cfg(feature = "actix")]
+mod http;
+#[cfg(feature = "tonic")]
+mod grpc;
+
+pub use code::{catalog, CatalogEntry, ErrorCode};
+pub use context::{ErrorContext, ResultExt};
+pub use retry::{Jitter, RetryHint};
+#[cfg(feature = "actix")]
+pub use http::ErrorResponse;
+#[cfg(feature = "tonic")]
+pub use grpc::ERROR_DOMAIN;
+
+#[derive(Error, Debug)]
+pub enum AppError {
+    #[error("Internal Server Error")]
+    InternalServerError,
+
+    #[error("Bad Request: {0}")]
+    BadRequest(String),
+
+    #[error("Unauthorized")]
+    Unauthorized,
+
+    #[error("Forbidden")]
+    Forbidden,
+
+    #[error("Not Found: {0}")]
+    NotFound(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Unprocessable Entity: {0}")]
+    UnprocessableEntity(String),
+
+    #[error("Too Many Requests: {0}")]
+    TooManyRequests(String),
+
+    #[error("Payload Too Large: {0}")]
+    PayloadTooLarge(String),
+
+    #[error("Too Many Requests: {message}")]
+    Throttled { message: String, retry: RetryHint },
+
+    #[error("Service Unavailable: {message}")]
+    Unavailable { message: String, retry: RetryHint },
+
+    /// An optimistic-concurrency check failed; re-read and retry.
+    #[error("Conflict: {message}")]
+    ConcurrentModification { message: String, retry: RetryHint },
+
+    #[cfg(feature = "sqlx")]
+    #[error("Database error")]
+    DatabaseError(#[from] sqlx::Error),
+
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+
+    #[error("JWT error")]
+    JwtError(#[from] jsonwebtoken::errors::Error),
+
+    #[error("Hash error")]
+    HashError(#[from] bcrypt::BcryptError),
+
+    #[error("IO error")]
+    IoError(#[from] std::io::Error),
+
+    /// Another error with a more specific code or server-side context.
+    #[error("{error}")]
+    Annotated {
+        code: Option<ErrorCode>,
+        context: Box<ErrorContext>,
+        error: Box<AppError>,
+    },
+}
+
+impl AppError {
+    /// Replaces the error's code, keeping its status and message.
+    pub fn with_code(self, code: ErrorCode) -> Self {
+        match self {
+            AppError::Annotated { context, error, .. } => AppError::Annotated {
+                code: Some(code),
+                context,
+                error,
+            },
+            error => AppError::Annotated {
+                code: Some(code),
+                context: Box::default(),
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// Adds server-side context; fields already set are kept.
+    pub fn with_context(self, added: ErrorContext) -> Self {
+        match self {
+            AppError::Annotated {
+                code,
+                mut context,
+                error,
+            } => {
+                context.merge(added);
+                AppError::Annotated { code, context, error }
+            }
+            error => AppError::Annotated {
+                code: None,
+                context: Box::new(added),
+                error: Box::new(error),
+            },
+        }
+    }
+
+    pub fn with_request_id(self, request_id: impl Into<String>) -> Self {
+        self.with_context(ErrorContext {
+            request_id: Some(request_id.into()),
+            ..ErrorContext::default()
+        })
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            AppError::InternalServerError => ErrorCode::Internal,
+            AppError::BadRequest(_) => ErrorCode::BadRequest,
+            AppError::Unauthorized => ErrorCode::Unauthenticated,
+            AppError::Forbidden => ErrorCode::PermissionDenied,
+            AppError::NotFound(_) => ErrorCode::NotFound,
+            AppError::Conflict(_) => ErrorCode::Conflict,
+            AppError::UnprocessableEntity(_) => ErrorCode::Unprocessable,
+            AppError::TooManyRequests(_) => ErrorCode::RateLimited,
+            AppError::PayloadTooLarge(_) => ErrorCode::PayloadTooLarge,
+            AppError::Throttled { .. } => ErrorCode::RateLimited,
+            AppError::Unavailable { .. } => ErrorCode::ServiceUnavailable,
+            AppError::ConcurrentModification { .. } => ErrorCode::ConcurrentModification,
+            #[cfg(feature = "sqlx")]
+            AppError::DatabaseError(_) => ErrorCode::DatabaseError,
+            AppError::ValidationError(_) => ErrorCode::ValidationFailed,
+            AppError::JwtError(e) => match e.kind() {
+                jsonwebtoken::errors::ErrorKind::ExpiredSignature => ErrorCode::AuthTokenExpired,
+                _ => ErrorCode::InvalidToken,
+            },
+            AppError::HashError(_) => ErrorCode::Internal,
+            AppError::IoError(_) => ErrorCode::StorageError,
+            AppError::Annotated { code, error, .. } => code.unwrap_or_else(|| error.code()),
+        }
+    }
+
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            AppError::Annotated { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// The underlying variant, with any code or context removed.
+    pub fn into_inner(self) -> Self {
+        match self {
+            AppError::Annotated { error, .. } => *error,
+            error => error,
+        }
+    }
+
+    fn inner(&self) -> &AppError {
+        match self {
+            AppError::Annotated { error, .. } => error,
+            error => error,
+        }
+    }
+
+    /// When the request may be retried, for errors that say so.
+    pub fn retry_hint(&self) -> Option<RetryHint> {
+        match self.inner() {
+            AppError::Throttled { retry, .. }
+            | AppError::Unavailable { retry, .. }
+            | AppError::ConcurrentModification { retry, .. } => Some(*retry),
+            _ => None,
+        }
+    }
+
+    /// Failures of the server rather than the request.
+    pub fn is_server_error(&self) -> bool {
+        match self.inner() {
+            AppError::InternalServerError | AppError::HashError(_) | AppError::IoError(_) => true,
+            #[cfg(feature = "sqlx")]
+            AppError::DatabaseError(_) => true,
+            _ => false,
+        }
+    }
+
+    /// A deadlock or serialization failure, after which Postgres rolled the
+    /// transaction back; running it again may well succeed.
+    pub fn is_transaction_conflict(&self) -> bool {
+        #[cfg(feature = "sqlx")]
+        if let AppError::DatabaseError(sqlx::Error::Database(e)) = self.inner() {
+            return matches!(e.code().as_deref(), Some("40P01") | Some("40001"));
+        }
+        false
+    }
+
+    /// The error and all of its sources, outermost first. Sources already
+    /// included in the previous message are skipped.
+    pub fn source_chain(&self) -> String {
+        let mut chain = self.inner().to_string();
+        let mut previous = chain.clone();
+        let mut source = self.inner().source();
+        while let Some(error) = source {
+            let message = error.to_string();
+            if !previous.ends_with(&message) {
+                chain.push_str(": ");
+                chain.push_str(&message);
+            }
+            previous = message;
+            source = error.source();
+        }
+        chain
+    }
+
+    /// Logs the error with its context and source chain: server errors at
+    /// error level, client errors at debug. `request_id` takes precedence
+    /// over one recorded in the context.
+    pub fn log(&self, request_id: Option<&str>) {
+        let context = self.context();
+        let operation = context.and_then(|c| c.operation);
+        let entity_id = context.and_then(|c| c.entity_id.as_deref());
+        let request_id = request_id.or_else(|| context.and_then(|c| c.request_id.as_deref()));
+
+        if self.is_server_error() {
+            tracing::error!(
+                error_code = %self.code(),
+                operation,
+                entity_id,
+                request_id,
+                "{}",
+                self.source_chain()
+            );
+        } else {
+            tracing::debug!(
+                error_code = %self.code(),
+                operation,
+                entity_id,
+                request_id,
+                "{}",
+                self.source_chain()
+            );
+        }
+    }
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "sqlx")]
+    fn database_failure() -> Result<(), sqlx::Error> {
+        Err(sqlx::Error::Io(std::io::Error::other("connection reset")))
+    }
+
+    #[cfg(feature = "sqlx")]
+    #[test]
+    fn context_keeps_message_and_code() {
+        let error = database_failure().entity_context("load user", 42).unwrap_err();
+
+        assert_eq!(error.to_string(), "Database error");
+        assert_eq!(error.code(), ErrorCode::DatabaseError);
+        assert!(error.is_server_error());
+        assert!(!error.is_transaction_conflict());
+
+        let context = error.context().unwrap();
+        assert_eq!(context.operation, Some("load user"));
+        assert_eq!(context.entity_id.as_deref(), Some("42"));
+    }
+
+    #[cfg(feature = "sqlx")]
+    #[test]
+    fn source_chain_includes_causes() {
+        let error = database_failure().context("load user").unwrap_err();
+
+        assert_eq!(
+            error.source_chain(),
+            "Database error: error communicating with database: connection reset"
+        );
+    }
+
+    #[test]
+    fn inner_context_and_code_survive_wrapping() {
+        let error = AppError::NotFound("User not found".to_string())
+            .with_code(ErrorCode::UserNotFound)
+            .with_context(ErrorContext {
+                operation: Some("load user"),
+                ..ErrorContext::default()
+            })
+            .with_context(ErrorContext {
+                operation: Some("update user"),
+                request_id: Some("req-1".to_string()),
+                ..ErrorContext::default()
+            });
+
+        assert_eq!(error.code(), ErrorCode::UserNotFound);
+        let context = error.context().unwrap();
+        assert_eq!(context.operation, Some("load user"));
+        assert_eq!(context.request_id.as_deref(), Some("req-1"));
+        assert!(!error.is_server_error());
+    }
+}
@@ -0,0 +1,63 @@
+use rand::Rng;
+use std::time::Duration;
+
+/// How a retry delay is spread so clients rejected together don't all come
+/// back at the same moment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Jitter {
+    /// Retry after exactly the hinted delay.
+    #[default]
+    None,
+    /// Anywhere between zero and the hinted delay.
+    Full,
+    /// Between half the hinted delay and the full delay.
+    Equal,
+}
+
+/// When a failed request may be retried. Rendered as `Retry-After` on REST
+/// responses and `google.rpc.RetryInfo` on gRPC statuses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryHint {
+    pub after: Duration,
+    pub jitter: Jitter,
+}
+
+impl RetryHint {
+    pub fn after(after: Duration) -> Self {
+        Self {
+            after,
+            jitter: Jitter::None,
+        }
+    }
+
+    pub fn with_jitter(self, jitter: Jitter) -> Self {
+        Self { jitter, ..self }
+    }
+
+    /// The delay to advertise, with jitter applied.
+    pub fn delay(&self) -> Duration {
+        let mut rng = rand::thread_rng();
+        match self.jitter {
+            Jitter::None => self.after,
+            Jitter::Full => self.after.mul_f64(rng.gen_range(0.0..=1.0)),
+            Jitter::Equal => self.after.mul_f64(rng.gen_range(0.5..=1.0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let after = Duration::from_secs(10);
+        for _ in 0..100 {
+            assert_eq!(RetryHint::after(after).delay(), after);
+            assert!(RetryHint::after(after).with_jitter(Jitter::Full).delay() <= after);
+
+            let equal = RetryHint::after(after).with_jitter(Jitter::Equal).delay();
+            assert!(equal >= after / 2 && equal <= after);
+        }
+    }
+}
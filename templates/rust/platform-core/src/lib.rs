@@ -0,0 +1,10 @@
+//! What every Rust template builds on: the error type, settings loading,
+//! the clock, lifecycle hooks, rate limiting and validated domain
+//! primitives.
+
+pub mod clock;
+pub mod config;
+pub mod domain;
+pub mod errors;
+pub mod lifecycle;
+pub mod rate_limit;
@@ -0,0 +1,369 @@
+//! Startup and shutdown hooks.
+//!
+//! Modules register hooks for three phases:
+//!
+//! - `start`: before the server accepts connections. A failing or
+//!   timed-out hook aborts startup.
+//! - `ready`: once the server is accepting connections. Failures are
+//!   logged.
+//! - `shutdown`: after the server has stopped accepting connections and
+//!   finished in-flight requests. Failures are logged
+//!   and the remaining hooks still run, as long as
+//!   `lifecycle.shutdown_timeout_secs` allows.
+//!
+//! Within a phase hooks run one at a time, lowest [`Hook::order`] first and
+//! in registration order among equals.
+//!
+//! Servers stop in steps on [`shutdown_signal`]: [`Draining::drain`] first
+//! fails readiness probes, so load balancers and Kubernetes endpoints stop
+//! sending new requests, then closes the listeners. The servers finish
+//! in-flight requests before the shutdown hooks run.
+
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// The `lifecycle` section of a template's settings.
+#[derive(Debug, Deserialize, Clone)]
+pub struct LifecycleSettings {
+    /// Time a hook gets unless it sets its own.
+    pub hook_timeout_secs: u64,
+    /// Time all shutdown hooks get together.
+    pub shutdown_timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Start,
+    Ready,
+    Shutdown,
+}
+
+type Run = Box<dyn FnOnce() -> BoxFuture<'static, anyhow::Result<()>> + Send>;
+
+/// A named piece of work run once in one phase.
+pub struct Hook {
+    name: String,
+    order: i32,
+    timeout: Option<Duration>,
+    run: Run,
+}
+
+impl Hook {
+    pub fn new<F, Fut>(name: impl Into<String>, run: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            order: 0,
+            timeout: None,
+            run: Box::new(move || Box::pin(run())),
+        }
+    }
+
+    /// Position within the phase; lower runs first. Defaults to 0.
+    pub fn order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Overrides `lifecycle.hook_timeout_secs` for this hook.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+pub struct Lifecycle {
+    settings: LifecycleSettings,
+    hooks: Vec<(Phase, Hook)>,
+}
+
+impl Lifecycle {
+    pub fn new(settings: &LifecycleSettings) -> Self {
+        Self {
+            settings: settings.clone(),
+            hooks: Vec::new(),
+        }
+    }
+
+    pub fn on_start(&mut self, hook: Hook) -> &mut Self {
+        self.register(Phase::Start, hook)
+    }
+
+    pub fn on_ready(&mut self, hook: Hook) -> &mut Self {
+        self.register(Phase::Ready, hook)
+    }
+
+    pub fn on_shutdown(&mut self, hook: Hook) -> &mut Self {
+        self.register(Phase::Shutdown, hook)
+    }
+
+    pub fn register(&mut self, phase: Phase, hook: Hook) -> &mut Self {
+        self.hooks.push((phase, hook));
+        self
+    }
+
+    /// Runs the start hooks, stopping at the first that fails.
+    pub async fn start(&mut self) -> anyhow::Result<()> {
+        for hook in self.take(Phase::Start) {
+            let name = hook.name.clone();
+            self.run(Phase::Start, hook)
+                .await
+                .map_err(|e| e.context(format!("start hook {} failed", name)))?;
+        }
+        Ok(())
+    }
+
+    pub async fn ready(&mut self) {
+        for hook in self.take(Phase::Ready) {
+            let name = hook.name.clone();
+            if let Err(e) = self.run(Phase::Ready, hook).await {
+                warn!("Ready hook {} failed: {:#}", name, e);
+            }
+        }
+    }
+
+    /// Runs the shutdown hooks; those not started within
+    /// `lifecycle.shutdown_timeout_secs` are skipped.
+    pub async fn shutdown(&mut self) {
+        let deadline = Instant::now() + Duration::from_secs(self.settings.shutdown_timeout_secs);
+
+        for hook in self.take(Phase::Shutdown) {
+            let name = hook.name.clone();
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero()) else {
+                warn!("Skipping shutdown hook {}: shutdown timeout reached", name);
+                continue;
+            };
+            let hook = Hook {
+                timeout: Some(self.timeout(&hook).min(remaining)),
+                ..hook
+            };
+            if let Err(e) = self.run(Phase::Shutdown, hook).await {
+                warn!("Shutdown hook {} failed: {:#}", name, e);
+            }
+        }
+    }
+
+    /// The phase's hooks in the order they run; each runs only once.
+    fn take(&mut self, phase: Phase) -> Vec<Hook> {
+        let (mut hooks, rest): (Vec<_>, Vec<_>) = std::mem::take(&mut self.hooks)
+            .into_iter()
+            .partition(|(hook_phase, _)| *hook_phase == phase);
+        self.hooks = rest;
+
+        hooks.sort_by_key(|(_, hook)| hook.order);
+        hooks.into_iter().map(|(_, hook)| hook).collect()
+    }
+
+    fn timeout(&self, hook: &Hook) -> Duration {
+        hook.timeout
+            .unwrap_or_else(|| Duration::from_secs(self.settings.hook_timeout_secs))
+    }
+
+    async fn run(&self, phase: Phase, hook: Hook) -> anyhow::Result<()> {
+        let timeout = self.timeout(&hook);
+        let started = Instant::now();
+
+        match tokio::time::timeout(timeout, (hook.run)()).await {
+            Ok(result) => {
+                result?;
+                info!("{:?} hook {} finished in {:?}", phase, hook.name, started.elapsed());
+                Ok(())
+            }
+            Err(_) => Err(anyhow::anyhow!("timed out after {:?}", timeout)),
+        }
+    }
+}
+
+/// Ctrl-C, or SIGTERM from the orchestrator.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("installing the SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum DrainState {
+    Serving,
+    Draining,
+    Closed,
+}
+
+/// How far a server has got in shutting down, shared by its readiness
+/// probes and accept loops.
+#[derive(Debug, Clone)]
+pub struct Draining(Arc<watch::Sender<DrainState>>);
+
+impl Default for Draining {
+    fn default() -> Self {
+        Self(Arc::new(watch::channel(DrainState::Serving).0))
+    }
+}
+
+impl Draining {
+    /// Fails readiness from now on, waits `delay` for load balancers to
+    /// notice, then closes the listeners.
+    pub async fn drain(&self, delay: Duration) {
+        self.start();
+        info!("Draining: not ready, closing listeners in {:?}", delay);
+        tokio::time::sleep(delay).await;
+        self.close();
+    }
+
+    /// Fails readiness probes; the listeners still accept connections.
+    pub fn start(&self) {
+        self.advance(DrainState::Draining);
+    }
+
+    /// Stops the listeners accepting connections.
+    pub fn close(&self) {
+        self.advance(DrainState::Closed);
+    }
+
+    /// Whether readiness probes should fail.
+    pub fn is_draining(&self) -> bool {
+        *self.0.borrow() >= DrainState::Draining
+    }
+
+    /// Resolves once the listeners are to stop accepting connections.
+    pub async fn closed(&self) {
+        let mut state = self.0.subscribe();
+        let _ = state.wait_for(|state| *state == DrainState::Closed).await;
+    }
+
+    fn advance(&self, to: DrainState) {
+        self.0.send_if_modified(|state| {
+            let advanced = *state < to;
+            *state = (*state).max(to);
+            advanced
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    fn test_lifecycle() -> Lifecycle {
+        Lifecycle::new(&LifecycleSettings {
+            hook_timeout_secs: 10,
+            shutdown_timeout_secs: 1,
+        })
+    }
+
+    fn record(log: &Log, name: &str) -> Hook {
+        let (log, entry) = (log.clone(), name.to_string());
+        Hook::new(name, move || async move {
+            log.lock().unwrap().push(entry);
+            Ok(())
+        })
+    }
+
+    fn failing(name: &str) -> Hook {
+        Hook::new(name, || async { anyhow::bail!("boom") })
+    }
+
+    #[tokio::test]
+    async fn hooks_run_once_by_phase_then_order() {
+        let log = Log::default();
+        let mut lifecycle = test_lifecycle();
+        lifecycle
+            .on_shutdown(record(&log, "deregister"))
+            .on_start(record(&log, "warm cache").order(10))
+            .on_start(record(&log, "check schema").order(-1))
+            .on_start(record(&log, "load keys").order(10))
+            .on_ready(record(&log, "register"));
+
+        lifecycle.start().await.unwrap();
+        lifecycle.ready().await;
+        lifecycle.shutdown().await;
+        lifecycle.shutdown().await;
+
+        let log = log.lock().unwrap().clone();
+        assert_eq!(log, ["check schema", "warm cache", "load keys", "register", "deregister"]);
+    }
+
+    #[tokio::test]
+    async fn failing_or_slow_start_hooks_abort_startup() {
+        let log = Log::default();
+        let mut lifecycle = test_lifecycle();
+        lifecycle.on_start(failing("migrations")).on_start(record(&log, "warm cache").order(1));
+        let error = format!("{:#}", lifecycle.start().await.unwrap_err());
+        assert!(error.contains("start hook migrations failed: boom"), "{}", error);
+        assert!(log.lock().unwrap().is_empty());
+
+        let mut lifecycle = test_lifecycle();
+        let slow = Hook::new("warm cache", || async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        });
+        lifecycle.on_start(slow.timeout(Duration::from_millis(20)));
+        let error = format!("{:#}", lifecycle.start().await.unwrap_err());
+        assert!(error.contains("start hook warm cache failed: timed out"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn shutdown_continues_past_failures_until_its_deadline() {
+        let log = Log::default();
+        let mut lifecycle = test_lifecycle();
+        let drain = Hook::new("drain", || async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        });
+        lifecycle
+            .on_shutdown(failing("deregister"))
+            .on_shutdown(record(&log, "flush outbox").order(1))
+            .on_shutdown(drain.order(2))
+            .on_shutdown(record(&log, "close database").order(3));
+
+        let started = Instant::now();
+        lifecycle.shutdown().await;
+
+        assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+        assert_eq!(*log.lock().unwrap(), ["flush outbox"]);
+    }
+
+    #[tokio::test]
+    async fn draining_fails_readiness_before_closing_listeners() {
+        let draining = Draining::default();
+        assert!(!draining.is_draining());
+
+        let closed = tokio::spawn({
+            let draining = draining.clone();
+            async move { draining.closed().await }
+        });
+        let drain = tokio::spawn({
+            let draining = draining.clone();
+            async move { draining.drain(Duration::from_millis(200)).await }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(draining.is_draining());
+        assert!(!closed.is_finished());
+
+        drain.await.unwrap();
+        closed.await.unwrap();
+
+        // Closing is final; a later start doesn't reopen anything
+        draining.start();
+        tokio::time::timeout(Duration::from_millis(1), draining.closed()).await.unwrap();
+    }
+}
@@ -0,0 +1,270 @@
+//! Token bucket rate limiting behind a [`RateLimitBackend`], so the
+//! templates' limiters don't care where buckets live: in the process by
+//! default, or in Redis (with the `redis` feature) so every replica draws
+//! from the same buckets.
+//!
+//! Buckets are named by the caller, typically the rule and the client:
+//!
+//! ```ignore
+//! let backend = rate_limit::from_settings(&settings.limits.backend).await?;
+//! if let Err(retry_after) = backend.take("login:ip:203.0.113.7", quota).await {
+//!     return Err(rate_limit::rejection(retry_after));
+//! }
+//! ```
+
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::errors::{AppError, RetryHint};
+
+/// Buckets are pruned once this many are tracked; full buckets carry no
+/// state worth keeping.
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+/// A bucket's refill rate and size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    pub per_second: f64,
+    pub burst: f64,
+}
+
+impl Quota {
+    /// `None` for a rate of 0, which means unlimited.
+    pub fn new(per_second: f64, burst: u32) -> Option<Self> {
+        (per_second > 0.0).then(|| Self {
+            per_second,
+            burst: burst.max(1) as f64,
+        })
+    }
+}
+
+/// Where buckets are kept.
+pub trait RateLimitBackend: Send + Sync {
+    /// Takes a token from bucket `key`, starting it full, or returns how
+    /// long until one is available.
+    fn take<'a>(&'a self, key: &'a str, quota: Quota) -> BoxFuture<'a, Result<(), Duration>>;
+}
+
+pub type SharedRateLimitBackend = Arc<dyn RateLimitBackend>;
+
+/// The `backend` of a template's rate limit settings.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BackendSettings {
+    /// Buckets in this process; each replica limits on its own.
+    #[default]
+    Memory,
+    /// Buckets shared by all replicas. Needs the `redis` feature.
+    Redis { url: String },
+}
+
+/// The backend `settings` describe; connects to Redis if they name it.
+pub async fn from_settings(settings: &BackendSettings) -> anyhow::Result<SharedRateLimitBackend> {
+    match settings {
+        BackendSettings::Memory => Ok(Arc::new(MemoryBackend::default())),
+        #[cfg(feature = "redis")]
+        BackendSettings::Redis { url } => Ok(Arc::new(RedisBackend::connect(url).await?)),
+        #[cfg(not(feature = "redis"))]
+        BackendSettings::Redis { .. } => anyhow::bail!("the redis rate limit backend needs the `redis` feature"),
+    }
+}
+
+/// 429 or RESOURCE_EXHAUSTED, telling the client when to retry.
+pub fn rejection(retry_after: Duration) -> AppError {
+    AppError::Throttled {
+        message: "Rate limit exceeded".to_string(),
+        retry: RetryHint::after(retry_after),
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    quota: Quota,
+}
+
+impl Bucket {
+    fn full(quota: Quota, now: Instant) -> Self {
+        Self {
+            tokens: quota.burst,
+            updated: now,
+            quota,
+        }
+    }
+
+    /// Takes a token, or returns how long until one is available.
+    fn try_take(&mut self, quota: Quota, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * quota.per_second).min(quota.burst);
+        self.updated = now;
+        self.quota = quota;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / quota.per_second))
+        }
+    }
+
+    fn is_full(&self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * self.quota.per_second >= self.quota.burst
+    }
+}
+
+/// Buckets in this process.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl MemoryBackend {
+    fn take_at(&self, key: &str, quota: Quota, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_BUCKETS {
+            buckets.retain(|_, bucket| !bucket.is_full(now));
+        }
+
+        buckets
+            .entry(key.to_string())
+            .or_insert_with(|| Bucket::full(quota, now))
+            .try_take(quota, now)
+    }
+}
+
+impl RateLimitBackend for MemoryBackend {
+    fn take<'a>(&'a self, key: &'a str, quota: Quota) -> BoxFuture<'a, Result<(), Duration>> {
+        Box::pin(std::future::ready(self.take_at(key, quota, Instant::now())))
+    }
+}
+
+#[cfg(feature = "redis")]
+pub use self::redis_backend::RedisBackend;
+
+#[cfg(feature = "redis")]
+mod redis_backend {
+    use redis::aio::ConnectionManager;
+    use redis::Script;
+
+    use super::*;
+
+    /// Refills and takes from the bucket at `KEYS[1]` atomically, on Redis'
+    /// clock so replicas' clocks don't matter. Returns the seconds until a
+    /// token is available, 0 if one was taken, as a string since Lua
+    /// numbers become integer replies.
+    const TAKE: &str = r"
+        local rate = tonumber(ARGV[1])
+        local burst = tonumber(ARGV[2])
+        local time = redis.call('TIME')
+        local now = tonumber(time[1]) + tonumber(time[2]) / 1000000
+        local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated')
+        local tokens = tonumber(bucket[1]) or burst
+        local updated = tonumber(bucket[2]) or now
+        tokens = math.min(burst, tokens + math.max(0, now - updated) * rate)
+        local wait = 0
+        if tokens >= 1 then
+            tokens = tokens - 1
+        else
+            wait = (1 - tokens) / rate
+        end
+        redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', tostring(now))
+        redis.call('EXPIRE', KEYS[1], math.ceil(burst / rate) + 1)
+        return tostring(wait)
+    ";
+
+    /// Buckets in Redis, as hashes under `rate_limit:<key>` that expire once
+    /// they would be full again.
+    pub struct RedisBackend {
+        redis: ConnectionManager,
+        script: Script,
+    }
+
+    impl RedisBackend {
+        pub async fn connect(url: &str) -> redis::RedisResult<Self> {
+            let client = redis::Client::open(url)?;
+            Ok(Self {
+                redis: ConnectionManager::new(client).await?,
+                script: Script::new(TAKE),
+            })
+        }
+    }
+
+    impl RateLimitBackend for RedisBackend {
+        /// Redis errors are logged and the request admitted, so an outage
+        /// degrades limiting rather than the service.
+        fn take<'a>(&'a self, key: &'a str, quota: Quota) -> BoxFuture<'a, Result<(), Duration>> {
+            Box::pin(async move {
+                let mut redis = self.redis.clone();
+                let wait: redis::RedisResult<String> = self
+                    .script
+                    .key(format!("rate_limit:{}", key))
+                    .arg(quota.per_second)
+                    .arg(quota.burst)
+                    .invoke_async(&mut redis)
+                    .await;
+
+                match wait.map(|wait| wait.parse::<f64>().unwrap_or(0.0)) {
+                    Ok(wait) if wait > 0.0 => Err(Duration::from_secs_f64(wait)),
+                    Ok(_) => Ok(()),
+                    Err(e) => {
+                        tracing::warn!("Failed to update rate limit bucket: {}", e);
+                        Ok(())
+                    }
+                }
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let quota = Quota::new(2.0, 1).unwrap();
+        let start = Instant::now();
+        let mut bucket = Bucket::full(quota, start);
+
+        assert!(bucket.try_take(quota, start).is_ok());
+        let retry_after = bucket.try_take(quota, start).unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(500));
+        assert!(bucket.try_take(quota, start + Duration::from_millis(500)).is_ok());
+    }
+
+    #[test]
+    fn memory_buckets_are_kept_per_key() {
+        let backend = MemoryBackend::default();
+        let quota = Quota::new(1.0, 2).unwrap();
+        let now = Instant::now();
+
+        assert!(backend.take_at("login:ip:1", quota, now).is_ok());
+        assert!(backend.take_at("login:ip:1", quota, now).is_ok());
+        assert!(backend.take_at("login:ip:1", quota, now).is_err());
+        assert!(backend.take_at("login:ip:2", quota, now).is_ok());
+    }
+
+    #[test]
+    fn a_zero_rate_is_unlimited() {
+        assert_eq!(Quota::new(0.0, 10), None);
+    }
+
+    #[tokio::test]
+    async fn the_redis_backend_needs_its_feature() {
+        let memory = from_settings(&BackendSettings::Memory).await;
+        assert!(memory.is_ok());
+
+        if cfg!(not(feature = "redis")) {
+            let redis = from_settings(&BackendSettings::Redis {
+                url: "redis://127.0.0.1:6379".to_string(),
+            })
+            .await;
+            assert!(redis.is_err());
+        }
+    }
+}
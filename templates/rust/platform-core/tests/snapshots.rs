@@ -0,0 +1,110 @@
+//! Wire format of every error variant, over REST and gRPC, so changes to
+//! bodies, statuses and codes show up in review.
+//!
+//! Review changes with `cargo insta review`.
+
+#![cfg(all(feature = "actix", feature = "tonic", feature = "sqlx"))]
+
+use actix_web::body::MessageBody;
+use actix_web::ResponseError;
+use insta::assert_json_snapshot;
+use platform_core::errors::{AppError, ErrorCode, RetryHint};
+use serde_json::{json, Map, Value};
+use std::time::Duration;
+use tonic::Status;
+use tonic_types::StatusExt;
+
+fn variants() -> Vec<(&'static str, AppError)> {
+    let retry = RetryHint::after(Duration::from_millis(1500));
+    vec![
+        ("InternalServerError", AppError::InternalServerError),
+        ("BadRequest", AppError::BadRequest("Bad input".to_string())),
+        ("Unauthorized", AppError::Unauthorized),
+        ("Forbidden", AppError::Forbidden),
+        ("NotFound", AppError::NotFound("User not found".to_string())),
+        ("Conflict", AppError::Conflict("Email taken".to_string())),
+        ("UnprocessableEntity", AppError::UnprocessableEntity("Cannot process".to_string())),
+        ("TooManyRequests", AppError::TooManyRequests("Slow down".to_string())),
+        ("PayloadTooLarge", AppError::PayloadTooLarge("Too big".to_string())),
+        (
+            "Throttled",
+            AppError::Throttled {
+                message: "Slow down".to_string(),
+                retry,
+            },
+        ),
+        (
+            "Unavailable",
+            AppError::Unavailable {
+                message: "Down for maintenance".to_string(),
+                retry,
+            },
+        ),
+        (
+            "ConcurrentModification",
+            AppError::ConcurrentModification {
+                message: "Changed since read".to_string(),
+                retry,
+            },
+        ),
+        ("DatabaseError", AppError::DatabaseError(sqlx::Error::RowNotFound)),
+        ("ValidationError", AppError::ValidationError("email: invalid".to_string())),
+        (
+            "JwtError",
+            AppError::JwtError(jsonwebtoken::errors::ErrorKind::InvalidToken.into()),
+        ),
+        (
+            "JwtErrorExpired",
+            AppError::JwtError(jsonwebtoken::errors::ErrorKind::ExpiredSignature.into()),
+        ),
+        ("HashError", AppError::HashError(bcrypt::BcryptError::InvalidCost("1".to_string()))),
+        ("IoError", AppError::IoError(std::io::Error::other("disk full"))),
+        (
+            "Annotated",
+            AppError::NotFound("User not found".to_string()).with_code(ErrorCode::UserNotFound),
+        ),
+    ]
+}
+
+fn rest(error: &AppError) -> Value {
+    let response = error.error_response();
+    let status = response.status().as_u16();
+    let retry_after = response
+        .headers()
+        .get("retry-after")
+        .map(|value| value.to_str().unwrap().to_string());
+    let body = response.into_body().try_into_bytes().expect("buffered body");
+
+    json!({
+        "status": status,
+        "retry_after": retry_after,
+        "body": serde_json::from_slice::<Value>(&body).unwrap(),
+    })
+}
+
+fn grpc(status: &Status) -> Value {
+    let details = status.get_error_details();
+    json!({
+        "code": format!("{:?}", status.code()),
+        "message": status.message(),
+        "reason": details.error_info().map(|info| info.reason.clone()),
+        "retry_delay_ms": details
+            .retry_info()
+            .and_then(|info| info.retry_delay)
+            .map(|delay| delay.as_millis() as u64),
+    })
+}
+
+#[test]
+fn wire_format() {
+    let snapshot: Map<String, Value> = variants()
+        .into_iter()
+        .map(|(name, error)| {
+            let rest = rest(&error);
+            let grpc = grpc(&Status::from(error));
+            (name.to_string(), json!({ "rest": rest, "grpc": grpc }))
+        })
+        .collect();
+
+    assert_json_snapshot!(snapshot);
+}
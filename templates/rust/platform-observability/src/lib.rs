@@ -0,0 +1,9 @@
+//! Observability shared by the templates: the tracing subscriber with a
+//! filter that can change at runtime, span export and trace context
+//! propagation over OpenTelemetry, service mesh headers handed on to called
+//! services, and tokio runtime statistics.
+
+pub mod log_level;
+pub mod otel;
+pub mod propagation;
+pub mod runtime_stats;
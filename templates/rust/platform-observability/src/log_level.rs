@@ -0,0 +1,229 @@
+//! The tracing filter, changeable at runtime.
+//!
+//! [`init`] installs the global subscriber behind a reloadable filter;
+//! [`LogLevel`] swaps that filter for a while (`sqlx=debug` to chase a
+//! slow query, say) and puts the configured one back afterwards.
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing_subscriber::layer::{Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter};
+
+use platform_core::clock::SharedClock;
+use platform_core::errors::{AppError, AppResult};
+
+use crate::otel::{self, OtelSettings};
+
+/// The `log_level` section of a template's settings.
+#[derive(Debug, Deserialize, Clone)]
+pub struct LogLevelSettings {
+    /// Filter directives used at startup and restored after overrides,
+    /// e.g. `info` or `info,sqlx=warn`.
+    pub default: String,
+    /// How long an override lasts unless the request says otherwise.
+    pub revert_after_secs: u64,
+    /// Longest an override may last.
+    pub max_revert_after_secs: u64,
+}
+
+type Reload = Box<dyn Fn(EnvFilter) -> Result<(), reload::Error> + Send + Sync>;
+
+/// Applies filters to the subscriber installed by [`init`]; unset in tests.
+static RELOAD: OnceCell<Reload> = OnceCell::new();
+
+/// Installs the global subscriber, filtering with `log_level.default`, and
+/// exporting spans when `otel.enabled` is set (see [`otel`]). With the
+/// `console` feature, tokio-console can attach too; the filter only applies
+/// to the logs, so the console sees every task and the exporter keeps
+/// `otel.filter`.
+pub fn init(settings: &LogLevelSettings, otel: &OtelSettings) -> anyhow::Result<()> {
+    let (filter, handle) = reload::Layer::new(parse(&settings.default)?);
+    let registry = tracing_subscriber::registry();
+    #[cfg(feature = "console")]
+    let registry = registry.with(console_subscriber::spawn());
+    registry
+        .with(otel::layer(otel)?)
+        .with(fmt::layer().with_filter(filter))
+        .try_init()?;
+
+    let _ = RELOAD.set(Box::new(move |filter| handle.reload(filter)));
+    Ok(())
+}
+
+fn parse(filter: &str) -> AppResult<EnvFilter> {
+    EnvFilter::try_new(filter).map_err(|e| AppError::ValidationError(format!("Invalid log filter: {}", e)))
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LogLevelStatus {
+    pub filter: String,
+    pub default_filter: String,
+    /// When the default comes back; `None` while it is in effect.
+    pub reverts_at: Option<DateTime<Utc>>,
+}
+
+struct Override {
+    filter: String,
+    reverts_at: DateTime<Utc>,
+    generation: u64,
+    revert: JoinHandle<()>,
+}
+
+pub struct LogLevel {
+    settings: LogLevelSettings,
+    current: Mutex<Option<Override>>,
+    generations: AtomicU64,
+    clock: SharedClock,
+}
+
+impl LogLevel {
+    pub fn new(settings: LogLevelSettings, clock: SharedClock) -> Self {
+        Self {
+            settings,
+            current: Mutex::new(None),
+            generations: AtomicU64::new(0),
+            clock,
+        }
+    }
+
+    pub fn status(&self) -> LogLevelStatus {
+        let current = self.current.lock().unwrap();
+        self.status_of(current.as_ref())
+    }
+
+    /// Switches to `filter`, e.g. `info,sqlx=debug`, until `revert_after`
+    /// (`log_level.revert_after_secs` by default) has passed. Replaces any
+    /// earlier override and its revert.
+    pub fn set(self: &Arc<Self>, filter: &str, revert_after: Option<Duration>) -> AppResult<LogLevelStatus> {
+        let revert_after = revert_after.unwrap_or(Duration::from_secs(self.settings.revert_after_secs));
+        if revert_after.is_zero() || revert_after > Duration::from_secs(self.settings.max_revert_after_secs) {
+            return Err(AppError::ValidationError(format!(
+                "revert_after_secs must be between 1 and {}",
+                self.settings.max_revert_after_secs
+            )));
+        }
+        let parsed = parse(filter)?;
+
+        // Held while applying so concurrent changes take effect in order
+        let mut current = self.current.lock().unwrap();
+        apply(parsed)?;
+        if let Some(previous) = current.take() {
+            previous.revert.abort();
+        }
+
+        let generation = self.generations.fetch_add(1, Ordering::Relaxed);
+        let this = self.clone();
+        let revert = tokio::spawn(async move {
+            tokio::time::sleep(revert_after).await;
+            this.revert_expired(generation);
+        });
+        *current = Some(Override {
+            filter: filter.to_string(),
+            reverts_at: self.clock.now() + chrono::Duration::from_std(revert_after).unwrap_or_default(),
+            generation,
+            revert,
+        });
+        Ok(self.status_of(current.as_ref()))
+    }
+
+    /// Puts the default filter back.
+    pub fn reset(&self) -> AppResult<LogLevelStatus> {
+        let mut current = self.current.lock().unwrap();
+        apply(parse(&self.settings.default)?)?;
+        if let Some(previous) = current.take() {
+            previous.revert.abort();
+        }
+        Ok(self.status_of(None))
+    }
+
+    fn revert_expired(&self, generation: u64) {
+        let mut current = self.current.lock().unwrap();
+        // A newer override may have replaced this one just as it expired
+        if current.as_ref().map(|current| current.generation) != Some(generation) {
+            return;
+        }
+        let expired = current.take().expect("checked above");
+        match parse(&self.settings.default).and_then(apply) {
+            Ok(()) => tracing::info!(filter = %expired.filter, "Log filter override expired; default restored"),
+            Err(e) => tracing::error!("Failed to restore the default log filter: {}", e),
+        }
+    }
+
+    fn status_of(&self, current: Option<&Override>) -> LogLevelStatus {
+        LogLevelStatus {
+            filter: current
+                .map(|current| current.filter.clone())
+                .unwrap_or_else(|| self.settings.default.clone()),
+            default_filter: self.settings.default.clone(),
+            reverts_at: current.map(|current| current.reverts_at),
+        }
+    }
+}
+
+fn apply(filter: EnvFilter) -> AppResult<()> {
+    let Some(reload) = RELOAD.get() else {
+        return Ok(());
+    };
+    reload(filter).map_err(|e| {
+        tracing::error!("Failed to reload the log filter: {}", e);
+        AppError::InternalServerError
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use platform_core::clock::SystemClock;
+
+    fn log_level() -> Arc<LogLevel> {
+        let settings = LogLevelSettings {
+            default: "info".to_string(),
+            revert_after_secs: 900,
+            max_revert_after_secs: 3600,
+        };
+        Arc::new(LogLevel::new(settings, Arc::new(SystemClock)))
+    }
+
+    #[tokio::test]
+    async fn overrides_revert_to_the_default() {
+        let log_level = log_level();
+
+        let status = log_level.set("info,sqlx=debug", Some(Duration::from_millis(50))).unwrap();
+        assert_eq!(status.filter, "info,sqlx=debug");
+        assert!(status.reverts_at.is_some());
+        assert_eq!(log_level.status(), status);
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(log_level.status().filter, "info");
+        assert_eq!(log_level.status().reverts_at, None);
+    }
+
+    #[tokio::test]
+    async fn a_newer_override_outlives_the_one_it_replaced() {
+        let log_level = log_level();
+
+        log_level.set("debug", Some(Duration::from_millis(50))).unwrap();
+        log_level.set("info,sqlx=debug", None).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(log_level.status().filter, "info,sqlx=debug");
+
+        assert_eq!(log_level.reset().unwrap().filter, "info");
+        assert_eq!(log_level.status().reverts_at, None);
+    }
+
+    #[tokio::test]
+    async fn invalid_filters_and_durations_are_rejected() {
+        let log_level = log_level();
+
+        assert!(log_level.set("sqlx=loud", None).is_err());
+        assert!(log_level.set("debug", Some(Duration::ZERO)).is_err());
+        assert!(log_level.set("debug", Some(Duration::from_secs(3601))).is_err());
+        assert_eq!(log_level.status().filter, "info");
+    }
+}
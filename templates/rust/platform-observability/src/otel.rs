@@ -0,0 +1,147 @@
+//! Distributed tracing: spans exported over OTLP and the W3C trace context
+//! (`traceparent`, `tracestate`) carried between services.
+//!
+//! [`log_level::init`](crate::log_level::init) adds the exporting layer when
+//! `otel.enabled` is set. Templates then continue traces from incoming
+//! requests with [`set_parent`] and hand them on to the services they call
+//! with [`inject`], each through adapters for its own header types:
+//!
+//! ```ignore
+//! let span = tracing::info_span!("grpc", method = %request.uri().path());
+//! otel::set_parent(&span, &HeaderExtractor(request.headers()));
+//! ```
+//!
+//! Sampling follows the caller's decision when there is one, and otherwise
+//! keeps `otel.sample_ratio` of traces.
+
+use opentelemetry::global;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{self as sdktrace, Sampler};
+use opentelemetry_sdk::{runtime, Resource};
+use serde::Deserialize;
+use tracing::{Span, Subscriber};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer};
+
+pub use opentelemetry::propagation::{Extractor, Injector};
+
+/// The `otel` section of a template's settings.
+#[derive(Debug, Deserialize, Clone)]
+pub struct OtelSettings {
+    pub enabled: bool,
+    /// OTLP/gRPC endpoint of the collector, e.g. `http://localhost:4317`.
+    pub endpoint: String,
+    /// `service.name` of the exported spans.
+    pub service_name: String,
+    /// Share of traces started here that are kept, from 0 to 1. Traces
+    /// continued from a caller keep its decision.
+    pub sample_ratio: f64,
+    /// Filter directives for the exported spans, independent of the log
+    /// filter so a `debug` override doesn't flood the collector.
+    pub filter: String,
+    /// How long to wait for the collector to accept a batch.
+    pub timeout_secs: u64,
+}
+
+/// The layer exporting spans to `otel.endpoint`, or `None` when
+/// `otel.enabled` is off. Also installs the W3C trace context propagator
+/// used by [`set_parent`] and [`inject`]. Needs a tokio runtime.
+pub fn layer<S>(settings: &OtelSettings) -> anyhow::Result<Option<impl Layer<S>>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    if !settings.enabled {
+        return Ok(None);
+    }
+    if !(0.0..=1.0).contains(&settings.sample_ratio) {
+        anyhow::bail!("otel.sample_ratio must be between 0 and 1");
+    }
+    let filter = EnvFilter::try_new(&settings.filter)
+        .map_err(|e| anyhow::anyhow!("Invalid otel.filter: {}", e))?;
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(&settings.endpoint)
+                .with_timeout(std::time::Duration::from_secs(settings.timeout_secs)),
+        )
+        .with_trace_config(
+            sdktrace::config()
+                .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(settings.sample_ratio))))
+                .with_resource(Resource::new([KeyValue::new("service.name", settings.service_name.clone())])),
+        )
+        .install_batch(runtime::Tokio)?;
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer).with_filter(filter)))
+}
+
+/// Exports the spans still buffered. Blocks, so call it off the runtime
+/// (`spawn_blocking`) once the server has stopped.
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Makes `span` a child of the trace `extractor` carries, if any, so its
+/// spans join the caller's trace.
+pub fn set_parent(span: &Span, extractor: &dyn Extractor) {
+    let context = global::get_text_map_propagator(|propagator| propagator.extract(extractor));
+    span.set_parent(context);
+}
+
+/// Writes the trace context of `span` into `injector`, typically the
+/// headers of a request to another service.
+pub fn inject(span: &Span, injector: &mut dyn Injector) {
+    let context = span.context();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, injector));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TracerProvider;
+    use std::collections::HashMap;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn spans_continue_the_callers_trace() {
+        let provider = sdktrace::TracerProvider::builder().build();
+        let subscriber =
+            tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let incoming = HashMap::from([("traceparent".to_string(), TRACEPARENT.to_string())]);
+            let span = tracing::info_span!("request");
+            set_parent(&span, &incoming);
+
+            let mut outgoing = HashMap::new();
+            inject(&span, &mut outgoing);
+            let traceparent = &outgoing["traceparent"];
+            assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+            assert!(!traceparent.contains("00f067aa0ba902b7"), "the child span is sent as the parent");
+        });
+    }
+
+    #[test]
+    fn disabled_tracing_adds_no_layer() {
+        let settings = OtelSettings {
+            enabled: false,
+            endpoint: "http://localhost:4317".to_string(),
+            service_name: "test".to_string(),
+            sample_ratio: 2.0,
+            filter: "info".to_string(),
+            timeout_secs: 10,
+        };
+
+        let layer = layer::<tracing_subscriber::Registry>(&settings).unwrap();
+        assert!(layer.is_none());
+    }
+}
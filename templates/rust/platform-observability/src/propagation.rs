@@ -0,0 +1,168 @@
+//! Tracing and routing headers handed on from incoming requests to the
+//! services they call: B3, `traceparent`, `baggage` and the like.
+//!
+//! Service meshes such as Istio and Linkerd trace calls in their sidecars,
+//! but can only join a request's outgoing calls to its trace if the service
+//! copies these headers across. Templates [`capture`] the allowed headers of
+//! each incoming request and run its handler in [`scope`]; their outgoing
+//! clients then [`inject`] them:
+//!
+//! ```ignore
+//! let headers = propagation::capture(&settings.propagation, &HeaderExtractor(request.headers()));
+//! propagation::scope(headers, handler(request)).await
+//! ```
+//!
+//! Inject them before [`otel::inject`](crate::otel::inject), so with
+//! `otel.enabled` the `traceparent` names this service's span rather than
+//! the caller's.
+
+use serde::Deserialize;
+use std::future::Future;
+
+use crate::otel::{Extractor, Injector};
+
+/// Headers propagated unless `propagation.headers` says otherwise: W3C
+/// trace context and baggage, B3 in its single and multi header forms,
+/// Envoy's request ID, OpenTracing's span context and Linkerd's context.
+pub const DEFAULT_HEADERS: &[&str] = &[
+    "traceparent",
+    "tracestate",
+    "baggage",
+    "b3",
+    "x-b3-traceid",
+    "x-b3-spanid",
+    "x-b3-parentspanid",
+    "x-b3-sampled",
+    "x-b3-flags",
+    "x-request-id",
+    "x-ot-span-context",
+    "l5d-ctx-*",
+];
+
+/// The `propagation` section of a template's settings.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PropagationSettings {
+    /// Names of the headers to hand on, case-insensitive; a trailing `*`
+    /// matches every header starting with what comes before it. Empty to
+    /// propagate nothing.
+    pub headers: Vec<String>,
+}
+
+impl Default for PropagationSettings {
+    fn default() -> Self {
+        Self {
+            headers: DEFAULT_HEADERS.iter().map(|header| header.to_string()).collect(),
+        }
+    }
+}
+
+impl PropagationSettings {
+    pub fn allows(&self, name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        self.headers.iter().any(|allowed| {
+            let allowed = allowed.to_ascii_lowercase();
+            match allowed.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == allowed,
+            }
+        })
+    }
+}
+
+/// Headers captured from an incoming request, lowercased.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Propagated(Vec<(String, String)>);
+
+impl Propagated {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.0.iter().find(|(key, _)| *key == name).map(|(_, value)| value.as_str())
+    }
+}
+
+tokio::task_local! {
+    static PROPAGATED: Propagated;
+}
+
+/// The headers of an incoming request that `settings` allows.
+pub fn capture(settings: &PropagationSettings, extractor: &dyn Extractor) -> Propagated {
+    Propagated(
+        extractor
+            .keys()
+            .into_iter()
+            .filter(|key| settings.allows(key))
+            .filter_map(|key| Some((key.to_ascii_lowercase(), extractor.get(key)?.to_string())))
+            .collect(),
+    )
+}
+
+/// Runs `future` with `headers` as the ones [`inject`] adds. Tasks it
+/// spawns don't inherit them; capture [`current`] and scope those too.
+pub async fn scope<F: Future>(headers: Propagated, future: F) -> F::Output {
+    PROPAGATED.scope(headers, future).await
+}
+
+/// The headers in scope, empty outside a request.
+pub fn current() -> Propagated {
+    PROPAGATED.try_with(Propagated::clone).unwrap_or_default()
+}
+
+/// Adds the headers in scope to an outgoing request. Leave them off
+/// requests to third parties.
+pub fn inject(injector: &mut dyn Injector) {
+    let _ = PROPAGATED.try_with(|headers| {
+        for (key, value) in &headers.0 {
+            injector.set(key, value.clone());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn incoming() -> HashMap<String, String> {
+        HashMap::from([
+            ("x-b3-traceid".to_string(), "80f198ee56343ba864fe8b2a57d3eff7".to_string()),
+            ("baggage".to_string(), "tenant=acme".to_string()),
+            ("l5d-ctx-trace".to_string(), "opaque".to_string()),
+            ("authorization".to_string(), "Bearer secret".to_string()),
+        ])
+    }
+
+    #[test]
+    fn only_allowed_headers_are_captured() {
+        let headers = capture(&PropagationSettings::default(), &incoming());
+
+        assert_eq!(headers.get("X-B3-TraceId"), Some("80f198ee56343ba864fe8b2a57d3eff7"));
+        assert_eq!(headers.get("baggage"), Some("tenant=acme"));
+        assert_eq!(headers.get("l5d-ctx-trace"), Some("opaque"));
+        assert_eq!(headers.get("authorization"), None);
+
+        let none = PropagationSettings { headers: Vec::new() };
+        assert!(capture(&none, &incoming()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn scoped_headers_are_injected() {
+        let headers = capture(&PropagationSettings::default(), &incoming());
+
+        let outgoing = scope(headers, async {
+            let mut outgoing = HashMap::new();
+            inject(&mut outgoing);
+            outgoing
+        })
+        .await;
+        assert_eq!(outgoing.len(), 3);
+        assert_eq!(outgoing["baggage"], "tenant=acme");
+
+        let mut outside = HashMap::new();
+        inject(&mut outside);
+        assert!(outside.is_empty());
+    }
+}
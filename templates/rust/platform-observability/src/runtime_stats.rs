@@ -0,0 +1,132 @@
+//! Tokio runtime statistics, for diagnosing stuck or saturated servers.
+//!
+//! Task, queue and busy-time figures are always available; blocking pool and
+//! poll time figures need `--cfg tokio_unstable`. Servers running several
+//! runtimes (actix runs one per worker) register each with [`Runtimes`].
+
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::runtime::Handle;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeStats {
+    pub workers: usize,
+    pub alive_tasks: usize,
+    /// Tasks scheduled from outside the runtime and not yet picked up.
+    pub global_queue_depth: usize,
+    /// Time each worker spent polling tasks since the runtime started.
+    pub worker_busy: Vec<Duration>,
+    /// A count that stops moving under load points at a worker blocked
+    /// inside a task.
+    pub worker_park_count: Vec<u64>,
+    /// Only with `--cfg tokio_unstable`.
+    pub blocking_pool: Option<BlockingPoolStats>,
+    /// Empty without `--cfg tokio_unstable`.
+    pub worker_mean_poll_time: Vec<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockingPoolStats {
+    pub threads: usize,
+    pub idle_threads: usize,
+    pub queue_depth: usize,
+}
+
+impl RuntimeStats {
+    /// Statistics of the runtime the caller runs on.
+    pub fn current() -> Self {
+        Self::of(&Handle::current())
+    }
+
+    pub fn of(handle: &Handle) -> Self {
+        let metrics = handle.metrics();
+        let workers = 0..metrics.num_workers();
+
+        Self {
+            workers: metrics.num_workers(),
+            alive_tasks: metrics.num_alive_tasks(),
+            global_queue_depth: metrics.global_queue_depth(),
+            worker_busy: workers
+                .clone()
+                .map(|worker| metrics.worker_total_busy_duration(worker))
+                .collect(),
+            worker_park_count: workers.clone().map(|worker| metrics.worker_park_count(worker)).collect(),
+            #[cfg(tokio_unstable)]
+            blocking_pool: Some(BlockingPoolStats {
+                threads: metrics.num_blocking_threads(),
+                idle_threads: metrics.num_idle_blocking_threads(),
+                queue_depth: metrics.blocking_queue_depth(),
+            }),
+            #[cfg(not(tokio_unstable))]
+            blocking_pool: None,
+            #[cfg(tokio_unstable)]
+            worker_mean_poll_time: workers.map(|worker| metrics.worker_mean_poll_time(worker)).collect(),
+            #[cfg(not(tokio_unstable))]
+            worker_mean_poll_time: Vec::new(),
+        }
+    }
+}
+
+/// The runtimes serving this process.
+#[derive(Default)]
+pub struct Runtimes {
+    handles: Mutex<Vec<(String, Handle)>>,
+}
+
+impl Runtimes {
+    /// Registers the calling thread's runtime as serving `server`, named
+    /// e.g. `api/actix-server worker 0`; repeat calls from the same thread
+    /// are ignored.
+    pub fn register_current(&self, server: &str) {
+        let Ok(handle) = Handle::try_current() else {
+            return;
+        };
+        let thread = std::thread::current();
+        let name = format!("{}/{}", server, thread.name().unwrap_or("unnamed"));
+
+        let mut handles = self.handles.lock().unwrap();
+        if !handles.iter().any(|(registered, _)| *registered == name) {
+            handles.push((name, handle));
+        }
+    }
+
+    /// Statistics of every registered runtime, by name.
+    pub fn stats(&self) -> Vec<(String, RuntimeStats)> {
+        let handles = self.handles.lock().unwrap();
+        handles
+            .iter()
+            .map(|(name, handle)| (name.clone(), RuntimeStats::of(handle)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn stats_describe_the_current_runtime() {
+        let task = tokio::spawn(std::future::pending::<()>());
+
+        let stats = RuntimeStats::current();
+        assert_eq!(stats.workers, 2);
+        assert!(stats.alive_tasks >= 1, "{:?}", stats);
+        assert_eq!(stats.worker_busy.len(), 2);
+        assert_eq!(stats.worker_park_count.len(), 2);
+        assert_eq!(stats.blocking_pool.is_some(), cfg!(tokio_unstable));
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn runtimes_register_once_per_thread() {
+        let runtimes = Runtimes::default();
+        runtimes.register_current("api");
+        runtimes.register_current("api");
+        runtimes.register_current("admin");
+
+        let names: Vec<_> = runtimes.stats().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names.len(), 2, "{:?}", names);
+        assert!(names[0].starts_with("api/") && names[1].starts_with("admin/"), "{:?}", names);
+    }
+}
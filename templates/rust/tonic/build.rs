@@ -1,9 +1,23 @@
+use std::env;
+use std::path::PathBuf;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Served by the reflection service
+    let descriptor_set = PathBuf::from(env::var("OUT_DIR")?).join("template_descriptor.bin");
+
     tonic_build::configure()
         .build_server(true)
         .build_client(true)
+        .file_descriptor_set_path(descriptor_set)
         .compile(
-            &["proto/user.proto", "proto/health.proto"],
+            &[
+                "proto/user.proto",
+                "proto/health.proto",
+                "proto/grpc/health/v1/health.proto",
+                "proto/file.proto",
+                "proto/operations.proto",
+                "proto/admin.proto",
+            ],
             &["proto"],
         )?;
     Ok(())
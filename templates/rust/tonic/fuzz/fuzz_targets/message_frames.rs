@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tonic_template::interceptors::message_size::fuzz_frames;
+
+// The first byte picks the chunk size, so framing is exercised across
+// chunk boundaries
+fuzz_target!(|data: &[u8]| {
+    if let Some((&chunk_size, body)) = data.split_first() {
+        fuzz_frames(1024, true, body, usize::from(chunk_size).max(1));
+    }
+});
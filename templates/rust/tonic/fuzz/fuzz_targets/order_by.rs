@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tonic_template::aip::OrderBy;
+
+const ALLOWED: &[&str] = &["username", "email", "created_at"];
+
+fuzz_target!(|input: &str| {
+    if let Ok(order_by) = OrderBy::parse(input, ALLOWED) {
+        assert!(order_by.fields().iter().all(|field| ALLOWED.contains(&field.field.as_str())));
+    }
+});
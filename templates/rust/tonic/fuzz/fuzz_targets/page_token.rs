@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tonic_template::aip::{PageRequest, PageTokens};
+use tonic_template::config::PaginationSettings;
+
+fuzz_target!(|page_token: &str| {
+    let settings = PaginationSettings {
+        token_ttl_secs: 3600,
+        max_streams: 4,
+        stream_idle_timeout_secs: 30,
+    };
+    let tokens = PageTokens::new("fuzz-secret", &settings);
+    let _ = PageRequest::parse(10, page_token, &["name"], &tokens);
+});
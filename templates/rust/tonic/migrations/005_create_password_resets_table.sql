@@ -0,0 +1,18 @@
+-- Single-use password reset tokens sent by email; only their SHA-256 is
+-- stored. See UserServiceImpl::request_password_reset.
+CREATE TABLE IF NOT EXISTS password_resets (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    token_hash VARCHAR(64) UNIQUE NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    consumed_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_password_resets_email ON password_resets(email, created_at);
+CREATE INDEX idx_password_resets_user_id ON password_resets(user_id);
+
+-- Refresh tokens are stateless JWTs; those issued before this instant are
+-- refused, which is how a password reset signs the user out everywhere
+ALTER TABLE users ADD COLUMN IF NOT EXISTS tokens_valid_after TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,122 @@
+use prost_types::FieldMask;
+use std::collections::BTreeSet;
+
+use crate::errors::{AppError, AppResult};
+
+/// The validated paths of an update mask (AIP-134).
+///
+/// A missing or empty mask means "every field set in the request"; the
+/// wildcard `*` means full replacement, i.e. every updatable field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldMaskPaths {
+    Implicit,
+    Paths(BTreeSet<String>),
+}
+
+impl FieldMaskPaths {
+    pub fn parse(mask: Option<&FieldMask>, updatable: &[&str]) -> AppResult<Self> {
+        let Some(mask) = mask.filter(|mask| !mask.paths.is_empty()) else {
+            return Ok(Self::Implicit);
+        };
+
+        if mask.paths.iter().any(|path| path == "*") {
+            if mask.paths.len() > 1 {
+                return Err(AppError::ValidationError(
+                    "update_mask '*' cannot be combined with other paths".to_string(),
+                ));
+            }
+            return Ok(Self::Paths(updatable.iter().map(|path| path.to_string()).collect()));
+        }
+
+        let mut paths = BTreeSet::new();
+        for path in &mask.paths {
+            if !updatable.contains(&path.as_str()) {
+                return Err(AppError::ValidationError(format!(
+                    "update_mask contains unsupported field '{}'",
+                    path
+                )));
+            }
+            paths.insert(path.clone());
+        }
+
+        Ok(Self::Paths(paths))
+    }
+
+    /// Whether `path` should be written. With an implicit mask this is
+    /// decided by whether the request set the field.
+    pub fn includes(&self, path: &str, is_set: bool) -> bool {
+        match self {
+            Self::Implicit => is_set,
+            Self::Paths(paths) => paths.contains(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    const UPDATABLE: &[&str] = &["email", "username", "full_name"];
+
+    fn mask(paths: &[&str]) -> FieldMask {
+        FieldMask {
+            paths: paths.iter().map(|path| path.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn missing_mask_follows_presence() {
+        let paths = FieldMaskPaths::parse(None, UPDATABLE).unwrap();
+        assert!(paths.includes("email", true));
+        assert!(!paths.includes("email", false));
+
+        let paths = FieldMaskPaths::parse(Some(&mask(&[])), UPDATABLE).unwrap();
+        assert_eq!(paths, FieldMaskPaths::Implicit);
+    }
+
+    #[test]
+    fn explicit_mask_ignores_presence() {
+        let paths = FieldMaskPaths::parse(Some(&mask(&["full_name"])), UPDATABLE).unwrap();
+        assert!(paths.includes("full_name", false));
+        assert!(!paths.includes("email", true));
+    }
+
+    #[test]
+    fn wildcard_selects_every_updatable_field() {
+        let paths = FieldMaskPaths::parse(Some(&mask(&["*"])), UPDATABLE).unwrap();
+        for field in UPDATABLE {
+            assert!(paths.includes(field, false));
+        }
+        assert!(FieldMaskPaths::parse(Some(&mask(&["*", "email"])), UPDATABLE).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_paths() {
+        assert!(FieldMaskPaths::parse(Some(&mask(&["password_hash"])), UPDATABLE).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn accepts_exactly_updatable_paths(
+            paths in vec("(email|username|full_name|password_hash|\\*|[a-z_]{1,8})", 0..6),
+        ) {
+            let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+            let valid = if paths.contains(&"*") {
+                paths.len() == 1
+            } else {
+                paths.iter().all(|path| UPDATABLE.contains(path))
+            };
+
+            match FieldMaskPaths::parse(Some(&mask(&paths)), UPDATABLE) {
+                Ok(FieldMaskPaths::Implicit) => prop_assert!(paths.is_empty()),
+                Ok(FieldMaskPaths::Paths(parsed)) => {
+                    prop_assert!(valid);
+                    prop_assert!(parsed.iter().all(|path| UPDATABLE.contains(&path.as_str())));
+                }
+                Err(_) => prop_assert!(!valid),
+            }
+        }
+    }
+}
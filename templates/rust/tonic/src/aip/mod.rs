@@ -0,0 +1,13 @@
+//! Helpers implementing Google API Improvement Proposals shared by all RPCs:
+//! pagination (AIP-158), ordering (AIP-132), field masks (AIP-134/161) and
+//! timestamp conversion.
+
+pub mod field_mask;
+pub mod order_by;
+pub mod pagination;
+pub mod timestamp;
+
+pub use field_mask::FieldMaskPaths;
+pub use order_by::{OrderBy, OrderField};
+pub use pagination::{parse_page_size, PageRequest, PageToken, PageTokens};
+pub use timestamp::{from_timestamp, to_timestamp};
@@ -0,0 +1,190 @@
+use sqlx::{Postgres, QueryBuilder};
+
+use crate::errors::{AppError, AppResult};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderField {
+    pub field: String,
+    pub descending: bool,
+}
+
+/// A parsed AIP-132 `order_by` clause, e.g. `"username, created_at desc"`.
+///
+/// Fields are validated against an allow-list, so they can be pushed into
+/// SQL as identifiers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrderBy {
+    fields: Vec<OrderField>,
+}
+
+impl OrderBy {
+    pub fn parse(input: &str, allowed: &[&str]) -> AppResult<Self> {
+        if input.trim().is_empty() {
+            return Ok(Self::default());
+        }
+
+        let mut fields: Vec<OrderField> = Vec::new();
+        for clause in input.split(',') {
+            let mut parts = clause.split_whitespace();
+            let field = parts.next().ok_or_else(|| invalid("empty field"))?;
+            let descending = match parts.next() {
+                None | Some("asc") => false,
+                Some("desc") => true,
+                Some(other) => return Err(invalid(&format!("unexpected '{}'", other))),
+            };
+            if parts.next().is_some() {
+                return Err(invalid(&format!("unexpected tokens after '{}'", field)));
+            }
+            if !allowed.contains(&field) {
+                return Err(invalid(&format!("unsupported field '{}'", field)));
+            }
+            if fields.iter().any(|existing| existing.field == field) {
+                return Err(invalid(&format!("duplicate field '{}'", field)));
+            }
+
+            fields.push(OrderField {
+                field: field.to_string(),
+                descending,
+            });
+        }
+
+        Ok(Self { fields })
+    }
+
+    pub fn fields(&self) -> &[OrderField] {
+        &self.fields
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Appends ` ORDER BY ...`, falling back to `default` when no ordering
+    /// was requested. `tiebreaker` is always appended last so pages are
+    /// stable.
+    pub fn push_sql(&self, query: &mut QueryBuilder<'_, Postgres>, default: &str, tiebreaker: &str) {
+        query.push(" ORDER BY ");
+        if self.fields.is_empty() {
+            query.push(default);
+        }
+        for (i, field) in self.fields.iter().enumerate() {
+            if i > 0 {
+                query.push(", ");
+            }
+            query.push(&field.field);
+            if field.descending {
+                query.push(" DESC");
+            }
+        }
+        query.push(", ").push(tiebreaker);
+    }
+}
+
+fn invalid(reason: &str) -> AppError {
+    AppError::ValidationError(format!("Invalid order_by: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use proptest::sample::subsequence;
+
+    const ALLOWED: &[&str] = &["username", "created_at"];
+
+    #[test]
+    fn parses_fields_and_directions() {
+        let order_by = OrderBy::parse(" username ,created_at desc", ALLOWED).unwrap();
+
+        assert_eq!(
+            order_by.fields(),
+            &[
+                OrderField {
+                    field: "username".to_string(),
+                    descending: false,
+                },
+                OrderField {
+                    field: "created_at".to_string(),
+                    descending: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_input_is_default_order() {
+        assert!(OrderBy::parse("  ", ALLOWED).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(OrderBy::parse("password_hash", ALLOWED).is_err());
+        assert!(OrderBy::parse("username; DROP TABLE users", ALLOWED).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_clauses() {
+        assert!(OrderBy::parse("username sideways", ALLOWED).is_err());
+        assert!(OrderBy::parse("username desc extra", ALLOWED).is_err());
+        assert!(OrderBy::parse("username,,created_at", ALLOWED).is_err());
+        assert!(OrderBy::parse("username, username desc", ALLOWED).is_err());
+    }
+
+    #[test]
+    fn renders_sql() {
+        let mut query = QueryBuilder::<Postgres>::new("SELECT * FROM users");
+        OrderBy::parse("created_at desc, username", ALLOWED)
+            .unwrap()
+            .push_sql(&mut query, "created_at DESC", "id");
+        assert_eq!(query.sql(), "SELECT * FROM users ORDER BY created_at DESC, username, id");
+
+        let mut query = QueryBuilder::<Postgres>::new("SELECT * FROM users");
+        OrderBy::default().push_sql(&mut query, "created_at DESC", "id");
+        assert_eq!(query.sql(), "SELECT * FROM users ORDER BY created_at DESC, id");
+    }
+
+    fn render(fields: &[&str], directions: &[Option<bool>]) -> String {
+        fields
+            .iter()
+            .zip(directions)
+            .map(|(field, direction)| match direction {
+                None => field.to_string(),
+                Some(false) => format!("{} asc", field),
+                Some(true) => format!("{} desc", field),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    proptest! {
+        #[test]
+        fn parses_rendered_clauses(
+            fields in subsequence(ALLOWED.to_vec(), 1..=ALLOWED.len()).prop_shuffle(),
+            directions in vec(any::<Option<bool>>(), ALLOWED.len()),
+        ) {
+            let order_by = OrderBy::parse(&render(&fields, &directions), ALLOWED).unwrap();
+
+            let expected: Vec<OrderField> = fields
+                .iter()
+                .zip(&directions)
+                .map(|(field, direction)| OrderField {
+                    field: field.to_string(),
+                    descending: *direction == Some(true),
+                })
+                .collect();
+            prop_assert_eq!(order_by.fields(), expected.as_slice());
+        }
+
+        #[test]
+        fn only_allowed_fields_are_accepted(
+            input in prop_oneof![any::<String>(), "(username|created_at|password_hash|asc|desc|;|,| )*"],
+        ) {
+            if let Ok(order_by) = OrderBy::parse(&input, ALLOWED) {
+                for field in order_by.fields() {
+                    prop_assert!(ALLOWED.contains(&field.field.as_str()));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::PaginationSettings;
+use crate::errors::{AppError, AppResult, ErrorCode};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const DEFAULT_PAGE_SIZE: u32 = 20;
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+const DERIVATION_CONTEXT: &[u8] = b"devxplatform-page-tokens-v1";
+
+/// Opaque continuation token. It records where the next page starts, a
+/// fingerprint of the request parameters, so a token can't be replayed
+/// against a different query (AIP-158), and when it was issued. Tokens are
+/// signed, so clients can't edit the offset to skip around.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PageToken {
+    #[serde(rename = "o")]
+    offset: u64,
+    #[serde(rename = "f")]
+    fingerprint: String,
+    #[serde(rename = "t")]
+    issued_at: i64,
+}
+
+/// Signs and verifies page tokens. The key is derived from the active JWT
+/// key, so every replica accepts tokens issued by the others; promoting
+/// another key invalidates outstanding page tokens.
+#[derive(Clone)]
+pub struct PageTokens {
+    key: [u8; 32],
+    ttl_secs: i64,
+}
+
+impl PageTokens {
+    pub fn new(jwt_secret: &str, settings: &PaginationSettings) -> Self {
+        let key = <HmacSha256 as Mac>::new_from_slice(jwt_secret.as_bytes())
+            .expect("HMAC accepts any key length")
+            .chain_update(DERIVATION_CONTEXT)
+            .finalize()
+            .into_bytes()
+            .into();
+        Self {
+            key,
+            ttl_secs: settings.token_ttl_secs as i64,
+        }
+    }
+
+    pub fn encode(&self, token: &PageToken) -> String {
+        let json = URL_SAFE_NO_PAD.encode(serde_json::to_vec(token).expect("page tokens serialize"));
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(json.as_bytes()).finalize().into_bytes());
+        format!("{}.{}", json, signature)
+    }
+
+    /// Checks the signature and expiry of `token` as of `now` (Unix seconds).
+    pub fn decode(&self, token: &str, now: i64) -> AppResult<PageToken> {
+        let (json, signature) = token.split_once('.').ok_or_else(invalid_token)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid_token())?;
+        self.mac(json.as_bytes())
+            .verify_slice(&signature)
+            .map_err(|_| invalid_token())?;
+
+        let token: PageToken = URL_SAFE_NO_PAD
+            .decode(json)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(invalid_token)?;
+        if now - token.issued_at > self.ttl_secs {
+            return Err(AppError::ValidationError("page_token has expired; start again from the first page".to_string())
+                .with_code(ErrorCode::PageTokenExpired));
+        }
+        Ok(token)
+    }
+
+    fn mac(&self, message: &[u8]) -> HmacSha256 {
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(message);
+        mac
+    }
+}
+
+/// A validated `page_size`/`page_token` pair.
+#[derive(Clone)]
+pub struct PageRequest {
+    pub page_size: u32,
+    pub offset: u64,
+    fingerprint: String,
+    tokens: PageTokens,
+    now: i64,
+}
+
+impl PageRequest {
+    /// `params` are the request fields that shape the result set (filter,
+    /// ordering, parent); a token issued for other params is rejected.
+    pub fn parse(page_size: i32, page_token: &str, params: &[&str], tokens: &PageTokens) -> AppResult<Self> {
+        Self::parse_at(page_size, page_token, params, tokens, Utc::now().timestamp())
+    }
+
+    /// [`parse`](Self::parse) as of `now` (Unix seconds).
+    pub fn parse_at(
+        page_size: i32,
+        page_token: &str,
+        params: &[&str],
+        tokens: &PageTokens,
+        now: i64,
+    ) -> AppResult<Self> {
+        let page_size = parse_page_size(page_size, "page_size")?;
+        let fingerprint = fingerprint(params);
+        let offset = if page_token.is_empty() {
+            0
+        } else {
+            let token = tokens.decode(page_token, now)?;
+            if token.fingerprint != fingerprint {
+                return Err(invalid_token());
+            }
+            token.offset
+        };
+
+        Ok(Self {
+            page_size,
+            offset,
+            fingerprint,
+            tokens: tokens.clone(),
+            now,
+        })
+    }
+
+    /// How many rows to fetch: one more than the page, to learn whether
+    /// another page follows without counting the whole table.
+    pub fn fetch_limit(&self) -> i64 {
+        self.page_size as i64 + 1
+    }
+
+    /// The token for the page after one that returned `returned` items, or
+    /// an empty string on the last page.
+    pub fn next_page_token(&self, returned: usize, has_more: bool) -> String {
+        if returned == 0 || !has_more {
+            return String::new();
+        }
+
+        self.tokens.encode(&PageToken {
+            offset: self.offset + returned as u64,
+            fingerprint: self.fingerprint.clone(),
+            issued_at: self.now,
+        })
+    }
+}
+
+/// `size` as requested in `field`: [`DEFAULT_PAGE_SIZE`] when unset, capped
+/// at [`MAX_PAGE_SIZE`].
+pub fn parse_page_size(size: i32, field: &str) -> AppResult<u32> {
+    match size {
+        size if size < 0 => Err(AppError::ValidationError(format!("{} must not be negative", field))),
+        0 => Ok(DEFAULT_PAGE_SIZE),
+        size => Ok((size as u32).min(MAX_PAGE_SIZE)),
+    }
+}
+
+fn fingerprint(params: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for param in params {
+        hasher.update(param.as_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(&hasher.finalize()[..8])
+}
+
+fn invalid_token() -> AppError {
+    AppError::ValidationError("Invalid page_token".to_string()).with_code(ErrorCode::PageTokenInvalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn settings() -> PaginationSettings {
+        PaginationSettings {
+            token_ttl_secs: 3600,
+            max_streams: 4,
+            stream_idle_timeout_secs: 30,
+        }
+    }
+
+    fn tokens() -> PageTokens {
+        PageTokens::new("test-secret", &settings())
+    }
+
+    fn parse(page_size: i32, page_token: &str, params: &[&str]) -> AppResult<PageRequest> {
+        PageRequest::parse_at(page_size, page_token, params, &tokens(), NOW)
+    }
+
+    fn error_code(result: AppResult<PageRequest>) -> ErrorCode {
+        result.err().expect("an error").code()
+    }
+
+    #[test]
+    fn defaults_and_caps_page_size() {
+        assert_eq!(parse(0, "", &[]).unwrap().page_size, DEFAULT_PAGE_SIZE);
+        assert_eq!(parse(1000, "", &[]).unwrap().page_size, MAX_PAGE_SIZE);
+        assert_eq!(parse(5, "", &[]).unwrap().page_size, 5);
+    }
+
+    #[test]
+    fn rejects_negative_page_size() {
+        assert!(parse(-1, "", &[]).is_err());
+    }
+
+    #[test]
+    fn next_page_token_continues_from_offset() {
+        let first = parse(2, "", &["name"]).unwrap();
+        let token = first.next_page_token(2, true);
+
+        let second = parse(2, &token, &["name"]).unwrap();
+        assert_eq!(second.offset, 2);
+
+        let third = parse(2, &second.next_page_token(2, true), &["name"]).unwrap();
+        assert_eq!(third.offset, 4);
+        assert_eq!(third.next_page_token(1, false), "");
+    }
+
+    #[test]
+    fn empty_page_ends_pagination() {
+        let page = parse(10, "", &[]).unwrap();
+        assert_eq!(page.next_page_token(0, true), "");
+    }
+
+    #[test]
+    fn rejects_token_from_different_params() {
+        let token = parse(2, "", &["name"]).unwrap().next_page_token(2, true);
+        assert!(parse(2, &token, &["created_at desc"]).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_token() {
+        assert!(parse(2, "not-a-token", &[]).is_err());
+        assert!(parse(2, "e30.", &[]).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_token() {
+        let token = parse(2, "", &["name"]).unwrap().next_page_token(2, true);
+        let (_, signature) = token.split_once('.').unwrap();
+
+        // A client rewriting the offset can't re-sign the token
+        let forged = PageToken {
+            offset: 1000,
+            fingerprint: fingerprint(&["name"]),
+            issued_at: NOW,
+        };
+        let json = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap());
+        let tampered = format!("{}.{}", json, signature);
+        assert_eq!(error_code(parse(2, &tampered, &["name"])), ErrorCode::PageTokenInvalid);
+
+        let unsigned = token.split_once('.').unwrap().0;
+        assert_eq!(error_code(parse(2, unsigned, &["name"])), ErrorCode::PageTokenInvalid);
+    }
+
+    #[test]
+    fn rejects_token_signed_with_another_key() {
+        let other = PageTokens::new("other-secret", &settings());
+        let token = PageRequest::parse_at(2, "", &["name"], &other, NOW).unwrap().next_page_token(2, true);
+        assert_eq!(error_code(parse(2, &token, &["name"])), ErrorCode::PageTokenInvalid);
+    }
+
+    #[test]
+    fn rejects_expired_token() {
+        let token = parse(2, "", &["name"]).unwrap().next_page_token(2, true);
+
+        let fresh = PageRequest::parse_at(2, &token, &["name"], &tokens(), NOW + 3600);
+        assert_eq!(fresh.unwrap().offset, 2);
+
+        let expired = PageRequest::parse_at(2, &token, &["name"], &tokens(), NOW + 3601);
+        assert_eq!(error_code(expired), ErrorCode::PageTokenExpired);
+    }
+
+    proptest! {
+        #[test]
+        fn page_tokens_round_trip(offset in any::<u64>(), fingerprint in "[0-9a-f]{16}", issued_at in 0..NOW) {
+            let token = PageToken { offset, fingerprint, issued_at };
+            prop_assert_eq!(tokens().decode(&tokens().encode(&token), issued_at).unwrap(), token);
+        }
+
+        #[test]
+        fn arbitrary_tokens_are_rejected_without_panicking(page_token in any::<String>(), page_size in any::<i32>()) {
+            let _ = parse(page_size, &page_token, &["name"]);
+        }
+
+        #[test]
+        fn pages_cover_every_item_once(total in 0u64..500, page_size in 1i32..50) {
+            let mut offset = 0;
+            let mut page_token = String::new();
+            loop {
+                let page = parse(page_size, &page_token, &["name"]).unwrap();
+                prop_assert_eq!(page.offset, offset);
+
+                let fetched = (total - offset).min(page.fetch_limit() as u64);
+                let returned = fetched.min(page.page_size as u64);
+                offset += returned;
+                page_token = page.next_page_token(returned as usize, fetched > returned);
+                if page_token.is_empty() {
+                    break;
+                }
+            }
+            prop_assert_eq!(offset, total);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use chrono::{DateTime, Utc};
+use prost_types::Timestamp;
+
+use crate::errors::{AppError, AppResult};
+
+pub fn to_timestamp(time: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    }
+}
+
+/// Rejects timestamps outside the range chrono (and RFC 3339) can represent.
+pub fn from_timestamp(timestamp: &Timestamp) -> AppResult<DateTime<Utc>> {
+    u32::try_from(timestamp.nanos)
+        .ok()
+        .and_then(|nanos| DateTime::from_timestamp(timestamp.seconds, nanos))
+        .ok_or_else(|| AppError::ValidationError("Invalid timestamp".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_with_nanosecond_precision() {
+        let time = DateTime::parse_from_rfc3339("2024-02-29T12:34:56.123456789Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(from_timestamp(&to_timestamp(time)).unwrap(), time);
+    }
+
+    #[test]
+    fn rejects_negative_nanos() {
+        let timestamp = Timestamp {
+            seconds: 0,
+            nanos: -1,
+        };
+
+        assert!(from_timestamp(&timestamp).is_err());
+    }
+}
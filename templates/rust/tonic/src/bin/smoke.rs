@@ -0,0 +1,44 @@
+//! Runs a smoke-test plan against a running server and exits non-zero if
+//! any check fails, for deploy pipelines to gate on.
+//!
+//! ```text
+//! SERVER_ADDR=http://10.0.0.7:50051 smoke [--plan smoke.toml]
+//! ```
+//!
+//! Without `--plan` the template's default plan runs; a plan file overrides
+//! any of `services`, `health`, `public_methods` and `crud`.
+
+use anyhow::{bail, Context, Result};
+use std::time::Duration;
+use tonic::transport::Endpoint;
+
+use tonic_template::smoke::{self, SmokePlan};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut plan_path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--plan" => plan_path = Some(args.next().context("--plan needs a file")?),
+            other => bail!("unknown argument {} (usage: smoke [--plan <file>])", other),
+        }
+    }
+    let plan = SmokePlan::load(plan_path.as_deref()).context("invalid smoke-test plan")?;
+
+    let addr = std::env::var("SERVER_ADDR").unwrap_or_else(|_| "http://127.0.0.1:50051".to_string());
+    let channel = Endpoint::from_shared(addr.clone())?
+        .connect_timeout(Duration::from_secs(10))
+        .timeout(Duration::from_secs(30))
+        .connect()
+        .await
+        .with_context(|| format!("cannot connect to {}", addr))?;
+
+    println!("Smoke-testing {}", addr);
+    let report = smoke::run(channel, &plan).await;
+    println!("{}", report);
+    if !report.passed() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::metadata::MetadataValue;
+use tonic::transport::{Channel, Endpoint};
+use tonic::Request;
+
+use tonic_template::config::{DiscoverySettings, Settings};
+use tonic_template::discovery::{self, ConsulDiscovery};
+use tonic_template::interceptors::inject_trace_context;
+
+use tonic_template::proto::file::v1::file_service_client::FileServiceClient;
+use tonic_template::proto::file::v1::upload_file_request::Data;
+use tonic_template::proto::file::v1::{DownloadFileRequest, FileMetadata, UploadFileRequest};
+use tonic_template::proto::health::v1::health_service_client::HealthServiceClient;
+use tonic_template::proto::health::v1::HealthCheckRequest;
+use tonic_template::proto::user::v1::user_service_client::UserServiceClient;
+use tonic_template::proto::user::v1::{GetUserRequest, LoginRequest};
+use tonic_template::resilience::Hedger;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let channel = connect().await?;
+
+    // Reads are hedged and retried; all clients share one retry budget
+    let hedger = Hedger::default();
+
+    // Public: no credentials required
+    let health = HealthServiceClient::new(channel.clone());
+    let status = hedger
+        .idempotent("/health.v1.HealthService/Check", || {
+            let mut health = health.clone();
+            async move { health.check(HealthCheckRequest::default()).await }
+        })
+        .await?
+        .into_inner();
+    println!("Health: {:?} (version {})", status.status(), status.version);
+
+    let (Ok(email), Ok(password)) = (std::env::var("EMAIL"), std::env::var("PASSWORD")) else {
+        println!("Set EMAIL and PASSWORD to call authenticated methods");
+        return Ok(());
+    };
+
+    // Calls carry the current trace, so a service calling on behalf of its
+    // own callers shows up in their traces
+    let users = UserServiceClient::with_interceptor(channel.clone(), inject_trace_context);
+    let login = hedger
+        .call("/user.v1.UserService/Login", || {
+            let mut users = users.clone();
+            async move { users.login(LoginRequest { email, password }).await }
+        })
+        .await?
+        .into_inner();
+
+    // Authenticated: bearer token in the authorization metadata
+    let authorization: MetadataValue<_> = format!("Bearer {}", login.access_token).parse()?;
+    let user_id = login.user.map(|user| user.id).unwrap_or_default();
+    let user = hedger
+        .idempotent("/user.v1.UserService/GetUser", || {
+            let mut users = users.clone();
+            let mut request = Request::new(GetUserRequest { id: user_id.clone() });
+            request.metadata_mut().insert("authorization", authorization.clone());
+            async move { users.get_user(request).await }
+        })
+        .await?
+        .into_inner();
+    println!("Logged in as {:?}", user.user);
+
+    if let Ok(path) = std::env::var("UPLOAD_FILE") {
+        round_trip_file(FileServiceClient::new(channel), &path, authorization).await?;
+    }
+
+    Ok(())
+}
+
+/// A channel to `SERVER_SERVICE` resolved through the Consul agent at
+/// `CONSUL_HTTP_ADDR`, balanced across its instances, or else to
+/// `SERVER_ADDR`.
+async fn connect() -> Result<Channel> {
+    if let Ok(service) = std::env::var("SERVER_SERVICE") {
+        let mut settings: DiscoverySettings = Settings::defaults()?.build()?.get("discovery")?;
+        if let Ok(consul_url) = std::env::var("CONSUL_HTTP_ADDR") {
+            settings.consul_url = consul_url;
+        }
+        let consul = Arc::new(ConsulDiscovery::from_settings(&settings)?);
+        let refresh_interval = Duration::from_secs(settings.refresh_interval_secs);
+        let (channel, _resolver) = discovery::balanced_channel(consul, &service, refresh_interval);
+        return Ok(channel);
+    }
+
+    let addr = std::env::var("SERVER_ADDR").unwrap_or_else(|_| "http://127.0.0.1:50051".to_string());
+    Ok(Endpoint::from_shared(addr)?.connect().await?)
+}
+
+/// Uploads a file in chunks, then downloads it again and compares checksums.
+async fn round_trip_file(
+    mut files: FileServiceClient<Channel>,
+    path: &str,
+    authorization: MetadataValue<tonic::metadata::Ascii>,
+) -> Result<()> {
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    let content = tokio::fs::read(path).await?;
+    let sha256 = hex::encode(Sha256::digest(&content));
+
+    let metadata = UploadFileRequest {
+        data: Some(Data::Metadata(FileMetadata {
+            filename: path.rsplit('/').next().unwrap_or(path).to_string(),
+            content_type: String::new(),
+            size: content.len() as u64,
+            sha256: sha256.clone(),
+        })),
+    };
+    let chunks = content.chunks(CHUNK_SIZE).map(|chunk| UploadFileRequest {
+        data: Some(Data::Chunk(chunk.to_vec())),
+    });
+    let messages: Vec<_> = std::iter::once(metadata).chain(chunks).collect();
+
+    let mut request = Request::new(tokio_stream::iter(messages));
+    request.metadata_mut().insert("authorization", authorization.clone());
+    let uploaded = files.upload_file(request).await?.into_inner();
+    println!("Uploaded {} ({} bytes)", uploaded.file_id, uploaded.size);
+
+    let mut request = Request::new(DownloadFileRequest {
+        file_id: uploaded.file_id,
+    });
+    request.metadata_mut().insert("authorization", authorization);
+    let mut stream = files.download_file(request).await?.into_inner();
+
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = stream.message().await? {
+        hasher.update(&chunk.chunk);
+        let received = chunk.offset + chunk.chunk.len() as u64;
+        println!("Downloaded {}/{} bytes", received, chunk.total_size);
+    }
+
+    if hex::encode(hasher.finalize()) != sha256 {
+        bail!("downloaded content does not match the upload");
+    }
+    println!("Checksum verified");
+
+    Ok(())
+}
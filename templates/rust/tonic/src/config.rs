@@ -1,17 +1,118 @@
-use config::{Config, ConfigError, Environment, File};
+use config::builder::{ConfigBuilder, DefaultState};
+use config::{Config, ConfigError};
+use platform_auth::workload::WorkloadIdentitySettings;
+use platform_core::lifecycle::LifecycleSettings;
+use platform_core::rate_limit::BackendSettings;
+use platform_observability::log_level::LogLevelSettings;
+use platform_observability::otel::OtelSettings;
+use platform_observability::propagation::{self, PropagationSettings};
 use serde::Deserialize;
+use std::collections::HashMap;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
     pub server: ServerSettings,
     pub database: DatabaseSettings,
     pub jwt: JwtSettings,
+    pub auth: AuthSettings,
+    pub storage: StorageSettings,
+    pub logging: LoggingSettings,
+    pub limits: LimitSettings,
+    pub maintenance: MaintenanceSettings,
+    pub operations: OperationSettings,
+    pub pagination: PaginationSettings,
+    pub password_reset: PasswordResetSettings,
+    pub discovery: DiscoverySettings,
+    pub lifecycle: LifecycleSettings,
+    pub log_level: LogLevelSettings,
+    /// Span export and trace context propagation.
+    pub otel: OtelSettings,
+    /// Mesh and tracing headers handed on to the services a request calls.
+    pub propagation: PropagationSettings,
+    /// Other services calling with Kubernetes or SPIFFE tokens.
+    #[serde(default)]
+    pub workload_identity: WorkloadIdentitySettings,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ServerSettings {
     pub host: String,
     pub port: u16,
+    /// Interval between HTTP/2 PINGs on idle connections; keep it below the
+    /// load balancer's idle timeout. 0 disables.
+    pub http2_keepalive_interval_secs: u64,
+    /// How long to wait for a PING acknowledgement before closing the
+    /// connection.
+    pub keepalive_timeout_secs: u64,
+    /// Connections are sent a GOAWAY after this age (with ±10% jitter) so
+    /// clients reconnect and rebalance. 0 disables.
+    pub max_connection_age_secs: u64,
+    /// Time in-flight calls get to finish after the GOAWAY.
+    pub max_connection_age_grace_secs: u64,
+    /// Time between SIGTERM and closing the listeners, while health checks
+    /// already answer NOT_SERVING, for load balancers to stop sending calls.
+    pub drain_delay_secs: u64,
+    /// Time in-flight calls and streams get to finish once the listeners
+    /// close; connections still open after it are dropped.
+    pub drain_timeout_secs: u64,
+    /// TCP keepalive probe idle time. 0 disables.
+    pub tcp_keepalive_secs: u64,
+    /// Largest message a service accepts, after decompression.
+    pub max_decoding_message_size: usize,
+    /// Largest message a service sends.
+    pub max_encoding_message_size: usize,
+    /// Addresses to accept connections on. Empty means a single plaintext
+    /// public listener on `host:port`.
+    pub listeners: Vec<ListenerSettings>,
+    /// Serve `grpc.reflection.v1alpha.ServerReflection` on public listeners,
+    /// for grpcurl and the `smoke` deploy gate.
+    pub reflection: bool,
+}
+
+impl ServerSettings {
+    /// The configured listeners, or `host:port` when none are.
+    pub fn listeners(&self) -> Vec<ListenerSettings> {
+        if !self.listeners.is_empty() {
+            return self.listeners.clone();
+        }
+        vec![ListenerSettings {
+            address: format!("{}:{}", self.host, self.port),
+            role: ListenerRole::Public,
+            tls: None,
+        }]
+    }
+}
+
+/// An address the server accepts connections on, e.g. `0.0.0.0:50051` or
+/// `[::]:50051`. IPv6 wildcards only accept IPv6, so listing both families
+/// on one port gives a dual-stack server.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ListenerSettings {
+    pub address: String,
+    #[serde(default)]
+    pub role: ListenerRole,
+    /// Serves gRPC over TLS when set.
+    pub tls: Option<TlsSettings>,
+}
+
+/// What a listener serves.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenerRole {
+    /// Every service.
+    #[default]
+    Public,
+    /// Health (`grpc.health.v1.Health` and `health.v1.HealthService`) and
+    /// `admin.v1.AdminService`, for probes and operators on an internal port.
+    Admin,
+}
+
+/// PEM files for a TLS listener.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TlsSettings {
+    /// Certificate chain, leaf first.
+    pub cert_path: String,
+    pub key_path: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -22,29 +123,282 @@ pub struct DatabaseSettings {
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct JwtSettings {
-    pub secret: String,
+    /// Single signing secret, used when no `keys` are configured.
+    pub secret: Option<String>,
+    /// Signing keys identified by `kid`; all of them validate tokens.
+    #[serde(default)]
+    pub keys: Vec<JwtKeySettings>,
+    /// The key that signs newly issued tokens.
+    pub active_kid: Option<String>,
     pub access_token_expiry: i64,
     pub refresh_token_expiry: i64,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct JwtKeySettings {
+    pub kid: String,
+    pub secret: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AuthSettings {
+    /// Methods callable without a token, as full gRPC paths
+    /// (`/user.v1.UserService/Login`) or whole services (`/health.v1.HealthService/*`).
+    pub public_methods: Vec<String>,
+    /// Methods only users holding a role may call, written the same way,
+    /// e.g. `{ method = "/user.v1.UserService/ListUsers", role = "support" }`.
+    /// A method listed with several roles accepts any of them.
+    #[serde(default)]
+    pub method_roles: Vec<MethodRole>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MethodRole {
+    pub method: String,
+    pub role: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct OperationSettings {
+    /// How often watch streams re-read the operation, to pick up updates
+    /// made by other replicas.
+    pub watch_poll_interval_ms: u64,
+}
+
+/// Resetting a forgotten password with a token sent by email.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PasswordResetSettings {
+    pub token_ttl_secs: i64,
+    /// Tokens sent to one email address per window.
+    pub send_limit: i64,
+    pub send_window_secs: i64,
+    /// Where links point; the token is appended as `?token=`. The page
+    /// passes it with the new password to `ResetPassword`.
+    pub reset_url: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PaginationSettings {
+    /// How long a `page_token` stays valid, so offsets into a changing
+    /// table aren't followed indefinitely.
+    pub token_ttl_secs: u64,
+    /// `ListUsersStream` calls served at once. Each holds a database
+    /// connection, so keep it well below `database.max_connections`.
+    pub max_streams: usize,
+    /// How long a stream waits for the client to take a batch before it
+    /// gives up its connection and fails with DEADLINE_EXCEEDED.
+    pub stream_idle_timeout_secs: u64,
+}
+
+/// Registering this instance and finding downstream services; see
+/// [`discovery`](crate::discovery).
+#[derive(Debug, Deserialize, Clone)]
+pub struct DiscoverySettings {
+    pub provider: DiscoveryProvider,
+    /// The name other services resolve this one by.
+    pub service_name: String,
+    /// Unique per replica; defaults to `{service_name}-{host}-{port}`.
+    pub instance_id: Option<String>,
+    /// The address other services reach this replica at; defaults to the
+    /// first public listener's, which must then not be a wildcard address.
+    pub advertise_host: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The instance is marked unhealthy unless it checks in this often; it
+    /// does so every half period.
+    pub ttl_secs: u64,
+    /// How long an instance stays registered while unhealthy, so crashed
+    /// replicas disappear without deregistering.
+    pub deregister_after_secs: u64,
+    /// How often clients re-resolve downstream services.
+    pub refresh_interval_secs: u64,
+    pub timeout_secs: u64,
+    pub consul_url: String,
+    pub consul_token: Option<String>,
+    pub kubernetes_api_url: String,
+    /// Directory with the pod's `token`, `ca.crt` and `namespace`.
+    pub kubernetes_service_account_path: String,
+    /// Namespace of downstream services; defaults to the pod's own.
+    pub kubernetes_namespace: Option<String>,
+    /// Which endpoint port to use when services expose several.
+    pub kubernetes_port_name: Option<String>,
+    /// Fixed addresses per service for the `static` provider.
+    #[serde(default)]
+    pub static_services: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiscoveryProvider {
+    /// No registration; downstream addresses come from configuration.
+    None,
+    /// Addresses from `discovery.static_services`.
+    Static,
+    Consul,
+    /// Reads the endpoints of Kubernetes services. Registration is left to
+    /// the kubelet's readiness probes.
+    Kubernetes,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StorageSettings {
+    /// Directory uploaded file contents are written to.
+    pub path: String,
+    pub max_upload_bytes: u64,
+    /// Size of the chunks downloads are streamed in.
+    pub chunk_size: usize,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LoggingSettings {
+    /// Fraction of calls (0.0-1.0) whose payloads are logged.
+    pub payload_sample_rate: f64,
+    /// Payload bytes logged per direction; the rest is truncated.
+    pub max_payload_bytes: usize,
+    /// Methods whose payloads are never logged, as full gRPC paths or
+    /// `/package.Service/*`.
+    pub redacted_methods: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LimitSettings {
+    /// Calls per second per caller across methods without their own rule.
+    /// 0 disables.
+    pub default_rate_per_second: f64,
+    pub default_burst: u32,
+    /// Concurrent calls per method without their own rule. 0 disables.
+    pub default_max_concurrency: usize,
+    /// Rules replacing the defaults for matching methods. Without any
+    /// configured, sign-in and sign-up are held to a trickle per caller.
+    #[serde(default = "default_method_limits")]
+    pub methods: Vec<MethodLimitSettings>,
+    /// Where token buckets are kept: `memory` per replica, or `redis` (with
+    /// the `redis` feature) shared by all of them.
+    #[serde(default)]
+    pub backend: BackendSettings,
+}
+
+fn default_method_limits() -> Vec<MethodLimitSettings> {
+    ["/user.v1.UserService/Login", "/user.v1.UserService/Register"]
+        .into_iter()
+        .map(|method| MethodLimitSettings {
+            method: method.to_string(),
+            rate_per_second: Some(0.5),
+            burst: Some(10),
+            max_concurrency: None,
+        })
+        .collect()
+}
+
+/// Maintenance mode: every call except health checks fails with
+/// UNAVAILABLE and a retry hint.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MaintenanceSettings {
+    pub enabled: bool,
+    pub retry_after_secs: u64,
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MethodLimitSettings {
+    /// Full gRPC path or `/package.Service/*`.
+    pub method: String,
+    pub rate_per_second: Option<f64>,
+    /// Defaults to one second's worth of calls.
+    pub burst: Option<u32>,
+    pub max_concurrency: Option<usize>,
+}
+
 impl Settings {
     pub fn new() -> Result<Self, ConfigError> {
-        let run_mode = std::env::var("RUN_MODE").unwrap_or_else(|_| "development".into());
+        platform_core::config::load(Self::defaults()?, "TONIC")
+    }
 
-        let config = Config::builder()
-            // Start off with default values
+    /// Builder holding only the default values, e.g. for tests to add
+    /// overrides to.
+    pub fn defaults() -> Result<ConfigBuilder<DefaultState>, ConfigError> {
+        Config::builder()
             .set_default("server.host", "0.0.0.0")?
             .set_default("server.port", 50051)?
+            .set_default("server.http2_keepalive_interval_secs", 60)?
+            .set_default("server.keepalive_timeout_secs", 20)?
+            .set_default("server.max_connection_age_secs", 1800)?
+            .set_default("server.max_connection_age_grace_secs", 30)?
+            .set_default("server.drain_delay_secs", 5)?
+            .set_default("server.drain_timeout_secs", 30)?
+            .set_default("server.tcp_keepalive_secs", 60)?
+            .set_default("server.max_decoding_message_size", 4 * 1024 * 1024)?
+            .set_default("server.max_encoding_message_size", 4 * 1024 * 1024)?
+            .set_default("server.listeners", Vec::<String>::new())?
+            .set_default("server.reflection", true)?
             .set_default("database.max_connections", 10)?
             .set_default("jwt.access_token_expiry", 3600)?
             .set_default("jwt.refresh_token_expiry", 86400)?
-            // Add in settings from config file
-            .add_source(File::with_name("config/default").required(false))
-            .add_source(File::with_name(&format!("config/{}", run_mode)).required(false))
-            // Add in settings from environment variables (with prefix TONIC)
-            .add_source(Environment::with_prefix("TONIC").separator("_"))
-            .build()?;
-
-        config.try_deserialize()
+            .set_default("storage.path", "./data/files")?
+            .set_default("storage.max_upload_bytes", 100 * 1024 * 1024)?
+            .set_default("storage.chunk_size", 64 * 1024)?
+            .set_default("logging.payload_sample_rate", 0.0)?
+            .set_default("logging.max_payload_bytes", 1024)?
+            .set_default(
+                "logging.redacted_methods",
+                vec![
+                    "/user.v1.UserService/CreateUser",
+                    "/user.v1.UserService/Login",
+                    "/user.v1.UserService/Register",
+                    "/user.v1.UserService/RefreshToken",
+                    "/user.v1.UserService/ValidateToken",
+                    "/user.v1.UserService/ResetPassword",
+                    "/file.v1.FileService/*",
+                ],
+            )?
+            .set_default("limits.default_rate_per_second", 50.0)?
+            .set_default("limits.default_burst", 100)?
+            .set_default("limits.default_max_concurrency", 256)?
+            .set_default("maintenance.enabled", false)?
+            .set_default("maintenance.retry_after_secs", 300)?
+            .set_default("maintenance.message", "Service is down for maintenance")?
+            .set_default("operations.watch_poll_interval_ms", 2000)?
+            .set_default("pagination.token_ttl_secs", 3600)?
+            .set_default("pagination.max_streams", 4)?
+            .set_default("pagination.stream_idle_timeout_secs", 30)?
+            .set_default("password_reset.token_ttl_secs", 1800)?
+            .set_default("password_reset.send_limit", 3)?
+            .set_default("password_reset.send_window_secs", 3600)?
+            .set_default("password_reset.reset_url", "http://localhost:3000/reset-password")?
+            .set_default("lifecycle.hook_timeout_secs", 10)?
+            .set_default("lifecycle.shutdown_timeout_secs", 30)?
+            .set_default("log_level.default", "info")?
+            .set_default("log_level.revert_after_secs", 900)?
+            .set_default("log_level.max_revert_after_secs", 86400)?
+            .set_default("otel.enabled", false)?
+            .set_default("otel.endpoint", "http://localhost:4317")?
+            .set_default("otel.service_name", "tonic-template")?
+            .set_default("otel.sample_ratio", 1.0)?
+            .set_default("otel.filter", "info")?
+            .set_default("otel.timeout_secs", 10)?
+            .set_default("propagation.headers", propagation::DEFAULT_HEADERS.to_vec())?
+            .set_default("discovery.provider", "none")?
+            .set_default("discovery.service_name", "tonic-template")?
+            .set_default("discovery.ttl_secs", 15)?
+            .set_default("discovery.deregister_after_secs", 60)?
+            .set_default("discovery.refresh_interval_secs", 10)?
+            .set_default("discovery.timeout_secs", 5)?
+            .set_default("discovery.consul_url", "http://127.0.0.1:8500")?
+            .set_default("discovery.kubernetes_api_url", "https://kubernetes.default.svc")?
+            .set_default("discovery.kubernetes_service_account_path", "/var/run/secrets/kubernetes.io/serviceaccount")?
+            .set_default(
+                "auth.public_methods",
+                vec![
+                    "/grpc.health.v1.Health/*",
+                    "/health.v1.HealthService/*",
+                    "/grpc.reflection.v1alpha.ServerReflection/*",
+                    "/user.v1.UserService/Login",
+                    "/user.v1.UserService/Register",
+                    "/user.v1.UserService/RefreshToken",
+                    "/user.v1.UserService/ValidateToken",
+                    "/user.v1.UserService/RequestPasswordReset",
+                    "/user.v1.UserService/ResetPassword",
+                ],
+            )
     }
 }
\ No newline at end of file
@@ -0,0 +1,159 @@
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+use crate::config::DiscoverySettings;
+use crate::discovery::{provider_failed, Discovery, Instance};
+use crate::errors::AppResult;
+
+/// Registers with and resolves through a Consul agent's HTTP API.
+///
+/// Instances are registered with a TTL check, so Consul stops returning
+/// them if they stop checking in and removes them after
+/// `discovery.deregister_after_secs`.
+pub struct ConsulDiscovery {
+    client: Client,
+    url: String,
+    token: Option<String>,
+    ttl: Duration,
+    deregister_after: Duration,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServiceEntry {
+    #[serde(rename = "Node")]
+    node: Node,
+    #[serde(rename = "Service")]
+    service: Service,
+}
+
+#[derive(Debug, Deserialize)]
+struct Node {
+    #[serde(rename = "Address")]
+    address: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Service {
+    #[serde(rename = "Address")]
+    address: String,
+    #[serde(rename = "Port")]
+    port: u16,
+}
+
+impl ConsulDiscovery {
+    pub fn from_settings(settings: &DiscoverySettings) -> Result<Self, config::ConfigError> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .build()
+            .map_err(|e| config::ConfigError::Message(format!("failed to build the Consul client: {}", e)))?;
+
+        Ok(Self {
+            client,
+            url: settings.consul_url.trim_end_matches('/').to_string(),
+            token: settings.consul_token.clone(),
+            ttl: Duration::from_secs(settings.ttl_secs),
+            deregister_after: Duration::from_secs(settings.deregister_after_secs),
+        })
+    }
+
+    fn put(&self, path: &str) -> RequestBuilder {
+        self.authorized(self.client.put(format!("{}{}", self.url, path)))
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => request.header("X-Consul-Token", token),
+            None => request,
+        }
+    }
+
+    async fn send(&self, request: RequestBuilder) -> AppResult<reqwest::Response> {
+        let response = request.send().await.map_err(|e| provider_failed("Consul", e))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(provider_failed("Consul", format!("{} {}", status, body.trim())));
+        }
+        Ok(response)
+    }
+}
+
+fn check_id(instance: &Instance) -> String {
+    format!("service:{}", instance.id)
+}
+
+#[tonic::async_trait]
+impl Discovery for ConsulDiscovery {
+    async fn register(&self, instance: &Instance) -> AppResult<()> {
+        let registration = json!({
+            "ID": instance.id,
+            "Name": instance.service,
+            "Address": instance.host,
+            "Port": instance.port,
+            "Tags": instance.tags,
+            "Check": {
+                "CheckID": check_id(instance),
+                "TTL": format!("{}s", self.ttl.as_secs()),
+                "DeregisterCriticalServiceAfter": format!("{}s", self.deregister_after.as_secs()),
+            },
+        });
+        self.send(self.put("/v1/agent/service/register").json(&registration)).await?;
+
+        // Healthy right away rather than after the first check-in
+        self.heartbeat(instance).await
+    }
+
+    async fn heartbeat(&self, instance: &Instance) -> AppResult<()> {
+        self.send(self.put(&format!("/v1/agent/check/pass/{}", check_id(instance)))).await?;
+        Ok(())
+    }
+
+    async fn deregister(&self, instance: &Instance) -> AppResult<()> {
+        self.send(self.put(&format!("/v1/agent/service/deregister/{}", instance.id))).await?;
+        Ok(())
+    }
+
+    async fn resolve(&self, service: &str) -> AppResult<Vec<String>> {
+        let request = self
+            .client
+            .get(format!("{}/v1/health/service/{}", self.url, service))
+            .query(&[("passing", "true")]);
+        let entries: Vec<ServiceEntry> = self
+            .send(self.authorized(request))
+            .await?
+            .json()
+            .await
+            .map_err(|e| provider_failed("Consul", e))?;
+
+        Ok(entries.into_iter().map(address).collect())
+    }
+}
+
+/// The service address, or the node's when the service didn't set one.
+fn address(entry: ServiceEntry) -> String {
+    let host = if entry.service.address.is_empty() {
+        entry.node.address
+    } else {
+        entry.service.address
+    };
+    format!("{}:{}", host, entry.service.port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_fall_back_to_the_node() {
+        let entries: Vec<ServiceEntry> = serde_json::from_value(serde_json::json!([
+            { "Node": { "Address": "10.0.0.1" }, "Service": { "Address": "10.1.0.1", "Port": 50051 } },
+            { "Node": { "Address": "10.0.0.2" }, "Service": { "Address": "", "Port": 50052 } },
+        ]))
+        .unwrap();
+
+        let addresses: Vec<_> = entries.into_iter().map(address).collect();
+        assert_eq!(addresses, ["10.1.0.1:50051", "10.0.0.2:50052"]);
+    }
+}
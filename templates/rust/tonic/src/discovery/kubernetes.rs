@@ -0,0 +1,158 @@
+use reqwest::{Certificate, Client};
+use serde::Deserialize;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::DiscoverySettings;
+use crate::discovery::{provider_failed, Discovery, Instance};
+use crate::errors::AppResult;
+
+/// Resolves services through the Kubernetes `Endpoints` API, authenticating
+/// with the pod's service account, which needs `get` on `endpoints`.
+///
+/// Pods are added to and removed from endpoints by their readiness probes,
+/// so registration does nothing.
+pub struct KubernetesDiscovery {
+    client: Client,
+    api_url: String,
+    token: String,
+    namespace: String,
+    port_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Endpoints {
+    #[serde(default)]
+    subsets: Vec<Subset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Subset {
+    /// Ready addresses only; unready ones are listed separately.
+    #[serde(default)]
+    addresses: Vec<EndpointAddress>,
+    #[serde(default)]
+    ports: Vec<EndpointPort>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EndpointAddress {
+    ip: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EndpointPort {
+    name: Option<String>,
+    port: u16,
+}
+
+impl KubernetesDiscovery {
+    pub fn from_settings(settings: &DiscoverySettings) -> Result<Self, config::ConfigError> {
+        let account = Path::new(&settings.kubernetes_service_account_path);
+        let read = |file: &str| {
+            std::fs::read_to_string(account.join(file)).map_err(|e| {
+                config::ConfigError::Message(format!(
+                    "failed to read the service account {}: {}",
+                    account.join(file).display(),
+                    e
+                ))
+            })
+        };
+
+        let token = read("token")?.trim().to_string();
+        let namespace = match &settings.kubernetes_namespace {
+            Some(namespace) => namespace.clone(),
+            None => read("namespace")?.trim().to_string(),
+        };
+        let ca = Certificate::from_pem(read("ca.crt")?.as_bytes())
+            .map_err(|e| config::ConfigError::Message(format!("invalid cluster CA certificate: {}", e)))?;
+
+        let client = Client::builder()
+            .add_root_certificate(ca)
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .build()
+            .map_err(|e| config::ConfigError::Message(format!("failed to build the Kubernetes client: {}", e)))?;
+
+        Ok(Self {
+            client,
+            api_url: settings.kubernetes_api_url.trim_end_matches('/').to_string(),
+            token,
+            namespace,
+            port_name: settings.kubernetes_port_name.clone(),
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Discovery for KubernetesDiscovery {
+    async fn register(&self, _instance: &Instance) -> AppResult<()> {
+        Ok(())
+    }
+
+    async fn heartbeat(&self, _instance: &Instance) -> AppResult<()> {
+        Ok(())
+    }
+
+    async fn deregister(&self, _instance: &Instance) -> AppResult<()> {
+        Ok(())
+    }
+
+    async fn resolve(&self, service: &str) -> AppResult<Vec<String>> {
+        let response = self
+            .client
+            .get(format!("{}/api/v1/namespaces/{}/endpoints/{}", self.api_url, self.namespace, service))
+            .bearer_auth(&self.token)
+            .send()
+            .await
+            .map_err(|e| provider_failed("Kubernetes", e))?;
+        if !response.status().is_success() {
+            return Err(provider_failed("Kubernetes", response.status()));
+        }
+
+        let endpoints: Endpoints = response.json().await.map_err(|e| provider_failed("Kubernetes", e))?;
+        Ok(addresses(endpoints, self.port_name.as_deref()))
+    }
+}
+
+/// Every ready address with the port named `port_name`, or the first port.
+fn addresses(endpoints: Endpoints, port_name: Option<&str>) -> Vec<String> {
+    endpoints
+        .subsets
+        .into_iter()
+        .filter_map(|subset| {
+            let port = match port_name {
+                Some(name) => subset.ports.iter().find(|port| port.name.as_deref() == Some(name)),
+                None => subset.ports.first(),
+            }?
+            .port;
+            Some(subset.addresses.into_iter().map(move |address| format!("{}:{}", address.ip, port)))
+        })
+        .flatten()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoints() -> Endpoints {
+        serde_json::from_value(serde_json::json!({
+            "subsets": [
+                {
+                    "addresses": [{ "ip": "10.2.0.4" }, { "ip": "10.2.0.5" }],
+                    "notReadyAddresses": [{ "ip": "10.2.0.6" }],
+                    "ports": [{ "name": "metrics", "port": 9090 }, { "name": "grpc", "port": 50051 }]
+                },
+                { "addresses": [{ "ip": "10.2.1.4" }], "ports": [{ "name": "metrics", "port": 9090 }] }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn ready_addresses_use_the_named_port() {
+        assert_eq!(addresses(endpoints(), Some("grpc")), ["10.2.0.4:50051", "10.2.0.5:50051"]);
+        assert_eq!(addresses(endpoints(), None), ["10.2.0.4:9090", "10.2.0.5:9090", "10.2.1.4:9090"]);
+        assert!(addresses(Endpoints { subsets: Vec::new() }, None).is_empty());
+    }
+}
@@ -0,0 +1,331 @@
+//! Service discovery: registering this instance and resolving the addresses
+//! of downstream services.
+//!
+//! The provider is picked by `discovery.provider`. [`ConsulDiscovery`]
+//! registers the instance with the local Consul agent under a TTL check,
+//! which [`maintain_registration`] keeps passing and removes on shutdown.
+//! [`KubernetesDiscovery`] reads service endpoints from the API server and
+//! leaves registration to readiness probes. [`StaticDiscovery`] serves
+//! addresses from configuration, for development and tests.
+//!
+//! Clients get a channel that follows a service as replicas come and go:
+//!
+//! ```ignore
+//! let discovery = discovery::from_settings(&settings.discovery)?;
+//! let (channel, _resolver) = discovery::balanced_channel(discovery, "users", refresh_interval);
+//! let users = UserServiceClient::new(channel);
+//! ```
+
+use platform_core::lifecycle::{Hook, Lifecycle};
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tonic::transport::{Channel, Endpoint};
+use tower::discover::Change;
+
+use crate::config::{DiscoveryProvider, DiscoverySettings, ListenerRole, ServerSettings, Settings};
+use crate::errors::{AppError, AppResult, RetryHint};
+
+pub mod consul;
+pub mod kubernetes;
+
+pub use consul::ConsulDiscovery;
+pub use kubernetes::KubernetesDiscovery;
+
+/// Endpoint changes buffered between the resolver and the channel.
+const CHANGE_CAPACITY: usize = 64;
+
+/// This replica as registered with the provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instance {
+    pub id: String,
+    pub service: String,
+    pub host: String,
+    pub port: u16,
+    pub tags: Vec<String>,
+}
+
+impl Instance {
+    /// The instance behind the first public listener.
+    pub fn from_settings(settings: &DiscoverySettings, server: &ServerSettings) -> Result<Self, config::ConfigError> {
+        let address = server
+            .listeners()
+            .into_iter()
+            .find(|listener| listener.role == ListenerRole::Public)
+            .and_then(|listener| listener.address.parse::<SocketAddr>().ok())
+            .ok_or_else(|| config::ConfigError::Message("server.listeners needs a public listener".to_string()))?;
+        let host = settings.advertise_host.clone().unwrap_or_else(|| address.ip().to_string());
+        if host.is_empty() || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_unspecified()) {
+            return Err(config::ConfigError::Message(
+                "discovery.advertise_host is required when the listener is a wildcard address".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            id: settings
+                .instance_id
+                .clone()
+                .unwrap_or_else(|| format!("{}-{}-{}", settings.service_name, host, address.port())),
+            service: settings.service_name.clone(),
+            host,
+            port: address.port(),
+            tags: settings.tags.clone(),
+        })
+    }
+}
+
+#[tonic::async_trait]
+pub trait Discovery: Send + Sync {
+    /// Announces `instance`; nothing for providers that discover instances
+    /// on their own.
+    async fn register(&self, instance: &Instance) -> AppResult<()>;
+
+    /// Tells the provider `instance` is still alive.
+    async fn heartbeat(&self, instance: &Instance) -> AppResult<()>;
+
+    async fn deregister(&self, instance: &Instance) -> AppResult<()>;
+
+    /// Healthy addresses of `service`, as `host:port`.
+    async fn resolve(&self, service: &str) -> AppResult<Vec<String>>;
+}
+
+/// Builds the provider selected by `discovery.provider`; `None` without one.
+pub fn from_settings(settings: &DiscoverySettings) -> Result<Option<Arc<dyn Discovery>>, config::ConfigError> {
+    let discovery: Arc<dyn Discovery> = match settings.provider {
+        DiscoveryProvider::None => return Ok(None),
+        DiscoveryProvider::Static => Arc::new(StaticDiscovery::new(settings.static_services.clone())),
+        DiscoveryProvider::Consul => Arc::new(ConsulDiscovery::from_settings(settings)?),
+        DiscoveryProvider::Kubernetes => Arc::new(KubernetesDiscovery::from_settings(settings)?),
+    };
+    Ok(Some(discovery))
+}
+
+/// Fixed addresses per service.
+#[derive(Debug, Default)]
+pub struct StaticDiscovery {
+    services: HashMap<String, Vec<String>>,
+}
+
+impl StaticDiscovery {
+    pub fn new(services: HashMap<String, Vec<String>>) -> Self {
+        Self { services }
+    }
+}
+
+#[tonic::async_trait]
+impl Discovery for StaticDiscovery {
+    async fn register(&self, _instance: &Instance) -> AppResult<()> {
+        Ok(())
+    }
+
+    async fn heartbeat(&self, _instance: &Instance) -> AppResult<()> {
+        Ok(())
+    }
+
+    async fn deregister(&self, _instance: &Instance) -> AppResult<()> {
+        Ok(())
+    }
+
+    async fn resolve(&self, service: &str) -> AppResult<Vec<String>> {
+        Ok(self.services.get(service).cloned().unwrap_or_default())
+    }
+}
+
+/// Keeps this instance registered with Consul from when the server is
+/// ready until shutdown, when it is deregistered. The other providers don't
+/// register instances.
+pub fn register_hooks(lifecycle: &mut Lifecycle, settings: &Settings) -> Result<(), config::ConfigError> {
+    let discovery = match from_settings(&settings.discovery)? {
+        Some(discovery) if settings.discovery.provider == DiscoveryProvider::Consul => discovery,
+        _ => return Ok(()),
+    };
+    let instance = Instance::from_settings(&settings.discovery, &settings.server)?;
+    let ttl = Duration::from_secs(settings.discovery.ttl_secs);
+
+    let (stop, stopped) = oneshot::channel::<()>();
+    let (started, registration) = oneshot::channel();
+    lifecycle.on_ready(Hook::new("discovery", move || async move {
+        let shutdown = async {
+            let _ = stopped.await;
+        };
+        let _ = started.send(tokio::spawn(maintain_registration(discovery, instance, ttl, shutdown)));
+        Ok(())
+    }));
+    lifecycle.on_shutdown(Hook::new("discovery", move || async move {
+        let _ = stop.send(());
+        if let Ok(registration) = registration.await {
+            registration.await?;
+        }
+        Ok(())
+    }));
+
+    Ok(())
+}
+
+/// Registers `instance`, checks in every half `ttl` until `shutdown`
+/// completes, then deregisters it.
+///
+/// A failed check-in registers the instance again, in case the provider
+/// lost it, e.g. after an agent restart.
+pub async fn maintain_registration(
+    discovery: Arc<dyn Discovery>,
+    instance: Instance,
+    ttl: Duration,
+    shutdown: impl Future<Output = ()>,
+) {
+    let mut registered = register(discovery.as_ref(), &instance).await;
+    let mut interval = tokio::time::interval(ttl / 2);
+    interval.tick().await;
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = &mut shutdown => break,
+        }
+
+        if registered {
+            if let Err(e) = discovery.heartbeat(&instance).await {
+                tracing::warn!(instance = %instance.id, "Service discovery check-in failed: {}", e);
+                registered = false;
+            }
+        }
+        if !registered {
+            registered = register(discovery.as_ref(), &instance).await;
+        }
+    }
+
+    match discovery.deregister(&instance).await {
+        Ok(()) => tracing::info!(instance = %instance.id, "Deregistered from service discovery"),
+        Err(e) => tracing::warn!(instance = %instance.id, "Failed to deregister from service discovery: {}", e),
+    }
+}
+
+async fn register(discovery: &dyn Discovery, instance: &Instance) -> bool {
+    match discovery.register(instance).await {
+        Ok(()) => {
+            tracing::info!(instance = %instance.id, service = %instance.service, "Registered with service discovery");
+            true
+        }
+        Err(e) => {
+            tracing::warn!(instance = %instance.id, "Service discovery registration failed: {}", e);
+            false
+        }
+    }
+}
+
+/// A channel balancing calls across the addresses of `service`, resolved
+/// now and every `refresh_interval`. When resolution fails the last known
+/// addresses are kept. The resolver stops once the channel and its clones
+/// are dropped.
+pub fn balanced_channel(
+    discovery: Arc<dyn Discovery>,
+    service: &str,
+    refresh_interval: Duration,
+) -> (Channel, JoinHandle<()>) {
+    let (channel, changes) = Channel::balance_channel::<String>(CHANGE_CAPACITY);
+    let service = service.to_string();
+
+    let resolver = tokio::spawn(async move {
+        let mut known = BTreeSet::new();
+        let mut interval = tokio::time::interval(refresh_interval);
+
+        loop {
+            interval.tick().await;
+            let current: BTreeSet<String> = match discovery.resolve(&service).await {
+                Ok(addresses) => addresses.into_iter().collect(),
+                Err(e) => {
+                    tracing::warn!(service = %service, "Failed to resolve service: {}", e);
+                    continue;
+                }
+            };
+            if current.is_empty() && !known.is_empty() {
+                tracing::warn!(service = %service, "No healthy instances of service");
+            }
+
+            for change in diff(&known, &current) {
+                let change = match change {
+                    Change::Insert(address, ()) => match Endpoint::from_shared(format!("http://{}", address)) {
+                        Ok(endpoint) => Change::Insert(address, endpoint),
+                        Err(e) => {
+                            tracing::warn!(service = %service, address = %address, "Ignoring invalid address: {}", e);
+                            continue;
+                        }
+                    },
+                    Change::Remove(address) => Change::Remove(address),
+                };
+                if changes.send(change).await.is_err() {
+                    // The channel is gone
+                    return;
+                }
+            }
+            known = current;
+        }
+    });
+
+    (channel, resolver)
+}
+
+/// Removals then insertions turning `known` into `current`.
+fn diff(known: &BTreeSet<String>, current: &BTreeSet<String>) -> Vec<Change<String, ()>> {
+    let removed = known.difference(current).map(|address| Change::Remove(address.clone()));
+    let added = current.difference(known).map(|address| Change::Insert(address.clone(), ()));
+    removed.chain(added).collect()
+}
+
+/// A provider request that failed; discovery keeps its last state and
+/// tries again.
+pub(crate) fn provider_failed(provider: &str, detail: impl std::fmt::Display) -> AppError {
+    AppError::Unavailable {
+        message: format!("{} request failed: {}", provider, detail),
+        retry: RetryHint::after(Duration::from_secs(5)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(addresses: &[&str]) -> BTreeSet<String> {
+        addresses.iter().map(|address| address.to_string()).collect()
+    }
+
+    fn describe(changes: Vec<Change<String, ()>>) -> Vec<String> {
+        changes
+            .into_iter()
+            .map(|change| match change {
+                Change::Insert(address, ()) => format!("+{}", address),
+                Change::Remove(address) => format!("-{}", address),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diff_removes_before_inserting() {
+        let changes = diff(&set(&["a:1", "b:1"]), &set(&["b:1", "c:1"]));
+        assert_eq!(describe(changes), ["-a:1", "+c:1"]);
+        assert!(diff(&set(&["a:1"]), &set(&["a:1"])).is_empty());
+    }
+
+    #[test]
+    fn instances_need_a_reachable_host() {
+        let settings = crate::config::Settings::defaults()
+            .and_then(|config| config.set_override("database.url", "postgres://localhost/test"))
+            .and_then(|config| config.set_override("jwt.secret", "secret"))
+            .and_then(|config| config.build())
+            .and_then(|config| config.try_deserialize::<crate::config::Settings>())
+            .unwrap();
+
+        assert!(Instance::from_settings(&settings.discovery, &settings.server).is_err());
+
+        let mut discovery = settings.discovery.clone();
+        discovery.advertise_host = Some("10.0.0.7".to_string());
+        let instance = Instance::from_settings(&discovery, &settings.server).unwrap();
+        assert_eq!(instance.id, "tonic-template-10.0.0.7-50051");
+        assert_eq!(instance.service, "tonic-template");
+    }
+}
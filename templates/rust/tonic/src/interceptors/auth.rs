@@ -0,0 +1,221 @@
+use futures_util::future::{ready, BoxFuture, Either, Ready};
+use platform_auth::workload::WorkloadVerifier;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::codegen::http::{header::AUTHORIZATION, Request, Response};
+use tonic::Status;
+use tower::{Layer, Service};
+
+use crate::config::AuthSettings;
+use crate::errors::{AppError, AppResult, ErrorCode};
+use crate::models::Claims;
+use crate::utils::{decode_jwt_token, JwtKeys};
+
+/// Authentication requirement of a single RPC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MethodPolicy {
+    /// Callable without credentials.
+    Public,
+    /// Requires a valid bearer token; its claims, or the
+    /// [`WorkloadIdentity`](platform_auth::workload::WorkloadIdentity) of a
+    /// calling service, are added to the request extensions.
+    Authenticated,
+}
+
+/// Maps gRPC method paths (`/package.Service/Method`) to their policy.
+///
+/// Methods are authenticated unless listed as public, either individually or
+/// through a `/package.Service/*` wildcard, so new RPCs are protected by
+/// default. Authenticated methods may further require a role of the
+/// caller's token, like the REST template's `RequireRole`.
+#[derive(Debug, Clone, Default)]
+pub struct MethodAuthMatrix {
+    public_methods: HashSet<String>,
+    public_services: HashSet<String>,
+    /// Roles accepted by a method or, under `/package.Service`, by every
+    /// method of a service.
+    roles: HashMap<String, Vec<String>>,
+}
+
+impl MethodAuthMatrix {
+    pub fn from_settings(settings: &AuthSettings) -> Self {
+        let mut matrix = Self::default();
+        for method in &settings.public_methods {
+            matrix = matrix.public(method);
+        }
+        for method_role in &settings.method_roles {
+            matrix = matrix.require_role(&method_role.method, &method_role.role);
+        }
+        matrix
+    }
+
+    /// Lets holders of `role` call `method`; once a method has roles, other
+    /// callers are refused with `PERMISSION_DENIED`. Workloads are checked
+    /// against the roles they're bound to.
+    pub fn require_role(mut self, method: &str, role: &str) -> Self {
+        let key = method.strip_suffix("/*").unwrap_or(method);
+        self.roles.entry(key.to_string()).or_default().push(role.to_string());
+        self
+    }
+
+    /// The roles `path` accepts, if any are required.
+    pub fn roles(&self, path: &str) -> Option<&[String]> {
+        let service = path.rsplit_once('/').map(|(service, _)| service).unwrap_or_default();
+        self.roles.get(path).or_else(|| self.roles.get(service)).map(Vec::as_slice)
+    }
+
+    pub fn public(mut self, method: &str) -> Self {
+        match method.strip_suffix("/*") {
+            Some(service) => self.public_services.insert(service.to_string()),
+            None => self.public_methods.insert(method.to_string()),
+        };
+        self
+    }
+
+    pub fn policy(&self, path: &str) -> MethodPolicy {
+        let service = path.rsplit_once('/').map(|(service, _)| service).unwrap_or_default();
+
+        if self.public_methods.contains(path) || self.public_services.contains(service) {
+            MethodPolicy::Public
+        } else {
+            MethodPolicy::Authenticated
+        }
+    }
+}
+
+/// Tower layer enforcing a [`MethodAuthMatrix`] in front of every service of
+/// the server, so public and protected RPCs can live in the same service.
+#[derive(Clone)]
+pub struct AuthLayer {
+    matrix: Arc<MethodAuthMatrix>,
+    jwt_keys: Arc<JwtKeys>,
+    workload_verifier: Option<Arc<WorkloadVerifier>>,
+}
+
+impl AuthLayer {
+    /// Access tokens are validated against every key of `jwt_keys`, picked
+    /// by their `kid` header.
+    pub fn new(matrix: MethodAuthMatrix, jwt_keys: JwtKeys) -> Self {
+        Self {
+            matrix: Arc::new(matrix),
+            jwt_keys: Arc::new(jwt_keys),
+            workload_verifier: None,
+        }
+    }
+
+    /// Also accepts other services' Kubernetes and SPIFFE tokens.
+    pub fn with_workload_verifier(mut self, verifier: Option<Arc<WorkloadVerifier>>) -> Self {
+        self.workload_verifier = verifier;
+        self
+    }
+}
+
+impl<S> Layer<S> for AuthLayer {
+    type Service = AuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthService {
+            inner,
+            matrix: self.matrix.clone(),
+            jwt_keys: self.jwt_keys.clone(),
+            workload_verifier: self.workload_verifier.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AuthService<S> {
+    inner: S,
+    matrix: Arc<MethodAuthMatrix>,
+    jwt_keys: Arc<JwtKeys>,
+    workload_verifier: Option<Arc<WorkloadVerifier>>,
+}
+
+impl<S> AuthService<S> {
+    fn token<B>(req: &Request<B>) -> AppResult<&str> {
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(AppError::Unauthorized)?;
+
+        // Remove "Bearer " prefix if present
+        Ok(token.strip_prefix("Bearer ").unwrap_or(token))
+    }
+
+    fn authenticate<B>(&self, req: &Request<B>) -> AppResult<Claims> {
+        let claims = decode_jwt_token(Self::token(req)?, &self.jwt_keys)?;
+        if let Some(roles) = self.matrix.roles(req.uri().path()) {
+            if !roles.iter().any(|role| claims.grants.has_role(role)) {
+                return Err(AppError::Forbidden.with_code(ErrorCode::AuthRoleMissing));
+            }
+        }
+        Ok(claims)
+    }
+
+    /// The verifier and the token, if `req` carries a workload token.
+    fn workload_token<B>(&self, req: &Request<B>) -> Option<(Arc<WorkloadVerifier>, String)> {
+        let verifier = self.workload_verifier.as_ref()?;
+        let token = Self::token(req).ok()?;
+        verifier.recognizes(token).then(|| (verifier.clone(), token.to_string()))
+    }
+}
+
+/// Rejected, passed straight through, or waiting on a workload token's
+/// verification.
+type ResponseFuture<S, E> = Either<Either<Ready<Result<Response<BoxBody>, E>>, S>, BoxFuture<'static, Result<Response<BoxBody>, E>>>;
+
+impl<S, B> Service<Request<B>> for AuthService<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, S::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        if self.matrix.policy(req.uri().path()) == MethodPolicy::Authenticated {
+            // Workload tokens may need their issuer's keys fetched first
+            if let Some((verifier, token)) = self.workload_token(&req) {
+                // The ready service is taken along; the clone waits for the next poll_ready
+                let clone = self.inner.clone();
+                let mut inner = std::mem::replace(&mut self.inner, clone);
+                let matrix = self.matrix.clone();
+                return Either::Right(Box::pin(async move {
+                    let verified = verifier.verify(&token).await.and_then(|identity| {
+                        match matrix.roles(req.uri().path()) {
+                            Some(roles) if !roles.iter().any(|role| identity.has_role(role)) => {
+                                Err(AppError::Forbidden.with_code(ErrorCode::AuthWorkloadRoleMissing))
+                            }
+                            _ => Ok(identity),
+                        }
+                    });
+                    match verified {
+                        Ok(identity) => {
+                            req.extensions_mut().insert(identity);
+                            inner.call(req).await
+                        }
+                        Err(error) => Ok(Status::from(error).to_http()),
+                    }
+                }));
+            }
+
+            match self.authenticate(&req) {
+                Ok(claims) => {
+                    req.extensions_mut().insert(claims);
+                }
+                Err(error) => return Either::Left(Either::Left(ready(Ok(Status::from(error).to_http())))),
+            }
+        }
+
+        Either::Left(Either::Right(self.inner.call(req)))
+    }
+}
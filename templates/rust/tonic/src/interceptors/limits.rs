@@ -0,0 +1,326 @@
+use futures_util::future::{BoxFuture, FutureExt};
+use hyper::body::{Bytes, HttpBody, SizeHint};
+use platform_auth::workload::WorkloadIdentity;
+use platform_core::rate_limit::{Quota, SharedRateLimitBackend};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tonic::body::BoxBody;
+use tonic::codegen::http::{HeaderMap, Request, Response};
+use tonic::transport::server::TcpConnectInfo;
+use tonic::Status;
+use tower::{Layer, Service};
+
+use super::method_matches;
+use crate::config::{LimitSettings, MethodLimitSettings};
+use crate::errors::{AppError, Jitter, RetryHint};
+use crate::models::Claims;
+
+/// Identity a caller is rate limited under. A TLS acceptor verifying client
+/// certificates inserts this into the request extensions (e.g. the
+/// certificate subject); without it callers are keyed by JWT subject, then
+/// by peer IP.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PeerIdentity(pub String);
+
+struct MethodRule {
+    pattern: String,
+    quota: Option<Quota>,
+    semaphore: Option<Arc<Semaphore>>,
+}
+
+/// Per-method concurrency caps and per-caller token buckets.
+///
+/// Methods with their own rule get their own bucket per caller; all other
+/// methods share the caller's default bucket. Buckets live in the
+/// `limits.backend`, concurrency caps in this process.
+struct Limiter {
+    default_quota: Option<Quota>,
+    default_max_concurrency: usize,
+    rules: Vec<MethodRule>,
+    default_semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+    backend: SharedRateLimitBackend,
+}
+
+impl Limiter {
+    fn new(settings: &LimitSettings, backend: SharedRateLimitBackend) -> Self {
+        let rules = settings
+            .methods
+            .iter()
+            .map(|rule: &MethodLimitSettings| MethodRule {
+                pattern: rule.method.clone(),
+                quota: rule
+                    .rate_per_second
+                    .and_then(|rate| Quota::new(rate, rule.burst.unwrap_or(rate.ceil() as u32))),
+                semaphore: rule
+                    .max_concurrency
+                    .filter(|max| *max > 0)
+                    .map(|max| Arc::new(Semaphore::new(max))),
+            })
+            .collect();
+
+        Self {
+            default_quota: Quota::new(settings.default_rate_per_second, settings.default_burst),
+            default_max_concurrency: settings.default_max_concurrency,
+            rules,
+            default_semaphores: Mutex::new(HashMap::new()),
+            backend,
+        }
+    }
+
+    /// Admits a call, returning the concurrency permit to hold until it
+    /// completes.
+    async fn admit(&self, method: &str, identity: &str) -> Result<Option<OwnedSemaphorePermit>, Rejection> {
+        let rule = self.rules.iter().find(|rule| method_matches(&rule.pattern, method));
+
+        let (scope, quota) = match rule.filter(|rule| rule.quota.is_some()) {
+            Some(rule) => (rule.pattern.as_str(), rule.quota),
+            None => ("*", self.default_quota),
+        };
+        if let Some(quota) = quota {
+            let key = format!("grpc:{}:{}", scope, identity);
+            self.backend.take(&key, quota).await.map_err(|retry_after| Rejection {
+                message: "Rate limit exceeded",
+                retry: RetryHint::after(retry_after),
+            })?;
+        }
+
+        let semaphore = match rule.and_then(|rule| rule.semaphore.clone()) {
+            Some(semaphore) => Some(semaphore),
+            None if self.default_max_concurrency > 0 => Some(
+                self.default_semaphores
+                    .lock()
+                    .unwrap()
+                    .entry(method.to_string())
+                    .or_insert_with(|| Arc::new(Semaphore::new(self.default_max_concurrency)))
+                    .clone(),
+            ),
+            None => None,
+        };
+
+        match semaphore {
+            Some(semaphore) => semaphore.try_acquire_owned().map(Some).map_err(|_| {
+                // Shed load rather than queue; in-flight calls finish quickly
+                // relative to a client's backoff
+                Rejection {
+                    message: "Too many concurrent requests for this method",
+                    retry: RetryHint::after(Duration::from_millis(100)).with_jitter(Jitter::Full),
+                }
+            }),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Rejection {
+    message: &'static str,
+    retry: RetryHint,
+}
+
+impl Rejection {
+    /// RESOURCE_EXHAUSTED carrying `google.rpc.RetryInfo` and `ErrorInfo`
+    /// details.
+    fn into_status(self) -> Status {
+        AppError::Throttled {
+            message: self.message.to_string(),
+            retry: self.retry,
+        }
+        .into()
+    }
+}
+
+fn identity<B>(req: &Request<B>) -> String {
+    let extensions = req.extensions();
+    if let Some(PeerIdentity(identity)) = extensions.get::<PeerIdentity>() {
+        return format!("peer:{}", identity);
+    }
+    if let Some(claims) = extensions.get::<Claims>() {
+        return format!("user:{}", claims.sub);
+    }
+    if let Some(workload) = extensions.get::<WorkloadIdentity>() {
+        return format!("workload:{}", workload.subject);
+    }
+    match extensions.get::<TcpConnectInfo>().and_then(|info| info.remote_addr()) {
+        Some(addr) => format!("ip:{}", addr.ip()),
+        None => "anonymous".to_string(),
+    }
+}
+
+/// Tower layer applying [`LimitSettings`], with buckets in `backend`. It
+/// must run inside the auth layer so authenticated callers are keyed by
+/// user rather than by IP.
+#[derive(Clone)]
+pub struct LimitLayer {
+    limiter: Arc<Limiter>,
+}
+
+impl LimitLayer {
+    pub fn new(settings: &LimitSettings, backend: SharedRateLimitBackend) -> Self {
+        Self {
+            limiter: Arc::new(Limiter::new(settings, backend)),
+        }
+    }
+}
+
+impl<S> Layer<S> for LimitLayer {
+    type Service = LimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LimitService {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LimitService<S> {
+    inner: S,
+    limiter: Arc<Limiter>,
+}
+
+impl<S, B> Service<Request<B>> for LimitService<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        // Buckets may live in Redis, so admission is awaited with the ready
+        // service taken along; the clone waits for the next poll_ready
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limiter = self.limiter.clone();
+        let method = req.uri().path().to_string();
+        let identity = identity(&req);
+
+        async move {
+            let permit = match limiter.admit(&method, &identity).await {
+                Ok(permit) => permit,
+                Err(rejection) => return Ok(rejection.into_status().to_http()),
+            };
+
+            let response = inner.call(req).await?;
+            Ok(match permit {
+                // Streaming calls hold their slot until the response ends
+                Some(permit) => response.map(|inner| {
+                    BoxBody::new(PermitBody {
+                        inner,
+                        _permit: permit,
+                    })
+                }),
+                None => response,
+            })
+        }
+        .boxed()
+    }
+}
+
+struct PermitBody {
+    inner: BoxBody,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl HttpBody for PermitBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use platform_core::rate_limit::MemoryBackend;
+    use tonic::Code;
+    use tonic_types::StatusExt;
+
+    fn limiter(methods: Vec<MethodLimitSettings>) -> Limiter {
+        let settings = LimitSettings {
+            default_rate_per_second: 1.0,
+            default_burst: 2,
+            default_max_concurrency: 0,
+            methods,
+            backend: Default::default(),
+        };
+        Limiter::new(&settings, Arc::new(MemoryBackend::default()))
+    }
+
+    #[tokio::test]
+    async fn default_bucket_is_shared_per_identity() {
+        let limiter = limiter(vec![]);
+
+        assert!(limiter.admit("/a.A/One", "user:1").await.is_ok());
+        assert!(limiter.admit("/a.A/Two", "user:1").await.is_ok());
+        let status = limiter.admit("/a.A/One", "user:1").await.unwrap_err().into_status();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert!(status.get_details_retry_info().is_some());
+
+        // Other callers have their own bucket
+        assert!(limiter.admit("/a.A/One", "user:2").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn method_rules_have_their_own_bucket() {
+        let limiter = limiter(vec![MethodLimitSettings {
+            method: "/a.A/Login".to_string(),
+            rate_per_second: Some(1.0),
+            burst: Some(1),
+            max_concurrency: None,
+        }]);
+
+        assert!(limiter.admit("/a.A/Login", "ip:1").await.is_ok());
+        assert!(limiter.admit("/a.A/Login", "ip:1").await.is_err());
+        assert!(limiter.admit("/a.A/Other", "ip:1").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn concurrency_cap_releases_on_drop() {
+        let limiter = limiter(vec![MethodLimitSettings {
+            method: "/a.A/*".to_string(),
+            rate_per_second: None,
+            burst: None,
+            max_concurrency: Some(1),
+        }]);
+
+        let permit = limiter.admit("/a.A/Upload", "user:1").await.unwrap();
+        assert!(permit.is_some());
+        assert!(limiter.admit("/a.A/Upload", "user:2").await.is_err());
+
+        drop(permit);
+        assert!(limiter.admit("/a.A/Upload", "user:2").await.is_ok());
+    }
+}
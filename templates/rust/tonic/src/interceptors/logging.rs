@@ -0,0 +1,370 @@
+use futures_util::future::BoxFuture;
+use futures_util::TryStreamExt;
+use hyper::body::{Bytes, HttpBody, SizeHint};
+use hyper::Body;
+use rand::Rng;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tonic::body::BoxBody;
+use tonic::codegen::http::{HeaderMap, Request, Response};
+use tonic::transport::server::TcpConnectInfo;
+use tonic::{Code, Status};
+use tower::{Layer, Service};
+
+use super::method_matches;
+use crate::config::LoggingSettings;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Tower layer logging every gRPC call once it completes: method, peer,
+/// status code and duration, plus sampled, size-capped request and response
+/// payloads. Also records per-method call counts and latencies.
+///
+/// Completion is observed on the response body, so streaming calls are
+/// logged with their full duration and final status.
+#[derive(Clone)]
+pub struct LoggingLayer {
+    settings: Arc<LoggingSettings>,
+}
+
+impl LoggingLayer {
+    pub fn new(settings: LoggingSettings) -> Self {
+        Self {
+            settings: Arc::new(settings),
+        }
+    }
+}
+
+impl<S> Layer<S> for LoggingLayer {
+    type Service = LoggingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LoggingService {
+            inner,
+            settings: self.settings.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LoggingService<S> {
+    inner: S,
+    settings: Arc<LoggingSettings>,
+}
+
+impl<S> LoggingService<S> {
+    fn should_sample(&self, method: &str) -> bool {
+        let rate = self.settings.payload_sample_rate;
+        rate > 0.0
+            && !self
+                .settings
+                .redacted_methods
+                .iter()
+                .any(|pattern| method_matches(pattern, method))
+            && rand::thread_rng().gen_bool(rate.min(1.0))
+    }
+}
+
+impl<S> Service<Request<Body>> for LoggingService<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let method = req.uri().path().to_string();
+        let payloads = self
+            .should_sample(&method)
+            .then(|| Arc::new(Mutex::new(Payloads::new(self.settings.max_payload_bytes))));
+
+        let call = CallLog {
+            peer: req
+                .extensions()
+                .get::<TcpConnectInfo>()
+                .and_then(|info| info.remote_addr()),
+            method,
+            start: Instant::now(),
+            payloads: payloads.clone(),
+        };
+
+        let req = match payloads {
+            Some(payloads) => req.map(|body| {
+                Body::wrap_stream(body.inspect_ok(move |chunk| {
+                    payloads.lock().unwrap().request.append(chunk);
+                }))
+            }),
+            None => req,
+        };
+
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let response = match future.await {
+                Ok(response) => response,
+                Err(e) => {
+                    call.finish(Code::Internal);
+                    return Err(e);
+                }
+            };
+
+            // Trailers-only responses (e.g. rejected calls) carry the status
+            // in the headers and have no body to observe
+            if let Some(code) = grpc_status(response.headers()) {
+                call.finish(code);
+                return Ok(response);
+            }
+
+            Ok(response.map(|inner| {
+                BoxBody::new(ObservedBody {
+                    inner,
+                    call: Some(call),
+                })
+            }))
+        })
+    }
+}
+
+struct CallLog {
+    method: String,
+    peer: Option<SocketAddr>,
+    start: Instant,
+    payloads: Option<Arc<Mutex<Payloads>>>,
+}
+
+impl CallLog {
+    fn record_response(&self, chunk: &[u8]) {
+        if let Some(payloads) = &self.payloads {
+            payloads.lock().unwrap().response.append(chunk);
+        }
+    }
+
+    fn finish(self, code: Code) {
+        let elapsed = self.start.elapsed();
+
+        metrics::counter!(
+            "grpc_server_handled_total",
+            "grpc_method" => self.method.clone(),
+            "grpc_code" => format!("{:?}", code),
+        )
+        .increment(1);
+        metrics::histogram!("grpc_server_handling_seconds", "grpc_method" => self.method.clone())
+            .record(elapsed.as_secs_f64());
+
+        let (request_payload, response_payload) = match &self.payloads {
+            Some(payloads) => {
+                let payloads = payloads.lock().unwrap();
+                (Some(payloads.request.render()), Some(payloads.response.render()))
+            }
+            None => (None, None),
+        };
+
+        macro_rules! log_call {
+            ($level:ident) => {
+                tracing::$level!(
+                    target: "grpc",
+                    method = %self.method,
+                    peer = ?self.peer,
+                    code = ?code,
+                    duration_ms = elapsed.as_millis() as u64,
+                    request_payload = request_payload.as_deref(),
+                    response_payload = response_payload.as_deref(),
+                    "gRPC call completed"
+                )
+            };
+        }
+
+        match code {
+            Code::Internal | Code::Unknown | Code::DataLoss | Code::Unavailable => log_call!(warn),
+            _ => log_call!(info),
+        }
+    }
+}
+
+/// Response body wrapper that captures payload bytes and reports the final
+/// status from the trailers. Dropping it unfinished means the client went
+/// away, which is logged as cancelled.
+struct ObservedBody {
+    inner: BoxBody,
+    call: Option<CallLog>,
+}
+
+impl ObservedBody {
+    fn finish(&mut self, code: Code) {
+        if let Some(call) = self.call.take() {
+            call.finish(code);
+        }
+    }
+}
+
+impl HttpBody for ObservedBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_data(cx);
+
+        match &poll {
+            Poll::Ready(Some(Ok(chunk))) => {
+                if let Some(call) = &this.call {
+                    call.record_response(chunk);
+                }
+            }
+            Poll::Ready(Some(Err(status))) => this.finish(status.code()),
+            _ => {}
+        }
+
+        poll
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_trailers(cx);
+
+        match &poll {
+            Poll::Ready(Ok(trailers)) => {
+                let code = trailers.as_ref().and_then(grpc_status).unwrap_or(Code::Unknown);
+                this.finish(code);
+            }
+            Poll::Ready(Err(status)) => this.finish(status.code()),
+            Poll::Pending => {}
+        }
+
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for ObservedBody {
+    fn drop(&mut self) {
+        self.finish(Code::Cancelled);
+    }
+}
+
+fn grpc_status(headers: &HeaderMap) -> Option<Code> {
+    headers
+        .get("grpc-status")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i32>().ok())
+        .map(Code::from_i32)
+}
+
+struct Payloads {
+    request: Capture,
+    response: Capture,
+}
+
+impl Payloads {
+    fn new(limit: usize) -> Self {
+        Self {
+            request: Capture::new(limit),
+            response: Capture::new(limit),
+        }
+    }
+}
+
+/// The first `limit` bytes of a payload.
+struct Capture {
+    bytes: Vec<u8>,
+    limit: usize,
+    truncated: bool,
+}
+
+impl Capture {
+    fn new(limit: usize) -> Self {
+        Self {
+            bytes: Vec::new(),
+            limit,
+            truncated: false,
+        }
+    }
+
+    fn append(&mut self, chunk: &[u8]) {
+        let remaining = self.limit.saturating_sub(self.bytes.len());
+        self.truncated |= chunk.len() > remaining;
+        self.bytes.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+    }
+
+    /// Renders the captured protobuf bytes with bearer tokens redacted and
+    /// non-printable bytes replaced by `.`.
+    fn render(&self) -> String {
+        let mut rendered: String = redact_jwts(&self.bytes)
+            .iter()
+            .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+            .collect();
+
+        if self.truncated {
+            rendered.push_str("...(truncated)");
+        }
+        rendered
+    }
+}
+
+/// Replaces anything shaped like a JWT (`eyJ...`) with a marker.
+fn redact_jwts(bytes: &[u8]) -> Vec<u8> {
+    let is_token_byte = |byte: &u8| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.');
+
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut rest = bytes;
+    while let Some(start) = rest.windows(3).position(|window| window == b"eyJ") {
+        output.extend_from_slice(&rest[..start]);
+        let token = &rest[start..];
+        let end = token.iter().position(|byte| !is_token_byte(byte)).unwrap_or(token.len());
+        output.extend_from_slice(REDACTED.as_bytes());
+        rest = &token[end..];
+    }
+    output.extend_from_slice(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_is_size_capped() {
+        let mut capture = Capture::new(4);
+        capture.append(b"abc");
+        capture.append(b"def");
+
+        assert_eq!(capture.render(), "abcd...(truncated)");
+    }
+
+    #[test]
+    fn render_replaces_binary_bytes() {
+        let mut capture = Capture::new(16);
+        capture.append(&[0, 0, 0, 0, 5, b'a', b'l', b'i', b'c', b'e']);
+
+        assert_eq!(capture.render(), ".....alice");
+    }
+
+    #[test]
+    fn render_redacts_tokens() {
+        let mut capture = Capture::new(256);
+        capture.append(b"\x0a\x20eyJhbGciOiJIUzI1NiJ9.eyJzdWIiOiIxIn0.sig-_x\x12\x04rest");
+
+        assert_eq!(capture.render(), ". [REDACTED]..rest");
+    }
+}
@@ -0,0 +1,82 @@
+use futures_util::future::{ready, BoxFuture, FutureExt};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tonic::body::BoxBody;
+use tonic::codegen::http::{Request, Response};
+use tonic::Status;
+use tower::{Layer, Service};
+
+use super::method_matches;
+use crate::config::MaintenanceSettings;
+use crate::errors::{AppError, ErrorCode, Jitter, RetryHint};
+
+/// Health checks keep answering so orchestrators don't restart the instance.
+const EXEMPT_METHODS: &[&str] = &["/grpc.health.v1.Health/*", "/health.v1.HealthService/*"];
+
+/// Tower layer rejecting every call except health checks with UNAVAILABLE
+/// and a `RetryInfo` hint while maintenance mode is enabled.
+#[derive(Clone)]
+pub struct MaintenanceLayer {
+    settings: MaintenanceSettings,
+}
+
+impl MaintenanceLayer {
+    pub fn new(settings: MaintenanceSettings) -> Self {
+        Self { settings }
+    }
+}
+
+impl<S> Layer<S> for MaintenanceLayer {
+    type Service = MaintenanceService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MaintenanceService {
+            inner,
+            settings: self.settings.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MaintenanceService<S> {
+    inner: S,
+    settings: MaintenanceSettings,
+}
+
+impl<S> MaintenanceService<S> {
+    fn rejection(&self) -> Status {
+        // Spread retries so clients don't all return at once
+        let retry = RetryHint::after(Duration::from_secs(self.settings.retry_after_secs))
+            .with_jitter(Jitter::Equal);
+        AppError::Unavailable {
+            message: self.settings.message.clone(),
+            retry,
+        }
+        .with_code(ErrorCode::MaintenanceMode)
+        .into()
+    }
+}
+
+impl<S, B> Service<Request<B>> for MaintenanceService<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let exempt = EXEMPT_METHODS.iter().any(|pattern| method_matches(pattern, req.uri().path()));
+        if self.settings.enabled && !exempt {
+            return ready(Ok(self.rejection().to_http())).boxed();
+        }
+
+        self.inner.call(req).boxed()
+    }
+}
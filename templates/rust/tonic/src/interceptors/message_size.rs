@@ -0,0 +1,309 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_util::StreamExt;
+use hyper::Body;
+use std::io::Read;
+use std::task::{Context, Poll};
+use tonic::codegen::http::Request;
+use tonic::Status;
+use tower::{Layer, Service};
+
+use crate::errors::{AppError, AppResult, ErrorCode};
+
+/// Compressed flag byte plus big-endian length.
+const FRAME_HEADER_LEN: usize = 5;
+
+/// Tower layer enforcing the decoding limit on incoming gRPC messages before
+/// tonic sees them, rejecting with RESOURCE_EXHAUSTED.
+///
+/// tonic checks the length prefix of a message but inflates compressed
+/// messages without bound, so a few KiB of gzip can expand into gigabytes.
+/// Here compressed messages are inflated against the same limit and passed
+/// on uncompressed.
+#[derive(Clone)]
+pub struct MessageSizeLayer {
+    limit: usize,
+}
+
+impl MessageSizeLayer {
+    pub fn new(limit: usize) -> Self {
+        Self { limit }
+    }
+}
+
+impl<S> Layer<S> for MessageSizeLayer {
+    type Service = MessageSizeService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MessageSizeService {
+            inner,
+            limit: self.limit,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MessageSizeService<S> {
+    inner: S,
+    limit: usize,
+}
+
+impl<S> Service<Request<Body>> for MessageSizeService<S>
+where
+    S: Service<Request<Body>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let method = req.uri().path().to_string();
+        // Other encodings are left compressed; tonic rejects the ones it
+        // doesn't accept
+        let gzip = req
+            .headers()
+            .get("grpc-encoding")
+            .is_some_and(|encoding| encoding == "gzip");
+        let mut guard = FrameGuard::new(self.limit, gzip);
+
+        let req = req.map(|body| {
+            Body::wrap_stream(body.map(move |chunk| {
+                let chunk = chunk?;
+                guard.push(chunk).map_err(|error| {
+                    metrics::counter!("grpc_server_messages_rejected_total", "grpc_method" => method.clone())
+                        .increment(1);
+                    tracing::warn!(method = %method, "Rejected message: {}", error);
+                    Box::new(Status::from(error)) as Box<dyn std::error::Error + Send + Sync>
+                })
+            }))
+        });
+
+        self.inner.call(req)
+    }
+}
+
+/// Feeds `body` through a frame guard in `chunk_size` pieces until it is
+/// rejected. Entry point for the `message_frames` fuzz target.
+#[cfg(fuzzing)]
+pub fn fuzz_frames(limit: usize, inflate: bool, body: &[u8], chunk_size: usize) {
+    let mut guard = FrameGuard::new(limit, inflate);
+    for chunk in body.chunks(chunk_size) {
+        if guard.push(Bytes::copy_from_slice(chunk)).is_err() {
+            return;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameState {
+    Header,
+    Message { remaining: usize },
+}
+
+/// Incremental parser for the gRPC length-prefixed message framing.
+/// Uncompressed messages are streamed through as they arrive; compressed
+/// ones are buffered (at most `limit` bytes) and inflated.
+struct FrameGuard {
+    limit: usize,
+    inflate: bool,
+    state: FrameState,
+    buffer: BytesMut,
+}
+
+impl FrameGuard {
+    fn new(limit: usize, inflate: bool) -> Self {
+        Self {
+            limit,
+            inflate,
+            state: FrameState::Header,
+            buffer: BytesMut::new(),
+        }
+    }
+
+    /// Consumes a body chunk and returns the bytes ready to forward.
+    fn push(&mut self, chunk: Bytes) -> AppResult<Bytes> {
+        self.buffer.extend_from_slice(&chunk);
+        let mut output = BytesMut::new();
+
+        loop {
+            match self.state {
+                FrameState::Header => {
+                    if self.buffer.len() < FRAME_HEADER_LEN {
+                        break;
+                    }
+
+                    let compressed = self.buffer[0] == 1;
+                    let len = u32::from_be_bytes([self.buffer[1], self.buffer[2], self.buffer[3], self.buffer[4]]) as usize;
+                    if len > self.limit {
+                        return Err(self.too_large(len));
+                    }
+
+                    if compressed && self.inflate {
+                        if self.buffer.len() < FRAME_HEADER_LEN + len {
+                            break;
+                        }
+                        self.buffer.advance(FRAME_HEADER_LEN);
+                        let compressed = self.buffer.split_to(len);
+                        let message = self.decompress(&compressed)?;
+                        output.put_u8(0);
+                        output.put_u32(message.len() as u32);
+                        output.extend_from_slice(&message);
+                    } else {
+                        output.extend_from_slice(&self.buffer.split_to(FRAME_HEADER_LEN));
+                        self.state = FrameState::Message { remaining: len };
+                    }
+                }
+                FrameState::Message { remaining } => {
+                    if remaining == 0 {
+                        self.state = FrameState::Header;
+                        continue;
+                    }
+                    if self.buffer.is_empty() {
+                        break;
+                    }
+
+                    let take = remaining.min(self.buffer.len());
+                    output.extend_from_slice(&self.buffer.split_to(take));
+                    self.state = FrameState::Message {
+                        remaining: remaining - take,
+                    };
+                }
+            }
+        }
+
+        Ok(output.freeze())
+    }
+
+    fn decompress(&self, compressed: &[u8]) -> AppResult<Vec<u8>> {
+        let mut message = Vec::new();
+        flate2::read::GzDecoder::new(compressed)
+            .take(self.limit as u64 + 1)
+            .read_to_end(&mut message)
+            .map_err(|_| AppError::ValidationError("Invalid gzip message".to_string()))?;
+
+        if message.len() > self.limit {
+            return Err(AppError::PayloadTooLarge(format!(
+                "Decompressed message exceeds the limit of {} bytes",
+                self.limit
+            ))
+            .with_code(ErrorCode::MessageTooLarge));
+        }
+        Ok(message)
+    }
+
+    fn too_large(&self, len: usize) -> AppError {
+        AppError::PayloadTooLarge(format!(
+            "Message of {} bytes exceeds the limit of {} bytes",
+            len, self.limit
+        ))
+        .with_code(ErrorCode::MessageTooLarge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use std::io::Write;
+
+    fn frame(compressed: bool, message: &[u8]) -> Vec<u8> {
+        let mut frame = vec![compressed as u8];
+        frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        frame.extend_from_slice(message);
+        frame
+    }
+
+    fn gzip(message: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(message).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn passes_messages_split_across_chunks() {
+        let mut guard = FrameGuard::new(16, true);
+        let input = [frame(false, b"hello"), frame(false, b"")].concat();
+
+        let mut output = Vec::new();
+        for chunk in input.chunks(3) {
+            output.extend_from_slice(&guard.push(Bytes::copy_from_slice(chunk)).unwrap());
+        }
+
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn rejects_oversized_length_prefix() {
+        let mut guard = FrameGuard::new(4, true);
+
+        let error = guard.push(Bytes::from(frame(false, b"hello"))).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::MessageTooLarge);
+    }
+
+    #[test]
+    fn inflates_compressed_messages() {
+        let mut guard = FrameGuard::new(64, true);
+
+        let output = guard.push(Bytes::from(frame(true, &gzip(b"hello")))).unwrap();
+        assert_eq!(output.as_ref(), frame(false, b"hello").as_slice());
+    }
+
+    #[test]
+    fn rejects_decompression_bombs() {
+        let bomb = gzip(&vec![0u8; 1024 * 1024]);
+        let mut guard = FrameGuard::new(64 * 1024, true);
+        assert!(bomb.len() < 64 * 1024);
+
+        let error = guard.push(Bytes::from(frame(true, &bomb))).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::MessageTooLarge);
+    }
+
+    proptest! {
+        #[test]
+        fn uncompressed_frames_pass_through_any_chunking(
+            messages in vec(vec(any::<u8>(), 0..64), 0..8),
+            chunk_size in 1usize..32,
+        ) {
+            let input: Vec<u8> = messages.iter().flat_map(|message| frame(false, message)).collect();
+            let mut guard = FrameGuard::new(64, true);
+
+            let mut output = Vec::new();
+            for chunk in input.chunks(chunk_size) {
+                output.extend_from_slice(&guard.push(Bytes::copy_from_slice(chunk)).unwrap());
+            }
+            prop_assert_eq!(output, input);
+        }
+
+        #[test]
+        fn compressed_frames_are_inflated(message in vec(any::<u8>(), 0..256), chunk_size in 1usize..64) {
+            let input = frame(true, &gzip(&message));
+            // Incompressible messages grow slightly when gzipped
+            let mut guard = FrameGuard::new(512, true);
+
+            let mut output = Vec::new();
+            for chunk in input.chunks(chunk_size) {
+                output.extend_from_slice(&guard.push(Bytes::copy_from_slice(chunk)).unwrap());
+            }
+            prop_assert_eq!(output, frame(false, &message));
+        }
+
+        #[test]
+        fn arbitrary_input_is_rejected_without_panicking(
+            chunks in vec(vec(any::<u8>(), 0..128), 0..8),
+            inflate in any::<bool>(),
+        ) {
+            let mut guard = FrameGuard::new(64, inflate);
+            for chunk in chunks {
+                if guard.push(Bytes::from(chunk)).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -1,36 +1,34 @@
-use tonic::{Request, Status};
-use crate::utils::decode_jwt_token;
-use crate::models::Claims;
+pub mod auth;
+pub mod limits;
+pub mod logging;
+pub mod maintenance;
+pub mod message_size;
+pub mod trace_context;
 
-pub fn auth_interceptor(mut req: Request<()>) -> Result<Request<()>, Status> {
-    let token = match req.metadata().get("authorization") {
-        Some(t) => t,
-        None => return Err(Status::unauthenticated("No authorization token provided")),
-    };
+pub use auth::{AuthLayer, MethodAuthMatrix, MethodPolicy};
+pub use limits::{LimitLayer, PeerIdentity};
+pub use logging::LoggingLayer;
+pub use maintenance::MaintenanceLayer;
+pub use message_size::MessageSizeLayer;
+pub use trace_context::{inject_trace_context, PropagationLayer};
 
-    let token_str = token
-        .to_str()
-        .map_err(|_| Status::unauthenticated("Invalid authorization token"))?;
+/// Matches full method paths and `/package.Service/*` wildcards.
+pub(crate) fn method_matches(pattern: &str, method: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(service) => method.rsplit_once('/').is_some_and(|(prefix, _)| prefix == service),
+        None => pattern == method,
+    }
+}
 
-    // Remove "Bearer " prefix if present
-    let token_str = if token_str.starts_with("Bearer ") {
-        &token_str[7..]
-    } else {
-        token_str
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // For now, we'll skip actual JWT validation in the interceptor
-    // In a real implementation, you'd want to access the app settings here
-    // This is a simplified version
-    
-    Ok(req)
+    #[test]
+    fn matches_methods_and_services() {
+        assert!(method_matches("/user.v1.UserService/Login", "/user.v1.UserService/Login"));
+        assert!(!method_matches("/user.v1.UserService/Login", "/user.v1.UserService/GetUser"));
+        assert!(method_matches("/file.v1.FileService/*", "/file.v1.FileService/UploadFile"));
+        assert!(!method_matches("/file.v1.FileService/*", "/file.v1.FileServiceX/UploadFile"));
+    }
 }
-
-pub fn logging_interceptor(req: Request<()>) -> Result<Request<()>, Status> {
-    tracing::info!(
-        "Received request: {:?} from {:?}",
-        req.uri(),
-        req.remote_addr()
-    );
-    Ok(req)
-}
\ No newline at end of file
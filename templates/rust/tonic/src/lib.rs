@@ -0,0 +1,197 @@
+pub mod aip;
+pub mod config;
+pub mod discovery;
+pub mod errors;
+pub mod factories;
+pub mod interceptors;
+pub mod mail;
+pub mod models;
+pub mod operations;
+pub mod resilience;
+pub mod services;
+pub mod smoke;
+pub mod storage;
+pub mod transport;
+pub mod utils;
+
+use hyper::body::{Bytes, HttpBody};
+use platform_auth::workload::WorkloadVerifier;
+use platform_core::lifecycle::Draining;
+use platform_core::rate_limit::SharedRateLimitBackend;
+use platform_observability::log_level::LogLevel;
+use std::sync::Arc;
+use tonic::codegen::http::{Request, Response};
+use tonic::codec::CompressionEncoding;
+use tonic::transport::Server;
+use tower::Service;
+
+use crate::config::Settings;
+use crate::interceptors::{
+    trace_context, AuthLayer, LimitLayer, LoggingLayer, MaintenanceLayer, MessageSizeLayer, MethodAuthMatrix,
+    PropagationLayer,
+};
+use crate::mail::Mailer;
+use crate::models::role::ROLE_ADMIN;
+use crate::proto::admin::v1::admin_service_server::AdminServiceServer;
+use crate::proto::file::v1::file_service_server::FileServiceServer;
+use crate::proto::grpc::health::v1::health_server::HealthServer;
+use crate::proto::health::v1::health_service_server::HealthServiceServer;
+use crate::proto::operations::v1::operations_server::OperationsServer;
+use crate::proto::user::v1::user_service_server::UserServiceServer;
+use crate::operations::OperationStore;
+use crate::services::grpc_health::{service_name, GrpcHealthServiceImpl, ADMIN_SERVICES, PUBLIC_SERVICES};
+use crate::services::{
+    admin::AdminServiceImpl, file::FileServiceImpl, health::HealthServiceImpl, operations::OperationsServiceImpl, user::UserServiceImpl,
+};
+use crate::storage::Storage;
+use crate::utils::JwtKeys;
+
+// Include the generated proto files
+pub mod proto {
+    pub mod admin {
+        pub mod v1 {
+            tonic::include_proto!("admin.v1");
+        }
+    }
+    pub mod file {
+        pub mod v1 {
+            tonic::include_proto!("file.v1");
+        }
+    }
+    /// The standard health checking protocol.
+    pub mod grpc {
+        pub mod health {
+            pub mod v1 {
+                tonic::include_proto!("grpc.health.v1");
+            }
+        }
+    }
+    pub mod health {
+        pub mod v1 {
+            tonic::include_proto!("health.v1");
+        }
+    }
+    pub mod operations {
+        pub mod v1 {
+            tonic::include_proto!("operations.v1");
+        }
+    }
+    pub mod user {
+        pub mod v1 {
+            tonic::include_proto!("user.v1");
+        }
+    }
+}
+
+/// Descriptors of every proto above, for the reflection service.
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("template_descriptor");
+
+#[derive(Clone)]
+pub struct AppState {
+    pub db: sqlx::PgPool,
+    pub settings: Settings,
+    /// Signs access tokens and validates them, per `jwt.keys`.
+    pub jwt_keys: JwtKeys,
+    pub storage: Storage,
+    pub operations: OperationStore,
+    /// The tracing filter, changeable through `admin.v1.AdminService`.
+    pub log_level: Arc<LogLevel>,
+    /// Verifies other services' Kubernetes and SPIFFE tokens; `None` unless
+    /// `workload_identity.issuers` are configured.
+    pub workload_verifier: Option<Arc<WorkloadVerifier>>,
+    /// Sends password reset links.
+    pub mailer: Arc<dyn Mailer>,
+    /// Token buckets of the rate limits, per `limits.backend`.
+    pub rate_limiter: SharedRateLimitBackend,
+    /// Set on SIGTERM, failing health checks while calls drain.
+    pub draining: Draining,
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// All gRPC services behind the shared layer stack, ready to be served by
+/// [`transport::serve`] or an in-process test channel.
+pub fn grpc_service(
+    state: Arc<AppState>,
+) -> impl Service<
+    Request<hyper::Body>,
+    Response = Response<impl HttpBody<Data = Bytes, Error = impl Into<BoxError>> + Send + 'static>,
+    Error = impl Into<BoxError> + Send,
+    Future = impl Send + 'static,
+> + Clone + Send + 'static {
+    let settings = &state.settings;
+
+    // Per-method authorization applies across all services
+    let auth_matrix = MethodAuthMatrix::from_settings(&settings.auth);
+
+    let reflection = settings.server.reflection.then(|| {
+        tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+            .build()
+            .expect("the generated descriptor set is valid")
+    });
+    // Health checks know every service served here, reflection included
+    let mut services = PUBLIC_SERVICES.to_vec();
+    services.extend(reflection.as_ref().map(service_name));
+
+    // Message size limits and compression apply to every service alike
+    macro_rules! configure {
+        ($server:expr) => {
+            $server
+                .max_decoding_message_size(settings.server.max_decoding_message_size)
+                .max_encoding_message_size(settings.server.max_encoding_message_size)
+                .accept_compressed(CompressionEncoding::Gzip)
+                .send_compressed(CompressionEncoding::Gzip)
+        };
+    }
+
+    Server::builder()
+        .layer(
+            tower::ServiceBuilder::new()
+                .layer(tower_http::trace::TraceLayer::new_for_grpc().make_span_with(trace_context::make_span))
+                .layer(PropagationLayer::new(settings.propagation.clone()))
+                .layer(LoggingLayer::new(settings.logging.clone()))
+                .layer(MaintenanceLayer::new(settings.maintenance.clone()))
+                .layer(
+                    AuthLayer::new(auth_matrix, state.jwt_keys.clone())
+                        .with_workload_verifier(state.workload_verifier.clone()),
+                )
+                .layer(LimitLayer::new(&settings.limits, state.rate_limiter.clone()))
+                .layer(MessageSizeLayer::new(settings.server.max_decoding_message_size)),
+        )
+        .add_service(configure!(HealthServer::new(GrpcHealthServiceImpl::new(state.clone(), services))))
+        .add_service(configure!(HealthServiceServer::new(HealthServiceImpl::new(state.clone()))))
+        .add_service(configure!(UserServiceServer::new(UserServiceImpl::new(state.clone()))))
+        .add_service(configure!(FileServiceServer::new(FileServiceImpl::new(state.clone()))))
+        .add_service(configure!(OperationsServer::new(OperationsServiceImpl::new(state.clone()))))
+        .add_optional_service(reflection)
+        .into_service()
+}
+
+/// The health services, standard and the template's own, and the admin
+/// service, for admin listeners. Health needs no token on public listeners
+/// either; admin RPCs always do, and the admin role on top of any
+/// `auth.method_roles` configured for them.
+pub fn admin_service(
+    state: Arc<AppState>,
+) -> impl Service<
+    Request<hyper::Body>,
+    Response = Response<impl HttpBody<Data = Bytes, Error = impl Into<BoxError>> + Send + 'static>,
+    Error = impl Into<BoxError> + Send,
+    Future = impl Send + 'static,
+> + Clone + Send + 'static {
+    let settings = &state.settings;
+    let auth_matrix =
+        MethodAuthMatrix::from_settings(&settings.auth).require_role("/admin.v1.AdminService/*", ROLE_ADMIN);
+
+    Server::builder()
+        .layer(
+            tower::ServiceBuilder::new()
+                .layer(tower_http::trace::TraceLayer::new_for_grpc().make_span_with(trace_context::make_span))
+                .layer(AuthLayer::new(auth_matrix, state.jwt_keys.clone())),
+        )
+        .add_service(HealthServer::new(GrpcHealthServiceImpl::new(state.clone(), ADMIN_SERVICES)))
+        .add_service(HealthServiceServer::new(HealthServiceImpl::new(state.clone())))
+        .add_service(AdminServiceServer::new(AdminServiceImpl::new(state)))
+        .into_service()
+}
@@ -0,0 +1,53 @@
+//! Outbound email behind the [`Mailer`] trait. Only [`ConsoleMailer`],
+//! which logs emails, ships here; implement `Mailer` for a provider the
+//! way the REST template's Postmark and SMTP mailers do.
+
+use futures_util::future::BoxFuture;
+use platform_core::domain::Email;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::errors::AppResult;
+
+/// A plain-text email.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailMessage {
+    pub to: Email,
+    pub subject: String,
+    pub text: String,
+}
+
+pub trait Mailer: Send + Sync {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, AppResult<()>>;
+}
+
+/// Emails kept by [`ConsoleMailer`] for [`outbox`](ConsoleMailer::outbox).
+const OUTBOX_CAPACITY: usize = 100;
+
+/// Logs emails instead of sending them, for development and tests.
+#[derive(Default)]
+pub struct ConsoleMailer {
+    outbox: Mutex<VecDeque<EmailMessage>>,
+}
+
+impl ConsoleMailer {
+    /// Emails sent so far, oldest first.
+    pub fn outbox(&self) -> Vec<EmailMessage> {
+        self.outbox.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl Mailer for ConsoleMailer {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> BoxFuture<'a, AppResult<()>> {
+        Box::pin(async move {
+            tracing::info!(to = %message.to, subject = %message.subject, "Email:\n{}", message.text);
+
+            let mut outbox = self.outbox.lock().unwrap();
+            if outbox.len() == OUTBOX_CAPACITY {
+                outbox.pop_front();
+            }
+            outbox.push_back(message.clone());
+            Ok(())
+        })
+    }
+}
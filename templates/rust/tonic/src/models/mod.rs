@@ -1,3 +1,9 @@
+pub mod file;
+pub mod operation;
+pub mod role;
 pub mod user;
 
-pub use user::{User, Claims};
\ No newline at end of file
+pub use file::StoredFile;
+pub use operation::Operation;
+pub use role::Grants;
+pub use user::{Claims, UpdateUser, User};
\ No newline at end of file
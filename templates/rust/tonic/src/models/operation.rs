@@ -0,0 +1,28 @@
+use chrono::{DateTime, Utc};
+use platform_core::domain::UserId;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+pub struct Operation {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub kind: String,
+    pub done: bool,
+    pub progress_percent: i32,
+    pub progress_message: Option<String>,
+    pub metadata: serde_json::Value,
+    pub response: Option<serde_json::Value>,
+    pub error_code: Option<String>,
+    pub error_message: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Operation {
+    /// The resource name, `operations/{id}`.
+    pub fn name(&self) -> String {
+        format!("operations/{}", self.id)
+    }
+}
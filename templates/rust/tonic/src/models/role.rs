@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+/// Manages users and their roles.
+pub const ROLE_ADMIN: &str = "admin";
+/// Looks up users to help them.
+pub const ROLE_SUPPORT: &str = "support";
+
+pub const USERS_READ: &str = "users.read";
+pub const USERS_WRITE: &str = "users.write";
+pub const USERS_DELETE: &str = "users.delete";
+
+/// A user's roles and the permissions they grant, as their access tokens
+/// carry them; the same claims the REST template issues.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Grants {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<String>,
+}
+
+impl Grants {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|granted| granted == role)
+    }
+
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|granted| granted == permission)
+    }
+}
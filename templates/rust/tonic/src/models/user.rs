@@ -1,47 +1,230 @@
 use chrono::{DateTime, Utc};
+use platform_auth::HashedPassword;
+use platform_core::domain::{Email, UserId, Username};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
-use uuid::Uuid;
+
+use crate::aip::{to_timestamp, FieldMaskPaths};
+use crate::errors::{AppError, AppResult};
+use crate::models::role::Grants;
+use crate::proto::user::v1::UpdateUserRequest;
 
 #[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
 pub struct User {
-    pub id: Uuid,
-    pub email: String,
-    pub username: String,
+    pub id: UserId,
+    pub email: Email,
+    pub username: Username,
     #[serde(skip_serializing)]
-    pub password_hash: String,
+    pub password_hash: HashedPassword,
     pub full_name: Option<String>,
     pub is_active: bool,
     pub is_verified: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Refresh tokens issued before this are refused; set by password
+    /// resets.
+    #[serde(skip_serializing)]
+    pub tokens_valid_after: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
-    pub sub: Uuid,
-    pub email: String,
+    pub sub: UserId,
+    pub email: Email,
     pub exp: usize,
     pub iat: usize,
+    /// Roles and permissions as of when the token was issued; absent for
+    /// users without any.
+    #[serde(flatten)]
+    pub grants: Grants,
+}
+
+/// Partial update of a user, mirroring the REST template's `UpdateUser`:
+/// `None` leaves a field untouched and `Some(None)` clears `full_name`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct UpdateUser {
+    pub email: Option<Email>,
+    pub username: Option<Username>,
+    pub full_name: Option<Option<String>>,
+    pub is_active: Option<bool>,
+}
+
+impl UpdateUser {
+    /// Fields addressable by an `UpdateUser` update mask.
+    pub const PATHS: &'static [&'static str] = &["email", "username", "full_name", "is_active"];
+
+    /// Maps a request onto a patch. Fields named by the mask but unset in the
+    /// request are reset to their default, which is rejected for required
+    /// fields, as are invalid emails and usernames.
+    pub fn from_request(req: UpdateUserRequest) -> AppResult<Self> {
+        let mask = FieldMaskPaths::parse(req.update_mask.as_ref(), Self::PATHS)?;
+
+        let required = |path: &str, value: Option<String>| -> AppResult<Option<String>> {
+            if !mask.includes(path, value.is_some()) {
+                return Ok(None);
+            }
+            match value.filter(|value| !value.is_empty()) {
+                Some(value) => Ok(Some(value)),
+                None => Err(AppError::ValidationError(format!("{} cannot be cleared", path))),
+            }
+        };
+
+        Ok(Self {
+            email: required("email", req.email)?.map(Email::parse).transpose()?,
+            username: required("username", req.username)?.map(Username::parse).transpose()?,
+            full_name: mask
+                .includes("full_name", req.full_name.is_some())
+                .then_some(req.full_name),
+            is_active: mask
+                .includes("is_active", req.is_active.is_some())
+                .then(|| req.is_active.unwrap_or_default()),
+        })
+    }
 }
 
 impl User {
     pub fn to_proto(&self) -> crate::proto::user::v1::User {
         crate::proto::user::v1::User {
             id: self.id.to_string(),
-            email: self.email.clone(),
-            username: self.username.clone(),
+            email: self.email.to_string(),
+            username: self.username.to_string(),
             full_name: self.full_name.clone(),
             is_active: self.is_active,
             is_verified: self.is_verified,
-            created_at: Some(prost_types::Timestamp {
-                seconds: self.created_at.timestamp(),
-                nanos: self.created_at.timestamp_subsec_nanos() as i32,
-            }),
-            updated_at: Some(prost_types::Timestamp {
-                seconds: self.updated_at.timestamp(),
-                nanos: self.updated_at.timestamp_subsec_nanos() as i32,
-            }),
+            created_at: Some(to_timestamp(self.created_at)),
+            updated_at: Some(to_timestamp(self.updated_at)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::option;
+    use proptest::prelude::*;
+    use proptest::sample::subsequence;
+    use prost_types::FieldMask;
+
+    fn mask(paths: &[&str]) -> Option<FieldMask> {
+        Some(FieldMask {
+            paths: paths.iter().map(|path| path.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn without_mask_writes_set_fields() {
+        let patch = UpdateUser::from_request(UpdateUserRequest {
+            username: Some("alice".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            patch,
+            UpdateUser {
+                username: Some(Username::parse("alice").unwrap()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn mask_limits_written_fields() {
+        let patch = UpdateUser::from_request(UpdateUserRequest {
+            email: Some("alice@example.com".to_string()),
+            username: Some("alice".to_string()),
+            update_mask: mask(&["username"]),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(patch.email, None);
+        assert_eq!(patch.username.unwrap(), "alice");
+    }
+
+    #[test]
+    fn masked_unset_fields_are_cleared() {
+        let patch = UpdateUser::from_request(UpdateUserRequest {
+            update_mask: mask(&["full_name", "is_active"]),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(patch.full_name, Some(None));
+        assert_eq!(patch.is_active, Some(false));
+    }
+
+    #[test]
+    fn required_fields_cannot_be_cleared() {
+        let result = UpdateUser::from_request(UpdateUserRequest {
+            update_mask: mask(&["email"]),
+            ..Default::default()
+        });
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn invalid_emails_and_usernames_are_rejected() {
+        for req in [
+            UpdateUserRequest {
+                email: Some("not-an-email".to_string()),
+                ..Default::default()
+            },
+            UpdateUserRequest {
+                username: Some("al".to_string()),
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(UpdateUser::from_request(req), Err(AppError::ValidationError(_))));
         }
     }
-}
\ No newline at end of file
+
+    proptest! {
+        #[test]
+        fn patch_writes_only_masked_fields(
+            email in option::of("([a-z]{1,6}@example\\.com)?"),
+            username in option::of("([a-z]{3,6})?"),
+            full_name in option::of("[a-z]{0,6}"),
+            is_active in option::of(any::<bool>()),
+            paths in subsequence(UpdateUser::PATHS.to_vec(), 0..=UpdateUser::PATHS.len()),
+        ) {
+            let written = |path: &str, is_set: bool| {
+                if paths.is_empty() { is_set } else { paths.contains(&path) }
+            };
+            let clears_required = [("email", &email), ("username", &username)]
+                .iter()
+                .any(|(path, value)| {
+                    written(path, value.is_some()) && value.as_deref().unwrap_or_default().is_empty()
+                });
+
+            let result = UpdateUser::from_request(UpdateUserRequest {
+                email: email.clone(),
+                username: username.clone(),
+                full_name: full_name.clone(),
+                is_active,
+                update_mask: mask(&paths),
+                ..Default::default()
+            });
+
+            match result {
+                Ok(patch) => {
+                    prop_assert!(!clears_required);
+                    prop_assert_eq!(patch.email.is_some(), written("email", email.is_some()));
+                    prop_assert_eq!(patch.username.is_some(), written("username", username.is_some()));
+                    prop_assert_eq!(patch.full_name.is_some(), written("full_name", full_name.is_some()));
+                    prop_assert_eq!(patch.is_active.is_some(), written("is_active", is_active.is_some()));
+
+                    // Written fields carry the request's value, or its default when unset
+                    if let Some(value) = patch.full_name {
+                        prop_assert_eq!(value, full_name);
+                    }
+                    if let Some(value) = patch.is_active {
+                        prop_assert_eq!(value, is_active.unwrap_or_default());
+                    }
+                }
+                Err(_) => prop_assert!(clears_required),
+            }
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tonic::{Code, Status};
+use tower::retry::budget::Budget;
+
+use super::LatencyWindow;
+
+#[derive(Debug, Clone)]
+pub struct HedgePolicy {
+    /// Latency percentile after which a hedge is sent.
+    pub percentile: f64,
+    /// Samples a method needs before its percentile is trusted; until then
+    /// hedges wait for `max_delay`.
+    pub min_samples: usize,
+    pub min_delay: Duration,
+    pub max_delay: Duration,
+    /// Attempts per call, counting the original, hedges and retries.
+    pub max_attempts: usize,
+    pub retry_backoff: Duration,
+    pub window_size: usize,
+    /// Retries and hedges may add this fraction on top of the original
+    /// calls, plus `budget_min_per_second` regardless of traffic.
+    pub budget_ratio: f32,
+    pub budget_min_per_second: u32,
+    pub budget_ttl: Duration,
+}
+
+impl Default for HedgePolicy {
+    fn default() -> Self {
+        Self {
+            percentile: 0.95,
+            min_samples: 20,
+            min_delay: Duration::from_millis(5),
+            max_delay: Duration::from_secs(1),
+            max_attempts: 3,
+            retry_backoff: Duration::from_millis(50),
+            window_size: 500,
+            budget_ratio: 0.1,
+            budget_min_per_second: 10,
+            budget_ttl: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Sends calls with hedging and budgeted retries. Cheap to clone; clones
+/// share latency history and the retry budget, so one `Hedger` per process
+/// caps the extra load all clients put on the server.
+#[derive(Clone)]
+pub struct Hedger {
+    policy: Arc<HedgePolicy>,
+    budget: Arc<Budget>,
+    latencies: Arc<Mutex<HashMap<String, LatencyWindow>>>,
+}
+
+impl Default for Hedger {
+    fn default() -> Self {
+        Self::new(HedgePolicy::default())
+    }
+}
+
+impl Hedger {
+    pub fn new(policy: HedgePolicy) -> Self {
+        let budget = Budget::new(policy.budget_ttl, policy.budget_min_per_second, policy.budget_ratio);
+        Self {
+            policy: Arc::new(policy),
+            budget: Arc::new(budget),
+            latencies: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Sends a call that is not safe to repeat. It is never hedged or
+    /// retried, but it still earns retry budget and feeds latency history.
+    pub async fn call<T, Fut>(&self, method: &str, attempt: impl FnOnce() -> Fut) -> Result<T, Status>
+    where
+        Fut: Future<Output = Result<T, Status>>,
+    {
+        self.budget.deposit();
+        let start = Instant::now();
+        let result = attempt().await;
+        if result.is_ok() {
+            self.record(method, start.elapsed());
+        }
+        result
+    }
+
+    /// Sends an idempotent call. If no response arrives within the method's
+    /// latency percentile a hedge is sent, and `Unavailable` failures are
+    /// retried; the first success wins and the other attempts are cancelled.
+    /// Every extra attempt is withdrawn from the shared retry budget.
+    pub async fn idempotent<T, Fut>(&self, method: &str, mut attempt: impl FnMut() -> Fut) -> Result<T, Status>
+    where
+        Fut: Future<Output = Result<T, Status>>,
+    {
+        self.budget.deposit();
+
+        let mut in_flight = FuturesUnordered::new();
+        in_flight.push(timed(Attempt::Original, Duration::ZERO, attempt()));
+        let mut sent = 1;
+
+        let hedge_timer = tokio::time::sleep(self.hedge_delay(method));
+        tokio::pin!(hedge_timer);
+        let mut hedge_pending = true;
+        let mut hedge_in_flight = false;
+
+        loop {
+            tokio::select! {
+                Some((kind, elapsed, result)) = in_flight.next() => {
+                    hedge_in_flight &= kind != Attempt::Hedge;
+
+                    let status = match result {
+                        Ok(response) => {
+                            self.record(method, elapsed);
+
+                            // Attempts still in flight are dropped on return,
+                            // which cancels their streams
+                            if kind == Attempt::Hedge {
+                                metrics::counter!("grpc_client_hedges_won_total", "grpc_method" => method.to_string())
+                                    .increment(1);
+                            } else if hedge_in_flight {
+                                metrics::counter!("grpc_client_hedges_cancelled_total", "grpc_method" => method.to_string())
+                                    .increment(1);
+                            }
+                            return Ok(response);
+                        }
+                        Err(status) => status,
+                    };
+
+                    if status.code() == Code::Unavailable && sent < self.policy.max_attempts && self.withdraw(method) {
+                        metrics::counter!("grpc_client_retries_total", "grpc_method" => method.to_string()).increment(1);
+                        in_flight.push(timed(Attempt::Retry, self.policy.retry_backoff, attempt()));
+                        sent += 1;
+                    } else if in_flight.is_empty() {
+                        return Err(status);
+                    }
+                }
+                _ = &mut hedge_timer, if hedge_pending => {
+                    hedge_pending = false;
+                    if sent < self.policy.max_attempts && self.withdraw(method) {
+                        metrics::counter!("grpc_client_hedges_total", "grpc_method" => method.to_string()).increment(1);
+                        in_flight.push(timed(Attempt::Hedge, Duration::ZERO, attempt()));
+                        sent += 1;
+                        hedge_in_flight = true;
+                    }
+                }
+            }
+        }
+    }
+
+    fn hedge_delay(&self, method: &str) -> Duration {
+        let latencies = self.latencies.lock().unwrap();
+        latencies
+            .get(method)
+            .filter(|window| window.len() >= self.policy.min_samples)
+            .and_then(|window| window.percentile(self.policy.percentile))
+            .map(|delay| delay.clamp(self.policy.min_delay, self.policy.max_delay))
+            .unwrap_or(self.policy.max_delay)
+    }
+
+    fn record(&self, method: &str, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        latencies
+            .entry(method.to_string())
+            .or_insert_with(|| LatencyWindow::new(self.policy.window_size))
+            .record(latency);
+    }
+
+    fn withdraw(&self, method: &str) -> bool {
+        let withdrawn = self.budget.withdraw().is_ok();
+        if !withdrawn {
+            metrics::counter!("grpc_client_retry_budget_exhausted_total", "grpc_method" => method.to_string())
+                .increment(1);
+        }
+        withdrawn
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Attempt {
+    Original,
+    Hedge,
+    Retry,
+}
+
+async fn timed<T>(
+    kind: Attempt,
+    backoff: Duration,
+    attempt: impl Future<Output = Result<T, Status>>,
+) -> (Attempt, Duration, Result<T, Status>) {
+    if !backoff.is_zero() {
+        tokio::time::sleep(backoff).await;
+    }
+    let start = Instant::now();
+    let result = attempt.await;
+    (kind, start.elapsed(), result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn policy() -> HedgePolicy {
+        HedgePolicy {
+            max_delay: Duration::from_millis(20),
+            retry_backoff: Duration::from_millis(1),
+            ..HedgePolicy::default()
+        }
+    }
+
+    /// An attempt closure whose n-th call sleeps for `delays[n]` and then
+    /// returns `results[n]`.
+    fn scripted(
+        calls: &Arc<AtomicUsize>,
+        script: &[(u64, Result<usize, Code>)],
+    ) -> impl FnMut() -> futures_util::future::BoxFuture<'static, Result<usize, Status>> {
+        let calls = calls.clone();
+        let script = script.to_vec();
+        move || {
+            let (delay, result) = script[calls.fetch_add(1, Ordering::SeqCst)];
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                result.map_err(|code| Status::new(code, "scripted"))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_call_is_hedged() {
+        let hedger = Hedger::new(policy());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let result = hedger
+            .idempotent("/test.Service/Get", scripted(&calls, &[(5_000, Ok(1)), (0, Ok(2))]))
+            .await;
+
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn unavailable_is_retried() {
+        let hedger = Hedger::new(policy());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let result = hedger
+            .idempotent(
+                "/test.Service/Get",
+                scripted(&calls, &[(0, Err(Code::Unavailable)), (0, Ok(2))]),
+            )
+            .await;
+
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_not_retried() {
+        let hedger = Hedger::new(policy());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let result = hedger
+            .idempotent("/test.Service/Get", scripted(&calls, &[(0, Err(Code::NotFound))]))
+            .await;
+
+        assert_eq!(result.unwrap_err().code(), Code::NotFound);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn exhausted_budget_stops_retries() {
+        let hedger = Hedger::new(HedgePolicy {
+            budget_ratio: 0.0,
+            budget_min_per_second: 0,
+            ..policy()
+        });
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let result = hedger
+            .idempotent(
+                "/test.Service/Get",
+                scripted(&calls, &[(0, Err(Code::Unavailable)), (0, Ok(2))]),
+            )
+            .await;
+
+        assert_eq!(result.unwrap_err().code(), Code::Unavailable);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn hedge_delay_follows_observed_latency() {
+        let hedger = Hedger::new(HedgePolicy {
+            min_samples: 2,
+            ..policy()
+        });
+        assert_eq!(hedger.hedge_delay("/test.Service/Get"), Duration::from_millis(20));
+
+        hedger.record("/test.Service/Get", Duration::from_millis(8));
+        hedger.record("/test.Service/Get", Duration::from_millis(10));
+        assert_eq!(hedger.hedge_delay("/test.Service/Get"), Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// The most recent `capacity` latencies of one method, used to derive the
+/// delay before a hedge is sent.
+#[derive(Debug)]
+pub struct LatencyWindow {
+    samples: VecDeque<Duration>,
+    capacity: usize,
+}
+
+impl LatencyWindow {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Nearest-rank percentile, `quantile` in `0.0..=1.0`.
+    pub fn percentile(&self, quantile: f64) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let mut sorted: Vec<_> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (quantile.clamp(0.0, 1.0) * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.saturating_sub(1)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(value: u64) -> Duration {
+        Duration::from_millis(value)
+    }
+
+    #[test]
+    fn percentile_uses_nearest_rank() {
+        let mut window = LatencyWindow::new(100);
+        for value in 1..=100 {
+            window.record(ms(value));
+        }
+
+        assert_eq!(window.percentile(0.95), Some(ms(95)));
+        assert_eq!(window.percentile(0.5), Some(ms(50)));
+        assert_eq!(window.percentile(1.0), Some(ms(100)));
+    }
+
+    #[test]
+    fn window_keeps_latest_samples() {
+        let mut window = LatencyWindow::new(2);
+        window.record(ms(500));
+        window.record(ms(10));
+        window.record(ms(20));
+
+        assert_eq!(window.len(), 2);
+        assert_eq!(window.percentile(1.0), Some(ms(20)));
+    }
+
+    #[test]
+    fn empty_window_has_no_percentile() {
+        assert_eq!(LatencyWindow::new(10).percentile(0.95), None);
+    }
+}
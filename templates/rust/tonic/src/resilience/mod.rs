@@ -0,0 +1,11 @@
+//! Client-side call policies: hedging for idempotent reads and a retry
+//! budget shared by every retry and hedge a client sends.
+//!
+//! Request bodies can't be replayed at the transport level, so hedging works
+//! per RPC: each attempt is a closure that builds and sends a fresh request.
+
+pub mod hedging;
+pub mod latency;
+
+pub use hedging::{HedgePolicy, Hedger};
+pub use latency::LatencyWindow;
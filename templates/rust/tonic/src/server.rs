@@ -1,61 +1,40 @@
 use anyhow::Result;
+use futures_util::future::{self, BoxFuture, FutureExt};
+use platform_auth::workload::WorkloadVerifier;
+use platform_core::clock::SystemClock;
+use platform_core::lifecycle::{shutdown_signal, Draining, Hook, Lifecycle};
+use platform_core::rate_limit;
+use platform_observability::log_level::{self, LogLevel};
+use platform_observability::otel;
 use sqlx::postgres::PgPoolOptions;
-use std::net::SocketAddr;
 use std::sync::Arc;
-use tonic::transport::Server;
-use tracing::{info, Level};
-use tracing_subscriber::FmtSubscriber;
-
-mod config;
-mod errors;
-mod interceptors;
-mod models;
-mod services;
-mod utils;
-
-use crate::config::Settings;
-use crate::interceptors::{auth_interceptor, logging_interceptor};
-use crate::services::{health::HealthServiceImpl, user::UserServiceImpl};
-
-// Include the generated proto files
-pub mod proto {
-    pub mod health {
-        pub mod v1 {
-            tonic::include_proto!("health.v1");
-        }
-    }
-    pub mod user {
-        pub mod v1 {
-            tonic::include_proto!("user.v1");
-        }
-    }
-}
-
-use proto::health::v1::health_service_server::HealthServiceServer;
-use proto::user::v1::user_service_server::UserServiceServer;
-
-#[derive(Clone)]
-pub struct AppState {
-    pub db: sqlx::PgPool,
-    pub settings: Settings,
-}
+use std::time::Duration;
+use tracing::info;
+
+use tonic_template::config::{ListenerRole, Settings};
+use tonic_template::discovery;
+use tonic_template::mail::ConsoleMailer;
+use tonic_template::operations::OperationStore;
+use tonic_template::storage::Storage;
+use tonic_template::transport;
+use tonic_template::utils::JwtKeys;
+use tonic_template::AppState;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Load environment variables
     dotenv::dotenv().ok();
 
-    // Initialize tracing
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(Level::INFO)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber)?;
-
-    // Load configuration
+    // Load configuration, then tracing with the configured filter,
+    // exporting spans if enabled
     let settings = Settings::new()?;
-    let addr: SocketAddr = format!("{}:{}", settings.server.host, settings.server.port).parse()?;
+    log_level::init(&settings.log_level, &settings.otel)?;
+    let listeners = transport::bind(&settings.server)?;
 
-    info!("Starting gRPC server at {}", addr);
+    for listener in &listeners {
+        let security = if listener.tls.is_some() { "TLS" } else { "plaintext" };
+        info!("Starting {:?} gRPC listener ({}) at {}", listener.role, security, listener.socket.local_addr()?);
+    }
 
     // Create database pool
     let db_pool = PgPoolOptions::new()
@@ -66,27 +45,72 @@ async fn main() -> Result<()> {
     // Run migrations
     sqlx::migrate!("./migrations").run(&db_pool).await?;
 
+    // Create file storage
+    let storage = Storage::new(&settings.storage.path).await?;
+
     // Create app state
+    let operations = OperationStore::new(db_pool.clone(), &settings.operations);
     let app_state = Arc::new(AppState {
         db: db_pool,
         settings: settings.clone(),
+        jwt_keys: JwtKeys::from_settings(&settings.jwt)?,
+        storage,
+        operations,
+        log_level: Arc::new(LogLevel::new(settings.log_level.clone(), Arc::new(SystemClock))),
+        workload_verifier: WorkloadVerifier::from_settings(&settings.workload_identity, Arc::new(SystemClock))?
+            .map(Arc::new),
+        mailer: Arc::new(ConsoleMailer::default()),
+        rate_limiter: rate_limit::from_settings(&settings.limits.backend).await?,
+        draining: Draining::default(),
     });
 
-    // Create services
-    let health_service = HealthServiceImpl::new(app_state.clone());
-    let user_service = UserServiceImpl::new(app_state.clone());
+    // Connections are served by our own accept loop so they can be aged
+    // out individually
+    let service = tonic_template::grpc_service(app_state.clone());
+    let db = app_state.db.clone();
+    let draining = app_state.draining.clone();
+    let admin_service = tonic_template::admin_service(app_state);
+    let servers = listeners.into_iter().map(|listener| -> BoxFuture<'_, Result<()>> {
+        let draining = draining.clone();
+        match listener.role {
+            ListenerRole::Public => transport::serve(listener, service.clone(), &settings.server, draining).boxed(),
+            ListenerRole::Admin => transport::serve(listener, admin_service.clone(), &settings.server, draining).boxed(),
+        }
+    });
 
-    // Build the server
-    let server = Server::builder()
-        .layer(tower::ServiceBuilder::new().layer(tower_http::trace::TraceLayer::new_for_grpc()))
-        .add_service(HealthServiceServer::new(health_service))
-        .add_service(
-            UserServiceServer::with_interceptor(user_service, auth_interceptor)
-        )
-        .serve(addr);
+    // Hooks run around serving; shutdown hooks once the listeners have
+    // drained
+    let mut lifecycle = Lifecycle::new(&settings.lifecycle);
+    discovery::register_hooks(&mut lifecycle, &settings)?;
+    let close_database = Hook::new("database", move || async move {
+        db.close().await;
+        Ok(())
+    });
+    lifecycle.on_shutdown(close_database.order(100));
+    lifecycle.start().await?;
+
+    // Run the server until asked to stop
+    let servers = future::try_join_all(servers);
+    tokio::pin!(servers);
+    lifecycle.ready().await;
+    let stopped = tokio::select! {
+        result = &mut servers => Some(result.map(drop)),
+        _ = shutdown_signal() => None,
+    };
+    let result = match stopped {
+        Some(result) => result,
+        // The listeners accept until the drain closes them, then return once
+        // their connections finish
+        None => {
+            let delay = Duration::from_secs(settings.server.drain_delay_secs);
+            let (result, ()) = future::join(servers, draining.drain(delay)).await;
+            result.map(drop)
+        }
+    };
 
-    // Run the server
-    server.await?;
+    lifecycle.shutdown().await;
+    tokio::task::spawn_blocking(otel::shutdown).await?;
+    result?;
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,131 @@
+use platform_observability::log_level::LogLevelStatus;
+use platform_observability::runtime_stats;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::{Request, Response, Status};
+
+use crate::aip::to_timestamp;
+use crate::errors::{AppError, AppResult};
+use crate::proto::admin::v1::admin_service_server::AdminService;
+use crate::proto::admin::v1::*;
+use crate::services::claims;
+use crate::{AppState, FILE_DESCRIPTOR_SET};
+
+pub struct AdminServiceImpl {
+    state: Arc<AppState>,
+}
+
+impl AdminServiceImpl {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+}
+
+impl From<LogLevelStatus> for LogLevel {
+    fn from(status: LogLevelStatus) -> Self {
+        LogLevel {
+            filter: status.filter,
+            default_filter: status.default_filter,
+            revert_time: status.reverts_at.map(to_timestamp),
+        }
+    }
+}
+
+impl From<runtime_stats::RuntimeStats> for RuntimeStats {
+    fn from(stats: runtime_stats::RuntimeStats) -> Self {
+        let to_duration = |duration: Duration| prost_types::Duration::try_from(duration).unwrap_or_default();
+
+        RuntimeStats {
+            workers: stats.workers as u64,
+            alive_tasks: stats.alive_tasks as u64,
+            global_queue_depth: stats.global_queue_depth as u64,
+            worker_busy: stats.worker_busy.into_iter().map(to_duration).collect(),
+            worker_park_count: stats.worker_park_count,
+            blocking_pool: stats.blocking_pool.map(|pool| BlockingPoolStats {
+                threads: pool.threads as u64,
+                idle_threads: pool.idle_threads as u64,
+                queue_depth: pool.queue_depth as u64,
+            }),
+            worker_mean_poll_time: stats.worker_mean_poll_time.into_iter().map(to_duration).collect(),
+            console: cfg!(feature = "console"),
+        }
+    }
+}
+
+fn revert_after(duration: Option<prost_types::Duration>) -> AppResult<Option<Duration>> {
+    duration
+        .map(|duration| {
+            Duration::try_from(duration)
+                .map_err(|_| AppError::ValidationError("revert_after must be positive".to_string()))
+        })
+        .transpose()
+}
+
+/// Records who changed the filter, alongside the other audit events in the
+/// logs.
+fn audit_change<T>(request: &Request<T>, previous: &LogLevelStatus, status: &LogLevelStatus) -> AppResult<()> {
+    tracing::info!(
+        target: "audit",
+        event_type = "admin.log_level_changed",
+        user_id = %claims(request)?.sub,
+        ip_address = ?request.remote_addr().map(|addr| addr.ip()),
+        filter = %status.filter,
+        previous = %previous.filter,
+        reverts_at = ?status.reverts_at,
+        "Audit event recorded"
+    );
+    Ok(())
+}
+
+#[tonic::async_trait]
+impl AdminService for AdminServiceImpl {
+    async fn get_log_level(&self, request: Request<GetLogLevelRequest>) -> Result<Response<LogLevel>, Status> {
+        claims(&request)?;
+
+        Ok(Response::new(self.state.log_level.status().into()))
+    }
+
+    async fn set_log_level(&self, request: Request<SetLogLevelRequest>) -> Result<Response<LogLevel>, Status> {
+        claims(&request)?;
+        let message = request.get_ref();
+        let revert_after = revert_after(message.revert_after.clone())?;
+
+        let previous = self.state.log_level.status();
+        let status = self.state.log_level.set(&message.filter, revert_after)?;
+        audit_change(&request, &previous, &status)?;
+
+        Ok(Response::new(status.into()))
+    }
+
+    async fn reset_log_level(&self, request: Request<ResetLogLevelRequest>) -> Result<Response<LogLevel>, Status> {
+        claims(&request)?;
+
+        let previous = self.state.log_level.status();
+        let status = self.state.log_level.reset()?;
+        audit_change(&request, &previous, &status)?;
+
+        Ok(Response::new(status.into()))
+    }
+
+    async fn get_runtime_stats(
+        &self,
+        request: Request<GetRuntimeStatsRequest>,
+    ) -> Result<Response<RuntimeStats>, Status> {
+        claims(&request)?;
+
+        Ok(Response::new(runtime_stats::RuntimeStats::current().into()))
+    }
+
+    async fn get_descriptor_set(
+        &self,
+        request: Request<GetDescriptorSetRequest>,
+    ) -> Result<Response<DescriptorSet>, Status> {
+        claims(&request)?;
+
+        Ok(Response::new(DescriptorSet {
+            file_descriptor_set: FILE_DESCRIPTOR_SET.to_vec(),
+            sha256: hex::encode(Sha256::digest(FILE_DESCRIPTOR_SET)),
+        }))
+    }
+}
@@ -0,0 +1,199 @@
+use platform_core::domain::UserId;
+use sha2::{Digest, Sha256};
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tonic::metadata::MetadataValue;
+use tonic::{Request, Response, Status, Streaming};
+use uuid::Uuid;
+
+use crate::errors::{AppError, AppResult, ErrorCode, ResultExt};
+use crate::models::StoredFile;
+use crate::proto::file::v1::file_service_server::FileService;
+use crate::proto::file::v1::upload_file_request::Data;
+use crate::proto::file::v1::*;
+use crate::services::claims;
+use crate::AppState;
+
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+pub struct FileServiceImpl {
+    state: Arc<AppState>,
+}
+
+impl FileServiceImpl {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+
+    async fn find_file(&self, file_id: Uuid, owner_id: UserId) -> AppResult<StoredFile> {
+        sqlx::query_as::<_, StoredFile>("SELECT * FROM files WHERE id = $1 AND owner_id = $2")
+            .bind(file_id)
+            .bind(owner_id)
+            .fetch_optional(&self.state.db)
+            .await
+            .entity_context("load file", file_id)?
+            .ok_or_else(|| AppError::NotFound("File not found".to_string()).with_code(ErrorCode::FileNotFound))
+    }
+}
+
+fn file_too_large(max_bytes: u64) -> AppError {
+    AppError::PayloadTooLarge(format!("Files are limited to {} bytes", max_bytes)).with_code(ErrorCode::FileTooLarge)
+}
+
+/// Reads the leading metadata message of an upload.
+async fn read_metadata(stream: &mut Streaming<UploadFileRequest>) -> Result<FileMetadata, Status> {
+    match stream.message().await? {
+        Some(UploadFileRequest {
+            data: Some(Data::Metadata(metadata)),
+        }) => Ok(metadata),
+        _ => Err(Status::invalid_argument("The first message must carry the file metadata")),
+    }
+}
+
+#[tonic::async_trait]
+impl FileService for FileServiceImpl {
+    type DownloadFileStream = Pin<Box<dyn Stream<Item = Result<DownloadFileResponse, Status>> + Send>>;
+
+    async fn upload_file(
+        &self,
+        request: Request<Streaming<UploadFileRequest>>,
+    ) -> Result<Response<UploadFileResponse>, Status> {
+        let owner_id = claims(&request)?.sub;
+        let max_bytes = self.state.settings.storage.max_upload_bytes;
+        let mut stream = request.into_inner();
+
+        let metadata = read_metadata(&mut stream).await?;
+        if metadata.filename.is_empty() {
+            return Err(AppError::ValidationError("Filename is required".to_string()).into());
+        }
+        if metadata.size > max_bytes {
+            return Err(file_too_large(max_bytes).into());
+        }
+
+        // Dropping the pending file on any early return discards the partial upload
+        let file_id = Uuid::new_v4();
+        let mut pending = self.state.storage.create(file_id).await.entity_context("create file", file_id)?;
+        let mut hasher = Sha256::new();
+        let mut received: u64 = 0;
+
+        while let Some(message) = stream.message().await? {
+            let Some(Data::Chunk(chunk)) = message.data else {
+                return Err(Status::invalid_argument("Only the first message may carry metadata"));
+            };
+
+            received += chunk.len() as u64;
+            if received > max_bytes {
+                return Err(file_too_large(max_bytes).into());
+            }
+
+            hasher.update(&chunk);
+            pending.write(&chunk).await.entity_context("write file", file_id)?;
+        }
+
+        if metadata.size != 0 && received != metadata.size {
+            return Err(Status::invalid_argument(format!(
+                "Received {} bytes, expected {}",
+                received, metadata.size
+            )));
+        }
+
+        let sha256 = hex::encode(hasher.finalize());
+        if !metadata.sha256.is_empty() && !metadata.sha256.eq_ignore_ascii_case(&sha256) {
+            return Err(Status::data_loss("Checksum mismatch"));
+        }
+
+        pending.commit().await.entity_context("commit file", file_id)?;
+
+        let content_type = if metadata.content_type.is_empty() {
+            DEFAULT_CONTENT_TYPE.to_string()
+        } else {
+            metadata.content_type
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO files (id, owner_id, filename, content_type, size, sha256)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(file_id)
+        .bind(owner_id)
+        .bind(&metadata.filename)
+        .bind(&content_type)
+        .bind(received as i64)
+        .bind(&sha256)
+        .execute(&self.state.db)
+        .await
+        .entity_context("insert file", file_id)?;
+
+        tracing::info!("Stored file {} ({} bytes)", file_id, received);
+
+        Ok(Response::new(UploadFileResponse {
+            file_id: file_id.to_string(),
+            size: received,
+            sha256,
+        }))
+    }
+
+    async fn download_file(
+        &self,
+        request: Request<DownloadFileRequest>,
+    ) -> Result<Response<Self::DownloadFileStream>, Status> {
+        let owner_id = claims(&request)?.sub;
+        let file_id = Uuid::parse_str(&request.get_ref().file_id)
+            .map_err(|_| Status::invalid_argument("Invalid file id"))?;
+
+        let file = self.find_file(file_id, owner_id).await?;
+        let mut reader = self.state.storage.open(file_id).await.entity_context("open file", file_id)?;
+        let chunk_size = self.state.settings.storage.chunk_size;
+        let total_size = file.size as u64;
+
+        let (tx, rx) = mpsc::channel(4);
+        tokio::spawn(async move {
+            let mut offset: u64 = 0;
+            let mut buffer = vec![0u8; chunk_size];
+            loop {
+                let message = match reader.read(&mut buffer).await {
+                    Ok(0) => break,
+                    Ok(read) => {
+                        let chunk = DownloadFileResponse {
+                            chunk: buffer[..read].to_vec(),
+                            offset,
+                            total_size,
+                        };
+                        offset += read as u64;
+                        Ok(chunk)
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to read file {}: {}", file_id, e);
+                        Err(Status::internal("Storage error"))
+                    }
+                };
+
+                let failed = message.is_err();
+                if tx.send(message).await.is_err() || failed {
+                    // Client disconnected or the read failed
+                    break;
+                }
+            }
+        });
+
+        // Sent as initial metadata so clients can show progress and verify
+        // the content before the first chunk arrives
+        let mut response = Response::new(Box::pin(ReceiverStream::new(rx)) as Self::DownloadFileStream);
+        let headers = response.metadata_mut();
+        headers.insert("x-file-size", MetadataValue::from(total_size));
+        headers.insert("x-file-sha256", file.sha256.parse().map_err(|_| Status::internal("Invalid checksum"))?);
+        if let Ok(content_type) = file.content_type.parse() {
+            headers.insert("x-content-type", content_type);
+        }
+        if let Ok(filename) = file.filename.parse() {
+            headers.insert("x-filename", filename);
+        }
+
+        Ok(response)
+    }
+}
@@ -0,0 +1,127 @@
+//! `grpc.health.v1.Health`, the standard health checking protocol, for
+//! grpcurl, Kubernetes gRPC probes and load balancers. It reports what
+//! `health.v1.HealthService` does: SERVING while the database answers and
+//! the server isn't draining, for the server as a whole (`""`) and for each
+//! service it serves. Service names come from the generated servers, so
+//! they can't drift from what the listeners actually serve.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tonic::server::NamedService;
+use tonic::{Request, Response, Status};
+
+use super::admin::AdminServiceImpl;
+use super::file::FileServiceImpl;
+use super::health::{database_up, HealthServiceImpl, WATCH_INTERVAL};
+use super::operations::OperationsServiceImpl;
+use super::user::UserServiceImpl;
+use crate::proto::admin::v1::admin_service_server::AdminServiceServer;
+use crate::proto::file::v1::file_service_server::FileServiceServer;
+use crate::proto::grpc::health::v1::health_check_response::ServingStatus;
+use crate::proto::grpc::health::v1::health_server::{Health, HealthServer};
+use crate::proto::grpc::health::v1::{HealthCheckRequest, HealthCheckResponse};
+use crate::proto::health::v1::health_service_server::HealthServiceServer;
+use crate::proto::operations::v1::operations_server::OperationsServer;
+use crate::proto::user::v1::user_service_server::UserServiceServer;
+use crate::AppState;
+
+/// The services of public listeners, as checks name them. Reflection is
+/// added when it's enabled; see [`service_name`].
+pub const PUBLIC_SERVICES: &[&str] = &[
+    <HealthServer<GrpcHealthServiceImpl> as NamedService>::NAME,
+    <HealthServiceServer<HealthServiceImpl> as NamedService>::NAME,
+    <UserServiceServer<UserServiceImpl> as NamedService>::NAME,
+    <FileServiceServer<FileServiceImpl> as NamedService>::NAME,
+    <OperationsServer<OperationsServiceImpl> as NamedService>::NAME,
+];
+
+/// The services of admin listeners.
+pub const ADMIN_SERVICES: &[&str] = &[
+    <HealthServer<GrpcHealthServiceImpl> as NamedService>::NAME,
+    <HealthServiceServer<HealthServiceImpl> as NamedService>::NAME,
+    <AdminServiceServer<AdminServiceImpl> as NamedService>::NAME,
+];
+
+/// The name of a service built at runtime, for services whose type can't
+/// be named, like the reflection server.
+pub fn service_name<S: NamedService>(_: &S) -> &'static str {
+    S::NAME
+}
+
+pub struct GrpcHealthServiceImpl {
+    state: Arc<AppState>,
+    /// Services a check may name besides the server as a whole.
+    services: Arc<[&'static str]>,
+}
+
+impl GrpcHealthServiceImpl {
+    pub fn new(state: Arc<AppState>, services: impl Into<Arc<[&'static str]>>) -> Self {
+        Self {
+            state,
+            services: services.into(),
+        }
+    }
+}
+
+/// Every service shares the database, so all of them serve or none do.
+async fn serving_status(state: &AppState, services: &[&str], service: &str) -> ServingStatus {
+    if !service.is_empty() && !services.contains(&service) {
+        ServingStatus::ServiceUnknown
+    } else if database_up(state).await && !state.draining.is_draining() {
+        ServingStatus::Serving
+    } else {
+        ServingStatus::NotServing
+    }
+}
+
+fn response(status: ServingStatus) -> HealthCheckResponse {
+    HealthCheckResponse { status: status.into() }
+}
+
+#[tonic::async_trait]
+impl Health for GrpcHealthServiceImpl {
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<HealthCheckResponse, Status>> + Send>>;
+
+    async fn check(&self, request: Request<HealthCheckRequest>) -> Result<Response<HealthCheckResponse>, Status> {
+        let service = &request.get_ref().service;
+        match serving_status(&self.state, &self.services, service).await {
+            ServingStatus::ServiceUnknown => Err(Status::not_found(format!("unknown service {}", service))),
+            status => Ok(Response::new(response(status))),
+        }
+    }
+
+    /// Checks again every [`WATCH_INTERVAL`] but only sends changes. The
+    /// stream ends once the server closes its listeners, so the connection
+    /// can close too.
+    async fn watch(&self, request: Request<HealthCheckRequest>) -> Result<Response<Self::WatchStream>, Status> {
+        let service = request.into_inner().service;
+        let (tx, rx) = mpsc::channel(4);
+        let (state, services) = (self.state.clone(), self.services.clone());
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(WATCH_INTERVAL);
+            let mut sent = None;
+            loop {
+                let closed = tokio::select! {
+                    _ = interval.tick() => false,
+                    _ = state.draining.closed() => true,
+                    _ = tx.closed() => break,
+                };
+                let status = serving_status(&state, &services, &service).await;
+                if sent != Some(status) {
+                    if tx.send(Ok(response(status))).await.is_err() {
+                        break;
+                    }
+                    sent = Some(status);
+                }
+                if closed {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
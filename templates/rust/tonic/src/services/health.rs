@@ -0,0 +1,93 @@
+use chrono::Utc;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tonic::{Request, Response, Status};
+
+use crate::proto::health::v1::health_check_response::ServingStatus;
+use crate::proto::health::v1::health_service_server::HealthService;
+use crate::proto::health::v1::{HealthCheckRequest, HealthCheckResponse};
+use crate::AppState;
+
+/// How often watched health is checked again.
+pub(super) const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct HealthServiceImpl {
+    state: Arc<AppState>,
+}
+
+impl HealthServiceImpl {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+}
+
+/// Whether the database answers a query.
+pub(super) async fn database_up(state: &AppState) -> bool {
+    sqlx::query("SELECT 1").execute(&state.db).await.is_ok()
+}
+
+/// NOT_SERVING while the database is down, and once the server is
+/// draining so load balancers stop sending calls.
+async fn check_health(state: &AppState) -> HealthCheckResponse {
+    let database = database_up(state).await;
+
+    let status = if database && !state.draining.is_draining() {
+        ServingStatus::Serving
+    } else {
+        ServingStatus::NotServing
+    };
+
+    let mut metadata = HashMap::new();
+    metadata.insert(
+        "database".to_string(),
+        if database { "healthy" } else { "unhealthy" }.to_string(),
+    );
+
+    HealthCheckResponse {
+        status: status.into(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        timestamp: Utc::now().to_rfc3339(),
+        metadata,
+    }
+}
+
+#[tonic::async_trait]
+impl HealthService for HealthServiceImpl {
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<HealthCheckResponse, Status>> + Send>>;
+
+    async fn check(
+        &self,
+        _request: Request<HealthCheckRequest>,
+    ) -> Result<Response<HealthCheckResponse>, Status> {
+        Ok(Response::new(check_health(&self.state).await))
+    }
+
+    async fn watch(
+        &self,
+        _request: Request<HealthCheckRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let (tx, rx) = mpsc::channel(4);
+        let state = self.state.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(WATCH_INTERVAL);
+            loop {
+                let closed = tokio::select! {
+                    _ = interval.tick() => false,
+                    _ = state.draining.closed() => true,
+                };
+                if tx.send(Ok(check_health(&state).await)).await.is_err() || closed {
+                    // Client disconnected, or the stream ends so its
+                    // connection can close
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
@@ -1,2 +1,28 @@
+use platform_auth::workload::WorkloadIdentity;
+use tonic::Request;
+
+use crate::errors::{AppError, AppResult};
+use crate::models::Claims;
+
+pub mod admin;
+pub mod file;
+pub mod grpc_health;
 pub mod health;
-pub mod user;
\ No newline at end of file
+pub mod operations;
+pub mod user;
+
+/// Claims attached by the auth layer; only present on authenticated methods.
+pub(crate) fn claims<T>(request: &Request<T>) -> AppResult<&Claims> {
+    request.extensions().get::<Claims>().ok_or(AppError::Unauthorized)
+}
+
+/// The calling service's identity if it was granted `role`; users are
+/// turned away. See [`platform_auth::workload`].
+pub fn require_workload_role<'a, T>(request: &'a Request<T>, role: &str) -> AppResult<&'a WorkloadIdentity> {
+    let identity = request
+        .extensions()
+        .get::<WorkloadIdentity>()
+        .ok_or(AppError::Forbidden)?;
+    identity.require_role(role)?;
+    Ok(identity)
+}
@@ -0,0 +1,151 @@
+use futures_util::StreamExt;
+use prost_types::{value::Kind, ListValue, Struct, Value};
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+use crate::aip::to_timestamp;
+use crate::errors::{AppError, AppResult};
+use crate::models::Operation as OperationRow;
+use crate::proto::operations::v1::operation::Result as OperationResult;
+use crate::proto::operations::v1::operations_server::Operations;
+use crate::proto::operations::v1::*;
+use crate::services::claims;
+use crate::AppState;
+
+pub struct OperationsServiceImpl {
+    state: Arc<AppState>,
+}
+
+impl OperationsServiceImpl {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+}
+
+/// Parses `operations/{id}`.
+fn parse_name(name: &str) -> AppResult<Uuid> {
+    name.strip_prefix("operations/")
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| AppError::ValidationError("Operation names look like operations/{id}".to_string()))
+}
+
+fn to_struct(value: serde_json::Value) -> Struct {
+    match to_value(value).kind {
+        Some(Kind::StructValue(fields)) => fields,
+        // Non-object results are wrapped so they still fit a Struct
+        kind => Struct {
+            fields: [("value".to_string(), Value { kind })].into(),
+        },
+    }
+}
+
+fn to_value(value: serde_json::Value) -> Value {
+    let kind = match value {
+        serde_json::Value::Null => Kind::NullValue(0),
+        serde_json::Value::Bool(b) => Kind::BoolValue(b),
+        serde_json::Value::Number(n) => Kind::NumberValue(n.as_f64().unwrap_or_default()),
+        serde_json::Value::String(s) => Kind::StringValue(s),
+        serde_json::Value::Array(values) => Kind::ListValue(ListValue {
+            values: values.into_iter().map(to_value).collect(),
+        }),
+        serde_json::Value::Object(fields) => Kind::StructValue(Struct {
+            fields: fields.into_iter().map(|(key, value)| (key, to_value(value))).collect(),
+        }),
+    };
+    Value { kind: Some(kind) }
+}
+
+impl From<OperationRow> for Operation {
+    fn from(operation: OperationRow) -> Self {
+        let name = operation.name();
+        let result = match (operation.error_code, operation.response) {
+            (Some(code), _) => Some(OperationResult::Error(OperationError {
+                code,
+                message: operation.error_message.unwrap_or_default(),
+            })),
+            (None, Some(response)) => Some(OperationResult::Response(to_struct(response))),
+            (None, None) => None,
+        };
+
+        Operation {
+            name,
+            done: operation.done,
+            metadata: Some(OperationMetadata {
+                kind: operation.kind,
+                progress_percent: operation.progress_percent,
+                progress_message: operation.progress_message,
+                details: Some(to_struct(operation.metadata)),
+                create_time: Some(to_timestamp(operation.created_at)),
+                update_time: Some(to_timestamp(operation.updated_at)),
+            }),
+            result,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Operations for OperationsServiceImpl {
+    type WatchOperationStream = Pin<Box<dyn Stream<Item = Result<Operation, Status>> + Send>>;
+
+    async fn get_operation(&self, request: Request<GetOperationRequest>) -> Result<Response<Operation>, Status> {
+        let user_id = claims(&request)?.sub;
+        let operation_id = parse_name(&request.get_ref().name)?;
+
+        let operation = self.state.operations.get(user_id, operation_id).await?;
+
+        Ok(Response::new(operation.into()))
+    }
+
+    async fn watch_operation(
+        &self,
+        request: Request<WatchOperationRequest>,
+    ) -> Result<Response<Self::WatchOperationStream>, Status> {
+        let user_id = claims(&request)?.sub;
+        let operation_id = parse_name(&request.get_ref().name)?;
+
+        // Fail the call itself rather than the stream if it doesn't exist
+        self.state.operations.get(user_id, operation_id).await?;
+
+        // Stream items are dictated by tonic's streaming API
+        #[allow(clippy::result_large_err)]
+        let to_message = |update: AppResult<OperationRow>| update.map(Operation::from).map_err(Status::from);
+        let updates = self.state.operations.watch(user_id, operation_id).map(to_message);
+
+        Ok(Response::new(Box::pin(updates)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn names_must_be_operation_resources() {
+        let id = Uuid::new_v4();
+        assert_eq!(parse_name(&format!("operations/{}", id)).unwrap(), id);
+
+        for name in ["", "operations/", "operations/not-a-uuid", &id.to_string(), &format!("users/{}", id)] {
+            assert!(parse_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn json_converts_to_struct() {
+        let converted = to_struct(json!({ "rows": 3, "tags": ["a"], "ok": true, "note": null }));
+
+        assert_eq!(converted.fields["rows"].kind, Some(Kind::NumberValue(3.0)));
+        assert_eq!(converted.fields["ok"].kind, Some(Kind::BoolValue(true)));
+        assert_eq!(converted.fields["note"].kind, Some(Kind::NullValue(0)));
+        let Some(Kind::ListValue(tags)) = &converted.fields["tags"].kind else {
+            panic!("tags is a list");
+        };
+        assert_eq!(tags.values[0].kind, Some(Kind::StringValue("a".to_string())));
+
+        let wrapped = to_struct(json!(42));
+        assert_eq!(wrapped.fields["value"].kind, Some(Kind::NumberValue(42.0)));
+    }
+}
@@ -0,0 +1,141 @@
+//! What a server serves, as its reflection service describes it, and calls
+//! to methods known only by path.
+
+use bytes::{Buf, BufMut, Bytes};
+use prost::Message;
+use prost_types::FileDescriptorProto;
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::metadata::MetadataValue;
+use tonic::transport::Channel;
+use tonic::{Code, Request, Status};
+use tonic_reflection::pb::server_reflection_client::ServerReflectionClient;
+use tonic_reflection::pb::server_reflection_request::MessageRequest;
+use tonic_reflection::pb::server_reflection_response::MessageResponse;
+use tonic_reflection::pb::ServerReflectionRequest;
+
+/// An RPC of a discovered service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Method {
+    /// `/package.Service/Method`
+    pub path: String,
+    pub client_streaming: bool,
+    pub server_streaming: bool,
+}
+
+/// The services the server lists, with their methods.
+pub async fn discover(channel: Channel) -> Result<Vec<(String, Vec<Method>)>, Status> {
+    let mut client = ServerReflectionClient::new(channel);
+
+    let MessageResponse::ListServicesResponse(list) = reflect(&mut client, MessageRequest::ListServices(String::new())).await? else {
+        return Err(Status::internal("reflection answered ListServices with something else"));
+    };
+
+    let mut services = Vec::new();
+    for service in list.service {
+        let request = MessageRequest::FileContainingSymbol(service.name.clone());
+        let MessageResponse::FileDescriptorResponse(files) = reflect(&mut client, request).await? else {
+            return Err(Status::internal("reflection answered FileContainingSymbol with something else"));
+        };
+        let mut methods = Vec::new();
+        for file in &files.file_descriptor_proto {
+            let file = FileDescriptorProto::decode(file.as_slice())
+                .map_err(|e| Status::internal(format!("undecodable descriptor: {}", e)))?;
+            methods.extend(service_methods(&file, &service.name));
+        }
+        services.push((service.name, methods));
+    }
+    Ok(services)
+}
+
+/// The methods of `service` (`package.Service`) if `file` declares it.
+pub fn service_methods(file: &FileDescriptorProto, service: &str) -> Vec<Method> {
+    file.service
+        .iter()
+        .filter(|candidate| match file.package() {
+            "" => candidate.name() == service,
+            package => format!("{}.{}", package, candidate.name()) == service,
+        })
+        .flat_map(|candidate| &candidate.method)
+        .map(|method| Method {
+            path: format!("/{}/{}", service, method.name()),
+            client_streaming: method.client_streaming(),
+            server_streaming: method.server_streaming(),
+        })
+        .collect()
+}
+
+async fn reflect(client: &mut ServerReflectionClient<Channel>, request: MessageRequest) -> Result<MessageResponse, Status> {
+    let request = ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(request),
+    };
+    let mut responses = client.server_reflection_info(tokio_stream::once(request)).await?.into_inner();
+    let response = responses
+        .message()
+        .await?
+        .ok_or_else(|| Status::internal("reflection closed the stream without answering"))?;
+
+    match response.message_response {
+        Some(MessageResponse::ErrorResponse(error)) => Err(Status::new(Code::from(error.error_code), error.error_message)),
+        Some(response) => Ok(response),
+        None => Err(Status::internal("empty reflection response")),
+    }
+}
+
+/// Calls `path` with an empty message, which every proto3 request type
+/// decodes from, and an optional `authorization` value. For checks that
+/// only look at the status, such as authentication happening before the
+/// handler.
+pub async fn call_empty(channel: Channel, path: &str, authorization: Option<&str>) -> Result<(), Status> {
+    let path: PathAndQuery = path.parse().map_err(|_| Status::invalid_argument(format!("invalid method path {}", path)))?;
+    let mut request = Request::new(Bytes::new());
+    if let Some(authorization) = authorization {
+        let value: MetadataValue<_> = authorization
+            .parse()
+            .map_err(|_| Status::invalid_argument("invalid authorization value"))?;
+        request.metadata_mut().insert("authorization", value);
+    }
+
+    let mut grpc = tonic::client::Grpc::new(channel);
+    grpc.ready().await.map_err(|e| Status::unavailable(e.to_string()))?;
+    grpc.unary(request, path, RawCodec).await.map(|_| ())
+}
+
+/// Passes message bytes through as they are.
+#[derive(Debug, Clone, Copy, Default)]
+struct RawCodec;
+
+impl Codec for RawCodec {
+    type Encode = Bytes;
+    type Decode = Bytes;
+    type Encoder = RawCodec;
+    type Decoder = RawCodec;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        RawCodec
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        RawCodec
+    }
+}
+
+impl Encoder for RawCodec {
+    type Item = Bytes;
+    type Error = Status;
+
+    fn encode(&mut self, item: Bytes, dst: &mut EncodeBuf<'_>) -> Result<(), Status> {
+        dst.put(item);
+        Ok(())
+    }
+}
+
+impl Decoder for RawCodec {
+    type Item = Bytes;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Bytes>, Status> {
+        Ok(Some(src.copy_to_bytes(src.remaining())))
+    }
+}
@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::errors::AppResult;
+
+/// Local filesystem storage for uploaded file contents, keyed by file id.
+#[derive(Debug, Clone)]
+pub struct Storage {
+    root: PathBuf,
+}
+
+impl Storage {
+    pub async fn new(root: impl Into<PathBuf>) -> AppResult<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root).await?;
+        Ok(Self { root })
+    }
+
+    /// Starts writing a file; it only becomes visible once committed.
+    pub async fn create(&self, id: Uuid) -> AppResult<PendingFile> {
+        let temp_path = self.root.join(format!("{}.part", id));
+        let file = File::create(&temp_path).await?;
+
+        Ok(PendingFile {
+            file,
+            temp_path,
+            path: self.path(id),
+            committed: false,
+        })
+    }
+
+    pub async fn open(&self, id: Uuid) -> AppResult<File> {
+        Ok(File::open(self.path(id)).await?)
+    }
+
+    fn path(&self, id: Uuid) -> PathBuf {
+        self.root.join(id.to_string())
+    }
+}
+
+/// A file being written. Dropping it without [`PendingFile::commit`] removes
+/// the partial content, so aborted uploads leave nothing behind.
+pub struct PendingFile {
+    file: File,
+    temp_path: PathBuf,
+    path: PathBuf,
+    committed: bool,
+}
+
+impl PendingFile {
+    pub async fn write(&mut self, chunk: &[u8]) -> AppResult<()> {
+        self.file.write_all(chunk).await?;
+        Ok(())
+    }
+
+    pub async fn commit(mut self) -> AppResult<()> {
+        self.file.sync_all().await?;
+        fs::rename(&self.temp_path, &self.path).await?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for PendingFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.temp_path);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Accept loop for the gRPC server with per-connection lifetime control.
+//!
+//! tonic's built-in `serve` can't age out individual connections, which
+//! behind an L4 load balancer means long-lived clients pin themselves to one
+//! replica forever. Serving connections here lets us send a graceful GOAWAY
+//! once a connection reaches `max_connection_age`, after which clients
+//! reconnect (and get rebalanced) without failing in-flight calls.
+//!
+//! Every address in `server.listeners` gets its own loop, with TLS
+//! terminated here when the listener has a certificate. Loops stop once
+//! [`Draining::closed`], sending every connection a GOAWAY and waiting up to
+//! `server.drain_timeout_secs` for their calls to finish.
+
+use anyhow::Context;
+use config::ConfigError;
+use futures_util::future;
+use hyper::body::HttpBody;
+use hyper::server::conn::Http;
+use hyper::Body;
+use platform_core::lifecycle::Draining;
+use rand::Rng;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::error::Error as StdError;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
+use tonic::codegen::http::{Request, Response};
+use tonic::transport::server::Connected;
+use tower::{Service, ServiceBuilder};
+
+use crate::config::{ListenerRole, ServerSettings, TlsSettings};
+
+/// Fraction of `max_connection_age` applied as random jitter, so connections
+/// opened together don't all reconnect at once.
+const MAX_CONNECTION_AGE_JITTER: f64 = 0.1;
+
+/// Connections queued per listener before the kernel refuses more.
+const BACKLOG: i32 = 1024;
+
+/// A bound socket, ready to be served.
+pub struct Listener {
+    pub role: ListenerRole,
+    pub socket: TcpListener,
+    pub tls: Option<rustls::ServerConfig>,
+}
+
+/// Binds every listener, so a bad address, certificate or key stops the
+/// server before it serves anything. Must be called within the runtime.
+pub fn bind(settings: &ServerSettings) -> anyhow::Result<Vec<Listener>> {
+    let listeners = settings.listeners();
+    if !listeners.iter().any(|listener| listener.role == ListenerRole::Public) {
+        return Err(ConfigError::Message("server.listeners needs a public listener".to_string()).into());
+    }
+
+    listeners
+        .iter()
+        .map(|listener| {
+            let address: SocketAddr = listener
+                .address
+                .parse()
+                .with_context(|| format!("invalid listener address {}", listener.address))?;
+            let tls = listener.tls.as_ref().map(tls_config).transpose()?;
+            let socket = bind_socket(address).with_context(|| format!("binding {}", address))?;
+
+            Ok(Listener {
+                role: listener.role,
+                socket,
+                tls,
+            })
+        })
+        .collect()
+}
+
+fn bind_socket(address: SocketAddr) -> std::io::Result<TcpListener> {
+    let socket = socket2::Socket::new(socket2::Domain::for_address(address), socket2::Type::STREAM, None)?;
+    // Otherwise `[::]` also takes the port for IPv4, and a separate IPv4
+    // listener on it fails to bind
+    if address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(BACKLOG)?;
+
+    TcpListener::from_std(socket.into())
+}
+
+/// Loads a listener's certificate chain and key. Only HTTP/2 is offered,
+/// as gRPC clients require.
+pub fn tls_config(settings: &TlsSettings) -> anyhow::Result<rustls::ServerConfig> {
+    let certs = CertificateDer::pem_file_iter(&settings.cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("reading TLS certificates from {}", settings.cert_path))?;
+    let key = PrivateKeyDer::from_pem_file(&settings.key_path)
+        .with_context(|| format!("reading TLS key from {}", settings.key_path))?;
+
+    let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .with_context(|| format!("loading TLS certificate {}", settings.cert_path))?;
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    Ok(config)
+}
+
+/// Accepts connections until `draining` closes the listener, then returns
+/// once they finish their calls or `server.drain_timeout_secs` passes.
+pub async fn serve<S, B>(listener: Listener, service: S, settings: &ServerSettings, draining: Draining) -> anyhow::Result<()>
+where
+    S: Service<Request<Body>, Response = Response<B>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    let tls = listener.tls.map(|config| TlsAcceptor::from(Arc::new(config)));
+
+    let mut http = Http::new();
+    http.http2_only(true)
+        .http2_keep_alive_interval(secs(settings.http2_keepalive_interval_secs))
+        .http2_keep_alive_timeout(Duration::from_secs(settings.keepalive_timeout_secs));
+
+    let mut connections = JoinSet::new();
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.socket.accept() => accepted,
+            // Reaps finished connections so the set doesn't grow
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            _ = draining.closed() => break,
+        };
+        let (stream, _) = match accepted {
+            Ok(connection) => connection,
+            Err(e) => {
+                tracing::warn!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+
+        if let Err(e) = configure_socket(&stream, settings) {
+            tracing::warn!("Failed to configure connection: {}", e);
+        }
+
+        // Lets services read the peer address through `Request::remote_addr`
+        let connect_info = stream.connect_info();
+        let service = ServiceBuilder::new()
+            .map_request(move |mut req: Request<Body>| {
+                req.extensions_mut().insert(connect_info.clone());
+                req
+            })
+            .service(service.clone());
+
+        let http = http.clone();
+        let tls = tls.clone();
+        let max_age = secs(settings.max_connection_age_secs).map(jittered);
+        let grace = Duration::from_secs(settings.max_connection_age_grace_secs);
+        let draining = draining.clone();
+
+        // The handshake happens here rather than in the accept loop so slow
+        // clients can't hold up others
+        connections.spawn(async move {
+            match tls {
+                Some(tls) => match tls.accept(stream).await {
+                    Ok(stream) => serve_connection(&http, stream, service, max_age, grace, draining).await,
+                    Err(e) => tracing::debug!("TLS handshake failed: {}", e),
+                },
+                None => serve_connection(&http, stream, service, max_age, grace, draining).await,
+            }
+        });
+    }
+
+    // Stop accepting; clients see refused connections rather than a
+    // backlog nobody serves
+    drop(listener.socket);
+    let drain_timeout = Duration::from_secs(settings.drain_timeout_secs);
+    let drained = tokio::time::timeout(drain_timeout, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        tracing::warn!("Dropping {} connections still open after the drain timeout", connections.len());
+    }
+    Ok(())
+}
+
+async fn serve_connection<I, S, B>(
+    http: &Http,
+    io: I,
+    service: S,
+    max_age: Option<Duration>,
+    grace: Duration,
+    draining: Draining,
+) where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Service<Request<Body>, Response = Response<B>> + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    let connection = http.serve_connection(io, service);
+    tokio::pin!(connection);
+
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        _ = sleep_for(max_age) => {
+            // Stop accepting new streams; in-flight calls get `grace` to finish
+            connection.as_mut().graceful_shutdown();
+            match tokio::time::timeout(grace, connection.as_mut()).await {
+                Ok(result) => result,
+                Err(_) => {
+                    tracing::debug!("Closing connection after max_connection_age grace period");
+                    Ok(())
+                }
+            }
+        }
+        _ = draining.closed() => {
+            // The same, with `serve` bounding the wait by the drain timeout
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        }
+    };
+
+    if let Err(e) = result {
+        tracing::debug!("Connection closed with error: {}", e);
+    }
+}
+
+/// Sleeps for `duration`, or forever without one.
+async fn sleep_for(duration: Option<Duration>) {
+    match duration {
+        Some(duration) => tokio::time::sleep(duration).await,
+        None => future::pending().await,
+    }
+}
+
+fn configure_socket(stream: &TcpStream, settings: &ServerSettings) -> std::io::Result<()> {
+    stream.set_nodelay(true)?;
+
+    if let Some(interval) = secs(settings.tcp_keepalive_secs) {
+        let keepalive = socket2::TcpKeepalive::new().with_time(interval);
+        socket2::SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
+    }
+
+    Ok(())
+}
+
+/// Zero disables a setting.
+fn secs(value: u64) -> Option<Duration> {
+    (value > 0).then(|| Duration::from_secs(value))
+}
+
+fn jittered(age: Duration) -> Duration {
+    let jitter = rand::thread_rng().gen_range(-MAX_CONNECTION_AGE_JITTER..=MAX_CONNECTION_AGE_JITTER);
+    age.mul_f64(1.0 + jitter)
+}
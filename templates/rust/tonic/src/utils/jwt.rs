@@ -1,13 +1,94 @@
-use crate::errors::AppResult;
+use crate::config::JwtSettings;
+use crate::errors::{AppError, AppResult};
+use crate::models::role::Grants;
 use crate::models::user::Claims;
 use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
-use uuid::Uuid;
+use config::ConfigError;
+use jsonwebtoken::{decode, decode_header, encode, DecodingKey, EncodingKey, Header, Validation};
+use platform_core::domain::{Email, UserId};
+use std::collections::HashMap;
+
+/// Key id assigned to the legacy single `jwt.secret`.
+pub const DEFAULT_KID: &str = "default";
+
+/// The set of keys tokens are signed and validated with, as in the REST
+/// template.
+///
+/// Exactly one key signs new tokens; every configured key validates, so a
+/// key can be introduced, promoted and later retired without invalidating
+/// sessions signed with its predecessor.
+#[derive(Clone)]
+pub struct JwtKeys {
+    active_kid: String,
+    secrets: HashMap<String, String>,
+}
+
+impl JwtKeys {
+    pub fn from_settings(settings: &JwtSettings) -> Result<Self, ConfigError> {
+        if settings.keys.is_empty() {
+            let secret = settings.secret.clone().ok_or_else(|| {
+                ConfigError::Message("either jwt.secret or jwt.keys must be configured".into())
+            })?;
+            return Ok(Self::single(secret));
+        }
+
+        let secrets: HashMap<String, String> = settings
+            .keys
+            .iter()
+            .map(|key| (key.kid.clone(), key.secret.clone()))
+            .collect();
+
+        let active_kid = settings.active_kid.clone().ok_or_else(|| {
+            ConfigError::Message("jwt.active_kid is required when jwt.keys is set".into())
+        })?;
+
+        if !secrets.contains_key(&active_kid) {
+            return Err(ConfigError::Message(format!(
+                "jwt.active_kid '{}' does not match any configured key",
+                active_kid
+            )));
+        }
+
+        Ok(Self {
+            active_kid,
+            secrets,
+        })
+    }
+
+    pub fn single(secret: String) -> Self {
+        Self {
+            active_kid: DEFAULT_KID.to_string(),
+            secrets: HashMap::from([(DEFAULT_KID.to_string(), secret)]),
+        }
+    }
+
+    pub fn active_kid(&self) -> &str {
+        &self.active_kid
+    }
+
+    /// The secret of the signing key, e.g. to derive other key material.
+    pub fn active_secret(&self) -> &str {
+        &self.secrets[&self.active_kid]
+    }
+
+    fn encoding_key(&self) -> EncodingKey {
+        EncodingKey::from_secret(self.active_secret().as_ref())
+    }
+
+    /// Tokens issued before `kid` headers were introduced are validated
+    /// against the active key.
+    fn decoding_key(&self, kid: Option<&str>) -> Option<DecodingKey> {
+        self.secrets
+            .get(kid.unwrap_or(&self.active_kid))
+            .map(|secret| DecodingKey::from_secret(secret.as_ref()))
+    }
+}
 
 pub fn create_jwt_token(
-    user_id: Uuid,
-    email: &str,
-    secret: &str,
+    user_id: UserId,
+    email: &Email,
+    grants: Grants,
+    keys: &JwtKeys,
     expiry_hours: i64,
 ) -> AppResult<String> {
     let now = Utc::now();
@@ -15,26 +96,147 @@ pub fn create_jwt_token(
     
     let claims = Claims {
         sub: user_id,
-        email: email.to_string(),
+        email: email.clone(),
         exp: expires_at.timestamp() as usize,
         iat: now.timestamp() as usize,
+        grants,
     };
     
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )?;
+    let header = Header {
+        kid: Some(keys.active_kid().to_string()),
+        ..Header::default()
+    };
+    let token = encode(&header, &claims, &keys.encoding_key())?;
     
     Ok(token)
 }
 
-pub fn decode_jwt_token(token: &str, secret: &str) -> AppResult<Claims> {
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    )?;
+pub fn decode_jwt_token(token: &str, keys: &JwtKeys) -> AppResult<Claims> {
+    let header = decode_header(token)?;
+    let decoding_key = keys
+        .decoding_key(header.kid.as_deref())
+        .ok_or(AppError::Unauthorized)?;
+
+    let token_data = decode::<Claims>(token, &decoding_key, &Validation::default())?;
     
     Ok(token_data.claims)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::JwtKeySettings;
+    use proptest::prelude::*;
+    use uuid::Uuid;
+
+    fn email() -> Email {
+        Email::parse("a@example.com").unwrap()
+    }
+
+    fn keys(secret: &str) -> JwtKeys {
+        JwtKeys::single(secret.to_string())
+    }
+
+    proptest! {
+        #[test]
+        fn claims_round_trip(id in any::<u128>(), email in "[a-z]{1,10}@[a-z]{1,10}\\.com", hours in 1i64..10_000) {
+            let (user_id, email) = (UserId::from(Uuid::from_u128(id)), Email::parse(email).unwrap());
+            let token = create_jwt_token(user_id, &email, Grants::default(), &keys("secret"), hours).unwrap();
+
+            let claims = decode_jwt_token(&token, &keys("secret")).unwrap();
+            prop_assert_eq!(claims.sub, user_id);
+            prop_assert_eq!(claims.email, email);
+            prop_assert_eq!(claims.exp - claims.iat, hours as usize * 3600);
+        }
+
+        #[test]
+        fn tampered_tokens_are_rejected(index in any::<prop::sample::Index>(), replacement in "[A-Za-z0-9_-]") {
+            let token = create_jwt_token(Uuid::nil().into(), &email(), Grants::default(), &keys("secret"), 1).unwrap();
+            let position = index.index(token.len());
+            prop_assume!(token[position..].chars().next() != replacement.chars().next());
+
+            let mut tampered = token.clone();
+            tampered.replace_range(position..position + 1, &replacement);
+            prop_assert!(decode_jwt_token(&tampered, &keys("secret")).is_err());
+        }
+
+        #[test]
+        fn arbitrary_tokens_are_rejected(token in any::<String>()) {
+            prop_assert!(decode_jwt_token(&token, &keys("secret")).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_other_secrets() {
+        let token = create_jwt_token(Uuid::nil().into(), &email(), Grants::default(), &keys("secret"), 1).unwrap();
+        assert!(decode_jwt_token(&token, &keys("other")).is_err());
+    }
+
+    #[test]
+    fn grants_round_trip() {
+        let grants = Grants {
+            roles: vec!["support".to_string()],
+            permissions: vec!["users.read".to_string()],
+        };
+        let token = create_jwt_token(Uuid::nil().into(), &email(), grants.clone(), &keys("secret"), 1).unwrap();
+        assert_eq!(decode_jwt_token(&token, &keys("secret")).unwrap().grants, grants);
+    }
+
+    fn rotated(active_kid: &str) -> JwtKeys {
+        let settings = JwtSettings {
+            secret: None,
+            keys: vec![
+                JwtKeySettings { kid: "old".to_string(), secret: "old-secret".to_string() },
+                JwtKeySettings { kid: "new".to_string(), secret: "new-secret".to_string() },
+            ],
+            active_kid: Some(active_kid.to_string()),
+            access_token_expiry: 3600,
+            refresh_token_expiry: 86400,
+        };
+        JwtKeys::from_settings(&settings).unwrap()
+    }
+
+    #[test]
+    fn tokens_of_previous_keys_validate_after_a_rotation() {
+        let before = create_jwt_token(Uuid::nil().into(), &email(), Grants::default(), &rotated("old"), 1).unwrap();
+        let after = create_jwt_token(Uuid::nil().into(), &email(), Grants::default(), &rotated("new"), 1).unwrap();
+        assert_eq!(decode_header(&after).unwrap().kid.as_deref(), Some("new"));
+
+        for token in [&before, &after] {
+            decode_jwt_token(token, &rotated("new")).unwrap();
+        }
+        // Once the old key is retired its tokens are refused
+        assert!(decode_jwt_token(&before, &keys("new-secret")).is_err());
+    }
+
+    #[test]
+    fn tokens_without_a_kid_validate_against_the_active_key() {
+        let header = Header::default();
+        let claims = Claims {
+            sub: Uuid::nil().into(),
+            email: email(),
+            exp: (Utc::now() + Duration::hours(1)).timestamp() as usize,
+            iat: Utc::now().timestamp() as usize,
+            grants: Grants::default(),
+        };
+        let token = encode(&header, &claims, &EncodingKey::from_secret(b"new-secret")).unwrap();
+
+        decode_jwt_token(&token, &rotated("new")).unwrap();
+        assert!(decode_jwt_token(&token, &rotated("old")).is_err());
+    }
+
+    #[test]
+    fn the_active_key_must_be_configured() {
+        let mut settings = JwtSettings {
+            secret: None,
+            keys: vec![JwtKeySettings { kid: "a".to_string(), secret: "s".to_string() }],
+            active_kid: Some("b".to_string()),
+            access_token_expiry: 3600,
+            refresh_token_expiry: 86400,
+        };
+        assert!(JwtKeys::from_settings(&settings).is_err());
+        settings.active_kid = None;
+        assert!(JwtKeys::from_settings(&settings).is_err());
+        settings.keys.clear();
+        assert!(JwtKeys::from_settings(&settings).is_err());
+    }
+}
@@ -1,5 +1,4 @@
 pub mod jwt;
-pub mod hash;
 
-pub use jwt::{create_jwt_token, decode_jwt_token};
-pub use hash::{hash_password, verify_password};
\ No newline at end of file
+pub use jwt::{create_jwt_token, decode_jwt_token, JwtKeys};
+pub use platform_auth::{generate_secret, hash_password, hash_token, verify_password, HashedPassword};
\ No newline at end of file
@@ -0,0 +1,117 @@
+mod common;
+
+use prost::Message;
+use sha2::{Digest, Sha256};
+use tonic::Code;
+use tonic_types::StatusExt;
+use uuid::Uuid;
+
+use common::{assert_status, authorized, capture_traces, in_process_channel, TestApp};
+use tonic_template::errors::ErrorCode;
+use tonic_template::proto::admin::v1::admin_service_client::AdminServiceClient;
+use tonic_template::proto::admin::v1::{
+    GetDescriptorSetRequest, GetLogLevelRequest, GetRuntimeStatsRequest, ResetLogLevelRequest, SetLogLevelRequest,
+};
+
+async fn admin_client(app: &TestApp) -> AdminServiceClient<tonic::transport::Channel> {
+    AdminServiceClient::new(in_process_channel(tonic_template::admin_service(app.state.clone())).await)
+}
+
+fn set(filter: &str, revert_after_secs: Option<i64>) -> SetLogLevelRequest {
+    SetLogLevelRequest {
+        filter: filter.to_string(),
+        revert_after: revert_after_secs.map(|seconds| prost_types::Duration { seconds, nanos: 0 }),
+    }
+}
+
+#[tokio::test]
+async fn admin_rpcs_need_an_admin_token_and_an_admin_listener() {
+    let app = TestApp::spawn().await;
+    let mut admin = admin_client(&app).await;
+
+    assert_status(admin.get_log_level(GetLogLevelRequest::default()).await, Code::Unauthenticated);
+
+    // Users without the admin role are refused
+    let token = app.token_for(Uuid::new_v4());
+    let request = authorized(GetLogLevelRequest::default(), &token);
+    let status = assert_status(admin.get_log_level(request).await, Code::PermissionDenied);
+    assert_eq!(status.get_details_error_info().expect("error info").reason, ErrorCode::AuthRoleMissing.as_str());
+
+    // Public listeners don't serve them at all
+    let mut public = AdminServiceClient::new(app.channel.clone());
+    let request = authorized(GetLogLevelRequest::default(), &token);
+    assert_status(public.get_log_level(request).await, Code::Unimplemented);
+}
+
+#[tokio::test]
+async fn invalid_filters_and_durations_are_rejected() {
+    let app = TestApp::spawn().await;
+    let token = app.admin_token_for(Uuid::new_v4());
+    let mut admin = admin_client(&app).await;
+
+    for request in [set("sqlx=loud", None), set("debug", Some(0)), set("debug", Some(-5)), set("debug", Some(86401))] {
+        assert_status(admin.set_log_level(authorized(request, &token)).await, Code::InvalidArgument);
+    }
+
+    let status = admin.get_log_level(authorized(GetLogLevelRequest::default(), &token)).await.unwrap();
+    assert_eq!(status.get_ref().filter, "info");
+    assert_eq!(status.get_ref().revert_time, None);
+}
+
+#[tokio::test]
+async fn log_level_changes_are_audited() {
+    let app = TestApp::spawn().await;
+    let user_id = Uuid::new_v4();
+    let token = app.admin_token_for(user_id);
+    let mut admin = admin_client(&app).await;
+    let (traces, _guard) = capture_traces();
+
+    let status = admin.set_log_level(authorized(set("info,sqlx=debug", Some(600)), &token)).await.unwrap();
+    assert_eq!(status.get_ref().filter, "info,sqlx=debug");
+    assert_eq!(status.get_ref().default_filter, "info");
+    assert!(status.get_ref().revert_time.is_some());
+
+    let status = admin.reset_log_level(authorized(ResetLogLevelRequest::default(), &token)).await.unwrap();
+    assert_eq!(status.get_ref().filter, "info");
+
+    let changes = traces.events("audit");
+    assert_eq!(changes.len(), 2, "{:?}", changes);
+    traces.assert_event("audit", "user_id", &user_id.to_string());
+    traces.assert_event("audit", "filter", "info,sqlx=debug");
+    traces.assert_event("audit", "previous", "info,sqlx=debug");
+}
+
+#[tokio::test]
+async fn runtime_stats_describe_the_serving_runtime() {
+    let app = TestApp::spawn().await;
+    let token = app.admin_token_for(Uuid::new_v4());
+    let mut admin = admin_client(&app).await;
+
+    assert_status(admin.get_runtime_stats(GetRuntimeStatsRequest::default()).await, Code::Unauthenticated);
+
+    let stats = admin.get_runtime_stats(authorized(GetRuntimeStatsRequest::default(), &token)).await.unwrap();
+    let stats = stats.get_ref();
+    assert_eq!(stats.workers, 1);
+    assert!(stats.alive_tasks >= 1, "{:?}", stats);
+    assert_eq!(stats.worker_busy.len(), 1);
+    assert!(!stats.console);
+}
+
+#[tokio::test]
+async fn descriptor_set_describes_every_proto() {
+    let app = TestApp::spawn().await;
+    let token = app.admin_token_for(Uuid::new_v4());
+    let mut admin = admin_client(&app).await;
+
+    assert_status(admin.get_descriptor_set(GetDescriptorSetRequest::default()).await, Code::Unauthenticated);
+
+    let descriptors = admin.get_descriptor_set(authorized(GetDescriptorSetRequest::default(), &token)).await.unwrap();
+    let descriptors = descriptors.into_inner();
+    assert_eq!(descriptors.sha256, hex::encode(Sha256::digest(&descriptors.file_descriptor_set)));
+    let set = prost_types::FileDescriptorSet::decode(descriptors.file_descriptor_set.as_slice()).unwrap();
+    let files: Vec<_> = set.file.iter().map(|file| file.name()).collect();
+    for proto in ["user.proto", "health.proto", "file.proto", "operations.proto", "admin.proto"] {
+        assert!(files.contains(&proto), "{} missing from {:?}", proto, files);
+    }
+    assert!(files.contains(&"google/protobuf/timestamp.proto"), "imports missing from {:?}", files);
+}
@@ -0,0 +1,231 @@
+//! Security invariants of authentication, asserted end to end.
+//!
+//! Refresh tokens here are stateless JWTs and logins aren't throttled, so
+//! single-use refresh tokens and account lockout are only covered by the
+//! REST template's suite; revocation by password reset is covered in
+//! `password_reset.rs`.
+
+mod common;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::json;
+use tonic::{Code, Request, Status};
+use uuid::Uuid;
+
+use common::{TestApp, TEST_JWT_SECRET};
+use tonic_template::factories::UserFactory;
+use tonic_template::interceptors::{MethodAuthMatrix, MethodPolicy};
+use tonic_template::models::{Claims, Grants};
+use tonic_template::proto::file::v1::file_service_client::FileServiceClient;
+use tonic_template::proto::file::v1::{DownloadFileRequest, UploadFileRequest};
+use tonic_template::proto::user::v1::user_service_client::UserServiceClient;
+use tonic_template::proto::user::v1::*;
+use tonic_template::utils::{create_jwt_token, JwtKeys};
+
+const PUBLIC_METHODS: &[&str] = &[
+    "/user.v1.UserService/Login",
+    "/user.v1.UserService/Register",
+    "/user.v1.UserService/RefreshToken",
+    "/user.v1.UserService/ValidateToken",
+    "/user.v1.UserService/RequestPasswordReset",
+    "/user.v1.UserService/ResetPassword",
+    "/health.v1.HealthService/Check",
+    "/health.v1.HealthService/Watch",
+    "/grpc.health.v1.Health/Check",
+    "/grpc.health.v1.Health/Watch",
+];
+
+const PROTECTED_METHODS: &[&str] = &[
+    "/user.v1.UserService/CreateUser",
+    "/user.v1.UserService/GetUser",
+    "/user.v1.UserService/UpdateUser",
+    "/user.v1.UserService/DeleteUser",
+    "/user.v1.UserService/ListUsers",
+    "/user.v1.UserService/ListUsersStream",
+    "/file.v1.FileService/UploadFile",
+    "/file.v1.FileService/DownloadFile",
+];
+
+fn request<T>(message: T, token: Option<&str>) -> Request<T> {
+    match token {
+        Some(token) => common::authorized(message, token),
+        None => Request::new(message),
+    }
+}
+
+/// Calls every protected RPC with `token` and returns the status codes.
+async fn call_protected(app: &TestApp, token: Option<&str>) -> Vec<(&'static str, Code)> {
+    let mut users = UserServiceClient::new(app.channel.clone());
+    let mut files = FileServiceClient::new(app.channel.clone());
+    let id = Uuid::from_u128(1).to_string();
+
+    fn code<T>(result: Result<T, Status>) -> Code {
+        result.err().map(|status| status.code()).unwrap_or(Code::Ok)
+    }
+
+    vec![
+        ("CreateUser", code(users.create_user(request(CreateUserRequest::default(), token)).await)),
+        ("GetUser", code(users.get_user(request(GetUserRequest { id: id.clone() }, token)).await)),
+        (
+            "UpdateUser",
+            code(
+                users
+                    .update_user(request(UpdateUserRequest { id: id.clone(), ..Default::default() }, token))
+                    .await,
+            ),
+        ),
+        ("DeleteUser", code(users.delete_user(request(DeleteUserRequest { id: id.clone() }, token)).await)),
+        ("ListUsers", code(users.list_users(request(ListUsersRequest::default(), token)).await)),
+        (
+            "ListUsersStream",
+            code(users.list_users_stream(request(ListUsersStreamRequest::default(), token)).await),
+        ),
+        (
+            "UploadFile",
+            code(
+                files
+                    .upload_file(request(tokio_stream::iter(Vec::<UploadFileRequest>::new()), token))
+                    .await,
+            ),
+        ),
+        (
+            "DownloadFile",
+            code(files.download_file(request(DownloadFileRequest { file_id: id }, token)).await),
+        ),
+    ]
+}
+
+#[track_caller]
+fn assert_all_unauthenticated(codes: Vec<(&str, Code)>) {
+    for (method, code) in codes {
+        assert_eq!(code, Code::Unauthenticated, "{}", method);
+    }
+}
+
+fn token_with_claims(claims: &Claims) -> String {
+    encode(&Header::default(), claims, &EncodingKey::from_secret(TEST_JWT_SECRET.as_ref())).unwrap()
+}
+
+fn replace_segment(token: &str, index: usize, segment: &str) -> String {
+    let mut segments: Vec<&str> = token.split('.').collect();
+    segments[index] = segment;
+    segments.join(".")
+}
+
+#[tokio::test]
+async fn only_auth_and_health_methods_are_public() {
+    let app = TestApp::spawn().await;
+    let matrix = MethodAuthMatrix::from_settings(&app.state.settings.auth);
+
+    for method in PUBLIC_METHODS {
+        assert_eq!(matrix.policy(method), MethodPolicy::Public, "{}", method);
+    }
+    for method in PROTECTED_METHODS {
+        assert_eq!(matrix.policy(method), MethodPolicy::Authenticated, "{}", method);
+    }
+}
+
+#[tokio::test]
+async fn every_protected_method_requires_a_token() {
+    let app = TestApp::spawn().await;
+
+    assert_all_unauthenticated(call_protected(&app, None).await);
+}
+
+#[tokio::test]
+async fn every_protected_method_rejects_a_malformed_token() {
+    let app = TestApp::spawn().await;
+
+    assert_all_unauthenticated(call_protected(&app, Some("not-a-jwt")).await);
+}
+
+#[tokio::test]
+async fn valid_token_passes_authentication() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::from_u128(1));
+
+    for (method, code) in call_protected(&app, Some(&token)).await {
+        assert_ne!(code, Code::Unauthenticated, "{}", method);
+    }
+}
+
+#[tokio::test]
+async fn expired_token_is_rejected() {
+    let app = TestApp::spawn().await;
+    let now = chrono::Utc::now().timestamp() as usize;
+    // Past the one-minute leeway
+    let token = token_with_claims(&Claims {
+        sub: Uuid::from_u128(1).into(),
+        email: "test@example.com".parse().unwrap(),
+        exp: now - 120,
+        iat: now - 3720,
+    });
+
+    assert_all_unauthenticated(call_protected(&app, Some(&token)).await);
+}
+
+#[tokio::test]
+async fn tampered_signature_is_rejected() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::from_u128(1));
+
+    let mut signature = URL_SAFE_NO_PAD.decode(token.split('.').nth(2).unwrap()).unwrap();
+    signature[0] ^= 0x01;
+    let tampered = replace_segment(&token, 2, &URL_SAFE_NO_PAD.encode(signature));
+
+    assert_all_unauthenticated(call_protected(&app, Some(&tampered)).await);
+}
+
+#[tokio::test]
+async fn tampered_claims_are_rejected() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::from_u128(1));
+
+    let now = chrono::Utc::now().timestamp() as usize;
+    let escalated = Claims {
+        sub: Uuid::from_u128(2).into(),
+        email: "test@example.com".parse().unwrap(),
+        exp: now + 3600,
+        iat: now,
+    };
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&escalated).unwrap());
+    let tampered = replace_segment(&token, 1, &payload);
+
+    assert_all_unauthenticated(call_protected(&app, Some(&tampered)).await);
+}
+
+#[tokio::test]
+async fn unsigned_token_is_rejected() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::from_u128(1));
+
+    let header = URL_SAFE_NO_PAD.encode(json!({ "alg": "none", "typ": "JWT" }).to_string());
+    let unsigned = replace_segment(&replace_segment(&token, 0, &header), 2, "");
+
+    assert_all_unauthenticated(call_protected(&app, Some(&unsigned)).await);
+}
+
+#[tokio::test]
+async fn token_signed_with_another_secret_is_rejected() {
+    let app = TestApp::spawn().await;
+    let email = "test@example.com".parse().unwrap();
+    let keys = JwtKeys::single("another-secret".to_string());
+    let token = create_jwt_token(Uuid::from_u128(1).into(), &email, Grants::default(), &keys, 1).unwrap();
+
+    assert_all_unauthenticated(call_protected(&app, Some(&token)).await);
+}
+
+#[test]
+fn password_hash_is_never_serialized() {
+    let user = UserFactory::build().into_user();
+
+    let json = serde_json::to_string(&user).unwrap();
+    let proto = format!("{:?}", user.to_proto());
+
+    for body in [json, proto] {
+        assert!(!body.contains("password"), "{}", body);
+        assert!(!body.contains(user.password_hash.as_str()), "{}", body);
+    }
+}
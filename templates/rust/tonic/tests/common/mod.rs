@@ -0,0 +1,320 @@
+//! Test kit for exercising services and layers end to end without binding
+//! ports: the full gRPC stack is served over an in-memory duplex stream.
+//!
+//! The database pool is lazy and points nowhere, so tests should stick to
+//! code paths that don't query it (auth, validation, layers) unless
+//! `TEST_DATABASE_URL` is set.
+
+#![allow(dead_code)]
+
+use config::builder::{ConfigBuilder, DefaultState};
+use hyper::body::{Bytes, HttpBody};
+use hyper::server::conn::Http;
+use platform_auth::workload::WorkloadVerifier;
+use platform_core::clock::SystemClock;
+use platform_core::domain::{Email, UserId};
+use platform_core::lifecycle::Draining;
+use platform_core::rate_limit::MemoryBackend;
+use platform_observability::log_level::LogLevel;
+use sqlx::postgres::PgPoolOptions;
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tonic::codegen::http::{Request as HttpRequest, Response as HttpResponse};
+use tonic::metadata::{Ascii, MetadataMap, MetadataValue};
+use tonic::transport::{Channel, Endpoint, Uri};
+use tonic::{Code, Request, Status};
+use tonic_types::StatusExt;
+use tower::Service;
+use tracing::field::{Field, Visit};
+use tracing::span::Attributes;
+use tracing::{Event, Id, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::prelude::*;
+use uuid::Uuid;
+
+use tonic_template::config::Settings;
+use tonic_template::factories::UserFactory;
+use tonic_template::mail::ConsoleMailer;
+use tonic_template::models::role::ROLE_ADMIN;
+use tonic_template::models::{Grants, User};
+use tonic_template::operations::OperationStore;
+use tonic_template::storage::Storage;
+use tonic_template::utils::{create_jwt_token, JwtKeys};
+use tonic_template::AppState;
+
+pub const TEST_JWT_SECRET: &str = "test-secret";
+
+/// The application served in-process, with a client channel connected to it.
+pub struct TestApp {
+    pub channel: Channel,
+    pub state: Arc<AppState>,
+    /// Emails the app sent.
+    pub mailer: Arc<ConsoleMailer>,
+}
+
+impl TestApp {
+    pub async fn spawn() -> Self {
+        Self::spawn_with(|config| config).await
+    }
+
+    /// Spawns the app with extra configuration, e.g.
+    /// `config.set_override("logging.payload_sample_rate", 1.0)`.
+    pub async fn spawn_with(
+        configure: impl FnOnce(ConfigBuilder<DefaultState>) -> ConfigBuilder<DefaultState>,
+    ) -> Self {
+        let storage_path = std::env::temp_dir().join(format!("tonic-template-test-{}", Uuid::new_v4()));
+        let database_url =
+            std::env::var("TEST_DATABASE_URL").unwrap_or_else(|_| "postgres://127.0.0.1:1/test".to_string());
+
+        let config = Settings::defaults()
+            .and_then(|config| config.set_override("jwt.secret", TEST_JWT_SECRET))
+            .and_then(|config| config.set_override("database.url", database_url.as_str()))
+            .and_then(|config| config.set_override("storage.path", storage_path.to_string_lossy().as_ref()))
+            .expect("valid test configuration");
+        let settings: Settings = configure(config)
+            .build()
+            .and_then(|config| config.try_deserialize())
+            .expect("valid test settings");
+
+        let db = PgPoolOptions::new()
+            .max_connections(settings.database.max_connections)
+            .acquire_timeout(Duration::from_secs(1))
+            .connect_lazy(&settings.database.url)
+            .expect("valid database url");
+        let storage = Storage::new(&settings.storage.path).await.expect("storage directory");
+
+        let operations = OperationStore::new(db.clone(), &settings.operations);
+        let log_level = Arc::new(LogLevel::new(settings.log_level.clone(), Arc::new(SystemClock)));
+        let workload_verifier = WorkloadVerifier::from_settings(&settings.workload_identity, Arc::new(SystemClock))
+            .expect("valid workload identity settings")
+            .map(Arc::new);
+        let mailer = Arc::new(ConsoleMailer::default());
+        let jwt_keys = JwtKeys::from_settings(&settings.jwt).expect("valid jwt settings");
+        let state = Arc::new(AppState {
+            db,
+            settings,
+            jwt_keys,
+            storage,
+            operations,
+            log_level,
+            workload_verifier,
+            mailer: mailer.clone(),
+            rate_limiter: Arc::new(MemoryBackend::default()),
+            draining: Draining::default(),
+        });
+        let channel = in_process_channel(tonic_template::grpc_service(state.clone())).await;
+
+        Self { channel, state, mailer }
+    }
+
+    /// Inserts a factory-built user; needs `TEST_DATABASE_URL`.
+    pub async fn insert_user(&self, factory: UserFactory) -> User {
+        factory.insert(&self.state.db).await.expect("insert user")
+    }
+
+    /// A valid access token for `user`.
+    pub fn token_for_user(&self, user: &User) -> String {
+        create_jwt_token(user.id, &user.email, Grants::default(), &self.state.jwt_keys, 1).expect("token")
+    }
+
+    /// A valid access token for a (not necessarily existing) user.
+    pub fn token_for(&self, user_id: impl Into<UserId>) -> String {
+        self.token_with_grants(user_id, Grants::default())
+    }
+
+    /// A valid access token for a (not necessarily existing) admin.
+    pub fn admin_token_for(&self, user_id: impl Into<UserId>) -> String {
+        let grants = Grants {
+            roles: vec![ROLE_ADMIN.to_string()],
+            permissions: vec![],
+        };
+        self.token_with_grants(user_id, grants)
+    }
+
+    /// A valid access token for a (not necessarily existing) user carrying
+    /// `grants`.
+    pub fn token_with_grants(&self, user_id: impl Into<UserId>, grants: Grants) -> String {
+        let email = Email::parse("test@example.com").unwrap();
+        create_jwt_token(user_id.into(), &email, grants, &self.state.jwt_keys, 1).expect("token")
+    }
+}
+
+/// Serves `service` over an in-memory duplex stream and returns a channel
+/// connected to it. Works for any tower service, so single layers can be
+/// tested in isolation as well as the full stack.
+pub async fn in_process_channel<S, B>(service: S) -> Channel
+where
+    S: Service<HttpRequest<hyper::Body>, Response = HttpResponse<B>> + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    let (client, server) = tokio::io::duplex(1024 * 1024);
+
+    tokio::spawn(async move {
+        if let Err(e) = Http::new().http2_only(true).serve_connection(server, service).await {
+            eprintln!("in-process server error: {}", e);
+        }
+    });
+
+    let mut client = Some(client);
+    Endpoint::from_static("http://in-process.test")
+        .connect_with_connector(tower::service_fn(move |_: Uri| {
+            let client = client.take();
+            async move {
+                client.ok_or_else(|| std::io::Error::other("in-process channel can only connect once"))
+            }
+        }))
+        .await
+        .expect("in-process channel")
+}
+
+/// Wraps `message` in a request carrying a bearer token.
+pub fn authorized<T>(message: T, token: &str) -> Request<T> {
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert("authorization", format!("Bearer {}", token).parse().expect("ascii token"));
+    request
+}
+
+/// Client-side interceptor adding a bearer token to every call, for use
+/// with `XServiceClient::with_interceptor`.
+// The closure signature is dictated by tonic's interceptor API
+#[allow(clippy::result_large_err)]
+pub fn bearer(token: &str) -> impl FnMut(Request<()>) -> Result<Request<()>, Status> + Clone {
+    let value: MetadataValue<Ascii> = format!("Bearer {}", token).parse().expect("ascii token");
+    move |mut request: Request<()>| {
+        request.metadata_mut().insert("authorization", value.clone());
+        Ok(request)
+    }
+}
+
+/// Asserts the call failed with `code` and returns the status for further
+/// checks.
+#[track_caller]
+pub fn assert_status<T: Debug>(result: Result<T, Status>, code: Code) -> Status {
+    match result {
+        Ok(response) => panic!("expected status {:?}, got response {:?}", code, response),
+        Err(status) => {
+            assert_eq!(status.code(), code, "unexpected status: {:?}", status);
+            status
+        }
+    }
+}
+
+/// Code, message and error details of a status, for snapshotting the wire
+/// format. Retry delays are reduced to whether one was sent.
+pub fn status_snapshot(status: &Status) -> serde_json::Value {
+    let error_info = status.get_details_error_info().map(|info| {
+        serde_json::json!({
+            "reason": info.reason,
+            "domain": info.domain,
+        })
+    });
+
+    serde_json::json!({
+        "code": format!("{:?}", status.code()),
+        "message": status.message(),
+        "error_info": error_info,
+        "retry_info": status.get_details_retry_info().is_some(),
+    })
+}
+
+#[track_caller]
+pub fn assert_metadata(metadata: &MetadataMap, key: &str, expected: &str) {
+    let value = metadata
+        .get(key)
+        .unwrap_or_else(|| panic!("missing metadata '{}' in {:?}", key, metadata));
+    assert_eq!(value.to_str().expect("ascii metadata"), expected, "metadata '{}'", key);
+}
+
+#[derive(Debug, Clone)]
+pub struct CapturedEvent {
+    pub target: String,
+    pub level: Level,
+    pub fields: HashMap<String, String>,
+}
+
+/// Spans and events recorded while a [`capture_traces`] guard is alive.
+///
+/// Capture is per thread, which covers tasks spawned by `#[tokio::test]`'s
+/// default current-thread runtime.
+#[derive(Clone, Default)]
+pub struct CapturedTraces {
+    spans: Arc<Mutex<Vec<String>>>,
+    events: Arc<Mutex<Vec<CapturedEvent>>>,
+}
+
+impl CapturedTraces {
+    pub fn span_names(&self) -> Vec<String> {
+        self.spans.lock().unwrap().clone()
+    }
+
+    pub fn events(&self, target: &str) -> Vec<CapturedEvent> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| event.target == target)
+            .cloned()
+            .collect()
+    }
+
+    /// Asserts that an event for `target` recorded `field` as `expected`.
+    #[track_caller]
+    pub fn assert_event(&self, target: &str, field: &str, expected: &str) {
+        let events = self.events(target);
+        assert!(
+            events
+                .iter()
+                .any(|event| event.fields.get(field).map(String::as_str) == Some(expected)),
+            "no '{}' event with {} = {:?}; captured: {:?}",
+            target,
+            field,
+            expected,
+            events
+        );
+    }
+}
+
+impl<S: Subscriber> Layer<S> for CapturedTraces {
+    fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+        self.spans.lock().unwrap().push(attrs.metadata().name().to_string());
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        self.events.lock().unwrap().push(CapturedEvent {
+            target: event.metadata().target().to_string(),
+            level: *event.metadata().level(),
+            fields: visitor.0,
+        });
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor(HashMap<String, String>);
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+/// Records spans and events on the current thread until the guard drops.
+pub fn capture_traces() -> (CapturedTraces, tracing::subscriber::DefaultGuard) {
+    let traces = CapturedTraces::default();
+    let subscriber = tracing_subscriber::registry().with(traces.clone());
+    (traces, tracing::subscriber::set_default(subscriber))
+}
@@ -0,0 +1,182 @@
+mod common;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Response, Server};
+use platform_core::lifecycle::Lifecycle;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::TestApp;
+use tonic_template::config::{DiscoveryProvider, Settings};
+use tonic_template::discovery::{self, ConsulDiscovery, Discovery, Instance, StaticDiscovery};
+use tonic_template::proto::health::v1::health_service_client::HealthServiceClient;
+use tonic_template::proto::health::v1::HealthCheckRequest;
+use tonic_template::transport;
+
+type Requests = Arc<Mutex<Vec<String>>>;
+
+/// A Consul agent that accepts everything, records `METHOD path?query
+/// [token]` and reports `10.0.0.9:50051` as the only healthy instance.
+async fn fake_consul() -> (String, Requests) {
+    let requests = Requests::default();
+    let recorded = requests.clone();
+
+    let make_service = make_service_fn(move |_| {
+        let requests = recorded.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                let requests = requests.clone();
+                async move {
+                    let token = request
+                        .headers()
+                        .get("x-consul-token")
+                        .map(|token| format!(" [{}]", token.to_str().unwrap()))
+                        .unwrap_or_default();
+                    requests
+                        .lock()
+                        .unwrap()
+                        .push(format!("{} {}{}", request.method(), request.uri(), token));
+
+                    let body = if request.uri().path().starts_with("/v1/health/service/") {
+                        r#"[{"Node": {"Address": "10.0.0.1"}, "Service": {"Address": "10.0.0.9", "Port": 50051}}]"#
+                    } else {
+                        ""
+                    };
+                    Ok::<_, Infallible>(Response::new(Body::from(body)))
+                }
+            }))
+        }
+    });
+    let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+
+    (url, requests)
+}
+
+fn consul(url: &str) -> ConsulDiscovery {
+    let mut settings = Settings::defaults()
+        .and_then(|config| config.build())
+        .and_then(|config| config.get::<tonic_template::config::DiscoverySettings>("discovery"))
+        .unwrap();
+    settings.provider = DiscoveryProvider::Consul;
+    settings.consul_url = url.to_string();
+    settings.consul_token = Some("acl-token".to_string());
+    ConsulDiscovery::from_settings(&settings).unwrap()
+}
+
+fn instance() -> Instance {
+    Instance {
+        id: "users-1".to_string(),
+        service: "users".to_string(),
+        host: "10.0.0.9".to_string(),
+        port: 50051,
+        tags: vec!["v1".to_string()],
+    }
+}
+
+#[tokio::test]
+async fn instances_check_in_until_shutdown_then_deregister() {
+    let (url, requests) = fake_consul().await;
+    let consul: Arc<dyn Discovery> = Arc::new(consul(&url));
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+
+    let registration = tokio::spawn(discovery::maintain_registration(
+        consul,
+        instance(),
+        Duration::from_millis(100),
+        async {
+            let _ = stopped.await;
+        },
+    ));
+    tokio::time::sleep(Duration::from_millis(180)).await;
+    stop.send(()).unwrap();
+    registration.await.unwrap();
+
+    let requests = requests.lock().unwrap().clone();
+    assert_eq!(requests.first().unwrap(), "PUT /v1/agent/service/register [acl-token]");
+    assert_eq!(requests.last().unwrap(), "PUT /v1/agent/service/deregister/users-1 [acl-token]");
+    let check_ins = requests
+        .iter()
+        .filter(|request| *request == "PUT /v1/agent/check/pass/service:users-1 [acl-token]")
+        .count();
+    // One when registering, then every half TTL
+    assert!(check_ins >= 3, "{:?}", requests);
+}
+
+#[tokio::test]
+async fn consul_registration_follows_the_lifecycle() {
+    let (url, requests) = fake_consul().await;
+    let app = TestApp::spawn().await;
+    let mut settings = app.state.settings.clone();
+    settings.discovery.provider = DiscoveryProvider::Consul;
+    settings.discovery.consul_url = url;
+    settings.discovery.advertise_host = Some("10.0.0.9".to_string());
+    settings.discovery.instance_id = Some("users-1".to_string());
+
+    let mut lifecycle = Lifecycle::new(&settings.lifecycle);
+    discovery::register_hooks(&mut lifecycle, &settings).unwrap();
+    lifecycle.start().await.unwrap();
+    assert!(requests.lock().unwrap().is_empty());
+
+    lifecycle.ready().await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(requests.lock().unwrap().first().unwrap(), "PUT /v1/agent/service/register");
+
+    lifecycle.shutdown().await;
+    assert_eq!(requests.lock().unwrap().last().unwrap(), "PUT /v1/agent/service/deregister/users-1");
+}
+
+#[tokio::test]
+async fn consul_resolves_passing_instances() {
+    let (url, requests) = fake_consul().await;
+
+    let addresses = consul(&url).resolve("users").await.unwrap();
+
+    assert_eq!(addresses, ["10.0.0.9:50051"]);
+    assert_eq!(
+        *requests.lock().unwrap(),
+        ["GET /v1/health/service/users?passing=true [acl-token]"]
+    );
+}
+
+#[tokio::test]
+async fn unreachable_providers_fail_with_unavailable() {
+    let error = consul("http://127.0.0.1:1").resolve("users").await.unwrap_err();
+
+    assert_eq!(error.code().as_str(), "SERVICE_UNAVAILABLE");
+}
+
+#[tokio::test]
+async fn balanced_channels_call_resolved_instances() {
+    let app = TestApp::spawn().await;
+    let mut settings = app.state.settings.server.clone();
+    settings.host = "127.0.0.1".to_string();
+    settings.port = 0;
+    let listener = transport::bind(&settings).unwrap().remove(0);
+    let addr = listener.socket.local_addr().unwrap();
+    let service = tonic_template::grpc_service(app.state.clone());
+    let draining = app.state.draining.clone();
+    tokio::spawn(async move { transport::serve(listener, service, &settings, draining).await });
+
+    let services = HashMap::from([("users".to_string(), vec![addr.to_string()])]);
+    let (channel, _resolver) =
+        discovery::balanced_channel(Arc::new(StaticDiscovery::new(services)), "users", Duration::from_secs(60));
+
+    let mut health = HealthServiceClient::new(channel);
+    let response = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match health.check(HealthCheckRequest::default()).await {
+                Ok(response) => return response.into_inner(),
+                // Until the server is listening
+                Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        }
+    })
+    .await
+    .expect("a response through the resolved address");
+    assert!(!response.version.is_empty());
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use std::time::Duration;
+use tonic::Code;
+
+use common::{assert_status, in_process_channel, TestApp};
+use tonic_template::proto::grpc::health::v1::health_check_response::ServingStatus;
+use tonic_template::proto::grpc::health::v1::health_client::HealthClient;
+use tonic_template::proto::grpc::health::v1::HealthCheckRequest;
+use tonic_template::smoke::reflection;
+
+fn request(service: &str) -> HealthCheckRequest {
+    HealthCheckRequest {
+        service: service.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn checks_report_not_serving_without_a_database() {
+    let app = TestApp::spawn().await;
+    let mut health = HealthClient::new(app.channel.clone());
+
+    for service in ["", "user.v1.UserService", "grpc.health.v1.Health"] {
+        let response = health.check(request(service)).await.unwrap().into_inner();
+        assert_eq!(response.status(), ServingStatus::NotServing, "{:?}", service);
+    }
+}
+
+#[tokio::test]
+async fn unknown_services_are_not_found() {
+    let app = TestApp::spawn().await;
+    let mut health = HealthClient::new(app.channel.clone());
+
+    assert_status(health.check(request("nope.v1.NopeService")).await, Code::NotFound);
+
+    // Admin listeners only know their own services
+    let mut admin = HealthClient::new(in_process_channel(tonic_template::admin_service(app.state.clone())).await);
+    assert_status(admin.check(request("user.v1.UserService")).await, Code::NotFound);
+    admin.check(request("admin.v1.AdminService")).await.unwrap();
+}
+
+#[tokio::test]
+async fn every_served_service_is_known() {
+    let app = TestApp::spawn().await;
+    let mut health = HealthClient::new(app.channel.clone());
+
+    let services = reflection::discover(app.channel.clone()).await.unwrap();
+    assert!(services.iter().any(|(name, _)| name == "grpc.reflection.v1alpha.ServerReflection"));
+    // The admin service is described but served on admin listeners
+    for (name, _) in services.iter().filter(|(name, _)| name != "admin.v1.AdminService") {
+        health.check(request(name)).await.unwrap();
+    }
+
+    // Without reflection, its name is unknown too
+    let app = TestApp::spawn_with(|config| config.set_override("server.reflection", false).unwrap()).await;
+    let mut health = HealthClient::new(app.channel.clone());
+    assert_status(health.check(request("grpc.reflection.v1alpha.ServerReflection")).await, Code::NotFound);
+    health.check(request("user.v1.UserService")).await.unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn checks_follow_the_database_and_draining() {
+    let app = TestApp::spawn().await;
+    let mut health = HealthClient::new(app.channel.clone());
+
+    let response = health.check(request("")).await.unwrap().into_inner();
+    assert_eq!(response.status(), ServingStatus::Serving);
+
+    app.state.draining.start();
+    let response = health.check(request("user.v1.UserService")).await.unwrap().into_inner();
+    assert_eq!(response.status(), ServingStatus::NotServing);
+}
+
+#[tokio::test]
+async fn watch_sends_the_status_and_ends_when_listeners_close() {
+    let app = TestApp::spawn().await;
+    let mut health = HealthClient::new(app.channel.clone());
+
+    let mut stream = health.watch(request("")).await.unwrap().into_inner();
+    let first = stream.message().await.unwrap().expect("current status");
+    assert_eq!(first.status(), ServingStatus::NotServing);
+
+    // Nothing changed, so the stream just ends
+    app.state.draining.close();
+    let end = tokio::time::timeout(Duration::from_secs(5), stream.message()).await.unwrap();
+    assert!(end.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn watching_an_unknown_service_reports_service_unknown() {
+    let app = TestApp::spawn().await;
+    let mut health = HealthClient::new(app.channel.clone());
+
+    let mut stream = health.watch(request("nope.v1.NopeService")).await.unwrap().into_inner();
+    let first = stream.message().await.unwrap().expect("status");
+    assert_eq!(first.status(), ServingStatus::ServiceUnknown);
+}
+
+#[tokio::test]
+async fn reflection_describes_the_standard_health_service() {
+    let app = TestApp::spawn().await;
+
+    let services = reflection::discover(app.channel.clone()).await.unwrap();
+    let (_, methods) = services
+        .iter()
+        .find(|(name, _)| name == "grpc.health.v1.Health")
+        .expect("grpc.health.v1.Health is listed");
+    let watch = methods.iter().find(|method| method.path == "/grpc.health.v1.Health/Watch").unwrap();
+    assert!(watch.server_streaming);
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use config::{File, FileFormat};
+use platform_core::rate_limit::BackendSettings;
+use tonic::Code;
+use tonic_types::StatusExt;
+
+use common::{assert_status, TestApp};
+use tonic_template::proto::user::v1::user_service_client::UserServiceClient;
+use tonic_template::proto::user::v1::{RegisterRequest, ValidateTokenRequest};
+
+#[tokio::test]
+async fn rate_limited_calls_carry_retry_info() {
+    let app = TestApp::spawn_with(|config| {
+        config
+            .set_override("limits.default_rate_per_second", 1.0)
+            .and_then(|config| config.set_override("limits.default_burst", 1))
+            .expect("limit overrides")
+    })
+    .await;
+    let mut client = UserServiceClient::new(app.channel.clone());
+    let request = || ValidateTokenRequest {
+        access_token: "token".to_string(),
+    };
+
+    client.validate_token(request()).await.expect("first call is admitted");
+
+    let status = assert_status(client.validate_token(request()).await, Code::ResourceExhausted);
+    let retry_info = status.get_details_retry_info().expect("retry info");
+    assert!(retry_info.retry_delay.is_some());
+}
+
+#[tokio::test]
+async fn sign_up_has_its_own_bucket() {
+    let settings = r#"
+        [[limits.methods]]
+        method = "/user.v1.UserService/Register"
+        rate_per_second = 0.1
+        burst = 1
+    "#;
+    let app = TestApp::spawn_with(|config| config.add_source(File::from_str(settings, FileFormat::Toml))).await;
+    let mut client = UserServiceClient::new(app.channel.clone());
+    // Rejected before reaching the database
+    let register = || RegisterRequest {
+        email: "not an email".to_string(),
+        username: "ada".to_string(),
+        password: "correct horse battery staple".to_string(),
+        full_name: None,
+    };
+
+    assert_status(client.register(register()).await, Code::InvalidArgument);
+    let status = assert_status(client.register(register()).await, Code::ResourceExhausted);
+    assert!(status.get_details_retry_info().is_some());
+
+    // Other methods draw from the default bucket
+    let request = ValidateTokenRequest {
+        access_token: "token".to_string(),
+    };
+    client.validate_token(request).await.expect("the default bucket is untouched");
+}
+
+#[tokio::test]
+async fn sign_in_and_sign_up_are_limited_in_memory_by_default() {
+    let app = TestApp::spawn().await;
+    let limits = &app.state.settings.limits;
+
+    let methods: Vec<_> = limits.methods.iter().map(|rule| rule.method.as_str()).collect();
+    assert_eq!(methods, ["/user.v1.UserService/Login", "/user.v1.UserService/Register"]);
+    assert_eq!(limits.backend, BackendSettings::Memory);
+}
@@ -0,0 +1,179 @@
+mod common;
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName};
+use std::net::{Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tonic::codegen::http::Uri;
+use tonic::transport::Channel;
+use tonic::Code;
+use uuid::Uuid;
+
+use common::{assert_status, authorized, TestApp};
+use tonic_template::config::{ListenerRole, ListenerSettings, ServerSettings, TlsSettings};
+use tonic_template::proto::admin::v1::admin_service_client::AdminServiceClient;
+use tonic_template::proto::admin::v1::GetLogLevelRequest;
+use tonic_template::proto::health::v1::health_service_client::HealthServiceClient;
+use tonic_template::proto::health::v1::health_check_response::ServingStatus;
+use tonic_template::proto::health::v1::HealthCheckRequest;
+use tonic_template::proto::user::v1::user_service_client::UserServiceClient;
+use tonic_template::proto::user::v1::GetUserRequest;
+use tonic_template::transport::{self, Listener};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+
+fn listener(address: &str, role: ListenerRole, tls: bool) -> ListenerSettings {
+    ListenerSettings {
+        address: address.to_string(),
+        role,
+        tls: tls.then(|| TlsSettings {
+            cert_path: format!("{}/server.pem", FIXTURES),
+            key_path: format!("{}/server.key", FIXTURES),
+        }),
+    }
+}
+
+async fn server_settings(listeners: Vec<ListenerSettings>) -> ServerSettings {
+    let app = TestApp::spawn().await;
+    ServerSettings {
+        listeners,
+        ..app.state.settings.server.clone()
+    }
+}
+
+fn address(listener: &Listener) -> SocketAddr {
+    listener.socket.local_addr().unwrap()
+}
+
+/// An HTTP/2 connection over TLS, trusting only the test CA.
+async fn tls_connection(addr: SocketAddr) -> hyper::client::conn::SendRequest<tonic::body::BoxBody> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(CertificateDer::from_pem_file(format!("{}/ca.pem", FIXTURES)).unwrap()).unwrap();
+    let mut config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec()];
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let server_name = ServerName::try_from("localhost").unwrap();
+    let tls = TlsConnector::from(Arc::new(config)).connect(server_name, tcp).await.unwrap();
+    assert_eq!(tls.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+
+    let (send_request, connection) = hyper::client::conn::Builder::new()
+        .http2_only(true)
+        .handshake(tls)
+        .await
+        .unwrap();
+    tokio::spawn(connection);
+    send_request
+}
+
+#[tokio::test]
+async fn host_and_port_are_the_default_listener() {
+    let settings = server_settings(vec![]).await;
+
+    let listeners = settings.listeners();
+    assert_eq!(listeners.len(), 1);
+    assert_eq!(listeners[0].address, format!("{}:{}", settings.host, settings.port));
+    assert_eq!(listeners[0].role, ListenerRole::Public);
+    assert!(listeners[0].tls.is_none());
+}
+
+#[tokio::test]
+async fn invalid_listeners_are_rejected_at_startup() {
+    let admin_only = server_settings(vec![listener("127.0.0.1:0", ListenerRole::Admin, false)]).await;
+    let error = transport::bind(&admin_only).err().unwrap().to_string();
+    assert!(error.contains("needs a public listener"), "{}", error);
+
+    let bad_address = server_settings(vec![listener("localhost", ListenerRole::Public, false)]).await;
+    let error = transport::bind(&bad_address).err().unwrap().to_string();
+    assert!(error.contains("invalid listener address localhost"), "{}", error);
+
+    let mut missing_key = listener("127.0.0.1:0", ListenerRole::Public, true);
+    missing_key.tls.as_mut().unwrap().key_path = format!("{}/missing.key", FIXTURES);
+    let error = transport::bind(&server_settings(vec![missing_key]).await).err().unwrap().to_string();
+    assert!(error.contains("reading TLS key"), "{}", error);
+}
+
+#[tokio::test]
+async fn ipv4_and_ipv6_can_share_a_port() {
+    let port = std::net::TcpListener::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+    let settings = server_settings(vec![
+        listener(&format!("0.0.0.0:{}", port), ListenerRole::Public, false),
+        listener(&format!("[::]:{}", port), ListenerRole::Public, false),
+    ])
+    .await;
+
+    let listeners = transport::bind(&settings).unwrap();
+    assert_eq!(address(&listeners[1]).ip(), Ipv6Addr::UNSPECIFIED);
+    assert_eq!(address(&listeners[1]).port(), port);
+}
+
+#[tokio::test]
+async fn listeners_serve_by_role_and_scheme() {
+    let app = TestApp::spawn().await;
+    let settings = ServerSettings {
+        listeners: vec![
+            listener("127.0.0.1:0", ListenerRole::Public, true),
+            listener("127.0.0.1:0", ListenerRole::Admin, false),
+        ],
+        ..app.state.settings.server.clone()
+    };
+    let mut bound = transport::bind(&settings).unwrap();
+    let (admin, tls) = (bound.pop().unwrap(), bound.pop().unwrap());
+    let (admin_addr, tls_addr) = (address(&admin), address(&tls));
+
+    let (public_settings, draining) = (settings.clone(), app.state.draining.clone());
+    let service = tonic_template::grpc_service(app.state.clone());
+    tokio::spawn(async move { transport::serve(tls, service, &public_settings, draining).await });
+    let (admin_service, draining) = (tonic_template::admin_service(app.state.clone()), app.state.draining.clone());
+    tokio::spawn(async move { transport::serve(admin, admin_service, &settings, draining).await });
+
+    let origin = Uri::from_static("https://localhost");
+    let mut health = HealthServiceClient::with_origin(tls_connection(tls_addr).await, origin.clone());
+    health.check(HealthCheckRequest::default()).await.unwrap();
+    let mut users = UserServiceClient::with_origin(tls_connection(tls_addr).await, origin);
+    assert_status(users.get_user(GetUserRequest::default()).await, Code::Unauthenticated);
+
+    // Admin listeners only serve health checks and admin RPCs
+    let channel = Channel::from_shared(format!("http://{}", admin_addr)).unwrap().connect().await.unwrap();
+    HealthServiceClient::new(channel.clone())
+        .check(HealthCheckRequest::default())
+        .await
+        .unwrap();
+    let request = authorized(GetUserRequest::default(), &app.token_for(Uuid::new_v4()));
+    assert_status(UserServiceClient::new(channel.clone()).get_user(request).await, Code::Unimplemented);
+    let request = GetLogLevelRequest::default();
+    assert_status(AdminServiceClient::new(channel).get_log_level(request).await, Code::Unauthenticated);
+}
+
+#[tokio::test]
+async fn draining_fails_health_checks_then_closes_listeners() {
+    let app = TestApp::spawn().await;
+    let settings = ServerSettings {
+        listeners: vec![listener("127.0.0.1:0", ListenerRole::Public, false)],
+        ..app.state.settings.server.clone()
+    };
+    let public = transport::bind(&settings).unwrap().remove(0);
+    let addr = address(&public);
+    let (service, draining) = (tonic_template::grpc_service(app.state.clone()), app.state.draining.clone());
+    let server = tokio::spawn(async move { transport::serve(public, service, &settings, draining).await });
+
+    let channel = Channel::from_shared(format!("http://{}", addr)).unwrap().connect().await.unwrap();
+    let mut health = HealthServiceClient::new(channel);
+
+    // Still serving calls, but reporting NOT_SERVING
+    app.state.draining.start();
+    let response = health.check(HealthCheckRequest::default()).await.unwrap().into_inner();
+    assert_eq!(response.status(), ServingStatus::NotServing);
+
+    // The open connection gets a GOAWAY and the loop returns once it closes
+    app.state.draining.close();
+    tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap().unwrap();
+    assert!(TcpStream::connect(addr).await.is_err());
+}
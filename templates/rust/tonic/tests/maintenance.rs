@@ -0,0 +1,61 @@
+mod common;
+
+use std::time::Duration;
+use tonic::Code;
+use tonic_types::StatusExt;
+
+use common::{assert_status, TestApp};
+use tonic_template::proto::grpc::health::v1::health_client::HealthClient;
+use tonic_template::proto::grpc::health::v1::HealthCheckRequest as StandardHealthCheckRequest;
+use tonic_template::proto::health::v1::health_service_client::HealthServiceClient;
+use tonic_template::proto::health::v1::HealthCheckRequest;
+use tonic_template::proto::user::v1::user_service_client::UserServiceClient;
+use tonic_template::proto::user::v1::ValidateTokenRequest;
+
+async fn spawn_in_maintenance() -> TestApp {
+    TestApp::spawn_with(|config| {
+        config
+            .set_override("maintenance.enabled", true)
+            .and_then(|config| config.set_override("maintenance.retry_after_secs", 60))
+            .expect("maintenance overrides")
+    })
+    .await
+}
+
+#[tokio::test]
+async fn calls_are_rejected_with_retry_info() {
+    let app = spawn_in_maintenance().await;
+    let mut client = UserServiceClient::new(app.channel.clone());
+
+    let status = assert_status(
+        client
+            .validate_token(ValidateTokenRequest {
+                access_token: "token".to_string(),
+            })
+            .await,
+        Code::Unavailable,
+    );
+
+    let error_info = status.get_details_error_info().expect("error info");
+    assert_eq!(error_info.reason, "MAINTENANCE_MODE");
+    let delay = status
+        .get_details_retry_info()
+        .and_then(|info| info.retry_delay)
+        .expect("retry delay");
+    assert!(delay >= Duration::from_secs(30) && delay <= Duration::from_secs(60));
+}
+
+#[tokio::test]
+async fn health_checks_keep_working() {
+    let app = spawn_in_maintenance().await;
+    let mut client = HealthServiceClient::new(app.channel.clone());
+
+    client
+        .check(HealthCheckRequest::default())
+        .await
+        .expect("health check is exempt");
+    HealthClient::new(app.channel.clone())
+        .check(StandardHealthCheckRequest::default())
+        .await
+        .expect("standard health check is exempt");
+}
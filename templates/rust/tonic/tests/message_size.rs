@@ -0,0 +1,51 @@
+mod common;
+
+use tonic::codec::CompressionEncoding;
+use tonic::Code;
+use tonic_types::StatusExt;
+
+use common::{assert_status, TestApp};
+use tonic_template::proto::user::v1::user_service_client::UserServiceClient;
+use tonic_template::proto::user::v1::ValidateTokenRequest;
+
+const LIMIT: usize = 64 * 1024;
+
+async fn spawn() -> TestApp {
+    TestApp::spawn_with(|config| {
+        config
+            .set_override("server.max_decoding_message_size", LIMIT as u64)
+            .expect("message size override")
+    })
+    .await
+}
+
+fn request(len: usize) -> ValidateTokenRequest {
+    ValidateTokenRequest {
+        access_token: "a".repeat(len),
+    }
+}
+
+#[tokio::test]
+async fn oversized_messages_are_rejected() {
+    let app = spawn().await;
+    let mut client = UserServiceClient::new(app.channel.clone());
+
+    let status = assert_status(client.validate_token(request(LIMIT + 1)).await, Code::ResourceExhausted);
+    assert_eq!(status.get_details_error_info().expect("error info").reason, "MESSAGE_TOO_LARGE");
+}
+
+#[tokio::test]
+async fn over_expanding_compressed_messages_are_rejected() {
+    let app = spawn().await;
+    let mut client = UserServiceClient::new(app.channel.clone()).send_compressed(CompressionEncoding::Gzip);
+
+    assert_status(client.validate_token(request(16 * LIMIT)).await, Code::ResourceExhausted);
+}
+
+#[tokio::test]
+async fn compressed_messages_within_the_limit_are_accepted() {
+    let app = spawn().await;
+    let mut client = UserServiceClient::new(app.channel.clone()).send_compressed(CompressionEncoding::Gzip);
+
+    client.validate_token(request(1024)).await.expect("compressed call succeeds");
+}
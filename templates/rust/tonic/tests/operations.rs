@@ -0,0 +1,126 @@
+mod common;
+
+use serde_json::json;
+use tokio_stream::StreamExt;
+use tonic::Code;
+use uuid::Uuid;
+
+use common::{assert_status, authorized, bearer, TestApp};
+use tonic_template::errors::AppError;
+use tonic_template::factories::UserFactory;
+use tonic_template::proto::operations::v1::operation::Result as OperationResult;
+use tonic_template::proto::operations::v1::operations_client::OperationsClient;
+use tonic_template::proto::operations::v1::{GetOperationRequest, WatchOperationRequest};
+
+#[tokio::test]
+async fn operations_require_authentication() {
+    let app = TestApp::spawn().await;
+    let mut client = OperationsClient::new(app.channel.clone());
+
+    let request = GetOperationRequest {
+        name: format!("operations/{}", Uuid::new_v4()),
+    };
+    assert_status(client.get_operation(request).await, Code::Unauthenticated);
+}
+
+#[tokio::test]
+async fn operation_names_are_validated() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(Uuid::from_u128(1));
+    let mut client = OperationsClient::new(app.channel.clone());
+
+    for name in ["", "operations/123", "users/1"] {
+        let request = GetOperationRequest { name: name.to_string() };
+        assert_status(client.get_operation(authorized(request, &token)).await, Code::InvalidArgument);
+        let request = WatchOperationRequest { name: name.to_string() };
+        assert_status(client.watch_operation(authorized(request, &token)).await, Code::InvalidArgument);
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn operations_are_private_to_their_user() {
+    let app = TestApp::spawn().await;
+    let owner = app.insert_user(UserFactory::build()).await;
+    let other = app.insert_user(UserFactory::build()).await;
+    let operation = app.state.operations.start(owner.id, "export", json!({})).await.unwrap();
+
+    let mut client = OperationsClient::with_interceptor(app.channel.clone(), bearer(&app.token_for_user(&other)));
+    let status = assert_status(
+        client.get_operation(GetOperationRequest { name: operation.name() }).await,
+        Code::NotFound,
+    );
+    assert!(status.message().contains("Operation not found"), "{:?}", status);
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn watching_streams_progress_until_done() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let operations = &app.state.operations;
+    let operation = operations
+        .start(user.id, "import", json!({ "filename": "users.csv" }))
+        .await
+        .unwrap();
+    let mut client = OperationsClient::with_interceptor(app.channel.clone(), bearer(&app.token_for_user(&user)));
+
+    let mut updates = client
+        .watch_operation(WatchOperationRequest { name: operation.name() })
+        .await
+        .unwrap()
+        .into_inner();
+
+    let initial = updates.next().await.unwrap().unwrap();
+    assert!(!initial.done);
+    let metadata = initial.metadata.unwrap();
+    assert_eq!(metadata.kind, "import");
+    assert_eq!(metadata.progress_percent, 0);
+    assert!(metadata.details.unwrap().fields.contains_key("filename"));
+
+    operations.report_progress(operation.id, 40, Some("Imported 40 rows")).await.unwrap();
+    let progress = updates.next().await.unwrap().unwrap();
+    let metadata = progress.metadata.unwrap();
+    assert_eq!(metadata.progress_percent, 40);
+    assert_eq!(metadata.progress_message.as_deref(), Some("Imported 40 rows"));
+
+    operations.succeed(operation.id, json!({ "imported": 100 })).await.unwrap();
+    let done = updates.next().await.unwrap().unwrap();
+    assert!(done.done);
+    let Some(OperationResult::Response(response)) = done.result else {
+        panic!("expected a response, got {:?}", done.result);
+    };
+    assert!(response.fields.contains_key("imported"));
+    assert!(updates.next().await.is_none());
+
+    // Finished operations can't be changed
+    operations.fail(operation.id, &AppError::InternalServerError).await.unwrap();
+    let fetched = client
+        .get_operation(GetOperationRequest { name: operation.name() })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(matches!(fetched.result, Some(OperationResult::Response(_))));
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn failures_hide_internal_details() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let operation = app.state.operations.start(user.id, "export", json!({})).await.unwrap();
+
+    app.state.operations.fail(operation.id, &AppError::InternalServerError).await.unwrap();
+
+    let mut client = OperationsClient::with_interceptor(app.channel.clone(), bearer(&app.token_for_user(&user)));
+    let fetched = client
+        .get_operation(GetOperationRequest { name: operation.name() })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(fetched.done);
+    let Some(OperationResult::Error(error)) = fetched.result else {
+        panic!("expected an error, got {:?}", fetched.result);
+    };
+    assert_eq!(error.code, "INTERNAL");
+}
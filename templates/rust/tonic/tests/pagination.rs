@@ -0,0 +1,211 @@
+mod common;
+
+use chrono::Utc;
+use std::collections::HashSet;
+use std::time::Duration;
+use tonic::{Code, Response, Status};
+use tonic_types::StatusExt;
+use uuid::Uuid;
+
+use common::{assert_status, bearer, TestApp, TEST_JWT_SECRET};
+use tonic_template::aip::{PageRequest, PageTokens};
+use tonic_template::factories::UserFactory;
+use tonic_template::proto::user::v1::user_service_client::UserServiceClient;
+use tonic_template::proto::user::v1::{ListUsersRequest, ListUsersResponse, ListUsersStreamRequest, User};
+
+fn page_tokens(app: &TestApp) -> PageTokens {
+    PageTokens::new(TEST_JWT_SECRET, &app.state.settings.pagination)
+}
+
+/// A token for the second page of two-user pages in the default order,
+/// issued `age_secs` ago.
+fn second_page_token(app: &TestApp, age_secs: i64) -> String {
+    let issued_at = Utc::now().timestamp() - age_secs;
+    PageRequest::parse_at(2, "", &[""], &page_tokens(app), issued_at)
+        .unwrap()
+        .next_page_token(2, true)
+}
+
+async fn list_users(app: &TestApp, request: ListUsersRequest) -> Result<Response<ListUsersResponse>, Status> {
+    let mut client = UserServiceClient::with_interceptor(app.channel.clone(), bearer(&app.token_for(Uuid::new_v4())));
+    client.list_users(request).await
+}
+
+/// Every batch of a `ListUsersStream` call.
+async fn stream_users(app: &TestApp, request: ListUsersStreamRequest) -> Result<Vec<Vec<User>>, Status> {
+    let mut client = UserServiceClient::with_interceptor(app.channel.clone(), bearer(&app.token_for(Uuid::new_v4())));
+    let mut stream = client.list_users_stream(request).await?.into_inner();
+    let mut batches = Vec::new();
+    while let Some(batch) = stream.message().await? {
+        batches.push(batch.users);
+    }
+    Ok(batches)
+}
+
+fn reason(status: &Status) -> String {
+    status.get_details_error_info().expect("error info").reason
+}
+
+#[tokio::test]
+async fn tampered_page_tokens_are_rejected() {
+    let app = TestApp::spawn().await;
+    let token = second_page_token(&app, 0);
+    let (payload, signature) = token.split_once('.').unwrap();
+
+    let mut tampered = signature.to_string();
+    let last = if tampered.ends_with('A') { "B" } else { "A" };
+    tampered.replace_range(tampered.len() - 1.., last);
+    for page_token in [format!("{}.{}", payload, tampered), payload.to_string()] {
+        let request = ListUsersRequest {
+            page_size: 2,
+            page_token,
+            ..Default::default()
+        };
+        let status = assert_status(list_users(&app, request).await, Code::InvalidArgument);
+        assert_eq!(reason(&status), "PAGE_TOKEN_INVALID");
+    }
+
+    // Tokens are only good for the query they were issued for
+    let request = ListUsersRequest {
+        page_size: 2,
+        page_token: token,
+        order_by: "email".to_string(),
+        ..Default::default()
+    };
+    let status = assert_status(list_users(&app, request).await, Code::InvalidArgument);
+    assert_eq!(reason(&status), "PAGE_TOKEN_INVALID");
+}
+
+#[tokio::test]
+async fn expired_page_tokens_are_rejected() {
+    let app = TestApp::spawn_with(|config| config.set_override("pagination.token_ttl_secs", 60).unwrap()).await;
+
+    let request = ListUsersRequest {
+        page_size: 2,
+        page_token: second_page_token(&app, 120),
+        ..Default::default()
+    };
+    let status = assert_status(list_users(&app, request).await, Code::InvalidArgument);
+    assert_eq!(reason(&status), "PAGE_TOKEN_EXPIRED");
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn pages_cover_every_user_with_or_without_a_total() {
+    let app = TestApp::spawn().await;
+    for _ in 0..3 {
+        app.insert_user(UserFactory::build()).await;
+    }
+
+    let first = list_users(&app, ListUsersRequest { page_size: 2, ..Default::default() })
+        .await
+        .unwrap()
+        .into_inner();
+    let total_size = first.total_size.expect("total_size by default") as usize;
+    assert!(total_size >= 3);
+
+    let mut seen = HashSet::new();
+    let mut page_token = String::new();
+    loop {
+        let request = ListUsersRequest {
+            page_size: 2,
+            page_token,
+            skip_total_count: true,
+            ..Default::default()
+        };
+        let page = list_users(&app, request).await.unwrap().into_inner();
+        assert_eq!(page.total_size, None);
+        assert!(page.users.len() <= 2);
+        for user in page.users {
+            assert!(seen.insert(user.id), "user listed twice");
+        }
+
+        page_token = page.next_page_token;
+        if page_token.is_empty() {
+            break;
+        }
+    }
+    assert_eq!(seen.len(), total_size);
+}
+
+#[tokio::test]
+async fn streams_check_the_batch_size_and_order_before_streaming() {
+    let app = TestApp::spawn().await;
+
+    let request = ListUsersStreamRequest { batch_size: -1, ..Default::default() };
+    assert_status(stream_users(&app, request).await, Code::InvalidArgument);
+
+    let request = ListUsersStreamRequest { order_by: "password_hash".to_string(), ..Default::default() };
+    assert_status(stream_users(&app, request).await, Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn stream_failures_end_the_stream_with_an_error() {
+    // No database to read from
+    let app = TestApp::spawn().await;
+
+    assert!(stream_users(&app, ListUsersStreamRequest::default()).await.is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn streams_cover_every_user_in_batches() {
+    let app = TestApp::spawn().await;
+    for _ in 0..5 {
+        app.insert_user(UserFactory::build()).await;
+    }
+
+    let total_size = list_users(&app, ListUsersRequest { page_size: 1, ..Default::default() })
+        .await
+        .unwrap()
+        .into_inner()
+        .total_size
+        .unwrap() as usize;
+    let request = ListUsersStreamRequest { batch_size: 2, order_by: "email".to_string() };
+    let batches = stream_users(&app, request).await.unwrap();
+
+    assert!(batches.iter().all(|batch| !batch.is_empty() && batch.len() <= 2));
+    let users: Vec<User> = batches.into_iter().flatten().collect();
+    assert_eq!(users.len(), total_size);
+    assert_eq!(users.iter().map(|user| &user.id).collect::<HashSet<_>>().len(), total_size, "user streamed twice");
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn stalled_streams_leave_connections_for_unary_calls() {
+    let app = TestApp::spawn_with(|config| {
+        config
+            .set_override("database.max_connections", 2)
+            .and_then(|config| config.set_override("pagination.max_streams", 1))
+            .and_then(|config| config.set_override("pagination.stream_idle_timeout_secs", 1))
+            .unwrap()
+    })
+    .await;
+    for _ in 0..3 {
+        app.insert_user(UserFactory::build()).await;
+    }
+
+    // Opened but never read, so it holds a connection
+    let mut client = UserServiceClient::with_interceptor(app.channel.clone(), bearer(&app.token_for(Uuid::new_v4())));
+    let request = ListUsersStreamRequest { batch_size: 1, ..Default::default() };
+    let mut stalled = client.list_users_stream(request.clone()).await.unwrap().into_inner();
+
+    // A second stream would take the last connection, so it's turned away
+    let status = assert_status(stream_users(&app, request).await, Code::ResourceExhausted);
+    assert_eq!(reason(&status), "RATE_LIMITED");
+
+    let unary = list_users(&app, ListUsersRequest { page_size: 1, ..Default::default() });
+    tokio::time::timeout(Duration::from_secs(5), unary).await.expect("unary call blocked").unwrap();
+
+    // Once idle, the stalled stream fails and gives its slot back
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let status = loop {
+        match stalled.message().await {
+            Ok(Some(_)) => continue,
+            Ok(None) => panic!("the stalled stream ended without an error"),
+            Err(status) => break status,
+        }
+    };
+    assert_eq!(status.code(), Code::DeadlineExceeded);
+    stream_users(&app, ListUsersStreamRequest::default()).await.unwrap();
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use tonic::Code;
+use tonic_types::StatusExt;
+
+use common::{assert_status, TestApp};
+use tonic_template::factories::{UserFactory, FACTORY_PASSWORD};
+use tonic_template::proto::user::v1::user_service_client::UserServiceClient;
+use tonic_template::proto::user::v1::*;
+
+const NEW_PASSWORD: &str = "a-new-password";
+
+fn reason(status: &tonic::Status) -> String {
+    status.get_details_error_info().expect("error info").reason
+}
+
+fn last_token(app: &TestApp) -> String {
+    let email = app.mailer.outbox().pop().expect("an email");
+    let (_, token) = email.text.split_once("?token=").expect("a link");
+    token.split_whitespace().next().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn malformed_requests_are_rejected_before_the_database() {
+    let app = TestApp::spawn().await;
+    let mut users = UserServiceClient::new(app.channel.clone());
+
+    let result = users
+        .request_password_reset(RequestPasswordResetRequest {
+            email: "not-an-email".to_string(),
+        })
+        .await;
+    assert_status(result, Code::InvalidArgument);
+
+    let result = users
+        .reset_password(ResetPasswordRequest {
+            token: "anything".to_string(),
+            new_password: "short".to_string(),
+        })
+        .await;
+    assert_status(result, Code::InvalidArgument);
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn resetting_changes_the_password_and_refuses_earlier_refresh_tokens() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let mut users = UserServiceClient::new(app.channel.clone());
+    let login = |password: &str| LoginRequest {
+        email: user.email.to_string(),
+        password: password.to_string(),
+    };
+    let session = users.login(login(FACTORY_PASSWORD)).await.unwrap().into_inner();
+    // JWTs carry whole seconds; see consume_reset
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+
+    users
+        .request_password_reset(RequestPasswordResetRequest {
+            email: user.email.to_string(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(app.mailer.outbox()[0].to, user.email);
+    let token = last_token(&app);
+    let reset = || ResetPasswordRequest {
+        token: token.clone(),
+        new_password: NEW_PASSWORD.to_string(),
+    };
+    users.reset_password(reset()).await.unwrap();
+
+    // The token works once
+    let status = assert_status(users.reset_password(reset()).await, Code::Unauthenticated);
+    assert_eq!(reason(&status), "PASSWORD_RESET_INVALID");
+
+    let refresh = RefreshTokenRequest {
+        refresh_token: session.refresh_token,
+    };
+    let status = assert_status(users.refresh_token(refresh).await, Code::Unauthenticated);
+    assert_eq!(reason(&status), "AUTH_REFRESH_TOKEN_INVALID");
+    assert_status(users.login(login(FACTORY_PASSWORD)).await, Code::Unauthenticated);
+
+    let session = users.login(login(NEW_PASSWORD)).await.unwrap().into_inner();
+    let refresh = RefreshTokenRequest {
+        refresh_token: session.refresh_token,
+    };
+    users.refresh_token(refresh).await.unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn newer_tokens_void_older_ones_and_sends_are_limited() {
+    let app = TestApp::spawn().await;
+    let user = app.insert_user(UserFactory::build()).await;
+    let mut users = UserServiceClient::new(app.channel.clone());
+    let request = || RequestPasswordResetRequest {
+        email: user.email.to_string(),
+    };
+
+    users.request_password_reset(request()).await.unwrap();
+    let voided = last_token(&app);
+    users.request_password_reset(request()).await.unwrap();
+    let result = users
+        .reset_password(ResetPasswordRequest {
+            token: voided,
+            new_password: NEW_PASSWORD.to_string(),
+        })
+        .await;
+    assert_status(result, Code::Unauthenticated);
+
+    // Three per window; the fourth succeeds without sending, as do
+    // unknown addresses
+    users.request_password_reset(request()).await.unwrap();
+    users.request_password_reset(request()).await.unwrap();
+    let unknown = RequestPasswordResetRequest {
+        email: "nobody@example.test".to_string(),
+    };
+    users.request_password_reset(unknown).await.unwrap();
+    assert_eq!(app.mailer.outbox().len(), 3);
+}
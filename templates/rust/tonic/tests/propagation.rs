@@ -0,0 +1,44 @@
+//! Mesh and tracing headers of incoming calls handed on by
+//! `inject_trace_context` to calls made while handling them.
+
+use std::convert::Infallible;
+use tonic::codegen::http::Request;
+use tonic::metadata::MetadataMap;
+use tower::{service_fn, Layer, ServiceExt};
+
+use platform_observability::propagation::PropagationSettings;
+use tonic_template::interceptors::{inject_trace_context, PropagationLayer};
+
+/// Handles `request` by returning the metadata a call to another service
+/// would carry.
+async fn outgoing_metadata(settings: PropagationSettings, request: Request<()>) -> MetadataMap {
+    let service = PropagationLayer::new(settings).layer(service_fn(|_: Request<()>| async {
+        let outgoing = inject_trace_context(tonic::Request::new(())).unwrap();
+        Ok::<_, Infallible>(outgoing.metadata().clone())
+    }));
+    service.oneshot(request).await.unwrap()
+}
+
+#[tokio::test]
+async fn allowed_headers_reach_outgoing_calls() {
+    let request = Request::builder()
+        .uri("/user.v1.UserService/GetUser")
+        .header("b3", "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1")
+        .header("baggage", "tenant=acme")
+        .header("authorization", "Bearer secret")
+        .body(())
+        .unwrap();
+
+    let outgoing = outgoing_metadata(PropagationSettings::default(), request).await;
+    assert_eq!(outgoing.get("b3").unwrap(), "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1");
+    assert_eq!(outgoing.get("baggage").unwrap(), "tenant=acme");
+    assert!(outgoing.get("authorization").is_none());
+}
+
+#[tokio::test]
+async fn nothing_is_handed_on_with_an_empty_allow_list() {
+    let request = Request::builder().header("baggage", "tenant=acme").body(()).unwrap();
+
+    let outgoing = outgoing_metadata(PropagationSettings { headers: Vec::new() }, request).await;
+    assert!(outgoing.get("baggage").is_none());
+}
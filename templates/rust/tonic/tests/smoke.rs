@@ -0,0 +1,72 @@
+//! The smoke-test plan run against the in-process server.
+
+mod common;
+
+use common::TestApp;
+use tonic_template::smoke::{self, reflection, SmokePlan};
+
+fn plan(health: bool, crud: bool) -> SmokePlan {
+    SmokePlan { health, crud, ..SmokePlan::load(None).expect("default plan") }
+}
+
+#[tokio::test]
+async fn reflection_lists_every_service_with_its_methods() {
+    let app = TestApp::spawn().await;
+
+    let services = reflection::discover(app.channel.clone()).await.expect("reflection");
+
+    let (_, methods) = services
+        .iter()
+        .find(|(name, _)| name == "file.v1.FileService")
+        .expect("file service listed");
+    let upload = methods.iter().find(|method| method.path == "/file.v1.FileService/UploadFile").unwrap();
+    assert!(upload.client_streaming && !upload.server_streaming);
+    for expected in plan(false, false).services {
+        assert!(services.iter().any(|(name, _)| *name == expected), "{} not listed", expected);
+    }
+}
+
+#[tokio::test]
+async fn default_plan_passes_without_database_checks() {
+    let app = TestApp::spawn().await;
+
+    let report = smoke::run(app.channel.clone(), &plan(false, false)).await;
+
+    assert!(report.passed(), "{}", report);
+    assert!(report.checks.iter().any(|check| check.name == "auth /user.v1.UserService/GetUser"));
+    assert!(!report.checks.iter().any(|check| check.name == "auth /user.v1.UserService/Login"));
+}
+
+#[tokio::test]
+async fn methods_wrongly_planned_as_protected_fail() {
+    let app = TestApp::spawn().await;
+    let plan = SmokePlan { public_methods: Vec::new(), ..plan(false, false) };
+
+    let report = smoke::run(app.channel.clone(), &plan).await;
+
+    let failed: Vec<_> = report.failures().map(|check| check.name.as_str()).collect();
+    assert!(failed.contains(&"auth /health.v1.HealthService/Check"), "{}", report);
+    assert!(!failed.contains(&"auth /user.v1.UserService/GetUser"), "{}", report);
+}
+
+#[tokio::test]
+async fn missing_services_fail() {
+    let app = TestApp::spawn().await;
+    let mut plan = plan(false, false);
+    plan.services.push("billing.v1.BillingService".to_string());
+
+    let report = smoke::run(app.channel.clone(), &plan).await;
+
+    let services = report.checks.iter().find(|check| check.name == "services").unwrap();
+    assert_eq!(services.failure.as_deref(), Some("not served: billing.v1.BillingService"));
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn default_plan_passes() {
+    let app = TestApp::spawn().await;
+
+    let report = smoke::run(app.channel.clone(), &SmokePlan::load(None).unwrap()).await;
+
+    assert!(report.passed(), "{}", report);
+}
@@ -0,0 +1,295 @@
+//! Snapshots of responses and error statuses, so wire-format changes show
+//! up in review. Dynamic fields (timestamps, versions) are redacted.
+//!
+//! Review changes with `cargo insta review`.
+
+mod common;
+
+use insta::assert_json_snapshot;
+use prost_types::FieldMask;
+use serde_json::json;
+use tonic::Code;
+use uuid::Uuid;
+
+use common::{assert_status, bearer, status_snapshot, TestApp};
+use tonic_template::proto::file::v1::file_service_client::FileServiceClient;
+use tonic_template::proto::file::v1::DownloadFileRequest;
+use tonic_template::proto::health::v1::health_service_client::HealthServiceClient;
+use tonic_template::proto::health::v1::HealthCheckRequest;
+use tonic_template::proto::user::v1::user_service_client::UserServiceClient;
+use tonic_template::proto::user::v1::{
+    DeleteUserRequest, GetUserRequest, ListUsersRequest, RefreshTokenRequest, UpdateUserRequest,
+    ValidateTokenRequest,
+};
+
+fn user_id() -> Uuid {
+    Uuid::from_u128(1)
+}
+
+fn other_user_id() -> Uuid {
+    Uuid::from_u128(2)
+}
+
+fn user_client(app: &TestApp) -> UserServiceClient<
+    tonic::codegen::InterceptedService<tonic::transport::Channel, impl tonic::service::Interceptor>,
+> {
+    let token = app.token_for(user_id());
+    UserServiceClient::with_interceptor(app.channel.clone(), bearer(&token))
+}
+
+// Responses without a database
+
+#[tokio::test]
+async fn health_without_database() {
+    if std::env::var("TEST_DATABASE_URL").is_ok() {
+        return;
+    }
+    let app = TestApp::spawn().await;
+    let mut client = HealthServiceClient::new(app.channel.clone());
+
+    let response = client.check(HealthCheckRequest::default()).await.unwrap().into_inner();
+
+    assert_json_snapshot!(json!({
+        "status": response.status().as_str_name(),
+        "version": response.version,
+        "timestamp": response.timestamp,
+        "metadata": response.metadata,
+    }), {
+        ".timestamp" => "[timestamp]",
+        ".version" => "[version]",
+    });
+}
+
+#[tokio::test]
+async fn validate_valid_token() {
+    let app = TestApp::spawn().await;
+    let mut client = UserServiceClient::new(app.channel.clone());
+
+    let response = client
+        .validate_token(ValidateTokenRequest {
+            access_token: app.token_for(user_id()),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_json_snapshot!(json!({
+        "valid": response.valid,
+        "user_id": response.user_id,
+        "email": response.email,
+    }));
+}
+
+#[tokio::test]
+async fn validate_invalid_token() {
+    let app = TestApp::spawn().await;
+    let mut client = UserServiceClient::new(app.channel.clone());
+
+    let response = client
+        .validate_token(ValidateTokenRequest {
+            access_token: "not-a-jwt".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_json_snapshot!(json!({
+        "valid": response.valid,
+        "user_id": response.user_id,
+        "email": response.email,
+    }));
+}
+
+// Error statuses
+
+#[tokio::test]
+async fn missing_token() {
+    let app = TestApp::spawn().await;
+    let mut client = UserServiceClient::new(app.channel.clone());
+
+    let result = client
+        .get_user(GetUserRequest {
+            id: user_id().to_string(),
+        })
+        .await;
+
+    assert_json_snapshot!(status_snapshot(&assert_status(result, Code::Unauthenticated)));
+}
+
+#[tokio::test]
+async fn invalid_user_id() {
+    let app = TestApp::spawn().await;
+    let mut client = user_client(&app);
+
+    let result = client
+        .get_user(GetUserRequest {
+            id: "not-a-uuid".to_string(),
+        })
+        .await;
+
+    assert_json_snapshot!(status_snapshot(&assert_status(result, Code::InvalidArgument)));
+}
+
+#[tokio::test]
+async fn update_other_user() {
+    let app = TestApp::spawn().await;
+    let mut client = user_client(&app);
+
+    let result = client
+        .update_user(UpdateUserRequest {
+            id: other_user_id().to_string(),
+            full_name: Some("Mallory".to_string()),
+            ..Default::default()
+        })
+        .await;
+
+    assert_json_snapshot!(status_snapshot(&assert_status(result, Code::PermissionDenied)));
+}
+
+#[tokio::test]
+async fn update_clearing_required_field() {
+    let app = TestApp::spawn().await;
+    let mut client = user_client(&app);
+
+    let result = client
+        .update_user(UpdateUserRequest {
+            id: user_id().to_string(),
+            update_mask: Some(FieldMask {
+                paths: vec!["email".to_string()],
+            }),
+            ..Default::default()
+        })
+        .await;
+
+    assert_json_snapshot!(status_snapshot(&assert_status(result, Code::InvalidArgument)));
+}
+
+#[tokio::test]
+async fn update_with_unknown_mask_path() {
+    let app = TestApp::spawn().await;
+    let mut client = user_client(&app);
+
+    let result = client
+        .update_user(UpdateUserRequest {
+            id: user_id().to_string(),
+            update_mask: Some(FieldMask {
+                paths: vec!["password_hash".to_string()],
+            }),
+            ..Default::default()
+        })
+        .await;
+
+    assert_json_snapshot!(status_snapshot(&assert_status(result, Code::InvalidArgument)));
+}
+
+#[tokio::test]
+async fn delete_other_user() {
+    let app = TestApp::spawn().await;
+    let mut client = user_client(&app);
+
+    let result = client
+        .delete_user(DeleteUserRequest {
+            id: other_user_id().to_string(),
+        })
+        .await;
+
+    assert_json_snapshot!(status_snapshot(&assert_status(result, Code::PermissionDenied)));
+}
+
+#[tokio::test]
+async fn list_users_with_unknown_order_field() {
+    let app = TestApp::spawn().await;
+    let mut client = user_client(&app);
+
+    let result = client
+        .list_users(ListUsersRequest {
+            order_by: "password_hash".to_string(),
+            ..Default::default()
+        })
+        .await;
+
+    assert_json_snapshot!(status_snapshot(&assert_status(result, Code::InvalidArgument)));
+}
+
+#[tokio::test]
+async fn list_users_with_invalid_page_token() {
+    let app = TestApp::spawn().await;
+    let mut client = user_client(&app);
+
+    let result = client
+        .list_users(ListUsersRequest {
+            page_token: "not-a-token".to_string(),
+            ..Default::default()
+        })
+        .await;
+
+    assert_json_snapshot!(status_snapshot(&assert_status(result, Code::InvalidArgument)));
+}
+
+#[tokio::test]
+async fn refresh_with_invalid_token() {
+    let app = TestApp::spawn().await;
+    let mut client = UserServiceClient::new(app.channel.clone());
+
+    let result = client
+        .refresh_token(RefreshTokenRequest {
+            refresh_token: "not-a-jwt".to_string(),
+        })
+        .await;
+
+    assert_json_snapshot!(status_snapshot(&assert_status(result, Code::Unauthenticated)));
+}
+
+#[tokio::test]
+async fn download_invalid_file_id() {
+    let app = TestApp::spawn().await;
+    let token = app.token_for(user_id());
+    let mut client = FileServiceClient::with_interceptor(app.channel.clone(), bearer(&token));
+
+    let result = client
+        .download_file(DownloadFileRequest {
+            file_id: "not-a-uuid".to_string(),
+        })
+        .await;
+
+    assert_json_snapshot!(status_snapshot(&assert_status(result, Code::InvalidArgument)));
+}
+
+#[tokio::test]
+async fn maintenance_mode() {
+    let app = TestApp::spawn_with(|config| {
+        config
+            .set_override("maintenance.enabled", true)
+            .expect("maintenance override")
+    })
+    .await;
+    let mut client = user_client(&app);
+
+    let result = client
+        .get_user(GetUserRequest {
+            id: user_id().to_string(),
+        })
+        .await;
+
+    assert_json_snapshot!(status_snapshot(&assert_status(result, Code::Unavailable)));
+}
+
+#[tokio::test]
+async fn rate_limited() {
+    let app = TestApp::spawn_with(|config| {
+        config
+            .set_override("limits.default_rate_per_second", 1.0)
+            .and_then(|config| config.set_override("limits.default_burst", 1))
+            .expect("limit overrides")
+    })
+    .await;
+    let mut client = UserServiceClient::new(app.channel.clone());
+    let request = || ValidateTokenRequest {
+        access_token: "token".to_string(),
+    };
+    client.validate_token(request()).await.expect("first call is admitted");
+
+    let result = client.validate_token(request()).await;
+
+    assert_json_snapshot!(status_snapshot(&assert_status(result, Code::ResourceExhausted)));
+}
@@ -0,0 +1,125 @@
+//! Other services calling with Kubernetes service-account tokens or SPIFFE
+//! JWT-SVIDs, verified by the auth layer against the key sets in
+//! `fixtures/workload`.
+
+mod common;
+
+use chrono::Utc;
+use config::{File, FileFormat};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde_json::json;
+use tonic::Code;
+
+use common::{assert_status, authorized, TestApp};
+use tonic_template::proto::user::v1::user_service_client::UserServiceClient;
+use tonic_template::proto::user::v1::GetUserRequest;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/workload");
+const CLUSTER: &str = "https://kubernetes.default.svc";
+
+async fn spawn() -> TestApp {
+    let settings = format!(
+        r#"
+        [[workload_identity.issuers]]
+        kind = "kubernetes"
+        issuer = "{CLUSTER}"
+        audience = "tonic-template"
+        keys = {{ path = "{FIXTURES}/kubernetes-jwks.json" }}
+
+        [[workload_identity.issuers]]
+        kind = "spiffe"
+        trust_domain = "example.org"
+        audience = "tonic-template"
+        keys = {{ path = "{FIXTURES}/spire-bundle.json" }}
+        "#
+    );
+    TestApp::spawn_with(|config| config.add_source(File::from_str(&settings, FileFormat::Toml))).await
+}
+
+fn sign(key: &str, kid: &str, claims: serde_json::Value) -> String {
+    let pem = std::fs::read(format!("{}/{}.pem", FIXTURES, key)).unwrap();
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = Some(kid.to_string());
+    encode(&header, &claims, &EncodingKey::from_ec_pem(&pem).unwrap()).unwrap()
+}
+
+fn service_account(audience: &str) -> String {
+    sign(
+        "kubernetes",
+        "kube-1",
+        json!({
+            "iss": CLUSTER,
+            "sub": "system:serviceaccount:billing:invoicer",
+            "aud": [audience],
+            "exp": Utc::now().timestamp() + 600,
+        }),
+    )
+}
+
+fn svid(spiffe_id: &str) -> String {
+    sign(
+        "spire",
+        "spire-1",
+        json!({ "sub": spiffe_id, "aud": "tonic-template", "exp": Utc::now().timestamp() + 300 }),
+    )
+}
+
+/// A call that fails validation inside the service, past the auth layer.
+fn get_user(token: &str) -> tonic::Request<GetUserRequest> {
+    authorized(
+        GetUserRequest {
+            id: "not-a-uuid".to_string(),
+        },
+        token,
+    )
+}
+
+#[tokio::test]
+async fn service_account_tokens_pass_the_auth_layer() {
+    let app = spawn().await;
+    let mut client = UserServiceClient::new(app.channel.clone());
+
+    let result = client.get_user(get_user(&service_account("tonic-template"))).await;
+
+    assert_status(result, Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn svids_pass_the_auth_layer() {
+    let app = spawn().await;
+    let mut client = UserServiceClient::new(app.channel.clone());
+
+    let result = client.get_user(get_user(&svid("spiffe://example.org/ns/billing/sa/worker"))).await;
+
+    assert_status(result, Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn tokens_for_other_audiences_are_rejected() {
+    let app = spawn().await;
+    let mut client = UserServiceClient::new(app.channel.clone());
+
+    let result = client.get_user(get_user(&service_account("payments"))).await;
+
+    assert_status(result, Code::Unauthenticated);
+}
+
+#[tokio::test]
+async fn svids_of_other_trust_domains_are_rejected() {
+    let app = spawn().await;
+    let mut client = UserServiceClient::new(app.channel.clone());
+
+    let result = client.get_user(get_user(&svid("spiffe://other.org/ns/billing/sa/worker"))).await;
+
+    assert_status(result, Code::Unauthenticated);
+}
+
+#[tokio::test]
+async fn workload_tokens_need_configured_issuers() {
+    let app = TestApp::spawn().await;
+    let mut client = UserServiceClient::new(app.channel.clone());
+
+    let result = client.get_user(get_user(&service_account("tonic-template"))).await;
+
+    assert_status(result, Code::Unauthenticated);
+}
@@ -0,0 +1,187 @@
+//! `cargo xtask bench`: load-tests a running server with
+//! [oha](https://github.com/hatoo/oha) and records the result, so server
+//! configurations can be compared on the same machine.
+//!
+//! Each run drives the URL `runs` times and keeps the median of each
+//! figure, which smooths over a noisy run without hiding a consistent
+//! difference. Results are appended as one labelled row to a CSV file,
+//! so running it once per configuration leaves a table to compare.
+
+use anyhow::{bail, Context, Result};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::process::Command;
+
+const HEADER: &str = "label,url,connections,keepalive,requests_per_sec,p50_ms,p99_ms,p99_9_ms,success_rate";
+
+pub struct Options {
+    /// Names the configuration under test in the results.
+    pub label: String,
+    pub url: String,
+    pub duration: Duration,
+    pub connections: usize,
+    /// With keep-alive off every request opens a connection, which is what
+    /// stresses the accept path.
+    pub keepalive: bool,
+    pub runs: usize,
+    /// CSV file rows are appended to.
+    pub out: PathBuf,
+}
+
+/// The figures of one run, or the medians of several.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub requests_per_sec: f64,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub p99_9: Duration,
+    pub success_rate: f64,
+}
+
+impl Summary {
+    /// Reads the figures from oha's `--json` output.
+    pub fn from_oha_json(json: &str) -> Result<Self> {
+        let summary = object(json, "summary")?;
+        let latencies = object(json, "latencyPercentiles")?;
+        Ok(Self {
+            requests_per_sec: number(summary, "requestsPerSec")?,
+            p50: Duration::from_secs_f64(number(latencies, "p50")?),
+            p99: Duration::from_secs_f64(number(latencies, "p99")?),
+            p99_9: Duration::from_secs_f64(number(latencies, "p99.9")?),
+            success_rate: number(summary, "successRate")?,
+        })
+    }
+
+    /// The median of each figure over `runs`, which must not be empty.
+    pub fn median(runs: &[Summary]) -> Self {
+        fn median<T: Copy + PartialOrd>(runs: &[Summary], figure: impl Fn(&Summary) -> T) -> T {
+            let mut values: Vec<T> = runs.iter().map(figure).collect();
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            values[values.len() / 2]
+        }
+
+        Self {
+            requests_per_sec: median(runs, |run| run.requests_per_sec),
+            p50: median(runs, |run| run.p50),
+            p99: median(runs, |run| run.p99),
+            p99_9: median(runs, |run| run.p99_9),
+            success_rate: median(runs, |run| run.success_rate),
+        }
+    }
+
+    /// The CSV row for `options`.
+    pub fn row(&self, options: &Options) -> String {
+        format!(
+            "{},{},{},{},{:.0},{:.2},{:.2},{:.2},{:.4}",
+            options.label,
+            options.url,
+            options.connections,
+            options.keepalive,
+            self.requests_per_sec,
+            millis(self.p50),
+            millis(self.p99),
+            millis(self.p99_9),
+            self.success_rate,
+        )
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.0} req/s, p50 {:.2} ms, p99 {:.2} ms, p99.9 {:.2} ms, {:.2}% succeeded",
+            self.requests_per_sec,
+            millis(self.p50),
+            millis(self.p99),
+            millis(self.p99_9),
+            self.success_rate * 100.0,
+        )
+    }
+}
+
+pub async fn bench(options: &Options) -> Result<Summary> {
+    if options.runs == 0 {
+        bail!("--runs must be at least 1");
+    }
+
+    let mut runs = Vec::with_capacity(options.runs);
+    for run in 1..=options.runs {
+        let summary = oha(options).await?;
+        println!("run {}/{}: {}", run, options.runs, summary);
+        runs.push(summary);
+    }
+    let summary = Summary::median(&runs);
+
+    let new = !options.out.exists();
+    let mut out = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&options.out)
+        .with_context(|| format!("cannot open {}", options.out.display()))?;
+    if new {
+        writeln!(out, "{}", HEADER)?;
+    }
+    writeln!(out, "{}", summary.row(options))?;
+    Ok(summary)
+}
+
+async fn oha(options: &Options) -> Result<Summary> {
+    let mut oha = Command::new("oha");
+    oha.args(["--json", "--no-tui", "-z"])
+        .arg(format!("{}s", options.duration.as_secs()))
+        .arg("-c")
+        .arg(options.connections.to_string());
+    if !options.keepalive {
+        oha.arg("--disable-keepalive");
+    }
+    oha.arg(&options.url);
+
+    let output = oha.output().await.context("cannot run oha; install it with `cargo install oha`")?;
+    if !output.status.success() {
+        bail!("oha failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Summary::from_oha_json(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// The text of the object under `key`, braces included. oha's figures are
+/// flat numbers, so matching braces is enough to find its end.
+fn object<'a>(json: &'a str, key: &str) -> Result<&'a str> {
+    let start = json
+        .find(&format!("\"{}\"", key))
+        .and_then(|at| json[at..].find('{').map(|brace| at + brace))
+        .with_context(|| format!("oha's output has no {}", key))?;
+    let mut depth = 0;
+    for (i, c) in json[start..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(&json[start..=start + i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    bail!("oha's {} isn't closed", key)
+}
+
+/// The number under `key` directly in `object`.
+fn number(object: &str, key: &str) -> Result<f64> {
+    let pattern = format!("\"{}\"", key);
+    let at = object.find(&pattern).with_context(|| format!("oha's output has no {}", key))?;
+    let value = object[at + pattern.len()..].trim_start().trim_start_matches(':').trim_start();
+    let end = value.find([',', '}']).unwrap_or(value.len());
+    value[..end]
+        .trim()
+        .parse()
+        .with_context(|| format!("oha's {} isn't a number", key))
+}
@@ -0,0 +1,238 @@
+//! `cargo xtask dev`: runs the actix template locally without Docker.
+//!
+//! A throwaway Postgres cluster is created with [pg-embed](pg_embed) in a
+//! temporary directory, migrated and seeded with the server's `seed`
+//! command. pg-embed downloads a Postgres build on first use and caches it,
+//! so nothing has to be installed. Redis runs from `redis-server` if it is
+//! installed; without it the Redis-backed features fall back to their
+//! in-process behaviour (auth throttling off, cache invalidations not
+//! broadcast). The server then runs
+//! under `cargo watch` when available, restarting on changes, or plain
+//! `cargo run` otherwise.
+//!
+//! Everything started is stopped and deleted when the server exits or on
+//! Ctrl-C.
+
+use anyhow::{bail, Context, Result};
+use pg_embed::pg_enums::PgAuthMethod;
+use pg_embed::pg_fetch::{PgFetchSettings, PG_V15};
+use pg_embed::postgres::{PgEmbed, PgSettings};
+use std::env;
+use std::fs;
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+
+const DATABASE: &str = "actix_dev";
+/// Only ever guards a cluster deleted on exit, listening on 127.0.0.1.
+const DEV_DB_PASSWORD: &str = "dev";
+/// Only ever signs tokens for a cluster deleted on exit.
+const DEV_JWT_SECRET: &str = "cargo-xtask-dev-only-jwt-secret-not-for-any-deployment";
+/// Sources `cargo watch` restarts the server for.
+const WATCHED: [&str; 4] = ["src", "migrations", "templates", "macros/src"];
+
+pub struct Options {
+    /// Factory users `seed` adds besides the `dev` user.
+    pub users: usize,
+    /// Restart the server on changes, if `cargo watch` is installed.
+    pub watch: bool,
+}
+
+/// An embedded Postgres cluster in a temporary directory, stopped and
+/// deleted on drop.
+pub struct Postgres {
+    /// Only `None` while dropping.
+    postgres: Option<PgEmbed>,
+    dir: PathBuf,
+}
+
+impl Postgres {
+    /// Initializes a cluster in `dir` (which must not exist), starts it on a
+    /// free port and creates the database. The first call downloads
+    /// Postgres.
+    pub async fn start(dir: &Path) -> Result<Self> {
+        let settings = PgSettings {
+            database_dir: dir.join("data"),
+            port: free_port()?,
+            user: "postgres".to_string(),
+            password: DEV_DB_PASSWORD.to_string(),
+            auth_method: PgAuthMethod::MD5,
+            persistent: false,
+            timeout: Some(Duration::from_secs(60)),
+            migration_dir: None,
+        };
+        let fetch = PgFetchSettings {
+            version: PG_V15,
+            ..Default::default()
+        };
+        let embedded = PgEmbed::new(settings, fetch).await.context("cannot set up embedded Postgres")?;
+
+        fs::create_dir_all(dir).with_context(|| format!("cannot create {}", dir.display()))?;
+        // Dropping it cleans up if a step fails
+        let mut postgres = Self {
+            postgres: Some(embedded),
+            dir: dir.to_path_buf(),
+        };
+        let embedded = postgres.postgres.as_mut().expect("set above");
+        embedded
+            .setup()
+            .await
+            .context("cannot download or initialize Postgres (initdb refuses to run as root)")?;
+        embedded.start_db().await.context("cannot start Postgres")?;
+        embedded.create_database(DATABASE).await.context("cannot create the database")?;
+        Ok(postgres)
+    }
+
+    pub fn url(&self) -> String {
+        self.postgres.as_ref().expect("started").full_db_uri(DATABASE)
+    }
+}
+
+impl Drop for Postgres {
+    fn drop(&mut self) {
+        // pg-embed stops the cluster and deletes its data directory as it
+        // drops, so the directory around it can go after
+        drop(self.postgres.take());
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// `redis-server` without persistence, killed on drop.
+pub struct Redis {
+    _server: Child,
+    port: u16,
+}
+
+impl Redis {
+    /// `None` if `redis-server` isn't installed.
+    pub fn start() -> Result<Option<Self>> {
+        let port = free_port()?;
+        let server = Command::new("redis-server")
+            .args(["--port", &port.to_string(), "--bind", "127.0.0.1", "--save", "", "--appendonly", "no"])
+            .stdout(Stdio::null())
+            .kill_on_drop(true)
+            .spawn();
+        match server {
+            Ok(server) => Ok(Some(Self { _server: server, port })),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("cannot start redis-server"),
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("redis://127.0.0.1:{}", self.port)
+    }
+}
+
+/// Settings pointing the server at `postgres` and `redis`, in the
+/// `ACTIX_*` variables it reads. A JWT secret is only added if none is set.
+pub fn server_env(postgres: &Postgres, redis: Option<&Redis>) -> Vec<(String, String)> {
+    let mut vars = vec![("ACTIX_DATABASE__URL".to_string(), postgres.url())];
+    match redis {
+        Some(redis) => vars.push(("ACTIX_REDIS__URL".to_string(), redis.url())),
+        None => {
+            // Nothing connects to this address with both features off
+            vars.push(("ACTIX_REDIS__URL".to_string(), "redis://127.0.0.1:6379".to_string()));
+            vars.push(("ACTIX_AUTH_THROTTLE__ENABLED".to_string(), "false".to_string()));
+            vars.push(("ACTIX_CACHE__BROADCAST_INVALIDATIONS".to_string(), "false".to_string()));
+        }
+    }
+    if env::var_os("ACTIX_JWT__SECRET").is_none() && env::var_os("ACTIX_JWT__KEYS").is_none() {
+        vars.push(("ACTIX_JWT__SECRET".to_string(), DEV_JWT_SECRET.to_string()));
+    }
+    vars
+}
+
+pub async fn dev(options: &Options) -> Result<()> {
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+
+    let actix = Path::new(env!("CARGO_MANIFEST_DIR")).join("../actix");
+    let dir = env::temp_dir().join(format!("actix-dev-{}", std::process::id()));
+    eprintln!("Starting Postgres in {}", dir.display());
+    let postgres = tokio::select! {
+        postgres = Postgres::start(&dir) => postgres?,
+        _ = &mut interrupted => return Ok(()),
+    };
+    let redis = Redis::start()?;
+    match &redis {
+        Some(redis) => eprintln!("Started Redis at {}", redis.url()),
+        None => eprintln!("redis-server not found; auth throttling and cache broadcasts are off"),
+    }
+    let vars = server_env(&postgres, redis.as_ref());
+
+    let mut seed = Command::new("cargo");
+    seed.current_dir(&actix)
+        .envs(vars.iter().cloned())
+        .args(["run", "--quiet", "--package", "actix-template", "--", "seed", "--users", &options.users.to_string()]);
+    tokio::select! {
+        result = run(&mut seed) => result.context("seeding failed")?,
+        _ = &mut interrupted => return Ok(()),
+    }
+
+    let mut server = if options.watch && cargo_watch_installed().await {
+        let mut watch = Command::new("cargo");
+        watch.current_dir(&actix).envs(vars.iter().cloned()).arg("watch");
+        for path in WATCHED.iter().filter(|path| actix.join(path).exists()) {
+            watch.args(["-w", path]);
+        }
+        watch.args(["-x", "run --package actix-template"]);
+        watch
+    } else {
+        if options.watch {
+            eprintln!("cargo-watch not found (cargo install cargo-watch); changes need a restart");
+        }
+        let mut run = Command::new("cargo");
+        run.current_dir(&actix)
+            .envs(vars.iter().cloned())
+            .args(["run", "--package", "actix-template"]);
+        run
+    };
+    let mut server = server.kill_on_drop(true).spawn().context("cannot start the server")?;
+
+    tokio::select! {
+        status = server.wait() => {
+            let status = status?;
+            if !status.success() {
+                bail!("server exited with {}", status);
+            }
+        }
+        // The server got the same Ctrl-C; let it shut down gracefully
+        _ = &mut interrupted => {
+            let _ = server.wait().await;
+        }
+    }
+    eprintln!("Stopping Postgres{}", if redis.is_some() { " and Redis" } else { "" });
+    Ok(())
+}
+
+/// A port nothing listens on right now.
+fn free_port() -> Result<u16> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).context("no free port")?;
+    Ok(listener.local_addr()?.port())
+}
+
+async fn cargo_watch_installed() -> bool {
+    Command::new("cargo")
+        .args(["watch", "--version"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .is_ok_and(|status| status.success())
+}
+
+async fn run(command: &mut Command) -> Result<()> {
+    let program = command.as_std().get_program().to_string_lossy().into_owned();
+    let status = command
+        .kill_on_drop(true)
+        .status()
+        .await
+        .with_context(|| format!("cannot run {}", program))?;
+    if !status.success() {
+        bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
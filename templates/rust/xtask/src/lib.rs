@@ -0,0 +1,8 @@
+//! Maintenance tasks for the Rust templates and the services generated from
+//! them, run with `cargo xtask <task>` from `templates/rust`.
+
+pub mod bench;
+pub mod dev;
+pub mod merge;
+pub mod protos;
+pub mod upgrade;
@@ -0,0 +1,143 @@
+use anyhow::{anyhow, bail, Result};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use xtask::bench;
+use xtask::dev;
+use xtask::protos::{self, Registry};
+use xtask::upgrade::{self, Options};
+
+const USAGE: &str = "usage:
+  cargo xtask upgrade-template --service <dir> --base <dir> --template <dir> [--dry-run]
+  cargo xtask dev [--users <count>] [--no-watch]
+  cargo xtask bench --label <name> --url <url> [--duration <secs>] [--connections <count>] [--keepalive]
+                    [--runs <count>] [--out <csv>]
+  cargo xtask protos check --registry <registry> [--protos <dir>]
+  cargo xtask protos publish --registry <registry> --version <version> [--protos <dir>]";
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("upgrade-template") => upgrade_template(&args[1..]),
+        Some("dev") => run_dev(&args[1..]),
+        Some("bench") => run_bench(&args[1..]),
+        Some("protos") => run_protos(&args[1..]),
+        _ => bail!(USAGE),
+    }
+}
+
+fn run_dev(args: &[String]) -> Result<()> {
+    let mut options = dev::Options { users: 20, watch: true };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--users" => {
+                options.users = args.next().and_then(|count| count.parse().ok()).ok_or_else(|| anyhow!("--users needs a count"))?
+            }
+            "--no-watch" => options.watch = false,
+            _ => bail!(USAGE),
+        }
+    }
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(dev::dev(&options))
+}
+
+fn run_bench(args: &[String]) -> Result<()> {
+    let mut options = bench::Options {
+        label: String::new(),
+        url: String::new(),
+        duration: Duration::from_secs(60),
+        connections: 512,
+        keepalive: false,
+        runs: 3,
+        out: PathBuf::from("bench.csv"),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or_else(|| anyhow!("{} needs a value", arg));
+        let count = |value: String| value.parse().map_err(|_| anyhow!("{} needs a count", arg));
+        match arg.as_str() {
+            "--label" => options.label = value()?,
+            "--url" => options.url = value()?,
+            "--duration" => options.duration = Duration::from_secs(count(value()?)?),
+            "--connections" => options.connections = count(value()?)?,
+            "--keepalive" => options.keepalive = true,
+            "--runs" => options.runs = count(value()?)?,
+            "--out" => options.out = PathBuf::from(value()?),
+            _ => bail!(USAGE),
+        }
+    }
+    if options.label.is_empty() || options.url.is_empty() {
+        bail!(USAGE);
+    }
+
+    let summary = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(bench::bench(&options))?;
+    println!("{} (median of {}): {}", options.label, options.runs, summary);
+    println!("recorded in {}", options.out.display());
+    Ok(())
+}
+
+fn run_protos(args: &[String]) -> Result<()> {
+    let (mut registry, mut version, mut dir) = (None, None, protos::default_protos());
+    let publish = match args.first().map(String::as_str) {
+        Some("check") => false,
+        Some("publish") => true,
+        _ => bail!(USAGE),
+    };
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or_else(|| anyhow!("{} needs a value", arg));
+        match arg.as_str() {
+            "--registry" => registry = Some(Registry::parse(&value()?)),
+            "--version" => version = Some(value()?),
+            "--protos" => dir = PathBuf::from(value()?),
+            _ => bail!(USAGE),
+        }
+    }
+    let Some(registry) = registry else {
+        bail!(USAGE);
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let report = match (publish, version) {
+        (false, _) => runtime.block_on(protos::check(&dir, &registry))?,
+        (true, Some(version)) => runtime.block_on(protos::publish(&dir, &registry, &version))?,
+        (true, None) => bail!(USAGE),
+    };
+    println!("{}", report);
+    if !report.breaking.is_empty() {
+        bail!("the protos break clients of the published version");
+    }
+    Ok(())
+}
+
+fn upgrade_template(args: &[String]) -> Result<()> {
+    let (mut service, mut base, mut template, mut dry_run) = (None, None, None, false);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().map(PathBuf::from).ok_or_else(|| anyhow!("{} needs a directory", arg));
+        match arg.as_str() {
+            "--service" => service = Some(value()?),
+            "--base" => base = Some(value()?),
+            "--template" => template = Some(value()?),
+            "--dry-run" => dry_run = true,
+            _ => bail!(USAGE),
+        }
+    }
+    let (Some(service), Some(base), Some(template)) = (service, base, template) else {
+        bail!(USAGE);
+    };
+
+    let report = upgrade::upgrade(&Options { service, base, template, dry_run })?;
+    println!("{}", report);
+    if !report.conflicts.is_empty() {
+        bail!("{} conflicts to resolve by hand", report.conflicts.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! Three-way merges of a file the service and the template both changed
+//! since the service was generated.
+//!
+//! Everything gets a line-based merge first. When that conflicts, TOML files
+//! and `.env` files are merged again key by key, so a dependency or config
+//! key added by the template merges cleanly next to the service's own.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use toml_edit::{DocumentMut, Item, Table};
+
+/// Merges `template`'s changes since `base` into `service`, or returns what
+/// conflicts: conflicting keys for TOML and `.env` files, the whole file
+/// otherwise.
+pub fn merge(path: &Path, base: &str, service: &str, template: &str) -> Result<String, Vec<String>> {
+    if let Ok(merged) = diffy::merge(base, service, template) {
+        return Ok(merged);
+    }
+
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    if name.ends_with(".toml") {
+        merge_toml(base, service, template)
+    } else if name.starts_with(".env") {
+        merge_env(base, service, template)
+    } else {
+        Err(vec![])
+    }
+}
+
+pub fn merge_toml(base: &str, service: &str, template: &str) -> Result<String, Vec<String>> {
+    let parse = |text: &str| text.parse::<DocumentMut>().map_err(|_| vec![]);
+    let (base, mut service, template) = (parse(base)?, parse(service)?, parse(template)?);
+
+    let mut conflicts = Vec::new();
+    merge_table(base.as_table(), service.as_table_mut(), template.as_table(), "", &mut conflicts);
+    if conflicts.is_empty() {
+        Ok(service.to_string())
+    } else {
+        Err(conflicts)
+    }
+}
+
+fn merge_table(base: &Table, service: &mut Table, template: &Table, prefix: &str, conflicts: &mut Vec<String>) {
+    let mut keys: Vec<String> = template.iter().map(|(key, _)| key.to_string()).collect();
+    keys.extend(base.iter().map(|(key, _)| key.to_string()).filter(|key| !template.contains_key(key)));
+
+    for key in keys {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        let (base, template) = (base.get(&key), template.get(&key));
+
+        if let (Some(Item::Table(template)), Some(Item::Table(service))) = (template, service.get_mut(&key)) {
+            let empty = Table::new();
+            let base = base.and_then(Item::as_table).unwrap_or(&empty);
+            merge_table(base, service, template, &path, conflicts);
+            continue;
+        }
+
+        let current = service.get(&key);
+        if same(base, template) || same(current, template) {
+            continue;
+        }
+        if !same(current, base) {
+            conflicts.push(path);
+            continue;
+        }
+        match template {
+            Some(template) => {
+                service.insert(&key, template.clone());
+            }
+            None => {
+                service.remove(&key);
+            }
+        }
+    }
+}
+
+/// Compares values as written, ignoring surrounding whitespace and comments.
+fn same(a: Option<&Item>, b: Option<&Item>) -> bool {
+    let render = |item: Option<&Item>| item.map(|item| item.to_string().trim().to_string());
+    render(a) == render(b)
+}
+
+pub fn merge_env(base: &str, service: &str, template: &str) -> Result<String, Vec<String>> {
+    let (base_vars, template_vars) = (env_vars(base), env_vars(template));
+    let service_vars = env_vars(service);
+
+    let mut lines: Vec<String> = service.lines().map(str::to_string).collect();
+    let mut conflicts = Vec::new();
+    let keys = template_vars.keys().chain(base_vars.keys().filter(|key| !template_vars.contains_key(*key)));
+
+    for key in keys {
+        let (base, current, template) = (base_vars.get(key), service_vars.get(key), template_vars.get(key));
+        if base == template || current == template {
+            continue;
+        }
+        if current != base {
+            conflicts.push(key.clone());
+            continue;
+        }
+        let position = lines.iter().position(|line| env_var(line).is_some_and(|(name, _)| name == key));
+        match (position, template) {
+            (Some(position), Some(template)) => lines[position] = format!("{}={}", key, template),
+            (Some(position), None) => {
+                lines.remove(position);
+            }
+            (None, Some(template)) => lines.push(format!("{}={}", key, template)),
+            (None, None) => {}
+        }
+    }
+
+    if conflicts.is_empty() {
+        Ok(lines.join("\n") + "\n")
+    } else {
+        Err(conflicts)
+    }
+}
+
+fn env_vars(text: &str) -> BTreeMap<String, String> {
+    text.lines()
+        .filter_map(env_var)
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+fn env_var(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return None;
+    }
+    line.split_once('=').map(|(key, value)| (key.trim(), value.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_dependencies_merge_next_to_the_services() {
+        let base = "[package]\nname = \"actix-template\"\n\n[dependencies]\nserde = \"1.0\"\n";
+        let service = "[package]\nname = \"billing\"\n\n[dependencies]\nserde = \"1.0\"\nrust_decimal = \"1\"\n";
+        let template = "[package]\nname = \"actix-template\"\n\n[dependencies]\nserde = \"1.0\"\nsha2 = \"0.10\"\n";
+
+        let merged = merge(Path::new("Cargo.toml"), base, service, template).unwrap();
+        let merged: DocumentMut = merged.parse().unwrap();
+        assert_eq!(merged["package"]["name"].as_str(), Some("billing"));
+        assert!(merged["dependencies"].get("rust_decimal").is_some());
+        assert_eq!(merged["dependencies"]["sha2"].as_str(), Some("0.10"));
+    }
+
+    #[test]
+    fn keys_both_sides_changed_conflict() {
+        let base = "[dependencies]\ntokio = \"1.36\"\n";
+        let service = "[dependencies]\ntokio = \"1.37\"\n";
+        let template = "[dependencies]\ntokio = \"1.39\"\n";
+
+        assert_eq!(merge_toml(base, service, template), Err(vec!["dependencies.tokio".to_string()]));
+    }
+
+    #[test]
+    fn env_keys_merge_by_name() {
+        let base = "# Server\nACTIX_SERVER__PORT=8080\nACTIX_OLD=1\n";
+        let service = "# Server\nACTIX_SERVER__PORT=9090\nACTIX_OLD=1\nBILLING_CURRENCY=EUR\n";
+        let template = "# Server\nACTIX_SERVER__PORT=8080\nACTIX_TELEMETRY__ENABLED=false\n";
+
+        let merged = merge_env(base, service, template).unwrap();
+        assert_eq!(
+            merged,
+            "# Server\nACTIX_SERVER__PORT=9090\nBILLING_CURRENCY=EUR\nACTIX_TELEMETRY__ENABLED=false\n"
+        );
+    }
+}
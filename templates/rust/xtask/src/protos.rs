@@ -0,0 +1,377 @@
+//! `cargo xtask protos`: keeps the tonic template's protos compatible with
+//! what was last published to a schema registry.
+//!
+//! `check` compiles the protos with `protoc` (or `$PROTOC`) and compares
+//! them with the latest published descriptor set, failing on changes that
+//! break clients built against it. `publish` runs the same check, then
+//! publishes the new set. A [`Registry`] is one of:
+//!
+//! - `buf.build/<owner>/<module>`: the Buf Schema Registry, through the
+//!   `buf` CLI (`buf breaking`, `buf push`). Pushing needs the module's
+//!   `name` in `proto/buf.yaml`.
+//! - An `http://` or `https://` URL of a custom registry: `GET <url>/latest`
+//!   returns the serialized `FileDescriptorSet` last published (404 before
+//!   the first), `PUT <url>/<version>` publishes one.
+//! - A path to a serialized `FileDescriptorSet`, e.g. a baseline committed
+//!   next to the protos; publishing overwrites it.
+
+use anyhow::{bail, Context, Result};
+use prost::Message;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, EnumDescriptorProto, FileDescriptorProto, FileDescriptorSet};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Registry {
+    Buf(String),
+    Http(String),
+    File(PathBuf),
+}
+
+impl Registry {
+    pub fn parse(registry: &str) -> Self {
+        if registry.starts_with("buf.build/") {
+            Self::Buf(registry.to_string())
+        } else if registry.starts_with("http://") || registry.starts_with("https://") {
+            Self::Http(registry.trim_end_matches('/').to_string())
+        } else {
+            Self::File(PathBuf::from(registry))
+        }
+    }
+
+    /// The descriptor set last published, `None` if nothing was yet.
+    pub async fn latest(&self) -> Result<Option<FileDescriptorSet>> {
+        let bytes = match self {
+            Self::Buf(_) => bail!("the Buf Schema Registry is only read by `buf breaking`"),
+            Self::Http(url) => {
+                let response = reqwest::get(format!("{}/latest", url))
+                    .await
+                    .with_context(|| format!("cannot reach {}", url))?;
+                if response.status() == reqwest::StatusCode::NOT_FOUND {
+                    return Ok(None);
+                }
+                response.error_for_status()?.bytes().await?.to_vec()
+            }
+            Self::File(path) if !path.exists() => return Ok(None),
+            Self::File(path) => fs::read(path).with_context(|| format!("cannot read {}", path.display()))?,
+        };
+        let set = FileDescriptorSet::decode(bytes.as_slice()).context("the published descriptor set is invalid")?;
+        Ok(Some(set))
+    }
+
+    async fn publish(&self, protos: &Path, set: &FileDescriptorSet, version: &str) -> Result<()> {
+        match self {
+            Self::Buf(_) => {
+                let mut push = Command::new("buf");
+                push.arg("push").arg(protos).args(["--tag", version]);
+                run(&mut push).await
+            }
+            Self::Http(url) => {
+                reqwest::Client::new()
+                    .put(format!("{}/{}", url, version))
+                    .header(reqwest::header::CONTENT_TYPE, "application/x-protobuf")
+                    .body(set.encode_to_vec())
+                    .send()
+                    .await
+                    .with_context(|| format!("cannot reach {}", url))?
+                    .error_for_status()?;
+                Ok(())
+            }
+            Self::File(path) => {
+                fs::write(path, set.encode_to_vec()).with_context(|| format!("cannot write {}", path.display()))
+            }
+        }
+    }
+}
+
+/// Changes that break clients built against the previous protos.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub breaking: Vec<String>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.breaking.is_empty() {
+            return write!(f, "No breaking changes");
+        }
+        writeln!(f, "{} breaking changes:", self.breaking.len())?;
+        for change in &self.breaking {
+            writeln!(f, "  {}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// The template's protos, next to this crate.
+pub fn default_protos() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../tonic/proto")
+}
+
+/// Compiles every `.proto` in `protos` into a descriptor set, imports
+/// included.
+pub async fn compile(protos: &Path) -> Result<FileDescriptorSet> {
+    let mut files: Vec<_> = fs::read_dir(protos)
+        .with_context(|| format!("cannot read {}", protos.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    files.retain(|path| path.extension().is_some_and(|extension| extension == "proto"));
+    files.sort();
+
+    let out = env::temp_dir().join(format!("xtask-protos-{}.binpb", std::process::id()));
+    let mut protoc = Command::new(env::var_os("PROTOC").unwrap_or_else(|| "protoc".into()));
+    protoc
+        .arg("--include_imports")
+        .arg(format!("--descriptor_set_out={}", out.display()))
+        .arg("-I")
+        .arg(protos)
+        .args(&files);
+    let compiled = run(&mut protoc).await.and_then(|()| Ok(fs::read(&out)?));
+    let _ = fs::remove_file(&out);
+    Ok(FileDescriptorSet::decode(compiled?.as_slice())?)
+}
+
+/// Compares the protos in `protos` with what `registry` has.
+pub async fn check(protos: &Path, registry: &Registry) -> Result<Report> {
+    if let Registry::Buf(module) = registry {
+        return buf_breaking(protos, module).await;
+    }
+    let current = compile(protos).await?;
+    compare(&current, registry).await
+}
+
+/// Checks the protos in `protos`, then publishes them as `version` unless a
+/// change would break clients.
+pub async fn publish(protos: &Path, registry: &Registry, version: &str) -> Result<Report> {
+    let (report, current) = match registry {
+        Registry::Buf(module) => (buf_breaking(protos, module).await?, FileDescriptorSet::default()),
+        _ => {
+            let current = compile(protos).await?;
+            (compare(&current, registry).await?, current)
+        }
+    };
+    if report.breaking.is_empty() {
+        registry.publish(protos, &current, version).await?;
+    }
+    Ok(report)
+}
+
+async fn compare(current: &FileDescriptorSet, registry: &Registry) -> Result<Report> {
+    Ok(match registry.latest().await? {
+        Some(previous) => breaking_changes(&previous, current),
+        None => Report::default(),
+    })
+}
+
+async fn buf_breaking(protos: &Path, module: &str) -> Result<Report> {
+    // buf prints the changes itself and exits non-zero if there are any
+    let status = Command::new("buf")
+        .arg("breaking")
+        .arg(protos)
+        .args(["--against", module])
+        .status()
+        .await
+        .context("cannot run buf")?;
+    let breaking = if status.success() { Vec::new() } else { vec![format!("buf breaking failed against {}", module)] };
+    Ok(Report { breaking })
+}
+
+/// Wire and JSON incompatibilities of `current` with `previous`:
+///
+/// - services, methods, messages and enums that disappeared,
+/// - methods whose request or response type or streaming changed,
+/// - fields and enum values deleted without reserving their number and
+///   name,
+/// - fields and enum values renamed (which changes their JSON name),
+/// - fields whose type or cardinality changed.
+///
+/// Well-known types (`google.protobuf.*`) are left out.
+pub fn breaking_changes(previous: &FileDescriptorSet, current: &FileDescriptorSet) -> Report {
+    let (previous, current) = (Symbols::of(previous), Symbols::of(current));
+    let mut breaking = Vec::new();
+
+    for (name, methods) in &previous.services {
+        let Some(now) = current.services.get(name) else {
+            breaking.push(format!("service {} deleted", name));
+            continue;
+        };
+        for (method, signature) in methods {
+            match now.get(method) {
+                None => breaking.push(format!("method {}.{} deleted", name, method)),
+                Some(changed) if changed != signature => {
+                    breaking.push(format!("method {}.{} changed from {} to {}", name, method, signature, changed))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    for (name, message) in &previous.messages {
+        let Some(now) = current.messages.get(name) else {
+            breaking.push(format!("message {} deleted", name));
+            continue;
+        };
+        for field in &message.field {
+            let number = field.number();
+            match now.field.iter().find(|candidate| candidate.number() == number) {
+                None if !reserves(now, number, field.name()) => {
+                    breaking.push(format!("field {}.{} ({}) deleted without reserving it", name, field.name(), number))
+                }
+                None => {}
+                Some(changed) if changed.name() != field.name() => breaking.push(format!(
+                    "field {} of {} renamed from {} to {}",
+                    number,
+                    name,
+                    field.name(),
+                    changed.name()
+                )),
+                Some(changed) if field_type(changed) != field_type(field) => breaking.push(format!(
+                    "field {}.{} changed from {} to {}",
+                    name,
+                    field.name(),
+                    field_type(field),
+                    field_type(changed)
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+
+    for (name, values) in &previous.enums {
+        let Some(now) = current.enums.get(name) else {
+            breaking.push(format!("enum {} deleted", name));
+            continue;
+        };
+        for value in &values.value {
+            let number = value.number();
+            match now.value.iter().find(|candidate| candidate.number() == number) {
+                None if !reserves_value(now, number, value.name()) => {
+                    breaking.push(format!("enum value {}.{} ({}) deleted without reserving it", name, value.name(), number))
+                }
+                None => {}
+                Some(changed) if changed.name() != value.name() => breaking.push(format!(
+                    "enum value {} of {} renamed from {} to {}",
+                    number,
+                    name,
+                    value.name(),
+                    changed.name()
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+
+    Report { breaking }
+}
+
+/// Definitions by fully-qualified name, nested ones included.
+#[derive(Default)]
+struct Symbols {
+    /// Method name to its signature, per service.
+    services: BTreeMap<String, BTreeMap<String, String>>,
+    messages: BTreeMap<String, DescriptorProto>,
+    enums: BTreeMap<String, EnumDescriptorProto>,
+}
+
+impl Symbols {
+    fn of(set: &FileDescriptorSet) -> Self {
+        let mut symbols = Self::default();
+        for file in set.file.iter().filter(|file| file.package() != "google.protobuf") {
+            symbols.add_file(file);
+        }
+        symbols
+    }
+
+    fn add_file(&mut self, file: &FileDescriptorProto) {
+        let scope = file.package();
+        for service in &file.service {
+            let methods = service
+                .method
+                .iter()
+                .map(|method| {
+                    let stream = |streaming| if streaming { "stream " } else { "" };
+                    let signature = format!(
+                        "({}{}) returns ({}{})",
+                        stream(method.client_streaming()),
+                        method.input_type().trim_start_matches('.'),
+                        stream(method.server_streaming()),
+                        method.output_type().trim_start_matches('.')
+                    );
+                    (method.name().to_string(), signature)
+                })
+                .collect();
+            self.services.insert(qualify(scope, service.name()), methods);
+        }
+        for message in &file.message_type {
+            self.add_message(scope, message);
+        }
+        for enumeration in &file.enum_type {
+            self.enums.insert(qualify(scope, enumeration.name()), enumeration.clone());
+        }
+    }
+
+    fn add_message(&mut self, scope: &str, message: &DescriptorProto) {
+        let name = qualify(scope, message.name());
+        for nested in &message.nested_type {
+            // Map entries are an encoding detail of their field's type
+            if !nested.options.as_ref().is_some_and(|options| options.map_entry()) {
+                self.add_message(&name, nested);
+            }
+        }
+        for enumeration in &message.enum_type {
+            self.enums.insert(qualify(&name, enumeration.name()), enumeration.clone());
+        }
+        self.messages.insert(name, message.clone());
+    }
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+/// E.g. `repeated .user.v1.User` or `int32`. Proto3 `optional` only
+/// changes presence tracking, not the encoding, so it's left out.
+fn field_type(field: &prost_types::FieldDescriptorProto) -> String {
+    let repeated = if field.label() == Label::Repeated { "repeated " } else { "" };
+    let type_name = match field.r#type() {
+        Type::Message | Type::Enum => field.type_name().trim_start_matches('.').to_string(),
+        other => other.as_str_name().trim_start_matches("TYPE_").to_lowercase(),
+    };
+    format!("{}{}", repeated, type_name)
+}
+
+fn reserves(message: &DescriptorProto, number: i32, name: &str) -> bool {
+    // Reserved ranges exclude their end
+    message.reserved_range.iter().any(|range| (range.start()..range.end()).contains(&number))
+        && message.reserved_name.iter().any(|reserved| reserved == name)
+}
+
+fn reserves_value(enumeration: &EnumDescriptorProto, number: i32, name: &str) -> bool {
+    // Unlike message ranges, enum ranges include their end
+    enumeration.reserved_range.iter().any(|range| (range.start()..=range.end()).contains(&number))
+        && enumeration.reserved_name.iter().any(|reserved| reserved == name)
+}
+
+async fn run(command: &mut Command) -> Result<()> {
+    let program = command.as_std().get_program().to_string_lossy().into_owned();
+    let status = command
+        .stdout(Stdio::inherit())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .with_context(|| format!("cannot run {}", program))?;
+    if !status.success() {
+        bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
@@ -0,0 +1,254 @@
+//! `cargo xtask upgrade-template`: brings a service generated from an older
+//! template up to a newer one.
+//!
+//! Every file is compared three ways: the template the service was generated
+//! from (`base`), the service as it is now, and the newer template. Changes
+//! only the template made are applied — new modules and migrations are
+//! added, untouched files updated or removed, and files both sides changed
+//! merged (see [`crate::merge`]). Anything that doesn't merge cleanly is left
+//! alone and reported, with the template's version written next to it as
+//! `<file>.template`.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::merge;
+
+/// Never compared: build output, lockfiles and local secrets.
+const IGNORED: [&str; 4] = ["target", ".git", "Cargo.lock", ".env"];
+
+/// Where `build.rs` and `/api/v1/info` take the template version from.
+const MANIFEST: &str = "src/manifest.rs";
+
+pub struct Options {
+    pub service: PathBuf,
+    /// A checkout of the template version the service was generated from.
+    pub base: PathBuf,
+    pub template: PathBuf,
+    /// Report without writing anything.
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Updated,
+    Merged,
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub from_version: Option<String>,
+    pub to_version: Option<String>,
+    pub changes: Vec<(PathBuf, Change)>,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Report {
+    fn conflict(&mut self, path: &Path, reason: impl Into<String>) {
+        self.conflicts.push(Conflict { path: path.to_path_buf(), reason: reason.into() });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = |version: &Option<String>| version.clone().unwrap_or_else(|| "unknown".to_string());
+        writeln!(f, "Template {} -> {}", version(&self.from_version), version(&self.to_version))?;
+        for (path, change) in &self.changes {
+            writeln!(f, "  {:<8} {}", format!("{:?}", change).to_lowercase(), path.display())?;
+        }
+        for conflict in &self.conflicts {
+            writeln!(f, "  conflict {}: {}", conflict.path.display(), conflict.reason)?;
+        }
+        write!(f, "{} applied, {} conflicts", self.changes.len(), self.conflicts.len())
+    }
+}
+
+pub fn upgrade(options: &Options) -> Result<Report> {
+    let mut report = Report {
+        from_version: template_version(&options.base)?,
+        to_version: template_version(&options.template)?,
+        ..Report::default()
+    };
+    if let (Some(base), Some(service)) = (&report.from_version, template_version(&options.service)?) {
+        if *base != service {
+            bail!("the service was generated from template {}, but the base is {}", service, base);
+        }
+    }
+
+    let mut paths = BTreeSet::new();
+    collect(&options.base, Path::new(""), &mut paths)?;
+    collect(&options.template, Path::new(""), &mut paths)?;
+    let migrations = migration_versions(&options.service)?;
+
+    for path in paths {
+        let base = read(&options.base.join(&path))?;
+        let template = read(&options.template.join(&path))?;
+        let target = options.service.join(&path);
+        let service = read(&target)?;
+
+        if base == template || service == template {
+            continue;
+        }
+        if service != base {
+            match (&base, &service, &template) {
+                (Some(base), Some(service), Some(template)) => merge(options, &path, base, service, template, &mut report)?,
+                (_, None, _) => report.conflict(&path, "deleted in the service, changed in the template"),
+                (None, Some(_), _) => report.conflict(&path, "added by both the service and the template"),
+                (_, Some(_), None) => report.conflict(&path, "changed in the service, removed from the template"),
+            }
+            continue;
+        }
+
+        match template {
+            Some(template) => {
+                if base.is_none() {
+                    if let Some(clash) = migration_clash(&path, &migrations) {
+                        report.conflict(&path, format!("migration version is taken by {}", clash));
+                        continue;
+                    }
+                }
+                write(options, &target, &template)?;
+                report.changes.push((path, if base.is_none() { Change::Added } else { Change::Updated }));
+            }
+            None => {
+                if !options.dry_run {
+                    fs::remove_file(&target).with_context(|| format!("removing {}", target.display()))?;
+                }
+                report.changes.push((path, Change::Removed));
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+fn merge(
+    options: &Options,
+    path: &Path,
+    base: &[u8],
+    service: &[u8],
+    template: &[u8],
+    report: &mut Report,
+) -> Result<()> {
+    let target = options.service.join(path);
+    let texts = (std::str::from_utf8(base), std::str::from_utf8(service), std::str::from_utf8(template));
+    let (Ok(base), Ok(service), Ok(text)) = texts else {
+        write(options, &with_suffix(&target), template)?;
+        report.conflict(path, "binary file changed on both sides");
+        return Ok(());
+    };
+
+    match merge::merge(path, base, service, text) {
+        Ok(merged) => {
+            write(options, &target, merged.as_bytes())?;
+            report.changes.push((path.to_path_buf(), Change::Merged));
+        }
+        Err(keys) => {
+            write(options, &with_suffix(&target), template)?;
+            let reason = if keys.is_empty() {
+                "changed on both sides".to_string()
+            } else {
+                format!("changed on both sides: {}", keys.join(", "))
+            };
+            report.conflict(path, reason);
+        }
+    }
+    Ok(())
+}
+
+fn with_suffix(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".template");
+    PathBuf::from(name)
+}
+
+fn write(options: &Options, path: &Path, contents: &[u8]) -> Result<()> {
+    if options.dry_run {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents).with_context(|| format!("writing {}", path.display()))
+}
+
+fn read(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+    }
+}
+
+/// Collects paths relative to `root`.
+fn collect(root: &Path, relative: &Path, paths: &mut BTreeSet<PathBuf>) -> Result<()> {
+    let dir = root.join(relative);
+    for entry in fs::read_dir(&dir).with_context(|| format!("reading {}", dir.display()))? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if IGNORED.iter().any(|ignored| name == *ignored) {
+            continue;
+        }
+        let path = relative.join(&name);
+        if entry.file_type()?.is_dir() {
+            collect(root, &path, paths)?;
+        } else {
+            paths.insert(path);
+        }
+    }
+    Ok(())
+}
+
+/// Reads `TEMPLATE_VERSION` from the manifest, for templates that have one.
+fn template_version(root: &Path) -> Result<Option<String>> {
+    let Some(manifest) = read(&root.join(MANIFEST))? else {
+        return Ok(None);
+    };
+    let manifest = String::from_utf8_lossy(&manifest);
+    Ok(manifest
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("pub const TEMPLATE_VERSION: &str = "))
+        .map(|value| value.trim_end_matches(';').trim_matches('"').to_string()))
+}
+
+/// The service's migrations by version, the numeric prefix sqlx orders
+/// them by.
+fn migration_versions(service: &Path) -> Result<Vec<(String, String)>> {
+    let dir = service.join("migrations");
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut versions = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if let Some((version, _)) = name.split_once('_') {
+            versions.push((version.to_string(), name));
+        }
+    }
+    Ok(versions)
+}
+
+/// A new template migration can't reuse a version the service already
+/// gave one of its own.
+fn migration_clash(path: &Path, migrations: &[(String, String)]) -> Option<String> {
+    if path.parent() != Some(Path::new("migrations")) {
+        return None;
+    }
+    let name = path.file_name()?.to_string_lossy();
+    let (version, _) = name.split_once('_')?;
+    migrations
+        .iter()
+        .find(|(taken, existing)| taken == version && *existing != name)
+        .map(|(_, existing)| existing.clone())
+}
@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use xtask::bench::{Options, Summary};
+
+/// Trimmed `oha --json` output; `rps` has percentiles of its own that
+/// mustn't be taken for latencies.
+const OHA_JSON: &str = r#"{
+  "summary": { "successRate": 1.0, "total": 60.01, "slowest": 0.09, "average": 0.004, "requestsPerSec": 41234.5 },
+  "responseTimeHistogram": { "0.0001": 10, "0.009": 2470000 },
+  "latencyPercentiles": { "p10": 0.001, "p50": 0.0035, "p99": 0.0121, "p99.9": 0.0304, "p99.99": 0.07 },
+  "rps": { "mean": 41234.5, "percentiles": { "p50": 41000.0, "p99": 45000.0, "p99.9": 46000.0 } },
+  "statusCodeDistribution": { "200": 2474070 },
+  "errorDistribution": {}
+}"#;
+
+fn summary(requests_per_sec: f64, p99_ms: u64) -> Summary {
+    Summary {
+        requests_per_sec,
+        p50: Duration::from_millis(1),
+        p99: Duration::from_millis(p99_ms),
+        p99_9: Duration::from_millis(p99_ms * 2),
+        success_rate: 1.0,
+    }
+}
+
+#[test]
+fn oha_output_is_summarized() {
+    let summary = Summary::from_oha_json(OHA_JSON).unwrap();
+    assert_eq!(summary.requests_per_sec, 41234.5);
+    assert_eq!(summary.p50, Duration::from_secs_f64(0.0035));
+    assert_eq!(summary.p99, Duration::from_secs_f64(0.0121));
+    assert_eq!(summary.p99_9, Duration::from_secs_f64(0.0304));
+    assert_eq!(summary.success_rate, 1.0);
+
+    assert!(Summary::from_oha_json(r#"{ "summary": {} }"#).is_err());
+}
+
+#[test]
+fn runs_are_recorded_as_their_medians() {
+    let median = Summary::median(&[summary(30000.0, 9), summary(41000.0, 30), summary(40000.0, 12)]);
+    assert_eq!(median, summary(40000.0, 12));
+
+    let options = Options {
+        label: "acceptors=4".to_string(),
+        url: "http://server:8080/api/v1/health".to_string(),
+        duration: Duration::from_secs(60),
+        connections: 512,
+        keepalive: false,
+        runs: 3,
+        out: PathBuf::from("bench.csv"),
+    };
+    let row = "acceptors=4,http://server:8080/api/v1/health,512,false,40000,1.00,12.00,24.00,1.0000";
+    assert_eq!(median.row(&options), row);
+}
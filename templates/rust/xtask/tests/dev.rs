@@ -0,0 +1,24 @@
+use std::net::TcpStream;
+use tempfile::TempDir;
+
+use xtask::dev::{server_env, Postgres};
+
+#[tokio::test]
+#[ignore = "downloads Postgres on first run; run as a user other than root"]
+async fn postgres_clusters_are_deleted_when_dropped() {
+    let parent = TempDir::new().unwrap();
+    let dir = parent.path().join("cluster");
+    let postgres = Postgres::start(&dir).await.unwrap();
+
+    let url = postgres.url();
+    let address = url.split('@').nth(1).unwrap().split('/').next().unwrap().to_string();
+    assert!(TcpStream::connect(&address).is_ok());
+
+    let vars = server_env(&postgres, None);
+    assert!(vars.contains(&("ACTIX_DATABASE__URL".to_string(), url)));
+    assert!(vars.contains(&("ACTIX_AUTH_THROTTLE__ENABLED".to_string(), "false".to_string())));
+
+    drop(postgres);
+    assert!(!dir.exists());
+    assert!(TcpStream::connect(&address).is_err());
+}
@@ -0,0 +1,149 @@
+use prost::Message;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::descriptor_proto::ReservedRange;
+use prost_types::{
+    DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet, MethodDescriptorProto,
+    ServiceDescriptorProto,
+};
+use tempfile::TempDir;
+
+use xtask::protos::{breaking_changes, Registry};
+
+fn field(name: &str, number: i32, r#type: Type) -> FieldDescriptorProto {
+    FieldDescriptorProto {
+        name: Some(name.to_string()),
+        number: Some(number),
+        label: Some(Label::Optional as i32),
+        r#type: Some(r#type as i32),
+        json_name: Some(name.to_string()),
+        ..Default::default()
+    }
+}
+
+fn message(name: &str, fields: Vec<FieldDescriptorProto>) -> DescriptorProto {
+    DescriptorProto { name: Some(name.to_string()), field: fields, ..Default::default() }
+}
+
+fn method(name: &str, input: &str, output: &str) -> MethodDescriptorProto {
+    MethodDescriptorProto {
+        name: Some(name.to_string()),
+        input_type: Some(format!(".user.v1.{}", input)),
+        output_type: Some(format!(".user.v1.{}", output)),
+        ..Default::default()
+    }
+}
+
+fn set(messages: Vec<DescriptorProto>, methods: Vec<MethodDescriptorProto>) -> FileDescriptorSet {
+    FileDescriptorSet {
+        file: vec![FileDescriptorProto {
+            name: Some("user.proto".to_string()),
+            package: Some("user.v1".to_string()),
+            message_type: messages,
+            service: vec![ServiceDescriptorProto {
+                name: Some("UserService".to_string()),
+                method: methods,
+                ..Default::default()
+            }],
+            syntax: Some("proto3".to_string()),
+            ..Default::default()
+        }],
+    }
+}
+
+fn user(fields: Vec<FieldDescriptorProto>) -> FileDescriptorSet {
+    set(
+        vec![
+            message("User", fields),
+            message("GetUserRequest", vec![field("id", 1, Type::String)]),
+        ],
+        vec![method("GetUser", "GetUserRequest", "User")],
+    )
+}
+
+fn published() -> FileDescriptorSet {
+    user(vec![field("id", 1, Type::String), field("email", 2, Type::String)])
+}
+
+#[test]
+fn additions_are_compatible() {
+    let mut current = user(vec![
+        field("id", 1, Type::String),
+        field("email", 2, Type::String),
+        field("full_name", 3, Type::String),
+    ]);
+    current.file[0].message_type.push(message("ListUsersRequest", Vec::new()));
+    current.file[0].service[0].method.push(method("ListUsers", "ListUsersRequest", "User"));
+
+    let report = breaking_changes(&published(), &current);
+
+    assert!(report.breaking.is_empty(), "{}", report);
+}
+
+#[test]
+fn deleted_fields_must_be_reserved() {
+    let mut current = user(vec![field("id", 1, Type::String)]);
+    assert_eq!(
+        breaking_changes(&published(), &current).breaking,
+        ["field user.v1.User.email (2) deleted without reserving it"]
+    );
+
+    let user = &mut current.file[0].message_type[0];
+    user.reserved_range.push(ReservedRange { start: Some(2), end: Some(3) });
+    user.reserved_name.push("email".to_string());
+    assert!(breaking_changes(&published(), &current).breaking.is_empty());
+}
+
+#[test]
+fn renamed_and_retyped_fields_break() {
+    let mut repeated = field("email", 2, Type::String);
+    repeated.label = Some(Label::Repeated as i32);
+    let current = user(vec![field("user_id", 1, Type::String), repeated]);
+
+    assert_eq!(
+        breaking_changes(&published(), &current).breaking,
+        [
+            "field 1 of user.v1.User renamed from id to user_id",
+            "field user.v1.User.email changed from string to repeated string",
+        ]
+    );
+}
+
+#[test]
+fn method_changes_break() {
+    let mut current = published();
+    current.file[0].service[0].method[0].server_streaming = Some(true);
+
+    assert_eq!(
+        breaking_changes(&published(), &current).breaking,
+        ["method user.v1.UserService.GetUser changed from (user.v1.GetUserRequest) returns (user.v1.User) to (user.v1.GetUserRequest) returns (stream user.v1.User)"]
+    );
+
+    current.file[0].service[0].method.clear();
+    current.file[0].message_type.remove(1);
+    assert_eq!(
+        breaking_changes(&published(), &current).breaking,
+        ["method user.v1.UserService.GetUser deleted", "message user.v1.GetUserRequest deleted"]
+    );
+}
+
+#[tokio::test]
+async fn file_registries_start_empty() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("published.binpb");
+    let registry = Registry::parse(path.to_str().unwrap());
+    assert_eq!(registry, Registry::File(path.clone()));
+
+    assert!(registry.latest().await.unwrap().is_none());
+
+    std::fs::write(&path, published().encode_to_vec()).unwrap();
+    assert_eq!(registry.latest().await.unwrap(), Some(published()));
+}
+
+#[test]
+fn registries_are_told_apart_by_prefix() {
+    assert_eq!(Registry::parse("buf.build/acme/users"), Registry::Buf("buf.build/acme/users".to_string()));
+    assert_eq!(
+        Registry::parse("https://schemas.internal/tonic-template/"),
+        Registry::Http("https://schemas.internal/tonic-template".to_string())
+    );
+}
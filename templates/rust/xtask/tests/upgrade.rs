@@ -0,0 +1,159 @@
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+use xtask::upgrade::{upgrade, Change, Options};
+
+fn tree(files: &[(&str, &str)]) -> TempDir {
+    let dir = TempDir::new().unwrap();
+    for (path, contents) in files {
+        let path = dir.path().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+fn manifest(version: &str) -> String {
+    format!("pub const TEMPLATE_NAME: &str = \"rust/actix\";\npub const TEMPLATE_VERSION: &str = \"{}\";\n", version)
+}
+
+fn options(service: &TempDir, base: &TempDir, template: &TempDir, dry_run: bool) -> Options {
+    Options {
+        service: service.path().to_path_buf(),
+        base: base.path().to_path_buf(),
+        template: template.path().to_path_buf(),
+        dry_run,
+    }
+}
+
+fn read(dir: &TempDir, path: &str) -> Option<String> {
+    fs::read_to_string(dir.path().join(path)).ok()
+}
+
+const BASE_MAIN: &str = "fn main() {\n    let settings = load();\n    serve(settings);\n}\n";
+
+#[test]
+fn template_only_changes_are_applied() {
+    let base_manifest = manifest("2.0.0");
+    let base = tree(&[
+        ("src/manifest.rs", &base_manifest),
+        ("src/main.rs", BASE_MAIN),
+        ("src/legacy.rs", "// gone in 2.1\n"),
+        ("migrations/001_create_users_table.sql", "CREATE TABLE users ();\n"),
+    ]);
+    let service = tree(&[
+        ("src/manifest.rs", &base_manifest),
+        ("src/main.rs", "fn main() {\n    let settings = load();\n    billing::init();\n    serve(settings);\n}\n"),
+        ("src/legacy.rs", "// gone in 2.1\n"),
+        ("src/billing.rs", "pub fn init() {}\n"),
+        ("migrations/001_create_users_table.sql", "CREATE TABLE users ();\n"),
+        ("migrations/002_create_invoices_table.sql", "CREATE TABLE invoices ();\n"),
+    ]);
+    let template = tree(&[
+        ("src/manifest.rs", &manifest("2.1.0")),
+        ("src/main.rs", "fn main() {\n    let settings = load();\n    serve(settings);\n    telemetry::flush();\n}\n"),
+        ("src/telemetry.rs", "pub fn flush() {}\n"),
+        ("migrations/001_create_users_table.sql", "CREATE TABLE users ();\n"),
+        ("migrations/003_create_jobs_table.sql", "CREATE TABLE jobs ();\n"),
+    ]);
+
+    let report = upgrade(&options(&service, &base, &template, false)).unwrap();
+    assert!(report.conflicts.is_empty(), "{}", report);
+    assert_eq!(report.from_version.as_deref(), Some("2.0.0"));
+    assert_eq!(report.to_version.as_deref(), Some("2.1.0"));
+
+    let changes: Vec<_> = report.changes.iter().map(|(path, change)| (path.to_str().unwrap(), *change)).collect();
+    assert_eq!(
+        changes,
+        [
+            ("migrations/003_create_jobs_table.sql", Change::Added),
+            ("src/legacy.rs", Change::Removed),
+            ("src/main.rs", Change::Merged),
+            ("src/manifest.rs", Change::Updated),
+            ("src/telemetry.rs", Change::Added),
+        ]
+    );
+    assert_eq!(
+        read(&service, "src/main.rs").unwrap(),
+        "fn main() {\n    let settings = load();\n    billing::init();\n    serve(settings);\n    telemetry::flush();\n}\n"
+    );
+    assert!(read(&service, "src/legacy.rs").is_none());
+    assert!(read(&service, "src/billing.rs").is_some());
+    assert!(read(&service, "migrations/003_create_jobs_table.sql").is_some());
+    assert!(read(&service, "src/manifest.rs").unwrap().contains("\"2.1.0\""));
+}
+
+#[test]
+fn conflicts_are_reported_and_left_alone() {
+    let base = tree(&[("src/main.rs", BASE_MAIN), ("src/config.rs", "pub struct Settings;\n")]);
+    let service_main = "fn main() {\n    let settings = load_billing();\n    serve(settings);\n}\n";
+    let service = tree(&[
+        ("src/main.rs", service_main),
+        ("migrations/002_create_invoices_table.sql", "CREATE TABLE invoices ();\n"),
+    ]);
+    let template_main = "fn main() {\n    let settings = load_layered();\n    serve(settings);\n}\n";
+    let template = tree(&[
+        ("src/main.rs", template_main),
+        ("src/config.rs", "pub struct Settings { pub telemetry: bool }\n"),
+        ("migrations/002_create_jobs_table.sql", "CREATE TABLE jobs ();\n"),
+    ]);
+
+    let report = upgrade(&options(&service, &base, &template, false)).unwrap();
+    let conflicts: Vec<_> = report.conflicts.iter().map(|conflict| conflict.path.to_str().unwrap()).collect();
+    assert_eq!(conflicts, ["migrations/002_create_jobs_table.sql", "src/config.rs", "src/main.rs"]);
+    assert!(report.conflicts[0].reason.contains("002_create_invoices_table.sql"), "{}", report);
+    assert!(report.changes.is_empty(), "{}", report);
+
+    assert_eq!(read(&service, "src/main.rs").unwrap(), service_main);
+    assert_eq!(read(&service, "src/main.rs.template").unwrap(), template_main);
+    assert!(read(&service, "migrations/002_create_jobs_table.sql").is_none());
+}
+
+#[test]
+fn config_keys_merge_where_lines_conflict() {
+    let base = tree(&[
+        ("Cargo.toml", "[package]\nname = \"actix-template\"\n\n[dependencies]\nserde = \"1.0\"\n"),
+        (".env.example", "ACTIX_SERVER__PORT=8080\n"),
+    ]);
+    let service = tree(&[
+        ("Cargo.toml", "[package]\nname = \"billing\"\n\n[dependencies]\nserde = \"1.0\"\nrust_decimal = \"1\"\n"),
+        (".env.example", "ACTIX_SERVER__PORT=9090\nBILLING_CURRENCY=EUR\n"),
+        (".env", "JWT_SECRET=local\n"),
+    ]);
+    let template = tree(&[
+        ("Cargo.toml", "[package]\nname = \"actix-template\"\n\n[dependencies]\nserde = \"1.0\"\nsha2 = \"0.10\"\n"),
+        (".env.example", "ACTIX_SERVER__PORT=8080\nACTIX_TELEMETRY__ENABLED=false\n"),
+    ]);
+
+    let report = upgrade(&options(&service, &base, &template, false)).unwrap();
+    assert!(report.conflicts.is_empty(), "{}", report);
+    assert_eq!(report.changes.len(), 2, "{}", report);
+
+    let cargo = read(&service, "Cargo.toml").unwrap();
+    assert!(cargo.contains("name = \"billing\"") && cargo.contains("rust_decimal") && cargo.contains("sha2"));
+    let env = read(&service, ".env.example").unwrap();
+    assert!(env.contains("ACTIX_SERVER__PORT=9090") && env.contains("ACTIX_TELEMETRY__ENABLED=false"));
+    assert_eq!(read(&service, ".env").unwrap(), "JWT_SECRET=local\n");
+}
+
+#[test]
+fn dry_runs_change_nothing() {
+    let base = tree(&[("src/main.rs", BASE_MAIN)]);
+    let service = tree(&[("src/main.rs", BASE_MAIN)]);
+    let template = tree(&[("src/main.rs", BASE_MAIN), ("src/telemetry.rs", "pub fn flush() {}\n")]);
+
+    let report = upgrade(&options(&service, &base, &template, true)).unwrap();
+    assert_eq!(report.changes.len(), 1);
+    assert!(!Path::new(&service.path().join("src/telemetry.rs")).exists());
+}
+
+#[test]
+fn the_base_must_be_the_services_template_version() {
+    let base = tree(&[("src/manifest.rs", &manifest("2.1.0"))]);
+    let service = tree(&[("src/manifest.rs", &manifest("2.0.0"))]);
+    let template = tree(&[("src/manifest.rs", &manifest("2.2.0"))]);
+
+    let error = upgrade(&options(&service, &base, &template, false)).err().unwrap();
+    assert!(error.to_string().contains("generated from template 2.0.0"), "{}", error);
+}